@@ -8,6 +8,8 @@ import type {
   SetPriorityRequest,
   AddCredentialRequest,
   AddCredentialResponse,
+  ReauthSession,
+  StartReauthRequest,
 } from '@/types/api'
 
 // 创建 axios 实例
@@ -84,3 +86,18 @@ export async function deleteCredential(id: number): Promise<SuccessResponse> {
   const { data } = await api.delete<SuccessResponse>(`/credentials/${id}`)
   return data
 }
+
+// 发起设备码重新登录
+export async function startReauth(
+  id: number,
+  req: StartReauthRequest = {}
+): Promise<ReauthSession> {
+  const { data } = await api.post<ReauthSession>(`/credentials/${id}/reauth`, req)
+  return data
+}
+
+// 查询重新登录状态
+export async function getReauth(id: number): Promise<ReauthSession> {
+  const { data } = await api.get<ReauthSession>(`/credentials/${id}/reauth`)
+  return data
+}
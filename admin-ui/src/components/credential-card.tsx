@@ -1,6 +1,6 @@
 import { useState } from 'react'
 import { toast } from 'sonner'
-import { RefreshCw, ChevronUp, ChevronDown, Trash2 } from 'lucide-react'
+import { RefreshCw, ChevronUp, ChevronDown, Trash2, KeyRound } from 'lucide-react'
 import { Card, CardContent, CardHeader, CardTitle } from '@/components/ui/card'
 import { Button } from '@/components/ui/button'
 import { Badge } from '@/components/ui/badge'
@@ -21,6 +21,8 @@ import {
   useResetFailure,
   useDeleteCredential,
   useCredentialBalance,
+  useStartReauth,
+  useReauthStatus,
 } from '@/hooks/use-credentials'
 import { Progress } from '@/components/ui/progress'
 
@@ -38,11 +40,14 @@ export function CredentialCard({ credential }: CredentialCardProps) {
   const [editingPriority, setEditingPriority] = useState(false)
   const [priorityValue, setPriorityValue] = useState(String(credential.priority))
   const [showDeleteDialog, setShowDeleteDialog] = useState(false)
+  const [showReauthDialog, setShowReauthDialog] = useState(false)
 
   const setDisabled = useSetDisabled()
   const setPriority = useSetPriority()
   const resetFailure = useResetFailure()
   const deleteCredential = useDeleteCredential()
+  const startReauth = useStartReauth()
+  const { data: reauth } = useReauthStatus(credential.id, showReauthDialog)
   const { data: balance, isLoading: balanceLoading, isFetching } = useCredentialBalance(
     credential.id,
     !credential.disabled // 仅启用的凭据自动刷新余额
@@ -111,6 +116,19 @@ export function CredentialCard({ credential }: CredentialCardProps) {
     })
   }
 
+  const handleReauth = () => {
+    setShowReauthDialog(true)
+    startReauth.mutate(
+      { id: credential.id },
+      {
+        onError: (err) => {
+          toast.error('发起重新登录失败: ' + (err as Error).message)
+          setShowReauthDialog(false)
+        },
+      }
+    )
+  }
+
   const formatExpiry = (expiresAt: string | null) => {
     if (!expiresAt) return '未知'
     const date = new Date(expiresAt)
@@ -199,6 +217,12 @@ export function CredentialCard({ credential }: CredentialCardProps) {
                 {credential.failureCount}
               </span>
             </div>
+            <div>
+              <span className="text-muted-foreground">卡顿次数：</span>
+              <span className={credential.stallCount > 0 ? 'text-yellow-600 font-medium' : ''}>
+                {credential.stallCount}
+              </span>
+            </div>
             <div>
               <span className="text-muted-foreground">认证方式：</span>
               <span className="font-medium">{formatAuthMethodLabel(credential.authMethod)}</span>
@@ -207,6 +231,22 @@ export function CredentialCard({ credential }: CredentialCardProps) {
               <span className="text-muted-foreground">Token 有效期：</span>
               <span className="font-medium">{formatExpiry(credential.expiresAt)}</span>
             </div>
+            {credential.expiry.reauthInDays !== null && (
+              <div>
+                <span className="text-muted-foreground">预计重新登录：</span>
+                <span className={credential.expiry.expiringSoon ? 'text-yellow-600 font-medium' : 'font-medium'}>
+                  {credential.expiry.reauthInDays < 0
+                    ? '已超期'
+                    : `${credential.expiry.reauthInDays} 天后`}
+                </span>
+              </div>
+            )}
+            {credential.sourceFile && (
+              <div className="col-span-2 truncate" title={credential.sourceFile}>
+                <span className="text-muted-foreground">来源文件：</span>
+                <span className="font-mono text-xs">{credential.sourceFile}</span>
+              </div>
+            )}
           </div>
 
           {/* 余额信息 */}
@@ -282,6 +322,15 @@ export function CredentialCard({ credential }: CredentialCardProps) {
               <ChevronDown className="h-4 w-4 mr-1" />
               降低优先级
             </Button>
+            <Button
+              size="sm"
+              variant="outline"
+              onClick={handleReauth}
+              disabled={startReauth.isPending}
+            >
+              <KeyRound className="h-4 w-4 mr-1" />
+              重新登录
+            </Button>
             <Button
               size="sm"
               variant="destructive"
@@ -296,6 +345,54 @@ export function CredentialCard({ credential }: CredentialCardProps) {
         </CardContent>
       </Card>
 
+      {/* 重新登录对话框 */}
+      <Dialog open={showReauthDialog} onOpenChange={setShowReauthDialog}>
+        <DialogContent>
+          <DialogHeader>
+            <DialogTitle>重新登录凭据 #{credential.id}</DialogTitle>
+            <DialogDescription>
+              在浏览器中打开验证链接并输入用户码，完成后新的 Token 会自动替换当前凭据。
+            </DialogDescription>
+          </DialogHeader>
+          {!reauth ? (
+            <div className="text-sm text-muted-foreground">正在发起设备授权...</div>
+          ) : (
+            <div className="space-y-2 text-sm">
+              <div>
+                <span className="text-muted-foreground">用户码：</span>
+                <span className="font-mono text-lg font-medium">{reauth.userCode}</span>
+              </div>
+              <div>
+                <span className="text-muted-foreground">验证链接：</span>
+                <a
+                  className="text-primary underline break-all"
+                  href={reauth.verificationUriComplete || reauth.verificationUri}
+                  target="_blank"
+                  rel="noreferrer"
+                >
+                  {reauth.verificationUriComplete || reauth.verificationUri}
+                </a>
+              </div>
+              <div>
+                <span className="text-muted-foreground">状态：</span>
+                {reauth.status === 'pending' && <span>等待授权...</span>}
+                {reauth.status === 'completed' && (
+                  <span className="text-green-600 font-medium">已完成，凭据已重新启用</span>
+                )}
+                {reauth.status === 'failed' && (
+                  <span className="text-red-500 font-medium">失败：{reauth.error}</span>
+                )}
+              </div>
+            </div>
+          )}
+          <DialogFooter>
+            <Button variant="outline" onClick={() => setShowReauthDialog(false)}>
+              关闭
+            </Button>
+          </DialogFooter>
+        </DialogContent>
+      </Dialog>
+
       {/* 删除确认对话框 */}
       <Dialog open={showDeleteDialog} onOpenChange={setShowDeleteDialog}>
         <DialogContent>
@@ -8,8 +8,10 @@ import {
   getCredentialBalance,
   addCredential,
   deleteCredential,
+  startReauth,
+  getReauth,
 } from '@/api/credentials'
-import type { AddCredentialRequest } from '@/types/api'
+import type { AddCredentialRequest, StartReauthRequest } from '@/types/api'
 
 // 查询凭据列表
 export function useCredentials() {
@@ -94,3 +96,32 @@ export function useDeleteCredential() {
     },
   })
 }
+
+// 发起重新登录
+export function useStartReauth() {
+  const queryClient = useQueryClient()
+  return useMutation({
+    mutationFn: ({ id, req }: { id: number; req?: StartReauthRequest }) => startReauth(id, req),
+    onSuccess: (session) => {
+      queryClient.setQueryData(['credential-reauth', session.id], session)
+    },
+  })
+}
+
+// 查询重新登录状态（进行中时每 3 秒轮询，完成后刷新凭据列表）
+export function useReauthStatus(id: number, enabled: boolean) {
+  const queryClient = useQueryClient()
+  return useQuery({
+    queryKey: ['credential-reauth', id],
+    queryFn: async () => {
+      const session = await getReauth(id)
+      if (session.status === 'completed') {
+        queryClient.invalidateQueries({ queryKey: ['credentials'] })
+      }
+      return session
+    },
+    enabled,
+    retry: false,
+    refetchInterval: (query) => (query.state.data?.status === 'pending' ? 3000 : false),
+  })
+}
@@ -4,6 +4,7 @@ export interface CredentialsStatusResponse {
   available: number
   currentId: number
   credentials: CredentialStatusItem[]
+  expiringSoon: number[]
 }
 
 // 单个凭据状态
@@ -12,10 +13,23 @@ export interface CredentialStatusItem {
   priority: number
   disabled: boolean
   failureCount: number
+  stallCount: number
   isCurrent: boolean
   expiresAt: string | null
   authMethod: string | null
   hasProfileArn: boolean
+  sourceFile: string | null
+  expiry: ExpiryForecast
+}
+
+// 凭据到期预估
+export interface ExpiryForecast {
+  refreshTokenObtainedAt: string | null
+  refreshTokenAgeDays: number | null
+  accessTokenLifetimeSecs: number | null
+  reauthDueAt: string | null
+  reauthInDays: number | null
+  expiringSoon: boolean
 }
 
 // 余额响应
@@ -68,3 +82,21 @@ export interface AddCredentialResponse {
   message: string
   credentialId: number
 }
+
+// 重新登录会话
+export interface ReauthSession {
+  id: number
+  status: 'pending' | 'completed' | 'failed'
+  userCode: string
+  verificationUri: string
+  verificationUriComplete: string | null
+  startedAt: string
+  expiresAt: string
+  error?: string
+}
+
+// 发起重新登录请求
+export interface StartReauthRequest {
+  startUrl?: string
+  region?: string
+}
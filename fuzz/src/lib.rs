@@ -0,0 +1,164 @@
+//! kiro-rs 模糊测试目标
+//!
+//! kiro-rs 只有 bin target，无法作为依赖引入，这里用 `#[path]` 直接挂载被测模块的源码，
+//! 模块树与主 crate 保持一致（`crate::kiro::...` / `super::...` 的引用无需改动）。
+//! 挂载的模块只依赖 serde、bytes、crc 等基础库，不涉及网络与运行时。
+//!
+//! 每个目标的入口函数都在这里实现，`fuzz_targets/` 下只做转发，便于在普通测试中复现崩溃输入。
+
+#![allow(dead_code, unused_imports, clippy::all)]
+
+#[path = "../../src/kiro"]
+pub mod kiro {
+    pub mod parser;
+
+    pub mod model {
+        pub mod events;
+        pub mod requests;
+    }
+}
+
+#[path = "../../src/anthropic"]
+pub mod anthropic {
+    pub mod canonical;
+    pub mod chat_completions;
+    pub mod converter;
+    pub mod render;
+    pub mod responses;
+    pub mod stop_reason;
+    pub mod stream;
+    pub mod types;
+}
+
+use anthropic::canonical::{FromCanonical, ToCanonical};
+use anthropic::render::{RenderFormat, StreamRenderer};
+use anthropic::stream::StreamContext;
+use kiro::model::events::Event;
+use kiro::parser::crc::crc32;
+use kiro::parser::decoder::EventStreamDecoder;
+
+/// 上游事件类型（`event_payloads` 按输入字节选择）
+const EVENT_TYPES: &[&str] = &[
+    "assistantResponseEvent",
+    "toolUseEvent",
+    "contextUsageEvent",
+    "meteringEvent",
+    "artifactEvent",
+    "unknownEvent",
+];
+
+/// 任意字节作为上游响应体：按首字节决定的块大小分批喂给解码器，走完整的流式处理链路
+pub fn event_stream(data: &[u8]) {
+    let Some((&control, body)) = data.split_first() else {
+        return;
+    };
+    let chunk_size = (control as usize & 0x3f) + 1;
+    replay(body.chunks(chunk_size), control & 0x40 != 0);
+}
+
+/// 合法 CRC 的帧包裹任意 payload：越过帧校验，直接考验各事件的 JSON 解析与后续转换
+///
+/// 输入格式为若干段 `[类型字节][长度字节][payload...]`
+pub fn event_payloads(data: &[u8]) {
+    let mut stream = Vec::new();
+    let mut rest = data;
+    while let [kind, len, tail @ ..] = rest {
+        let len = (*len as usize).min(tail.len());
+        let (payload, tail) = tail.split_at(len);
+        let event_type = EVENT_TYPES[*kind as usize % EVENT_TYPES.len()];
+        // 高两位全为 1 时作为 error/exception 消息，其余为普通事件
+        let message_type = match kind >> 6 {
+            3 => ["error", "exception"][*kind as usize & 1],
+            _ => "event",
+        };
+        stream.extend(encode_frame(message_type, event_type, payload));
+        rest = tail;
+    }
+    let thinking = data.first().is_some_and(|b| b & 0x20 != 0);
+    replay(stream.chunks(7), thinking);
+}
+
+/// 任意 JSON 作为客户端请求体：依次尝试三种入口格式，转换为规范请求与 Kiro 请求
+pub fn request_json(data: &[u8]) {
+    use anthropic::chat_completions::ChatCompletionRequest;
+    use anthropic::responses::ResponsesRequest;
+    use anthropic::types::MessagesRequest;
+
+    if let Ok(request) = serde_json::from_slice::<MessagesRequest>(data) {
+        let _ = anthropic::converter::convert_request(&request);
+        if let Ok(canonical) = request.to_canonical() {
+            let _ = MessagesRequest::from_canonical(&canonical);
+        }
+    }
+    if let Ok(request) = serde_json::from_slice::<ResponsesRequest>(data) {
+        if let Ok(canonical) = request.to_canonical() {
+            let _ =
+                anthropic::converter::convert_request(&MessagesRequest::from_canonical(&canonical));
+        }
+    }
+    if let Ok(request) = serde_json::from_slice::<ChatCompletionRequest>(data) {
+        if let Ok(canonical) = request.to_canonical() {
+            let _ =
+                anthropic::converter::convert_request(&MessagesRequest::from_canonical(&canonical));
+        }
+    }
+    // 非流式响应的格式转换同样只读取 JSON 字段
+    if let Ok(message) = serde_json::from_slice::<serde_json::Value>(data) {
+        anthropic::responses::from_message(&message);
+        anthropic::chat_completions::from_message(&message);
+    }
+}
+
+/// 与 handlers 的流式处理相同：解码 → 事件 → StreamContext → 各格式渲染
+fn replay<'a>(chunks: impl Iterator<Item = &'a [u8]>, thinking: bool) {
+    let mut ctx = StreamContext::new_with_thinking("claude-sonnet-4-5", 100, thinking);
+    let mut events = ctx.generate_initial_events();
+    let mut decoder = EventStreamDecoder::new();
+    for chunk in chunks {
+        let _ = decoder.feed(chunk);
+        for frame in decoder.decode_iter().flatten() {
+            match Event::from_frame(frame) {
+                Ok(Event::Artifact(artifact)) => {
+                    events.extend(ctx.process_artifact_text(&artifact.name))
+                }
+                Ok(event) => events.extend(ctx.process_kiro_event(&event)),
+                Err(_) => {}
+            }
+        }
+    }
+    events.extend(ctx.generate_final_events());
+
+    for format in [
+        RenderFormat::Anthropic,
+        RenderFormat::OpenAi,
+        RenderFormat::OpenAiResponses,
+        RenderFormat::PlainText,
+    ] {
+        StreamRenderer::new(format).render_all(events.clone());
+    }
+}
+
+/// 编码一个 CRC 正确的 Event Stream 帧
+pub fn encode_frame(message_type: &str, event_type: &str, payload: &[u8]) -> Vec<u8> {
+    let mut headers = Vec::new();
+    for (name, value) in [(":message-type", message_type), (":event-type", event_type)] {
+        headers.push(name.len() as u8);
+        headers.extend_from_slice(name.as_bytes());
+        // 值类型 7 = String
+        headers.push(7);
+        headers.extend_from_slice(&(value.len() as u16).to_be_bytes());
+        headers.extend_from_slice(value.as_bytes());
+    }
+
+    let total_length = 12 + headers.len() + payload.len() + 4;
+    let mut frame = Vec::with_capacity(total_length);
+    frame.extend_from_slice(&(total_length as u32).to_be_bytes());
+    frame.extend_from_slice(&(headers.len() as u32).to_be_bytes());
+    let prelude_crc = crc32(&frame);
+    frame.extend_from_slice(&prelude_crc.to_be_bytes());
+    frame.extend(headers);
+    frame.extend_from_slice(payload);
+    let message_crc = crc32(&frame);
+    frame.extend_from_slice(&message_crc.to_be_bytes());
+    frame
+}
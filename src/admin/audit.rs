@@ -0,0 +1,204 @@
+//! Admin 操作审计日志
+//!
+//! 每次成功的 Admin API 变更（禁用凭据、开通密钥等）记录操作者、时间以及变更前后的值。
+//! 设置 stateDir 时以 JSON Lines 只追加写入 `{stateDir}/audit.jsonl`，否则只在内存中保留最近的记录。
+
+use std::collections::VecDeque;
+use std::fs::OpenOptions;
+use std::io::{BufRead, BufReader, Write};
+use std::path::PathBuf;
+
+use chrono::{DateTime, Utc};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// 审计日志文件名（位于 stateDir）
+const AUDIT_FILE: &str = "audit.jsonl";
+
+/// 未设置 stateDir 时内存中保留的记录数
+const MEMORY_LIMIT: usize = 1000;
+
+/// 单条审计记录
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditEntry {
+    pub time: DateTime<Utc>,
+    /// 操作者（`x-admin-actor` 请求头，未提供时为 `admin`）
+    pub actor: String,
+    /// 操作名称，如 `credential.disable`
+    pub action: String,
+    /// 操作对象，如凭据 ID 或客户端名称
+    pub target: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub before: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub after: Option<Value>,
+}
+
+/// 审计日志查询条件
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditQuery {
+    pub actor: Option<String>,
+    /// 按操作名称前缀过滤，如 `credential`
+    pub action: Option<String>,
+    pub target: Option<String>,
+    /// 最多返回条数（默认 100）
+    pub limit: Option<usize>,
+}
+
+/// 审计日志
+pub struct AuditLog {
+    path: Option<PathBuf>,
+    /// 写文件与内存记录共用一把锁，保证记录顺序
+    memory: Mutex<VecDeque<AuditEntry>>,
+}
+
+impl AuditLog {
+    /// 仅内存中保留
+    pub fn in_memory() -> Self {
+        Self {
+            path: None,
+            memory: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    /// 写入 `{stateDir}/audit.jsonl`，未设置 stateDir 时仅内存中保留
+    pub fn open(state_dir: Option<&str>) -> Self {
+        Self {
+            path: state_dir.map(|dir| PathBuf::from(dir).join(AUDIT_FILE)),
+            memory: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    /// 记录一次变更
+    pub fn record(
+        &self,
+        actor: &str,
+        action: &str,
+        target: impl Into<String>,
+        before: Option<Value>,
+        after: Option<Value>,
+    ) {
+        let entry = AuditEntry {
+            time: Utc::now(),
+            actor: actor.to_string(),
+            action: action.to_string(),
+            target: target.into(),
+            before,
+            after,
+        };
+        tracing::info!(
+            "审计: {} 执行 {} ({})",
+            entry.actor,
+            entry.action,
+            entry.target
+        );
+
+        let mut memory = self.memory.lock();
+        let Some(path) = &self.path else {
+            memory.push_back(entry);
+            while memory.len() > MEMORY_LIMIT {
+                memory.pop_front();
+            }
+            return;
+        };
+        let result = serde_json::to_string(&entry)
+            .map_err(std::io::Error::other)
+            .and_then(|line| {
+                let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+                writeln!(file, "{}", line)
+            });
+        if let Err(e) = result {
+            tracing::error!("写入审计日志失败 {}: {}", path.display(), e);
+        }
+    }
+
+    /// 按条件查询，最新的在前
+    pub fn query(&self, query: &AuditQuery) -> Vec<AuditEntry> {
+        let memory = self.memory.lock();
+        let entries: Vec<AuditEntry> = match &self.path {
+            Some(path) => match std::fs::File::open(path) {
+                Ok(file) => BufReader::new(file)
+                    .lines()
+                    .map_while(Result::ok)
+                    .filter_map(|line| serde_json::from_str(&line).ok())
+                    .collect(),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+                Err(e) => {
+                    tracing::error!("读取审计日志失败 {}: {}", path.display(), e);
+                    Vec::new()
+                }
+            },
+            None => memory.iter().cloned().collect(),
+        };
+        drop(memory);
+
+        entries
+            .into_iter()
+            .rev()
+            .filter(|e| query.actor.as_ref().is_none_or(|a| e.actor == *a))
+            .filter(|e| {
+                query
+                    .action
+                    .as_ref()
+                    .is_none_or(|a| e.action.starts_with(a.as_str()))
+            })
+            .filter(|e| query.target.as_ref().is_none_or(|t| e.target == *t))
+            .take(query.limit.unwrap_or(100))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_append_and_query() {
+        let dir = std::env::temp_dir().join(format!("kiro-audit-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let log = AuditLog::open(dir.to_str());
+
+        log.record(
+            "alice",
+            "credential.disable",
+            "1",
+            Some(json!({"disabled": false})),
+            Some(json!({"disabled": true})),
+        );
+        log.record("bob", "client-key.provision", "contractor", None, None);
+
+        // 重新打开后仍能查到（只追加写入文件）
+        let log = AuditLog::open(dir.to_str());
+        let all = log.query(&AuditQuery::default());
+        assert_eq!(all.len(), 2);
+        assert_eq!(all[0].action, "client-key.provision");
+        assert_eq!(all[1].after, Some(json!({"disabled": true})));
+
+        let filtered = log.query(&AuditQuery {
+            action: Some("credential".to_string()),
+            ..Default::default()
+        });
+        assert_eq!(filtered.len(), 1);
+        assert_eq!(filtered[0].actor, "alice");
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_in_memory_limit() {
+        let log = AuditLog::in_memory();
+        for i in 0..MEMORY_LIMIT + 5 {
+            log.record("admin", "credential.priority", i.to_string(), None, None);
+        }
+        let all = log.query(&AuditQuery {
+            limit: Some(usize::MAX),
+            ..Default::default()
+        });
+        assert_eq!(all.len(), MEMORY_LIMIT);
+        assert_eq!(all[0].target, (MEMORY_LIMIT + 4).to_string());
+    }
+}
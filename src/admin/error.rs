@@ -20,6 +20,27 @@ pub enum AdminServiceError {
 
     /// 凭据无效（验证失败）
     InvalidCredential(String),
+
+    /// 滥用检测标记不存在
+    FlagNotFound { client: String },
+
+    /// 客户端密钥请求无效
+    InvalidClientKey(String),
+
+    /// 开通的客户端密钥不存在
+    ClientKeyNotFound { name: String },
+
+    /// 凭据没有发起过重新登录
+    ReauthNotStarted { id: u64 },
+
+    /// 登录会话不存在
+    LoginNotFound { session: String },
+
+    /// 查询参数无效
+    InvalidQuery(String),
+
+    /// 进行中的请求不存在（已结束或 ID 无效）
+    RequestNotFound { id: u64 },
 }
 
 impl fmt::Display for AdminServiceError {
@@ -31,6 +52,23 @@ impl fmt::Display for AdminServiceError {
             AdminServiceError::UpstreamError(msg) => write!(f, "上游服务错误: {}", msg),
             AdminServiceError::InternalError(msg) => write!(f, "内部错误: {}", msg),
             AdminServiceError::InvalidCredential(msg) => write!(f, "凭据无效: {}", msg),
+            AdminServiceError::FlagNotFound { client } => {
+                write!(f, "客户端未被标记: {}", client)
+            }
+            AdminServiceError::InvalidClientKey(msg) => write!(f, "客户端密钥无效: {}", msg),
+            AdminServiceError::ClientKeyNotFound { name } => {
+                write!(f, "开通的客户端密钥不存在: {}", name)
+            }
+            AdminServiceError::ReauthNotStarted { id } => {
+                write!(f, "凭据 #{} 没有进行中的重新登录", id)
+            }
+            AdminServiceError::LoginNotFound { session } => {
+                write!(f, "登录会话不存在: {}", session)
+            }
+            AdminServiceError::InvalidQuery(msg) => write!(f, "查询参数无效: {}", msg),
+            AdminServiceError::RequestNotFound { id } => {
+                write!(f, "请求 #{} 不存在或已结束", id)
+            }
         }
     }
 }
@@ -45,18 +83,34 @@ impl AdminServiceError {
             AdminServiceError::UpstreamError(_) => StatusCode::BAD_GATEWAY,
             AdminServiceError::InternalError(_) => StatusCode::INTERNAL_SERVER_ERROR,
             AdminServiceError::InvalidCredential(_) => StatusCode::BAD_REQUEST,
+            AdminServiceError::FlagNotFound { .. } => StatusCode::NOT_FOUND,
+            AdminServiceError::InvalidClientKey(_) => StatusCode::BAD_REQUEST,
+            AdminServiceError::ClientKeyNotFound { .. } => StatusCode::NOT_FOUND,
+            AdminServiceError::ReauthNotStarted { .. } => StatusCode::NOT_FOUND,
+            AdminServiceError::LoginNotFound { .. } => StatusCode::NOT_FOUND,
+            AdminServiceError::InvalidQuery(_) => StatusCode::BAD_REQUEST,
+            AdminServiceError::RequestNotFound { .. } => StatusCode::NOT_FOUND,
         }
     }
 
     /// 转换为 API 错误响应
     pub fn into_response(self) -> AdminErrorResponse {
         match &self {
-            AdminServiceError::NotFound { .. } => AdminErrorResponse::not_found(self.to_string()),
+            AdminServiceError::NotFound { .. }
+            | AdminServiceError::FlagNotFound { .. }
+            | AdminServiceError::ClientKeyNotFound { .. }
+            | AdminServiceError::ReauthNotStarted { .. }
+            | AdminServiceError::LoginNotFound { .. }
+            | AdminServiceError::RequestNotFound { .. } => {
+                AdminErrorResponse::not_found(self.to_string())
+            }
             AdminServiceError::UpstreamError(_) => AdminErrorResponse::api_error(self.to_string()),
             AdminServiceError::InternalError(_) => {
                 AdminErrorResponse::internal_error(self.to_string())
             }
-            AdminServiceError::InvalidCredential(_) => {
+            AdminServiceError::InvalidCredential(_)
+            | AdminServiceError::InvalidClientKey(_)
+            | AdminServiceError::InvalidQuery(_) => {
                 AdminErrorResponse::invalid_request(self.to_string())
             }
         }
@@ -1,14 +1,28 @@
 //! Admin API HTTP 处理器
 
+use std::convert::Infallible;
+
 use axum::{
-    Json,
-    extract::{Path, State},
-    response::IntoResponse,
+    Extension, Json,
+    extract::{Path, Query, State},
+    response::{
+        IntoResponse,
+        sse::{Event, KeepAlive, Sse},
+    },
 };
+use futures::Stream;
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::common::ledger::UsageSummaryQuery;
 
 use super::{
-    middleware::AdminState,
-    types::{AddCredentialRequest, SetDisabledRequest, SetPriorityRequest, SuccessResponse},
+    audit::AuditQuery,
+    middleware::{AdminActor, AdminState},
+    types::{
+        AddCredentialRequest, ArmRawCaptureRequest, ProvisionClientKeyRequest, SetDisabledRequest,
+        SetMaintenanceRequest, SetPriorityRequest, StartLoginRequest, StartReauthRequest,
+        SuccessResponse,
+    },
 };
 
 /// GET /api/admin/credentials
@@ -22,10 +36,11 @@ pub async fn get_all_credentials(State(state): State<AdminState>) -> impl IntoRe
 /// 设置凭据禁用状态
 pub async fn set_credential_disabled(
     State(state): State<AdminState>,
+    Extension(actor): Extension<AdminActor>,
     Path(id): Path<u64>,
     Json(payload): Json<SetDisabledRequest>,
 ) -> impl IntoResponse {
-    match state.service.set_disabled(id, payload.disabled) {
+    match state.service.set_disabled(id, payload.disabled, &actor.0) {
         Ok(_) => {
             let action = if payload.disabled { "禁用" } else { "启用" };
             Json(SuccessResponse::new(format!("凭据 #{} 已{}", id, action))).into_response()
@@ -34,14 +49,60 @@ pub async fn set_credential_disabled(
     }
 }
 
+/// POST /api/admin/credentials/:id/enable
+/// 启用凭据（无需请求体）
+pub async fn enable_credential(
+    State(state): State<AdminState>,
+    Extension(actor): Extension<AdminActor>,
+    Path(id): Path<u64>,
+) -> impl IntoResponse {
+    set_credential_disabled(
+        State(state),
+        Extension(actor),
+        Path(id),
+        Json(SetDisabledRequest { disabled: false }),
+    )
+    .await
+}
+
+/// POST /api/admin/credentials/:id/disable
+/// 禁用凭据（无需请求体）
+pub async fn disable_credential(
+    State(state): State<AdminState>,
+    Extension(actor): Extension<AdminActor>,
+    Path(id): Path<u64>,
+) -> impl IntoResponse {
+    set_credential_disabled(
+        State(state),
+        Extension(actor),
+        Path(id),
+        Json(SetDisabledRequest { disabled: true }),
+    )
+    .await
+}
+
+/// POST /api/admin/credentials/:id/refresh
+/// 强制刷新凭据的 Token
+pub async fn refresh_credential(
+    State(state): State<AdminState>,
+    Extension(actor): Extension<AdminActor>,
+    Path(id): Path<u64>,
+) -> impl IntoResponse {
+    match state.service.force_refresh(id, &actor.0).await {
+        Ok(response) => Json(response).into_response(),
+        Err(e) => (e.status_code(), Json(e.into_response())).into_response(),
+    }
+}
+
 /// POST /api/admin/credentials/:id/priority
 /// 设置凭据优先级
 pub async fn set_credential_priority(
     State(state): State<AdminState>,
+    Extension(actor): Extension<AdminActor>,
     Path(id): Path<u64>,
     Json(payload): Json<SetPriorityRequest>,
 ) -> impl IntoResponse {
-    match state.service.set_priority(id, payload.priority) {
+    match state.service.set_priority(id, payload.priority, &actor.0) {
         Ok(_) => Json(SuccessResponse::new(format!(
             "凭据 #{} 优先级已设置为 {}",
             id, payload.priority
@@ -55,9 +116,10 @@ pub async fn set_credential_priority(
 /// 重置失败计数并重新启用
 pub async fn reset_failure_count(
     State(state): State<AdminState>,
+    Extension(actor): Extension<AdminActor>,
     Path(id): Path<u64>,
 ) -> impl IntoResponse {
-    match state.service.reset_and_enable(id) {
+    match state.service.reset_and_enable(id, &actor.0) {
         Ok(_) => Json(SuccessResponse::new(format!(
             "凭据 #{} 失败计数已重置并重新启用",
             id
@@ -79,13 +141,62 @@ pub async fn get_credential_balance(
     }
 }
 
+/// POST /api/admin/credentials/:id/reauth
+/// 发起设备码重新登录，返回验证链接和用户码
+pub async fn start_reauth(
+    State(state): State<AdminState>,
+    Extension(actor): Extension<AdminActor>,
+    Path(id): Path<u64>,
+    Json(payload): Json<StartReauthRequest>,
+) -> impl IntoResponse {
+    match state.service.start_reauth(id, payload, &actor.0).await {
+        Ok(session) => Json(session).into_response(),
+        Err(e) => (e.status_code(), Json(e.into_response())).into_response(),
+    }
+}
+
+/// GET /api/admin/credentials/:id/reauth
+/// 查询重新登录状态
+pub async fn get_reauth(State(state): State<AdminState>, Path(id): Path<u64>) -> impl IntoResponse {
+    match state.service.get_reauth(id) {
+        Ok(session) => Json(session).into_response(),
+        Err(e) => (e.status_code(), Json(e.into_response())).into_response(),
+    }
+}
+
+/// POST /api/admin/login
+/// 发起设备码登录，完成后添加为新凭据
+pub async fn start_login(
+    State(state): State<AdminState>,
+    Extension(actor): Extension<AdminActor>,
+    Json(payload): Json<StartLoginRequest>,
+) -> impl IntoResponse {
+    match state.service.start_login(payload, &actor.0).await {
+        Ok(session) => Json(session).into_response(),
+        Err(e) => (e.status_code(), Json(e.into_response())).into_response(),
+    }
+}
+
+/// GET /api/admin/login/:session
+/// 查询登录状态
+pub async fn get_login(
+    State(state): State<AdminState>,
+    Path(session): Path<String>,
+) -> impl IntoResponse {
+    match state.service.get_login(&session) {
+        Ok(session) => Json(session).into_response(),
+        Err(e) => (e.status_code(), Json(e.into_response())).into_response(),
+    }
+}
+
 /// POST /api/admin/credentials
 /// 添加新凭据
 pub async fn add_credential(
     State(state): State<AdminState>,
+    Extension(actor): Extension<AdminActor>,
     Json(payload): Json<AddCredentialRequest>,
 ) -> impl IntoResponse {
-    match state.service.add_credential(payload).await {
+    match state.service.add_credential(payload, &actor.0).await {
         Ok(response) => Json(response).into_response(),
         Err(e) => (e.status_code(), Json(e.into_response())).into_response(),
     }
@@ -95,10 +206,325 @@ pub async fn add_credential(
 /// 删除凭据
 pub async fn delete_credential(
     State(state): State<AdminState>,
+    Extension(actor): Extension<AdminActor>,
     Path(id): Path<u64>,
 ) -> impl IntoResponse {
-    match state.service.delete_credential(id) {
+    match state.service.delete_credential(id, &actor.0) {
         Ok(_) => Json(SuccessResponse::new(format!("凭据 #{} 已删除", id))).into_response(),
         Err(e) => (e.status_code(), Json(e.into_response())).into_response(),
     }
 }
+
+/// GET /api/admin/rate-limits
+/// 获取从上游 429 学到的按凭据限流窗口与节流目标
+pub async fn get_rate_limits(State(state): State<AdminState>) -> impl IntoResponse {
+    Json(state.service.get_rate_limits())
+}
+
+/// GET /api/admin/slo
+/// 获取成功率与延迟 SLO 的错误预算消耗速率
+pub async fn get_slo(State(state): State<AdminState>) -> impl IntoResponse {
+    Json(state.service.get_slo())
+}
+
+/// GET /api/admin/shadow
+/// 获取影子流量统计
+pub async fn get_shadow_report(State(state): State<AdminState>) -> impl IntoResponse {
+    Json(state.service.get_shadow_report())
+}
+
+/// GET /api/admin/raw-capture
+/// 获取原始帧抓取状态
+pub async fn get_raw_capture(State(state): State<AdminState>) -> impl IntoResponse {
+    match state.service.get_raw_capture() {
+        Ok(report) => Json(report).into_response(),
+        Err(e) => (e.status_code(), Json(e.into_response())).into_response(),
+    }
+}
+
+/// POST /api/admin/raw-capture
+/// 预约抓取之后若干个请求的上游原始字节
+pub async fn arm_raw_capture(
+    State(state): State<AdminState>,
+    Extension(actor): Extension<AdminActor>,
+    Json(payload): Json<ArmRawCaptureRequest>,
+) -> impl IntoResponse {
+    match state.service.arm_raw_capture(payload.requests, &actor.0) {
+        Ok(report) => Json(report).into_response(),
+        Err(e) => (e.status_code(), Json(e.into_response())).into_response(),
+    }
+}
+
+/// GET /api/admin/maintenance
+/// 获取维护模式状态与进行中的请求数
+pub async fn get_maintenance(State(state): State<AdminState>) -> impl IntoResponse {
+    match state.service.get_maintenance() {
+        Ok(status) => Json(status).into_response(),
+        Err(e) => (e.status_code(), Json(e.into_response())).into_response(),
+    }
+}
+
+/// POST /api/admin/maintenance
+/// 进入或退出维护模式
+pub async fn set_maintenance(
+    State(state): State<AdminState>,
+    Extension(actor): Extension<AdminActor>,
+    Json(payload): Json<SetMaintenanceRequest>,
+) -> impl IntoResponse {
+    match state.service.set_maintenance(payload, &actor.0) {
+        Ok(status) => Json(status).into_response(),
+        Err(e) => (e.status_code(), Json(e.into_response())).into_response(),
+    }
+}
+
+/// GET /api/admin/events
+/// 以 SSE 推送凭据事件（事件名为事件类型，数据为事件 JSON），供管理面板实时刷新
+pub async fn stream_events(
+    State(state): State<AdminState>,
+) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+    let events = state.service.subscribe_events();
+    let stream = futures::stream::unfold(events, |mut events| async move {
+        loop {
+            match events.recv().await {
+                Ok(event) => {
+                    let data = serde_json::to_string(&event).unwrap_or_default();
+                    let sse = Event::default().event(event.kind()).data(data);
+                    return Some((Ok(sse), events));
+                }
+                Err(RecvError::Lagged(skipped)) => {
+                    tracing::warn!("Admin 事件流落后，丢失 {} 个事件", skipped);
+                }
+                Err(RecvError::Closed) => return None,
+            }
+        }
+    });
+    Sse::new(stream).keep_alive(KeepAlive::default())
+}
+
+/// GET /api/admin/client-keys
+/// 列出客户端密钥（不含完整密钥）
+pub async fn list_client_keys(State(state): State<AdminState>) -> impl IntoResponse {
+    match state.service.list_client_keys() {
+        Ok(response) => Json(response).into_response(),
+        Err(e) => (e.status_code(), Json(e.into_response())).into_response(),
+    }
+}
+
+/// POST /api/admin/client-keys
+/// 开通客户端密钥，响应中包含完整密钥（仅此一次）
+pub async fn provision_client_key(
+    State(state): State<AdminState>,
+    Extension(actor): Extension<AdminActor>,
+    Json(payload): Json<ProvisionClientKeyRequest>,
+) -> impl IntoResponse {
+    match state.service.provision_client_key(payload, &actor.0) {
+        Ok(record) => Json(record).into_response(),
+        Err(e) => (e.status_code(), Json(e.into_response())).into_response(),
+    }
+}
+
+/// DELETE /api/admin/client-keys/:name
+/// 吊销开通的客户端密钥
+pub async fn revoke_client_key(
+    State(state): State<AdminState>,
+    Extension(actor): Extension<AdminActor>,
+    Path(name): Path<String>,
+) -> impl IntoResponse {
+    match state.service.revoke_client_key(&name, &actor.0) {
+        Ok(_) => Json(SuccessResponse::new(format!("客户端密钥 {} 已吊销", name))).into_response(),
+        Err(e) => (e.status_code(), Json(e.into_response())).into_response(),
+    }
+}
+
+/// GET /api/admin/audit
+/// 查询审计日志（最新的在前），支持 `actor`、`action`（前缀）、`target`、`limit` 参数
+pub async fn get_audit_log(
+    State(state): State<AdminState>,
+    Query(query): Query<AuditQuery>,
+) -> impl IntoResponse {
+    Json(state.service.get_audit_log(&query))
+}
+
+/// GET /api/admin/usage
+/// 获取客户端用量（含中途断开请求已生成的 tokens）
+pub async fn get_usage(State(state): State<AdminState>) -> impl IntoResponse {
+    Json(state.service.get_usage())
+}
+
+/// GET /api/admin/usage/summary
+/// 按时间窗口汇总请求数、tokens 与错误率，支持 `since`（默认 `7d`）、`groupBy`（client/credential/model）参数
+pub async fn get_usage_summary(
+    State(state): State<AdminState>,
+    Query(query): Query<UsageSummaryQuery>,
+) -> impl IntoResponse {
+    match state.service.get_usage_summary(&query) {
+        Ok(rows) => Json(rows).into_response(),
+        Err(e) => (e.status_code(), Json(e.into_response())).into_response(),
+    }
+}
+
+/// GET /api/admin/connections
+/// 获取进行中连接的写出统计与慢客户端
+pub async fn get_connections(State(state): State<AdminState>) -> impl IntoResponse {
+    match state.service.get_connections() {
+        Ok(report) => Json(report).into_response(),
+        Err(e) => (e.status_code(), Json(e.into_response())).into_response(),
+    }
+}
+
+/// GET /api/admin/requests
+/// 获取进行中的请求
+pub async fn get_in_flight_requests(State(state): State<AdminState>) -> impl IntoResponse {
+    Json(state.service.get_in_flight_requests())
+}
+
+/// DELETE /api/admin/requests/:id
+/// 中止进行中的请求
+pub async fn cancel_request(
+    State(state): State<AdminState>,
+    Extension(actor): Extension<AdminActor>,
+    Path(id): Path<u64>,
+) -> impl IntoResponse {
+    match state.service.cancel_request(id, &actor.0) {
+        Ok(_) => Json(SuccessResponse::new(format!("请求 #{} 已中止", id))).into_response(),
+        Err(e) => (e.status_code(), Json(e.into_response())).into_response(),
+    }
+}
+
+/// GET /api/admin/abuse-flags
+/// 获取滥用检测标记
+pub async fn get_abuse_flags(State(state): State<AdminState>) -> impl IntoResponse {
+    Json(state.service.get_abuse_flags())
+}
+
+/// DELETE /api/admin/abuse-flags/:client
+/// 清除指定客户端的滥用检测标记
+pub async fn clear_abuse_flag(
+    State(state): State<AdminState>,
+    Extension(actor): Extension<AdminActor>,
+    Path(client): Path<String>,
+) -> impl IntoResponse {
+    match state.service.clear_abuse_flag(&client, &actor.0) {
+        Ok(_) => Json(SuccessResponse::new(format!(
+            "客户端 {} 的标记已清除",
+            client
+        )))
+        .into_response(),
+        Err(e) => (e.status_code(), Json(e.into_response())).into_response(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use axum::body::to_bytes;
+    use axum::http::StatusCode;
+    use axum::response::Response;
+    use chrono::{Duration, Utc};
+
+    use super::*;
+    use crate::admin::service::AdminService;
+    use crate::common::persist;
+    use crate::kiro::health_state;
+    use crate::kiro::model::credentials::KiroCredentials;
+    use crate::kiro::token_manager::MultiTokenManager;
+    use crate::model::config::Config;
+
+    async fn body(response: Response) -> (StatusCode, serde_json::Value) {
+        let status = response.status();
+        let bytes = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (status, serde_json::from_slice(&bytes).unwrap())
+    }
+
+    fn actor() -> Extension<AdminActor> {
+        Extension(AdminActor("ops".to_string()))
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_enable_disable_and_refresh_endpoints() {
+        let path = std::env::temp_dir().join(format!("kiro-admin-{}.json", uuid::Uuid::new_v4()));
+        let expires_at = (Utc::now() + Duration::hours(1)).to_rfc3339();
+        let creds = serde_json::json!([{
+            "id": 1, "refreshToken": "r", "accessToken": "fresh", "expiresAt": expires_at,
+        }]);
+        let cred = KiroCredentials {
+            id: Some(1),
+            refresh_token: Some("r".to_string()),
+            ..Default::default()
+        };
+        // 只读模式下“刷新”改为重新读取凭据文件，无需访问网络
+        let manager = MultiTokenManager::new(
+            Config::default(),
+            vec![cred],
+            None,
+            Some(path.clone()),
+            true,
+        )
+        .unwrap()
+        .with_read_only(true);
+        let state = AdminState::new("sk-admin", AdminService::new(Arc::new(manager)));
+        // 持锁实例刷新后回写的凭据
+        std::fs::write(&path, creds.to_string()).unwrap();
+
+        let (status, json) = body(
+            disable_credential(State(state.clone()), actor(), Path(1))
+                .await
+                .into_response(),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK, "{}", json);
+        assert!(state.service.get_all_credentials().credentials[0].disabled);
+
+        let (status, _) = body(
+            enable_credential(State(state.clone()), actor(), Path(1))
+                .await
+                .into_response(),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(!state.service.get_all_credentials().credentials[0].disabled);
+
+        let (status, json) = body(
+            refresh_credential(State(state.clone()), actor(), Path(1))
+                .await
+                .into_response(),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK, "{}", json);
+        assert_eq!(json["id"], 1);
+        assert_eq!(json["expiresAt"], expires_at);
+
+        let (status, _) = body(
+            refresh_credential(State(state.clone()), actor(), Path(9))
+                .await
+                .into_response(),
+        )
+        .await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+
+        let actions: Vec<String> = state
+            .service
+            .get_audit_log(&AuditQuery::default())
+            .into_iter()
+            .map(|e| e.action)
+            .collect();
+        for action in [
+            "credential.disable",
+            "credential.enable",
+            "credential.refresh",
+        ] {
+            assert!(
+                actions.iter().any(|a| a == action),
+                "缺少审计记录 {}",
+                action
+            );
+        }
+
+        let state_path = health_state::state_path(None, Some(&path)).unwrap();
+        for file in [path.as_path(), state_path.as_path()] {
+            std::fs::remove_file(file).ok();
+            std::fs::remove_file(persist::backup_path(file)).ok();
+        }
+    }
+}
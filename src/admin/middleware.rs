@@ -5,7 +5,7 @@ use std::sync::Arc;
 use axum::{
     body::Body,
     extract::State,
-    http::{Request, StatusCode},
+    http::{Method, Request, StatusCode},
     middleware::Next,
     response::{IntoResponse, Json, Response},
 };
@@ -14,11 +14,22 @@ use super::service::AdminService;
 use super::types::AdminErrorResponse;
 use crate::common::auth;
 
+/// Admin 角色
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AdminRole {
+    /// 只读：只能调用 GET 端点
+    Viewer,
+    /// 操作员：可调用所有端点
+    Operator,
+}
+
 /// Admin API 共享状态
 #[derive(Clone)]
 pub struct AdminState {
-    /// Admin API 密钥
+    /// Admin API 密钥（操作员）
     pub admin_api_key: String,
+    /// 只读密钥
+    pub viewer_keys: Arc<Vec<String>>,
     /// Admin 服务
     pub service: Arc<AdminService>,
 }
@@ -27,24 +38,98 @@ impl AdminState {
     pub fn new(admin_api_key: impl Into<String>, service: AdminService) -> Self {
         Self {
             admin_api_key: admin_api_key.into(),
+            viewer_keys: Arc::new(Vec::new()),
             service: Arc::new(service),
         }
     }
+
+    /// 设置只读密钥（忽略空字符串）
+    pub fn with_viewer_keys(mut self, keys: Vec<String>) -> Self {
+        self.viewer_keys = Arc::new(keys.into_iter().filter(|k| !k.trim().is_empty()).collect());
+        self
+    }
+
+    /// 根据密钥确定角色
+    fn role(&self, key: &str) -> Option<AdminRole> {
+        if auth::constant_time_eq(key, &self.admin_api_key) {
+            return Some(AdminRole::Operator);
+        }
+        self.viewer_keys
+            .iter()
+            .any(|k| auth::constant_time_eq(key, k))
+            .then_some(AdminRole::Viewer)
+    }
+}
+
+/// 审计日志中的操作者
+///
+/// 取自 `x-admin-actor` 请求头（多人共用 Admin Key 时用于区分），未提供时为 `admin`
+#[derive(Debug, Clone)]
+pub struct AdminActor(pub String);
+
+impl AdminActor {
+    fn from_request(request: &Request<Body>) -> Self {
+        let actor = request
+            .headers()
+            .get("x-admin-actor")
+            .and_then(|v| v.to_str().ok())
+            .map(str::trim)
+            .filter(|v| !v.is_empty())
+            .unwrap_or("admin");
+        Self(actor.to_string())
+    }
 }
 
 /// Admin API 认证中间件
+///
+/// 只读密钥只能调用 GET 端点；认证通过后将 `AdminActor` 写入请求扩展
 pub async fn admin_auth_middleware(
     State(state): State<AdminState>,
-    request: Request<Body>,
+    mut request: Request<Body>,
     next: Next,
 ) -> Response {
-    let api_key = auth::extract_api_key(&request);
+    let role = auth::extract_api_key(&request).and_then(|key| state.role(&key));
 
-    match api_key {
-        Some(key) if auth::constant_time_eq(&key, &state.admin_api_key) => next.run(request).await,
+    match role {
+        Some(AdminRole::Viewer) if !matches!(*request.method(), Method::GET | Method::HEAD) => {
+            let error = AdminErrorResponse::permission_error("只读 Admin 密钥不能执行变更操作");
+            (StatusCode::FORBIDDEN, Json(error)).into_response()
+        }
+        Some(_) => {
+            let actor = AdminActor::from_request(&request);
+            request.extensions_mut().insert(actor);
+            next.run(request).await
+        }
         _ => {
             let error = AdminErrorResponse::authentication_error();
             (StatusCode::UNAUTHORIZED, Json(error)).into_response()
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kiro::model::credentials::KiroCredentials;
+    use crate::kiro::token_manager::MultiTokenManager;
+    use crate::model::config::Config;
+
+    #[test]
+    fn test_role() {
+        let tm = MultiTokenManager::new(
+            Config::default(),
+            vec![KiroCredentials::default()],
+            None,
+            None,
+            false,
+        )
+        .unwrap();
+        let state = AdminState::new("sk-admin", AdminService::new(Arc::new(tm)))
+            .with_viewer_keys(vec!["sk-dashboard".to_string(), " ".to_string()]);
+
+        assert_eq!(state.role("sk-admin"), Some(AdminRole::Operator));
+        assert_eq!(state.role("sk-dashboard"), Some(AdminRole::Viewer));
+        assert_eq!(state.role(" "), None);
+        assert_eq!(state.role("sk-wrong"), None);
+    }
+}
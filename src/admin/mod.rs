@@ -8,6 +8,7 @@
 //! - 修改凭据优先级
 //! - 重置失败计数
 //! - 查询凭据余额
+//! - 审计所有变更操作
 //!
 //! # 使用
 //! ```ignore
@@ -16,9 +17,11 @@
 //! let admin_router = create_admin_router(admin_state);
 //! ```
 
+pub mod audit;
 mod error;
 mod handlers;
 mod middleware;
+pub mod retention;
 mod router;
 mod service;
 pub mod types;
@@ -0,0 +1,317 @@
+//! 数据保留期
+//!
+//! 后台任务每 `retention.vacuumIntervalSecs` 秒清理一次过期数据，删除数写入审计日志（操作者 `retention`）：
+//! - 用量账本：默认永久保留，配置 `retention.usageDays`（客户端密钥可用 `usageRetentionDays` 覆盖）后
+//!   删除超过保留期的记录，按客户端记录（操作 `usage.vacuum`）
+//! - 本地产物（`/v1/artifacts/{id}`）：下载链接过期（`artifacts.urlTtlSecs`）后删除（操作 `artifacts.vacuum`）
+//! - 原始帧抓取文件：配置 `retention.captureDays` 后删除更早的文件（操作 `raw-capture.vacuum`）
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+
+use super::audit::AuditLog;
+use crate::anthropic::ArtifactStore;
+use crate::common::client_keys::ClientKeyStore;
+use crate::common::usage::UsageTracker;
+use crate::kiro::raw_capture::RawCapture;
+use crate::model::config::RetentionConfig;
+
+/// 审计日志中的操作者
+const ACTOR: &str = "retention";
+
+/// 过期数据清理任务
+pub struct RetentionVacuum {
+    config: RetentionConfig,
+    usage: Arc<UsageTracker>,
+    client_keys: Arc<ClientKeyStore>,
+    audit: Arc<AuditLog>,
+    artifacts: Option<Arc<ArtifactStore>>,
+    raw_capture: Option<Arc<RawCapture>>,
+}
+
+impl RetentionVacuum {
+    /// 从配置创建，`vacuumIntervalSecs` 为 0 时返回 None
+    pub fn from_config(
+        config: &RetentionConfig,
+        usage: Arc<UsageTracker>,
+        client_keys: Arc<ClientKeyStore>,
+        audit: Arc<AuditLog>,
+    ) -> Option<Self> {
+        if config.vacuum_interval_secs == 0 {
+            return None;
+        }
+        Some(Self {
+            config: config.clone(),
+            usage,
+            client_keys,
+            audit,
+            artifacts: None,
+            raw_capture: None,
+        })
+    }
+
+    /// 同时清理过期的本地产物
+    pub fn with_artifacts(mut self, artifacts: Arc<ArtifactStore>) -> Self {
+        self.artifacts = Some(artifacts);
+        self
+    }
+
+    /// 同时清理过期的原始帧抓取文件
+    pub fn with_raw_capture(mut self, raw_capture: Arc<RawCapture>) -> Self {
+        self.raw_capture = Some(raw_capture);
+        self
+    }
+
+    /// 客户端用量记录的保留天数（0 表示永久保留）
+    fn usage_retention_days(&self, client: &str) -> u32 {
+        self.client_keys
+            .find_by_name(client)
+            .and_then(|(_, key)| key.usage_retention_days)
+            .unwrap_or(self.config.usage_days)
+    }
+
+    /// 清理一次，返回删除的用量记录、产物与抓取文件总数
+    pub fn run(&self, now: DateTime<Utc>) -> usize {
+        self.vacuum_usage(now) + self.vacuum_artifacts() + self.vacuum_captures(now)
+    }
+
+    /// 删除过期的用量记录
+    fn vacuum_usage(&self, now: DateTime<Utc>) -> usize {
+        let Some(ledger) = self.usage.ledger() else {
+            return 0;
+        };
+        let removed = match ledger.vacuum(now, |client| self.usage_retention_days(client)) {
+            Ok(removed) => removed,
+            Err(e) => {
+                tracing::error!("清理过期用量记录失败: {}", e);
+                return 0;
+            }
+        };
+        for (client, count) in &removed {
+            self.audit.record(
+                ACTOR,
+                "usage.vacuum",
+                client.clone(),
+                None,
+                Some(serde_json::json!({
+                    "removed": count,
+                    "retentionDays": self.usage_retention_days(client),
+                })),
+            );
+        }
+        removed.values().sum()
+    }
+
+    /// 删除下载链接已过期的本地产物
+    fn vacuum_artifacts(&self) -> usize {
+        let Some(artifacts) = &self.artifacts else {
+            return 0;
+        };
+        let removed = artifacts.remove_expired();
+        self.record("artifacts.vacuum", "artifacts", removed, None);
+        removed
+    }
+
+    /// 删除超过保留期的原始帧抓取文件
+    fn vacuum_captures(&self, now: DateTime<Utc>) -> usize {
+        let Some(raw_capture) = &self.raw_capture else {
+            return 0;
+        };
+        if self.config.capture_days == 0 {
+            return 0;
+        }
+        let cutoff = now - chrono::Duration::days(i64::from(self.config.capture_days));
+        let removed = raw_capture.remove_older_than(cutoff);
+        self.record(
+            "raw-capture.vacuum",
+            "raw-capture",
+            removed,
+            Some(self.config.capture_days),
+        );
+        removed
+    }
+
+    /// 有删除时写入审计日志
+    fn record(&self, action: &str, target: &str, removed: usize, retention_days: Option<u32>) {
+        if removed == 0 {
+            return;
+        }
+        let mut details = serde_json::json!({"removed": removed});
+        if let Some(days) = retention_days {
+            details["retentionDays"] = days.into();
+        }
+        self.audit
+            .record(ACTOR, action, target, None, Some(details));
+    }
+
+    /// 在后台按间隔清理
+    pub fn spawn(self) {
+        let period = Duration::from_secs(self.config.vacuum_interval_secs);
+        let vacuum = Arc::new(self);
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(period);
+            loop {
+                interval.tick().await;
+                let vacuum = vacuum.clone();
+                if let Err(e) = tokio::task::spawn_blocking(move || vacuum.run(Utc::now())).await {
+                    tracing::error!("数据保留期清理任务异常退出: {}", e);
+                }
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::admin::audit::AuditQuery;
+    use crate::common::ledger::{UsageLedger, UsageOutcome, UsageRecord};
+    use crate::common::privacy::Capabilities;
+    use crate::model::config::ClientKeyConfig;
+
+    fn record(client: &str, days_ago: i64) -> UsageRecord {
+        UsageRecord {
+            time: Utc::now() - chrono::Duration::days(days_ago),
+            client: client.to_string(),
+            credential_id: Some(1),
+            model: None,
+            endpoint: None,
+            input_tokens: 10,
+            output_tokens: 1,
+            duration_ms: 100,
+            outcome: UsageOutcome::Completed,
+        }
+    }
+
+    #[test]
+    fn test_vacuum_uses_key_override_and_audits() {
+        let key: ClientKeyConfig = serde_json::from_value(serde_json::json!({
+            "key": "sk-short", "name": "short", "usageRetentionDays": 7
+        }))
+        .unwrap();
+        let client_keys = Arc::new(ClientKeyStore::new(vec![key]));
+        let usage =
+            Arc::new(UsageTracker::new().with_ledger(UsageLedger::open(None, Capabilities::ALL)));
+        let audit = Arc::new(AuditLog::in_memory());
+        let config = RetentionConfig {
+            usage_days: 90,
+            ..RetentionConfig::default()
+        };
+        let vacuum =
+            RetentionVacuum::from_config(&config, usage.clone(), client_keys, audit.clone())
+                .unwrap();
+
+        let ledger = usage.ledger().unwrap();
+        ledger.append(record("short", 10));
+        ledger.append(record("short", 1));
+        ledger.append(record("other", 10));
+        ledger.append(record("other", 100));
+
+        assert_eq!(vacuum.run(Utc::now()), 2);
+        let entries = audit.query(&AuditQuery::default());
+        let mut targets: Vec<(&str, i64)> = entries
+            .iter()
+            .map(|e| {
+                assert_eq!(
+                    (e.actor.as_str(), e.action.as_str()),
+                    (ACTOR, "usage.vacuum")
+                );
+                let after = e.after.as_ref().unwrap();
+                assert_eq!(after["removed"], 1);
+                (e.target.as_str(), after["retentionDays"].as_i64().unwrap())
+            })
+            .collect();
+        targets.sort();
+        assert_eq!(targets, [("other", 90), ("short", 7)]);
+        assert_eq!(vacuum.run(Utc::now()), 0);
+    }
+
+    /// 在 `dir` 下写一个修改时间为 `days_ago` 天前的文件
+    fn write_aged(dir: &std::path::Path, name: &str, days_ago: u64) {
+        let file = std::fs::File::create(dir.join(name)).unwrap();
+        let modified =
+            std::time::SystemTime::now() - Duration::from_secs(days_ago * 24 * 3600 + 60);
+        file.set_modified(modified).unwrap();
+    }
+
+    #[test]
+    fn test_vacuum_artifacts_and_captures() {
+        use crate::model::config::{ArtifactStoreConfig, Config, TlsBackend};
+
+        let root = std::env::temp_dir().join(format!("kiro-retention-{}", uuid::Uuid::new_v4()));
+        let artifacts_dir = root.join("artifacts");
+        let captures_dir = root.join("captures");
+        let store = ArtifactStore::new(
+            ArtifactStoreConfig {
+                enabled: true,
+                dir: Some(artifacts_dir.display().to_string()),
+                url_ttl_secs: 3600,
+                ..Default::default()
+            },
+            "http://127.0.0.1:8080".to_string(),
+            None,
+            TlsBackend::Rustls,
+        )
+        .unwrap();
+        // 链接已过期的产物（文件与元数据）与仍有效的产物
+        write_aged(&artifacts_dir, "old", 1);
+        write_aged(&artifacts_dir, "old.json", 1);
+        write_aged(&artifacts_dir, "new", 0);
+        write_aged(&artifacts_dir, "new.json", 0);
+
+        let mut config = Config::default();
+        config.raw_capture.dir = Some(captures_dir.display().to_string());
+        let raw_capture = Arc::new(RawCapture::new(&config, Capabilities::ALL));
+        std::fs::create_dir_all(&captures_dir).unwrap();
+        write_aged(&captures_dir, "old.bin", 10);
+        write_aged(&captures_dir, "new.bin", 1);
+        write_aged(&captures_dir, "notes.txt", 10);
+
+        let audit = Arc::new(AuditLog::in_memory());
+        let retention = RetentionConfig {
+            capture_days: 7,
+            ..RetentionConfig::default()
+        };
+        let vacuum = RetentionVacuum::from_config(
+            &retention,
+            Arc::new(UsageTracker::new()),
+            Arc::new(ClientKeyStore::new(Vec::new())),
+            audit.clone(),
+        )
+        .unwrap()
+        .with_artifacts(Arc::new(store))
+        .with_raw_capture(raw_capture);
+
+        assert_eq!(vacuum.run(Utc::now()), 2);
+        assert!(!artifacts_dir.join("old").exists() && !artifacts_dir.join("old.json").exists());
+        assert!(artifacts_dir.join("new").exists());
+        assert!(!captures_dir.join("old.bin").exists());
+        assert!(captures_dir.join("new.bin").exists() && captures_dir.join("notes.txt").exists());
+
+        let mut actions: Vec<(String, i64)> = audit
+            .query(&AuditQuery::default())
+            .iter()
+            .map(|e| {
+                (
+                    e.action.clone(),
+                    e.after.as_ref().unwrap()["removed"].as_i64().unwrap(),
+                )
+            })
+            .collect();
+        actions.sort();
+        assert_eq!(
+            actions,
+            [
+                ("artifacts.vacuum".to_string(), 1),
+                ("raw-capture.vacuum".to_string(), 1)
+            ]
+        );
+        // 没有可删除的数据时不写审计日志
+        assert_eq!(vacuum.run(Utc::now()), 0);
+        assert_eq!(audit.query(&AuditQuery::default()).len(), 2);
+
+        std::fs::remove_dir_all(&root).ok();
+    }
+}
@@ -7,8 +7,13 @@ use axum::{
 
 use super::{
     handlers::{
-        add_credential, delete_credential, get_all_credentials, get_credential_balance,
-        reset_failure_count, set_credential_disabled, set_credential_priority,
+        add_credential, arm_raw_capture, cancel_request, clear_abuse_flag, delete_credential,
+        disable_credential, enable_credential, get_abuse_flags, get_all_credentials, get_audit_log,
+        get_connections, get_credential_balance, get_in_flight_requests, get_login,
+        get_maintenance, get_rate_limits, get_raw_capture, get_reauth, get_shadow_report, get_slo,
+        get_usage, get_usage_summary, list_client_keys, provision_client_key, refresh_credential,
+        reset_failure_count, revoke_client_key, set_credential_disabled, set_credential_priority,
+        set_maintenance, start_login, start_reauth, stream_events,
     },
     middleware::{AdminState, admin_auth_middleware},
 };
@@ -20,9 +25,35 @@ use super::{
 /// - `POST /credentials` - 添加新凭据
 /// - `DELETE /credentials/:id` - 删除凭据
 /// - `POST /credentials/:id/disabled` - 设置凭据禁用状态
+/// - `POST /credentials/:id/enable` - 启用凭据
+/// - `POST /credentials/:id/disable` - 禁用凭据
+/// - `POST /credentials/:id/refresh` - 强制刷新 Token
 /// - `POST /credentials/:id/priority` - 设置凭据优先级
 /// - `POST /credentials/:id/reset` - 重置失败计数
 /// - `GET /credentials/:id/balance` - 获取凭据余额
+/// - `POST /credentials/:id/reauth` - 发起设备码重新登录
+/// - `GET /credentials/:id/reauth` - 查询重新登录状态
+/// - `POST /login` - 发起设备码登录以添加新凭据
+/// - `GET /login/:session` - 查询登录状态
+/// - `GET /abuse-flags` - 获取滥用检测标记
+/// - `DELETE /abuse-flags/:client` - 清除客户端的滥用检测标记
+/// - `GET /client-keys` - 列出客户端密钥
+/// - `POST /client-keys` - 开通客户端密钥
+/// - `DELETE /client-keys/:name` - 吊销开通的客户端密钥
+/// - `GET /usage` - 获取客户端用量
+/// - `GET /usage/summary` - 按时间窗口汇总用量账本（按客户端、凭据或模型分组）
+/// - `GET /connections` - 获取进行中连接的写出统计与慢客户端
+/// - `GET /requests` - 获取进行中的请求
+/// - `DELETE /requests/:id` - 中止进行中的请求
+/// - `GET /audit` - 查询审计日志
+/// - `GET /shadow` - 获取影子流量统计
+/// - `GET /rate-limits` - 获取从上游 429 学到的按凭据限流
+/// - `GET /slo` - 获取成功率与延迟 SLO 的错误预算消耗速率
+/// - `GET /raw-capture` - 获取原始帧抓取状态
+/// - `POST /raw-capture` - 预约抓取之后若干个请求的上游原始字节
+/// - `GET /maintenance` - 获取维护模式状态
+/// - `POST /maintenance` - 进入/退出维护模式
+/// - `GET /events` - 以 SSE 推送凭据事件
 ///
 /// # 认证
 /// 需要 Admin API Key 认证，支持：
@@ -36,9 +67,37 @@ pub fn create_admin_router(state: AdminState) -> Router {
         )
         .route("/credentials/{id}", delete(delete_credential))
         .route("/credentials/{id}/disabled", post(set_credential_disabled))
+        .route("/credentials/{id}/enable", post(enable_credential))
+        .route("/credentials/{id}/disable", post(disable_credential))
+        .route("/credentials/{id}/refresh", post(refresh_credential))
         .route("/credentials/{id}/priority", post(set_credential_priority))
         .route("/credentials/{id}/reset", post(reset_failure_count))
         .route("/credentials/{id}/balance", get(get_credential_balance))
+        .route(
+            "/credentials/{id}/reauth",
+            get(get_reauth).post(start_reauth),
+        )
+        .route("/login", post(start_login))
+        .route("/login/{session}", get(get_login))
+        .route("/abuse-flags", get(get_abuse_flags))
+        .route("/abuse-flags/{client}", delete(clear_abuse_flag))
+        .route(
+            "/client-keys",
+            get(list_client_keys).post(provision_client_key),
+        )
+        .route("/client-keys/{name}", delete(revoke_client_key))
+        .route("/usage", get(get_usage))
+        .route("/usage/summary", get(get_usage_summary))
+        .route("/connections", get(get_connections))
+        .route("/requests", get(get_in_flight_requests))
+        .route("/requests/{id}", delete(cancel_request))
+        .route("/audit", get(get_audit_log))
+        .route("/shadow", get(get_shadow_report))
+        .route("/rate-limits", get(get_rate_limits))
+        .route("/slo", get(get_slo))
+        .route("/raw-capture", get(get_raw_capture).post(arm_raw_capture))
+        .route("/maintenance", get(get_maintenance).post(set_maintenance))
+        .route("/events", get(stream_events))
         .layer(middleware::from_fn_with_state(
             state.clone(),
             admin_auth_middleware,
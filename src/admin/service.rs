@@ -1,14 +1,37 @@
 //! Admin API 业务逻辑服务
 
+use std::collections::HashMap;
 use std::sync::Arc;
 
+use chrono::Utc;
+use parking_lot::Mutex;
+use serde_json::{Value, json};
+use uuid::Uuid;
+
+use crate::common::abuse::AbuseGuard;
+use crate::common::client_keys::{ClientKeyError, ClientKeyStore, ProvisionedKey};
+use crate::common::drain::{DrainReport, DrainTracker};
+use crate::common::in_flight::{InFlightRequest, InFlightRequests};
+use crate::common::ledger::{UsageSummaryQuery, UsageSummaryRow};
+use crate::common::maintenance::{MaintenanceMode, MaintenanceStatus};
+use crate::common::slo::{SloReport, SloTracker};
+use crate::common::usage::UsageTracker;
+use crate::kiro::device_auth::{self, BUILDER_ID_START_URL};
+use crate::kiro::events::TokenEvent;
 use crate::kiro::model::credentials::KiroCredentials;
+use crate::kiro::rate_learning::{RateLearningReport, RatePacer};
+use crate::kiro::raw_capture::{CaptureReport, RawCapture};
+use crate::kiro::shadow::{ShadowMirror, ShadowReport};
 use crate::kiro::token_manager::MultiTokenManager;
+use crate::model::config::ClientKeyConfig;
 
+use super::audit::{AuditEntry, AuditLog, AuditQuery};
 use super::error::AdminServiceError;
 use super::types::{
-    AddCredentialRequest, AddCredentialResponse, BalanceResponse, CredentialStatusItem,
-    CredentialsStatusResponse,
+    AbuseFlagsResponse, AddCredentialRequest, AddCredentialResponse, BalanceResponse,
+    ClientKeysResponse, CredentialStatusItem, CredentialsStatusResponse, LoginSession,
+    ProvisionClientKeyRequest, ReauthSession, ReauthStatus, RefreshCredentialResponse,
+    SetMaintenanceRequest, StartLoginRequest, StartReauthRequest, UsageResponse,
 };
 
 /// Admin 服务
@@ -16,11 +39,395 @@ use super::types::{
 /// 封装所有 Admin API 的业务逻辑
 pub struct AdminService {
     token_manager: Arc<MultiTokenManager>,
+    abuse_guard: Option<Arc<AbuseGuard>>,
+    shadow: Option<Arc<ShadowMirror>>,
+    rate_pacer: Option<Arc<RatePacer>>,
+    slo: Option<Arc<SloTracker>>,
+    raw_capture: Option<Arc<RawCapture>>,
+    usage: Option<Arc<UsageTracker>>,
+    drain: Option<Arc<DrainTracker>>,
+    in_flight: Option<Arc<InFlightRequests>>,
+    client_keys: Option<Arc<ClientKeyStore>>,
+    maintenance: Option<Arc<MaintenanceMode>>,
+    audit: Arc<AuditLog>,
+    /// 各凭据最近一次重新登录会话
+    reauth_sessions: Mutex<HashMap<u64, ReauthSession>>,
+    /// 登录添加新凭据的会话（按会话 ID）
+    login_sessions: Mutex<HashMap<String, LoginSession>>,
 }
 
 impl AdminService {
     pub fn new(token_manager: Arc<MultiTokenManager>) -> Self {
-        Self { token_manager }
+        Self {
+            token_manager,
+            abuse_guard: None,
+            shadow: None,
+            rate_pacer: None,
+            slo: None,
+            raw_capture: None,
+            usage: None,
+            drain: None,
+            in_flight: None,
+            client_keys: None,
+            maintenance: None,
+            audit: Arc::new(AuditLog::in_memory()),
+            reauth_sessions: Mutex::new(HashMap::new()),
+            login_sessions: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// 设置滥用检测器
+    pub fn with_abuse_guard(mut self, guard: Arc<AbuseGuard>) -> Self {
+        self.abuse_guard = Some(guard);
+        self
+    }
+
+    /// 设置影子流量镜像器
+    pub fn with_shadow(mut self, shadow: Arc<ShadowMirror>) -> Self {
+        self.shadow = Some(shadow);
+        self
+    }
+
+    /// 设置按凭据的自动节流器
+    pub fn with_rate_pacer(mut self, pacer: Arc<RatePacer>) -> Self {
+        self.rate_pacer = Some(pacer);
+        self
+    }
+
+    /// 设置 SLO 跟踪
+    pub fn with_slo(mut self, slo: Arc<SloTracker>) -> Self {
+        self.slo = Some(slo);
+        self
+    }
+
+    /// 设置上游原始帧抓取器
+    pub fn with_raw_capture(mut self, capture: Arc<RawCapture>) -> Self {
+        self.raw_capture = Some(capture);
+        self
+    }
+
+    /// 设置用量统计
+    pub fn with_usage_tracker(mut self, usage: Arc<UsageTracker>) -> Self {
+        self.usage = Some(usage);
+        self
+    }
+
+    /// 设置下游连接写出统计
+    pub fn with_drain_tracker(mut self, drain: Arc<DrainTracker>) -> Self {
+        self.drain = Some(drain);
+        self
+    }
+
+    /// 设置进行中的请求登记表
+    pub fn with_in_flight(mut self, in_flight: Arc<InFlightRequests>) -> Self {
+        self.in_flight = Some(in_flight);
+        self
+    }
+
+    /// 设置审计日志
+    pub fn with_audit_log(mut self, audit: Arc<AuditLog>) -> Self {
+        self.audit = audit;
+        self
+    }
+
+    /// 查询审计日志
+    pub fn get_audit_log(&self, query: &AuditQuery) -> Vec<AuditEntry> {
+        self.audit.query(query)
+    }
+
+    /// 凭据当前的可变状态（审计记录用，不含密钥）
+    fn credential_state(&self, id: u64) -> Option<Value> {
+        self.token_manager
+            .snapshot()
+            .entries
+            .into_iter()
+            .find(|e| e.id == id)
+            .map(|e| {
+                json!({
+                    "priority": e.priority,
+                    "disabled": e.disabled,
+                    "failureCount": e.failure_count,
+                    "authMethod": e.auth_method,
+                })
+            })
+    }
+
+    /// 设置客户端密钥存储
+    pub fn with_client_key_store(mut self, store: Arc<ClientKeyStore>) -> Self {
+        self.client_keys = Some(store);
+        self
+    }
+
+    fn client_key_store(&self) -> Result<&ClientKeyStore, AdminServiceError> {
+        self.client_keys
+            .as_deref()
+            .ok_or_else(|| AdminServiceError::InternalError("客户端密钥存储未初始化".to_string()))
+    }
+
+    /// 列出客户端密钥
+    pub fn list_client_keys(&self) -> Result<ClientKeysResponse, AdminServiceError> {
+        Ok(ClientKeysResponse {
+            keys: self.client_key_store()?.list(),
+        })
+    }
+
+    /// 开通客户端密钥
+    pub fn provision_client_key(
+        &self,
+        req: ProvisionClientKeyRequest,
+        actor: &str,
+    ) -> Result<ProvisionedKey, AdminServiceError> {
+        let config = ClientKeyConfig {
+            key: String::new(),
+            name: Some(req.name),
+            local_tools: req.local_tools,
+            stream_policy: req.stream_policy,
+            not_before: req.not_before,
+            expires_at: req.expires_at,
+            access_windows: req.access_windows,
+            scopes: req.scopes,
+            defaults: req.defaults,
+            priority: req.priority,
+            max_duration_secs: req.max_duration_secs,
+            stream_max_duration_secs: req.stream_max_duration_secs,
+            preset: req.preset,
+            usage_retention_days: req.usage_retention_days,
+            response_cache: req.response_cache,
+            log_content: req.log_content,
+            session_tokens: req.session_tokens,
+        };
+        let record = self
+            .client_key_store()?
+            .provision(config)
+            .map_err(client_key_error)?;
+        self.audit.record(
+            actor,
+            "client-key.provision",
+            record.config.name.clone().unwrap_or_default(),
+            None,
+            Some(client_key_audit(&record.config)),
+        );
+        Ok(record)
+    }
+
+    /// 吊销开通的客户端密钥
+    pub fn revoke_client_key(&self, name: &str, actor: &str) -> Result<(), AdminServiceError> {
+        let store = self.client_key_store()?;
+        let before = store
+            .list()
+            .into_iter()
+            .find(|k| k.name == name)
+            .and_then(|k| serde_json::to_value(k).ok());
+        store.revoke(name).map_err(client_key_error)?;
+        self.audit
+            .record(actor, "client-key.revoke", name, before, None);
+        Ok(())
+    }
+
+    /// 获取客户端用量
+    pub fn get_usage(&self) -> UsageResponse {
+        UsageResponse {
+            clients: self
+                .usage
+                .as_ref()
+                .map(|u| u.snapshot())
+                .unwrap_or_default(),
+        }
+    }
+
+    /// 按时间窗口汇总用量账本（按客户端、凭据或模型分组）
+    pub fn get_usage_summary(
+        &self,
+        query: &UsageSummaryQuery,
+    ) -> Result<Vec<UsageSummaryRow>, AdminServiceError> {
+        let ledger = self
+            .usage
+            .as_ref()
+            .and_then(|u| u.ledger())
+            .ok_or_else(|| AdminServiceError::InternalError("用量账本未初始化".to_string()))?;
+        ledger
+            .summary(query)
+            .map_err(AdminServiceError::InvalidQuery)
+    }
+
+    /// 获取进行中连接的写出统计（当前阻塞最久的在前）
+    pub fn get_connections(&self) -> Result<DrainReport, AdminServiceError> {
+        self.drain
+            .as_ref()
+            .map(|d| d.report())
+            .ok_or_else(|| AdminServiceError::InternalError("连接写出统计未初始化".to_string()))
+    }
+
+    /// 获取进行中的请求（最早开始的在前）
+    pub fn get_in_flight_requests(&self) -> Vec<InFlightRequest> {
+        self.in_flight
+            .as_ref()
+            .map(|r| r.list())
+            .unwrap_or_default()
+    }
+
+    /// 中止进行中的请求
+    pub fn cancel_request(&self, id: u64, actor: &str) -> Result<(), AdminServiceError> {
+        let cancelled = self
+            .in_flight
+            .as_ref()
+            .and_then(|r| r.cancel(id))
+            .ok_or(AdminServiceError::RequestNotFound { id })?;
+        tracing::warn!(
+            "管理员 {} 中止了请求 #{}（客户端 {}，{} {}，已进行 {}ms）",
+            actor,
+            id,
+            cancelled.client,
+            cancelled.method,
+            cancelled.path,
+            cancelled.elapsed_ms
+        );
+        self.audit.record(
+            actor,
+            "request.cancel",
+            id.to_string(),
+            serde_json::to_value(&cancelled).ok(),
+            None,
+        );
+        Ok(())
+    }
+
+    /// 获取原始帧抓取状态
+    pub fn get_raw_capture(&self) -> Result<CaptureReport, AdminServiceError> {
+        self.raw_capture
+            .as_ref()
+            .map(|c| c.report())
+            .ok_or_else(|| AdminServiceError::InternalError("原始帧抓取未初始化".to_string()))
+    }
+
+    /// 预约抓取之后的若干个请求
+    pub fn arm_raw_capture(
+        &self,
+        requests: usize,
+        actor: &str,
+    ) -> Result<CaptureReport, AdminServiceError> {
+        let capture = self
+            .raw_capture
+            .as_ref()
+            .ok_or_else(|| AdminServiceError::InternalError("原始帧抓取未初始化".to_string()))?;
+        let before = capture.report().armed;
+        let armed = capture.arm(requests);
+        self.audit.record(
+            actor,
+            "raw-capture.arm",
+            "raw-capture",
+            Some(json!({"armed": before})),
+            Some(json!({"armed": armed})),
+        );
+        Ok(capture.report())
+    }
+
+    /// 设置维护模式
+    pub fn with_maintenance(mut self, maintenance: Arc<MaintenanceMode>) -> Self {
+        self.maintenance = Some(maintenance);
+        self
+    }
+
+    fn maintenance(&self) -> Result<&MaintenanceMode, AdminServiceError> {
+        self.maintenance
+            .as_deref()
+            .ok_or_else(|| AdminServiceError::InternalError("维护模式未初始化".to_string()))
+    }
+
+    /// 获取维护模式状态
+    pub fn get_maintenance(&self) -> Result<MaintenanceStatus, AdminServiceError> {
+        Ok(self.maintenance()?.status())
+    }
+
+    /// 进入或退出维护模式
+    pub fn set_maintenance(
+        &self,
+        req: SetMaintenanceRequest,
+        actor: &str,
+    ) -> Result<MaintenanceStatus, AdminServiceError> {
+        let maintenance = self.maintenance()?;
+        let before = maintenance.current();
+        let (action, after) = if req.enabled {
+            let window = maintenance.enter(req.message, req.retry_after_secs);
+            tracing::warn!("进入维护模式: {}", window.message);
+            ("maintenance.enter", Some(window))
+        } else {
+            maintenance.exit();
+            tracing::info!("退出维护模式");
+            ("maintenance.exit", None)
+        };
+        self.audit.record(
+            actor,
+            action,
+            "maintenance",
+            before.map(|w| json!(w)),
+            after.map(|w| json!(w)),
+        );
+        Ok(maintenance.status())
+    }
+
+    /// 订阅凭据事件
+    pub fn subscribe_events(&self) -> tokio::sync::broadcast::Receiver<TokenEvent> {
+        self.token_manager.subscribe()
+    }
+
+    /// 获取影子流量统计
+    pub fn get_shadow_report(&self) -> ShadowReport {
+        match &self.shadow {
+            Some(shadow) => shadow.report(),
+            None => ShadowReport::empty(&self.token_manager.config().shadow),
+        }
+    }
+
+    /// 获取从上游 429 学到的按凭据限流
+    pub fn get_rate_limits(&self) -> RateLearningReport {
+        match &self.rate_pacer {
+            Some(pacer) => pacer.report(),
+            None => RateLearningReport::empty(&self.token_manager.config().rate_learning),
+        }
+    }
+
+    /// 获取 SLO 状态与错误预算消耗速率
+    pub fn get_slo(&self) -> SloReport {
+        match &self.slo {
+            Some(slo) => slo.report(Utc::now()),
+            None => SloReport::empty(&self.token_manager.config().slo),
+        }
+    }
+
+    /// 获取滥用检测标记
+    pub fn get_abuse_flags(&self) -> AbuseFlagsResponse {
+        AbuseFlagsResponse {
+            enabled: self.token_manager.config().abuse_guard.enabled,
+            flags: self
+                .abuse_guard
+                .as_ref()
+                .map(|g| g.flags())
+                .unwrap_or_default(),
+        }
+    }
+
+    /// 清除指定客户端的滥用检测标记
+    pub fn clear_abuse_flag(&self, client: &str, actor: &str) -> Result<(), AdminServiceError> {
+        let before = self.abuse_guard.as_ref().and_then(|g| {
+            g.flags()
+                .into_iter()
+                .find(|f| f.client == client)
+                .and_then(|f| serde_json::to_value(f).ok())
+        });
+        let cleared = self
+            .abuse_guard
+            .as_ref()
+            .is_some_and(|g| g.clear_flag(client));
+        if cleared {
+            self.audit
+                .record(actor, "abuse-flag.clear", client, before, None);
+            Ok(())
+        } else {
+            Err(AdminServiceError::FlagNotFound {
+                client: client.to_string(),
+            })
+        }
     }
 
     /// 获取所有凭据状态
@@ -33,35 +440,67 @@ impl AdminService {
             .map(|entry| CredentialStatusItem {
                 id: entry.id,
                 priority: entry.priority,
+                weight: entry.weight,
+                reserve: entry.reserve,
+                canary: entry.canary,
                 disabled: entry.disabled,
                 failure_count: entry.failure_count,
+                stall_count: entry.stall_count,
                 is_current: entry.id == snapshot.current_id,
                 expires_at: entry.expires_at,
                 auth_method: entry.auth_method,
                 has_profile_arn: entry.has_profile_arn,
+                source_file: entry.source_file,
+                expiry: entry.expiry,
+                last_refresh_at: entry.last_refresh_at,
+                exhausted_until: entry.exhausted_until,
             })
             .collect();
 
         // 按优先级排序（数字越小优先级越高）
         credentials.sort_by_key(|c| c.priority);
+        let expiring_soon = credentials
+            .iter()
+            .filter(|c| c.expiry.expiring_soon)
+            .map(|c| c.id)
+            .collect();
 
         CredentialsStatusResponse {
             total: snapshot.total,
             available: snapshot.available,
             current_id: snapshot.current_id,
             credentials,
+            expiring_soon,
         }
     }
 
     /// 设置凭据禁用状态
-    pub fn set_disabled(&self, id: u64, disabled: bool) -> Result<(), AdminServiceError> {
+    pub fn set_disabled(
+        &self,
+        id: u64,
+        disabled: bool,
+        actor: &str,
+    ) -> Result<(), AdminServiceError> {
         // 先获取当前凭据 ID，用于判断是否需要切换
         let snapshot = self.token_manager.snapshot();
         let current_id = snapshot.current_id;
+        let before = self.credential_state(id);
 
         self.token_manager
             .set_disabled(id, disabled)
             .map_err(|e| self.classify_error(e, id))?;
+        let action = if disabled {
+            "credential.disable"
+        } else {
+            "credential.enable"
+        };
+        self.audit.record(
+            actor,
+            action,
+            id.to_string(),
+            before,
+            self.credential_state(id),
+        );
 
         // 只有禁用的是当前凭据时才尝试切换到下一个
         if disabled && id == current_id {
@@ -71,17 +510,261 @@ impl AdminService {
     }
 
     /// 设置凭据优先级
-    pub fn set_priority(&self, id: u64, priority: u32) -> Result<(), AdminServiceError> {
+    pub fn set_priority(
+        &self,
+        id: u64,
+        priority: u32,
+        actor: &str,
+    ) -> Result<(), AdminServiceError> {
+        let before = self.credential_state(id);
         self.token_manager
             .set_priority(id, priority)
-            .map_err(|e| self.classify_error(e, id))
+            .map_err(|e| self.classify_error(e, id))?;
+        self.audit.record(
+            actor,
+            "credential.priority",
+            id.to_string(),
+            before,
+            self.credential_state(id),
+        );
+        Ok(())
     }
 
     /// 重置失败计数并重新启用
-    pub fn reset_and_enable(&self, id: u64) -> Result<(), AdminServiceError> {
+    pub fn reset_and_enable(&self, id: u64, actor: &str) -> Result<(), AdminServiceError> {
+        let before = self.credential_state(id);
         self.token_manager
             .reset_and_enable(id)
-            .map_err(|e| self.classify_error(e, id))
+            .map_err(|e| self.classify_error(e, id))?;
+        self.audit.record(
+            actor,
+            "credential.reset",
+            id.to_string(),
+            before,
+            self.credential_state(id),
+        );
+        Ok(())
+    }
+
+    /// 强制刷新凭据的 Token（无论是否即将过期）
+    pub async fn force_refresh(
+        &self,
+        id: u64,
+        actor: &str,
+    ) -> Result<RefreshCredentialResponse, AdminServiceError> {
+        let before = self.credential_state(id);
+        let credentials = self
+            .token_manager
+            .force_refresh(id)
+            .await
+            .map_err(|e| self.classify_balance_error(e, id))?;
+        self.audit.record(
+            actor,
+            "credential.refresh",
+            id.to_string(),
+            before,
+            self.credential_state(id),
+        );
+        Ok(RefreshCredentialResponse {
+            success: true,
+            id,
+            expires_at: credentials.expires_at,
+        })
+    }
+
+    /// 为凭据发起设备码重新登录
+    ///
+    /// 返回验证链接和用户码；后台轮询直到操作员完成授权，随后换上新的 Token 并重新启用凭据。
+    /// 已有未过期的进行中会话时直接返回该会话
+    pub async fn start_reauth(
+        self: &Arc<Self>,
+        id: u64,
+        req: StartReauthRequest,
+        actor: &str,
+    ) -> Result<ReauthSession, AdminServiceError> {
+        let credentials = self
+            .token_manager
+            .credentials_of(id)
+            .ok_or(AdminServiceError::NotFound { id })?;
+        if let Some(session) = self.reauth_sessions.lock().get(&id)
+            && session.status == ReauthStatus::Pending
+            && session.expires_at > Utc::now()
+        {
+            return Ok(session.clone());
+        }
+
+        let config = self.token_manager.config();
+        let region = req
+            .region
+            .or(credentials.region)
+            .unwrap_or_else(|| config.region.clone());
+        let start_url = req.start_url.as_deref().unwrap_or(BUILDER_ID_START_URL);
+        let auth = device_auth::start(&region, start_url, config, self.token_manager.proxy())
+            .await
+            .map_err(|e| AdminServiceError::UpstreamError(e.to_string()))?;
+
+        let started_at = Utc::now();
+        let session = ReauthSession {
+            id,
+            status: ReauthStatus::Pending,
+            user_code: auth.user_code.clone(),
+            verification_uri: auth.verification_uri.clone(),
+            verification_uri_complete: auth.verification_uri_complete.clone(),
+            started_at,
+            expires_at: started_at + chrono::Duration::seconds(auth.expires_in),
+            error: None,
+        };
+        self.reauth_sessions.lock().insert(id, session.clone());
+        tracing::info!("凭据 #{} 已发起重新登录，用户码 {}", id, auth.user_code);
+
+        let service = self.clone();
+        let actor = actor.to_string();
+        tokio::spawn(async move {
+            let manager = &service.token_manager;
+            let before = service.credential_state(id);
+            let result = match device_auth::poll(&auth, manager.config(), manager.proxy()).await {
+                Ok(token) => manager.reauthenticate(id, |cred| auth.apply(token, cred)),
+                Err(e) => Err(e),
+            };
+            let error = match result {
+                Ok(()) => {
+                    service.audit.record(
+                        &actor,
+                        "credential.reauth",
+                        id.to_string(),
+                        before,
+                        service.credential_state(id),
+                    );
+                    None
+                }
+                Err(e) => {
+                    tracing::warn!("凭据 #{} 重新登录失败: {}", id, e);
+                    Some(e.to_string())
+                }
+            };
+            if let Some(session) = service.reauth_sessions.lock().get_mut(&id)
+                && session.started_at == started_at
+            {
+                session.status = if error.is_none() {
+                    ReauthStatus::Completed
+                } else {
+                    ReauthStatus::Failed
+                };
+                session.error = error;
+            }
+        });
+
+        Ok(session)
+    }
+
+    /// 获取凭据最近一次重新登录的状态
+    pub fn get_reauth(&self, id: u64) -> Result<ReauthSession, AdminServiceError> {
+        self.reauth_sessions
+            .lock()
+            .get(&id)
+            .cloned()
+            .ok_or(AdminServiceError::ReauthNotStarted { id })
+    }
+
+    /// 发起设备码登录，完成授权后添加为新凭据
+    ///
+    /// 与 `kiro login` 相同，但由运行中的实例添加（校验 Token 并回写凭据文件），无需重启
+    pub async fn start_login(
+        self: &Arc<Self>,
+        req: StartLoginRequest,
+        actor: &str,
+    ) -> Result<LoginSession, AdminServiceError> {
+        let config = self.token_manager.config();
+        let region = req.region.unwrap_or_else(|| config.region.clone());
+        let start_url = req.start_url.as_deref().unwrap_or(BUILDER_ID_START_URL);
+        let auth = device_auth::start(&region, start_url, config, self.token_manager.proxy())
+            .await
+            .map_err(|e| AdminServiceError::UpstreamError(e.to_string()))?;
+
+        let started_at = Utc::now();
+        let session = LoginSession {
+            session_id: Uuid::new_v4().to_string(),
+            status: ReauthStatus::Pending,
+            user_code: auth.user_code.clone(),
+            verification_uri: auth.verification_uri.clone(),
+            verification_uri_complete: auth.verification_uri_complete.clone(),
+            started_at,
+            expires_at: started_at + chrono::Duration::seconds(auth.expires_in),
+            credential_id: None,
+            error: None,
+        };
+        let session_id = session.session_id.clone();
+        self.login_sessions
+            .lock()
+            .insert(session_id.clone(), session.clone());
+        tracing::info!("已发起登录添加新凭据，用户码 {}", auth.user_code);
+
+        let service = self.clone();
+        let actor = actor.to_string();
+        tokio::spawn(async move {
+            let manager = &service.token_manager;
+            let result = match device_auth::poll(&auth, manager.config(), manager.proxy()).await {
+                Ok(token) => {
+                    let credentials = device_auth::login_credentials(
+                        &auth,
+                        token,
+                        req.priority,
+                        req.profile_arn,
+                        manager.config(),
+                        manager.proxy(),
+                    )
+                    .await;
+                    manager.add_credential(credentials).await
+                }
+                Err(e) => Err(e),
+            };
+            service.finish_login(&session_id, &actor, result);
+        });
+
+        Ok(session)
+    }
+
+    /// 记录登录添加新凭据的结果：成功时写入审计日志，并更新登录会话的状态
+    fn finish_login(&self, session_id: &str, actor: &str, result: anyhow::Result<u64>) {
+        let result = match result {
+            Ok(id) => {
+                self.audit.record(
+                    actor,
+                    "credential.login",
+                    id.to_string(),
+                    None,
+                    self.credential_state(id),
+                );
+                Ok(id)
+            }
+            Err(e) => {
+                tracing::warn!("登录添加新凭据失败: {}", e);
+                Err(e.to_string())
+            }
+        };
+        if let Some(session) = self.login_sessions.lock().get_mut(session_id) {
+            match result {
+                Ok(id) => {
+                    session.status = ReauthStatus::Completed;
+                    session.credential_id = Some(id);
+                }
+                Err(error) => {
+                    session.status = ReauthStatus::Failed;
+                    session.error = Some(error);
+                }
+            }
+        }
+    }
+
+    /// 获取登录会话的状态
+    pub fn get_login(&self, session: &str) -> Result<LoginSession, AdminServiceError> {
+        self.login_sessions
+            .lock()
+            .get(session)
+            .cloned()
+            .ok_or_else(|| AdminServiceError::LoginNotFound {
+                session: session.to_string(),
+            })
     }
 
     /// 获取凭据余额
@@ -116,6 +799,7 @@ impl AdminService {
     pub async fn add_credential(
         &self,
         req: AddCredentialRequest,
+        actor: &str,
     ) -> Result<AddCredentialResponse, AdminServiceError> {
         // 构建凭据对象
         let new_cred = KiroCredentials {
@@ -128,8 +812,14 @@ impl AdminService {
             client_id: req.client_id,
             client_secret: req.client_secret,
             priority: req.priority,
+            weight: req.weight,
+            reserve: req.reserve,
             region: req.region,
             machine_id: req.machine_id,
+            api_region: req.api_region,
+            api_endpoint: req.api_endpoint,
+            refresh_token_obtained_at: None,
+            source_file: None,
         };
 
         // 调用 token_manager 添加凭据
@@ -138,6 +828,13 @@ impl AdminService {
             .add_credential(new_cred)
             .await
             .map_err(|e| self.classify_add_error(e))?;
+        self.audit.record(
+            actor,
+            "credential.add",
+            credential_id.to_string(),
+            None,
+            self.credential_state(credential_id),
+        );
 
         Ok(AddCredentialResponse {
             success: true,
@@ -147,10 +844,14 @@ impl AdminService {
     }
 
     /// 删除凭据
-    pub fn delete_credential(&self, id: u64) -> Result<(), AdminServiceError> {
+    pub fn delete_credential(&self, id: u64, actor: &str) -> Result<(), AdminServiceError> {
+        let before = self.credential_state(id);
         self.token_manager
             .delete_credential(id)
-            .map_err(|e| self.classify_delete_error(e, id))
+            .map_err(|e| self.classify_delete_error(e, id))?;
+        self.audit
+            .record(actor, "credential.delete", id.to_string(), before, None);
+        Ok(())
     }
 
     /// 分类简单操作错误（set_disabled, set_priority, reset_and_enable）
@@ -232,3 +933,75 @@ impl AdminService {
         }
     }
 }
+
+fn client_key_error(e: ClientKeyError) -> AdminServiceError {
+    match e {
+        ClientKeyError::Invalid(msg) => AdminServiceError::InvalidClientKey(msg),
+        ClientKeyError::NotFound(name) => AdminServiceError::ClientKeyNotFound { name },
+        ClientKeyError::Persist(_) => AdminServiceError::InternalError(e.to_string()),
+    }
+}
+
+/// 客户端密钥的审计值（不含密钥本身）
+fn client_key_audit(config: &ClientKeyConfig) -> Value {
+    json!({
+        "scopes": config.scopes,
+        "localTools": config.local_tools,
+        "streamPolicy": config.stream_policy,
+        "notBefore": config.not_before,
+        "expiresAt": config.expires_at,
+        "accessWindows": config.access_windows,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::config::Config;
+
+    #[test]
+    fn test_finish_login_updates_session_and_audits() {
+        let manager = MultiTokenManager::new(
+            Config::default(),
+            vec![KiroCredentials::default()],
+            None,
+            None,
+            false,
+        )
+        .unwrap();
+        let service = AdminService::new(Arc::new(manager));
+        let session = |id: &str| LoginSession {
+            session_id: id.to_string(),
+            status: ReauthStatus::Pending,
+            user_code: "ABCD-EFGH".to_string(),
+            verification_uri: "https://device.sso.us-east-1.amazonaws.com/".to_string(),
+            verification_uri_complete: None,
+            started_at: Utc::now(),
+            expires_at: Utc::now(),
+            credential_id: None,
+            error: None,
+        };
+        for id in ["ok", "failed"] {
+            service
+                .login_sessions
+                .lock()
+                .insert(id.to_string(), session(id));
+        }
+
+        service.finish_login("ok", "ops", Ok(1));
+        service.finish_login("failed", "ops", Err(anyhow::anyhow!("设备码已过期")));
+
+        let ok = service.get_login("ok").unwrap();
+        assert_eq!(ok.status, ReauthStatus::Completed);
+        assert_eq!(ok.credential_id, Some(1));
+        let failed = service.get_login("failed").unwrap();
+        assert_eq!(failed.status, ReauthStatus::Failed);
+        assert_eq!(failed.error.as_deref(), Some("设备码已过期"));
+        assert!(service.get_login("unknown").is_err());
+
+        let audit = service.get_audit_log(&AuditQuery::default());
+        assert_eq!(audit.len(), 1);
+        assert_eq!(audit[0].action, "credential.login");
+        assert_eq!(audit[0].target, "1");
+    }
+}
@@ -1,7 +1,17 @@
 //! Admin API 类型定义
 
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 
+use crate::common::abuse::AbuseFlag;
+use crate::common::client_keys::ClientKeySummary;
+use crate::common::usage::ClientUsage;
+use crate::kiro::expiry::ExpiryForecast;
+use crate::model::config::{
+    AccessWindow, ClientKeyScopes, LocalToolKind, LogContentLimit, PriorityClass, RequestDefaults,
+    ResponseCacheMode, StreamPolicy,
+};
+
 // ============ 凭据状态 ============
 
 /// 所有凭据状态响应
@@ -16,6 +26,8 @@ pub struct CredentialsStatusResponse {
     pub current_id: u64,
     /// 各凭据状态列表
     pub credentials: Vec<CredentialStatusItem>,
+    /// 即将需要重新登录的凭据 ID
+    pub expiring_soon: Vec<u64>,
 }
 
 /// 单个凭据的状态信息
@@ -26,10 +38,18 @@ pub struct CredentialStatusItem {
     pub id: u64,
     /// 优先级（数字越小优先级越高）
     pub priority: u32,
+    /// 加权选择时的权重
+    pub weight: u32,
+    /// 是否为备用凭据
+    pub reserve: bool,
+    /// 是否处于灰度期
+    pub canary: bool,
     /// 是否被禁用
     pub disabled: bool,
     /// 连续失败次数
     pub failure_count: u32,
+    /// 流式响应卡顿次数
+    pub stall_count: u64,
     /// 是否为当前活跃凭据
     pub is_current: bool,
     /// Token 过期时间（RFC3339 格式）
@@ -38,6 +58,14 @@ pub struct CredentialStatusItem {
     pub auth_method: Option<String>,
     /// 是否有 Profile ARN
     pub has_profile_arn: bool,
+    /// 来源文件（来自凭据目录或 `credentialSources` 时）
+    pub source_file: Option<String>,
+    /// 到期预估
+    pub expiry: ExpiryForecast,
+    /// 最近一次刷新 Token 的时间（RFC3339 格式）
+    pub last_refresh_at: Option<String>,
+    /// 额度用尽后预计恢复的时间（RFC3339 格式）
+    pub exhausted_until: Option<String>,
 }
 
 // ============ 操作请求 ============
@@ -50,6 +78,166 @@ pub struct SetDisabledRequest {
     pub disabled: bool,
 }
 
+/// 进入/退出维护模式请求
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SetMaintenanceRequest {
+    /// 是否处于维护模式
+    pub enabled: bool,
+    /// 返回给客户端的提示（默认使用配置 maintenance.message）
+    #[serde(default)]
+    pub message: Option<String>,
+    /// Retry-After 秒数（默认使用配置 maintenance.retryAfterSecs）
+    #[serde(default)]
+    pub retry_after_secs: Option<u64>,
+}
+
+/// 发起重新登录请求
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StartReauthRequest {
+    /// 登录入口（默认 AWS Builder ID；IAM Identity Center 填组织的 start URL）
+    #[serde(default)]
+    pub start_url: Option<String>,
+    /// OIDC 区域（默认凭据的 region，其次 config.json 的 region）
+    #[serde(default)]
+    pub region: Option<String>,
+}
+
+/// 重新登录（或登录添加新凭据）状态
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ReauthStatus {
+    /// 等待操作员在浏览器中完成授权
+    Pending,
+    /// 已换上新的 Token（登录时为已添加凭据）
+    Completed,
+    /// 授权失败、被拒绝或设备码过期
+    Failed,
+}
+
+/// 重新登录会话
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReauthSession {
+    /// 凭据 ID
+    pub id: u64,
+    pub status: ReauthStatus,
+    /// 需要在验证页面输入的用户码
+    pub user_code: String,
+    pub verification_uri: String,
+    /// 已带上用户码的验证链接
+    pub verification_uri_complete: Option<String>,
+    pub started_at: DateTime<Utc>,
+    /// 设备码过期时间
+    pub expires_at: DateTime<Utc>,
+    /// 失败原因
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// 登录添加新凭据请求
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StartLoginRequest {
+    /// 登录入口（默认 AWS Builder ID；IAM Identity Center 填组织的 start URL）
+    #[serde(default)]
+    pub start_url: Option<String>,
+    /// OIDC 区域（默认 config.json 的 region）
+    #[serde(default)]
+    pub region: Option<String>,
+    /// 新凭据的优先级
+    #[serde(default)]
+    pub priority: u32,
+    /// Profile ARN（设备授权不返回，需要时手动填写）
+    #[serde(default)]
+    pub profile_arn: Option<String>,
+}
+
+/// 登录添加新凭据的会话
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LoginSession {
+    /// 会话 ID（查询状态时使用）
+    pub session_id: String,
+    pub status: ReauthStatus,
+    /// 需要在验证页面输入的用户码
+    pub user_code: String,
+    pub verification_uri: String,
+    /// 已带上用户码的验证链接
+    pub verification_uri_complete: Option<String>,
+    pub started_at: DateTime<Utc>,
+    /// 设备码过期时间
+    pub expires_at: DateTime<Utc>,
+    /// 添加的凭据 ID（完成后）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub credential_id: Option<u64>,
+    /// 失败原因
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// 预约原始帧抓取请求
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ArmRawCaptureRequest {
+    /// 抓取之后的请求数（0 表示取消，最多 20）
+    pub requests: usize,
+}
+
+/// 开通客户端密钥请求（密钥由服务端生成）
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProvisionClientKeyRequest {
+    /// 客户端名称（唯一，用于吊销）
+    pub name: String,
+    /// 使用范围限制
+    #[serde(default)]
+    pub scopes: ClientKeyScopes,
+    /// 默认生成参数
+    #[serde(default)]
+    pub defaults: RequestDefaults,
+    /// 准入队列中的优先级类别
+    #[serde(default)]
+    pub priority: PriorityClass,
+    /// 非流式请求的最长时长（秒）
+    #[serde(default)]
+    pub max_duration_secs: Option<u64>,
+    /// 流式请求的最长时长（秒）
+    #[serde(default)]
+    pub stream_max_duration_secs: Option<u64>,
+    /// 请求预设
+    #[serde(default)]
+    pub preset: Option<String>,
+    /// 用量记录的保留天数
+    #[serde(default)]
+    pub usage_retention_days: Option<u32>,
+    /// 允许代理代为执行的本地工具
+    #[serde(default)]
+    pub local_tools: Vec<LocalToolKind>,
+    /// 流式策略
+    #[serde(default)]
+    pub stream_policy: StreamPolicy,
+    /// 生效时间
+    #[serde(default)]
+    pub not_before: Option<DateTime<Utc>>,
+    /// 过期时间
+    #[serde(default)]
+    pub expires_at: Option<DateTime<Utc>>,
+    /// 允许访问的时段
+    #[serde(default)]
+    pub access_windows: Vec<AccessWindow>,
+    /// 非流式响应缓存
+    #[serde(default)]
+    pub response_cache: ResponseCacheMode,
+    /// 日志中保留的内容长度
+    #[serde(default)]
+    pub log_content: Option<LogContentLimit>,
+    /// 允许换取短期会话 Token
+    #[serde(default)]
+    pub session_tokens: bool,
+}
+
 /// 修改优先级请求
 #[derive(Debug, Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -79,6 +267,13 @@ pub struct AddCredentialRequest {
     #[serde(default)]
     pub priority: u32,
 
+    /// 加权选择时的权重（可选，默认 1）
+    pub weight: Option<u32>,
+
+    /// 备用凭据（可选，默认 false）
+    #[serde(default)]
+    pub reserve: bool,
+
     /// 凭据级 Region 配置（用于 OIDC token 刷新）
     /// 未配置时回退到 config.json 的全局 region
     pub region: Option<String>,
@@ -86,6 +281,12 @@ pub struct AddCredentialRequest {
     /// 凭据级 Machine ID（可选，64 位字符串）
     /// 未配置时回退到 config.json 的 machineId
     pub machine_id: Option<String>,
+
+    /// 凭据签发所在的 API Region（可选）
+    pub api_region: Option<String>,
+
+    /// 自定义上游端点（可选）
+    pub api_endpoint: Option<String>,
 }
 
 fn default_auth_method() -> String {
@@ -124,8 +325,46 @@ pub struct BalanceResponse {
     pub next_reset_at: Option<f64>,
 }
 
+// ============ 滥用检测 ============
+
+/// 滥用检测标记列表响应
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AbuseFlagsResponse {
+    /// 是否启用滥用检测
+    pub enabled: bool,
+    /// 被标记的客户端
+    pub flags: Vec<AbuseFlag>,
+}
+
+/// 客户端密钥列表响应
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ClientKeysResponse {
+    pub keys: Vec<ClientKeySummary>,
+}
+
+/// 客户端用量响应
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UsageResponse {
+    /// 按客户端名称排序的累计用量
+    pub clients: Vec<ClientUsage>,
+}
+
 // ============ 通用响应 ============
 
+/// 强制刷新 Token 响应
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RefreshCredentialResponse {
+    pub success: bool,
+    /// 凭据 ID
+    pub id: u64,
+    /// 新 Token 的过期时间（RFC3339 格式）
+    pub expires_at: Option<String>,
+}
+
 /// 操作成功响应
 #[derive(Debug, Serialize)]
 pub struct SuccessResponse {
@@ -173,6 +412,10 @@ impl AdminErrorResponse {
         Self::new("authentication_error", "Invalid or missing admin API key")
     }
 
+    pub fn permission_error(message: impl Into<String>) -> Self {
+        Self::new("permission_error", message)
+    }
+
     pub fn not_found(message: impl Into<String>) -> Self {
         Self::new("not_found", message)
     }
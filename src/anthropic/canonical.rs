@@ -0,0 +1,738 @@
+//! 规范化的内部消息/事件模型
+//!
+//! 与具体协议无关的请求（消息、内容块、工具调用）和响应事件（内容增量、用量、停止原因）表示，
+//! 作为各前端协议与 Kiro 后端之间的中间格式。新增兼容层只需实现与这里的互相转换，
+//! 而不是与每个已有格式两两映射。
+
+use std::fmt;
+
+use serde_json::{Value, json};
+
+use super::stop_reason::StopReason;
+use super::stream::SseEvent;
+use super::types;
+
+/// 消息角色
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    User,
+    Assistant,
+}
+
+/// 工具调用
+#[derive(Debug, Clone, PartialEq)]
+pub struct ToolCall {
+    pub id: String,
+    pub name: String,
+    pub input: Value,
+}
+
+/// 内容块
+#[derive(Debug, Clone, PartialEq)]
+pub enum ContentBlock {
+    Text(String),
+    Thinking(String),
+    /// base64 图片
+    Image {
+        media_type: String,
+        data: String,
+    },
+    ToolUse(ToolCall),
+    ToolResult {
+        tool_use_id: String,
+        content: String,
+        is_error: bool,
+    },
+}
+
+/// 消息
+#[derive(Debug, Clone, PartialEq)]
+pub struct Message {
+    pub role: Role,
+    pub content: Vec<ContentBlock>,
+}
+
+/// 工具定义
+#[derive(Debug, Clone, PartialEq)]
+pub struct ToolSpec {
+    pub name: String,
+    pub description: String,
+    pub input_schema: Value,
+}
+
+/// 请求
+#[derive(Debug, Clone, PartialEq)]
+#[allow(dead_code)]
+pub struct Request {
+    pub model: String,
+    pub system: Vec<String>,
+    pub messages: Vec<Message>,
+    pub tools: Vec<ToolSpec>,
+    pub max_tokens: i32,
+    pub stream: bool,
+    pub thinking: bool,
+}
+
+/// 用量
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Usage {
+    pub input_tokens: i32,
+    pub output_tokens: i32,
+}
+
+/// 内容块类型（流式事件中块开始时携带）
+#[derive(Debug, Clone, PartialEq)]
+pub enum BlockKind {
+    Text,
+    Thinking,
+    ToolUse { id: String, name: String },
+}
+
+/// 响应事件
+#[derive(Debug, Clone, PartialEq)]
+pub enum Event {
+    MessageStart {
+        id: String,
+        model: String,
+        usage: Usage,
+    },
+    BlockStart {
+        index: i32,
+        kind: BlockKind,
+    },
+    TextDelta {
+        index: i32,
+        text: String,
+    },
+    ThinkingDelta {
+        index: i32,
+        thinking: String,
+    },
+    /// 工具输入 JSON 片段
+    ToolInputDelta {
+        index: i32,
+        partial_json: String,
+    },
+    BlockStop {
+        index: i32,
+    },
+    MessageDelta {
+        stop_reason: StopReason,
+        usage: Usage,
+    },
+    MessageStop,
+    Ping,
+    Error {
+        error_type: String,
+        message: String,
+    },
+}
+
+/// 转换失败原因
+#[derive(Debug, Clone, PartialEq)]
+pub enum CanonicalError {
+    /// 不支持的角色
+    Role(String),
+    /// 不支持或缺少字段的内容块
+    Block(String),
+    /// 不支持或缺少字段的事件
+    Event(String),
+}
+
+impl fmt::Display for CanonicalError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CanonicalError::Role(role) => write!(f, "不支持的消息角色: {}", role),
+            CanonicalError::Block(block) => write!(f, "无法转换的内容块: {}", block),
+            CanonicalError::Event(event) => write!(f, "无法转换的事件: {}", event),
+        }
+    }
+}
+
+impl std::error::Error for CanonicalError {}
+
+/// 转换为规范模型
+pub trait ToCanonical {
+    type Canonical;
+
+    fn to_canonical(&self) -> Result<Self::Canonical, CanonicalError>;
+}
+
+/// 从规范模型转换（供其他前端输出与回放使用）
+#[allow(dead_code)]
+pub trait FromCanonical<C>: Sized {
+    fn from_canonical(value: &C) -> Self;
+}
+
+// === Anthropic 请求 ===
+
+impl ToCanonical for types::MessagesRequest {
+    type Canonical = Request;
+
+    fn to_canonical(&self) -> Result<Request, CanonicalError> {
+        Ok(Request {
+            model: self.model.clone(),
+            system: self
+                .system
+                .iter()
+                .flatten()
+                .map(|s| s.text.clone())
+                .collect(),
+            messages: self
+                .messages
+                .iter()
+                .map(ToCanonical::to_canonical)
+                .collect::<Result<_, _>>()?,
+            tools: self
+                .tools
+                .iter()
+                .flatten()
+                .map(|t| ToolSpec {
+                    name: t.name.clone(),
+                    description: t.description.clone(),
+                    input_schema: json!(t.input_schema),
+                })
+                .collect(),
+            max_tokens: self.max_tokens,
+            stream: self.stream,
+            thinking: self
+                .thinking
+                .as_ref()
+                .is_some_and(|t| t.thinking_type == "enabled"),
+        })
+    }
+}
+
+impl FromCanonical<Request> for types::MessagesRequest {
+    fn from_canonical(request: &Request) -> Self {
+        types::MessagesRequest {
+            model: request.model.clone(),
+            max_tokens: request.max_tokens,
+            messages: request
+                .messages
+                .iter()
+                .map(types::Message::from_canonical)
+                .collect(),
+            stream: request.stream,
+            system: (!request.system.is_empty()).then(|| {
+                request
+                    .system
+                    .iter()
+                    .map(|text| types::SystemMessage { text: text.clone() })
+                    .collect()
+            }),
+            tools: (!request.tools.is_empty()).then(|| {
+                request
+                    .tools
+                    .iter()
+                    .map(|tool| types::Tool {
+                        tool_type: None,
+                        name: tool.name.clone(),
+                        description: tool.description.clone(),
+                        input_schema: tool
+                            .input_schema
+                            .as_object()
+                            .map(|schema| schema.clone().into_iter().collect())
+                            .unwrap_or_default(),
+                        max_uses: None,
+                    })
+                    .collect()
+            }),
+            tool_choice: None,
+            thinking: request.thinking.then(|| types::Thinking {
+                thinking_type: "enabled".to_string(),
+                budget_tokens: types::default_budget_tokens(),
+            }),
+            metadata: None,
+            temperature: None,
+            stop_sequences: None,
+        }
+    }
+}
+
+impl ToCanonical for types::Message {
+    type Canonical = Message;
+
+    fn to_canonical(&self) -> Result<Message, CanonicalError> {
+        let role = match self.role.as_str() {
+            "user" => Role::User,
+            "assistant" => Role::Assistant,
+            other => return Err(CanonicalError::Role(other.to_string())),
+        };
+        let content = match &self.content {
+            Value::String(text) => vec![ContentBlock::Text(text.clone())],
+            Value::Array(blocks) => blocks
+                .iter()
+                .map(block_to_canonical)
+                .collect::<Result<_, _>>()?,
+            other => return Err(CanonicalError::Block(other.to_string())),
+        };
+        Ok(Message { role, content })
+    }
+}
+
+impl FromCanonical<Message> for types::Message {
+    fn from_canonical(message: &Message) -> Self {
+        types::Message {
+            role: match message.role {
+                Role::User => "user",
+                Role::Assistant => "assistant",
+            }
+            .to_string(),
+            content: Value::Array(message.content.iter().map(block_from_canonical).collect()),
+        }
+    }
+}
+
+fn block_to_canonical(block: &Value) -> Result<ContentBlock, CanonicalError> {
+    let invalid = || CanonicalError::Block(block.to_string());
+    let str_field = |name: &str| block[name].as_str().map(|s| s.to_string());
+    Ok(match block["type"].as_str().ok_or_else(invalid)? {
+        "text" => ContentBlock::Text(str_field("text").ok_or_else(invalid)?),
+        "thinking" => ContentBlock::Thinking(str_field("thinking").ok_or_else(invalid)?),
+        "image" => {
+            let source = &block["source"];
+            ContentBlock::Image {
+                media_type: source["media_type"]
+                    .as_str()
+                    .ok_or_else(invalid)?
+                    .to_string(),
+                data: source["data"].as_str().ok_or_else(invalid)?.to_string(),
+            }
+        }
+        "tool_use" => ContentBlock::ToolUse(ToolCall {
+            id: str_field("id").ok_or_else(invalid)?,
+            name: str_field("name").ok_or_else(invalid)?,
+            input: block.get("input").cloned().unwrap_or_else(|| json!({})),
+        }),
+        "tool_result" => ContentBlock::ToolResult {
+            tool_use_id: str_field("tool_use_id").ok_or_else(invalid)?,
+            content: tool_result_text(&block["content"]),
+            is_error: block["is_error"].as_bool().unwrap_or(false),
+        },
+        _ => return Err(invalid()),
+    })
+}
+
+/// tool_result 的 content 可以是字符串或文本块数组
+fn tool_result_text(content: &Value) -> String {
+    match content {
+        Value::String(text) => text.clone(),
+        Value::Array(parts) => parts
+            .iter()
+            .filter_map(|p| p["text"].as_str())
+            .collect::<Vec<_>>()
+            .join("\n"),
+        _ => String::new(),
+    }
+}
+
+#[allow(dead_code)]
+fn block_from_canonical(block: &ContentBlock) -> Value {
+    match block {
+        ContentBlock::Text(text) => json!({"type": "text", "text": text}),
+        ContentBlock::Thinking(thinking) => json!({"type": "thinking", "thinking": thinking}),
+        ContentBlock::Image { media_type, data } => json!({
+            "type": "image",
+            "source": {"type": "base64", "media_type": media_type, "data": data}
+        }),
+        ContentBlock::ToolUse(call) => json!({
+            "type": "tool_use",
+            "id": call.id,
+            "name": call.name,
+            "input": call.input
+        }),
+        ContentBlock::ToolResult {
+            tool_use_id,
+            content,
+            is_error,
+        } => json!({
+            "type": "tool_result",
+            "tool_use_id": tool_use_id,
+            "content": content,
+            "is_error": is_error
+        }),
+    }
+}
+
+// === Anthropic 流式事件 ===
+
+impl ToCanonical for SseEvent {
+    type Canonical = Event;
+
+    fn to_canonical(&self) -> Result<Event, CanonicalError> {
+        let data = &self.data;
+        let invalid = || CanonicalError::Event(format!("{} {}", self.event, data));
+        let index = || data["index"].as_i64().map(|i| i as i32).ok_or_else(invalid);
+        let usage = |usage: &Value| Usage {
+            input_tokens: usage["input_tokens"].as_i64().unwrap_or_default() as i32,
+            output_tokens: usage["output_tokens"].as_i64().unwrap_or_default() as i32,
+        };
+        Ok(match self.event.as_str() {
+            "message_start" => {
+                let message = &data["message"];
+                Event::MessageStart {
+                    id: message["id"].as_str().unwrap_or_default().to_string(),
+                    model: message["model"].as_str().unwrap_or_default().to_string(),
+                    usage: usage(&message["usage"]),
+                }
+            }
+            "content_block_start" => {
+                let block = &data["content_block"];
+                let kind = match block["type"].as_str() {
+                    Some("text") => BlockKind::Text,
+                    Some("thinking") => BlockKind::Thinking,
+                    Some("tool_use") => BlockKind::ToolUse {
+                        id: block["id"].as_str().unwrap_or_default().to_string(),
+                        name: block["name"].as_str().unwrap_or_default().to_string(),
+                    },
+                    _ => return Err(invalid()),
+                };
+                Event::BlockStart {
+                    index: index()?,
+                    kind,
+                }
+            }
+            "content_block_delta" => {
+                let delta = &data["delta"];
+                let text = |name: &str| {
+                    delta[name]
+                        .as_str()
+                        .map(|s| s.to_string())
+                        .ok_or_else(invalid)
+                };
+                match delta["type"].as_str() {
+                    Some("text_delta") => Event::TextDelta {
+                        index: index()?,
+                        text: text("text")?,
+                    },
+                    Some("thinking_delta") => Event::ThinkingDelta {
+                        index: index()?,
+                        thinking: text("thinking")?,
+                    },
+                    Some("input_json_delta") => Event::ToolInputDelta {
+                        index: index()?,
+                        partial_json: text("partial_json")?,
+                    },
+                    _ => return Err(invalid()),
+                }
+            }
+            "content_block_stop" => Event::BlockStop { index: index()? },
+            "message_delta" => Event::MessageDelta {
+                stop_reason: data["delta"]["stop_reason"]
+                    .as_str()
+                    .and_then(StopReason::from_anthropic)
+                    .unwrap_or(StopReason::EndTurn),
+                usage: usage(&data["usage"]),
+            },
+            "message_stop" => Event::MessageStop,
+            "ping" => Event::Ping,
+            "error" => Event::Error {
+                error_type: data["error"]["type"]
+                    .as_str()
+                    .unwrap_or("api_error")
+                    .to_string(),
+                message: data["error"]["message"]
+                    .as_str()
+                    .unwrap_or_default()
+                    .to_string(),
+            },
+            _ => return Err(invalid()),
+        })
+    }
+}
+
+impl FromCanonical<Event> for SseEvent {
+    fn from_canonical(event: &Event) -> Self {
+        let usage = |usage: &Usage| json!({"input_tokens": usage.input_tokens, "output_tokens": usage.output_tokens});
+        match event {
+            Event::MessageStart {
+                id,
+                model,
+                usage: u,
+            } => SseEvent::new(
+                "message_start",
+                json!({
+                    "type": "message_start",
+                    "message": {
+                        "id": id,
+                        "type": "message",
+                        "role": "assistant",
+                        "content": [],
+                        "model": model,
+                        "stop_reason": null,
+                        "stop_sequence": null,
+                        "usage": usage(u)
+                    }
+                }),
+            ),
+            Event::BlockStart { index, kind } => {
+                let block = match kind {
+                    BlockKind::Text => json!({"type": "text", "text": ""}),
+                    BlockKind::Thinking => json!({"type": "thinking", "thinking": ""}),
+                    BlockKind::ToolUse { id, name } => {
+                        json!({"type": "tool_use", "id": id, "name": name, "input": {}})
+                    }
+                };
+                SseEvent::new(
+                    "content_block_start",
+                    json!({"type": "content_block_start", "index": index, "content_block": block}),
+                )
+            }
+            Event::TextDelta { index, text } => {
+                delta_event(*index, json!({"type": "text_delta", "text": text}))
+            }
+            Event::ThinkingDelta { index, thinking } => delta_event(
+                *index,
+                json!({"type": "thinking_delta", "thinking": thinking}),
+            ),
+            Event::ToolInputDelta {
+                index,
+                partial_json,
+            } => delta_event(
+                *index,
+                json!({"type": "input_json_delta", "partial_json": partial_json}),
+            ),
+            Event::BlockStop { index } => SseEvent::new(
+                "content_block_stop",
+                json!({"type": "content_block_stop", "index": index}),
+            ),
+            Event::MessageDelta {
+                stop_reason,
+                usage: u,
+            } => SseEvent::new(
+                "message_delta",
+                json!({
+                    "type": "message_delta",
+                    "delta": {"stop_reason": stop_reason.as_anthropic(), "stop_sequence": null},
+                    "usage": usage(u)
+                }),
+            ),
+            Event::MessageStop => SseEvent::new("message_stop", json!({"type": "message_stop"})),
+            Event::Ping => SseEvent::new("ping", json!({"type": "ping"})),
+            Event::Error {
+                error_type,
+                message,
+            } => SseEvent::new(
+                "error",
+                json!({"type": "error", "error": {"type": error_type, "message": message}}),
+            ),
+        }
+    }
+}
+
+#[allow(dead_code)]
+fn delta_event(index: i32, delta: Value) -> SseEvent {
+    SseEvent::new(
+        "content_block_delta",
+        json!({"type": "content_block_delta", "index": index, "delta": delta}),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn anthropic_message(value: Value) -> types::Message {
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn test_request_to_canonical() {
+        let request: types::MessagesRequest = serde_json::from_value(json!({
+            "model": "claude-sonnet-4-5",
+            "max_tokens": 1024,
+            "stream": true,
+            "system": "你是助手",
+            "thinking": {"type": "enabled", "budget_tokens": 1024},
+            "tools": [{"name": "read", "description": "读取文件", "input_schema": {"type": "object"}}],
+            "messages": [{"role": "user", "content": "你好"}]
+        }))
+        .unwrap();
+
+        let canonical = request.to_canonical().unwrap();
+        assert_eq!(canonical.system, vec!["你是助手"]);
+        assert!(canonical.stream && canonical.thinking);
+        assert_eq!(canonical.max_tokens, 1024);
+        assert_eq!(canonical.tools[0].name, "read");
+        assert_eq!(canonical.tools[0].input_schema, json!({"type": "object"}));
+        assert_eq!(
+            canonical.messages,
+            vec![Message {
+                role: Role::User,
+                content: vec![ContentBlock::Text("你好".to_string())]
+            }]
+        );
+    }
+
+    #[test]
+    fn test_request_round_trips() {
+        let request = Request {
+            model: "claude-sonnet-4-5".to_string(),
+            system: vec!["你是助手".to_string()],
+            messages: vec![Message {
+                role: Role::User,
+                content: vec![ContentBlock::Text("你好".to_string())],
+            }],
+            tools: vec![ToolSpec {
+                name: "read".to_string(),
+                description: "读取文件".to_string(),
+                input_schema: json!({"type": "object"}),
+            }],
+            max_tokens: 1024,
+            stream: true,
+            thinking: true,
+        };
+        let anthropic = types::MessagesRequest::from_canonical(&request);
+        assert_eq!(anthropic.to_canonical().unwrap(), request);
+    }
+
+    #[test]
+    fn test_every_block_round_trips() {
+        let message = Message {
+            role: Role::Assistant,
+            content: vec![
+                ContentBlock::Text("文本".to_string()),
+                ContentBlock::Thinking("思考".to_string()),
+                ContentBlock::Image {
+                    media_type: "image/png".to_string(),
+                    data: "aGk=".to_string(),
+                },
+                ContentBlock::ToolUse(ToolCall {
+                    id: "toolu_1".to_string(),
+                    name: "read".to_string(),
+                    input: json!({"path": "a"}),
+                }),
+                ContentBlock::ToolResult {
+                    tool_use_id: "toolu_1".to_string(),
+                    content: "ok".to_string(),
+                    is_error: true,
+                },
+            ],
+        };
+        let anthropic = types::Message::from_canonical(&message);
+        assert_eq!(anthropic.to_canonical().unwrap(), message);
+    }
+
+    #[test]
+    fn test_tool_result_array_content() {
+        let message = anthropic_message(json!({"role": "user", "content": [{
+            "type": "tool_result",
+            "tool_use_id": "t",
+            "content": [{"type": "text", "text": "a"}, {"type": "text", "text": "b"}]
+        }]}));
+        assert_eq!(
+            message.to_canonical().unwrap().content,
+            vec![ContentBlock::ToolResult {
+                tool_use_id: "t".to_string(),
+                content: "a\nb".to_string(),
+                is_error: false,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_message_errors() {
+        let role = anthropic_message(json!({"role": "system", "content": "x"}));
+        assert_eq!(
+            role.to_canonical(),
+            Err(CanonicalError::Role("system".to_string()))
+        );
+
+        let unknown = anthropic_message(json!({"role": "user", "content": [{"type": "document"}]}));
+        assert!(matches!(
+            unknown.to_canonical(),
+            Err(CanonicalError::Block(_))
+        ));
+
+        let missing = anthropic_message(
+            json!({"role": "user", "content": [{"type": "tool_use", "name": "x"}]}),
+        );
+        assert!(matches!(
+            missing.to_canonical(),
+            Err(CanonicalError::Block(_))
+        ));
+    }
+
+    #[test]
+    fn test_every_event_round_trips() {
+        let usage = Usage {
+            input_tokens: 10,
+            output_tokens: 5,
+        };
+        let events = vec![
+            Event::MessageStart {
+                id: "msg_1".to_string(),
+                model: "claude-sonnet-4-5".to_string(),
+                usage,
+            },
+            Event::BlockStart {
+                index: 0,
+                kind: BlockKind::Thinking,
+            },
+            Event::ThinkingDelta {
+                index: 0,
+                thinking: "嗯".to_string(),
+            },
+            Event::BlockStop { index: 0 },
+            Event::BlockStart {
+                index: 1,
+                kind: BlockKind::Text,
+            },
+            Event::TextDelta {
+                index: 1,
+                text: "你好".to_string(),
+            },
+            Event::BlockStart {
+                index: 2,
+                kind: BlockKind::ToolUse {
+                    id: "toolu_1".to_string(),
+                    name: "read".to_string(),
+                },
+            },
+            Event::ToolInputDelta {
+                index: 2,
+                partial_json: "{}".to_string(),
+            },
+            Event::MessageDelta {
+                stop_reason: StopReason::ToolUse,
+                usage,
+            },
+            Event::MessageStop,
+            Event::Ping,
+            Event::Error {
+                error_type: "api_error".to_string(),
+                message: "中止".to_string(),
+            },
+        ];
+        for event in events {
+            let sse = SseEvent::from_canonical(&event);
+            assert_eq!(sse.data["type"], sse.event.as_str());
+            assert_eq!(sse.to_canonical().unwrap(), event);
+        }
+    }
+
+    #[test]
+    fn test_event_errors() {
+        for (name, data) in [
+            ("unknown", json!({})),
+            (
+                "content_block_start",
+                json!({"index": 0, "content_block": {"type": "server_tool_use"}}),
+            ),
+            (
+                "content_block_delta",
+                json!({"delta": {"type": "text_delta", "text": "x"}}),
+            ),
+            (
+                "content_block_delta",
+                json!({"index": 0, "delta": {"type": "signature_delta"}}),
+            ),
+        ] {
+            assert!(matches!(
+                SseEvent::new(name, data).to_canonical(),
+                Err(CanonicalError::Event(_))
+            ));
+        }
+    }
+}
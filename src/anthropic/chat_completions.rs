@@ -0,0 +1,282 @@
+//! OpenAI Chat Completions 兼容层（Azure OpenAI 路径）
+//!
+//! 部分企业工具只支持 Azure OpenAI 的约定：`POST /openai/deployments/{name}/chat/completions?api-version=...`，
+//! 用 `api-key` 请求头认证，请求体中不带模型（由部署名决定）。部署名按 `azureDeployments`
+//! 映射为模型名（未配置时直接把部署名当作模型名），请求转换为规范请求后复用 Messages 的处理流程。
+//! 流式响应按 `chat.completion.chunk` 渲染（见 `render`），非流式响应由 Anthropic 消息转换为
+//! `chat.completion` 对象。`api-version` 只做兼容，不影响行为。
+
+use serde::Deserialize;
+use serde_json::{Value, json};
+
+use super::canonical::{
+    CanonicalError, ContentBlock, Message, Request, Role, ToCanonical, ToolCall, ToolSpec,
+};
+use super::responses::{content_text, data_url_image, push_block};
+use super::stop_reason::StopReason;
+
+/// Chat Completions 请求体
+#[derive(Debug, Deserialize)]
+pub struct ChatCompletionRequest {
+    /// Azure 路径下由部署名决定，请求体中的值会被忽略
+    #[serde(default)]
+    pub model: Option<String>,
+    pub messages: Vec<Value>,
+    #[serde(default)]
+    pub tools: Vec<Value>,
+    #[serde(default)]
+    pub max_tokens: Option<i32>,
+    /// 新版字段，优先于 `max_tokens`
+    #[serde(default)]
+    pub max_completion_tokens: Option<i32>,
+    #[serde(default)]
+    pub stream: bool,
+    #[serde(default)]
+    pub temperature: Option<f32>,
+    #[serde(default)]
+    pub reasoning_effort: Option<String>,
+}
+
+/// Azure 路径的查询参数
+#[derive(Debug, Deserialize)]
+pub struct AzureQuery {
+    #[serde(default, rename = "api-version")]
+    pub api_version: Option<String>,
+}
+
+impl ToCanonical for ChatCompletionRequest {
+    type Canonical = Request;
+
+    fn to_canonical(&self) -> Result<Request, CanonicalError> {
+        let mut system = Vec::new();
+        let mut messages: Vec<Message> = Vec::new();
+        for message in &self.messages {
+            chat_message(message, &mut system, &mut messages)?;
+        }
+
+        let tools = self
+            .tools
+            .iter()
+            .filter(|tool| {
+                let function = tool["type"].as_str() == Some("function");
+                if !function {
+                    tracing::debug!("忽略不支持的 Chat Completions 工具: {}", tool["type"]);
+                }
+                function
+            })
+            .map(|tool| {
+                let function = &tool["function"];
+                ToolSpec {
+                    name: function["name"].as_str().unwrap_or_default().to_string(),
+                    description: function["description"]
+                        .as_str()
+                        .unwrap_or_default()
+                        .to_string(),
+                    input_schema: function
+                        .get("parameters")
+                        .cloned()
+                        .unwrap_or_else(|| json!({"type": "object"})),
+                }
+            })
+            .collect();
+
+        Ok(Request {
+            model: self.model.clone().unwrap_or_default(),
+            system,
+            messages,
+            tools,
+            max_tokens: self.max_completion_tokens.or(self.max_tokens).unwrap_or(0),
+            stream: self.stream,
+            thinking: self
+                .reasoning_effort
+                .as_deref()
+                .is_some_and(|effort| effort != "none" && effort != "minimal"),
+        })
+    }
+}
+
+/// 转换单条消息（tool 消息转为 user 角色的 tool_result，与相邻消息合并）
+fn chat_message(
+    message: &Value,
+    system: &mut Vec<String>,
+    messages: &mut Vec<Message>,
+) -> Result<(), CanonicalError> {
+    let invalid = || CanonicalError::Block(message.to_string());
+    match message["role"].as_str().ok_or_else(invalid)? {
+        "system" | "developer" => system.push(content_text(&message["content"])),
+        "user" => match &message["content"] {
+            Value::String(text) => {
+                push_block(messages, Role::User, ContentBlock::Text(text.clone()))
+            }
+            Value::Array(parts) => {
+                for part in parts {
+                    push_block(messages, Role::User, content_part(part)?);
+                }
+            }
+            _ => return Err(invalid()),
+        },
+        "assistant" => {
+            let text = content_text(&message["content"]);
+            if !text.is_empty() {
+                push_block(messages, Role::Assistant, ContentBlock::Text(text));
+            }
+            for call in message["tool_calls"].as_array().into_iter().flatten() {
+                let function = &call["function"];
+                let arguments = function["arguments"].as_str().unwrap_or_default();
+                let call = ToolCall {
+                    id: call["id"].as_str().ok_or_else(invalid)?.to_string(),
+                    name: function["name"].as_str().ok_or_else(invalid)?.to_string(),
+                    input: serde_json::from_str(arguments).unwrap_or_else(|_| json!({})),
+                };
+                push_block(messages, Role::Assistant, ContentBlock::ToolUse(call));
+            }
+        }
+        "tool" => {
+            let block = ContentBlock::ToolResult {
+                tool_use_id: message["tool_call_id"]
+                    .as_str()
+                    .ok_or_else(invalid)?
+                    .to_string(),
+                content: content_text(&message["content"]),
+                is_error: false,
+            };
+            push_block(messages, Role::User, block);
+        }
+        other => return Err(CanonicalError::Role(other.to_string())),
+    }
+    Ok(())
+}
+
+fn content_part(part: &Value) -> Result<ContentBlock, CanonicalError> {
+    let invalid = || CanonicalError::Block(part.to_string());
+    Ok(match part["type"].as_str().ok_or_else(invalid)? {
+        "text" => ContentBlock::Text(part["text"].as_str().ok_or_else(invalid)?.to_string()),
+        "image_url" => part["image_url"]["url"]
+            .as_str()
+            .and_then(data_url_image)
+            .ok_or_else(invalid)?,
+        _ => return Err(invalid()),
+    })
+}
+
+/// 把非流式的 Anthropic 消息转换为 chat.completion 对象
+pub fn from_message(message: &Value) -> Value {
+    let id = message["id"].as_str().unwrap_or_default();
+    let mut text = String::new();
+    let mut reasoning = String::new();
+    let mut tool_calls = Vec::new();
+    for block in message["content"].as_array().into_iter().flatten() {
+        match block["type"].as_str() {
+            Some("text") => text.push_str(block["text"].as_str().unwrap_or_default()),
+            Some("thinking") => reasoning.push_str(block["thinking"].as_str().unwrap_or_default()),
+            Some("tool_use") => tool_calls.push(json!({
+                "id": block["id"],
+                "type": "function",
+                "function": {
+                    "name": block["name"],
+                    "arguments": block["input"].to_string()
+                }
+            })),
+            _ => {}
+        }
+    }
+
+    let mut reply = json!({
+        "role": "assistant",
+        "content": if text.is_empty() && !tool_calls.is_empty() { Value::Null } else { json!(text) }
+    });
+    if !reasoning.is_empty() {
+        reply["reasoning_content"] = json!(reasoning);
+    }
+    if !tool_calls.is_empty() {
+        reply["tool_calls"] = json!(tool_calls);
+    }
+    let finish_reason = message["stop_reason"]
+        .as_str()
+        .and_then(StopReason::from_anthropic)
+        .unwrap_or(StopReason::EndTurn)
+        .as_openai();
+    let input_tokens = message["usage"]["input_tokens"]
+        .as_i64()
+        .unwrap_or_default();
+    let output_tokens = message["usage"]["output_tokens"]
+        .as_i64()
+        .unwrap_or_default();
+
+    json!({
+        "id": format!("chatcmpl-{}", id.trim_start_matches("msg_")),
+        "object": "chat.completion",
+        "created": chrono::Utc::now().timestamp(),
+        "model": message["model"],
+        "choices": [{"index": 0, "message": reply, "finish_reason": finish_reason}],
+        "usage": {
+            "prompt_tokens": input_tokens,
+            "completion_tokens": output_tokens,
+            "total_tokens": input_tokens + output_tokens
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_request_and_response_conversion() {
+        let request: ChatCompletionRequest = serde_json::from_value(json!({
+            "messages": [
+                {"role": "system", "content": "简洁回答"},
+                {"role": "user", "content": [
+                    {"type": "text", "text": "读文件"},
+                    {"type": "image_url", "image_url": {"url": "data:image/png;base64,AAAA"}}
+                ]},
+                {"role": "assistant", "content": null, "tool_calls": [
+                    {"id": "call_1", "type": "function", "function": {"name": "read", "arguments": "{\"path\":\"a\"}"}}
+                ]},
+                {"role": "tool", "tool_call_id": "call_1", "content": "内容"}
+            ],
+            "tools": [{"type": "function", "function": {"name": "read", "parameters": {"type": "object"}}}],
+            "max_tokens": 100,
+            "max_completion_tokens": 200,
+            "stream": true
+        }))
+        .unwrap();
+        let canonical = request.to_canonical().unwrap();
+
+        assert_eq!(canonical.system, vec!["简洁回答".to_string()]);
+        assert_eq!(canonical.max_tokens, 200);
+        assert_eq!(canonical.tools[0].name, "read");
+        assert_eq!(canonical.messages.len(), 3);
+        assert_eq!(canonical.messages[0].content.len(), 2);
+        assert_eq!(
+            canonical.messages[1].content,
+            vec![ContentBlock::ToolUse(ToolCall {
+                id: "call_1".to_string(),
+                name: "read".to_string(),
+                input: json!({"path": "a"}),
+            })]
+        );
+        assert_eq!(canonical.messages[2].role, Role::User);
+
+        let completion = from_message(&json!({
+            "id": "msg_abc",
+            "model": "claude-sonnet-4-5",
+            "content": [
+                {"type": "text", "text": "好的"},
+                {"type": "tool_use", "id": "toolu_1", "name": "read", "input": {"path": "b"}}
+            ],
+            "stop_reason": "tool_use",
+            "usage": {"input_tokens": 10, "output_tokens": 5}
+        }));
+        assert_eq!(completion["id"], "chatcmpl-abc");
+        assert_eq!(completion["object"], "chat.completion");
+        let choice = &completion["choices"][0];
+        assert_eq!(choice["finish_reason"], "tool_calls");
+        assert_eq!(choice["message"]["content"], "好的");
+        assert_eq!(
+            choice["message"]["tool_calls"][0]["function"]["arguments"],
+            "{\"path\":\"b\"}"
+        );
+        assert_eq!(completion["usage"]["total_tokens"], 15);
+    }
+}
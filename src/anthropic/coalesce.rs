@@ -0,0 +1,311 @@
+//! 相同流式请求合并
+//!
+//! CI 流水线等场景常在短时间内发出完全相同的流式请求。开启 `coalesce` 后，同一客户端在
+//! `windowMs` 内发出的相同请求（请求体序列化后逐字节相同）共用一次上游调用：第一个请求发起上游
+//! 流，后续请求重放已收到的数据块并继续接收新的数据块，各自按请求的格式渲染，上游额度只消耗一次。
+//! 上游调用失败时，等待中的请求各自重新发起调用。所有客户端都断开后停止读取上游。
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+use axum::http::HeaderMap;
+use bytes::Bytes;
+use futures::StreamExt;
+use futures::stream;
+use parking_lot::Mutex;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use tokio::sync::Notify;
+
+use super::stall::{ReadError, UpstreamBody};
+use crate::model::config::CoalesceConfig;
+
+/// 合并键（客户端名与请求体的 SHA-256）
+pub type CoalesceKey = [u8; 32];
+
+/// 打开的上游流：字节流、提供响应的凭据与上游响应头
+type Opened = (UpstreamBody, Option<u64>, HeaderMap);
+
+/// 可在订阅者之间共享的读取结果
+#[derive(Clone)]
+enum Chunk {
+    Data(Bytes),
+    Stalled(Duration),
+    Failed(Arc<str>),
+}
+
+/// 上游调用的状态
+#[derive(Clone)]
+enum Upstream {
+    Pending,
+    Failed,
+    Ready {
+        credential_id: Option<u64>,
+        headers: HeaderMap,
+    },
+}
+
+struct FlightState {
+    upstream: Upstream,
+    chunks: Vec<Chunk>,
+    done: bool,
+}
+
+/// 一次进行中的上游调用
+struct Flight {
+    started: Instant,
+    state: Mutex<FlightState>,
+    notify: Notify,
+    subscribers: AtomicUsize,
+}
+
+/// 订阅者计数（随订阅流一起 drop）
+struct Subscription(Arc<Flight>);
+
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        self.0.subscribers.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+impl Flight {
+    fn new() -> Self {
+        Self {
+            started: Instant::now(),
+            state: Mutex::new(FlightState {
+                upstream: Upstream::Pending,
+                chunks: Vec::new(),
+                done: false,
+            }),
+            notify: Notify::new(),
+            subscribers: AtomicUsize::new(0),
+        }
+    }
+
+    /// 等待发起者的上游调用结果
+    async fn wait_upstream(&self) -> Upstream {
+        loop {
+            let notified = self.notify.notified();
+            let upstream = self.state.lock().upstream.clone();
+            if !matches!(upstream, Upstream::Pending) {
+                return upstream;
+            }
+            notified.await;
+        }
+    }
+
+    fn finish_upstream(&self, upstream: Upstream) {
+        self.state.lock().upstream = upstream;
+        self.notify.notify_waiters();
+    }
+
+    /// 从头重放并继续接收数据块
+    fn subscribe(self: &Arc<Self>) -> UpstreamBody {
+        self.subscribers.fetch_add(1, Ordering::SeqCst);
+        stream::unfold(
+            (Subscription(self.clone()), 0usize),
+            |(subscription, index)| async move {
+                loop {
+                    let flight = subscription.0.clone();
+                    let notified = flight.notify.notified();
+                    let next = {
+                        let state = flight.state.lock();
+                        match state.chunks.get(index) {
+                            Some(chunk) => Some(chunk.clone()),
+                            None if state.done => return None,
+                            None => None,
+                        }
+                    };
+                    let item = match next {
+                        Some(Chunk::Data(bytes)) => Ok(bytes),
+                        Some(Chunk::Stalled(timeout)) => Err(ReadError::Stalled(timeout)),
+                        Some(Chunk::Failed(message)) => {
+                            Err(ReadError::Coalesced(message.to_string()))
+                        }
+                        None => {
+                            notified.await;
+                            continue;
+                        }
+                    };
+                    return Some((item, (subscription, index + 1)));
+                }
+            },
+        )
+        .boxed()
+    }
+
+    /// 在后台读取上游流并分发给订阅者
+    fn drive(self: Arc<Self>, mut body: UpstreamBody, window: Duration) {
+        tokio::spawn(async move {
+            while let Some(item) = body.next().await {
+                let chunk = match item {
+                    Ok(bytes) => Chunk::Data(bytes),
+                    Err(ReadError::Stalled(timeout)) => Chunk::Stalled(timeout),
+                    Err(e) => Chunk::Failed(e.to_string().into()),
+                };
+                self.state.lock().chunks.push(chunk);
+                self.notify.notify_waiters();
+                // 合并窗口已过且所有客户端都已断开
+                if self.subscribers.load(Ordering::SeqCst) == 0 && self.started.elapsed() >= window
+                {
+                    tracing::debug!("合并的流式请求已无客户端，停止读取上游");
+                    break;
+                }
+            }
+            self.state.lock().done = true;
+            self.notify.notify_waiters();
+        });
+    }
+}
+
+/// 相同流式请求合并
+pub struct Coalescer {
+    window: Duration,
+    flights: Mutex<HashMap<CoalesceKey, Arc<Flight>>>,
+}
+
+impl Coalescer {
+    /// 从配置创建（未启用时返回 None）
+    pub fn from_config(config: &CoalesceConfig) -> Option<Arc<Self>> {
+        (config.enabled && config.window_ms > 0).then(|| {
+            Arc::new(Self {
+                window: Duration::from_millis(config.window_ms),
+                flights: Mutex::new(HashMap::new()),
+            })
+        })
+    }
+
+    /// 计算合并键
+    pub fn key(client: &str, payload: &impl Serialize) -> CoalesceKey {
+        let mut hasher = Sha256::new();
+        hasher.update(client.as_bytes());
+        hasher.update([0]);
+        hasher.update(serde_json::to_vec(payload).unwrap_or_default());
+        hasher.finalize().into()
+    }
+
+    /// 加入合并窗口内的相同请求，或作为第一个请求发起上游调用
+    pub async fn open<F, Fut>(&self, key: CoalesceKey, open: F) -> anyhow::Result<Opened>
+    where
+        F: Fn() -> Fut,
+        Fut: Future<Output = anyhow::Result<Opened>>,
+    {
+        let (flight, leader) = {
+            let mut flights = self.flights.lock();
+            flights.retain(|_, flight| flight.started.elapsed() < self.window);
+            match flights.get(&key) {
+                Some(flight) => (flight.clone(), false),
+                None => {
+                    let flight = Arc::new(Flight::new());
+                    flights.insert(key, flight.clone());
+                    (flight, true)
+                }
+            }
+        };
+
+        if !leader {
+            return match flight.wait_upstream().await {
+                Upstream::Ready {
+                    credential_id,
+                    headers,
+                } => {
+                    tracing::info!("相同的流式请求正在进行，合并到同一上游流");
+                    Ok((flight.subscribe(), credential_id, headers))
+                }
+                // 发起者的上游调用失败：自行调用
+                _ => open().await,
+            };
+        }
+
+        // 上游调用失败或发起者中途断开时，让等待中的请求各自调用
+        let mut abandon = Abandon {
+            coalescer: self,
+            flight: Some(flight.clone()),
+        };
+        let (body, credential_id, headers) = open().await?;
+        abandon.flight = None;
+
+        let subscription = flight.subscribe();
+        flight.finish_upstream(Upstream::Ready {
+            credential_id,
+            headers: headers.clone(),
+        });
+        flight.drive(body, self.window);
+        Ok((subscription, credential_id, headers))
+    }
+}
+
+/// 发起者未能打开上游流时撤销该次合并
+struct Abandon<'a> {
+    coalescer: &'a Coalescer,
+    flight: Option<Arc<Flight>>,
+}
+
+impl Drop for Abandon<'_> {
+    fn drop(&mut self) {
+        if let Some(flight) = self.flight.take() {
+            self.coalescer
+                .flights
+                .lock()
+                .retain(|_, other| !Arc::ptr_eq(other, &flight));
+            flight.finish_upstream(Upstream::Failed);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicU32;
+
+    #[tokio::test]
+    async fn test_identical_requests_share_one_upstream_call() {
+        let coalescer = Coalescer::from_config(&CoalesceConfig {
+            enabled: true,
+            window_ms: 60_000,
+        })
+        .unwrap();
+        let calls = AtomicU32::new(0);
+        let (tx, rx) = futures::channel::mpsc::unbounded::<Result<Bytes, ReadError>>();
+        let rx = Mutex::new(Some(rx));
+        let open = || {
+            calls.fetch_add(1, Ordering::SeqCst);
+            let body = rx.lock().take().map(|rx| rx.boxed());
+            async move {
+                match body {
+                    Some(body) => Ok((body, Some(7), HeaderMap::new())),
+                    None => anyhow::bail!("上游已被调用过"),
+                }
+            }
+        };
+
+        let key = Coalescer::key("ci", &serde_json::json!({"model": "m", "stream": true}));
+        let (mut first, credential, _) = coalescer.open(key, open).await.unwrap();
+        assert_eq!(credential, Some(7));
+        tx.unbounded_send(Ok(Bytes::from_static(b"a"))).unwrap();
+        assert_eq!(first.next().await.unwrap().unwrap(), "a");
+
+        // 之后加入的请求从头重放
+        let (mut second, credential, _) = coalescer.open(key, open).await.unwrap();
+        assert_eq!(credential, Some(7));
+        tx.unbounded_send(Ok(Bytes::from_static(b"b"))).unwrap();
+        drop(tx);
+        let rest: Vec<_> = second.by_ref().map(|r| r.unwrap()).collect().await;
+        assert_eq!(
+            rest,
+            vec![Bytes::from_static(b"a"), Bytes::from_static(b"b")]
+        );
+        assert_eq!(first.next().await.unwrap().unwrap(), "b");
+        assert!(first.next().await.is_none());
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        // 其他客户端或不同的请求体不合并
+        let other = Coalescer::key("other", &serde_json::json!({"model": "m", "stream": true}));
+        assert_ne!(other, key);
+        assert!(coalescer.open(other, open).await.is_err());
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+}
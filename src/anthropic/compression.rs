@@ -0,0 +1,391 @@
+//! 摘要式上下文压缩
+//!
+//! 对话估算 tokens 超过 `contextCompression.thresholdTokens` 时，保留最近的消息，把更早的轮次
+//! 交给摘要模型总结，并以追加到 system 的摘要替换这些轮次。切分点总是落在普通 user 消息上，
+//! 不会拆开 tool_use 与 tool_result。
+//!
+//! 摘要按会话（metadata 中的 session，缺省时按首条消息）缓存，并记录覆盖的消息数与其指纹：
+//! 前缀未变时直接复用，对话增长后只需把旧摘要与新增的轮次一起重新总结。
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use parking_lot::Mutex;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+
+use crate::model::config::ContextCompressionConfig;
+
+use super::converter::extract_session_id;
+use super::types::{Message, MessagesRequest, SystemMessage};
+
+/// 生成摘要的系统提示
+const SUMMARY_PROMPT: &str = "你是对话摘要助手。请用简洁的要点总结下面的对话，保留用户的目标与约束、\
+已做出的决定、关键事实与数据、涉及的文件和代码位置、工具调用的结果以及尚未完成的事项。只输出摘要本身。";
+
+/// 单个工具结果在摘要输入中保留的最大字符数
+const MAX_TOOL_RESULT_CHARS: usize = 2000;
+
+/// 会话的已缓存摘要
+struct CachedSummary {
+    /// 摘要覆盖的前缀消息数
+    covered: usize,
+    /// 被覆盖消息的指纹
+    fingerprint: String,
+    summary: String,
+    updated_at: Instant,
+}
+
+/// 压缩计划
+#[derive(Debug)]
+pub struct CompressionPlan {
+    conversation_id: String,
+    /// 被摘要替换的前缀消息数
+    cut: usize,
+    /// 可复用的已有摘要及其覆盖的消息数
+    previous: Option<(usize, String)>,
+}
+
+impl CompressionPlan {
+    /// 已有摘要恰好覆盖要替换的消息时直接复用
+    pub fn cached_summary(&self) -> Option<&str> {
+        self.previous
+            .as_ref()
+            .filter(|(covered, _)| *covered == self.cut)
+            .map(|(_, summary)| summary.as_str())
+    }
+}
+
+/// 上下文压缩器
+pub struct ContextCompressor {
+    config: ContextCompressionConfig,
+    cache: Mutex<HashMap<String, CachedSummary>>,
+}
+
+impl ContextCompressor {
+    /// 从配置创建（未启用时返回 None）
+    pub fn from_config(config: &ContextCompressionConfig) -> Option<Arc<Self>> {
+        config.enabled.then(|| {
+            Arc::new(Self {
+                config: config.clone(),
+                cache: Mutex::new(HashMap::new()),
+            })
+        })
+    }
+
+    fn cache_ttl(&self) -> Duration {
+        Duration::from_secs(self.config.cache_ttl_secs)
+    }
+
+    /// 估算 tokens 超过阈值且有可替换的较早轮次时给出压缩计划
+    pub fn plan(&self, payload: &MessagesRequest, tokens: u64) -> Option<CompressionPlan> {
+        if tokens <= self.config.threshold_tokens {
+            return None;
+        }
+        let cut = cut_point(&payload.messages, self.config.keep_recent_messages);
+        if cut == 0 {
+            return None;
+        }
+
+        let conversation_id = conversation_id(payload);
+        let previous = self.cache.lock().get(&conversation_id).and_then(|cached| {
+            let valid = cached.updated_at.elapsed() < self.cache_ttl()
+                && cached.covered <= cut
+                && fingerprint(&payload.messages[..cached.covered]) == cached.fingerprint;
+            valid.then(|| (cached.covered, cached.summary.clone()))
+        });
+        Some(CompressionPlan {
+            conversation_id,
+            cut,
+            previous,
+        })
+    }
+
+    /// 构建生成摘要的请求（有旧摘要时只附上新增的轮次）
+    pub fn summary_request(
+        &self,
+        payload: &MessagesRequest,
+        plan: &CompressionPlan,
+    ) -> MessagesRequest {
+        let input = match &plan.previous {
+            Some((covered, summary)) => format!(
+                "此前对话的摘要：\n{}\n\n之后的对话：\n{}",
+                summary,
+                render_transcript(&payload.messages[*covered..plan.cut])
+            ),
+            None => render_transcript(&payload.messages[..plan.cut]),
+        };
+        MessagesRequest {
+            model: self
+                .config
+                .summary_model
+                .clone()
+                .unwrap_or_else(|| payload.model.clone()),
+            max_tokens: self.config.max_summary_tokens,
+            messages: vec![Message {
+                role: "user".to_string(),
+                content: Value::String(input),
+            }],
+            stream: false,
+            system: Some(vec![SystemMessage {
+                text: SUMMARY_PROMPT.to_string(),
+            }]),
+            tools: None,
+            tool_choice: None,
+            thinking: None,
+            metadata: None,
+            temperature: None,
+            stop_sequences: None,
+        }
+    }
+
+    /// 缓存新生成的摘要（清理过期条目，超过上限时淘汰最久未更新的会话）
+    pub fn store(&self, payload: &MessagesRequest, plan: &CompressionPlan, summary: &str) {
+        let mut cache = self.cache.lock();
+        let ttl = self.cache_ttl();
+        cache.retain(|_, cached| cached.updated_at.elapsed() < ttl);
+        if !cache.contains_key(&plan.conversation_id)
+            && cache.len() >= self.config.max_cache_entries
+            && let Some(oldest) = cache
+                .iter()
+                .min_by_key(|(_, cached)| cached.updated_at)
+                .map(|(id, _)| id.clone())
+        {
+            cache.remove(&oldest);
+        }
+        cache.insert(
+            plan.conversation_id.clone(),
+            CachedSummary {
+                covered: plan.cut,
+                fingerprint: fingerprint(&payload.messages[..plan.cut]),
+                summary: summary.to_string(),
+                updated_at: Instant::now(),
+            },
+        );
+    }
+
+    /// 以摘要替换被压缩的消息
+    pub fn apply(payload: &mut MessagesRequest, plan: &CompressionPlan, summary: &str) {
+        payload.messages.drain(..plan.cut);
+        payload
+            .system
+            .get_or_insert_with(Vec::new)
+            .push(SystemMessage {
+                text: format!(
+                    "以下是此前对话的摘要（较早的 {} 条消息已被压缩）：\n{}",
+                    plan.cut, summary
+                ),
+            });
+    }
+}
+
+/// 会话标识：metadata 中的 session，缺省时使用首条消息的指纹
+fn conversation_id(payload: &MessagesRequest) -> String {
+    payload
+        .metadata
+        .as_ref()
+        .and_then(|m| m.user_id.as_deref())
+        .and_then(extract_session_id)
+        .unwrap_or_else(|| fingerprint(&payload.messages[..1]))
+}
+
+/// 消息列表的指纹
+fn fingerprint(messages: &[Message]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(serde_json::to_vec(messages).unwrap_or_default());
+    hex::encode(hasher.finalize())
+}
+
+/// 只含 tool_result 的 user 消息（不能作为保留部分的开头）
+fn is_tool_result_only(message: &Message) -> bool {
+    message.content.as_array().is_some_and(|blocks| {
+        !blocks.is_empty()
+            && blocks
+                .iter()
+                .all(|b| b.get("type").and_then(|t| t.as_str()) == Some("tool_result"))
+    })
+}
+
+/// 切分点：保留至少 `keep_recent` 条消息，且保留部分以普通 user 消息开头（0 表示无法压缩）
+fn cut_point(messages: &[Message], keep_recent: usize) -> usize {
+    let latest = messages.len().saturating_sub(keep_recent.max(1));
+    (1..=latest)
+        .rev()
+        .find(|&i| messages[i].role == "user" && !is_tool_result_only(&messages[i]))
+        .unwrap_or(0)
+}
+
+/// 把消息渲染为摘要模型的输入文本
+fn render_transcript(messages: &[Message]) -> String {
+    let mut out = String::new();
+    for message in messages {
+        let role = if message.role == "assistant" {
+            "助手"
+        } else {
+            "用户"
+        };
+        out.push_str(&format!("{}：", role));
+        match &message.content {
+            Value::String(text) => out.push_str(text),
+            Value::Array(blocks) => {
+                for block in blocks {
+                    if let Some(part) = render_block(block) {
+                        out.push_str(&part);
+                        out.push('\n');
+                    }
+                }
+            }
+            _ => {}
+        }
+        out.push_str("\n\n");
+    }
+    out
+}
+
+fn render_block(block: &Value) -> Option<String> {
+    match block.get("type")?.as_str()? {
+        "text" => block["text"].as_str().map(|s| s.to_string()),
+        "tool_use" => Some(format!(
+            "[调用工具 {}: {}]",
+            block["name"].as_str().unwrap_or_default(),
+            block["input"]
+        )),
+        "tool_result" => {
+            let content = match &block["content"] {
+                Value::String(text) => text.clone(),
+                Value::Array(parts) => parts
+                    .iter()
+                    .filter_map(|p| p["text"].as_str())
+                    .collect::<Vec<_>>()
+                    .join("\n"),
+                _ => String::new(),
+            };
+            let truncated: String = content.chars().take(MAX_TOOL_RESULT_CHARS).collect();
+            let more = if truncated.len() < content.len() {
+                "…"
+            } else {
+                ""
+            };
+            Some(format!("[工具结果: {}{}]", truncated, more))
+        }
+        "image" => Some("[图片]".to_string()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn message(role: &str, content: Value) -> Message {
+        Message {
+            role: role.to_string(),
+            content,
+        }
+    }
+
+    fn request(messages: Vec<Message>) -> MessagesRequest {
+        serde_json::from_value(json!({
+            "model": "claude-sonnet-4-5",
+            "max_tokens": 1024,
+            "messages": messages
+        }))
+        .unwrap()
+    }
+
+    fn conversation() -> Vec<Message> {
+        vec![
+            message("user", json!("帮我重构 parser")),
+            message(
+                "assistant",
+                json!([{"type": "tool_use", "id": "t1", "name": "read", "input": {"path": "a.rs"}}]),
+            ),
+            message(
+                "user",
+                json!([{"type": "tool_result", "tool_use_id": "t1", "content": "fn main() {}"}]),
+            ),
+            message("assistant", json!("已读取")),
+            message("user", json!("继续")),
+            message("assistant", json!("好的")),
+            message(
+                "user",
+                json!([{"type": "tool_result", "tool_use_id": "t2", "content": "ok"}]),
+            ),
+            message("assistant", json!("完成")),
+            message("user", json!("再检查一下")),
+        ]
+    }
+
+    #[test]
+    fn test_plan_and_cache() {
+        let config = ContextCompressionConfig {
+            enabled: true,
+            threshold_tokens: 100,
+            keep_recent_messages: 3,
+            ..ContextCompressionConfig::default()
+        };
+        let compressor = ContextCompressor::from_config(&config).unwrap();
+        let payload = request(conversation());
+
+        assert!(compressor.plan(&payload, 100).is_none());
+        // 最近 3 条之前最后一个普通 user 消息是第 4 条（跳过只含 tool_result 的第 6 条）
+        let plan = compressor.plan(&payload, 1000).unwrap();
+        assert_eq!(plan.cut, 4);
+        assert!(plan.previous.is_none());
+        let summary_request = compressor.summary_request(&payload, &plan);
+        assert_eq!(summary_request.model, "claude-haiku-4-5");
+        let input = summary_request.messages[0].content.as_str().unwrap();
+        assert!(input.contains("[调用工具 read"));
+        assert!(input.contains("[工具结果: fn main() {}]"));
+        assert!(!input.contains("继续"));
+
+        compressor.store(&payload, &plan, "摘要一");
+        let plan = compressor.plan(&payload, 1000).unwrap();
+        assert_eq!(plan.cached_summary(), Some("摘要一"));
+
+        // 对话增长后基于旧摘要增量更新
+        let mut grown = conversation();
+        grown.push(message("assistant", json!("没问题")));
+        grown.push(message("user", json!("提交吧")));
+        grown.push(message("assistant", json!("已提交")));
+        grown.push(message("user", json!("谢谢")));
+        let grown = request(grown);
+        let plan = compressor.plan(&grown, 1000).unwrap();
+        assert_eq!(plan.cut, 10);
+        assert!(plan.cached_summary().is_none());
+        let input = compressor.summary_request(&grown, &plan).messages[0]
+            .content
+            .as_str()
+            .unwrap()
+            .to_string();
+        assert!(input.starts_with("此前对话的摘要：\n摘要一"));
+        assert!(input.contains("继续") && !input.contains("帮我重构"));
+
+        // 前缀被改写时不复用
+        let mut edited = conversation();
+        edited[0] = message("user", json!("帮我重构 lexer"));
+        let plan = compressor.plan(&request(edited), 1000).unwrap();
+        assert!(plan.previous.is_none());
+    }
+
+    #[test]
+    fn test_apply_replaces_prefix() {
+        let mut payload = request(conversation());
+        let plan = CompressionPlan {
+            conversation_id: "c".to_string(),
+            cut: 4,
+            previous: None,
+        };
+        ContextCompressor::apply(&mut payload, &plan, "之前在重构 parser");
+
+        assert_eq!(payload.messages.len(), 5);
+        assert_eq!(payload.messages[0].content, json!("继续"));
+        let system = payload.system.unwrap();
+        assert!(system[0].text.contains("较早的 4 条消息"));
+        assert!(system[0].text.ends_with("之前在重构 parser"));
+
+        assert_eq!(cut_point(&conversation()[..2], 3), 0);
+    }
+}
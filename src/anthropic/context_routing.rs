@@ -0,0 +1,149 @@
+//! 上下文长度路由
+//!
+//! 转发前按估算的提示词 tokens 检查模型的上下文窗口（按映射后的 Kiro 模型 ID 匹配
+//! `contextRouting.models`）：超出时沿 `fallbackModel` 查找能容纳的模型并改写请求模型，
+//! 都无法容纳时返回与 Anthropic 一致的 "prompt is too long" 错误，附带超出的 tokens 数。
+
+use std::sync::Arc;
+
+use crate::kiro::transform::model_matches;
+use crate::model::config::{ContextRoutingConfig, ContextWindowRule};
+
+use super::converter::map_model;
+
+/// 路由结果
+#[derive(Debug, PartialEq)]
+pub enum ContextRoute {
+    /// 当前模型可以容纳
+    Fits,
+    /// 改用更大上下文的模型
+    Reroute { model: String },
+    /// 所有候选模型都无法容纳（按请求模型的窗口计算超出量）
+    Overflow { context_window: u64, over_by: u64 },
+}
+
+/// 上下文长度路由器
+pub struct ContextRouter {
+    config: ContextRoutingConfig,
+}
+
+impl ContextRouter {
+    /// 从配置创建（未启用时返回 None）
+    pub fn from_config(config: &ContextRoutingConfig) -> Option<Arc<Self>> {
+        config.enabled.then(|| {
+            Arc::new(Self {
+                config: config.clone(),
+            })
+        })
+    }
+
+    fn rule_for(&self, model: &str) -> Option<&ContextWindowRule> {
+        let model_id = map_model(model)?;
+        self.config
+            .models
+            .iter()
+            .find(|rule| model_matches(&rule.model, &model_id))
+    }
+
+    /// 模型的上下文窗口（不支持的模型或窗口为 0 时不检查）
+    pub fn context_window(&self, model: &str) -> Option<u64> {
+        map_model(model)?;
+        let window = self
+            .rule_for(model)
+            .map(|rule| rule.context_window)
+            .unwrap_or(self.config.default_context_window);
+        (window > 0).then_some(window)
+    }
+
+    /// 根据估算的提示词 tokens 选择模型
+    pub fn route(&self, model: &str, tokens: u64) -> ContextRoute {
+        let Some(window) = self.context_window(model) else {
+            return ContextRoute::Fits;
+        };
+        if tokens <= window {
+            return ContextRoute::Fits;
+        }
+
+        let mut visited = vec![model.to_string()];
+        let mut current = model.to_string();
+        while let Some(next) = self
+            .rule_for(&current)
+            .and_then(|rule| rule.fallback_model.clone())
+        {
+            if visited.contains(&next) {
+                break;
+            }
+            visited.push(next.clone());
+            match self.context_window(&next) {
+                Some(fallback_window) if tokens > fallback_window => current = next,
+                _ => return ContextRoute::Reroute { model: next },
+            }
+        }
+
+        ContextRoute::Overflow {
+            context_window: window,
+            over_by: tokens - window,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(model: &str, context_window: u64, fallback_model: Option<&str>) -> ContextWindowRule {
+        ContextWindowRule {
+            model: model.to_string(),
+            context_window,
+            fallback_model: fallback_model.map(|m| m.to_string()),
+        }
+    }
+
+    #[test]
+    fn test_route_fallback_and_overflow() {
+        let config = ContextRoutingConfig {
+            enabled: true,
+            default_context_window: 200_000,
+            models: vec![
+                rule("claude-haiku-4.5", 100_000, Some("claude-sonnet-4-5")),
+                rule("claude-sonnet-4.5", 150_000, Some("claude-opus-4-6")),
+                rule("claude-opus-4.6", 300_000, Some("claude-haiku-4-5")),
+            ],
+        };
+        let router = ContextRouter::from_config(&config).unwrap();
+
+        assert_eq!(router.route("claude-haiku-4-5", 90_000), ContextRoute::Fits);
+        assert_eq!(
+            router.route("claude-haiku-4-5", 120_000),
+            ContextRoute::Reroute {
+                model: "claude-sonnet-4-5".to_string()
+            }
+        );
+        // 沿回退链跳过同样放不下的模型
+        assert_eq!(
+            router.route("claude-haiku-4-5", 250_000),
+            ContextRoute::Reroute {
+                model: "claude-opus-4-6".to_string()
+            }
+        );
+        // 回退链成环且都放不下时按请求模型的窗口报告超出量
+        assert_eq!(
+            router.route("claude-haiku-4-5", 350_000),
+            ContextRoute::Overflow {
+                context_window: 100_000,
+                over_by: 250_000
+            }
+        );
+        // 未配置规则的模型使用默认窗口，不支持的模型交给转换层处理
+        assert_eq!(
+            router.route("claude-opus-4-5", 200_001),
+            ContextRoute::Overflow {
+                context_window: 200_000,
+                over_by: 1
+            }
+        );
+        assert_eq!(router.route("gpt-4", 1_000_000), ContextRoute::Fits);
+
+        assert!(ContextRouter::from_config(&ContextRoutingConfig::default()).is_none());
+    }
+}
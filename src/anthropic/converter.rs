@@ -2,6 +2,8 @@
 //!
 //! 负责将 Anthropic API 请求格式转换为 Kiro API 请求格式
 
+use std::sync::OnceLock;
+
 use uuid::Uuid;
 
 use crate::kiro::model::requests::conversation::{
@@ -14,13 +16,55 @@ use crate::kiro::model::requests::tool::{
 
 use super::types::{ContentBlock, MessagesRequest, Thinking};
 
+/// 自定义模型（配置 `customModels`）
+#[derive(Debug, Clone)]
+pub struct CustomModel {
+    /// 客户端请求中的模型名
+    pub id: String,
+    /// Kiro 模型 ID
+    pub model_id: String,
+    /// 覆盖的 agentTaskType
+    pub agent_task_type: Option<String>,
+    /// 显示名
+    pub display_name: Option<String>,
+}
+
+/// 已注册的自定义模型
+static CUSTOM_MODELS: OnceLock<Vec<CustomModel>> = OnceLock::new();
+
+/// 注册自定义模型
+///
+/// 应在应用启动时调用一次
+pub fn init_custom_models(models: Vec<CustomModel>) {
+    let _ = CUSTOM_MODELS.set(models);
+}
+
+/// 已注册的自定义模型
+pub fn custom_models() -> &'static [CustomModel] {
+    CUSTOM_MODELS.get().map(Vec::as_slice).unwrap_or_default()
+}
+
+/// 按模型名（不区分大小写）查找已注册的自定义模型
+fn custom_model(model: &str) -> Option<&'static CustomModel> {
+    find_custom_model(custom_models(), model)
+}
+
+fn find_custom_model<'a>(models: &'a [CustomModel], model: &str) -> Option<&'a CustomModel> {
+    models
+        .iter()
+        .find(|custom| custom.id.eq_ignore_ascii_case(model))
+}
+
 /// 模型映射：将 Anthropic 模型名映射到 Kiro 模型 ID
 ///
-/// 按照用户要求：
+/// 自定义模型优先，其余按照用户要求：
 /// - 所有 sonnet → claude-sonnet-4.5
 /// - 所有 opus → claude-opus-4.5
 /// - 所有 haiku → claude-haiku-4.5
 pub fn map_model(model: &str) -> Option<String> {
+    if let Some(custom) = custom_model(model) {
+        return Some(custom.model_id.clone());
+    }
     let model_lower = model.to_lowercase();
 
     if model_lower.contains("sonnet") {
@@ -67,14 +111,14 @@ impl std::error::Error for ConversionError {}
 ///
 /// user_id 格式: user_xxx_account__session_0b4445e1-f5be-49e1-87ce-62bbc28ad705
 /// 提取 session_ 后面的 UUID 作为 conversationId
-fn extract_session_id(user_id: &str) -> Option<String> {
+pub fn extract_session_id(user_id: &str) -> Option<String> {
     // 查找 "session_" 后面的内容
     if let Some(pos) = user_id.find("session_") {
         let session_part = &user_id[pos + 8..]; // "session_" 长度为 8
         // session_part 应该是 UUID 格式: xxxxxxxx-xxxx-xxxx-xxxx-xxxxxxxxxxxx
         // 验证是否是有效的 UUID 格式（36 字符，包含 4 个连字符）
-        if session_part.len() >= 36 {
-            let uuid_str = &session_part[..36];
+        // 用 get 截取：user_id 来自客户端，第 36 字节可能落在多字节字符中间
+        if let Some(uuid_str) = session_part.get(..36) {
             // 简单验证 UUID 格式
             if uuid_str.chars().filter(|c| *c == '-').count() == 4 {
                 return Some(uuid_str.to_string());
@@ -198,9 +242,12 @@ pub fn convert_request(req: &MessagesRequest) -> Result<ConversionResult, Conver
     let current_message = CurrentMessage::new(user_input);
 
     // 12. 构建 ConversationState
+    let agent_task_type = custom_model(&req.model)
+        .and_then(|custom| custom.agent_task_type.as_deref())
+        .unwrap_or("vibe");
     let conversation_state = ConversationState::new(conversation_id)
         .with_agent_continuation_id(agent_continuation_id)
-        .with_agent_task_type("vibe")
+        .with_agent_task_type(agent_task_type)
         .with_chat_trigger_type(chat_trigger_type)
         .with_current_message(current_message)
         .with_history(history);
@@ -668,6 +715,19 @@ mod tests {
         assert!(map_model("gpt-4").is_none());
     }
 
+    #[test]
+    fn test_find_custom_model() {
+        let models = vec![CustomModel {
+            id: "kiro-next-preview".to_string(),
+            model_id: "claude-next".to_string(),
+            agent_task_type: Some("spec".to_string()),
+            display_name: None,
+        }];
+        let custom = find_custom_model(&models, "Kiro-Next-Preview").unwrap();
+        assert_eq!(custom.model_id, "claude-next");
+        assert!(find_custom_model(&models, "kiro-next").is_none());
+    }
+
     #[test]
     fn test_determine_chat_trigger_type() {
         // 无工具时返回 MANUAL
@@ -681,6 +741,8 @@ mod tests {
             tool_choice: None,
             thinking: None,
             metadata: None,
+            temperature: None,
+            stop_sequences: None,
         };
         assert_eq!(determine_chat_trigger_type(&req), "MANUAL");
     }
@@ -759,6 +821,8 @@ mod tests {
             tool_choice: None,
             thinking: None,
             metadata: None,
+            temperature: None,
+            stop_sequences: None,
         };
 
         let result = convert_request(&req).unwrap();
@@ -805,6 +869,13 @@ mod tests {
         assert_eq!(session_id, None);
     }
 
+    #[test]
+    fn test_extract_session_id_multibyte() {
+        // 第 36 字节落在多字节字符中间时不应 panic
+        let user_id = "user_xxx_session_8bb5523b-ec7c-4540-a9ca-beb6d79f155中";
+        assert_eq!(extract_session_id(user_id), None);
+    }
+
     #[test]
     fn test_convert_request_with_session_metadata() {
         use super::super::types::{Message as AnthropicMessage, Metadata};
@@ -827,6 +898,8 @@ mod tests {
                     "user_0dede55c6dcc4a11a30bbb5e7f22e6fdf86cdeba3820019cc27612af4e1243cd_account__session_a0662283-7fd3-4399-a7eb-52b9a717ae88".to_string(),
                 ),
             }),
+            temperature: None,
+            stop_sequences: None,
         };
 
         let result = convert_request(&req).unwrap();
@@ -854,6 +927,8 @@ mod tests {
             tool_choice: None,
             thinking: None,
             metadata: None,
+            temperature: None,
+            stop_sequences: None,
         };
 
         let result = convert_request(&req).unwrap();
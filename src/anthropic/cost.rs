@@ -0,0 +1,149 @@
+//! 请求成本估算
+//!
+//! 按 `costEstimation.models`（匹配映射后的 Kiro 模型 ID）估算每个请求消耗的 Kiro 请求次数，
+//! 配置了 token 单价时再估算 token 成本，结果以响应头返回。流式响应在发送响应头时尚不知道
+//! 输出 tokens，只返回请求次数与输入 tokens。
+
+use std::sync::Arc;
+
+use axum::http::{HeaderMap, HeaderValue};
+
+use crate::kiro::transform::model_matches;
+use crate::model::config::CostEstimationConfig;
+
+use super::converter::map_model;
+
+/// 本次请求消耗的 Kiro 请求次数
+pub const REQUESTS_CONSUMED_HEADER: &str = "x-kiro-requests-consumed";
+/// 估算的输入 tokens
+pub const INPUT_TOKENS_HEADER: &str = "x-kiro-estimated-input-tokens";
+/// 估算的输出 tokens（仅非流式响应）
+pub const OUTPUT_TOKENS_HEADER: &str = "x-kiro-estimated-output-tokens";
+/// 估算的 token 成本（仅非流式响应，且配置了 token 单价）
+pub const COST_HEADER: &str = "x-kiro-estimated-cost";
+
+/// 单个模型的成本
+#[derive(Debug, Clone, PartialEq)]
+pub struct CostRate {
+    pub requests: f64,
+    pub input_cost_per_million: f64,
+    pub output_cost_per_million: f64,
+}
+
+impl CostRate {
+    /// 写入成本估算响应头（`output_tokens` 未知时只写请求次数与输入 tokens）
+    pub fn apply_headers(
+        &self,
+        headers: &mut HeaderMap,
+        input_tokens: i32,
+        output_tokens: Option<i32>,
+    ) {
+        let mut insert = |name: &'static str, value: String| {
+            if let Ok(value) = HeaderValue::from_str(&value) {
+                headers.insert(name, value);
+            }
+        };
+        insert(REQUESTS_CONSUMED_HEADER, self.requests.to_string());
+        insert(INPUT_TOKENS_HEADER, input_tokens.to_string());
+        let Some(output_tokens) = output_tokens else {
+            return;
+        };
+        insert(OUTPUT_TOKENS_HEADER, output_tokens.to_string());
+        if self.input_cost_per_million > 0.0 || self.output_cost_per_million > 0.0 {
+            let cost = (input_tokens.max(0) as f64 * self.input_cost_per_million
+                + output_tokens.max(0) as f64 * self.output_cost_per_million)
+                / 1_000_000.0;
+            insert(COST_HEADER, format!("{:.6}", cost));
+        }
+    }
+}
+
+/// 模型成本表
+pub struct CostTable {
+    config: CostEstimationConfig,
+}
+
+impl CostTable {
+    /// 从配置创建（未启用时返回 None）
+    pub fn from_config(config: &CostEstimationConfig) -> Option<Arc<Self>> {
+        config.enabled.then(|| {
+            Arc::new(Self {
+                config: config.clone(),
+            })
+        })
+    }
+
+    /// 查找模型的成本（未匹配时每次请求按 `defaultRequests` 计）
+    pub fn rate(&self, model: &str) -> CostRate {
+        let rule = map_model(model).and_then(|model_id| {
+            self.config
+                .models
+                .iter()
+                .find(|rule| model_matches(&rule.model, &model_id))
+        });
+        match rule {
+            Some(rule) => CostRate {
+                requests: rule.requests,
+                input_cost_per_million: rule.input_cost_per_million,
+                output_cost_per_million: rule.output_cost_per_million,
+            },
+            None => CostRate {
+                requests: self.config.default_requests,
+                input_cost_per_million: 0.0,
+                output_cost_per_million: 0.0,
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::config::ModelCostRule;
+
+    #[test]
+    fn test_rate_and_headers() {
+        let mut config = CostEstimationConfig {
+            enabled: true,
+            ..CostEstimationConfig::default()
+        };
+        config.models.insert(
+            0,
+            ModelCostRule {
+                model: "claude-opus-4.6".to_string(),
+                requests: 3.0,
+                input_cost_per_million: 15.0,
+                output_cost_per_million: 75.0,
+            },
+        );
+        let table = CostTable::from_config(&config).unwrap();
+
+        assert_eq!(table.rate("claude-sonnet-4-5-20250929").requests, 1.3);
+        assert_eq!(table.rate("claude-opus-4-5").requests, 2.2);
+        assert_eq!(table.rate("gpt-4").requests, 1.0);
+
+        let mut headers = HeaderMap::new();
+        table
+            .rate("claude-opus-4-6")
+            .apply_headers(&mut headers, 1000, None);
+        assert_eq!(headers[REQUESTS_CONSUMED_HEADER], "3");
+        assert_eq!(headers[INPUT_TOKENS_HEADER], "1000");
+        assert!(headers.get(COST_HEADER).is_none());
+
+        table
+            .rate("claude-opus-4-6")
+            .apply_headers(&mut headers, 1000, Some(2000));
+        assert_eq!(headers[OUTPUT_TOKENS_HEADER], "2000");
+        assert_eq!(headers[COST_HEADER], "0.165000");
+
+        // 未配置 token 单价时不返回成本
+        let mut headers = HeaderMap::new();
+        table
+            .rate("claude-haiku-4-5")
+            .apply_headers(&mut headers, 10, Some(10));
+        assert_eq!(headers[REQUESTS_CONSUMED_HEADER], "0.4");
+        assert!(headers.get(COST_HEADER).is_none());
+
+        assert!(CostTable::from_config(&CostEstimationConfig::default()).is_none());
+    }
+}
@@ -0,0 +1,275 @@
+//! 请求/响应过滤器
+//!
+//! 过滤器在固定的挂载点检查或修改 JSON：
+//! - `Request`：Anthropic 请求体，在使用范围检查和请求转换之前
+//! - `Response`：非流式响应体，在返回客户端之前（流式响应不经过这一挂载点）
+//! - `Error`：错误响应 `{"status": 502, "body": {...}}`，在返回客户端之前
+//!
+//! 过滤器按配置顺序执行（先 WASM 过滤器，后脚本钩子），前一个的输出是后一个的输入；任一过滤器拒绝即中止。
+
+#[cfg(feature = "scripting")]
+pub mod script;
+#[cfg(feature = "wasm-filters")]
+pub mod wasm;
+
+use std::fmt;
+use std::sync::Arc;
+
+use serde_json::Value;
+
+use crate::model::config::{Config, ScriptHooksConfig, WasmFilterConfig};
+
+/// 挂载点
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FilterHook {
+    Request,
+    Response,
+    Error,
+}
+
+impl FilterHook {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FilterHook::Request => "request",
+            FilterHook::Response => "response",
+            FilterHook::Error => "error",
+        }
+    }
+}
+
+/// 单个过滤器的处理结果
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(
+    not(any(feature = "wasm-filters", feature = "scripting")),
+    allow(dead_code)
+)]
+pub enum FilterOutcome {
+    /// 原样放行
+    Continue,
+    /// 替换为新的 JSON
+    Replace(Value),
+    /// 拒绝，附带返回给客户端的原因
+    Reject(String),
+}
+
+/// 过滤器
+pub trait Filter: Send + Sync {
+    /// 名称（用于日志）
+    fn name(&self) -> &str;
+
+    /// 执行失败时是否放行
+    fn fail_open(&self) -> bool {
+        false
+    }
+
+    /// 处理一个挂载点的 JSON；返回 Err 表示过滤器自身执行失败
+    fn apply(&self, hook: FilterHook, value: &Value) -> Result<FilterOutcome, String>;
+}
+
+/// 过滤链中止的原因
+#[derive(Debug, Clone, PartialEq)]
+pub enum FilterError {
+    /// 被过滤器拒绝
+    Rejected { filter: String, message: String },
+    /// 过滤器执行失败（且未设置 failOpen）
+    Failed { filter: String, message: String },
+}
+
+impl fmt::Display for FilterError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FilterError::Rejected { filter, message } => {
+                write!(f, "请求被过滤器 {} 拒绝: {}", filter, message)
+            }
+            FilterError::Failed { filter, message } => {
+                write!(f, "过滤器 {} 执行失败: {}", filter, message)
+            }
+        }
+    }
+}
+
+impl std::error::Error for FilterError {}
+
+/// 过滤链
+#[derive(Default, Clone)]
+pub struct FilterChain {
+    filters: Vec<Arc<dyn Filter>>,
+}
+
+impl FilterChain {
+    /// 按配置加载 WASM 过滤器与脚本钩子
+    pub fn from_config(config: &Config) -> anyhow::Result<Self> {
+        let mut chain = Self::default();
+        for filter in &config.wasm_filters {
+            chain.push(load_wasm(filter)?);
+        }
+        if !config.script_hooks.is_empty() {
+            chain.push(load_script(&config.script_hooks)?);
+        }
+        Ok(chain)
+    }
+
+    /// 追加过滤器
+    pub fn push(&mut self, filter: Arc<dyn Filter>) {
+        self.filters.push(filter);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.filters.is_empty()
+    }
+
+    /// 依次执行所有过滤器，全部放行时返回 `Some(最终 JSON)`，无过滤器修改时返回 None
+    pub fn apply(&self, hook: FilterHook, value: &Value) -> Result<Option<Value>, FilterError> {
+        let mut current: Option<Value> = None;
+        for filter in &self.filters {
+            let input = current.as_ref().unwrap_or(value);
+            match filter.apply(hook, input) {
+                Ok(FilterOutcome::Continue) => {}
+                Ok(FilterOutcome::Replace(next)) => {
+                    tracing::debug!("过滤器 {} 修改了 {}", filter.name(), hook.as_str());
+                    current = Some(next);
+                }
+                Ok(FilterOutcome::Reject(message)) => {
+                    tracing::info!(
+                        "过滤器 {} 拒绝了 {}: {}",
+                        filter.name(),
+                        hook.as_str(),
+                        message
+                    );
+                    return Err(FilterError::Rejected {
+                        filter: filter.name().to_string(),
+                        message,
+                    });
+                }
+                Err(message) if filter.fail_open() => {
+                    tracing::warn!("过滤器 {} 执行失败（放行）: {}", filter.name(), message);
+                }
+                Err(message) => {
+                    tracing::error!("过滤器 {} 执行失败: {}", filter.name(), message);
+                    return Err(FilterError::Failed {
+                        filter: filter.name().to_string(),
+                        message,
+                    });
+                }
+            }
+        }
+        Ok(current)
+    }
+}
+
+#[cfg(feature = "wasm-filters")]
+fn load_wasm(config: &WasmFilterConfig) -> anyhow::Result<Arc<dyn Filter>> {
+    let filter = wasm::WasmFilter::load(config)?;
+    tracing::info!("已加载 WASM 过滤器: {}", filter.name());
+    Ok(Arc::new(filter))
+}
+
+#[cfg(not(feature = "wasm-filters"))]
+fn load_wasm(config: &WasmFilterConfig) -> anyhow::Result<Arc<dyn Filter>> {
+    anyhow::bail!(
+        "配置了 WASM 过滤器 {}，但编译时未启用 wasm-filters 特性",
+        config.path
+    )
+}
+
+#[cfg(feature = "scripting")]
+fn load_script(config: &ScriptHooksConfig) -> anyhow::Result<Arc<dyn Filter>> {
+    let filter = script::ScriptFilter::load(config)?;
+    tracing::info!("已加载脚本钩子");
+    Ok(Arc::new(filter))
+}
+
+#[cfg(not(feature = "scripting"))]
+fn load_script(_config: &ScriptHooksConfig) -> anyhow::Result<Arc<dyn Filter>> {
+    anyhow::bail!("配置了 scriptHooks，但编译时未启用 scripting 特性")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    /// 按固定结果处理的过滤器
+    struct Fixed {
+        name: &'static str,
+        fail_open: bool,
+        outcome: Result<FilterOutcome, String>,
+    }
+
+    impl Filter for Fixed {
+        fn name(&self) -> &str {
+            self.name
+        }
+
+        fn fail_open(&self) -> bool {
+            self.fail_open
+        }
+
+        fn apply(&self, _hook: FilterHook, _value: &Value) -> Result<FilterOutcome, String> {
+            self.outcome.clone()
+        }
+    }
+
+    fn chain(filters: Vec<Fixed>) -> FilterChain {
+        let mut chain = FilterChain::default();
+        for filter in filters {
+            chain.push(Arc::new(filter));
+        }
+        chain
+    }
+
+    fn fixed(name: &'static str, outcome: Result<FilterOutcome, String>) -> Fixed {
+        Fixed {
+            name,
+            fail_open: false,
+            outcome,
+        }
+    }
+
+    #[test]
+    fn test_chain_replace_and_reject() {
+        let body = json!({"model": "a"});
+        assert_eq!(
+            FilterChain::default().apply(FilterHook::Request, &body),
+            Ok(None)
+        );
+
+        let replaced = chain(vec![
+            fixed("pass", Ok(FilterOutcome::Continue)),
+            fixed("rewrite", Ok(FilterOutcome::Replace(json!({"model": "b"})))),
+        ]);
+        assert_eq!(
+            replaced.apply(FilterHook::Request, &body),
+            Ok(Some(json!({"model": "b"})))
+        );
+
+        let rejected = chain(vec![
+            fixed("rewrite", Ok(FilterOutcome::Replace(json!({})))),
+            fixed("deny", Ok(FilterOutcome::Reject("不允许".to_string()))),
+        ]);
+        assert_eq!(
+            rejected.apply(FilterHook::Request, &body),
+            Err(FilterError::Rejected {
+                filter: "deny".to_string(),
+                message: "不允许".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn test_chain_failures() {
+        let body = json!({});
+        let closed = chain(vec![fixed("broken", Err("trap".to_string()))]);
+        assert!(matches!(
+            closed.apply(FilterHook::Response, &body),
+            Err(FilterError::Failed { .. })
+        ));
+
+        let open = chain(vec![Fixed {
+            name: "broken",
+            fail_open: true,
+            outcome: Err("trap".to_string()),
+        }]);
+        assert_eq!(open.apply(FilterHook::Response, &body), Ok(None));
+    }
+}
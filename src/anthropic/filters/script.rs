@@ -0,0 +1,166 @@
+//! Rhai 脚本钩子
+//!
+//! 每个挂载点可配置一个脚本。脚本中的 `body` 变量是该挂载点的 JSON（对象映射为 Rhai map），
+//! 执行结束时 `body` 被修改则替换原 JSON；`throw "原因"` 拒绝请求。
+//!
+//! 脚本运行在受限的引擎中：没有文件/网络访问，禁用 `eval`，限制操作数、调用深度和字符串/集合大小；
+//! `print`/`debug` 输出到日志。
+
+use anyhow::Context;
+use rhai::{AST, Dynamic, Engine, EvalAltResult, Scope};
+use serde_json::Value;
+
+use super::{Filter, FilterHook, FilterOutcome};
+use crate::model::config::ScriptHooksConfig;
+
+/// 已编译的脚本钩子
+pub struct ScriptFilter {
+    engine: Engine,
+    hooks: Vec<(FilterHook, AST)>,
+    fail_open: bool,
+}
+
+impl ScriptFilter {
+    /// 读取并编译配置的脚本
+    pub fn load(config: &ScriptHooksConfig) -> anyhow::Result<Self> {
+        let mut sources = Vec::new();
+        for (hook, path) in [
+            (FilterHook::Request, &config.pre_request),
+            (FilterHook::Response, &config.post_response),
+            (FilterHook::Error, &config.on_error),
+        ] {
+            if let Some(path) = path {
+                let source = std::fs::read_to_string(path)
+                    .with_context(|| format!("读取脚本失败: {}", path))?;
+                sources.push((hook, source));
+            }
+        }
+        Self::compile(&sources, config)
+    }
+
+    fn compile(
+        sources: &[(FilterHook, String)],
+        config: &ScriptHooksConfig,
+    ) -> anyhow::Result<Self> {
+        let mut engine = Engine::new();
+        engine.set_max_operations(config.max_operations);
+        engine.set_max_call_levels(32);
+        engine.set_max_expr_depths(64, 32);
+        engine.set_max_string_size(1024 * 1024);
+        engine.set_max_array_size(100_000);
+        engine.set_max_map_size(100_000);
+        engine.disable_symbol("eval");
+        engine.on_print(|text| tracing::info!("脚本输出: {}", text));
+        engine.on_debug(|text, _, pos| tracing::debug!("脚本输出 ({}): {}", pos, text));
+
+        let hooks = sources
+            .iter()
+            .map(|(hook, source)| {
+                engine
+                    .compile(source)
+                    .map(|ast| (*hook, ast))
+                    .map_err(|e| anyhow::anyhow!("编译 {} 脚本失败: {}", hook.as_str(), e))
+            })
+            .collect::<anyhow::Result<_>>()?;
+        Ok(Self {
+            engine,
+            hooks,
+            fail_open: config.fail_open,
+        })
+    }
+}
+
+impl Filter for ScriptFilter {
+    fn name(&self) -> &str {
+        "script"
+    }
+
+    fn fail_open(&self) -> bool {
+        self.fail_open
+    }
+
+    fn apply(&self, hook: FilterHook, value: &Value) -> Result<FilterOutcome, String> {
+        let Some((_, ast)) = self.hooks.iter().find(|(h, _)| *h == hook) else {
+            return Ok(FilterOutcome::Continue);
+        };
+
+        let body = rhai::serde::to_dynamic(value).map_err(|e| e.to_string())?;
+        let mut scope = Scope::new();
+        scope.push_dynamic("body", body);
+        if let Err(e) = self.engine.run_ast_with_scope(&mut scope, ast) {
+            return match *e {
+                EvalAltResult::ErrorRuntime(reason, _) => Ok(FilterOutcome::Reject(
+                    reason
+                        .into_string()
+                        .unwrap_or_else(|reason| reason.to_string()),
+                )),
+                other => Err(other.to_string()),
+            };
+        }
+
+        let body = scope.get_value::<Dynamic>("body").unwrap_or_default();
+        let output: Value = rhai::serde::from_dynamic(&body).map_err(|e| e.to_string())?;
+        Ok(if output == *value {
+            FilterOutcome::Continue
+        } else {
+            FilterOutcome::Replace(output)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn filter(hook: FilterHook, source: &str) -> ScriptFilter {
+        ScriptFilter::compile(&[(hook, source.to_string())], &ScriptHooksConfig::default()).unwrap()
+    }
+
+    #[test]
+    fn test_rewrite_and_reject() {
+        let script = r#"
+            if body.model.starts_with("claude-opus") {
+                if body.max_tokens > 4096 { throw "opus 最多 4096 tokens"; }
+                body.model = "claude-sonnet-4-5";
+            }
+        "#;
+        let filter = filter(FilterHook::Request, script);
+
+        assert_eq!(
+            filter.apply(
+                FilterHook::Request,
+                &json!({"model": "claude-opus-4-6", "max_tokens": 1024})
+            ),
+            Ok(FilterOutcome::Replace(
+                json!({"model": "claude-sonnet-4-5", "max_tokens": 1024})
+            ))
+        );
+        assert_eq!(
+            filter.apply(
+                FilterHook::Request,
+                &json!({"model": "claude-opus-4-6", "max_tokens": 8192})
+            ),
+            Ok(FilterOutcome::Reject("opus 最多 4096 tokens".to_string()))
+        );
+        assert_eq!(
+            filter.apply(FilterHook::Request, &json!({"model": "claude-haiku-4-5"})),
+            Ok(FilterOutcome::Continue)
+        );
+        // 未配置的挂载点放行
+        assert_eq!(
+            filter.apply(FilterHook::Error, &json!({})),
+            Ok(FilterOutcome::Continue)
+        );
+    }
+
+    #[test]
+    fn test_sandbox_limits() {
+        let looping = filter(FilterHook::Response, "loop { }");
+        assert!(looping.apply(FilterHook::Response, &json!({})).is_err());
+
+        let config = ScriptHooksConfig::default();
+        let eval = [(FilterHook::Request, r#"eval("1")"#.to_string())];
+        assert!(ScriptFilter::compile(&eval, &config).is_err());
+    }
+}
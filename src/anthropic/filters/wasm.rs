@@ -0,0 +1,230 @@
+//! WASM 过滤器
+//!
+//! 模块不能导入任何宿主函数（没有 WASI、文件或网络访问），每次调用都在新的实例中执行，
+//! 受 fuel（指令预算）和线性内存上限约束。
+//!
+//! # 接口约定
+//!
+//! 模块需导出：
+//! - `memory`：线性内存
+//! - `alloc(len: i32) -> i32`：分配 `len` 字节，返回偏移
+//! - `on_request`、`on_response`、`on_error`：挂载点处理函数（签名 `(ptr: i32, len: i32) -> i64`，
+//!   可只导出其中一部分），输入为 UTF-8 JSON
+//!
+//! 处理函数返回 0 表示原样放行；否则返回 `(ptr << 32) | len`，指向一段 UTF-8 JSON：
+//! - `{"action": "continue"}`：原样放行
+//! - `{"action": "replace", "body": {...}}`：替换为 `body`
+//! - `{"action": "reject", "message": "..."}`：拒绝，`message` 返回给客户端
+
+use anyhow::{Context, bail};
+use serde::Deserialize;
+use serde_json::Value;
+use wasmtime::{Config, Engine, Instance, Module, Store, StoreLimits, StoreLimitsBuilder};
+
+use super::{Filter, FilterHook, FilterOutcome};
+use crate::model::config::WasmFilterConfig;
+
+/// 挂载点处理函数名
+const HOOK_EXPORTS: [&str; 3] = ["on_request", "on_response", "on_error"];
+
+/// 处理函数返回的结果
+#[derive(Deserialize)]
+#[serde(tag = "action", rename_all = "lowercase")]
+enum WasmOutcome {
+    Continue,
+    Replace { body: Value },
+    Reject { message: String },
+}
+
+/// 已编译的 WASM 过滤器
+pub struct WasmFilter {
+    name: String,
+    engine: Engine,
+    module: Module,
+    fuel: u64,
+    max_memory_bytes: usize,
+    fail_open: bool,
+}
+
+impl WasmFilter {
+    /// 读取并编译模块
+    pub fn load(config: &WasmFilterConfig) -> anyhow::Result<Self> {
+        let bytes = std::fs::read(&config.path)
+            .with_context(|| format!("读取 WASM 过滤器失败: {}", config.path))?;
+        Self::from_binary(&bytes, config)
+            .with_context(|| format!("加载 WASM 过滤器失败: {}", config.path))
+    }
+
+    fn from_binary(bytes: &[u8], config: &WasmFilterConfig) -> anyhow::Result<Self> {
+        let mut engine_config = Config::new();
+        engine_config.consume_fuel(true);
+        let engine = Engine::new(&engine_config)?;
+        let module = Module::new(&engine, bytes)?;
+
+        if module.imports().next().is_some() {
+            bail!("WASM 过滤器不能导入宿主函数");
+        }
+        let exported = |name: &str| module.exports().any(|e| e.name() == name);
+        for required in ["memory", "alloc"] {
+            if !exported(required) {
+                bail!("缺少导出 {}", required);
+            }
+        }
+        if !HOOK_EXPORTS.iter().any(|name| exported(name)) {
+            bail!("未导出任何挂载点处理函数（{}）", HOOK_EXPORTS.join("、"));
+        }
+
+        let name = config.name.clone().unwrap_or_else(|| {
+            std::path::Path::new(&config.path)
+                .file_stem()
+                .map(|s| s.to_string_lossy().into_owned())
+                .unwrap_or_else(|| config.path.clone())
+        });
+        Ok(Self {
+            name,
+            engine,
+            module,
+            fuel: config.fuel,
+            max_memory_bytes: config.max_memory_mb * 1024 * 1024,
+            fail_open: config.fail_open,
+        })
+    }
+
+    /// 在新实例中调用处理函数，返回其输出（返回 0 时为 None）
+    fn call(&self, export: &str, input: &[u8]) -> anyhow::Result<Option<Vec<u8>>> {
+        let limits = StoreLimitsBuilder::new()
+            .memory_size(self.max_memory_bytes)
+            .build();
+        let mut store: Store<StoreLimits> = Store::new(&self.engine, limits);
+        store.limiter(|limits| limits);
+        store.set_fuel(self.fuel)?;
+
+        let instance = Instance::new(&mut store, &self.module, &[])?;
+        let memory = instance
+            .get_memory(&mut store, "memory")
+            .context("缺少导出 memory")?;
+        let alloc = instance.get_typed_func::<i32, i32>(&mut store, "alloc")?;
+        let hook = instance.get_typed_func::<(i32, i32), i64>(&mut store, export)?;
+
+        let len = i32::try_from(input.len()).context("输入过大")?;
+        let ptr = alloc.call(&mut store, len)?;
+        memory.write(&mut store, ptr as u32 as usize, input)?;
+        let ret = hook.call(&mut store, (ptr, len))? as u64;
+        if ret == 0 {
+            return Ok(None);
+        }
+
+        let (out_ptr, out_len) = ((ret >> 32) as usize, (ret & 0xffff_ffff) as usize);
+        let mut output = vec![0; out_len];
+        memory.read(&store, out_ptr, &mut output)?;
+        Ok(Some(output))
+    }
+}
+
+impl Filter for WasmFilter {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn fail_open(&self) -> bool {
+        self.fail_open
+    }
+
+    fn apply(&self, hook: FilterHook, value: &Value) -> Result<FilterOutcome, String> {
+        let export = match hook {
+            FilterHook::Request => "on_request",
+            FilterHook::Response => "on_response",
+            FilterHook::Error => "on_error",
+        };
+        if !self.module.exports().any(|e| e.name() == export) {
+            return Ok(FilterOutcome::Continue);
+        }
+
+        let input = serde_json::to_vec(value).map_err(|e| e.to_string())?;
+        let Some(output) = self.call(export, &input).map_err(|e| format!("{:#}", e))? else {
+            return Ok(FilterOutcome::Continue);
+        };
+        let outcome: WasmOutcome =
+            serde_json::from_slice(&output).map_err(|e| format!("无法解析过滤器输出: {}", e))?;
+        Ok(match outcome {
+            WasmOutcome::Continue => FilterOutcome::Continue,
+            WasmOutcome::Replace { body } => FilterOutcome::Replace(body),
+            WasmOutcome::Reject { message } => FilterOutcome::Reject(message),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn config() -> WasmFilterConfig {
+        serde_json::from_value(json!({"path": "test.wasm", "fuel": 1_000_000})).unwrap()
+    }
+
+    /// 返回固定输出的过滤器：输出放在偏移 0，输入分配到偏移 1024
+    fn fixed_output(export: &str, output: &str) -> WasmFilter {
+        let wat = format!(
+            r#"(module
+                (memory (export "memory") 1)
+                (data (i32.const 0) "{escaped}")
+                (func (export "alloc") (param i32) (result i32) i32.const 1024)
+                (func (export "{export}") (param i32 i32) (result i64)
+                    i64.const {len}))"#,
+            escaped = output.replace('"', "\\\""),
+            len = output.len(),
+        );
+        WasmFilter::from_binary(&wat::parse_str(wat).unwrap(), &config()).unwrap()
+    }
+
+    #[test]
+    fn test_reject_and_replace() {
+        let body = json!({"model": "claude-opus-4-6"});
+
+        let deny = fixed_output("on_request", r#"{"action":"reject","message":"禁止"}"#);
+        assert_eq!(deny.name(), "test");
+        assert_eq!(
+            deny.apply(FilterHook::Request, &body),
+            Ok(FilterOutcome::Reject("禁止".to_string()))
+        );
+        // 未导出 on_response 时放行
+        assert_eq!(
+            deny.apply(FilterHook::Response, &body),
+            Ok(FilterOutcome::Continue)
+        );
+
+        let rewrite = fixed_output(
+            "on_request",
+            r#"{"action":"replace","body":{"model":"claude-sonnet-4-5"}}"#,
+        );
+        assert_eq!(
+            rewrite.apply(FilterHook::Request, &body),
+            Ok(FilterOutcome::Replace(
+                json!({"model": "claude-sonnet-4-5"})
+            ))
+        );
+    }
+
+    #[test]
+    fn test_fuel_exhausted() {
+        let wat = r#"(module
+            (memory (export "memory") 1)
+            (func (export "alloc") (param i32) (result i32) i32.const 0)
+            (func (export "on_request") (param i32 i32) (result i64)
+                (loop (br 0))
+                i64.const 0))"#;
+        let filter = WasmFilter::from_binary(&wat::parse_str(wat).unwrap(), &config()).unwrap();
+        assert!(filter.apply(FilterHook::Request, &json!({})).is_err());
+    }
+
+    #[test]
+    fn test_rejects_imports() {
+        let wat = r#"(module
+            (import "env" "log" (func))
+            (memory (export "memory") 1)
+            (func (export "alloc") (param i32) (result i32) i32.const 0)
+            (func (export "on_request") (param i32 i32) (result i64) i64.const 0))"#;
+        assert!(WasmFilter::from_binary(&wat::parse_str(wat).unwrap(), &config()).is_err());
+    }
+}
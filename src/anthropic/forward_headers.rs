@@ -0,0 +1,107 @@
+//! 上游响应头透传
+//!
+//! 按 `forwardHeaders`（上游响应头名 -> 下游响应头名，下游名为空时沿用原名）把选定的上游响应头
+//! （如请求 ID、限流提示、Server-Timing）附加到返回给客户端的响应上，便于客户端工具关联上游上下文。
+//! 描述响应体本身或连接的头（`content-type`、`content-length`、`transfer-encoding` 等）不允许透传。
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use axum::http::{HeaderMap, HeaderName};
+
+/// 不允许透传的响应头（由本服务按下游响应生成）
+const RESERVED: &[&str] = &[
+    "connection",
+    "content-encoding",
+    "content-length",
+    "content-type",
+    "keep-alive",
+    "set-cookie",
+    "transfer-encoding",
+    "upgrade",
+];
+
+/// 上游响应头透传规则
+pub struct HeaderForwarder {
+    /// （上游名, 下游名），按上游名排序
+    rules: Vec<(HeaderName, HeaderName)>,
+}
+
+impl HeaderForwarder {
+    /// 从配置创建（未配置或全部无效时返回 None）
+    pub fn from_config(config: &HashMap<String, String>) -> Option<Arc<Self>> {
+        let mut rules = Vec::new();
+        for (upstream, downstream) in config {
+            let downstream = if downstream.trim().is_empty() {
+                upstream
+            } else {
+                downstream
+            };
+            match (parse_name(upstream), parse_name(downstream)) {
+                (Some(from), Some(to)) => rules.push((from, to)),
+                _ => tracing::warn!(
+                    "forwardHeaders 中的 {} -> {} 无效或不允许透传，已忽略",
+                    upstream,
+                    downstream
+                ),
+            }
+        }
+        rules.sort_by(|a, b| a.0.as_str().cmp(b.0.as_str()));
+        (!rules.is_empty()).then(|| Arc::new(Self { rules }))
+    }
+
+    /// 把上游响应头中匹配的头按规则写入下游响应头（同名多值保持原样）
+    pub fn apply(&self, upstream: &HeaderMap, downstream: &mut HeaderMap) {
+        for (from, to) in &self.rules {
+            let mut values = upstream.get_all(from).iter().cloned();
+            if let Some(first) = values.next() {
+                downstream.insert(to.clone(), first);
+                for value in values {
+                    downstream.append(to.clone(), value);
+                }
+            }
+        }
+    }
+}
+
+fn parse_name(name: &str) -> Option<HeaderName> {
+    let name = HeaderName::from_bytes(name.trim().as_bytes()).ok()?;
+    (!RESERVED.contains(&name.as_str())).then_some(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+
+    #[test]
+    fn test_forward_and_rename() {
+        let config: HashMap<String, String> = [
+            ("X-Amzn-RequestId", "x-upstream-request-id"),
+            ("server-timing", ""),
+            ("content-length", ""),
+            ("bad header", ""),
+        ]
+        .into_iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect();
+        let forwarder = HeaderForwarder::from_config(&config).unwrap();
+        assert_eq!(forwarder.rules.len(), 2);
+
+        let mut upstream = HeaderMap::new();
+        upstream.insert("x-amzn-requestid", HeaderValue::from_static("req-1"));
+        upstream.append("server-timing", HeaderValue::from_static("db;dur=5"));
+        upstream.append("server-timing", HeaderValue::from_static("app;dur=9"));
+        upstream.insert("content-length", HeaderValue::from_static("10"));
+
+        let mut downstream = HeaderMap::new();
+        downstream.insert("content-length", HeaderValue::from_static("42"));
+        forwarder.apply(&upstream, &mut downstream);
+        assert_eq!(downstream["x-upstream-request-id"], "req-1");
+        assert!(!downstream.contains_key("x-amzn-requestid"));
+        assert_eq!(downstream.get_all("server-timing").iter().count(), 2);
+        assert_eq!(downstream["content-length"], "42");
+
+        assert!(HeaderForwarder::from_config(&HashMap::new()).is_none());
+    }
+}
@@ -2,88 +2,832 @@
 
 use std::convert::Infallible;
 
-use crate::kiro::model::events::Event;
+use crate::common::abuse::AbuseVerdict;
+use crate::common::log_content::{clip, log_error};
+use crate::common::metrics;
+use crate::common::session_token::{self, SessionClaims};
+use crate::common::usage::UsageRecorder;
+use crate::kiro::model::events::{ArtifactEvent, Event};
 use crate::kiro::model::requests::kiro::KiroRequest;
 use crate::kiro::parser::decoder::EventStreamDecoder;
+use crate::kiro::parser::frame;
+use crate::kiro::parser::text::TextJoiner;
+use crate::kiro::provider::{
+    CallOptions, KiroProvider, RetryPolicy, ServedCredential, is_valid_region,
+};
+use crate::model::config::{
+    ContextRoutingConfig, LocalToolKind, RequestDefaults, RequestPreset, StreamPolicy,
+};
 use crate::token;
 use axum::{
     Json as JsonExtractor,
     body::Body,
-    extract::State,
-    http::{StatusCode, header},
+    extract::{Extension, Path, Query, State},
+    http::{HeaderMap, HeaderValue, StatusCode, header},
     response::{IntoResponse, Json, Response},
 };
 use bytes::Bytes;
 use futures::{Stream, StreamExt, stream};
 use serde_json::json;
-use std::time::Duration;
+use sha2::{Digest, Sha256};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 use tokio::time::interval;
 use uuid::Uuid;
 
-use super::converter::{ConversionError, convert_request};
-use super::middleware::AppState;
+use super::artifacts::{self, ArtifactStore};
+use super::canonical::{FromCanonical, ToCanonical};
+use super::chat_completions::{self, AzureQuery, ChatCompletionRequest};
+use super::coalesce::{CoalesceKey, Coalescer};
+use super::compression::ContextCompressor;
+use super::context_routing::ContextRoute;
+use super::converter::{ConversionError, convert_request, custom_models, map_model};
+use super::cost::CostRate;
+use super::filters::{FilterChain, FilterError, FilterHook};
+use super::forward_headers::HeaderForwarder;
+use super::local_tools::{LocalToolRunner, is_local_tool};
+use super::middleware::{AppState, ClientIdentity};
+use super::presets::RequestPresets;
+use super::render::{RenderFormat, StreamRenderer};
+use super::response_cache::{CACHE_HEADER, CacheHit, CacheQuery, ResponseCache};
+use super::responses::{self, ResponsesRequest};
+use super::stall::{self, ReadError, UpstreamBody};
+use super::stop_reason::StopReason;
 use super::stream::{BufferedStreamContext, SseEvent, StreamContext};
 use super::types::{
-    CountTokensRequest, CountTokensResponse, ErrorResponse, MessagesRequest, Model, ModelsResponse,
+    ArtifactDownloadQuery, CountTokensRequest, CountTokensResponse, ErrorResponse, Message,
+    MessagesRequest, Model, ModelCapabilities, ModelPricing, ModelsResponse, SessionTokenRequest,
+    SessionTokenResponse, SystemMessage,
 };
+use super::watermark;
 use super::websearch;
 
-/// GET /v1/models
+/// GET /metrics/load
 ///
-/// 返回可用的模型列表
-pub async fn get_models() -> impl IntoResponse {
-    tracing::info!("Received GET /v1/models request");
+/// 返回进行中的请求数、排队深度、可用凭据数、准入队列各类别的等待时间和凭据事件计数，
+/// 可直接作为 KEDA metrics-api scaler 的数据源（如 `valueLocation: inFlight`）
+pub async fn get_load_metrics(State(state): State<AppState>) -> Response {
+    let Some(load) = &state.load else {
+        return (
+            StatusCode::SERVICE_UNAVAILABLE,
+            Json(ErrorResponse::new("service_unavailable", "负载统计未启用")),
+        )
+            .into_response();
+    };
+    let (available, total, expiring_soon) = state
+        .kiro_provider
+        .as_ref()
+        .map(|p| {
+            let tm = p.token_manager();
+            (
+                tm.available_count(),
+                tm.total_count(),
+                tm.expiring_soon_count(),
+            )
+        })
+        .unwrap_or((0, 0, 0));
+    let mut metrics = load.snapshot(available, total);
+    metrics.credentials_expiring_soon = expiring_soon;
+    if let Some(admission) = &state.admission {
+        metrics.queues = admission.metrics();
+    }
+    metrics.credential_events = state.token_events.as_ref().map(|c| c.snapshot());
+    metrics.slow_clients = state.drain.as_ref().map(|d| d.slow_clients());
+    Json(metrics).into_response()
+}
 
-    let models = vec![
-        Model {
-            id: "claude-sonnet-4-5-20250929".to_string(),
-            object: "model".to_string(),
-            created: 1727568000,
-            owned_by: "anthropic".to_string(),
-            display_name: "Claude Sonnet 4.5".to_string(),
-            model_type: "chat".to_string(),
-            max_tokens: 32000,
-        },
-        Model {
-            id: "claude-opus-4-5-20251101".to_string(),
-            object: "model".to_string(),
-            created: 1730419200,
-            owned_by: "anthropic".to_string(),
-            display_name: "Claude Opus 4.5".to_string(),
-            model_type: "chat".to_string(),
-            max_tokens: 32000,
+/// GET /metrics
+///
+/// Prometheus 文本格式的指标：按端点、模型与客户端（哈希）分类的请求数、tokens 与处理时长，
+/// 与 `/metrics/load` 相同的负载和凭据数量，以及上游事件流的重新同步次数
+pub async fn get_prometheus_metrics(State(state): State<AppState>) -> Response {
+    let mut body = String::new();
+    if let Some(load) = &state.load {
+        let (available, total) = state
+            .kiro_provider
+            .as_ref()
+            .map(|p| {
+                let tm = p.token_manager();
+                (tm.available_count(), tm.total_count())
+            })
+            .unwrap_or((0, 0));
+        let snapshot = load.snapshot(available, total);
+        metrics::single(&mut body, &metrics::IN_FLIGHT_REQUESTS, snapshot.in_flight);
+        metrics::single(&mut body, &metrics::QUEUE_DEPTH, snapshot.queue_depth);
+        metrics::single(
+            &mut body,
+            &metrics::AVAILABLE_CREDENTIALS,
+            snapshot.available_credentials,
+        );
+        metrics::single(&mut body, &metrics::CREDENTIALS, snapshot.total_credentials);
+    }
+    metrics::single(&mut body, &metrics::STREAM_RESYNCS, frame::resync_count());
+    if let Some(metrics) = state.usage.as_ref().and_then(|usage| usage.metrics()) {
+        metrics.render(&mut body);
+    }
+    if let Some(slo) = &state.slo {
+        slo.render(&mut body, chrono::Utc::now());
+    }
+    (
+        [(
+            header::CONTENT_TYPE,
+            "text/plain; version=0.0.4; charset=utf-8",
+        )],
+        body,
+    )
+        .into_response()
+}
+
+/// GET /health
+///
+/// 健康检查（始终返回 200）：`status` 为 `ok`、`degraded`（没有可用凭据，API 返回 503）
+/// 或 `maintenance`（维护模式）；`readOnly` 表示因另一实例持有实例锁而以只读模式运行
+pub async fn get_health(State(state): State<AppState>) -> Response {
+    let (usable, available, total, read_only) = state
+        .kiro_provider
+        .as_ref()
+        .map(|p| {
+            let tm = p.token_manager();
+            (
+                tm.has_usable_credentials(),
+                tm.available_count(),
+                tm.total_count(),
+                tm.is_read_only(),
+            )
+        })
+        .unwrap_or((false, 0, 0, false));
+    let status = if state
+        .maintenance
+        .as_ref()
+        .is_some_and(|m| m.current().is_some())
+    {
+        "maintenance"
+    } else if usable {
+        "ok"
+    } else {
+        "degraded"
+    };
+    Json(json!({
+        "status": status,
+        "availableCredentials": available,
+        "totalCredentials": total,
+        "readOnly": read_only,
+    }))
+    .into_response()
+}
+
+/// 内置模型
+struct BuiltinModel<'a> {
+    id: &'a str,
+    created: i64,
+    display_name: &'a str,
+}
+
+const MODELS: [BuiltinModel<'static>; 4] = [
+    BuiltinModel {
+        id: "claude-sonnet-4-5-20250929",
+        created: 1727568000,
+        display_name: "Claude Sonnet 4.5",
+    },
+    BuiltinModel {
+        id: "claude-opus-4-5-20251101",
+        created: 1730419200,
+        display_name: "Claude Opus 4.5",
+    },
+    BuiltinModel {
+        id: "claude-opus-4-6-20260206",
+        created: 1770253200,
+        display_name: "Claude Opus 4.6",
+    },
+    BuiltinModel {
+        id: "claude-haiku-4-5-20251001",
+        created: 1727740800,
+        display_name: "Claude Haiku 4.5",
+    },
+];
+
+/// 按上下文长度路由与成本表补充模型元数据（`id` 为列出的名称，元数据按内置模型查找）
+fn describe_model(state: &AppState, id: &str, builtin: &BuiltinModel) -> Model {
+    let context_length = state
+        .context_router
+        .as_ref()
+        .and_then(|router| router.context_window(builtin.id))
+        .unwrap_or_else(|| ContextRoutingConfig::default().default_context_window);
+    let per_token = |per_million: f64| (per_million / 1_000_000.0).to_string();
+    let pricing = match state
+        .cost_table
+        .as_ref()
+        .map(|table| table.rate(builtin.id))
+    {
+        Some(rate) => ModelPricing {
+            prompt: per_token(rate.input_cost_per_million),
+            completion: per_token(rate.output_cost_per_million),
+            requests: rate.requests,
         },
-        Model {
-            id: "claude-opus-4-6-20260206".to_string(),
-            object: "model".to_string(),
-            created: 1770253200,
-            owned_by: "anthropic".to_string(),
-            display_name: "Claude Opus 4.6".to_string(),
-            model_type: "chat".to_string(),
-            max_tokens: 32000,
+        None => ModelPricing {
+            prompt: "0".to_string(),
+            completion: "0".to_string(),
+            requests: 1.0,
         },
-        Model {
-            id: "claude-haiku-4-5-20251001".to_string(),
-            object: "model".to_string(),
-            created: 1727740800,
-            owned_by: "anthropic".to_string(),
-            display_name: "Claude Haiku 4.5".to_string(),
-            model_type: "chat".to_string(),
-            max_tokens: 32000,
+    };
+    Model {
+        id: id.to_string(),
+        object: "model".to_string(),
+        created: builtin.created,
+        owned_by: "anthropic".to_string(),
+        display_name: builtin.display_name.to_string(),
+        model_type: "chat".to_string(),
+        max_tokens: 32000,
+        context_length,
+        capabilities: ModelCapabilities {
+            streaming: true,
+            tool_use: true,
+            vision: true,
+            thinking: true,
         },
-    ];
+        pricing,
+    }
+}
+
+/// 内置模型、自定义模型与 Azure 部署名（映射到内置模型时，按部署名列出）
+fn list_models(state: &AppState) -> Vec<Model> {
+    let mut models: Vec<Model> = MODELS
+        .iter()
+        .map(|builtin| describe_model(state, builtin.id, builtin))
+        .collect();
+
+    for custom in custom_models() {
+        let model = BuiltinModel {
+            id: &custom.id,
+            created: 0,
+            display_name: custom.display_name.as_deref().unwrap_or(&custom.id),
+        };
+        models.push(describe_model(state, &custom.id, &model));
+    }
+
+    let mut deployments: Vec<_> = state.azure_deployments.iter().collect();
+    deployments.sort();
+    for (deployment, model) in deployments {
+        let model_id = map_model(model);
+        let builtin = MODELS
+            .iter()
+            .find(|builtin| model_id.is_some() && map_model(builtin.id) == model_id);
+        if let Some(builtin) = builtin {
+            models.push(describe_model(state, deployment, builtin));
+        }
+    }
+    models
+}
+
+/// GET /v1/models
+///
+/// 返回可用的模型列表（含上下文窗口、能力与价格），附带已配置的 Azure 部署名
+pub async fn get_models(State(state): State<AppState>) -> impl IntoResponse {
+    tracing::info!("Received GET /v1/models request");
 
     Json(ModelsResponse {
         object: "list".to_string(),
-        data: models,
+        data: list_models(&state),
     })
 }
 
+/// 请求级 region 覆盖请求头
+const REGION_OVERRIDE_HEADER: &str = "x-kiro-region";
+
+/// 请求级重试策略请求头
+const RETRY_POLICY_HEADER: &str = "x-kiro-retry";
+
+/// 从请求头解析单次请求的调用选项
+fn call_options_from_headers(headers: &HeaderMap) -> Result<CallOptions, Box<Response>> {
+    let mut options = CallOptions::default();
+
+    if let Some(value) = headers.get(REGION_OVERRIDE_HEADER) {
+        let region = value.to_str().unwrap_or_default().trim();
+        if !is_valid_region(region) {
+            return Err(Box::new(
+                (
+                    StatusCode::BAD_REQUEST,
+                    Json(ErrorResponse::new(
+                        "invalid_request_error",
+                        format!("无效的 {} 请求头: {:?}", REGION_OVERRIDE_HEADER, region),
+                    )),
+                )
+                    .into_response(),
+            ));
+        }
+        options = options.with_region(region);
+    }
+
+    if let Some(value) = headers.get(RETRY_POLICY_HEADER) {
+        let value = value.to_str().unwrap_or_default();
+        let Some(retry) = RetryPolicy::parse(value) else {
+            return Err(Box::new(
+                (
+                    StatusCode::BAD_REQUEST,
+                    Json(ErrorResponse::new(
+                        "invalid_request_error",
+                        format!(
+                            "无效的 {} 请求头: {:?}（应为 none、default 或 aggressive）",
+                            RETRY_POLICY_HEADER, value
+                        ),
+                    )),
+                )
+                    .into_response(),
+            ));
+        };
+        options = options.with_retry(retry);
+    }
+
+    Ok(options)
+}
+
+/// 执行滥用检测，命中限流时返回 429 响应
+fn check_abuse(
+    state: &AppState,
+    identity: &ClientIdentity,
+    payload: &MessagesRequest,
+) -> Option<Response> {
+    let guard = state.abuse_guard.as_ref().filter(|g| g.is_enabled())?;
+
+    let client = client_identity(identity, payload);
+    let fingerprint = request_fingerprint(payload);
+    let tool_rounds = count_trailing_tool_rounds(&payload.messages);
+
+    match guard.check(&client, &fingerprint, tool_rounds) {
+        AbuseVerdict::Throttled(reason) => Some(
+            (
+                StatusCode::TOO_MANY_REQUESTS,
+                Json(ErrorResponse::new(
+                    "rate_limit_error",
+                    format!("请求被滥用检测拦截: {}", reason),
+                )),
+            )
+                .into_response(),
+        ),
+        AbuseVerdict::Allow | AbuseVerdict::Flagged(_) => None,
+    }
+}
+
+/// 过滤链中止时的错误响应
+fn filter_error_response(error: FilterError) -> Response {
+    let (status, error_type) = match &error {
+        FilterError::Rejected { .. } => (StatusCode::FORBIDDEN, "permission_error"),
+        FilterError::Failed { .. } => (StatusCode::INTERNAL_SERVER_ERROR, "api_error"),
+    };
+    (
+        status,
+        Json(ErrorResponse::new(error_type, error.to_string())),
+    )
+        .into_response()
+}
+
+/// 执行请求过滤器，过滤器替换的请求体会重新解析
+fn apply_request_filters(state: &AppState, payload: &mut MessagesRequest) -> Option<Response> {
+    if state.filters.is_empty() {
+        return None;
+    }
+    let value = match serde_json::to_value(&*payload) {
+        Ok(value) => value,
+        Err(e) => {
+            tracing::error!("序列化请求失败: {}", e);
+            return None;
+        }
+    };
+    match state.filters.apply(FilterHook::Request, &value) {
+        Ok(None) => None,
+        Ok(Some(next)) => match serde_json::from_value(next) {
+            Ok(next) => {
+                *payload = next;
+                None
+            }
+            Err(e) => Some(
+                (
+                    StatusCode::BAD_REQUEST,
+                    Json(ErrorResponse::new(
+                        "invalid_request_error",
+                        format!("过滤器输出的请求无效: {}", e),
+                    )),
+                )
+                    .into_response(),
+            ),
+        },
+        Err(e) => Some(filter_error_response(e)),
+    }
+}
+
+/// 没有可用凭据时的 503 响应
+fn no_credentials_response(provider: &KiroProvider) -> Option<Response> {
+    let tm = provider.token_manager();
+    if tm.has_usable_credentials() {
+        return None;
+    }
+    let message = if tm.total_count() == 0 {
+        "代理尚未配置凭据，请通过 Admin API 添加凭据".to_string()
+    } else {
+        format!(
+            "代理没有可用凭据（{} 个凭据均已禁用），请通过 Admin API 启用或添加凭据",
+            tm.total_count()
+        )
+    };
+    tracing::warn!("{}", message);
+    Some(
+        (
+            StatusCode::SERVICE_UNAVAILABLE,
+            Json(ErrorResponse::new("service_unavailable", message)),
+        )
+            .into_response(),
+    )
+}
+
+/// 用客户端密钥的默认生成参数补齐请求中缺失的字段（请求自带的值优先），再应用客户端密钥选择的请求预设
+fn apply_request_defaults(
+    presets: Option<&RequestPresets>,
+    identity: &ClientIdentity,
+    payload: &mut MessagesRequest,
+) -> Option<Response> {
+    fill_missing(&identity.defaults, payload);
+    if let Some(name) = &identity.preset {
+        match presets.and_then(|presets| presets.get(name)) {
+            Some(preset) => apply_preset(preset, payload),
+            None => tracing::warn!(
+                "客户端 {} 的请求预设 {} 不存在，已忽略",
+                identity.name,
+                name
+            ),
+        }
+    }
+
+    if payload.max_tokens == 0 {
+        return Some(
+            (
+                StatusCode::BAD_REQUEST,
+                Json(ErrorResponse::new(
+                    "invalid_request_error",
+                    "max_tokens: Field required",
+                )),
+            )
+                .into_response(),
+        );
+    }
+    None
+}
+
+/// 应用请求预设：补齐仍缺失的参数、追加系统提示词、按上限下调 max_tokens
+fn apply_preset(preset: &RequestPreset, payload: &mut MessagesRequest) {
+    fill_missing(&preset.defaults, payload);
+    if let Some(text) = &preset.system_append {
+        let message = SystemMessage { text: text.clone() };
+        match &mut payload.system {
+            Some(system) if !system.is_empty() => system.push(message),
+            _ => payload.system = Some(vec![message]),
+        }
+    }
+    if let Some(max_tokens) = preset.max_tokens
+        && payload.max_tokens > max_tokens
+    {
+        payload.max_tokens = max_tokens;
+    }
+}
+
+/// 用默认生成参数补齐请求中缺失的字段
+fn fill_missing(defaults: &RequestDefaults, payload: &mut MessagesRequest) {
+    if payload.max_tokens == 0
+        && let Some(max_tokens) = defaults.max_tokens
+    {
+        payload.max_tokens = max_tokens;
+    }
+    if payload.temperature.is_none() {
+        payload.temperature = defaults.temperature;
+    }
+    if payload.system.as_ref().is_none_or(|s| s.is_empty())
+        && let Some(system) = &defaults.system
+    {
+        payload.system = Some(vec![SystemMessage {
+            text: system.clone(),
+        }]);
+    }
+    if payload.stop_sequences.is_none() && !defaults.stop_sequences.is_empty() {
+        payload.stop_sequences = Some(defaults.stop_sequences.clone());
+    }
+}
+
+/// 检查客户端密钥的使用范围（模型、速率、预算），并按上限下调 max_tokens
+fn check_scopes(
+    state: &AppState,
+    identity: &ClientIdentity,
+    payload: &mut MessagesRequest,
+) -> Option<Response> {
+    let scopes = &identity.scopes;
+    let denied = |status: StatusCode, error_type: &str, message: String| {
+        scope_denied(identity, status, error_type, message)
+    };
+
+    if let Some(response) = check_model_scope(identity, &payload.model) {
+        return Some(response);
+    }
+
+    if let Some(budget) = scopes.token_budget
+        && let Some(usage) = &state.usage
+        && usage.tokens_used(&identity.name) >= budget
+    {
+        return denied(
+            StatusCode::FORBIDDEN,
+            "permission_error",
+            format!("API Key 已用完 {} tokens 的预算", budget),
+        );
+    }
+
+    if let Some(per_minute) = scopes.requests_per_minute
+        && !state.client_keys.check_rate(&identity.name, per_minute)
+    {
+        return denied(
+            StatusCode::TOO_MANY_REQUESTS,
+            "rate_limit_error",
+            format!("API Key 超过每分钟 {} 次请求的限制", per_minute),
+        );
+    }
+
+    if let Some(max_tokens) = scopes.max_tokens
+        && payload.max_tokens > max_tokens
+    {
+        tracing::debug!(
+            "客户端 {} 的 max_tokens {} 超过上限，已下调为 {}",
+            identity.name,
+            payload.max_tokens,
+            max_tokens
+        );
+        payload.max_tokens = max_tokens;
+    }
+
+    None
+}
+
+/// 使用范围拒绝响应
+fn scope_denied(
+    identity: &ClientIdentity,
+    status: StatusCode,
+    error_type: &str,
+    message: String,
+) -> Option<Response> {
+    tracing::info!("拒绝客户端 {} 的请求: {}", identity.name, message);
+    Some((status, Json(ErrorResponse::new(error_type, message))).into_response())
+}
+
+/// 检查客户端密钥是否允许使用该模型（`scopes.models` 以 `*` 结尾时按前缀匹配）
+fn check_model_scope(identity: &ClientIdentity, model: &str) -> Option<Response> {
+    let models = &identity.scopes.models;
+    if models.is_empty()
+        || models
+            .iter()
+            .any(|pattern| match pattern.strip_suffix('*') {
+                Some(prefix) => model.starts_with(prefix),
+                None => pattern == model,
+            })
+    {
+        return None;
+    }
+    scope_denied(
+        identity,
+        StatusCode::FORBIDDEN,
+        "permission_error",
+        format!("API Key 无权使用模型 {}", model),
+    )
+}
+
+/// 摘要式上下文压缩：对话过长时以摘要替换较早的轮次，生成摘要失败时原样转发
+///
+/// 生成摘要的上游调用计入客户端用量（`endpoint` 为所属请求的路由模板）
+async fn apply_context_compression(
+    state: &AppState,
+    provider: &KiroProvider,
+    identity: &ClientIdentity,
+    endpoint: &'static str,
+    payload: &mut MessagesRequest,
+    options: &CallOptions,
+) {
+    let Some(compressor) = state.context_compressor.as_ref() else {
+        return;
+    };
+    let tokens = token::count_all_tokens(
+        payload.model.clone(),
+        payload.system.clone(),
+        payload.messages.clone(),
+        payload.tools.clone(),
+    );
+    let Some(plan) = compressor.plan(payload, tokens) else {
+        return;
+    };
+
+    let summary = match plan.cached_summary() {
+        Some(summary) => summary.to_string(),
+        None => {
+            let request = compressor.summary_request(payload, &plan);
+            let input_tokens = token::count_all_tokens(
+                request.model.clone(),
+                request.system.clone(),
+                request.messages.clone(),
+                request.tools.clone(),
+            ) as i32;
+            let mut usage = state.usage.as_ref().map(|tracker| {
+                tracker
+                    .start(identity.name.clone(), input_tokens)
+                    .with_model(&request.model)
+                    .with_endpoint(endpoint)
+            });
+            let summary = match build_request_body(&request, state, options) {
+                Ok(body) => call_and_aggregate(provider, None, &body, false, None, options).await,
+                Err(response) => Err(*response),
+            };
+            let summary = match summary {
+                Ok(aggregated) => {
+                    let output_tokens = token::estimate_output_tokens(&[
+                        json!({"type": "text", "text": aggregated.text}),
+                    ]);
+                    let input_tokens = aggregated.context_input_tokens.unwrap_or(input_tokens);
+                    served_by(&mut usage, aggregated.credential_id);
+                    complete_usage(&mut usage, (input_tokens, output_tokens));
+                    Ok(aggregated.text)
+                }
+                Err(response) => {
+                    discard_usage(usage);
+                    Err(response)
+                }
+            };
+            match summary {
+                Ok(summary) if !summary.trim().is_empty() => {
+                    compressor.store(payload, &plan, &summary);
+                    summary
+                }
+                Ok(_) => {
+                    tracing::warn!("生成对话摘要失败: 摘要为空，不压缩上下文");
+                    return;
+                }
+                Err(response) => {
+                    tracing::warn!("生成对话摘要失败: {}，不压缩上下文", response.status());
+                    return;
+                }
+            }
+        }
+    };
+
+    tracing::info!("对话约 {} tokens，已将较早的轮次压缩为摘要", tokens);
+    ContextCompressor::apply(payload, &plan, &summary);
+}
+
+/// 上下文长度路由：提示词超出模型上下文窗口时改用回退模型，无法容纳时返回 400
+fn apply_context_routing(state: &AppState, payload: &mut MessagesRequest) -> Option<Response> {
+    let router = state.context_router.as_ref()?;
+    let tokens = token::count_all_tokens(
+        payload.model.clone(),
+        payload.system.clone(),
+        payload.messages.clone(),
+        payload.tools.clone(),
+    );
+    match router.route(&payload.model, tokens) {
+        ContextRoute::Fits => None,
+        ContextRoute::Reroute { model } => {
+            tracing::info!(
+                "提示词约 {} tokens，超出 {} 的上下文窗口，改用 {}",
+                tokens,
+                payload.model,
+                model
+            );
+            payload.model = model;
+            None
+        }
+        ContextRoute::Overflow {
+            context_window,
+            over_by,
+        } => {
+            tracing::warn!(
+                "提示词约 {} tokens，超出 {} 的上下文窗口 {} tokens",
+                tokens,
+                payload.model,
+                over_by
+            );
+            Some(
+                (
+                    StatusCode::BAD_REQUEST,
+                    Json(ErrorResponse::new(
+                        "invalid_request_error",
+                        format!(
+                            "prompt is too long: {} tokens > {} maximum (over by {} tokens)",
+                            tokens, context_window, over_by
+                        ),
+                    )),
+                )
+                    .into_response(),
+            )
+        }
+    }
+}
+
+/// 客户端标识：优先使用 metadata.user_id（Claude Code 会话），否则使用客户端密钥名称
+fn client_identity(identity: &ClientIdentity, payload: &MessagesRequest) -> String {
+    payload
+        .metadata
+        .as_ref()
+        .and_then(|m| m.user_id.clone())
+        .filter(|id| !id.is_empty())
+        .unwrap_or_else(|| identity.name.clone())
+}
+
+/// 请求内容指纹（模型 + system + messages）
+fn request_fingerprint(payload: &MessagesRequest) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(payload.model.as_bytes());
+    for system in payload.system.iter().flatten() {
+        hasher.update(system.text.as_bytes());
+    }
+    hasher.update(serde_json::to_vec(&payload.messages).unwrap_or_default());
+    hex::encode(hasher.finalize())
+}
+
+/// 统计对话末尾连续的工具调用轮次（只含 tool_result 的 user 消息数）
+fn count_trailing_tool_rounds(messages: &[Message]) -> usize {
+    let mut rounds = 0;
+    for message in messages.iter().rev().filter(|m| m.role == "user") {
+        let only_tool_results = message.content.as_array().is_some_and(|blocks| {
+            !blocks.is_empty()
+                && blocks
+                    .iter()
+                    .all(|b| b.get("type").and_then(|t| t.as_str()) == Some("tool_result"))
+        });
+        if !only_tool_results {
+            break;
+        }
+        rounds += 1;
+    }
+    rounds
+}
+
+/// 转换 Anthropic 请求并序列化为 Kiro 请求体
+fn build_request_body(
+    payload: &MessagesRequest,
+    state: &AppState,
+    options: &CallOptions,
+) -> Result<String, Box<Response>> {
+    let conversion_result = match convert_request(payload) {
+        Ok(result) => result,
+        Err(e) => {
+            let (error_type, message) = match &e {
+                ConversionError::UnsupportedModel(model) => {
+                    ("invalid_request_error", format!("模型不支持: {}", model))
+                }
+                ConversionError::EmptyMessages => {
+                    ("invalid_request_error", "消息列表为空".to_string())
+                }
+            };
+            tracing::warn!("请求转换失败: {}", e);
+            return Err(Box::new(
+                (
+                    StatusCode::BAD_REQUEST,
+                    Json(ErrorResponse::new(error_type, message)),
+                )
+                    .into_response(),
+            ));
+        }
+    };
+
+    // 构建 Kiro 请求
+    let mut kiro_request = KiroRequest::new(conversion_result.conversation_state);
+    if let Some(arn) = &state.profile_arn {
+        kiro_request = kiro_request.with_profile_arn(arn);
+    }
+
+    let request_body = match kiro_request.to_json() {
+        Ok(body) => body,
+        Err(e) => {
+            tracing::error!("序列化请求失败: {}", e);
+            return Err(Box::new(
+                (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    Json(ErrorResponse::new(
+                        "internal_error",
+                        format!("序列化请求失败: {}", e),
+                    )),
+                )
+                    .into_response(),
+            ));
+        }
+    };
+
+    let limit = state
+        .kiro_provider
+        .as_ref()
+        .map(|provider| provider.log_limit(options))
+        .unwrap_or_default();
+    tracing::debug!("Kiro request body: {}", clip(limit, &request_body));
+    Ok(request_body)
+}
+
 /// POST /v1/messages
 ///
 /// 创建消息（对话）
 pub async fn post_messages(
     State(state): State<AppState>,
+    Extension(identity): Extension<ClientIdentity>,
+    headers: HeaderMap,
     JsonExtractor(payload): JsonExtractor<MessagesRequest>,
 ) -> Response {
     tracing::info!(
@@ -93,6 +837,150 @@ pub async fn post_messages(
         message_count = %payload.messages.len(),
         "Received POST /v1/messages request"
     );
+    handle_messages(
+        state,
+        identity,
+        headers,
+        payload,
+        MessagesEndpoint::Messages,
+    )
+    .await
+}
+
+/// POST /v1/responses
+///
+/// OpenAI Responses API 兼容端点：转换为 Messages 请求后按相同流程处理
+pub async fn post_responses(
+    State(state): State<AppState>,
+    Extension(identity): Extension<ClientIdentity>,
+    headers: HeaderMap,
+    JsonExtractor(request): JsonExtractor<ResponsesRequest>,
+) -> Response {
+    tracing::info!(
+        model = %request.model,
+        stream = %request.stream,
+        "Received POST /v1/responses request"
+    );
+    if request.previous_response_id.is_some() {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(ErrorResponse::new(
+                "invalid_request_error",
+                "不支持 previous_response_id，请在 input 中传入完整对话",
+            )),
+        )
+            .into_response();
+    }
+    let mut payload = match request.to_canonical() {
+        Ok(canonical) => MessagesRequest::from_canonical(&canonical),
+        Err(e) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(ErrorResponse::new("invalid_request_error", e.to_string())),
+            )
+                .into_response();
+        }
+    };
+    payload.temperature = request.temperature;
+    handle_messages(
+        state,
+        identity,
+        headers,
+        payload,
+        MessagesEndpoint::Responses,
+    )
+    .await
+}
+
+/// POST /openai/deployments/{deployment}/chat/completions
+///
+/// Azure OpenAI 风格的 Chat Completions 端点：模型由部署名经 `azureDeployments` 映射决定
+pub async fn post_azure_chat_completions(
+    State(state): State<AppState>,
+    Extension(identity): Extension<ClientIdentity>,
+    Path(deployment): Path<String>,
+    Query(query): Query<AzureQuery>,
+    headers: HeaderMap,
+    JsonExtractor(mut request): JsonExtractor<ChatCompletionRequest>,
+) -> Response {
+    let model = state
+        .azure_deployments
+        .get(&deployment)
+        .cloned()
+        .unwrap_or_else(|| deployment.clone());
+    tracing::info!(
+        deployment = %deployment,
+        model = %model,
+        api_version = ?query.api_version,
+        stream = %request.stream,
+        "Received POST /openai/deployments/{{deployment}}/chat/completions request"
+    );
+    request.model = Some(model);
+    let mut payload = match request.to_canonical() {
+        Ok(canonical) => MessagesRequest::from_canonical(&canonical),
+        Err(e) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(ErrorResponse::new("invalid_request_error", e.to_string())),
+            )
+                .into_response();
+        }
+    };
+    payload.temperature = request.temperature;
+    handle_messages(
+        state,
+        identity,
+        headers,
+        payload,
+        MessagesEndpoint::AzureChat,
+    )
+    .await
+}
+
+/// 进入 Messages 处理流程的端点
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum MessagesEndpoint {
+    /// POST /v1/messages
+    Messages,
+    /// POST /cc/v1/messages（流式响应缓冲到 contextUsageEvent 后再输出）
+    ClaudeCode,
+    /// POST /v1/responses
+    Responses,
+    /// POST /openai/deployments/{deployment}/chat/completions
+    AzureChat,
+}
+
+impl MessagesEndpoint {
+    /// 路由模板（用作指标标签）
+    fn route(self) -> &'static str {
+        match self {
+            Self::Messages => "/v1/messages",
+            Self::ClaudeCode => "/cc/v1/messages",
+            Self::Responses => "/v1/responses",
+            Self::AzureChat => "/openai/deployments/{deployment}/chat/completions",
+        }
+    }
+
+    /// 响应的输出格式
+    fn format(self) -> RenderFormat {
+        match self {
+            Self::Messages | Self::ClaudeCode => RenderFormat::Anthropic,
+            Self::Responses => RenderFormat::OpenAiResponses,
+            Self::AzureChat => RenderFormat::OpenAi,
+        }
+    }
+}
+
+/// Messages 处理流程（各兼容端点共用），`endpoint` 决定响应的输出格式与流式缓冲方式
+async fn handle_messages(
+    state: AppState,
+    identity: ClientIdentity,
+    headers: HeaderMap,
+    mut payload: MessagesRequest,
+    endpoint: MessagesEndpoint,
+) -> Response {
+    let format = endpoint.format();
+
     // 检查 KiroProvider 是否可用
     let provider = match &state.kiro_provider {
         Some(p) => p.clone(),
@@ -109,8 +997,63 @@ pub async fn post_messages(
         }
     };
 
-    // 检查是否为 WebSearch 请求
-    if websearch::has_web_search_tool(&payload) {
+    // 降级模式：没有可用凭据时不调用上游
+    if let Some(response) = no_credentials_response(&provider) {
+        return response;
+    }
+
+    // 解析请求级调用选项（如 region 覆盖）
+    let options = match call_options_from_headers(&headers) {
+        Ok(options) => options.with_log_content(identity.log_content),
+        Err(response) => return *response,
+    };
+
+    // 客户端密钥默认参数
+    if let Some(response) =
+        apply_request_defaults(state.presets.as_deref(), &identity, &mut payload)
+    {
+        return response;
+    }
+
+    // 请求过滤器
+    if let Some(response) = apply_request_filters(&state, &mut payload) {
+        return response;
+    }
+
+    // 客户端密钥使用范围
+    if let Some(response) = check_scopes(&state, &identity, &mut payload) {
+        return response;
+    }
+
+    // 滥用检测
+    if let Some(response) = check_abuse(&state, &identity, &payload) {
+        return response;
+    }
+
+    // 摘要式上下文压缩（在范围与滥用检查之后，被拒绝的请求不会调用上游生成摘要）
+    apply_context_compression(
+        &state,
+        &provider,
+        &identity,
+        endpoint.route(),
+        &mut payload,
+        &options,
+    )
+    .await;
+
+    // 上下文长度路由（回退模型同样受模型范围限制）
+    let requested_model = payload.model.clone();
+    if let Some(response) = apply_context_routing(&state, &mut payload) {
+        return response;
+    }
+    if payload.model != requested_model
+        && let Some(response) = check_model_scope(&identity, &payload.model)
+    {
+        return response;
+    }
+
+    // 检查是否为 WebSearch 请求（WebSearch 与本地工具循环只输出 Anthropic 格式）
+    if format == RenderFormat::Anthropic && websearch::has_web_search_tool(&payload) {
         tracing::info!("检测到 WebSearch 工具，路由到 WebSearch 处理");
 
         // 估算输入 tokens
@@ -121,53 +1064,52 @@ pub async fn post_messages(
             payload.tools.clone(),
         ) as i32;
 
-        return websearch::handle_websearch_request(provider, &payload, input_tokens).await;
+        return websearch::handle_websearch_request(provider, &payload, input_tokens, &options)
+            .await;
     }
 
-    // 转换请求
-    let conversion_result = match convert_request(&payload) {
-        Ok(result) => result,
-        Err(e) => {
-            let (error_type, message) = match &e {
-                ConversionError::UnsupportedModel(model) => {
-                    ("invalid_request_error", format!("模型不支持: {}", model))
-                }
-                ConversionError::EmptyMessages => {
-                    ("invalid_request_error", "消息列表为空".to_string())
-                }
-            };
-            tracing::warn!("请求转换失败: {}", e);
-            return (
-                StatusCode::BAD_REQUEST,
-                Json(ErrorResponse::new(error_type, message)),
-            )
-                .into_response();
+    // 本地工具 agent 循环（仅非流式请求，且客户端被授予本地工具权限）
+    if format == RenderFormat::Anthropic
+        && let Some(runner) = local_tool_runner_for(&state, &identity, &payload)
+    {
+        let input_tokens = token::count_all_tokens(
+            payload.model.clone(),
+            payload.system.clone(),
+            payload.messages.clone(),
+            payload.tools.clone(),
+        ) as i32;
+        return handle_local_tool_loop(
+            provider,
+            &state,
+            runner,
+            &identity.local_tools,
+            payload,
+            input_tokens,
+            &options,
+        )
+        .await;
+    }
+
+    // 响应缓存（仅非流式请求）
+    let mut cache_slot = None;
+    if !payload.stream
+        && let Some(cache) = &state.response_cache
+        && let Some(mut query) = cache.query(&identity.name, identity.response_cache, &payload)
+    {
+        if let Some(hit) = cache.lookup(&mut query).await {
+            return cached_response(&identity, format, hit);
         }
-    };
+        cache_slot = Some((cache.clone(), query));
+    }
 
-    // 构建 Kiro 请求
-    let kiro_request = KiroRequest {
-        conversation_state: conversion_result.conversation_state,
-        profile_arn: state.profile_arn.clone(),
-    };
+    let coalesce = coalesce_slot(&state, &identity, &payload);
 
-    let request_body = match serde_json::to_string(&kiro_request) {
+    // 转换请求并构建 Kiro 请求体
+    let request_body = match build_request_body(&payload, &state, &options) {
         Ok(body) => body,
-        Err(e) => {
-            tracing::error!("序列化请求失败: {}", e);
-            return (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(ErrorResponse::new(
-                    "internal_error",
-                    format!("序列化请求失败: {}", e),
-                )),
-            )
-                .into_response();
-        }
+        Err(response) => return *response,
     };
 
-    tracing::debug!("Kiro request body: {}", request_body);
-
     // 估算输入 tokens
     let input_tokens = token::count_all_tokens(
         payload.model.clone(),
@@ -183,45 +1125,137 @@ pub async fn post_messages(
         .map(|t| t.thinking_type == "enabled")
         .unwrap_or(false);
 
-    if payload.stream {
-        // 流式响应
-        handle_stream_request(
-            provider,
-            &request_body,
+    let completion = Completion {
+        format,
+        cache: cache_slot,
+        coalesce,
+        ..start_completion(
+            &state,
+            &identity,
+            endpoint.route(),
             &payload.model,
             input_tokens,
-            thinking_enabled,
         )
-        .await
-    } else {
-        // 非流式响应
-        handle_non_stream_request(provider, &request_body, &payload.model, input_tokens).await
+    };
+
+    // 禁止增量流式或 Claude Code 端点：缓冲全部事件后一次性返回
+    let buffered =
+        identity.stream_policy == StreamPolicy::Forbid || endpoint == MessagesEndpoint::ClaudeCode;
+    match (payload.stream, identity.stream_policy) {
+        (true, _) if buffered => {
+            handle_stream_request_buffered(
+                provider,
+                state.artifacts.clone(),
+                &request_body,
+                &payload.model,
+                input_tokens,
+                thinking_enabled,
+                completion,
+                StreamRenderer::new(format),
+                &options,
+            )
+            .await
+        }
+        (true, _) => {
+            // 流式响应
+            handle_stream_request(
+                provider,
+                state.artifacts.clone(),
+                &request_body,
+                &payload.model,
+                input_tokens,
+                thinking_enabled,
+                completion,
+                StreamRenderer::new(format),
+                &options,
+            )
+            .await
+        }
+        (false, policy) => {
+            // 非流式响应（Force 策略下上游走流式路径）
+            handle_non_stream_request(
+                provider,
+                state.artifacts.clone(),
+                &request_body,
+                &payload.model,
+                input_tokens,
+                policy == StreamPolicy::Force,
+                completion,
+                &options,
+            )
+            .await
+        }
+    }
+}
+
+/// 流式请求的合并键（未启用合并或非流式请求时为 None）
+fn coalesce_slot(
+    state: &AppState,
+    identity: &ClientIdentity,
+    payload: &MessagesRequest,
+) -> Option<(Arc<Coalescer>, CoalesceKey)> {
+    let coalescer = state.coalescer.as_ref().filter(|_| payload.stream)?;
+    Some((coalescer.clone(), Coalescer::key(&identity.name, payload)))
+}
+
+/// 发起流式调用（启用合并时与窗口内的相同请求共用上游流）
+async fn open_upstream_stream(
+    provider: &KiroProvider,
+    request_body: &str,
+    options: &CallOptions,
+    coalesce: Option<(Arc<Coalescer>, CoalesceKey)>,
+) -> anyhow::Result<(UpstreamBody, Option<u64>, HeaderMap)> {
+    match coalesce {
+        Some((coalescer, key)) => {
+            coalescer
+                .open(key, || stall::open_stream(provider, request_body, options))
+                .await
+        }
+        None => stall::open_stream(provider, request_body, options).await,
     }
 }
 
 /// 处理流式请求
+#[allow(clippy::too_many_arguments)]
 async fn handle_stream_request(
     provider: std::sync::Arc<crate::kiro::provider::KiroProvider>,
+    artifacts: Option<Arc<ArtifactStore>>,
     request_body: &str,
     model: &str,
     input_tokens: i32,
     thinking_enabled: bool,
+    mut completion: Completion,
+    renderer: StreamRenderer,
+    options: &CallOptions,
 ) -> Response {
-    // 调用 Kiro API（支持多凭据故障转移）
-    let response = match provider.call_api_stream(request_body).await {
-        Ok(resp) => resp,
-        Err(e) => {
-            tracing::error!("Kiro API 调用失败: {}", e);
-            return (
-                StatusCode::BAD_GATEWAY,
-                Json(ErrorResponse::new(
-                    "api_error",
-                    format!("上游 API 调用失败: {}", e),
-                )),
-            )
-                .into_response();
-        }
-    };
+    // 调用 Kiro API（支持多凭据故障转移与卡顿重试）
+    let (body_stream, upstream_headers) =
+        match open_upstream_stream(&provider, request_body, options, completion.coalesce.take())
+            .await
+        {
+            Ok((body, credential_id, headers)) => {
+                served_by(&mut completion.usage, credential_id);
+                (body, headers)
+            }
+            Err(e) => {
+                tracing::error!(
+                    "Kiro API 调用失败: {}",
+                    log_error(provider.log_limit(options), &e)
+                );
+                discard_usage(completion.usage);
+                return (
+                    StatusCode::BAD_GATEWAY,
+                    Json(ErrorResponse::new(
+                        "api_error",
+                        format!("上游 API 调用失败: {}", e),
+                    )),
+                )
+                    .into_response();
+            }
+        };
+
+    let cost = completion.cost.take();
+    let forwarder = completion.forward_headers.take();
 
     // 创建流处理上下文
     let mut ctx = StreamContext::new_with_thinking(model, input_tokens, thinking_enabled);
@@ -230,45 +1264,188 @@ async fn handle_stream_request(
     let initial_events = ctx.generate_initial_events();
 
     // 创建 SSE 流
-    let stream = create_sse_stream(response, ctx, initial_events);
+    let content_type = renderer.content_type();
+    let stream = create_sse_stream(
+        body_stream,
+        ctx,
+        initial_events,
+        artifacts,
+        completion,
+        renderer,
+    );
 
     // 返回 SSE 响应
-    Response::builder()
+    let mut response = Response::builder()
         .status(StatusCode::OK)
-        .header(header::CONTENT_TYPE, "text/event-stream")
+        .header(header::CONTENT_TYPE, content_type)
         .header(header::CACHE_CONTROL, "no-cache")
         .header(header::CONNECTION, "keep-alive")
         .body(Body::from_stream(stream))
-        .unwrap()
+        .unwrap();
+    if let Some(cost) = cost {
+        cost.apply_headers(response.headers_mut(), input_tokens, None);
+    }
+    if let Some(forwarder) = forwarder {
+        forwarder.apply(&upstream_headers, response.headers_mut());
+    }
+    response
 }
 
 /// Ping 事件间隔（25秒）
 const PING_INTERVAL_SECS: u64 = 25;
 
-/// 创建 ping 事件的 SSE 字符串
-fn create_ping_sse() -> Bytes {
-    Bytes::from("event: ping\ndata: {\"type\": \"ping\"}\n\n")
+/// 响应正常完成时的附加处理
+struct Completion {
+    /// 用量记录（未启用用量统计时为 None）
+    usage: Option<UsageRecorder>,
+    /// 追加到响应末尾的水印（未启用时为 None）
+    watermark: Option<String>,
+    /// 响应过滤器（仅非流式响应）
+    filters: Arc<FilterChain>,
+    /// 非流式请求的最长时长（未限制时为 None）
+    max_duration: Option<Duration>,
+    /// 流式请求的截止时间（未限制时为 None）
+    stream_deadline: Option<Instant>,
+    /// 成本估算（未启用时为 None）
+    cost: Option<CostRate>,
+    /// 非流式响应的输出格式
+    format: RenderFormat,
+    /// 未命中的响应缓存查询，响应正常结束后写入（未启用或不缓存时为 None）
+    cache: Option<(Arc<ResponseCache>, CacheQuery)>,
+    /// 上游响应头透传（未配置时为 None）
+    forward_headers: Option<Arc<HeaderForwarder>>,
+    /// 相同流式请求合并（未启用或非流式请求时为 None）
+    coalesce: Option<(Arc<Coalescer>, CoalesceKey)>,
+}
+
+/// 为请求准备用量记录与水印，`endpoint` 为路由模板（用作指标标签）
+fn start_completion(
+    state: &AppState,
+    identity: &ClientIdentity,
+    endpoint: &'static str,
+    model: &str,
+    input_tokens: i32,
+) -> Completion {
+    let config = state
+        .kiro_provider
+        .as_ref()
+        .map(|provider| provider.token_manager().config());
+    let watermark =
+        config.and_then(|config| watermark::render(&config.watermark, &identity.name, model));
+    let max_duration_secs = identity
+        .max_duration_secs
+        .or(config.map(|config| config.non_stream.max_duration_secs))
+        .unwrap_or(0);
+    let stream_max_duration_secs = identity
+        .stream_max_duration_secs
+        .or(config.map(|config| config.streaming.max_duration_secs))
+        .unwrap_or(0);
+    Completion {
+        usage: state.usage.as_ref().map(|tracker| {
+            tracker
+                .start(identity.name.clone(), input_tokens)
+                .with_model(model)
+                .with_endpoint(endpoint)
+        }),
+        watermark,
+        filters: state.filters.clone(),
+        max_duration: (max_duration_secs > 0).then(|| Duration::from_secs(max_duration_secs)),
+        stream_deadline: (stream_max_duration_secs > 0)
+            .then(|| Instant::now() + Duration::from_secs(stream_max_duration_secs)),
+        cost: state.cost_table.as_ref().map(|table| table.rate(model)),
+        format: RenderFormat::Anthropic,
+        cache: None,
+        forward_headers: state.header_forwarder.clone(),
+        coalesce: None,
+    }
+}
+
+/// 返回缓存命中的响应（`x-kiro-cache: hit`，语义命中为 `semantic`）
+fn cached_response(identity: &ClientIdentity, format: RenderFormat, hit: CacheHit) -> Response {
+    let kind = match hit.similarity {
+        Some(similarity) => {
+            tracing::info!(
+                "客户端 {} 语义缓存命中（相似度 {:.3}）",
+                identity.name,
+                similarity
+            );
+            "semantic"
+        }
+        None => {
+            tracing::info!("客户端 {} 响应缓存命中", identity.name);
+            "hit"
+        }
+    };
+    let mut response = (StatusCode::OK, Json(render_message(format, hit.response))).into_response();
+    response
+        .headers_mut()
+        .insert(CACHE_HEADER, HeaderValue::from_static(kind));
+    response
+}
+
+/// 按输出格式转换 Anthropic 消息响应
+fn render_message(format: RenderFormat, message: serde_json::Value) -> serde_json::Value {
+    match format {
+        RenderFormat::OpenAi => chat_completions::from_message(&message),
+        RenderFormat::OpenAiResponses => responses::from_message(&message),
+        _ => message,
+    }
+}
+
+/// 更新目前为止的用量（客户端断开时按此记录）
+fn update_usage(usage: &mut Option<UsageRecorder>, (input_tokens, output_tokens): (i32, i32)) {
+    if let Some(usage) = usage {
+        usage.set_tokens(input_tokens, output_tokens);
+    }
+}
+
+/// 记录提供响应的凭据
+fn served_by(usage: &mut Option<UsageRecorder>, credential_id: Option<u64>) {
+    if let Some(usage) = usage {
+        usage.set_credential(credential_id);
+    }
+}
+
+/// 等待到流式请求的截止时间（未限制时一直等待）
+async fn until_deadline(deadline: Option<Instant>) {
+    match deadline {
+        Some(deadline) => tokio::time::sleep_until(deadline.into()).await,
+        None => std::future::pending().await,
+    }
+}
+
+/// 上游结束（或被代理中止）时记为完成
+fn complete_usage(usage: &mut Option<UsageRecorder>, tokens: (i32, i32)) {
+    update_usage(usage, tokens);
+    if let Some(mut usage) = usage.take() {
+        usage.upstream_finished();
+        usage.complete();
+    }
+}
+
+/// 上游调用失败，不计入用量
+fn discard_usage(usage: Option<UsageRecorder>) {
+    if let Some(usage) = usage {
+        usage.discard();
+    }
 }
 
 /// 创建 SSE 事件流
 fn create_sse_stream(
-    response: reqwest::Response,
+    body_stream: UpstreamBody,
     ctx: StreamContext,
     initial_events: Vec<SseEvent>,
+    artifacts: Option<Arc<ArtifactStore>>,
+    completion: Completion,
+    mut renderer: StreamRenderer,
 ) -> impl Stream<Item = Result<Bytes, Infallible>> {
     // 先发送初始事件
-    let initial_stream = stream::iter(
-        initial_events
-            .into_iter()
-            .map(|e| Ok(Bytes::from(e.to_sse_string()))),
-    );
+    let initial_stream = stream::iter(renderer.render_all(initial_events));
 
     // 然后处理 Kiro 响应流，同时每25秒发送 ping 保活
-    let body_stream = response.bytes_stream();
-
     let processing_stream = stream::unfold(
-        (body_stream, ctx, EventStreamDecoder::new(), false, interval(Duration::from_secs(PING_INTERVAL_SECS))),
-        |(mut body_stream, mut ctx, mut decoder, finished, mut ping_interval)| async move {
+        (body_stream, ctx, EventStreamDecoder::new(), false, interval(Duration::from_secs(PING_INTERVAL_SECS)), artifacts, completion, renderer),
+        |(mut body_stream, mut ctx, mut decoder, finished, mut ping_interval, artifacts, mut completion, mut renderer)| async move {
             if finished {
                 return None;
             }
@@ -289,6 +1466,12 @@ fn create_sse_stream(
                                 match result {
                                     Ok(frame) => {
                                         if let Ok(event) = Event::from_frame(frame) {
+                                            if let Event::Artifact(artifact) = &event {
+                                                if let Some(text) = artifacts::surface(artifacts.as_deref(), artifact).await {
+                                                    events.extend(ctx.process_artifact_text(&text));
+                                                }
+                                                continue;
+                                            }
                                             let sse_events = ctx.process_kiro_event(&event);
                                             events.extend(sse_events);
                                         }
@@ -299,40 +1482,61 @@ fn create_sse_stream(
                                 }
                             }
 
+                            update_usage(&mut completion.usage, ctx.tokens());
+
                             // 转换为 SSE 字节流
-                            let bytes: Vec<Result<Bytes, Infallible>> = events
-                                .into_iter()
-                                .map(|e| Ok(Bytes::from(e.to_sse_string())))
-                                .collect();
+                            let bytes: Vec<Result<Bytes, Infallible>> = renderer.render_all(events);
 
-                            Some((stream::iter(bytes), (body_stream, ctx, decoder, false, ping_interval)))
+                            Some((stream::iter(bytes), (body_stream, ctx, decoder, false, ping_interval, artifacts, completion, renderer)))
+                        }
+                        Some(Err(ReadError::Stalled(timeout))) => {
+                            // 卡顿：以 error 事件中止
+                            complete_usage(&mut completion.usage, ctx.tokens());
+                            let bytes: Vec<Result<Bytes, Infallible>> = renderer.render_all(vec![stall::stalled_event(timeout)]);
+                            Some((stream::iter(bytes), (body_stream, ctx, decoder, true, ping_interval, artifacts, completion, renderer)))
+                        }
+                        Some(Err(ReadError::Corrupted(_))) => {
+                            // 上游数据损坏：以 error 事件中止，不发送损坏的内容
+                            complete_usage(&mut completion.usage, ctx.tokens());
+                            let bytes: Vec<Result<Bytes, Infallible>> = renderer.render_all(vec![stall::corrupted_event()]);
+                            Some((stream::iter(bytes), (body_stream, ctx, decoder, true, ping_interval, artifacts, completion, renderer)))
                         }
                         Some(Err(e)) => {
                             tracing::error!("读取响应流失败: {}", e);
                             // 发送最终事件并结束
                             let final_events = ctx.generate_final_events();
-                            let bytes: Vec<Result<Bytes, Infallible>> = final_events
-                                .into_iter()
-                                .map(|e| Ok(Bytes::from(e.to_sse_string())))
-                                .collect();
-                            Some((stream::iter(bytes), (body_stream, ctx, decoder, true, ping_interval)))
+                            complete_usage(&mut completion.usage, ctx.tokens());
+                            let bytes: Vec<Result<Bytes, Infallible>> = renderer.render_all(final_events);
+                            Some((stream::iter(bytes), (body_stream, ctx, decoder, true, ping_interval, artifacts, completion, renderer)))
                         }
                         None => {
-                            // 流结束，发送最终事件
-                            let final_events = ctx.generate_final_events();
-                            let bytes: Vec<Result<Bytes, Infallible>> = final_events
-                                .into_iter()
-                                .map(|e| Ok(Bytes::from(e.to_sse_string())))
-                                .collect();
-                            Some((stream::iter(bytes), (body_stream, ctx, decoder, true, ping_interval)))
+                            // 流结束，追加水印后发送最终事件
+                            let mut final_events = completion
+                                .watermark
+                                .take()
+                                .map(|mark| ctx.process_artifact_text(&mark))
+                                .unwrap_or_default();
+                            final_events.extend(ctx.generate_final_events());
+                            complete_usage(&mut completion.usage, ctx.tokens());
+                            let bytes: Vec<Result<Bytes, Infallible>> = renderer.render_all(final_events);
+                            Some((stream::iter(bytes), (body_stream, ctx, decoder, true, ping_interval, artifacts, completion, renderer)))
                         }
                     }
                 }
+                // 超过最长时长：以 timeout 结束，结束后 drop 上游流即中止上游
+                _ = until_deadline(completion.stream_deadline) => {
+                    tracing::warn!("流式请求超过最长时长，以 timeout 结束并中止上游");
+                    ctx.set_stop_reason(StopReason::Timeout);
+                    let final_events = ctx.generate_final_events();
+                    complete_usage(&mut completion.usage, ctx.tokens());
+                    let bytes: Vec<Result<Bytes, Infallible>> = renderer.render_all(final_events);
+                    Some((stream::iter(bytes), (body_stream, ctx, decoder, true, ping_interval, artifacts, completion, renderer)))
+                }
                 // 发送 ping 保活
                 _ = ping_interval.tick() => {
                     tracing::trace!("发送 ping 保活事件");
-                    let bytes: Vec<Result<Bytes, Infallible>> = vec![Ok(create_ping_sse())];
-                    Some((stream::iter(bytes), (body_stream, ctx, decoder, false, ping_interval)))
+                    let bytes: Vec<Result<Bytes, Infallible>> = renderer.ping().map(Ok).into_iter().collect();
+                    Some((stream::iter(bytes), (body_stream, ctx, decoder, false, ping_interval, artifacts, completion, renderer)))
                 }
             }
         },
@@ -346,54 +1550,297 @@ fn create_sse_stream(
 const CONTEXT_WINDOW_SIZE: i32 = 200_000;
 
 /// 处理非流式请求
+///
+/// `upstream_stream` 为 true 时强制上游走流式调用（否则按 `nonStream.upstreamStream` 配置），
+/// 聚合后仍返回完整 JSON 响应
+#[allow(clippy::too_many_arguments)]
 async fn handle_non_stream_request(
     provider: std::sync::Arc<crate::kiro::provider::KiroProvider>,
+    artifacts: Option<Arc<ArtifactStore>>,
     request_body: &str,
     model: &str,
     input_tokens: i32,
+    upstream_stream: bool,
+    mut completion: Completion,
+    options: &CallOptions,
 ) -> Response {
-    // 调用 Kiro API（支持多凭据故障转移）
-    let response = match provider.call_api(request_body).await {
+    let mut aggregated = match call_and_aggregate(
+        &provider,
+        artifacts.as_deref(),
+        request_body,
+        upstream_stream,
+        completion.max_duration,
+        options,
+    )
+    .await
+    {
+        Ok(aggregated) => aggregated,
+        Err(response) => {
+            discard_usage(completion.usage);
+            return response;
+        }
+    };
+
+    if let Some(mark) = completion.watermark.take() {
+        aggregated.text.push_str(&mark);
+    }
+
+    let aggregated_credential = aggregated.credential_id;
+    let upstream_headers = std::mem::take(&mut aggregated.upstream_headers);
+    let response_body = build_message_response(model, aggregated, input_tokens, None);
+    let usage_tokens = |key: &str| response_body["usage"][key].as_i64().unwrap_or(0) as i32;
+    let tokens = (usage_tokens("input_tokens"), usage_tokens("output_tokens"));
+    served_by(&mut completion.usage, aggregated_credential);
+    complete_usage(&mut completion.usage, tokens);
+    let response_body = match completion
+        .filters
+        .apply(FilterHook::Response, &response_body)
+    {
+        Ok(filtered) => filtered.unwrap_or(response_body),
+        Err(e) => return filter_error_response(e),
+    };
+    if let Some((cache, query)) = completion.cache.take()
+        && response_body["stop_reason"] == "end_turn"
+    {
+        cache.store(query, response_body.clone());
+    }
+    let response_body = render_message(completion.format, response_body);
+    let mut response = (StatusCode::OK, Json(response_body)).into_response();
+    if let Some(cost) = &completion.cost {
+        cost.apply_headers(response.headers_mut(), tokens.0, Some(tokens.1));
+    }
+    if let Some(forwarder) = &completion.forward_headers {
+        forwarder.apply(&upstream_headers, response.headers_mut());
+    }
+    response
+}
+
+/// 非流式响应的聚合结果
+struct AggregatedResponse {
+    /// 文本内容
+    text: String,
+    /// 完整的工具调用（tool_use 内容块）
+    tool_uses: Vec<serde_json::Value>,
+    /// 停止原因
+    stop_reason: StopReason,
+    /// 从 contextUsageEvent 计算的实际输入 tokens
+    context_input_tokens: Option<i32>,
+    /// 待保存的文件事件
+    artifacts: Vec<ArtifactEvent>,
+    /// 提供响应的凭据
+    credential_id: Option<u64>,
+    /// 上游响应头
+    upstream_headers: HeaderMap,
+}
+
+/// 调用上游并聚合完整响应
+///
+/// 设置 `max_duration` 时上游走流式调用，超时后返回已聚合的部分内容（停止原因为 `timeout`）
+async fn call_and_aggregate(
+    provider: &KiroProvider,
+    artifacts: Option<&ArtifactStore>,
+    request_body: &str,
+    upstream_stream: bool,
+    max_duration: Option<Duration>,
+    options: &CallOptions,
+) -> Result<AggregatedResponse, Response> {
+    let config = &provider.token_manager().config().non_stream;
+    let upstream_stream = upstream_stream || config.upstream_stream || max_duration.is_some();
+    let deadline = max_duration.map(|d| Instant::now() + d);
+    let idle_timeout = (upstream_stream && config.idle_timeout_secs > 0)
+        .then(|| Duration::from_secs(config.idle_timeout_secs));
+
+    // 事件流 CRC 校验失败时重新调用一次
+    let mut retried = false;
+    let (body_bytes, timed_out, credential_id, upstream_headers) = loop {
+        let (response, credential_id, upstream_headers) = call_upstream(
+            provider,
+            request_body,
+            upstream_stream,
+            deadline,
+            max_duration,
+            options,
+        )
+        .await?;
+        let (body_bytes, timed_out) =
+            read_body_with_watchdog(response, idle_timeout, deadline).await?;
+        match frame::verified_len(&body_bytes) {
+            (_, Some(e)) if !retried => {
+                retried = true;
+                tracing::warn!("上游响应校验失败，重试一次: {}", e);
+            }
+            (_, Some(e)) => {
+                tracing::error!("上游响应校验失败: {}", e);
+                return Err((
+                    StatusCode::BAD_GATEWAY,
+                    Json(ErrorResponse::new(
+                        "api_error",
+                        format!("上游响应校验失败: {}", e),
+                    )),
+                )
+                    .into_response());
+            }
+            (_, None) => break (body_bytes, timed_out, credential_id, upstream_headers),
+        }
+    };
+
+    // 超时时只保留已完整接收的内容（未结束的工具调用被丢弃）
+    let mut aggregated = aggregate_events(&body_bytes);
+    aggregated.credential_id = credential_id;
+    aggregated.upstream_headers = upstream_headers;
+    if timed_out {
+        aggregated.stop_reason = StopReason::Timeout;
+    }
+
+    // 保存文件事件，将下载链接追加到文本
+    for artifact in std::mem::take(&mut aggregated.artifacts) {
+        if let Some(text) = artifacts::surface(artifacts, &artifact).await {
+            aggregated.text.push_str(&text);
+        }
+    }
+
+    Ok(aggregated)
+}
+
+/// 调用 Kiro API（支持多凭据故障转移），返回响应、提供响应的凭据与上游响应头
+async fn call_upstream(
+    provider: &KiroProvider,
+    request_body: &str,
+    upstream_stream: bool,
+    deadline: Option<Instant>,
+    max_duration: Option<Duration>,
+    options: &CallOptions,
+) -> Result<(reqwest::Response, Option<u64>, HeaderMap), Response> {
+    let call = async {
+        if upstream_stream {
+            provider.call_api_stream(request_body, options).await
+        } else {
+            provider.call_api(request_body, options).await
+        }
+    };
+    let result = match deadline {
+        Some(deadline) => match tokio::time::timeout_at(deadline.into(), call).await {
+            Ok(result) => result,
+            Err(_) => {
+                let secs = max_duration.unwrap_or_default().as_secs();
+                tracing::warn!("非流式请求超过 {} 秒仍未收到上游响应", secs);
+                return Err((
+                    StatusCode::GATEWAY_TIMEOUT,
+                    Json(ErrorResponse::new(
+                        "api_error",
+                        format!("请求超过 {} 秒仍未收到上游响应", secs),
+                    )),
+                )
+                    .into_response());
+            }
+        },
+        None => call.await,
+    };
+    let response = match result {
         Ok(resp) => resp,
         Err(e) => {
-            tracing::error!("Kiro API 调用失败: {}", e);
-            return (
+            tracing::error!(
+                "Kiro API 调用失败: {}",
+                log_error(provider.log_limit(options), &e)
+            );
+            return Err((
                 StatusCode::BAD_GATEWAY,
                 Json(ErrorResponse::new(
                     "api_error",
                     format!("上游 API 调用失败: {}", e),
                 )),
             )
-                .into_response();
+                .into_response());
         }
     };
 
-    // 读取响应体
-    let body_bytes = match response.bytes().await {
-        Ok(bytes) => bytes,
-        Err(e) => {
-            tracing::error!("读取响应体失败: {}", e);
-            return (
-                StatusCode::BAD_GATEWAY,
-                Json(ErrorResponse::new(
-                    "api_error",
-                    format!("读取响应失败: {}", e),
-                )),
-            )
-                .into_response();
-        }
+    let credential_id = ServedCredential::of(&response);
+    let upstream_headers = response.headers().clone();
+    Ok((response, credential_id, upstream_headers))
+}
+
+/// 读取完整响应体，返回响应体与是否因到达 `deadline` 而提前结束
+///
+/// 设置 `idle_timeout` 时，连续超过该时长未收到任何数据即中止（drop 响应会断开上游连接）；
+/// 到达 `deadline` 时停止读取并返回已接收的部分
+async fn read_body_with_watchdog(
+    response: reqwest::Response,
+    idle_timeout: Option<Duration>,
+    deadline: Option<Instant>,
+) -> Result<(Vec<u8>, bool), Response> {
+    let read_error = |e: reqwest::Error| {
+        tracing::error!("读取响应体失败: {}", e);
+        (
+            StatusCode::BAD_GATEWAY,
+            Json(ErrorResponse::new(
+                "api_error",
+                format!("读取响应失败: {}", e),
+            )),
+        )
+            .into_response()
     };
 
+    if idle_timeout.is_none() && deadline.is_none() {
+        return response
+            .bytes()
+            .await
+            .map(|b| (b.to_vec(), false))
+            .map_err(read_error);
+    }
+
+    let mut body = Vec::new();
+    let mut chunks = response.bytes_stream();
+    loop {
+        let wait_until = [idle_timeout.map(|t| Instant::now() + t), deadline]
+            .into_iter()
+            .flatten()
+            .min()
+            .unwrap_or_else(Instant::now);
+        match tokio::time::timeout_at(wait_until.into(), chunks.next()).await {
+            Ok(Some(Ok(chunk))) => body.extend_from_slice(&chunk),
+            Ok(Some(Err(e))) => return Err(read_error(e)),
+            Ok(None) => return Ok((body, false)),
+            Err(_) if deadline.is_some_and(|d| Instant::now() >= d) => {
+                tracing::warn!(
+                    "非流式请求超过最长时长，返回已接收的部分内容（{} 字节）",
+                    body.len()
+                );
+                return Ok((body, true));
+            }
+            Err(_) => {
+                let idle_timeout = idle_timeout.unwrap_or_default();
+                tracing::warn!(
+                    "上游 {} 秒内无输出，中止非流式请求（已接收 {} 字节）",
+                    idle_timeout.as_secs(),
+                    body.len()
+                );
+                return Err((
+                    StatusCode::GATEWAY_TIMEOUT,
+                    Json(ErrorResponse::new(
+                        "api_error",
+                        format!("上游 {} 秒内无输出，请求已中止", idle_timeout.as_secs()),
+                    )),
+                )
+                    .into_response());
+            }
+        }
+    }
+}
+
+/// 解析完整事件流并聚合为文本与工具调用
+fn aggregate_events(body_bytes: &[u8]) -> AggregatedResponse {
     // 解析事件流
     let mut decoder = EventStreamDecoder::new();
-    if let Err(e) = decoder.feed(&body_bytes) {
+    if let Err(e) = decoder.feed(body_bytes) {
         tracing::warn!("缓冲区溢出: {}", e);
     }
 
     let mut text_content = String::new();
+    let mut text_joiner = TextJoiner::default();
     let mut tool_uses: Vec<serde_json::Value> = Vec::new();
+    let mut artifact_events: Vec<ArtifactEvent> = Vec::new();
     let mut has_tool_use = false;
-    let mut stop_reason = "end_turn".to_string();
+    let mut explicit_stop_reason: Option<StopReason> = None;
     // 从 contextUsageEvent 计算的实际输入 tokens
     let mut context_input_tokens: Option<i32> = None;
 
@@ -407,7 +1854,7 @@ async fn handle_non_stream_request(
                 if let Ok(event) = Event::from_frame(frame) {
                     match event {
                         Event::AssistantResponse(resp) => {
-                            text_content.push_str(&resp.content);
+                            text_content.push_str(&resp.join(&mut text_joiner));
                         }
                         Event::ToolUse(tool_use) => {
                             has_tool_use = true;
@@ -452,10 +1899,13 @@ async fn handle_non_stream_request(
                             );
                         }
                         Event::Exception { exception_type, .. } => {
-                            if exception_type == "ContentLengthExceededException" {
-                                stop_reason = "max_tokens".to_string();
+                            if let Some(reason) = StopReason::from_exception(&exception_type) {
+                                explicit_stop_reason = Some(reason);
                             }
                         }
+                        Event::Artifact(artifact) => {
+                            artifact_events.push(artifact);
+                        }
                         _ => {}
                     }
                 }
@@ -466,45 +1916,175 @@ async fn handle_non_stream_request(
         }
     }
 
-    // 确定 stop_reason
-    if has_tool_use && stop_reason == "end_turn" {
-        stop_reason = "tool_use".to_string();
+    text_content.push_str(&text_joiner.finish());
+
+    AggregatedResponse {
+        text: text_content,
+        tool_uses,
+        // 确定 stop_reason
+        stop_reason: StopReason::resolve(explicit_stop_reason, has_tool_use),
+        context_input_tokens,
+        artifacts: artifact_events,
+        credential_id: None,
+        upstream_headers: HeaderMap::new(),
     }
+}
 
+/// 构建 Anthropic 非流式响应体
+///
+/// `stop_reason_override` 用于代理侧强制结束（如 max_turns_exceeded）
+fn build_message_response(
+    model: &str,
+    aggregated: AggregatedResponse,
+    input_tokens: i32,
+    stop_reason_override: Option<StopReason>,
+) -> serde_json::Value {
     // 构建响应内容
     let mut content: Vec<serde_json::Value> = Vec::new();
 
-    if !text_content.is_empty() {
+    if !aggregated.text.is_empty() {
         content.push(json!({
             "type": "text",
-            "text": text_content
+            "text": aggregated.text
         }));
     }
 
-    content.extend(tool_uses);
+    content.extend(aggregated.tool_uses);
 
     // 估算输出 tokens
     let output_tokens = token::estimate_output_tokens(&content);
 
     // 使用从 contextUsageEvent 计算的 input_tokens，如果没有则使用估算值
-    let final_input_tokens = context_input_tokens.unwrap_or(input_tokens);
+    let final_input_tokens = aggregated.context_input_tokens.unwrap_or(input_tokens);
+    let stop_reason = stop_reason_override.unwrap_or(aggregated.stop_reason);
 
     // 构建 Anthropic 响应
-    let response_body = json!({
+    json!({
         "id": format!("msg_{}", Uuid::new_v4().to_string().replace('-', "")),
         "type": "message",
         "role": "assistant",
         "content": content,
         "model": model,
-        "stop_reason": stop_reason,
+        "stop_reason": stop_reason.as_anthropic(),
         "stop_sequence": null,
         "usage": {
             "input_tokens": final_input_tokens,
             "output_tokens": output_tokens
         }
-    });
+    })
+}
+
+/// 若客户端可使用本地工具，返回执行器
+///
+/// 仅对非流式、且客户端在 clientKeys 中被授予本地工具的请求生效
+fn local_tool_runner_for(
+    state: &AppState,
+    identity: &ClientIdentity,
+    payload: &MessagesRequest,
+) -> Option<Arc<LocalToolRunner>> {
+    if payload.stream || identity.local_tools.is_empty() {
+        return None;
+    }
+    state.local_tools.clone()
+}
+
+/// 本地工具 agent 循环
+///
+/// 注入本地工具定义后反复调用上游：模型只请求本地工具时由代理执行并回填结果，
+/// 直到模型给出最终回复、请求了客户端自己的工具，或超过 toolLoop 轮次/时长上限。
+async fn handle_local_tool_loop(
+    provider: Arc<KiroProvider>,
+    state: &AppState,
+    runner: Arc<LocalToolRunner>,
+    allowed: &[LocalToolKind],
+    mut payload: MessagesRequest,
+    input_tokens: i32,
+    options: &CallOptions,
+) -> Response {
+    let limits = provider.token_manager().config().tool_loop.clone();
+    let deadline = Instant::now() + Duration::from_secs(limits.max_duration_secs);
+
+    // 注入本地工具定义（不覆盖客户端同名工具）
+    let tools = payload.tools.get_or_insert_with(Vec::new);
+    for definition in runner.definitions(allowed) {
+        if !tools.iter().any(|t| t.name == definition.name) {
+            tools.push(definition);
+        }
+    }
+
+    let mut rounds = 0;
+    loop {
+        let request_body = match build_request_body(&payload, state, options) {
+            Ok(body) => body,
+            Err(response) => return *response,
+        };
+        let aggregated = match call_and_aggregate(
+            &provider,
+            state.artifacts.as_deref(),
+            &request_body,
+            false,
+            None,
+            options,
+        )
+        .await
+        {
+            Ok(aggregated) => aggregated,
+            Err(response) => return response,
+        };
+
+        let all_local = !aggregated.tool_uses.is_empty()
+            && aggregated.tool_uses.iter().all(|t| {
+                t.get("name")
+                    .and_then(|n| n.as_str())
+                    .is_some_and(is_local_tool)
+            });
+        // 最终回复，或需要客户端自己执行的工具：直接返回
+        if !all_local {
+            let body = build_message_response(&payload.model, aggregated, input_tokens, None);
+            return (StatusCode::OK, Json(body)).into_response();
+        }
+
+        if rounds >= limits.max_rounds || Instant::now() >= deadline {
+            tracing::warn!("本地工具循环超过上限（已执行 {} 轮）", rounds);
+            let body = build_message_response(
+                &payload.model,
+                aggregated,
+                input_tokens,
+                Some(StopReason::MaxTurnsExceeded),
+            );
+            return (StatusCode::OK, Json(body)).into_response();
+        }
+        rounds += 1;
+
+        // 执行本地工具并回填结果
+        let mut tool_results = Vec::new();
+        for tool_use in &aggregated.tool_uses {
+            let name = tool_use["name"].as_str().unwrap_or_default();
+            tracing::info!(tool = %name, round = rounds, "执行本地工具");
+            let outcome = runner.execute(allowed, name, &tool_use["input"]).await;
+            tool_results.push(json!({
+                "type": "tool_result",
+                "tool_use_id": tool_use["id"],
+                "content": outcome.content,
+                "is_error": outcome.is_error
+            }));
+        }
 
-    (StatusCode::OK, Json(response_body)).into_response()
+        let mut assistant_content = Vec::new();
+        if !aggregated.text.is_empty() {
+            assistant_content.push(json!({"type": "text", "text": aggregated.text}));
+        }
+        assistant_content.extend(aggregated.tool_uses);
+
+        payload.messages.push(Message {
+            role: "assistant".to_string(),
+            content: serde_json::Value::Array(assistant_content),
+        });
+        payload.messages.push(Message {
+            role: "user".to_string(),
+            content: serde_json::Value::Array(tool_results),
+        });
+    }
 }
 
 /// POST /v1/messages/count_tokens
@@ -531,170 +2111,338 @@ pub async fn count_tokens(
     })
 }
 
-/// POST /cc/v1/messages
+/// POST /v1/dry-run
 ///
-/// Claude Code 兼容端点，与 /v1/messages 的区别在于：
-/// - 流式响应会等待 kiro 端返回 contextUsageEvent 后再发送 message_start
-/// - message_start 中的 input_tokens 是从 contextUsageEvent 计算的准确值
-pub async fn post_messages_cc(
+/// 预演 Messages 请求：依次执行默认参数、过滤器、上下文路由、使用范围检查、请求转换与 token 估算，
+/// 返回将发送给上游的请求体（profileArn 已脱敏）以及将使用的模型与凭据，不调用上游。
+/// 摘要式压缩需要调用上游，这里只报告是否会触发
+pub async fn post_dry_run(
     State(state): State<AppState>,
-    JsonExtractor(payload): JsonExtractor<MessagesRequest>,
+    Extension(identity): Extension<ClientIdentity>,
+    headers: HeaderMap,
+    JsonExtractor(mut payload): JsonExtractor<MessagesRequest>,
 ) -> Response {
-    tracing::info!(
-        model = %payload.model,
-        max_tokens = %payload.max_tokens,
-        stream = %payload.stream,
-        message_count = %payload.messages.len(),
-        "Received POST /cc/v1/messages request"
-    );
-
-    // 检查 KiroProvider 是否可用
-    let provider = match &state.kiro_provider {
-        Some(p) => p.clone(),
-        None => {
-            tracing::error!("KiroProvider 未配置");
-            return (
-                StatusCode::SERVICE_UNAVAILABLE,
-                Json(ErrorResponse::new(
-                    "service_unavailable",
-                    "Kiro API provider not configured",
-                )),
-            )
-                .into_response();
-        }
+    let Some(provider) = state.kiro_provider.clone() else {
+        return (
+            StatusCode::SERVICE_UNAVAILABLE,
+            Json(ErrorResponse::new(
+                "service_unavailable",
+                "Kiro API provider not configured",
+            )),
+        )
+            .into_response();
     };
+    let options = match call_options_from_headers(&headers) {
+        Ok(options) => options.with_log_content(identity.log_content),
+        Err(response) => return *response,
+    };
+    let requested_model = payload.model.clone();
 
-    // 检查是否为 WebSearch 请求
-    if websearch::has_web_search_tool(&payload) {
-        tracing::info!("检测到 WebSearch 工具，路由到 WebSearch 处理");
-
-        // 估算输入 tokens
-        let input_tokens = token::count_all_tokens(
+    if let Some(response) =
+        apply_request_defaults(state.presets.as_deref(), &identity, &mut payload)
+    {
+        return response;
+    }
+    if let Some(response) = apply_request_filters(&state, &mut payload) {
+        return response;
+    }
+    let context_compression = state.context_compressor.as_ref().is_some_and(|compressor| {
+        let tokens = token::count_all_tokens(
             payload.model.clone(),
             payload.system.clone(),
             payload.messages.clone(),
             payload.tools.clone(),
-        ) as i32;
-
-        return websearch::handle_websearch_request(provider, &payload, input_tokens).await;
+        );
+        compressor.plan(&payload, tokens).is_some()
+    });
+    if let Some(response) = apply_context_routing(&state, &mut payload) {
+        return response;
+    }
+    if let Some(response) = check_scopes(&state, &identity, &mut payload) {
+        return response;
     }
 
-    // 转换请求
-    let conversion_result = match convert_request(&payload) {
-        Ok(result) => result,
-        Err(e) => {
-            let (error_type, message) = match &e {
-                ConversionError::UnsupportedModel(model) => {
-                    ("invalid_request_error", format!("模型不支持: {}", model))
-                }
-                ConversionError::EmptyMessages => {
-                    ("invalid_request_error", "消息列表为空".to_string())
-                }
-            };
-            tracing::warn!("请求转换失败: {}", e);
-            return (
-                StatusCode::BAD_REQUEST,
-                Json(ErrorResponse::new(error_type, message)),
-            )
-                .into_response();
-        }
+    let handler = if websearch::has_web_search_tool(&payload) {
+        "webSearch"
+    } else if local_tool_runner_for(&state, &identity, &payload).is_some() {
+        "localTools"
+    } else {
+        "messages"
+    };
+    let request_body = match build_request_body(&payload, &state, &options) {
+        Ok(body) => body,
+        Err(response) => return *response,
     };
+    let input_tokens = token::count_all_tokens(
+        payload.model.clone(),
+        payload.system.clone(),
+        payload.messages.clone(),
+        payload.tools.clone(),
+    );
 
-    // 构建 Kiro 请求
-    let kiro_request = KiroRequest {
-        conversation_state: conversion_result.conversation_state,
-        profile_arn: state.profile_arn.clone(),
+    let (credential, upstream_request) = match provider.plan_api_call(&request_body, &options) {
+        Ok(plan) => (
+            json!({
+                "id": plan.credential_id,
+                "region": plan.region,
+                "url": plan.url,
+            }),
+            plan.request_body,
+        ),
+        Err(e) => (json!({ "error": e.to_string() }), request_body),
     };
+    let mut upstream_request: serde_json::Value =
+        serde_json::from_str(&upstream_request).unwrap_or_default();
+    if let Some(arn) = upstream_request.get_mut("profileArn")
+        && arn.is_string()
+    {
+        *arn = json!("[REDACTED]");
+    }
 
-    let request_body = match serde_json::to_string(&kiro_request) {
-        Ok(body) => body,
-        Err(e) => {
-            tracing::error!("序列化请求失败: {}", e);
-            return (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(ErrorResponse::new(
-                    "internal_error",
-                    format!("序列化请求失败: {}", e),
-                )),
-            )
-                .into_response();
-        }
+    tracing::info!(
+        "客户端 {} 预演请求: 模型 {}，约 {} tokens",
+        identity.name,
+        payload.model,
+        input_tokens
+    );
+    Json(json!({
+        "client": identity.name,
+        "handler": handler,
+        "requestedModel": requested_model,
+        "model": payload.model,
+        "upstreamModel": map_model(&payload.model),
+        "stream": payload.stream,
+        "maxTokens": payload.max_tokens,
+        "inputTokens": input_tokens,
+        "contextCompression": context_compression,
+        "credential": credential,
+        "upstreamRequest": upstream_request,
+    }))
+    .into_response()
+}
+
+/// POST /v1/session-tokens
+///
+/// 用当前 API Key 换取短期会话 Token（JWT），可收窄模型范围与 max_tokens 上限
+pub async fn post_session_token(
+    State(state): State<AppState>,
+    Extension(identity): Extension<ClientIdentity>,
+    JsonExtractor(request): JsonExtractor<SessionTokenRequest>,
+) -> Response {
+    let Some(issuer) = &state.session_tokens else {
+        return (
+            StatusCode::NOT_FOUND,
+            Json(ErrorResponse::new(
+                "not_found_error",
+                "未启用 sessionTokens".to_string(),
+            )),
+        )
+            .into_response();
+    };
+    let forbidden = |message: &str| {
+        (
+            StatusCode::FORBIDDEN,
+            Json(ErrorResponse::new("permission_error", message.to_string())),
+        )
+            .into_response()
     };
+    if identity.via_session_token {
+        return forbidden("会话 Token 不能换取新的会话 Token");
+    }
+    if !identity.session_tokens {
+        return forbidden("该 API Key 未被允许换取会话 Token");
+    }
+    if !session_token::models_within(&request.models, &identity.scopes.models) {
+        return forbidden("请求的模型超出该 API Key 的使用范围");
+    }
 
-    tracing::debug!("Kiro request body: {}", request_body);
+    let Some(kid) = state.key_fingerprint(&identity) else {
+        return forbidden("找不到该 API Key");
+    };
 
-    // 估算输入 tokens
-    let input_tokens = token::count_all_tokens(
-        payload.model.clone(),
-        payload.system,
-        payload.messages,
-        payload.tools,
-    ) as i32;
+    let ttl_secs = issuer.ttl_secs(request.ttl_secs);
+    let now = chrono::Utc::now();
+    let expires_at = now + chrono::Duration::seconds(ttl_secs as i64);
+    let token = issuer.issue(&SessionClaims {
+        sub: identity.name.clone(),
+        global: identity.global,
+        kid,
+        iat: now.timestamp(),
+        exp: expires_at.timestamp(),
+        models: request.models,
+        max_tokens: request.max_tokens,
+    });
+    tracing::info!(
+        "客户端 {} 换取了会话 Token，{} 秒后过期",
+        identity.name,
+        ttl_secs
+    );
+    Json(SessionTokenResponse {
+        token,
+        token_type: "Bearer",
+        expires_at,
+        expires_in: ttl_secs,
+    })
+    .into_response()
+}
 
-    // 检查是否启用了thinking
-    let thinking_enabled = payload
-        .thinking
-        .as_ref()
-        .map(|t| t.thinking_type == "enabled")
-        .unwrap_or(false);
+/// GET /v1/artifacts/{id}
+///
+/// 下载上游文件（local 存储），凭签名链接访问，无需 API Key
+pub async fn get_artifact(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Query(query): Query<ArtifactDownloadQuery>,
+) -> Response {
+    let not_found = || {
+        (
+            StatusCode::NOT_FOUND,
+            Json(ErrorResponse::new("not_found_error", "Artifact not found")),
+        )
+            .into_response()
+    };
 
-    if payload.stream {
-        // 流式响应（缓冲模式）
-        handle_stream_request_buffered(
-            provider,
-            &request_body,
-            &payload.model,
-            input_tokens,
-            thinking_enabled,
+    let Some(store) = state.artifacts.as_ref() else {
+        return not_found();
+    };
+    if !store.verify(&id, query.expires, &query.signature) {
+        return (
+            StatusCode::FORBIDDEN,
+            Json(ErrorResponse::new(
+                "permission_error",
+                "Invalid or expired artifact signature",
+            )),
         )
-        .await
-    } else {
-        // 非流式响应（复用现有逻辑，已经使用正确的 input_tokens）
-        handle_non_stream_request(provider, &request_body, &payload.model, input_tokens).await
+            .into_response();
     }
+    let Some((meta, data)) = store.open(&id).await else {
+        return not_found();
+    };
+
+    artifact_response(&meta, data)
+}
+
+/// 产物下载响应
+///
+/// mimeType 来自上游事件，不是合法的头部值时按二进制下载；禁止浏览器嗅探内容类型
+fn artifact_response(meta: &artifacts::StoredArtifact, data: Vec<u8>) -> Response {
+    let content_type = HeaderValue::from_str(&meta.mime_type)
+        .unwrap_or(HeaderValue::from_static("application/octet-stream"));
+    let disposition = HeaderValue::try_from(format!(
+        "attachment; filename*=UTF-8''{}",
+        urlencoding::encode(&meta.name)
+    ))
+    .unwrap_or(HeaderValue::from_static("attachment"));
+    (
+        StatusCode::OK,
+        [
+            (header::CONTENT_TYPE, content_type),
+            (header::CONTENT_DISPOSITION, disposition),
+            (
+                header::X_CONTENT_TYPE_OPTIONS,
+                HeaderValue::from_static("nosniff"),
+            ),
+        ],
+        Body::from(data),
+    )
+        .into_response()
+}
+
+/// POST /cc/v1/messages
+///
+/// Claude Code 兼容端点，与 /v1/messages 的区别在于：
+/// - 流式响应会等待 kiro 端返回 contextUsageEvent 后再发送 message_start
+/// - message_start 中的 input_tokens 是从 contextUsageEvent 计算的准确值
+pub async fn post_messages_cc(
+    State(state): State<AppState>,
+    Extension(identity): Extension<ClientIdentity>,
+    headers: HeaderMap,
+    JsonExtractor(payload): JsonExtractor<MessagesRequest>,
+) -> Response {
+    tracing::info!(
+        model = %payload.model,
+        max_tokens = %payload.max_tokens,
+        stream = %payload.stream,
+        message_count = %payload.messages.len(),
+        "Received POST /cc/v1/messages request"
+    );
+    handle_messages(
+        state,
+        identity,
+        headers,
+        payload,
+        MessagesEndpoint::ClaudeCode,
+    )
+    .await
 }
 
 /// 处理流式请求（缓冲版本）
 ///
 /// 与 `handle_stream_request` 不同，此函数会缓冲所有事件直到流结束，
 /// 然后用从 contextUsageEvent 计算的正确 input_tokens 生成 message_start 事件。
+#[allow(clippy::too_many_arguments)]
 async fn handle_stream_request_buffered(
     provider: std::sync::Arc<crate::kiro::provider::KiroProvider>,
+    artifacts: Option<Arc<ArtifactStore>>,
     request_body: &str,
     model: &str,
     estimated_input_tokens: i32,
     thinking_enabled: bool,
+    mut completion: Completion,
+    renderer: StreamRenderer,
+    options: &CallOptions,
 ) -> Response {
-    // 调用 Kiro API（支持多凭据故障转移）
-    let response = match provider.call_api_stream(request_body).await {
-        Ok(resp) => resp,
-        Err(e) => {
-            tracing::error!("Kiro API 调用失败: {}", e);
-            return (
-                StatusCode::BAD_GATEWAY,
-                Json(ErrorResponse::new(
-                    "api_error",
-                    format!("上游 API 调用失败: {}", e),
-                )),
-            )
-                .into_response();
-        }
-    };
+    // 调用 Kiro API（支持多凭据故障转移与卡顿重试）
+    let (body_stream, upstream_headers) =
+        match open_upstream_stream(&provider, request_body, options, completion.coalesce.take())
+            .await
+        {
+            Ok((body, credential_id, headers)) => {
+                served_by(&mut completion.usage, credential_id);
+                (body, headers)
+            }
+            Err(e) => {
+                tracing::error!(
+                    "Kiro API 调用失败: {}",
+                    log_error(provider.log_limit(options), &e)
+                );
+                discard_usage(completion.usage);
+                return (
+                    StatusCode::BAD_GATEWAY,
+                    Json(ErrorResponse::new(
+                        "api_error",
+                        format!("上游 API 调用失败: {}", e),
+                    )),
+                )
+                    .into_response();
+            }
+        };
+
+    let cost = completion.cost.take();
+    let forwarder = completion.forward_headers.take();
 
     // 创建缓冲流处理上下文
     let ctx = BufferedStreamContext::new(model, estimated_input_tokens, thinking_enabled);
 
     // 创建缓冲 SSE 流
-    let stream = create_buffered_sse_stream(response, ctx);
+    let content_type = renderer.content_type();
+    let stream = create_buffered_sse_stream(body_stream, ctx, artifacts, completion, renderer);
 
     // 返回 SSE 响应
-    Response::builder()
+    let mut response = Response::builder()
         .status(StatusCode::OK)
-        .header(header::CONTENT_TYPE, "text/event-stream")
+        .header(header::CONTENT_TYPE, content_type)
         .header(header::CACHE_CONTROL, "no-cache")
         .header(header::CONNECTION, "keep-alive")
         .body(Body::from_stream(stream))
-        .unwrap()
+        .unwrap();
+    if let Some(cost) = cost {
+        cost.apply_headers(response.headers_mut(), estimated_input_tokens, None);
+    }
+    if let Some(forwarder) = forwarder {
+        forwarder.apply(&upstream_headers, response.headers_mut());
+    }
+    response
 }
 
 /// 创建缓冲 SSE 事件流
@@ -705,11 +2453,12 @@ async fn handle_stream_request_buffered(
 /// 3. 流结束后，用正确的 input_tokens 更正 message_start 事件
 /// 4. 一次性发送所有事件
 fn create_buffered_sse_stream(
-    response: reqwest::Response,
+    body_stream: UpstreamBody,
     ctx: BufferedStreamContext,
+    artifacts: Option<Arc<ArtifactStore>>,
+    completion: Completion,
+    renderer: StreamRenderer,
 ) -> impl Stream<Item = Result<Bytes, Infallible>> {
-    let body_stream = response.bytes_stream();
-
     stream::unfold(
         (
             body_stream,
@@ -717,8 +2466,11 @@ fn create_buffered_sse_stream(
             EventStreamDecoder::new(),
             false,
             interval(Duration::from_secs(PING_INTERVAL_SECS)),
+            artifacts,
+            completion,
+            renderer,
         ),
-        |(mut body_stream, mut ctx, mut decoder, finished, mut ping_interval)| async move {
+        |(mut body_stream, mut ctx, mut decoder, finished, mut ping_interval, artifacts, mut completion, mut renderer)| async move {
             if finished {
                 return None;
             }
@@ -732,8 +2484,18 @@ fn create_buffered_sse_stream(
                     // 优先检查 ping 保活（等待期间唯一发送的数据）
                     _ = ping_interval.tick() => {
                         tracing::trace!("发送 ping 保活事件（缓冲模式）");
-                        let bytes: Vec<Result<Bytes, Infallible>> = vec![Ok(create_ping_sse())];
-                        return Some((stream::iter(bytes), (body_stream, ctx, decoder, false, ping_interval)));
+                        let bytes: Vec<Result<Bytes, Infallible>> = renderer.ping().map(Ok).into_iter().collect();
+                        return Some((stream::iter(bytes), (body_stream, ctx, decoder, false, ping_interval, artifacts, completion, renderer)));
+                    }
+
+                    // 超过最长时长：以 timeout 结束并返回已缓冲的事件
+                    _ = until_deadline(completion.stream_deadline) => {
+                        tracing::warn!("流式请求超过最长时长，以 timeout 结束并中止上游（缓冲模式）");
+                        ctx.set_stop_reason(StopReason::Timeout);
+                        let all_events = ctx.finish_and_get_all_events();
+                        complete_usage(&mut completion.usage, ctx.tokens());
+                        let bytes: Vec<Result<Bytes, Infallible>> = renderer.render_all(all_events);
+                        return Some((stream::iter(bytes), (body_stream, ctx, decoder, true, ping_interval, artifacts, completion, renderer)));
                     }
 
                     // 然后处理数据流
@@ -749,6 +2511,12 @@ fn create_buffered_sse_stream(
                                     match result {
                                         Ok(frame) => {
                                             if let Ok(event) = Event::from_frame(frame) {
+                                                if let Event::Artifact(artifact) = &event {
+                                                    if let Some(text) = artifacts::surface(artifacts.as_deref(), artifact).await {
+                                                        ctx.buffer_artifact_text(&text);
+                                                    }
+                                                    continue;
+                                                }
                                                 // 缓冲事件（复用 StreamContext 的处理逻辑）
                                                 ctx.process_and_buffer(&event);
                                             }
@@ -758,26 +2526,38 @@ fn create_buffered_sse_stream(
                                         }
                                     }
                                 }
+                                update_usage(&mut completion.usage, ctx.tokens());
                                 // 继续读取下一个 chunk，不发送任何数据
                             }
+                            Some(Err(ReadError::Stalled(timeout))) => {
+                                // 卡顿：丢弃已缓冲的事件，以 error 事件中止
+                                complete_usage(&mut completion.usage, ctx.tokens());
+                                let bytes: Vec<Result<Bytes, Infallible>> = renderer.render_all(vec![stall::stalled_event(timeout)]);
+                                return Some((stream::iter(bytes), (body_stream, ctx, decoder, true, ping_interval, artifacts, completion, renderer)));
+                            }
+                            Some(Err(ReadError::Corrupted(_))) => {
+                                // 上游数据损坏：丢弃已缓冲的事件，以 error 事件中止
+                                complete_usage(&mut completion.usage, ctx.tokens());
+                                let bytes: Vec<Result<Bytes, Infallible>> = renderer.render_all(vec![stall::corrupted_event()]);
+                                return Some((stream::iter(bytes), (body_stream, ctx, decoder, true, ping_interval, artifacts, completion, renderer)));
+                            }
                             Some(Err(e)) => {
                                 tracing::error!("读取响应流失败: {}", e);
                                 // 发生错误，完成处理并返回所有事件
                                 let all_events = ctx.finish_and_get_all_events();
-                                let bytes: Vec<Result<Bytes, Infallible>> = all_events
-                                    .into_iter()
-                                    .map(|e| Ok(Bytes::from(e.to_sse_string())))
-                                    .collect();
-                                return Some((stream::iter(bytes), (body_stream, ctx, decoder, true, ping_interval)));
+                                complete_usage(&mut completion.usage, ctx.tokens());
+                                let bytes: Vec<Result<Bytes, Infallible>> = renderer.render_all(all_events);
+                                return Some((stream::iter(bytes), (body_stream, ctx, decoder, true, ping_interval, artifacts, completion, renderer)));
                             }
                             None => {
-                                // 流结束，完成处理并返回所有事件（已更正 input_tokens）
+                                // 流结束，追加水印，完成处理并返回所有事件（已更正 input_tokens）
+                                if let Some(mark) = completion.watermark.take() {
+                                    ctx.buffer_artifact_text(&mark);
+                                }
                                 let all_events = ctx.finish_and_get_all_events();
-                                let bytes: Vec<Result<Bytes, Infallible>> = all_events
-                                    .into_iter()
-                                    .map(|e| Ok(Bytes::from(e.to_sse_string())))
-                                    .collect();
-                                return Some((stream::iter(bytes), (body_stream, ctx, decoder, true, ping_interval)));
+                                complete_usage(&mut completion.usage, ctx.tokens());
+                                let bytes: Vec<Result<Bytes, Infallible>> = renderer.render_all(all_events);
+                                return Some((stream::iter(bytes), (body_stream, ctx, decoder, true, ping_interval, artifacts, completion, renderer)));
                             }
                         }
                     }
@@ -787,3 +2567,272 @@ fn create_buffered_sse_stream(
     )
     .flatten()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_artifact_response_sanitizes_content_type() {
+        let meta = |mime_type: &str| artifacts::StoredArtifact {
+            name: "报告 1.txt".to_string(),
+            mime_type: mime_type.to_string(),
+        };
+        let response = artifact_response(&meta("text/plain"), b"hi".to_vec());
+        let headers = response.headers();
+        assert_eq!(headers[header::CONTENT_TYPE], "text/plain");
+        assert_eq!(headers[header::X_CONTENT_TYPE_OPTIONS], "nosniff");
+        assert_eq!(
+            headers[header::CONTENT_DISPOSITION],
+            "attachment; filename*=UTF-8''%E6%8A%A5%E5%91%8A%201.txt"
+        );
+
+        let response = artifact_response(&meta("text/html\r\nSet-Cookie: a=b"), Vec::new());
+        assert_eq!(
+            response.headers()[header::CONTENT_TYPE],
+            "application/octet-stream"
+        );
+        assert!(response.headers().get(header::SET_COOKIE).is_none());
+    }
+
+    fn message(role: &str, content: serde_json::Value) -> Message {
+        Message {
+            role: role.to_string(),
+            content,
+        }
+    }
+
+    #[test]
+    fn test_count_trailing_tool_rounds() {
+        let tool_result = json!([{"type": "tool_result", "tool_use_id": "t", "content": "ok"}]);
+        let tool_use = json!([{"type": "tool_use", "id": "t", "name": "x", "input": {}}]);
+        let messages = vec![
+            message("user", json!("hello")),
+            message("assistant", tool_use.clone()),
+            message("user", tool_result.clone()),
+            message("assistant", tool_use),
+            message("user", tool_result),
+        ];
+        assert_eq!(count_trailing_tool_rounds(&messages), 2);
+        assert_eq!(count_trailing_tool_rounds(&messages[..1]), 0);
+    }
+
+    #[test]
+    fn test_list_models_with_metadata() {
+        use super::super::context_routing::ContextRouter;
+        use super::super::cost::CostTable;
+        use crate::model::config::{ContextWindowRule, CostEstimationConfig};
+        use std::collections::HashMap;
+
+        let routing = ContextRoutingConfig {
+            enabled: true,
+            models: vec![ContextWindowRule {
+                model: "claude-opus-4.6".to_string(),
+                context_window: 1_000_000,
+                fallback_model: None,
+            }],
+            ..ContextRoutingConfig::default()
+        };
+        let cost = CostEstimationConfig {
+            enabled: true,
+            ..CostEstimationConfig::default()
+        };
+        let deployments = HashMap::from([
+            ("gpt-4o".to_string(), "claude-sonnet-4-5".to_string()),
+            ("unknown".to_string(), "gpt-4".to_string()),
+        ]);
+        let state = AppState::new("key")
+            .with_context_router(ContextRouter::from_config(&routing).unwrap())
+            .with_cost_table(CostTable::from_config(&cost).unwrap())
+            .with_azure_deployments(deployments);
+
+        let models = list_models(&state);
+        assert_eq!(models.len(), 5);
+        let opus = models
+            .iter()
+            .find(|m| m.id.starts_with("claude-opus-4-6"))
+            .unwrap();
+        assert_eq!(opus.context_length, 1_000_000);
+        assert_eq!(opus.pricing.requests, 2.2);
+        let deployment = &models[4];
+        assert_eq!(deployment.id, "gpt-4o");
+        assert_eq!(deployment.display_name, "Claude Sonnet 4.5");
+        assert_eq!(deployment.context_length, 200_000);
+
+        // 未启用成本估算时价格为占位值
+        let plain = list_models(&AppState::new("key"));
+        assert_eq!(plain.len(), 4);
+        assert_eq!(plain[0].pricing.prompt, "0");
+    }
+
+    #[test]
+    fn test_messages_endpoint_route_and_format() {
+        // /cc/v1/messages 与 /v1/messages 共用流程，只在指标标签和流式缓冲上不同
+        assert_eq!(MessagesEndpoint::ClaudeCode.route(), "/cc/v1/messages");
+        assert_eq!(
+            MessagesEndpoint::ClaudeCode.format(),
+            MessagesEndpoint::Messages.format()
+        );
+        assert_eq!(MessagesEndpoint::AzureChat.format(), RenderFormat::OpenAi);
+        assert_eq!(
+            MessagesEndpoint::Responses.format(),
+            RenderFormat::OpenAiResponses
+        );
+    }
+
+    #[test]
+    fn test_check_model_scope() {
+        let identity = ClientIdentity {
+            name: "team".to_string(),
+            local_tools: vec![],
+            stream_policy: StreamPolicy::default(),
+            scopes: serde_json::from_value(json!({"models": ["claude-sonnet-*"]})).unwrap(),
+            priority: Default::default(),
+            max_duration_secs: None,
+            stream_max_duration_secs: None,
+            preset: None,
+            response_cache: Default::default(),
+            log_content: None,
+            session_tokens: false,
+            via_session_token: false,
+            global: false,
+            defaults: Default::default(),
+        };
+        assert!(check_model_scope(&identity, "claude-sonnet-4.5").is_none());
+        // 上下文路由改用的回退模型同样受模型范围限制
+        let denied = check_model_scope(&identity, "claude-opus-4.5").unwrap();
+        assert_eq!(denied.status(), StatusCode::FORBIDDEN);
+    }
+
+    #[test]
+    fn test_apply_request_defaults() {
+        let identity = ClientIdentity {
+            name: "team".to_string(),
+            local_tools: vec![],
+            stream_policy: StreamPolicy::default(),
+            scopes: Default::default(),
+            priority: Default::default(),
+            max_duration_secs: None,
+            stream_max_duration_secs: None,
+            preset: None,
+            response_cache: Default::default(),
+            log_content: None,
+            session_tokens: false,
+            via_session_token: false,
+            global: false,
+            defaults: serde_json::from_value(json!({
+                "maxTokens": 2048,
+                "temperature": 0.2,
+                "system": "团队规范",
+                "stopSequences": ["END"]
+            }))
+            .unwrap(),
+        };
+        let request =
+            |body: serde_json::Value| -> MessagesRequest { serde_json::from_value(body).unwrap() };
+
+        let mut bare = request(json!({"model": "m", "messages": []}));
+        assert!(apply_request_defaults(None, &identity, &mut bare).is_none());
+        assert_eq!(bare.max_tokens, 2048);
+        assert_eq!(bare.temperature, Some(0.2));
+        assert_eq!(bare.system.unwrap()[0].text, "团队规范");
+        assert_eq!(bare.stop_sequences, Some(vec!["END".to_string()]));
+
+        // 请求自带的值优先
+        let mut explicit = request(json!({
+            "model": "m", "messages": [], "max_tokens": 100, "temperature": 1.0,
+            "system": "自定义", "stop_sequences": []
+        }));
+        assert!(apply_request_defaults(None, &identity, &mut explicit).is_none());
+        assert_eq!(explicit.max_tokens, 100);
+        assert_eq!(explicit.temperature, Some(1.0));
+        assert_eq!(explicit.system.unwrap()[0].text, "自定义");
+        assert_eq!(explicit.stop_sequences, Some(vec![]));
+
+        // 没有默认值时 max_tokens 仍是必填项
+        let plain = ClientIdentity {
+            defaults: Default::default(),
+            ..identity
+        };
+        let mut missing = request(json!({"model": "m", "messages": []}));
+        let response = apply_request_defaults(None, &plain, &mut missing).unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        // 预设在客户端密钥的默认值之后应用
+        let presets = RequestPresets::from_config(&crate::model::config::Config::default());
+        let cline = ClientIdentity {
+            preset: Some("cline".to_string()),
+            ..plain
+        };
+        let mut bare = request(json!({"model": "m", "messages": [], "system": "项目说明"}));
+        assert!(apply_request_defaults(Some(&presets), &cline, &mut bare).is_none());
+        assert_eq!(bare.max_tokens, 8192);
+        assert_eq!(bare.temperature, Some(0.0));
+        let system = bare.system.unwrap();
+        assert_eq!(system.len(), 2);
+        assert_eq!(system[0].text, "项目说明");
+
+        let mut large = request(json!({"model": "m", "messages": [], "max_tokens": 64000}));
+        assert!(apply_request_defaults(Some(&presets), &cline, &mut large).is_none());
+        assert_eq!(large.max_tokens, 32000);
+    }
+
+    #[tokio::test]
+    async fn test_read_body_returns_partial_at_deadline() {
+        let chunks = stream::once(async { Ok::<_, Infallible>(Bytes::from_static(b"partial")) })
+            .chain(stream::pending());
+        fn response(
+            chunks: impl Stream<Item = Result<Bytes, Infallible>> + Send + Sync + 'static,
+        ) -> reqwest::Response {
+            reqwest::Response::from(http::Response::new(reqwest::Body::wrap_stream(chunks)))
+        }
+
+        let deadline = Instant::now() + Duration::from_millis(50);
+        let (body, timed_out) = read_body_with_watchdog(response(chunks), None, Some(deadline))
+            .await
+            .unwrap();
+        assert_eq!(body, b"partial");
+        assert!(timed_out);
+
+        let complete = stream::iter([Ok::<_, Infallible>(Bytes::from_static(b"done"))]);
+        let (body, timed_out) = read_body_with_watchdog(response(complete), None, Some(deadline))
+            .await
+            .unwrap();
+        assert_eq!(body, b"done");
+        assert!(!timed_out);
+    }
+
+    #[tokio::test]
+    async fn test_stream_ends_with_timeout_at_deadline() {
+        let completion = Completion {
+            usage: None,
+            watermark: None,
+            filters: Arc::new(FilterChain::default()),
+            max_duration: None,
+            stream_deadline: Some(Instant::now()),
+            cost: None,
+            format: RenderFormat::Anthropic,
+            cache: None,
+            forward_headers: None,
+            coalesce: None,
+        };
+        let mut ctx = StreamContext::new_with_thinking("claude-sonnet-4-5", 10, false);
+        let initial_events = ctx.generate_initial_events();
+        // 上游一直不结束
+        let body: UpstreamBody = stream::pending().boxed();
+        let output: Vec<Bytes> = create_sse_stream(
+            body,
+            ctx,
+            initial_events,
+            None,
+            completion,
+            StreamRenderer::new(RenderFormat::Anthropic),
+        )
+        .map(|chunk| chunk.unwrap())
+        .collect()
+        .await;
+        let output = String::from_utf8(output.concat()).unwrap();
+        assert!(output.contains(r#""stop_reason":"timeout""#));
+        assert!(output.contains("message_stop"));
+    }
+}
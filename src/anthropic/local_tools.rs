@@ -0,0 +1,563 @@
+//! 本地工具沙箱
+//!
+//! 为无法自行执行工具的客户端提供代理侧的工具执行能力：
+//! - `local_fs_read`: 读取 fsRoot 内的文件（禁止越出根目录）
+//! - `local_shell`: 执行白名单内的命令（不经过 shell 解释，参数按空白切分；程序按白名单中的绝对路径执行，
+//!   参数不得为绝对路径或包含 `..`，指向的路径（含尚不存在的写入目标）同样不得越出根目录）
+//! - `local_http_fetch`: GET 抓取允许域名的 http/https 资源
+//!
+//! 需同时满足：config.localTools.enabled 为 true，且客户端密钥在 clientKeys 中被授予对应工具。
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use reqwest::Client;
+use reqwest::redirect::Policy;
+use serde_json::json;
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+use crate::http_client::{ProxyConfig, client_builder};
+use crate::model::config::{LocalToolKind, LocalToolsConfig, TlsBackend};
+
+use super::types::Tool;
+
+/// 工具执行结果
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ToolOutcome {
+    pub content: String,
+    pub is_error: bool,
+}
+
+impl ToolOutcome {
+    fn ok(content: impl Into<String>) -> Self {
+        Self {
+            content: content.into(),
+            is_error: false,
+        }
+    }
+
+    fn error(content: impl Into<String>) -> Self {
+        Self {
+            content: content.into(),
+            is_error: true,
+        }
+    }
+}
+
+impl LocalToolKind {
+    /// 暴露给模型的工具名
+    pub fn tool_name(&self) -> &'static str {
+        match self {
+            Self::FsRead => "local_fs_read",
+            Self::Shell => "local_shell",
+            Self::HttpFetch => "local_http_fetch",
+        }
+    }
+
+    fn from_tool_name(name: &str) -> Option<Self> {
+        [Self::FsRead, Self::Shell, Self::HttpFetch]
+            .into_iter()
+            .find(|k| k.tool_name() == name)
+    }
+
+    /// 工具定义
+    fn definition(&self) -> Tool {
+        let (description, property, property_description) = match self {
+            Self::FsRead => (
+                "Read a UTF-8 text file from the sandbox directory.",
+                "path",
+                "File path relative to the sandbox root",
+            ),
+            Self::Shell => (
+                "Run an allowlisted command in the sandbox directory. No shell syntax is supported.",
+                "command",
+                "Command line, e.g. `ls -la`",
+            ),
+            Self::HttpFetch => (
+                "Fetch a URL with HTTP GET and return the response body.",
+                "url",
+                "Absolute http(s) URL",
+            ),
+        };
+
+        let mut input_schema = HashMap::new();
+        input_schema.insert("type".to_string(), json!("object"));
+        input_schema.insert(
+            "properties".to_string(),
+            json!({ property: { "type": "string", "description": property_description } }),
+        );
+        input_schema.insert("required".to_string(), json!([property]));
+
+        Tool {
+            tool_type: None,
+            name: self.tool_name().to_string(),
+            description: description.to_string(),
+            input_schema,
+            max_uses: None,
+        }
+    }
+}
+
+/// 判断工具名是否属于本地工具
+pub fn is_local_tool(name: &str) -> bool {
+    LocalToolKind::from_tool_name(name).is_some()
+}
+
+/// 本地工具执行器
+pub struct LocalToolRunner {
+    config: LocalToolsConfig,
+    client: Client,
+}
+
+impl LocalToolRunner {
+    /// 创建执行器
+    ///
+    /// HTTP 抓取不跟随重定向，避免通过跳转绕过域名白名单；命令白名单必须为绝对路径
+    pub fn new(
+        config: LocalToolsConfig,
+        proxy: Option<&ProxyConfig>,
+        tls_backend: TlsBackend,
+    ) -> anyhow::Result<Self> {
+        if let Some(program) = config
+            .shell_allowlist
+            .iter()
+            .find(|p| !Path::new(p).is_absolute())
+        {
+            anyhow::bail!("localTools.shellAllowlist 需为绝对路径: {}", program);
+        }
+        let client = client_builder(proxy, config.timeout_secs, tls_backend)?
+            .redirect(Policy::none())
+            .build()?;
+        Ok(Self { config, client })
+    }
+
+    /// 生成客户端可用的工具定义
+    pub fn definitions(&self, allowed: &[LocalToolKind]) -> Vec<Tool> {
+        allowed.iter().map(|k| k.definition()).collect()
+    }
+
+    /// 执行工具
+    ///
+    /// `allowed` 为当前客户端被授予的工具，未授权的调用会返回错误结果而不是执行
+    pub async fn execute(
+        &self,
+        allowed: &[LocalToolKind],
+        name: &str,
+        input: &serde_json::Value,
+    ) -> ToolOutcome {
+        let Some(kind) = LocalToolKind::from_tool_name(name) else {
+            return ToolOutcome::error(format!("未知的本地工具: {}", name));
+        };
+        if !allowed.contains(&kind) {
+            return ToolOutcome::error(format!("当前客户端无权使用工具: {}", name));
+        }
+
+        let arg = |key: &str| input.get(key).and_then(|v| v.as_str()).unwrap_or_default();
+        let timeout = Duration::from_secs(self.config.timeout_secs);
+
+        let result = match kind {
+            LocalToolKind::FsRead => tokio::time::timeout(timeout, self.fs_read(arg("path"))).await,
+            LocalToolKind::Shell => tokio::time::timeout(timeout, self.shell(arg("command"))).await,
+            LocalToolKind::HttpFetch => {
+                tokio::time::timeout(timeout, self.http_fetch(arg("url"))).await
+            }
+        };
+
+        match result {
+            Ok(Ok(output)) => ToolOutcome::ok(self.truncate(output)),
+            Ok(Err(e)) => ToolOutcome::error(e.to_string()),
+            Err(_) => ToolOutcome::error(format!(
+                "工具执行超时（{} 秒）",
+                self.config.timeout_secs
+            )),
+        }
+    }
+
+    /// 沙箱根目录（已规范化）
+    fn root(&self) -> anyhow::Result<PathBuf> {
+        let root = self
+            .config
+            .fs_root
+            .as_deref()
+            .ok_or_else(|| anyhow::anyhow!("未配置 localTools.fsRoot"))?;
+        Ok(Path::new(root).canonicalize()?)
+    }
+
+    /// 将相对路径解析到沙箱内，越界时返回错误
+    fn resolve_path(&self, path: &str) -> anyhow::Result<PathBuf> {
+        let root = self.root()?;
+        let resolved = root.join(path.trim_start_matches('/')).canonicalize()?;
+        if !resolved.starts_with(&root) {
+            anyhow::bail!("路径超出沙箱目录: {}", path);
+        }
+        Ok(resolved)
+    }
+
+    async fn fs_read(&self, path: &str) -> anyhow::Result<String> {
+        let path = self.resolve_path(path)?;
+        // 只读取到输出上限，截断由 execute 统一处理
+        let file = tokio::fs::File::open(&path).await?;
+        let mut bytes = Vec::new();
+        file.take(self.read_limit()).read_to_end(&mut bytes).await?;
+        Ok(String::from_utf8_lossy(&bytes).into_owned())
+    }
+
+    /// 读取输出的字节上限（多读一个字节，以便 execute 判断是否需要截断）
+    fn read_limit(&self) -> u64 {
+        self.config.max_output_bytes as u64 + 1
+    }
+
+    /// 按程序名（或完整路径）查找白名单中的绝对路径
+    fn resolve_program(&self, program: &str) -> anyhow::Result<PathBuf> {
+        self.config
+            .shell_allowlist
+            .iter()
+            .map(Path::new)
+            .find(|path| {
+                path.as_os_str() == program
+                    || (!program.contains('/') && path.file_name().is_some_and(|n| n == program))
+            })
+            .map(Path::to_path_buf)
+            .ok_or_else(|| anyhow::anyhow!("命令不在白名单中: {}", program))
+    }
+
+    /// 校验命令参数：绝对路径、`~` 开头或含 `..` 的参数被拒绝，指向的路径不得越出沙箱
+    ///
+    /// `--name=value` 形式的选项校验 `=` 之后的值；短选项的值可能紧跟在选项后（如 `-f/etc/passwd`），
+    /// 因此其余选项中不允许出现 `/`、`~` 或 `..`
+    fn check_arg(&self, root: &Path, arg: &str) -> anyhow::Result<()> {
+        let value = match arg.split_once('=') {
+            Some((flag, value)) if flag.starts_with("--") => value,
+            _ if arg.starts_with('-') => {
+                if arg.contains(['/', '~']) || arg.contains("..") {
+                    anyhow::bail!("参数超出沙箱目录: {}", arg);
+                }
+                return Ok(());
+            }
+            _ => arg,
+        };
+        if value.starts_with('/')
+            || value.starts_with('~')
+            || Path::new(value)
+                .components()
+                .any(|c| c == std::path::Component::ParentDir)
+        {
+            anyhow::bail!("参数超出沙箱目录: {}", arg);
+        }
+        // 尚不存在的路径（如写入目标）按最近的已存在上级目录校验，防止经由符号链接目录越界
+        let mut path = root.join(value);
+        while path.symlink_metadata().is_err() && path.pop() {}
+        if !path.canonicalize()?.starts_with(root) {
+            anyhow::bail!("参数超出沙箱目录: {}", arg);
+        }
+        Ok(())
+    }
+
+    async fn shell(&self, command: &str) -> anyhow::Result<String> {
+        let mut parts = command.split_whitespace();
+        let program = parts
+            .next()
+            .ok_or_else(|| anyhow::anyhow!("命令为空"))?;
+        let program = self.resolve_program(program)?;
+        let root = self.root()?;
+        let args: Vec<&str> = parts.collect();
+        for arg in &args {
+            self.check_arg(&root, arg)?;
+        }
+
+        // 子进程不继承 PATH，白名单中的程序无法再按名称启动其他命令（如 find -exec）
+        let mut child = tokio::process::Command::new(program)
+            .args(args)
+            .current_dir(root)
+            .env_clear()
+            .env("PATH", "")
+            .stdin(std::process::Stdio::null())
+            .stdout(std::process::Stdio::piped())
+            .stderr(std::process::Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()?;
+
+        // 各自只读取到输出上限，读取结束后关闭管道，避免子进程阻塞在写入上
+        let limit = self.read_limit();
+        let (stdout, stderr) = tokio::try_join!(
+            read_capped(child.stdout.take(), limit),
+            read_capped(child.stderr.take(), limit),
+        )?;
+
+        let mut text = String::from_utf8_lossy(&stdout).into_owned();
+        if !stderr.is_empty() {
+            text.push_str("\n[stderr]\n");
+            text.push_str(&String::from_utf8_lossy(&stderr));
+        }
+        // 输出超过上限时不再等待命令结束
+        if stdout.len() as u64 >= limit || stderr.len() as u64 >= limit {
+            let _ = child.start_kill();
+            return Ok(text);
+        }
+        let status = child.wait().await?;
+        if !status.success() {
+            anyhow::bail!("命令退出码 {}: {}", status, text);
+        }
+        Ok(text)
+    }
+
+    async fn http_fetch(&self, url: &str) -> anyhow::Result<String> {
+        let parsed = reqwest::Url::parse(url)?;
+        if !matches!(parsed.scheme(), "http" | "https") {
+            anyhow::bail!("仅支持 http/https: {}", url);
+        }
+        let host = parsed.host_str().unwrap_or_default();
+        if !self.config.http_allowed_hosts.iter().any(|h| h == host) {
+            anyhow::bail!("域名不在允许列表中: {}", host);
+        }
+
+        let mut response = self.client.get(parsed).send().await?;
+        let status = response.status();
+        // 超过输出上限后停止读取，截断由 execute 统一处理
+        let mut body = Vec::new();
+        while body.len() <= self.config.max_output_bytes
+            && let Some(chunk) = response.chunk().await?
+        {
+            body.extend_from_slice(&chunk);
+        }
+        let body = String::from_utf8_lossy(&body).into_owned();
+        if !status.is_success() {
+            anyhow::bail!("HTTP {}: {}", status, self.truncate(body));
+        }
+        Ok(body)
+    }
+
+    /// 按字节上限截断输出（保证 UTF-8 边界）
+    fn truncate(&self, mut text: String) -> String {
+        let max = self.config.max_output_bytes;
+        if text.len() > max {
+            let mut end = max;
+            while !text.is_char_boundary(end) {
+                end -= 1;
+            }
+            text.truncate(end);
+            text.push_str("\n[output truncated]");
+        }
+        text
+    }
+}
+
+/// 读取管道直到结束或达到上限，返回后管道随之关闭
+async fn read_capped(pipe: Option<impl AsyncRead + Unpin>, limit: u64) -> std::io::Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    if let Some(pipe) = pipe {
+        pipe.take(limit).read_to_end(&mut bytes).await?;
+    }
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn runner(root: &Path) -> LocalToolRunner {
+        let config = LocalToolsConfig {
+            enabled: true,
+            fs_root: Some(root.to_string_lossy().into_owned()),
+            shell_allowlist: vec!["/bin/echo".to_string(), "/bin/cat".to_string()],
+            max_output_bytes: 8,
+            ..Default::default()
+        };
+        LocalToolRunner::new(config, None, TlsBackend::Rustls).unwrap()
+    }
+
+    fn sandbox() -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("local-tools-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_is_local_tool() {
+        assert!(is_local_tool("local_fs_read"));
+        assert!(is_local_tool("local_shell"));
+        assert!(!is_local_tool("local_unknown"));
+        assert!(!is_local_tool("web_search"));
+    }
+
+    #[tokio::test]
+    async fn test_fs_read_inside_and_outside_root() {
+        let dir = sandbox();
+        std::fs::write(dir.join("a.txt"), "hello").unwrap();
+        let runner = runner(&dir);
+        let allowed = [LocalToolKind::FsRead];
+
+        let ok = runner
+            .execute(&allowed, "local_fs_read", &json!({"path": "a.txt"}))
+            .await;
+        assert_eq!(ok, ToolOutcome::ok("hello"));
+
+        let escaped = runner
+            .execute(&allowed, "local_fs_read", &json!({"path": "../"}))
+            .await;
+        assert!(escaped.is_error);
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn test_permission_and_allowlist_enforced() {
+        let dir = sandbox();
+        let runner = runner(&dir);
+
+        let denied = runner
+            .execute(&[], "local_shell", &json!({"command": "echo hi"}))
+            .await;
+        assert!(denied.is_error);
+
+        let not_allowlisted = runner
+            .execute(
+                &[LocalToolKind::Shell],
+                "local_shell",
+                &json!({"command": "rm -rf x"}),
+            )
+            .await;
+        assert!(not_allowlisted.is_error);
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn test_shell_arguments_confined_to_root() {
+        let dir = sandbox();
+        std::fs::write(dir.join("a.txt"), "hello").unwrap();
+        let runner = &runner(&dir);
+        let shell = |command: &str| {
+            let input = json!({ "command": command });
+            async move {
+                runner
+                    .execute(&[LocalToolKind::Shell], "local_shell", &input)
+                    .await
+            }
+        };
+
+        assert_eq!(shell("cat a.txt").await, ToolOutcome::ok("hello"));
+        for command in [
+            "cat /etc/passwd",
+            "cat ../a.txt",
+            "cat --file=/etc/passwd",
+            "cat -f/etc/passwd",
+            "cat -o~/out",
+            "cat -fsub/../../a.txt",
+            "/usr/bin/cat a.txt",
+        ] {
+            let outcome = shell(command).await;
+            assert!(outcome.is_error, "{} 应被拒绝", command);
+        }
+
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink("/etc", dir.join("etc")).unwrap();
+            assert!(shell("cat etc/passwd").await.is_error);
+            // 尚不存在的写入目标按所在目录校验
+            assert!(shell("echo etc/new-file").await.is_error);
+            assert!(shell("echo --out=etc/new-file").await.is_error);
+            assert_eq!(shell("echo new").await, ToolOutcome::ok("new\n"));
+        }
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn test_fs_read_and_shell_stop_reading_at_output_limit() {
+        let dir = sandbox();
+        std::fs::write(dir.join("big.txt"), "x".repeat(1 << 20)).unwrap();
+        let runner = runner(&dir);
+        let truncated = ToolOutcome::ok("xxxxxxxx\n[output truncated]");
+
+        let read = runner
+            .execute(
+                &[LocalToolKind::FsRead],
+                "local_fs_read",
+                &json!({"path": "big.txt"}),
+            )
+            .await;
+        assert_eq!(read, truncated);
+        let shell = runner
+            .execute(
+                &[LocalToolKind::Shell],
+                "local_shell",
+                &json!({"command": "cat big.txt"}),
+            )
+            .await;
+        assert_eq!(shell, truncated);
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_allowlist_requires_absolute_paths() {
+        let config = LocalToolsConfig {
+            shell_allowlist: vec!["cat".to_string()],
+            ..Default::default()
+        };
+        assert!(LocalToolRunner::new(config, None, TlsBackend::Rustls).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_http_fetch_stops_reading_at_output_limit() {
+        use tokio::io::AsyncWriteExt;
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let header = "HTTP/1.1 200 OK\r\nContent-Type: text/plain\r\n\r\n";
+            let _ = socket.write_all(header.as_bytes()).await;
+            // 不声明长度、一直写入：读取方必须在上限处停止
+            let chunk = vec![b'x'; 1024];
+            while socket.write_all(&chunk).await.is_ok() {}
+        });
+
+        let dir = sandbox();
+        let config = LocalToolsConfig {
+            enabled: true,
+            fs_root: Some(dir.to_string_lossy().into_owned()),
+            http_allowed_hosts: vec!["127.0.0.1".to_string()],
+            max_output_bytes: 8,
+            ..Default::default()
+        };
+        let runner = LocalToolRunner::new(config, None, TlsBackend::Rustls).unwrap();
+        let outcome = runner
+            .execute(
+                &[LocalToolKind::HttpFetch],
+                "local_http_fetch",
+                &json!({ "url": format!("http://{}/", addr) }),
+            )
+            .await;
+        assert_eq!(outcome, ToolOutcome::ok("xxxxxxxx\n[output truncated]"));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn test_http_fetch_requires_allowed_host() {
+        let dir = sandbox();
+        let runner = runner(&dir);
+        let outcome = runner
+            .execute(
+                &[LocalToolKind::HttpFetch],
+                "local_http_fetch",
+                &json!({"url": "https://example.com/"}),
+            )
+            .await;
+        assert!(outcome.is_error);
+        assert!(outcome.content.contains("example.com"));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_truncate_respects_char_boundary() {
+        let dir = sandbox();
+        let runner = runner(&dir);
+        let truncated = runner.truncate("你好世界".to_string());
+        assert!(truncated.starts_with("你好"));
+        assert!(truncated.ends_with("[output truncated]"));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -1,20 +1,126 @@
 //! Anthropic API 中间件
 
+use std::collections::HashMap;
 use std::sync::Arc;
 
 use axum::{
     body::Body,
     extract::State,
-    http::{Request, StatusCode},
+    http::{Request, StatusCode, header},
     middleware::Next,
     response::{IntoResponse, Json, Response},
 };
 
+use chrono::{DateTime, Datelike, FixedOffset, NaiveTime, Utc};
+use futures::{StreamExt, stream};
+
+use crate::common::abuse::AbuseGuard;
+use crate::common::admission::AdmissionQueue;
 use crate::common::auth;
+use crate::common::client_keys::ClientKeyStore;
+use crate::common::drain::DrainTracker;
+use crate::common::in_flight::{self, InFlightRequests};
+use crate::common::load::LoadTracker;
+use crate::common::maintenance::MaintenanceMode;
+use crate::common::session_token::{self, SessionTokenIssuer};
+use crate::common::slo::SloTracker;
+use crate::common::usage::UsageTracker;
+use crate::kiro::events::TokenEventCounters;
 use crate::kiro::provider::KiroProvider;
+use crate::model::config::{
+    AccessWindow, ClientKeyConfig, ClientKeyScopes, LocalToolKind, LogContentLimit, PriorityClass,
+    RequestDefaults, ResponseCacheMode, StreamPolicy,
+};
 
+use super::artifacts::ArtifactStore;
+use super::coalesce::Coalescer;
+use super::compression::ContextCompressor;
+use super::context_routing::ContextRouter;
+use super::cost::CostTable;
+use super::filters::{FilterChain, FilterHook};
+use super::forward_headers::HeaderForwarder;
+use super::local_tools::LocalToolRunner;
+use super::presets::RequestPresets;
+use super::response_cache::ResponseCache;
 use super::types::ErrorResponse;
 
+/// 已认证的客户端身份（由认证中间件写入请求扩展）
+#[derive(Debug, Clone)]
+pub struct ClientIdentity {
+    /// 客户端名称（全局 apiKey 为 "default"）
+    pub name: String,
+    /// 允许代理代为执行的本地工具
+    pub local_tools: Vec<LocalToolKind>,
+    /// 流式策略
+    pub stream_policy: StreamPolicy,
+    /// 使用范围限制
+    pub scopes: ClientKeyScopes,
+    /// 默认生成参数
+    pub defaults: RequestDefaults,
+    /// 准入队列中的优先级类别
+    pub priority: PriorityClass,
+    /// 非流式请求的最长时长（秒），None 时使用 `nonStream.maxDurationSecs`
+    pub max_duration_secs: Option<u64>,
+    /// 流式请求的最长时长（秒），None 时使用 `streaming.maxDurationSecs`
+    pub stream_max_duration_secs: Option<u64>,
+    /// 请求预设名称
+    pub preset: Option<String>,
+    /// 非流式响应缓存
+    pub response_cache: ResponseCacheMode,
+    /// 日志中保留的内容长度（None 时使用 `logContent.maxChars`）
+    pub log_content: Option<LogContentLimit>,
+    /// 允许换取短期会话 Token
+    pub session_tokens: bool,
+    /// 通过会话 Token 认证（不能再换取新的 Token）
+    pub via_session_token: bool,
+    /// 全局 apiKey 对应的默认客户端（与名为 `default` 的客户端密钥区分）
+    pub global: bool,
+}
+
+impl ClientIdentity {
+    /// 全局 apiKey 对应的默认客户端（不授予额外权限）
+    fn default_client() -> Self {
+        Self {
+            name: "default".to_string(),
+            local_tools: Vec::new(),
+            stream_policy: StreamPolicy::default(),
+            scopes: ClientKeyScopes::default(),
+            defaults: RequestDefaults::default(),
+            priority: PriorityClass::default(),
+            max_duration_secs: None,
+            stream_max_duration_secs: None,
+            preset: None,
+            response_cache: ResponseCacheMode::default(),
+            log_content: None,
+            session_tokens: true,
+            via_session_token: false,
+            global: true,
+        }
+    }
+
+    fn from_client_key(index: usize, key: &ClientKeyConfig) -> Self {
+        Self {
+            name: key
+                .name
+                .clone()
+                .unwrap_or_else(|| format!("client-{}", index + 1)),
+            local_tools: key.local_tools.clone(),
+            stream_policy: key.stream_policy,
+            scopes: key.scopes.clone(),
+            defaults: key.defaults.clone(),
+            priority: key.priority,
+            max_duration_secs: key.max_duration_secs,
+            stream_max_duration_secs: key.stream_max_duration_secs,
+            preset: key.preset.clone(),
+            response_cache: key.response_cache,
+            log_content: key.log_content,
+            session_tokens: key.session_tokens,
+            via_session_token: false,
+            global: false,
+        }
+    }
+}
+
 /// 应用共享状态
 #[derive(Clone)]
 pub struct AppState {
@@ -25,6 +131,50 @@ pub struct AppState {
     pub kiro_provider: Option<Arc<KiroProvider>>,
     /// Profile ARN（可选，用于请求）
     pub profile_arn: Option<String>,
+    /// 滥用检测器（可选，与 Admin API 共享）
+    pub abuse_guard: Option<Arc<AbuseGuard>>,
+    /// 额外的客户端 API Key（配置文件中的与通过 Admin API 开通的）
+    pub client_keys: Arc<ClientKeyStore>,
+    /// 本地工具执行器（可选，启用 localTools 时存在）
+    pub local_tools: Option<Arc<LocalToolRunner>>,
+    /// 上游文件存储（可选，启用 artifacts 时存在）
+    pub artifacts: Option<Arc<ArtifactStore>>,
+    /// 负载统计（可选，与 KiroProvider 共享）
+    pub load: Option<Arc<LoadTracker>>,
+    /// 用量统计（可选，与 Admin API 共享）
+    pub usage: Option<Arc<UsageTracker>>,
+    /// SLO 跟踪（可选，启用 slo 时存在，与 Admin API 共享）
+    pub slo: Option<Arc<SloTracker>>,
+    /// 下游连接写出统计（可选，与 Admin API 共享）
+    pub drain: Option<Arc<DrainTracker>>,
+    /// 进行中的请求（可选，与 Admin API 共享）
+    pub in_flight: Option<Arc<InFlightRequests>>,
+    /// 请求/响应过滤器
+    pub filters: Arc<FilterChain>,
+    /// 并发准入队列（可选，配置了 admission.maxConcurrent 时存在）
+    pub admission: Option<Arc<AdmissionQueue>>,
+    /// 维护模式（可选，与 Admin API 共享）
+    pub maintenance: Option<Arc<MaintenanceMode>>,
+    /// 凭据事件计数（可选，订阅 MultiTokenManager 的事件）
+    pub token_events: Option<Arc<TokenEventCounters>>,
+    /// 上下文长度路由（可选，启用 contextRouting 时存在）
+    pub context_router: Option<Arc<ContextRouter>>,
+    /// 摘要式上下文压缩（可选，启用 contextCompression 时存在）
+    pub context_compressor: Option<Arc<ContextCompressor>>,
+    /// 模型成本表（可选，启用 costEstimation 时存在）
+    pub cost_table: Option<Arc<CostTable>>,
+    /// 上游响应头透传（可选，配置 forwardHeaders 时存在）
+    pub header_forwarder: Option<Arc<HeaderForwarder>>,
+    /// 非流式响应缓存（可选，启用 responseCache 时存在）
+    pub response_cache: Option<Arc<ResponseCache>>,
+    /// 相同流式请求合并（可选，启用 coalesce 时存在）
+    pub coalescer: Option<Arc<Coalescer>>,
+    /// 请求预设（客户端密钥用 `preset` 选择）
+    pub presets: Option<Arc<RequestPresets>>,
+    /// 会话 Token 签发与校验（可选，启用 sessionTokens 时存在）
+    pub session_tokens: Option<Arc<SessionTokenIssuer>>,
+    /// Azure OpenAI 兼容路径的部署名 -> 模型名
+    pub azure_deployments: Arc<HashMap<String, String>>,
 }
 
 impl AppState {
@@ -34,6 +184,28 @@ impl AppState {
             api_key: api_key.into(),
             kiro_provider: None,
             profile_arn: None,
+            abuse_guard: None,
+            client_keys: Arc::new(ClientKeyStore::new(Vec::new())),
+            local_tools: None,
+            artifacts: None,
+            load: None,
+            usage: None,
+            slo: None,
+            drain: None,
+            in_flight: None,
+            filters: Arc::new(FilterChain::default()),
+            admission: None,
+            maintenance: None,
+            token_events: None,
+            context_router: None,
+            context_compressor: None,
+            cost_table: None,
+            header_forwarder: None,
+            response_cache: None,
+            coalescer: None,
+            presets: None,
+            session_tokens: None,
+            azure_deployments: Arc::new(HashMap::new()),
         }
     }
 
@@ -48,21 +220,597 @@ impl AppState {
         self.profile_arn = Some(arn.into());
         self
     }
+
+    /// 设置滥用检测器
+    pub fn with_abuse_guard(mut self, guard: Arc<AbuseGuard>) -> Self {
+        self.abuse_guard = Some(guard);
+        self
+    }
+
+    /// 设置额外的客户端 API Key（开通的密钥不持久化）
+    #[allow(dead_code)]
+    pub fn with_client_keys(mut self, keys: Vec<ClientKeyConfig>) -> Self {
+        self.client_keys = Arc::new(ClientKeyStore::new(keys));
+        self
+    }
+
+    /// 设置客户端密钥存储（与 Admin API 共享）
+    pub fn with_client_key_store(mut self, store: Arc<ClientKeyStore>) -> Self {
+        self.client_keys = store;
+        self
+    }
+
+    /// 设置本地工具执行器
+    pub fn with_local_tools(mut self, runner: LocalToolRunner) -> Self {
+        self.local_tools = Some(Arc::new(runner));
+        self
+    }
+
+    /// 设置上游文件存储
+    pub fn with_artifacts(mut self, store: ArtifactStore) -> Self {
+        self.artifacts = Some(Arc::new(store));
+        self
+    }
+
+    /// 设置负载统计
+    pub fn with_load_tracker(mut self, load: Arc<LoadTracker>) -> Self {
+        self.load = Some(load);
+        self
+    }
+
+    /// 设置用量统计
+    pub fn with_usage_tracker(mut self, usage: Arc<UsageTracker>) -> Self {
+        self.usage = Some(usage);
+        self
+    }
+
+    /// 设置 SLO 跟踪
+    pub fn with_slo(mut self, slo: Arc<SloTracker>) -> Self {
+        self.slo = Some(slo);
+        self
+    }
+
+    /// 设置下游连接写出统计
+    pub fn with_drain_tracker(mut self, drain: Arc<DrainTracker>) -> Self {
+        self.drain = Some(drain);
+        self
+    }
+
+    /// 设置进行中的请求登记表
+    pub fn with_in_flight(mut self, in_flight: Arc<InFlightRequests>) -> Self {
+        self.in_flight = Some(in_flight);
+        self
+    }
+
+    /// 设置并发准入队列
+    pub fn with_admission(mut self, admission: Arc<AdmissionQueue>) -> Self {
+        self.admission = Some(admission);
+        self
+    }
+
+    /// 设置维护模式
+    pub fn with_maintenance(mut self, maintenance: Arc<MaintenanceMode>) -> Self {
+        self.maintenance = Some(maintenance);
+        self
+    }
+
+    /// 设置凭据事件计数
+    pub fn with_token_events(mut self, counters: Arc<TokenEventCounters>) -> Self {
+        self.token_events = Some(counters);
+        self
+    }
+
+    /// 设置上下文长度路由
+    pub fn with_context_router(mut self, router: Arc<ContextRouter>) -> Self {
+        self.context_router = Some(router);
+        self
+    }
+
+    /// 设置摘要式上下文压缩
+    pub fn with_context_compressor(mut self, compressor: Arc<ContextCompressor>) -> Self {
+        self.context_compressor = Some(compressor);
+        self
+    }
+
+    /// 设置请求预设
+    pub fn with_presets(mut self, presets: Arc<RequestPresets>) -> Self {
+        self.presets = Some(presets);
+        self
+    }
+
+    /// 设置模型成本表
+    pub fn with_cost_table(mut self, table: Arc<CostTable>) -> Self {
+        self.cost_table = Some(table);
+        self
+    }
+
+    /// 设置上游响应头透传
+    pub fn with_header_forwarder(mut self, forwarder: Arc<HeaderForwarder>) -> Self {
+        self.header_forwarder = Some(forwarder);
+        self
+    }
+
+    /// 设置非流式响应缓存
+    pub fn with_response_cache(mut self, cache: Arc<ResponseCache>) -> Self {
+        self.response_cache = Some(cache);
+        self
+    }
+
+    /// 设置相同流式请求合并
+    pub fn with_coalescer(mut self, coalescer: Arc<Coalescer>) -> Self {
+        self.coalescer = Some(coalescer);
+        self
+    }
+
+    /// 设置会话 Token 签发与校验
+    pub fn with_session_tokens(mut self, issuer: Arc<SessionTokenIssuer>) -> Self {
+        self.session_tokens = Some(issuer);
+        self
+    }
+
+    /// 设置 Azure OpenAI 部署名映射
+    pub fn with_azure_deployments(mut self, deployments: HashMap<String, String>) -> Self {
+        self.azure_deployments = Arc::new(deployments);
+        self
+    }
+
+    /// 设置请求/响应过滤器
+    pub fn with_filters(mut self, filters: FilterChain) -> Self {
+        self.filters = Arc::new(filters);
+        self
+    }
+
+    /// 根据 API Key 识别客户端，并检查客户端密钥的有效期与访问时段
+    fn identify(&self, key: &str, now: DateTime<Utc>) -> Result<ClientIdentity, AuthRejection> {
+        if auth::constant_time_eq(key, &self.api_key) {
+            return Ok(ClientIdentity::default_client());
+        }
+        if let Some(issuer) = &self.session_tokens
+            && session_token::looks_like_token(key)
+        {
+            return self.identify_session(issuer, key, now);
+        }
+        let (index, client) = self
+            .client_keys
+            .find(key)
+            .ok_or(AuthRejection::InvalidKey)?;
+        self.admit_client_key(index, &client, now)
+    }
+
+    /// 会话 Token：校验后沿用签发它的客户端身份，并按声明收窄使用范围
+    fn identify_session(
+        &self,
+        issuer: &SessionTokenIssuer,
+        token: &str,
+        now: DateTime<Utc>,
+    ) -> Result<ClientIdentity, AuthRejection> {
+        let claims = issuer
+            .verify(token, now.timestamp())
+            .ok_or(AuthRejection::InvalidKey)?;
+        let mut identity = if claims.global {
+            // 全局 apiKey 轮换后拒绝
+            if claims.kid != session_token::key_fingerprint(&self.api_key) {
+                return Err(AuthRejection::InvalidKey);
+            }
+            ClientIdentity::default_client()
+        } else {
+            // 找不到签发它的密钥（已吊销或改名），或同名密钥已被替换时拒绝
+            let (index, client) = self
+                .client_keys
+                .find_by_name(&claims.sub)
+                .filter(|(_, client)| session_token::key_fingerprint(&client.key) == claims.kid)
+                .ok_or(AuthRejection::InvalidKey)?;
+            self.admit_client_key(index, &client, now)?
+        };
+        if !identity.session_tokens {
+            return Err(AuthRejection::InvalidKey);
+        }
+        identity.via_session_token = true;
+        identity.session_tokens = false;
+        identity.local_tools.clear();
+        // 原密钥的模型范围签发后可能又被收窄，取两者的交集
+        identity.scopes.models = session_token::intersect_models(
+            &claims.models,
+            &identity.scopes.models,
+        )
+        .ok_or_else(|| {
+            AuthRejection::Denied("会话 Token 的模型范围已超出原 API Key 的使用范围".to_string())
+        })?;
+        if let Some(max_tokens) = claims.max_tokens {
+            identity.scopes.max_tokens = Some(
+                identity
+                    .scopes
+                    .max_tokens
+                    .map_or(max_tokens, |limit| limit.min(max_tokens)),
+            );
+        }
+        Ok(identity)
+    }
+
+    /// 当前身份所用密钥的指纹（写入会话 Token 的 `kid`，与 `identify_session` 的查找方式一致）
+    pub fn key_fingerprint(&self, identity: &ClientIdentity) -> Option<String> {
+        if identity.global {
+            return Some(session_token::key_fingerprint(&self.api_key));
+        }
+        self.client_keys
+            .find_by_name(&identity.name)
+            .map(|(_, client)| session_token::key_fingerprint(&client.key))
+    }
+
+    /// 检查客户端密钥的有效期与访问时段
+    fn admit_client_key(
+        &self,
+        index: usize,
+        client: &ClientKeyConfig,
+        now: DateTime<Utc>,
+    ) -> Result<ClientIdentity, AuthRejection> {
+        let identity = ClientIdentity::from_client_key(index, client);
+        match access_denial(client, now) {
+            Some(reason) => {
+                tracing::info!("拒绝客户端 {} 的请求: {}", identity.name, reason);
+                Err(AuthRejection::Denied(reason))
+            }
+            None => Ok(identity),
+        }
+    }
+}
+
+/// 认证失败原因
+#[derive(Debug, PartialEq, Eq)]
+enum AuthRejection {
+    /// 缺少或无效的 API Key
+    InvalidKey,
+    /// 密钥有效，但不在有效期或访问时段内
+    Denied(String),
+}
+
+/// 检查客户端密钥在 `now` 是否可用，不可用时返回原因
+fn access_denial(client: &ClientKeyConfig, now: DateTime<Utc>) -> Option<String> {
+    if let Some(not_before) = client.not_before
+        && now < not_before
+    {
+        return Some(format!("API Key 将于 {} 生效", not_before.to_rfc3339()));
+    }
+    if let Some(expires_at) = client.expires_at
+        && now >= expires_at
+    {
+        return Some(format!("API Key 已于 {} 过期", expires_at.to_rfc3339()));
+    }
+    if client.access_windows.is_empty()
+        || client
+            .access_windows
+            .iter()
+            .any(|w| window_contains(w, now))
+    {
+        return None;
+    }
+    let windows: Vec<String> = client
+        .access_windows
+        .iter()
+        .map(|w| {
+            let days = if w.days.is_empty() {
+                "每天".to_string()
+            } else {
+                w.days
+                    .iter()
+                    .map(|d| d.to_string())
+                    .collect::<Vec<_>>()
+                    .join(",")
+            };
+            format!("{} {}-{} (UTC{})", days, w.start, w.end, w.utc_offset)
+        })
+        .collect();
+    Some(format!(
+        "当前时间不在 API Key 允许的访问时段内（{}）",
+        windows.join("; ")
+    ))
+}
+
+/// 判断时间是否落在访问时段内（配置无法解析时视为不匹配）
+fn window_contains(window: &AccessWindow, now: DateTime<Utc>) -> bool {
+    let parsed = (
+        window.utc_offset.parse::<FixedOffset>(),
+        NaiveTime::parse_from_str(&window.start, "%H:%M"),
+        NaiveTime::parse_from_str(&window.end, "%H:%M"),
+    );
+    let (Ok(offset), Ok(start), Ok(end)) = parsed else {
+        tracing::warn!(
+            "无法解析访问时段 {}-{} (UTC{})，按不在时段内处理",
+            window.start,
+            window.end,
+            window.utc_offset
+        );
+        return false;
+    };
+
+    let local = now.with_timezone(&offset);
+    let time = local.time();
+    // 跨午夜时段（如 22:00-06:00）的凌晨部分属于前一天开始的时段
+    let (in_time, day) = if start <= end {
+        (time >= start && time < end, local.weekday())
+    } else if time >= start {
+        (true, local.weekday())
+    } else {
+        (time < end, local.weekday().pred())
+    };
+    in_time && (window.days.is_empty() || window.days.contains(&day))
 }
 
 /// API Key 认证中间件
+///
+/// 认证通过后将 `ClientIdentity` 写入请求扩展，供 handler 做权限判断
 pub async fn auth_middleware(
     State(state): State<AppState>,
-    request: Request<Body>,
+    mut request: Request<Body>,
     next: Next,
 ) -> Response {
-    match auth::extract_api_key(&request) {
-        Some(key) if auth::constant_time_eq(&key, &state.api_key) => next.run(request).await,
-        _ => {
+    let identity = auth::extract_api_key(&request)
+        .ok_or(AuthRejection::InvalidKey)
+        .and_then(|key| state.identify(&key, Utc::now()));
+    match identity {
+        Ok(identity) => {
+            request.extensions_mut().insert(identity);
+            next.run(request).await
+        }
+        Err(AuthRejection::InvalidKey) => {
             let error = ErrorResponse::authentication_error();
             (StatusCode::UNAUTHORIZED, Json(error)).into_response()
         }
+        Err(AuthRejection::Denied(reason)) => {
+            let error = ErrorResponse::new("permission_error", reason);
+            (StatusCode::FORBIDDEN, Json(error)).into_response()
+        }
+    }
+}
+
+/// 负载统计中间件
+///
+/// 请求进入时计入 in-flight，响应体发送完毕（流式请求为流结束或客户端断开）后减一
+pub async fn load_middleware(
+    State(state): State<AppState>,
+    request: Request<Body>,
+    next: Next,
+) -> Response {
+    let Some(load) = &state.load else {
+        return next.run(request).await;
+    };
+    let guard = load.start_request();
+    let response = next.run(request).await;
+    response.map(|body| {
+        Body::from_stream(body.into_data_stream().map(move |chunk| {
+            let _ = &guard;
+            chunk
+        }))
+    })
+}
+
+/// 下游写出统计中间件
+///
+/// 记录响应块交给 hyper 到下一次轮询之间的等待，客户端读得慢时这段等待变长。
+/// 需位于认证中间件之内（读取 `ClientIdentity`）
+pub async fn drain_middleware(
+    State(state): State<AppState>,
+    request: Request<Body>,
+    next: Next,
+) -> Response {
+    let Some(drain) = &state.drain else {
+        return next.run(request).await;
+    };
+    let client = request
+        .extensions()
+        .get::<ClientIdentity>()
+        .map(|identity| identity.name.clone())
+        .unwrap_or_default();
+    let handle = drain.open(client, request.uri().path());
+    let response = next.run(request).await;
+    response.map(|body| {
+        Body::from_stream(stream::unfold(
+            (body.into_data_stream(), handle),
+            |(mut body, handle)| async move {
+                handle.polled();
+                let chunk = body.next().await?;
+                if let Ok(bytes) = &chunk {
+                    handle.written(bytes.len());
+                }
+                Some((chunk, (body, handle)))
+            },
+        ))
+    })
+}
+
+/// 进行中请求登记中间件
+///
+/// 为生成类请求分配请求 ID（响应头 `x-kiro-request-id`），Admin 中止时丢弃处理中的 future
+/// （尚未返回响应时返回 503），或在流式响应中发送 SSE error 事件后结束流；两种情况都会断开上游调用。
+/// 需位于认证中间件之内（读取 `ClientIdentity`）
+pub async fn in_flight_middleware(
+    State(state): State<AppState>,
+    request: Request<Body>,
+    next: Next,
+) -> Response {
+    let Some(in_flight) = &state.in_flight else {
+        return next.run(request).await;
+    };
+    if !is_generation_request(&request) {
+        return next.run(request).await;
+    }
+    let client = request
+        .extensions()
+        .get::<ClientIdentity>()
+        .map(|identity| identity.name.clone())
+        .unwrap_or_default();
+    let handle = in_flight.register(client, request.method().as_str(), request.uri().path());
+    let request_id = handle.id();
+
+    let mut response = tokio::select! {
+        biased;
+        _ = handle.cancelled() => {
+            tracing::warn!("请求 #{} 已被管理员中止（响应返回前）", request_id);
+            let error = ErrorResponse::new("api_error", "请求已被管理员中止");
+            (StatusCode::SERVICE_UNAVAILABLE, Json(error)).into_response()
+        }
+        response = next.run(request) => response,
+    };
+    if let Ok(value) = request_id.to_string().parse() {
+        response
+            .headers_mut()
+            .insert(in_flight::REQUEST_ID_HEADER, value);
+    }
+    let is_sse = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.starts_with("text/event-stream"));
+
+    response.map(|body| {
+        Body::from_stream(stream::unfold(
+            Some((body.into_data_stream(), handle)),
+            move |state| async move {
+                let (mut body, handle) = state?;
+                tokio::select! {
+                    biased;
+                    _ = handle.cancelled() => {
+                        tracing::warn!("请求 #{} 已被管理员中止（流式响应中）", request_id);
+                        // 丢弃响应体即断开上游流
+                        drop(body);
+                        if !is_sse {
+                            return None;
+                        }
+                        let event = format!(
+                            "event: error\ndata: {}\n\n",
+                            serde_json::json!({
+                                "type": "error",
+                                "error": {"type": "api_error", "message": "请求已被管理员中止"},
+                            })
+                        );
+                        Some((Ok(axum::body::Bytes::from(event)), None))
+                    }
+                    chunk = body.next() => {
+                        let chunk = chunk?;
+                        Some((chunk, Some((body, handle))))
+                    }
+                }
+            },
+        ))
+    })
+}
+
+/// 是否为生成类请求（`POST .../messages`、`POST /v1/responses`、`POST .../chat/completions`）
+fn is_generation_request(request: &Request<Body>) -> bool {
+    let path = request.uri().path();
+    request.method() == axum::http::Method::POST
+        && (path.ends_with("/messages")
+            || path.ends_with("/responses")
+            || path.ends_with("/chat/completions"))
+}
+
+/// 维护模式中间件
+///
+/// 维护期间拒绝新请求（503 + Retry-After），已进入的请求不受影响
+pub async fn maintenance_middleware(
+    State(state): State<AppState>,
+    request: Request<Body>,
+    next: Next,
+) -> Response {
+    let Some(window) = state.maintenance.as_ref().and_then(|m| m.current()) else {
+        return next.run(request).await;
+    };
+    let error = ErrorResponse::new("service_unavailable", window.message);
+    (
+        StatusCode::SERVICE_UNAVAILABLE,
+        [(header::RETRY_AFTER, window.retry_after_secs.to_string())],
+        Json(error),
+    )
+        .into_response()
+}
+
+/// 并发准入中间件
+///
+/// 对 `POST .../messages`、`POST /v1/responses`、`POST .../chat/completions` 按客户端密钥的优先级类别排队，许可持有到响应发送完毕（流式请求持续到流结束）。
+/// 需位于认证中间件之内（读取 `ClientIdentity`）
+pub async fn admission_middleware(
+    State(state): State<AppState>,
+    request: Request<Body>,
+    next: Next,
+) -> Response {
+    let Some(admission) = &state.admission else {
+        return next.run(request).await;
+    };
+    if !is_generation_request(&request) {
+        return next.run(request).await;
+    }
+    let class = request
+        .extensions()
+        .get::<ClientIdentity>()
+        .map(|identity| identity.priority)
+        .unwrap_or_default();
+
+    let permit = match admission.acquire(class).await {
+        Ok(permit) => permit,
+        Err(e) => {
+            tracing::warn!("{} 请求排队超时: {}", class.as_str(), e);
+            let error = ErrorResponse::new("overloaded_error", e.to_string());
+            return (StatusCode::SERVICE_UNAVAILABLE, Json(error)).into_response();
+        }
+    };
+    let response = next.run(request).await;
+    response.map(|body| {
+        Body::from_stream(body.into_data_stream().map(move |chunk| {
+            let _ = &permit;
+            chunk
+        }))
+    })
+}
+
+/// 错误响应过滤的最大响应体大小
+const MAX_FILTERED_ERROR_BODY: usize = 1024 * 1024;
+
+/// 错误响应过滤中间件
+///
+/// 对 JSON 错误响应执行 `Error` 挂载点的过滤器，过滤器可改写状态码（`status`）与响应体（`body`）。
+/// 过滤器拒绝或执行失败时原样返回错误响应。
+pub async fn error_filter_middleware(
+    State(state): State<AppState>,
+    request: Request<Body>,
+    next: Next,
+) -> Response {
+    let response = next.run(request).await;
+    let status = response.status();
+    if state.filters.is_empty() || !(status.is_client_error() || status.is_server_error()) {
+        return response;
+    }
+
+    let (mut parts, body) = response.into_parts();
+    let bytes = match axum::body::to_bytes(body, MAX_FILTERED_ERROR_BODY).await {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            tracing::warn!("读取错误响应失败: {}", e);
+            return Response::from_parts(parts, Body::empty());
+        }
+    };
+    let Ok(body) = serde_json::from_slice::<serde_json::Value>(&bytes) else {
+        return Response::from_parts(parts, Body::from(bytes));
+    };
+
+    let input = serde_json::json!({"status": status.as_u16(), "body": body});
+    let output = match state.filters.apply(FilterHook::Error, &input) {
+        Ok(Some(output)) => output,
+        Ok(None) => return Response::from_parts(parts, Body::from(bytes)),
+        Err(e) => {
+            tracing::warn!("错误响应过滤失败，原样返回: {}", e);
+            return Response::from_parts(parts, Body::from(bytes));
+        }
+    };
+    if let Some(status) = output["status"]
+        .as_u64()
+        .and_then(|s| u16::try_from(s).ok())
+        .and_then(|s| StatusCode::from_u16(s).ok())
+    {
+        parts.status = status;
     }
+    let body = output.get("body").unwrap_or(&body);
+    parts.headers.remove(header::CONTENT_LENGTH);
+    Response::from_parts(parts, Body::from(body.to_string()))
 }
 
 /// CORS 中间件层
@@ -82,3 +830,307 @@ pub fn cors_layer() -> tower_http::cors::CorsLayer {
         .allow_methods(Any)
         .allow_headers(Any)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_identify_client_keys() {
+        let state = AppState::new("sk-main").with_client_keys(vec![
+            ClientKeyConfig {
+                key: "sk-agent".to_string(),
+                name: Some("agent".to_string()),
+                local_tools: vec![LocalToolKind::FsRead],
+                stream_policy: StreamPolicy::Force,
+                not_before: None,
+                expires_at: None,
+                access_windows: vec![],
+                scopes: ClientKeyScopes::default(),
+                defaults: RequestDefaults::default(),
+                priority: PriorityClass::default(),
+                max_duration_secs: None,
+                stream_max_duration_secs: None,
+                preset: None,
+                usage_retention_days: None,
+                response_cache: ResponseCacheMode::default(),
+                log_content: None,
+                session_tokens: false,
+            },
+            ClientKeyConfig {
+                key: "sk-anon".to_string(),
+                name: None,
+                local_tools: vec![],
+                stream_policy: StreamPolicy::default(),
+                not_before: None,
+                expires_at: None,
+                access_windows: vec![],
+                scopes: ClientKeyScopes::default(),
+                defaults: RequestDefaults::default(),
+                priority: PriorityClass::default(),
+                max_duration_secs: None,
+                stream_max_duration_secs: None,
+                preset: None,
+                usage_retention_days: None,
+                response_cache: ResponseCacheMode::default(),
+                log_content: None,
+                session_tokens: false,
+            },
+        ]);
+
+        let now = Utc::now();
+        let main = state.identify("sk-main", now).unwrap();
+        assert_eq!(main.name, "default");
+        assert!(main.local_tools.is_empty());
+
+        let agent = state.identify("sk-agent", now).unwrap();
+        assert_eq!(agent.name, "agent");
+        assert_eq!(agent.local_tools, vec![LocalToolKind::FsRead]);
+        assert_eq!(agent.stream_policy, StreamPolicy::Force);
+        assert_eq!(main.stream_policy, StreamPolicy::Passthrough);
+
+        assert_eq!(state.identify("sk-anon", now).unwrap().name, "client-2");
+        assert_eq!(
+            state.identify("sk-wrong", now).unwrap_err(),
+            AuthRejection::InvalidKey
+        );
+    }
+
+    #[test]
+    fn test_identify_session_token() {
+        use crate::common::session_token::SessionClaims;
+        use crate::model::config::SessionTokenConfig;
+
+        let issuer = SessionTokenIssuer::from_config(&SessionTokenConfig {
+            enabled: true,
+            ..Default::default()
+        })
+        .unwrap();
+        let state = AppState::new("sk-main")
+            .with_client_keys(vec![
+                contractor(serde_json::json!({
+                    "sessionTokens": true,
+                    "localTools": ["shell"],
+                    "scopes": {"maxTokens": 4096},
+                })),
+                serde_json::from_value(serde_json::json!({"key": "sk-plain", "name": "plain"}))
+                    .unwrap(),
+            ])
+            .with_session_tokens(issuer.clone());
+        let now = Utc::now();
+        let token = |sub: &str, exp: i64| {
+            issuer.issue(&SessionClaims {
+                sub: sub.to_string(),
+                global: false,
+                kid: session_token::key_fingerprint(&format!("sk-{}", sub)),
+                iat: now.timestamp(),
+                exp,
+                models: vec!["claude-haiku-*".to_string()],
+                max_tokens: Some(8192),
+            })
+        };
+
+        let identity = state
+            .identify(&token("contractor", now.timestamp() + 60), now)
+            .unwrap();
+        assert_eq!(identity.name, "contractor");
+        assert!(identity.via_session_token && !identity.session_tokens);
+        assert!(identity.local_tools.is_empty());
+        assert_eq!(identity.scopes.models, vec!["claude-haiku-*".to_string()]);
+        assert_eq!(identity.scopes.max_tokens, Some(4096));
+
+        // 过期、未被允许换取 Token 的密钥、已吊销的密钥
+        for token in [
+            token("contractor", now.timestamp() - 1),
+            token("plain", now.timestamp() + 60),
+            token("revoked", now.timestamp() + 60),
+        ] {
+            assert_eq!(
+                state.identify(&token, now).unwrap_err(),
+                AuthRejection::InvalidKey
+            );
+        }
+
+        // 密钥被删除后以同名重建：旧 Token 不会获得新密钥的权限
+        let recreated = state.clone().with_client_keys(vec![
+            serde_json::from_value(serde_json::json!({
+                "key": "sk-contractor-new",
+                "name": "contractor",
+                "sessionTokens": true,
+            }))
+            .unwrap(),
+        ]);
+        assert_eq!(
+            recreated
+                .identify(&token("contractor", now.timestamp() + 60), now)
+                .unwrap_err(),
+            AuthRejection::InvalidKey
+        );
+    }
+
+    #[test]
+    fn test_identify_session_token_global_and_fail_closed() {
+        use crate::common::session_token::SessionClaims;
+        use crate::model::config::SessionTokenConfig;
+
+        let issuer = SessionTokenIssuer::from_config(&SessionTokenConfig {
+            enabled: true,
+            ..Default::default()
+        })
+        .unwrap();
+        let now = Utc::now();
+        let claims = |sub: &str, global: bool| SessionClaims {
+            sub: sub.to_string(),
+            global,
+            kid: session_token::key_fingerprint(if global { "sk-main" } else { "sk-default" }),
+            iat: now.timestamp(),
+            exp: now.timestamp() + 60,
+            models: vec![],
+            max_tokens: None,
+        };
+        let state = AppState::new("sk-main").with_session_tokens(issuer.clone());
+
+        // 全局 apiKey 签发的 Token 沿用默认客户端
+        let identity = state
+            .identify(&issuer.issue(&claims("default", true)), now)
+            .unwrap();
+        assert!(identity.global && identity.via_session_token);
+        // 全局 apiKey 轮换后失效
+        let rotated = AppState::new("sk-main-new").with_session_tokens(issuer.clone());
+        assert_eq!(
+            rotated
+                .identify(&issuer.issue(&claims("default", true)), now)
+                .unwrap_err(),
+            AuthRejection::InvalidKey
+        );
+
+        // 名为 default 的客户端密钥签发的 Token：密钥被吊销后不会退回全局身份
+        let token = issuer.issue(&claims("default", false));
+        assert_eq!(
+            state.identify(&token, now).unwrap_err(),
+            AuthRejection::InvalidKey
+        );
+        let with_key = state.clone().with_client_keys(vec![
+            serde_json::from_value(serde_json::json!({
+                "key": "sk-default",
+                "name": "default",
+                "sessionTokens": true,
+                "scopes": {"models": ["claude-haiku-*"]},
+            }))
+            .unwrap(),
+        ]);
+        let identity = with_key.identify(&token, now).unwrap();
+        assert!(!identity.global);
+        assert_eq!(identity.scopes.models, vec!["claude-haiku-*".to_string()]);
+
+        // 形如 JWT、不带 kst_ 前缀的客户端密钥按普通密钥识别
+        let jwt_like = "eyJhbGciOi.eyJzdWIiOi.c2ln";
+        let state = state.with_client_keys(vec![
+            serde_json::from_value(serde_json::json!({"key": jwt_like, "name": "jwt-like"}))
+                .unwrap(),
+        ]);
+        assert_eq!(state.identify(jwt_like, now).unwrap().name, "jwt-like");
+    }
+
+    #[test]
+    fn test_identify_session_token_scope_narrowing() {
+        use crate::common::session_token::SessionClaims;
+        use crate::model::config::SessionTokenConfig;
+
+        let issuer = SessionTokenIssuer::from_config(&SessionTokenConfig {
+            enabled: true,
+            ..Default::default()
+        })
+        .unwrap();
+        let now = Utc::now();
+        let token = |models: &[&str]| {
+            issuer.issue(&SessionClaims {
+                sub: "contractor".to_string(),
+                global: false,
+                kid: session_token::key_fingerprint("sk-contractor"),
+                iat: now.timestamp(),
+                exp: now.timestamp() + 60,
+                models: models.iter().map(|m| m.to_string()).collect(),
+                max_tokens: None,
+            })
+        };
+        // 签发时密钥允许 claude-*，之后被收窄为 claude-haiku-*
+        let broad = token(&["claude-*"]);
+        let opus = token(&["claude-opus-*"]);
+        let unscoped = token(&[]);
+        let state = AppState::new("sk-main")
+            .with_client_keys(vec![contractor(serde_json::json!({
+                "sessionTokens": true,
+                "scopes": {"models": ["claude-haiku-*"]},
+            }))])
+            .with_session_tokens(issuer.clone());
+
+        for token in [&broad, &unscoped] {
+            assert_eq!(
+                state.identify(token, now).unwrap().scopes.models,
+                vec!["claude-haiku-*".to_string()]
+            );
+        }
+        assert!(matches!(
+            state.identify(&opus, now).unwrap_err(),
+            AuthRejection::Denied(_)
+        ));
+    }
+
+    fn at(rfc3339: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(rfc3339).unwrap().to_utc()
+    }
+
+    fn contractor(access: serde_json::Value) -> ClientKeyConfig {
+        let mut value = serde_json::json!({"key": "sk-contractor", "name": "contractor"});
+        value
+            .as_object_mut()
+            .unwrap()
+            .extend(access.as_object().unwrap().clone());
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn test_access_expiry() {
+        let client = contractor(serde_json::json!({
+            "notBefore": "2026-03-02T00:00:00Z",
+            "expiresAt": "2026-03-31T00:00:00Z"
+        }));
+        assert!(access_denial(&client, at("2026-03-01T23:59:59Z")).is_some());
+        assert!(access_denial(&client, at("2026-03-15T12:00:00Z")).is_none());
+        assert!(access_denial(&client, at("2026-03-31T00:00:00Z")).is_some());
+
+        let state = AppState::new("sk-main").with_client_keys(vec![client]);
+        assert!(matches!(
+            state.identify("sk-contractor", at("2026-04-01T00:00:00Z")),
+            Err(AuthRejection::Denied(_))
+        ));
+    }
+
+    #[test]
+    fn test_access_windows() {
+        // 工作日 09:00-18:00（UTC+8）
+        let client = contractor(serde_json::json!({
+            "accessWindows": [
+                {"days": ["Mon", "Tue", "Wed", "Thu", "Fri"], "start": "09:00", "end": "18:00", "utcOffset": "+08:00"}
+            ]
+        }));
+        // 2026-03-02 为周一
+        assert!(access_denial(&client, at("2026-03-02T01:00:00Z")).is_none()); // 09:00 +08
+        assert!(access_denial(&client, at("2026-03-02T10:00:00Z")).is_some()); // 18:00 +08
+        assert!(access_denial(&client, at("2026-03-07T03:00:00Z")).is_some()); // 周六
+
+        // 跨午夜：周五 22:00 到周六 06:00 都属于周五的时段
+        let night = contractor(serde_json::json!({
+            "accessWindows": [{"days": ["Fri"], "start": "22:00", "end": "06:00"}]
+        }));
+        assert!(access_denial(&night, at("2026-03-06T23:00:00Z")).is_none());
+        assert!(access_denial(&night, at("2026-03-07T05:59:00Z")).is_none());
+        assert!(access_denial(&night, at("2026-03-07T22:30:00Z")).is_some());
+
+        let broken = contractor(serde_json::json!({
+            "accessWindows": [{"start": "9am", "end": "18:00"}]
+        }));
+        assert!(access_denial(&broken, at("2026-03-02T10:00:00Z")).is_some());
+    }
+}
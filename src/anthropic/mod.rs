@@ -8,6 +8,11 @@
 //! - `GET /v1/models` - 获取可用模型列表
 //! - `POST /v1/messages` - 创建消息（对话）
 //! - `POST /v1/messages/count_tokens` - 计算 token 数量
+//! - `POST /v1/responses` - OpenAI Responses API 兼容端点（input items、`response.*` 流式事件、function 工具调用）
+//! - `GET /v1/artifacts/{id}` - 下载上游文件（签名链接，无需 API Key）
+//!
+//! ## Azure OpenAI 兼容端点 (/openai)
+//! - `POST /openai/deployments/{deployment}/chat/completions` - Chat Completions（部署名按 `azureDeployments` 映射为模型，支持 `api-key` 请求头）
 //!
 //! ## Claude Code 兼容端点 (/cc/v1)
 //! - `POST /cc/v1/messages` - 创建消息（流式响应会等待 contextUsageEvent 后再发送 message_start，确保 input_tokens 准确）
@@ -17,17 +22,50 @@
 //! ```rust,ignore
 //! use kiro_rs::anthropic;
 //!
-//! let app = anthropic::create_router("your-api-key");
+//! let state = anthropic::AppState::new("your-api-key").with_kiro_provider(provider);
+//! let app = anthropic::create_router(state);
 //! let listener = tokio::net::TcpListener::bind("0.0.0.0:3000").await?;
 //! axum::serve(listener, app).await?;
 //! ```
 
+mod artifacts;
+mod canonical;
+mod coalesce;
+pub(crate) mod chat_completions;
+mod compression;
+mod context_routing;
 mod converter;
+mod cost;
+mod forward_headers;
+pub mod filters;
 mod handlers;
+mod local_tools;
 mod middleware;
+mod presets;
+mod render;
+mod response_cache;
+pub(crate) mod responses;
 mod router;
+#[cfg(test)]
+mod sse_conformance;
+mod stall;
+mod stop_reason;
 mod stream;
 pub mod types;
+mod watermark;
 mod websearch;
+#[cfg(test)]
+mod wire_compat;
 
-pub use router::create_router_with_provider;
+pub use artifacts::ArtifactStore;
+pub use coalesce::Coalescer;
+pub use compression::ContextCompressor;
+pub use context_routing::ContextRouter;
+pub use converter::{CustomModel, init_custom_models};
+pub use cost::CostTable;
+pub use forward_headers::HeaderForwarder;
+pub use local_tools::LocalToolRunner;
+pub use middleware::AppState;
+pub use presets::RequestPresets;
+pub use response_cache::ResponseCache;
+pub use router::create_router;
@@ -0,0 +1,97 @@
+//! 常见客户端的请求预设
+//!
+//! 不同的 Agent 客户端对生成参数和系统提示词各有习惯，直接转发到 Kiro 时效果不一。这里内置
+//! `claude-code`、`cline`、`cursor` 三个预设（`presets/*.json`，编译进二进制，可随社区经验更新），
+//! 客户端密钥用 `preset` 选择；配置 `requestPresets` 可覆盖同名内置预设或新增预设。
+//! 预设在客户端密钥的 `defaults` 之后应用：补齐仍缺失的生成参数、在系统提示词末尾追加内容、
+//! 按上限下调 max_tokens。
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::model::config::{Config, RequestPreset};
+
+/// 内置预设
+const BUILTIN_PRESETS: [(&str, &str); 3] = [
+    ("claude-code", include_str!("presets/claude-code.json")),
+    ("cline", include_str!("presets/cline.json")),
+    ("cursor", include_str!("presets/cursor.json")),
+];
+
+/// 请求预设表（内置预设 + 配置覆盖）
+pub struct RequestPresets {
+    presets: HashMap<String, RequestPreset>,
+}
+
+impl RequestPresets {
+    /// 从配置创建，并提示客户端密钥引用了不存在的预设
+    pub fn from_config(config: &Config) -> Arc<Self> {
+        let mut presets: HashMap<String, RequestPreset> = BUILTIN_PRESETS
+            .iter()
+            .map(|(name, json)| {
+                let preset = serde_json::from_str(json)
+                    .unwrap_or_else(|e| panic!("内置请求预设 {} 无效: {}", name, e));
+                (name.to_string(), preset)
+            })
+            .collect();
+        for (name, preset) in &config.request_presets {
+            presets.insert(name.clone(), preset.clone());
+        }
+
+        for key in &config.client_keys {
+            if let Some(name) = &key.preset
+                && !presets.contains_key(name)
+            {
+                tracing::warn!(
+                    "客户端密钥 {} 引用的请求预设 {} 不存在，将被忽略",
+                    key.name.as_deref().unwrap_or("<unnamed>"),
+                    name
+                );
+            }
+        }
+        Arc::new(Self { presets })
+    }
+
+    /// 按名称查找预设
+    pub fn get(&self, name: &str) -> Option<&RequestPreset> {
+        self.presets.get(name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_builtin_presets_and_overrides() {
+        let presets = RequestPresets::from_config(&Config::default());
+        for (name, _) in BUILTIN_PRESETS {
+            assert!(presets.get(name).is_some(), "缺少内置预设 {}", name);
+        }
+        assert_eq!(
+            presets.get("cline").unwrap().defaults.temperature,
+            Some(0.0)
+        );
+        assert!(presets.get("unknown").is_none());
+
+        let config = Config {
+            request_presets: HashMap::from([
+                (
+                    "cline".to_string(),
+                    RequestPreset {
+                        max_tokens: Some(4096),
+                        ..RequestPreset::default()
+                    },
+                ),
+                ("internal".to_string(), RequestPreset::default()),
+            ]),
+            ..Config::default()
+        };
+        let presets = RequestPresets::from_config(&config);
+        let cline = presets.get("cline").unwrap();
+        assert_eq!(cline.max_tokens, Some(4096));
+        assert!(cline.system_append.is_none());
+        assert!(presets.get("internal").is_some());
+        assert!(presets.get("cursor").is_some());
+    }
+}
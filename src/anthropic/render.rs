@@ -0,0 +1,337 @@
+//! 流式响应渲染
+//!
+//! `StreamContext` 把 Kiro 事件翻译为一条事件流（Anthropic Messages 的事件序列：
+//! message_start、content_block_*、message_delta、message_stop、error）。
+//! Anthropic 格式原样输出，其余格式先转换为规范事件（见 `canonical`）再渲染。
+//! 各端点只选择渲染格式，共用同一套翻译逻辑，新增兼容前端时不必再从 Kiro 事件重写一遍。
+//!
+//! SSE 输出约定（见 `sse_conformance` 中按各客户端 SDK 解析规则编写的测试）：
+//! - 每个事件只有一行 `data:`（JSON 不含换行；其他数据按行拆分），冒号后带一个空格，以 `\n\n` 结束
+//! - 每个字节块都是完整的事件，不会在 UTF-8 多字节字符中间切断
+//! - 终止事件（message_stop、error）之后不再输出任何数据；OpenAI 格式的 `data: [DONE]` 恰好一次且在最后，
+//!   出错时紧跟在 error 之后
+//! - 不输出 `id:` / `retry:`：流无法从断点续传，断线后客户端应重新发起请求
+
+use std::collections::HashMap;
+use std::convert::Infallible;
+
+use bytes::Bytes;
+use serde_json::{Value, json};
+
+use super::canonical::{BlockKind, Event, ToCanonical};
+use super::responses::ResponsesRenderer;
+use super::stream::SseEvent;
+
+/// 输出格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RenderFormat {
+    /// Anthropic Messages SSE（原样输出规范事件）
+    Anthropic,
+    /// OpenAI Chat Completions SSE（`chat.completion.chunk` + `[DONE]`）
+    OpenAi,
+    /// OpenAI Responses SSE（`response.*` 事件）
+    OpenAiResponses,
+}
+
+/// 格式化一个 SSE 事件（`data` 中的 CR / LF / CRLF 按行拆分为多行 `data:`）
+pub fn sse_frame(event: Option<&str>, data: &str) -> String {
+    let mut frame = String::with_capacity(data.len() + 32);
+    if let Some(event) = event {
+        frame.push_str("event: ");
+        frame.push_str(event);
+        frame.push('\n');
+    }
+    for line in data.split("\r\n").flat_map(|l| l.split(['\r', '\n'])) {
+        frame.push_str("data: ");
+        frame.push_str(line);
+        frame.push('\n');
+    }
+    frame.push('\n');
+    frame
+}
+
+/// 把规范事件渲染为指定格式的字节流
+pub struct StreamRenderer {
+    format: RenderFormat,
+    openai: OpenAiState,
+    responses: ResponsesRenderer,
+    /// 已输出终止事件（message_stop 或 error），之后的事件与保活全部丢弃
+    finished: bool,
+}
+
+/// OpenAI 格式渲染状态
+#[derive(Default)]
+struct OpenAiState {
+    id: String,
+    model: String,
+    created: i64,
+    /// 规范事件中的块索引 -> OpenAI `tool_calls` 索引
+    tool_calls: HashMap<i32, usize>,
+}
+
+impl StreamRenderer {
+    pub fn new(format: RenderFormat) -> Self {
+        Self {
+            format,
+            openai: OpenAiState::default(),
+            responses: ResponsesRenderer::default(),
+            finished: false,
+        }
+    }
+
+    /// 响应的 Content-Type
+    pub fn content_type(&self) -> &'static str {
+        "text/event-stream"
+    }
+
+    /// 保活数据（流已结束时返回 None）
+    pub fn ping(&self) -> Option<Bytes> {
+        if self.finished {
+            return None;
+        }
+        match self.format {
+            RenderFormat::Anthropic => {
+                Some(Bytes::from("event: ping\ndata: {\"type\": \"ping\"}\n\n"))
+            }
+            RenderFormat::OpenAi | RenderFormat::OpenAiResponses => Some(Bytes::from(": ping\n\n")),
+        }
+    }
+
+    /// 渲染一批规范事件
+    pub fn render_all(&mut self, events: Vec<SseEvent>) -> Vec<Result<Bytes, Infallible>> {
+        events
+            .iter()
+            .filter_map(|e| self.render(e))
+            .map(|s| Ok(Bytes::from(s)))
+            .collect()
+    }
+
+    /// 渲染单个规范事件，该格式下无对应输出时返回 None
+    pub fn render(&mut self, event: &SseEvent) -> Option<String> {
+        if self.finished {
+            tracing::debug!("流已结束，丢弃事件: {}", event.event);
+            return None;
+        }
+        if matches!(event.event.as_str(), "message_stop" | "error") {
+            self.finished = true;
+        }
+        if self.format == RenderFormat::Anthropic {
+            return Some(event.to_sse_string());
+        }
+        let event = match event.to_canonical() {
+            Ok(event) => event,
+            Err(e) => {
+                tracing::debug!("跳过{}", e);
+                return None;
+            }
+        };
+        match self.format {
+            RenderFormat::OpenAiResponses => self.responses.render(&event),
+            _ => self.render_openai(&event),
+        }
+    }
+
+    fn render_openai(&mut self, event: &Event) -> Option<String> {
+        match event {
+            Event::MessageStart { id, model, .. } => {
+                self.openai.id = format!("chatcmpl-{}", id.trim_start_matches("msg_"));
+                self.openai.model = model.clone();
+                self.openai.created = chrono::Utc::now().timestamp();
+                Some(self.openai_chunk(json!({"role": "assistant", "content": ""}), None, None))
+            }
+            Event::BlockStart {
+                index,
+                kind: BlockKind::ToolUse { id, name },
+            } => {
+                let call_index = self.openai.tool_calls.len();
+                self.openai.tool_calls.insert(*index, call_index);
+                Some(self.openai_chunk(
+                    json!({"tool_calls": [{
+                        "index": call_index,
+                        "id": id,
+                        "type": "function",
+                        "function": {"name": name, "arguments": ""}
+                    }]}),
+                    None,
+                    None,
+                ))
+            }
+            Event::TextDelta { text, .. } => {
+                Some(self.openai_chunk(json!({"content": text}), None, None))
+            }
+            Event::ThinkingDelta { thinking, .. } => {
+                Some(self.openai_chunk(json!({"reasoning_content": thinking}), None, None))
+            }
+            Event::ToolInputDelta {
+                index,
+                partial_json,
+            } => {
+                let call_index = self.openai.tool_calls.get(index)?;
+                Some(self.openai_chunk(
+                    json!({"tool_calls": [{
+                        "index": call_index,
+                        "function": {"arguments": partial_json}
+                    }]}),
+                    None,
+                    None,
+                ))
+            }
+            Event::MessageDelta { stop_reason, usage } => {
+                let usage = json!({
+                    "prompt_tokens": usage.input_tokens,
+                    "completion_tokens": usage.output_tokens,
+                    "total_tokens": usage.input_tokens + usage.output_tokens
+                });
+                Some(self.openai_chunk(json!({}), Some(stop_reason.as_openai()), Some(usage)))
+            }
+            Event::MessageStop => Some(sse_frame(None, "[DONE]")),
+            // 出错时同样以 [DONE] 结束，按行读取到 [DONE] 才退出的客户端不会一直等待
+            Event::Error {
+                error_type,
+                message,
+            } => {
+                let error = json!({"error": {"type": error_type, "message": message}});
+                let mut frames = sse_frame(None, &error.to_string());
+                frames.push_str(&sse_frame(None, "[DONE]"));
+                Some(frames)
+            }
+            Event::BlockStart { .. } | Event::BlockStop { .. } | Event::Ping => None,
+        }
+    }
+
+    fn openai_chunk(
+        &self,
+        delta: Value,
+        finish_reason: Option<&str>,
+        usage: Option<Value>,
+    ) -> String {
+        let mut chunk = json!({
+            "id": self.openai.id,
+            "object": "chat.completion.chunk",
+            "created": self.openai.created,
+            "model": self.openai.model,
+            "choices": [{"index": 0, "delta": delta, "finish_reason": finish_reason}]
+        });
+        if let Some(usage) = usage {
+            chunk["usage"] = usage;
+        }
+        sse_frame(None, &chunk.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 一条包含文本、工具调用的规范事件流
+    fn events() -> Vec<SseEvent> {
+        vec![
+            SseEvent::new(
+                "message_start",
+                json!({"type": "message_start", "message": {"id": "msg_abc", "model": "claude-sonnet-4-5"}}),
+            ),
+            SseEvent::new(
+                "content_block_start",
+                json!({"type": "content_block_start", "index": 0, "content_block": {"type": "text", "text": ""}}),
+            ),
+            SseEvent::new(
+                "content_block_delta",
+                json!({"type": "content_block_delta", "index": 0, "delta": {"type": "text_delta", "text": "你好"}}),
+            ),
+            SseEvent::new(
+                "content_block_stop",
+                json!({"type": "content_block_stop", "index": 0}),
+            ),
+            SseEvent::new(
+                "content_block_start",
+                json!({"type": "content_block_start", "index": 1, "content_block": {"type": "tool_use", "id": "toolu_1", "name": "read", "input": {}}}),
+            ),
+            SseEvent::new(
+                "content_block_delta",
+                json!({"type": "content_block_delta", "index": 1, "delta": {"type": "input_json_delta", "partial_json": "{\"path\":\"a\"}"}}),
+            ),
+            SseEvent::new(
+                "message_delta",
+                json!({"type": "message_delta", "delta": {"stop_reason": "tool_use"}, "usage": {"input_tokens": 10, "output_tokens": 5}}),
+            ),
+            SseEvent::new("message_stop", json!({"type": "message_stop"})),
+        ]
+    }
+
+    fn render(format: RenderFormat) -> Vec<String> {
+        let mut renderer = StreamRenderer::new(format);
+        events().iter().filter_map(|e| renderer.render(e)).collect()
+    }
+
+    #[test]
+    fn test_anthropic_passthrough() {
+        let out = render(RenderFormat::Anthropic);
+        assert_eq!(out.len(), events().len());
+        assert!(out[0].starts_with("event: message_start\ndata: "));
+    }
+
+    #[test]
+    fn test_openai_chunks() {
+        let out = render(RenderFormat::OpenAi);
+        let chunks: Vec<Value> = out
+            .iter()
+            .filter_map(|s| s.strip_prefix("data: "))
+            .filter_map(|s| serde_json::from_str(s.trim_end()).ok())
+            .collect();
+
+        assert_eq!(chunks[0]["id"], "chatcmpl-abc");
+        assert_eq!(chunks[0]["choices"][0]["delta"]["role"], "assistant");
+        assert_eq!(chunks[1]["choices"][0]["delta"]["content"], "你好");
+        let call = &chunks[2]["choices"][0]["delta"]["tool_calls"][0];
+        assert_eq!(call["index"], 0);
+        assert_eq!(call["function"]["name"], "read");
+        assert_eq!(
+            chunks[3]["choices"][0]["delta"]["tool_calls"][0]["function"]["arguments"],
+            "{\"path\":\"a\"}"
+        );
+        assert_eq!(chunks[4]["choices"][0]["finish_reason"], "tool_calls");
+        assert_eq!(chunks[4]["usage"]["total_tokens"], 15);
+        assert_eq!(out.last().unwrap(), "data: [DONE]\n\n");
+    }
+
+    #[test]
+    fn test_openai_responses_events() {
+        let out = render(RenderFormat::OpenAiResponses).concat();
+        let events: Vec<Value> = out
+            .lines()
+            .filter_map(|line| line.strip_prefix("data: "))
+            .map(|data| serde_json::from_str(data).unwrap())
+            .collect();
+        let types: Vec<&str> = events.iter().map(|e| e["type"].as_str().unwrap()).collect();
+        assert_eq!(
+            types,
+            vec![
+                "response.created",
+                "response.in_progress",
+                "response.output_item.added",
+                "response.content_part.added",
+                "response.output_text.delta",
+                "response.output_text.done",
+                "response.content_part.done",
+                "response.output_item.done",
+                "response.output_item.added",
+                "response.function_call_arguments.delta",
+                // 未收到 content_block_stop 的工具调用在结束时补齐
+                "response.function_call_arguments.done",
+                "response.output_item.done",
+                "response.completed",
+            ]
+        );
+        assert!(out.starts_with("event: response.created\ndata: "));
+        assert_eq!(events[0]["response"]["id"], "resp_abc");
+        assert_eq!(events[4]["delta"], "你好");
+        assert_eq!(events[12]["sequence_number"], 12);
+
+        let completed = &events[12]["response"];
+        assert_eq!(completed["status"], "completed");
+        assert_eq!(completed["output"][0]["content"][0]["text"], "你好");
+        assert_eq!(completed["output"][1]["call_id"], "toolu_1");
+        assert_eq!(completed["output"][1]["arguments"], "{\"path\":\"a\"}");
+        assert_eq!(completed["usage"]["total_tokens"], 15);
+    }
+}
@@ -0,0 +1,430 @@
+//! 非流式响应缓存
+//!
+//! 问答类的工作负载经常重复提问，每次都消耗上游额度。按客户端密钥的 `responseCache` 开启：
+//! - `exact`：请求（模型、system、消息、工具与生成参数，不含 `stream` 与 `metadata`）完全相同时命中
+//! - `semantic`：另外允许最后一条用户消息不同，只要此前的上下文相同、两条消息的向量余弦相似度
+//!   不低于 `similarityThreshold`。向量由 `embeddingUrl` 指向的 OpenAI 兼容 embeddings 接口（如本地
+//!   Ollama）生成；未配置时启动校验会拒绝使用 `semantic` 的客户端密钥，Admin API 开通的密钥则退回精确匹配
+//!
+//! 缓存按客户端隔离，只缓存正常结束（`end_turn`）的响应，命中时响应头 `x-kiro-cache` 为 `hit`
+//! 或 `semantic`。
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use parking_lot::Mutex;
+use serde_json::{Value, json};
+use sha2::{Digest, Sha256};
+
+use crate::common::privacy::Capabilities;
+use crate::model::config::{ResponseCacheConfig, ResponseCacheMode};
+
+use super::types::MessagesRequest;
+
+/// 命中时附带的响应头
+pub const CACHE_HEADER: &str = "x-kiro-cache";
+
+/// embeddings 接口的超时
+const EMBEDDING_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// 一次缓存查询（未命中时用于写入）
+#[derive(Debug)]
+pub struct CacheQuery {
+    client: String,
+    /// 整个请求的指纹
+    key: String,
+    /// 除最后一条用户消息外的上下文指纹
+    context: String,
+    /// 最后一条用户消息的文本（语义模式且内容只有文本时）
+    prompt: Option<String>,
+    embedding: Option<Vec<f32>>,
+}
+
+/// 命中的缓存
+#[derive(Debug)]
+pub struct CacheHit {
+    /// Anthropic 格式的响应体
+    pub response: Value,
+    /// 语义命中时的相似度（精确命中为 None）
+    pub similarity: Option<f32>,
+}
+
+struct Entry {
+    client: String,
+    context: String,
+    embedding: Option<Vec<f32>>,
+    response: Value,
+    created_at: Instant,
+}
+
+/// OpenAI 兼容的 embeddings 接口
+struct Embedder {
+    client: reqwest::Client,
+    url: String,
+    model: Option<String>,
+}
+
+/// 响应缓存
+pub struct ResponseCache {
+    config: ResponseCacheConfig,
+    /// 未配置 `embeddingUrl` 时为 None（`semantic` 退回精确匹配）
+    embedder: Option<Embedder>,
+    entries: Mutex<HashMap<String, Entry>>,
+}
+
+impl ResponseCache {
+    /// 从配置创建（未启用或隐私模式下返回 None）
+    pub fn from_config(
+        config: &ResponseCacheConfig,
+        capabilities: Capabilities,
+    ) -> Option<Arc<Self>> {
+        if !config.enabled || !capabilities.cache {
+            return None;
+        }
+        let embedder = config.embedding_url.as_ref().map(|url| Embedder {
+            client: reqwest::Client::builder()
+                .timeout(EMBEDDING_TIMEOUT)
+                .build()
+                .unwrap_or_default(),
+            url: url.clone(),
+            model: config.embedding_model.clone(),
+        });
+        Some(Arc::new(Self {
+            config: config.clone(),
+            embedder,
+            entries: Mutex::new(HashMap::new()),
+        }))
+    }
+
+    fn ttl(&self) -> Duration {
+        Duration::from_secs(self.config.ttl_secs)
+    }
+
+    /// 为请求生成查询（客户端未开启缓存时返回 None）
+    pub fn query(
+        &self,
+        client: &str,
+        mode: ResponseCacheMode,
+        payload: &MessagesRequest,
+    ) -> Option<CacheQuery> {
+        if mode == ResponseCacheMode::Off {
+            return None;
+        }
+        let mut request = serde_json::to_value(payload).ok()?;
+        let object = request.as_object_mut()?;
+        object.remove("stream");
+        object.remove("metadata");
+        let key = fingerprint(client, &request);
+
+        let prompt = (mode == ResponseCacheMode::Semantic && self.embedder.is_some())
+            .then(|| payload.messages.last())
+            .flatten()
+            .filter(|message| message.role == "user")
+            .and_then(|message| plain_text(&message.content));
+        if prompt.is_some()
+            && let Some(messages) = request["messages"].as_array_mut()
+        {
+            messages.pop();
+        }
+        let context = fingerprint(client, &request);
+
+        Some(CacheQuery {
+            client: client.to_string(),
+            key,
+            context,
+            prompt,
+            embedding: None,
+        })
+    }
+
+    /// 查找缓存；语义模式下顺带为最后一条用户消息生成向量（供未命中时写入）
+    pub async fn lookup(&self, query: &mut CacheQuery) -> Option<CacheHit> {
+        if let Some(response) = self.find_exact(&query.key) {
+            return Some(CacheHit {
+                response,
+                similarity: None,
+            });
+        }
+        let prompt = query.prompt.as_deref()?;
+        query.embedding = match self.embed(prompt).await {
+            Ok(embedding) => Some(embedding),
+            Err(e) => {
+                tracing::warn!("生成响应缓存向量失败，只按精确匹配缓存: {}", e);
+                None
+            }
+        };
+        let embedding = query.embedding.as_deref()?;
+        self.find_similar(&query.client, &query.context, embedding)
+            .map(|(response, similarity)| CacheHit {
+                response,
+                similarity: Some(similarity),
+            })
+    }
+
+    fn find_exact(&self, key: &str) -> Option<Value> {
+        let ttl = self.ttl();
+        let entries = self.entries.lock();
+        entries
+            .get(key)
+            .filter(|entry| entry.created_at.elapsed() < ttl)
+            .map(|entry| entry.response.clone())
+    }
+
+    fn find_similar(&self, client: &str, context: &str, embedding: &[f32]) -> Option<(Value, f32)> {
+        let ttl = self.ttl();
+        let entries = self.entries.lock();
+        entries
+            .values()
+            .filter(|entry| {
+                entry.client == client
+                    && entry.context == context
+                    && entry.created_at.elapsed() < ttl
+            })
+            .filter_map(|entry| {
+                let similarity = cosine(entry.embedding.as_deref()?, embedding);
+                Some((entry, similarity))
+            })
+            .filter(|(_, similarity)| *similarity >= self.config.similarity_threshold)
+            .max_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(entry, similarity)| (entry.response.clone(), similarity))
+    }
+
+    /// 写入正常结束的响应
+    pub fn store(&self, query: CacheQuery, response: Value) {
+        let ttl = self.ttl();
+        let mut entries = self.entries.lock();
+        entries.retain(|_, entry| entry.created_at.elapsed() < ttl);
+        while entries.len() >= self.config.max_entries.max(1) {
+            let Some(oldest) = entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.created_at)
+                .map(|(key, _)| key.clone())
+            else {
+                break;
+            };
+            entries.remove(&oldest);
+        }
+        entries.insert(
+            query.key,
+            Entry {
+                client: query.client,
+                context: query.context,
+                embedding: query.embedding,
+                response,
+                created_at: Instant::now(),
+            },
+        );
+    }
+
+    async fn embed(&self, text: &str) -> anyhow::Result<Vec<f32>> {
+        let Embedder { client, url, model } = self
+            .embedder
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("未配置 embeddingUrl"))?;
+        let response: Value = client
+            .post(url)
+            .json(&json!({ "model": model, "input": text }))
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        let mut embedding: Vec<f32> = response["data"][0]["embedding"]
+            .as_array()
+            .ok_or_else(|| anyhow::anyhow!("embeddings 响应中没有 data[0].embedding"))?
+            .iter()
+            .filter_map(|v| v.as_f64().map(|v| v as f32))
+            .collect();
+        normalize(&mut embedding);
+        Ok(embedding)
+    }
+}
+
+/// 客户端与请求内容的指纹
+fn fingerprint(client: &str, request: &Value) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(client.as_bytes());
+    hasher.update([0]);
+    hasher.update(request.to_string().as_bytes());
+    hex::encode(hasher.finalize())
+}
+
+/// 只含文本的消息内容（含图片、工具结果等时返回 None）
+fn plain_text(content: &Value) -> Option<String> {
+    match content {
+        Value::String(text) => Some(text.clone()),
+        Value::Array(blocks) => blocks
+            .iter()
+            .map(|block| match block["type"].as_str() {
+                Some("text") => block["text"].as_str(),
+                _ => None,
+            })
+            .collect::<Option<Vec<_>>>()
+            .map(|texts| texts.join("\n")),
+        _ => None,
+    }
+}
+
+fn normalize(vector: &mut [f32]) {
+    let norm = vector.iter().map(|v| v * v).sum::<f32>().sqrt();
+    if norm > 0.0 {
+        vector.iter_mut().for_each(|v| *v /= norm);
+    }
+}
+
+/// 两个已归一化向量的余弦相似度（维度不同时为 0）
+fn cosine(a: &[f32], b: &[f32]) -> f32 {
+    if a.len() != b.len() {
+        return 0.0;
+    }
+    a.iter().zip(b).map(|(x, y)| x * y).sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(system: &str, question: &str) -> MessagesRequest {
+        serde_json::from_value(json!({
+            "model": "claude-sonnet-4-5",
+            "max_tokens": 1024,
+            "system": system,
+            "messages": [{"role": "user", "content": question}],
+            "metadata": {"user_id": format!("session-{}", uuid::Uuid::new_v4())}
+        }))
+        .unwrap()
+    }
+
+    /// 模拟 embeddings 接口：忽略大小写与标点后按词袋生成向量
+    async fn embedding_server() -> String {
+        use axum::{Json, Router, routing::post};
+
+        let app = Router::new().route(
+            "/v1/embeddings",
+            post(|Json(body): Json<Value>| async move {
+                let text = body["input"].as_str().unwrap_or_default().to_lowercase();
+                let mut vector = vec![0.0f64; 64];
+                for word in text.split(|c: char| !c.is_alphanumeric()) {
+                    if !word.is_empty() {
+                        let hash = word.bytes().fold(0usize, |h, b| h * 31 + b as usize);
+                        vector[hash % 64] += 1.0;
+                    }
+                }
+                Json(json!({"data": [{"embedding": vector}]}))
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        format!("http://{}/v1/embeddings", addr)
+    }
+
+    #[tokio::test]
+    async fn test_exact_and_semantic_hits() {
+        let cache = ResponseCache::from_config(
+            &ResponseCacheConfig {
+                enabled: true,
+                embedding_url: Some(embedding_server().await),
+                ..Default::default()
+            },
+            Capabilities::ALL,
+        )
+        .unwrap();
+        let answer = json!({"content": [{"type": "text", "text": "在设置页点击“重置密码”"}]});
+
+        let payload = request("客服助手", "How do I reset my password?");
+        let mut query = cache
+            .query("team", ResponseCacheMode::Semantic, &payload)
+            .unwrap();
+        assert!(cache.lookup(&mut query).await.is_none());
+        cache.store(query, answer.clone());
+
+        // metadata 不同仍精确命中
+        let mut exact = cache
+            .query("team", ResponseCacheMode::Exact, &payload)
+            .unwrap();
+        let hit = cache.lookup(&mut exact).await.unwrap();
+        assert_eq!(hit.response, answer);
+        assert_eq!(hit.similarity, None);
+
+        // 措辞略有差异：语义命中，精确模式不命中
+        let reworded = request("客服助手", "how do I reset my password");
+        let mut query = cache
+            .query("team", ResponseCacheMode::Semantic, &reworded)
+            .unwrap();
+        let hit = cache.lookup(&mut query).await.unwrap();
+        assert!(hit.similarity.unwrap() > 0.92);
+        let mut query = cache
+            .query("team", ResponseCacheMode::Exact, &reworded)
+            .unwrap();
+        assert!(cache.lookup(&mut query).await.is_none());
+
+        // 不同的问题、不同的上下文、其他客户端都不命中
+        for (client, system, question) in [
+            ("team", "客服助手", "What are your opening hours?"),
+            ("team", "销售助手", "How do I reset my password?"),
+            ("other", "客服助手", "How do I reset my password?"),
+        ] {
+            let mut query = cache
+                .query(
+                    client,
+                    ResponseCacheMode::Semantic,
+                    &request(system, question),
+                )
+                .unwrap();
+            assert!(
+                cache.lookup(&mut query).await.is_none(),
+                "{client} {system} {question}"
+            );
+        }
+
+        assert!(
+            cache
+                .query("team", ResponseCacheMode::Off, &payload)
+                .is_none()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_semantic_without_embeddings_is_exact() {
+        let cache = ResponseCache::from_config(
+            &ResponseCacheConfig {
+                enabled: true,
+                ..Default::default()
+            },
+            Capabilities::ALL,
+        )
+        .unwrap();
+        let answer = json!({"content": [{"type": "text", "text": "在设置中打开"}]});
+        let mut query = cache
+            .query(
+                "team",
+                ResponseCacheMode::Semantic,
+                &request("运维助手", "How to enable X?"),
+            )
+            .unwrap();
+        assert!(cache.lookup(&mut query).await.is_none());
+        cache.store(query, answer.clone());
+
+        // 否定意思的提问、仅大小写不同的提问都不命中
+        for question in ["How to disable X?", "how to enable x?"] {
+            let mut query = cache
+                .query(
+                    "team",
+                    ResponseCacheMode::Semantic,
+                    &request("运维助手", question),
+                )
+                .unwrap();
+            assert!(cache.lookup(&mut query).await.is_none(), "{question}");
+        }
+        let mut query = cache
+            .query(
+                "team",
+                ResponseCacheMode::Semantic,
+                &request("运维助手", "How to enable X?"),
+            )
+            .unwrap();
+        assert_eq!(cache.lookup(&mut query).await.unwrap().response, answer);
+    }
+}
@@ -0,0 +1,660 @@
+//! OpenAI Responses API 兼容层
+//!
+//! `POST /v1/responses` 的请求（instructions、input items、function 工具）先转换为规范请求，
+//! 再复用 Messages 的处理流程。流式响应由 `ResponsesRenderer` 把规范事件渲染为 `response.*`
+//! 事件（`response.output_text.delta`、`response.function_call_arguments.delta` 等），非流式响应
+//! 由 Anthropic 消息转换为 response 对象。
+//!
+//! 不保存服务端状态：`previous_response_id` 不受支持，客户端需要在 `input` 中传入完整对话
+//! （即 `store: false` 的用法）。
+
+use std::collections::HashMap;
+
+use serde::Deserialize;
+use serde_json::{Value, json};
+use uuid::Uuid;
+
+use super::canonical::{
+    BlockKind, CanonicalError, ContentBlock, Event, Message, Request, Role, ToCanonical, ToolCall,
+    ToolSpec, Usage,
+};
+use super::render::sse_frame;
+use super::stop_reason::StopReason;
+
+/// Responses 请求体
+#[derive(Debug, Deserialize)]
+pub struct ResponsesRequest {
+    pub model: String,
+    /// 系统指令
+    #[serde(default)]
+    pub instructions: Option<String>,
+    /// 字符串或 input item 数组
+    pub input: Value,
+    #[serde(default)]
+    pub tools: Vec<Value>,
+    #[serde(default)]
+    pub max_output_tokens: Option<i32>,
+    #[serde(default)]
+    pub stream: bool,
+    #[serde(default)]
+    pub reasoning: Option<Reasoning>,
+    #[serde(default)]
+    pub temperature: Option<f32>,
+    #[serde(default)]
+    pub previous_response_id: Option<String>,
+}
+
+/// 推理配置
+#[derive(Debug, Deserialize)]
+pub struct Reasoning {
+    #[serde(default)]
+    pub effort: Option<String>,
+}
+
+impl ToCanonical for ResponsesRequest {
+    type Canonical = Request;
+
+    fn to_canonical(&self) -> Result<Request, CanonicalError> {
+        let mut system: Vec<String> = self.instructions.iter().cloned().collect();
+        let mut messages: Vec<Message> = Vec::new();
+        match &self.input {
+            Value::String(text) => {
+                push_block(&mut messages, Role::User, ContentBlock::Text(text.clone()))
+            }
+            Value::Array(items) => {
+                for item in items {
+                    input_item(item, &mut system, &mut messages)?;
+                }
+            }
+            other => return Err(CanonicalError::Block(other.to_string())),
+        }
+
+        let tools = self
+            .tools
+            .iter()
+            .filter(|tool| {
+                let function = tool["type"].as_str() == Some("function");
+                if !function {
+                    tracing::debug!("忽略不支持的 Responses 工具: {}", tool["type"]);
+                }
+                function
+            })
+            .map(|tool| ToolSpec {
+                name: tool["name"].as_str().unwrap_or_default().to_string(),
+                description: tool["description"].as_str().unwrap_or_default().to_string(),
+                input_schema: tool
+                    .get("parameters")
+                    .cloned()
+                    .unwrap_or_else(|| json!({"type": "object"})),
+            })
+            .collect();
+
+        Ok(Request {
+            model: self.model.clone(),
+            system,
+            messages,
+            tools,
+            max_tokens: self.max_output_tokens.unwrap_or(0),
+            stream: self.stream,
+            thinking: self
+                .reasoning
+                .as_ref()
+                .and_then(|r| r.effort.as_deref())
+                .is_some_and(|effort| effort != "none" && effort != "minimal"),
+        })
+    }
+}
+
+/// 追加内容块，与上一条消息角色相同时合并（连续的 function_call / function_call_output）
+pub(super) fn push_block(messages: &mut Vec<Message>, role: Role, block: ContentBlock) {
+    match messages.last_mut() {
+        Some(last) if last.role == role => last.content.push(block),
+        _ => messages.push(Message {
+            role,
+            content: vec![block],
+        }),
+    }
+}
+
+/// 转换单个 input item（缺省 `type` 时视为 message）
+fn input_item(
+    item: &Value,
+    system: &mut Vec<String>,
+    messages: &mut Vec<Message>,
+) -> Result<(), CanonicalError> {
+    let invalid = || CanonicalError::Block(item.to_string());
+    let str_field = |name: &str| item[name].as_str().map(|s| s.to_string());
+    match item["type"].as_str().unwrap_or("message") {
+        "message" => {
+            let role = match item["role"].as_str().ok_or_else(invalid)? {
+                "user" => Role::User,
+                "assistant" => Role::Assistant,
+                "system" | "developer" => {
+                    system.push(content_text(&item["content"]));
+                    return Ok(());
+                }
+                other => return Err(CanonicalError::Role(other.to_string())),
+            };
+            match &item["content"] {
+                Value::String(text) => push_block(messages, role, ContentBlock::Text(text.clone())),
+                Value::Array(parts) => {
+                    for part in parts {
+                        push_block(messages, role, content_part(part)?);
+                    }
+                }
+                _ => return Err(invalid()),
+            }
+        }
+        "function_call" => {
+            let arguments = str_field("arguments").unwrap_or_default();
+            let call = ToolCall {
+                id: str_field("call_id").ok_or_else(invalid)?,
+                name: str_field("name").ok_or_else(invalid)?,
+                input: serde_json::from_str(&arguments).unwrap_or_else(|_| json!({})),
+            };
+            push_block(messages, Role::Assistant, ContentBlock::ToolUse(call));
+        }
+        "function_call_output" => {
+            let block = ContentBlock::ToolResult {
+                tool_use_id: str_field("call_id").ok_or_else(invalid)?,
+                content: content_text(&item["output"]),
+                is_error: false,
+            };
+            push_block(messages, Role::User, block);
+        }
+        // 推理内容不回传上游
+        "reasoning" => {}
+        _ => return Err(invalid()),
+    }
+    Ok(())
+}
+
+/// 消息内容的文本（字符串或文本 part 数组）
+pub(super) fn content_text(content: &Value) -> String {
+    match content {
+        Value::String(text) => text.clone(),
+        Value::Array(parts) => parts
+            .iter()
+            .filter_map(|p| p["text"].as_str())
+            .collect::<Vec<_>>()
+            .join("\n"),
+        _ => String::new(),
+    }
+}
+
+fn content_part(part: &Value) -> Result<ContentBlock, CanonicalError> {
+    let invalid = || CanonicalError::Block(part.to_string());
+    Ok(match part["type"].as_str().ok_or_else(invalid)? {
+        "input_text" | "output_text" => {
+            ContentBlock::Text(part["text"].as_str().ok_or_else(invalid)?.to_string())
+        }
+        "refusal" => ContentBlock::Text(part["refusal"].as_str().unwrap_or_default().to_string()),
+        "input_image" => part["image_url"]
+            .as_str()
+            .and_then(data_url_image)
+            .ok_or_else(invalid)?,
+        _ => return Err(invalid()),
+    })
+}
+
+/// 解析图片 data URL（`data:image/png;base64,...`），不支持远程图片链接
+pub(super) fn data_url_image(url: &str) -> Option<ContentBlock> {
+    let (media_type, data) = url
+        .strip_prefix("data:")
+        .and_then(|rest| rest.split_once(";base64,"))?;
+    Some(ContentBlock::Image {
+        media_type: media_type.to_string(),
+        data: data.to_string(),
+    })
+}
+
+/// response 对象的状态与未完成原因
+fn status_of(stop_reason: StopReason) -> (&'static str, Value) {
+    match stop_reason {
+        StopReason::EndTurn | StopReason::ToolUse => ("completed", Value::Null),
+        StopReason::ContentFilter => ("incomplete", json!({"reason": "content_filter"})),
+        StopReason::MaxTokens | StopReason::MaxTurnsExceeded | StopReason::Timeout => {
+            ("incomplete", json!({"reason": "max_output_tokens"}))
+        }
+    }
+}
+
+fn response_object(
+    id: &str,
+    model: &str,
+    created_at: i64,
+    status: &str,
+    output: Vec<Value>,
+    usage: Option<Usage>,
+) -> Value {
+    json!({
+        "id": id,
+        "object": "response",
+        "created_at": created_at,
+        "status": status,
+        "model": model,
+        "output": output,
+        "usage": usage.map(|u| json!({
+            "input_tokens": u.input_tokens,
+            "output_tokens": u.output_tokens,
+            "total_tokens": u.input_tokens + u.output_tokens
+        })),
+        "incomplete_details": null,
+        "error": null
+    })
+}
+
+fn message_item(id: &str, status: &str, text: &str) -> Value {
+    json!({
+        "id": id,
+        "type": "message",
+        "status": status,
+        "role": "assistant",
+        "content": [{"type": "output_text", "text": text, "annotations": []}]
+    })
+}
+
+fn function_call_item(id: &str, status: &str, call_id: &str, name: &str, arguments: &str) -> Value {
+    json!({
+        "id": id,
+        "type": "function_call",
+        "status": status,
+        "call_id": call_id,
+        "name": name,
+        "arguments": arguments
+    })
+}
+
+fn reasoning_item(id: &str, text: &str) -> Value {
+    json!({
+        "id": id,
+        "type": "reasoning",
+        "summary": [],
+        "content": [{"type": "reasoning_text", "text": text}]
+    })
+}
+
+fn new_item_id(prefix: &str) -> String {
+    format!("{}_{}", prefix, Uuid::new_v4().simple())
+}
+
+/// 把非流式的 Anthropic 消息转换为 response 对象
+pub fn from_message(message: &Value) -> Value {
+    let id = message["id"].as_str().unwrap_or_default();
+    let usage = Usage {
+        input_tokens: message["usage"]["input_tokens"]
+            .as_i64()
+            .unwrap_or_default() as i32,
+        output_tokens: message["usage"]["output_tokens"]
+            .as_i64()
+            .unwrap_or_default() as i32,
+    };
+    let output = message["content"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|block| match block["type"].as_str()? {
+            "text" => Some(message_item(
+                &new_item_id("msg"),
+                "completed",
+                block["text"].as_str().unwrap_or_default(),
+            )),
+            "tool_use" => {
+                let call_id = block["id"].as_str().unwrap_or_default();
+                Some(function_call_item(
+                    &format!("fc_{}", call_id),
+                    "completed",
+                    call_id,
+                    block["name"].as_str().unwrap_or_default(),
+                    &block["input"].to_string(),
+                ))
+            }
+            _ => None,
+        })
+        .collect();
+    let stop_reason = message["stop_reason"]
+        .as_str()
+        .and_then(StopReason::from_anthropic)
+        .unwrap_or(StopReason::EndTurn);
+    let (status, incomplete_details) = status_of(stop_reason);
+
+    let mut response = response_object(
+        &format!("resp_{}", id.trim_start_matches("msg_")),
+        message["model"].as_str().unwrap_or_default(),
+        chrono::Utc::now().timestamp(),
+        status,
+        output,
+        Some(usage),
+    );
+    response["incomplete_details"] = incomplete_details;
+    response
+}
+
+/// 流式输出中尚未结束的 output item
+struct OpenItem {
+    output_index: usize,
+    item_id: String,
+    kind: BlockKind,
+    /// 文本、推理内容或工具参数
+    buffer: String,
+}
+
+/// Responses 流式事件渲染状态
+#[derive(Default)]
+pub struct ResponsesRenderer {
+    id: String,
+    model: String,
+    created_at: i64,
+    sequence_number: u64,
+    next_output_index: usize,
+    /// 规范事件中的块索引 -> 进行中的 output item
+    open: HashMap<i32, OpenItem>,
+    /// 已结束的 output item（按 output_index）
+    done: Vec<(usize, Value)>,
+    stop_reason: Option<StopReason>,
+    usage: Option<Usage>,
+}
+
+impl ResponsesRenderer {
+    fn event(&mut self, event_type: &str, mut data: Value) -> String {
+        data["type"] = json!(event_type);
+        data["sequence_number"] = json!(self.sequence_number);
+        self.sequence_number += 1;
+        sse_frame(Some(event_type), &data.to_string())
+    }
+
+    fn response(&self, status: &str) -> Value {
+        let mut done = self.done.clone();
+        done.sort_by_key(|(index, _)| *index);
+        response_object(
+            &self.id,
+            &self.model,
+            self.created_at,
+            status,
+            done.into_iter().map(|(_, item)| item).collect(),
+            self.usage,
+        )
+    }
+
+    /// 渲染单个规范事件
+    pub fn render(&mut self, event: &Event) -> Option<String> {
+        match event {
+            Event::MessageStart { id, model, .. } => {
+                self.id = format!("resp_{}", id.trim_start_matches("msg_"));
+                self.model = model.clone();
+                self.created_at = chrono::Utc::now().timestamp();
+                let response = self.response("in_progress");
+                let mut frames = self.event("response.created", json!({"response": response}));
+                frames.push_str(&self.event("response.in_progress", json!({"response": response})));
+                Some(frames)
+            }
+            Event::BlockStart { index, kind } => Some(self.start_item(*index, kind.clone())),
+            Event::TextDelta { index, text } => {
+                self.delta(*index, "response.output_text.delta", text)
+            }
+            Event::ThinkingDelta { index, thinking } => {
+                self.delta(*index, "response.reasoning_text.delta", thinking)
+            }
+            Event::ToolInputDelta {
+                index,
+                partial_json,
+            } => self.delta(
+                *index,
+                "response.function_call_arguments.delta",
+                partial_json,
+            ),
+            Event::BlockStop { index } => self.finish_item(*index),
+            Event::MessageDelta { stop_reason, usage } => {
+                self.stop_reason = Some(*stop_reason);
+                self.usage = Some(*usage);
+                None
+            }
+            Event::MessageStop => {
+                let mut out = String::new();
+                let mut indices: Vec<i32> = self.open.keys().copied().collect();
+                indices.sort();
+                for index in indices {
+                    out.extend(self.finish_item(index));
+                }
+                let (status, incomplete_details) =
+                    status_of(self.stop_reason.unwrap_or(StopReason::EndTurn));
+                let mut response = self.response(status);
+                response["incomplete_details"] = incomplete_details;
+                let event_type = if status == "completed" {
+                    "response.completed"
+                } else {
+                    "response.incomplete"
+                };
+                out.push_str(&self.event(event_type, json!({"response": response})));
+                Some(out)
+            }
+            Event::Error {
+                error_type,
+                message,
+            } => {
+                let mut response = self.response("failed");
+                response["error"] = json!({"code": error_type, "message": message});
+                Some(self.event("response.failed", json!({"response": response})))
+            }
+            Event::Ping => None,
+        }
+    }
+
+    fn start_item(&mut self, index: i32, kind: BlockKind) -> String {
+        let output_index = self.next_output_index;
+        self.next_output_index += 1;
+        let (item_id, item) = match &kind {
+            BlockKind::Text => {
+                let id = new_item_id("msg");
+                let mut item = message_item(&id, "in_progress", "");
+                item["content"] = json!([]);
+                (id, item)
+            }
+            BlockKind::Thinking => {
+                let id = new_item_id("rs");
+                let mut item = reasoning_item(&id, "");
+                item["content"] = json!([]);
+                (id, item)
+            }
+            BlockKind::ToolUse { id, name } => {
+                let item_id = format!("fc_{}", id);
+                let item = function_call_item(&item_id, "in_progress", id, name, "");
+                (item_id, item)
+            }
+        };
+        let mut out = self.event(
+            "response.output_item.added",
+            json!({"output_index": output_index, "item": item}),
+        );
+        if kind == BlockKind::Text {
+            out.push_str(&self.event(
+                "response.content_part.added",
+                json!({
+                    "item_id": item_id,
+                    "output_index": output_index,
+                    "content_index": 0,
+                    "part": {"type": "output_text", "text": "", "annotations": []}
+                }),
+            ));
+        }
+        self.open.insert(
+            index,
+            OpenItem {
+                output_index,
+                item_id,
+                kind,
+                buffer: String::new(),
+            },
+        );
+        out
+    }
+
+    fn delta(&mut self, index: i32, event_type: &str, delta: &str) -> Option<String> {
+        let item = self.open.get_mut(&index)?;
+        item.buffer.push_str(delta);
+        let mut data = json!({
+            "item_id": item.item_id,
+            "output_index": item.output_index,
+            "delta": delta
+        });
+        if !matches!(item.kind, BlockKind::ToolUse { .. }) {
+            data["content_index"] = json!(0);
+        }
+        Some(self.event(event_type, data))
+    }
+
+    fn finish_item(&mut self, index: i32) -> Option<String> {
+        let item = self.open.remove(&index)?;
+        let (output_index, item_id) = (item.output_index, item.item_id.as_str());
+        let mut out = String::new();
+        let done_item = match &item.kind {
+            BlockKind::Text => {
+                out.push_str(&self.event(
+                    "response.output_text.done",
+                    json!({
+                        "item_id": item_id,
+                        "output_index": output_index,
+                        "content_index": 0,
+                        "text": item.buffer
+                    }),
+                ));
+                out.push_str(&self.event(
+                    "response.content_part.done",
+                    json!({
+                        "item_id": item_id,
+                        "output_index": output_index,
+                        "content_index": 0,
+                        "part": {"type": "output_text", "text": item.buffer, "annotations": []}
+                    }),
+                ));
+                message_item(item_id, "completed", &item.buffer)
+            }
+            BlockKind::Thinking => {
+                out.push_str(&self.event(
+                    "response.reasoning_text.done",
+                    json!({
+                        "item_id": item_id,
+                        "output_index": output_index,
+                        "content_index": 0,
+                        "text": item.buffer
+                    }),
+                ));
+                reasoning_item(item_id, &item.buffer)
+            }
+            BlockKind::ToolUse { id, name } => {
+                out.push_str(&self.event(
+                    "response.function_call_arguments.done",
+                    json!({
+                        "item_id": item_id,
+                        "output_index": output_index,
+                        "arguments": item.buffer
+                    }),
+                ));
+                function_call_item(item_id, "completed", id, name, &item.buffer)
+            }
+        };
+        out.push_str(&self.event(
+            "response.output_item.done",
+            json!({"output_index": output_index, "item": done_item}),
+        ));
+        self.done.push((output_index, done_item));
+        Some(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(value: Value) -> ResponsesRequest {
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn test_request_to_canonical() {
+        let canonical = request(json!({
+            "model": "claude-sonnet-4-5",
+            "instructions": "你是助手",
+            "max_output_tokens": 1024,
+            "reasoning": {"effort": "high"},
+            "tools": [
+                {"type": "function", "name": "read", "description": "读取文件", "parameters": {"type": "object"}},
+                {"type": "web_search_preview"}
+            ],
+            "input": [
+                {"role": "developer", "content": "简洁回答"},
+                {"role": "user", "content": [{"type": "input_text", "text": "读 a.rs"}, {"type": "input_image", "image_url": "data:image/png;base64,aGk="}]},
+                {"type": "reasoning", "summary": []},
+                {"type": "function_call", "call_id": "call_1", "name": "read", "arguments": "{\"path\":\"a.rs\"}"},
+                {"type": "function_call", "call_id": "call_2", "name": "read", "arguments": "{\"path\":\"b.rs\"}"},
+                {"type": "function_call_output", "call_id": "call_1", "output": "fn a() {}"},
+                {"type": "function_call_output", "call_id": "call_2", "output": "fn b() {}"}
+            ]
+        }))
+        .to_canonical()
+        .unwrap();
+
+        assert_eq!(canonical.system, vec!["你是助手", "简洁回答"]);
+        assert_eq!(canonical.max_tokens, 1024);
+        assert!(canonical.thinking);
+        assert_eq!(canonical.tools.len(), 1);
+        assert_eq!(canonical.messages.len(), 3);
+        assert_eq!(
+            canonical.messages[0].content[1],
+            ContentBlock::Image {
+                media_type: "image/png".to_string(),
+                data: "aGk=".to_string()
+            }
+        );
+        // 连续的工具调用与结果分别合并为一条 assistant / user 消息
+        assert_eq!(canonical.messages[1].role, Role::Assistant);
+        assert_eq!(
+            canonical.messages[1].content[0],
+            ContentBlock::ToolUse(ToolCall {
+                id: "call_1".to_string(),
+                name: "read".to_string(),
+                input: json!({"path": "a.rs"}),
+            })
+        );
+        assert_eq!(canonical.messages[2].content.len(), 2);
+
+        let text = request(json!({"model": "m", "input": "你好"}))
+            .to_canonical()
+            .unwrap();
+        assert_eq!(
+            text.messages[0].content,
+            vec![ContentBlock::Text("你好".to_string())]
+        );
+
+        let invalid = request(json!({"model": "m", "input": [{"type": "computer_call"}]}));
+        assert!(matches!(
+            invalid.to_canonical(),
+            Err(CanonicalError::Block(_))
+        ));
+    }
+
+    #[test]
+    fn test_from_message() {
+        let response = from_message(&json!({
+            "id": "msg_abc",
+            "model": "claude-sonnet-4-5",
+            "content": [
+                {"type": "text", "text": "你好"},
+                {"type": "tool_use", "id": "toolu_1", "name": "read", "input": {"path": "a"}}
+            ],
+            "stop_reason": "max_tokens",
+            "usage": {"input_tokens": 10, "output_tokens": 5}
+        }));
+
+        assert_eq!(response["id"], "resp_abc");
+        assert_eq!(response["status"], "incomplete");
+        assert_eq!(
+            response["incomplete_details"]["reason"],
+            "max_output_tokens"
+        );
+        assert_eq!(response["output"][0]["content"][0]["text"], "你好");
+        assert_eq!(response["output"][1]["call_id"], "toolu_1");
+        assert_eq!(response["output"][1]["arguments"], "{\"path\":\"a\"}");
+        assert_eq!(response["usage"]["total_tokens"], 15);
+    }
+}
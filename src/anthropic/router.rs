@@ -7,11 +7,16 @@ use axum::{
     routing::{get, post},
 };
 
-use crate::kiro::provider::KiroProvider;
-
 use super::{
-    handlers::{count_tokens, get_models, post_messages, post_messages_cc},
-    middleware::{AppState, auth_middleware, cors_layer},
+    handlers::{
+        count_tokens, get_artifact, get_health, get_load_metrics, get_models,
+        get_prometheus_metrics, post_azure_chat_completions, post_dry_run, post_messages,
+        post_messages_cc, post_responses, post_session_token,
+    },
+    middleware::{
+        AppState, admission_middleware, auth_middleware, cors_layer, drain_middleware,
+        error_filter_middleware, in_flight_middleware, load_middleware, maintenance_middleware,
+    },
 };
 
 /// 请求体最大大小限制 (50MB)
@@ -23,54 +28,92 @@ const MAX_BODY_SIZE: usize = 50 * 1024 * 1024;
 /// - `GET /v1/models` - 获取可用模型列表
 /// - `POST /v1/messages` - 创建消息（对话）
 /// - `POST /v1/messages/count_tokens` - 计算 token 数量
+/// - `POST /v1/responses` - OpenAI Responses API 兼容端点
+/// - `POST /v1/dry-run` - 预演请求（返回将发送给上游的请求体与将使用的凭据，不调用上游）
+/// - `POST /v1/session-tokens` - 用 API Key 换取短期会话 Token（JWT）
+/// - `POST /openai/deployments/{deployment}/chat/completions` - Azure OpenAI 风格的 Chat Completions 端点
+/// - `GET /v1/artifacts/{id}` - 下载上游文件
+/// - `GET /metrics/load` - 负载指标（KEDA / HPA 外部指标）
+/// - `GET /metrics` - Prometheus 指标（按端点、模型、客户端分类）
+/// - `GET /health` - 健康检查（无可用凭据时为 degraded）
 ///
 /// # 认证
-/// 所有 `/v1` 路径需要 API Key 认证，支持：
+/// 除文件下载（凭签名链接访问）、指标和健康检查外，所有 `/v1`、`/openai` 路径需要 API Key
+/// （或会话 Token）认证，支持：
 /// - `x-api-key` header
+/// - `api-key` header（Azure OpenAI 约定）
 /// - `Authorization: Bearer <token>` header
 ///
 /// # 参数
-/// - `api_key`: API 密钥，用于验证客户端请求
-/// - `kiro_provider`: 可选的 KiroProvider，用于调用上游 API
-
-/// 创建带有 KiroProvider 的 Anthropic API 路由
-pub fn create_router_with_provider(
-    api_key: impl Into<String>,
-    kiro_provider: Option<KiroProvider>,
-    profile_arn: Option<String>,
-) -> Router {
-    let mut state = AppState::new(api_key);
-    if let Some(provider) = kiro_provider {
-        state = state.with_kiro_provider(provider);
-    }
-    if let Some(arn) = profile_arn {
-        state = state.with_profile_arn(arn);
-    }
-
+/// - `state`: 应用状态，包含 API 密钥、KiroProvider 等
+pub fn create_router(state: AppState) -> Router {
     // 需要认证的 /v1 路由
     let v1_routes = Router::new()
         .route("/models", get(get_models))
         .route("/messages", post(post_messages))
         .route("/messages/count_tokens", post(count_tokens))
-        .layer(middleware::from_fn_with_state(
-            state.clone(),
-            auth_middleware,
-        ));
+        .route("/responses", post(post_responses))
+        .route("/dry-run", post(post_dry_run))
+        .route("/session-tokens", post(post_session_token));
 
     // 需要认证的 /cc/v1 路由（Claude Code 兼容端点）
     // 与 /v1 的区别：流式响应会等待 contextUsageEvent 后再发送 message_start
     let cc_v1_routes = Router::new()
         .route("/messages", post(post_messages_cc))
-        .route("/messages/count_tokens", post(count_tokens))
-        .layer(middleware::from_fn_with_state(
-            state.clone(),
-            auth_middleware,
-        ));
+        .route("/messages/count_tokens", post(count_tokens));
+
+    // 需要认证的 /openai 路由（Azure OpenAI 兼容端点）
+    let azure_routes = Router::new().route(
+        "/deployments/{deployment}/chat/completions",
+        post(post_azure_chat_completions),
+    );
+
+    // 文件下载使用签名链接鉴权，不经过 API Key 认证
+    let artifact_routes = Router::new().route("/artifacts/{id}", get(get_artifact));
 
     Router::new()
-        .nest("/v1", v1_routes)
-        .nest("/cc/v1", cc_v1_routes)
+        .route("/health", get(get_health))
+        .route("/metrics", get(get_prometheus_metrics))
+        .route("/metrics/load", get(get_load_metrics))
+        .nest("/v1", protected(v1_routes, &state).merge(artifact_routes))
+        .nest("/cc/v1", protected(cc_v1_routes, &state))
+        .nest("/openai", protected(azure_routes, &state))
         .layer(cors_layer())
         .layer(DefaultBodyLimit::max(MAX_BODY_SIZE))
         .with_state(state)
 }
+
+/// 为需要认证的路由加上中间件栈
+///
+/// 请求由外到内依次经过：维护模式 → 负载统计 → 错误过滤 → 认证 → 下游写出统计 → 进行中请求登记 → 准入队列
+fn protected(routes: Router<AppState>, state: &AppState) -> Router<AppState> {
+    routes
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            admission_middleware,
+        ))
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            in_flight_middleware,
+        ))
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            drain_middleware,
+        ))
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            auth_middleware,
+        ))
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            error_filter_middleware,
+        ))
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            load_middleware,
+        ))
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            maintenance_middleware,
+        ))
+}
@@ -0,0 +1,328 @@
+//! SSE 输出的规范一致性测试
+//!
+//! 按几类常见客户端解析 SSE 的实际规则各实现一个解析器，断言各渲染格式的输出都能被正确解析：
+//! - WHATWG 事件流规范（浏览器 EventSource、eventsource-parser、fetch-event-source）
+//! - anthropic-sdk-python / openai-python 共用的 `SSEDecoder`（按事件块切分后逐行 UTF-8 解码，
+//!   openai 遇到以 `[DONE]` 开头的 data 即停止，anthropic 遇到 `error` 事件即抛错）
+//! - 按行读取 `data: ` 前缀的简易客户端（LiteLLM 等自行解析 OpenAI 流的工具、curl 脚本），
+//!   要求每个事件只有一行 data、冒号后带空格、以 `data: [DONE]` 结束
+
+use serde_json::{Value, json};
+
+use super::render::{RenderFormat, StreamRenderer, sse_frame};
+use super::stream::SseEvent;
+
+/// 解析出的事件
+#[derive(Debug, Default, Clone, PartialEq)]
+struct Parsed {
+    event: Option<String>,
+    data: String,
+    id: Option<String>,
+    retry: Option<u64>,
+}
+
+/// 按字段名更新正在构建的事件（`data` 多行以 `\n` 连接）
+fn apply_field(current: &mut Parsed, has_data: &mut bool, line: &str) {
+    if line.starts_with(':') {
+        return;
+    }
+    let (field, value) = line.split_once(':').unwrap_or((line, ""));
+    let value = value.strip_prefix(' ').unwrap_or(value);
+    match field {
+        "event" => current.event = Some(value.to_string()),
+        "data" => {
+            if *has_data {
+                current.data.push('\n');
+            }
+            current.data.push_str(value);
+            *has_data = true;
+        }
+        "id" if !value.contains('\0') => current.id = Some(value.to_string()),
+        "retry" => current.retry = value.parse().ok().or(current.retry),
+        _ => {}
+    }
+}
+
+/// WHATWG 规范：整个流按 UTF-8 解码，行以 CRLF / CR / LF 结束，空行派发事件（无 data 时丢弃）
+fn parse_whatwg(chunks: &[Vec<u8>]) -> Vec<Parsed> {
+    let bytes: Vec<u8> = chunks.concat();
+    let text = std::str::from_utf8(&bytes).expect("流不是合法的 UTF-8");
+    let mut events = Vec::new();
+    let mut current = Parsed::default();
+    let mut has_data = false;
+    let mut rest = text;
+    while let Some(pos) = rest.find(['\r', '\n']) {
+        let line = &rest[..pos];
+        let skip = if rest[pos..].starts_with("\r\n") {
+            2
+        } else {
+            1
+        };
+        rest = &rest[pos + skip..];
+        if line.is_empty() {
+            if has_data {
+                events.push(std::mem::take(&mut current));
+            } else {
+                current = Parsed::default();
+            }
+            has_data = false;
+        } else {
+            apply_field(&mut current, &mut has_data, line);
+        }
+    }
+    assert!(rest.is_empty(), "流末尾有未结束的行: {:?}", rest);
+    events
+}
+
+/// Python SDK 的 `SSEDecoder`：按字节块累积到连续两个换行，再逐行解码（每行单独做 UTF-8 解码）
+fn parse_python_sdk(chunks: &[Vec<u8>]) -> Vec<Parsed> {
+    let mut events = Vec::new();
+    let mut buffer = Vec::new();
+    for chunk in chunks {
+        buffer.extend_from_slice(chunk);
+        while let Some(end) = buffer.windows(2).position(|w| w == b"\n\n") {
+            let block: Vec<u8> = buffer.drain(..end + 2).collect();
+            let mut current = Parsed::default();
+            let mut has_data = false;
+            for line in block.split(|b| *b == b'\n') {
+                let line = std::str::from_utf8(line).expect("行被切断在 UTF-8 字符中间");
+                if !line.is_empty() {
+                    apply_field(&mut current, &mut has_data, line);
+                }
+            }
+            if has_data || current.event.is_some() {
+                events.push(current);
+            }
+        }
+    }
+    events
+}
+
+/// 按行读取 `data: ` 的简易客户端：每行 data 是一个独立的 JSON，读到 `[DONE]` 即停止
+fn parse_line_reader(chunks: &[Vec<u8>]) -> (Vec<Value>, bool) {
+    let bytes: Vec<u8> = chunks.concat();
+    let text = String::from_utf8(bytes).unwrap();
+    let mut payloads = Vec::new();
+    for line in text.split('\n') {
+        let Some(payload) = line.strip_prefix("data: ") else {
+            continue;
+        };
+        if payload == "[DONE]" {
+            return (payloads, true);
+        }
+        payloads.push(serde_json::from_str(payload).expect("data 行不是完整的 JSON"));
+    }
+    (payloads, false)
+}
+
+/// 包含多字节字符、换行与工具调用的事件流，`error` 为 true 时在文本后以 error 事件中止
+fn events(error: bool) -> Vec<SseEvent> {
+    let mut events = vec![
+        SseEvent::new(
+            "message_start",
+            json!({"type": "message_start", "message": {"id": "msg_1", "model": "claude-sonnet-4-5"}}),
+        ),
+        SseEvent::new(
+            "content_block_start",
+            json!({"type": "content_block_start", "index": 0, "content_block": {"type": "text", "text": ""}}),
+        ),
+    ];
+    for text in [
+        "你好，",
+        "世界 🎉\n",
+        "第二行\r\n: 不是注释",
+        "\n\ndata: 也不是事件",
+    ] {
+        events.push(SseEvent::new(
+            "content_block_delta",
+            json!({"type": "content_block_delta", "index": 0, "delta": {"type": "text_delta", "text": text}}),
+        ));
+    }
+    if error {
+        events.push(SseEvent::new(
+            "error",
+            json!({"type": "error", "error": {"type": "overloaded_error", "message": "上游卡顿"}}),
+        ));
+    }
+    events.extend([
+        SseEvent::new(
+            "content_block_stop",
+            json!({"type": "content_block_stop", "index": 0}),
+        ),
+        SseEvent::new(
+            "content_block_start",
+            json!({"type": "content_block_start", "index": 1, "content_block": {"type": "tool_use", "id": "toolu_1", "name": "write", "input": {}}}),
+        ),
+        SseEvent::new(
+            "content_block_delta",
+            json!({"type": "content_block_delta", "index": 1, "delta": {"type": "input_json_delta", "partial_json": "{\"text\":\"多行\\n内容\"}"}}),
+        ),
+        SseEvent::new(
+            "content_block_stop",
+            json!({"type": "content_block_stop", "index": 1}),
+        ),
+        SseEvent::new(
+            "message_delta",
+            json!({"type": "message_delta", "delta": {"stop_reason": "tool_use"}, "usage": {"input_tokens": 10, "output_tokens": 5}}),
+        ),
+        SseEvent::new("message_stop", json!({"type": "message_stop"})),
+    ]);
+    events
+}
+
+const TEXT: &str = "你好，世界 🎉\n第二行\r\n: 不是注释\n\ndata: 也不是事件";
+
+/// 渲染后的字节块（中间插入一次保活）
+fn render(format: RenderFormat, error: bool) -> Vec<Vec<u8>> {
+    let mut renderer = StreamRenderer::new(format);
+    let mut chunks = Vec::new();
+    for (i, event) in events(error).iter().enumerate() {
+        if i == 2 {
+            chunks.extend(renderer.ping().map(|b| b.to_vec()));
+        }
+        chunks.extend(renderer.render(event).map(String::into_bytes));
+    }
+    chunks.extend(renderer.ping().map(|b| b.to_vec()));
+    chunks
+}
+
+fn json_events(parsed: &[Parsed]) -> Vec<Value> {
+    parsed
+        .iter()
+        .map(|e| serde_json::from_str(&e.data).expect("data 不是 JSON"))
+        .collect()
+}
+
+#[test]
+fn test_chunks_are_whole_events() {
+    for format in [
+        RenderFormat::Anthropic,
+        RenderFormat::OpenAi,
+        RenderFormat::OpenAiResponses,
+    ] {
+        for chunk in render(format, false) {
+            let text = std::str::from_utf8(&chunk).expect("字节块不是合法的 UTF-8");
+            assert!(text.ends_with("\n\n"), "{:?}: {:?}", format, text);
+            assert!(!text.contains('\r'));
+            // 每个字节块单独解析也是完整的事件
+            assert!(
+                !parse_whatwg(std::slice::from_ref(&chunk)).is_empty() || text.starts_with(':')
+            );
+        }
+    }
+}
+
+#[test]
+fn test_anthropic_with_sdk_parsers() {
+    let chunks = render(RenderFormat::Anthropic, false);
+    let whatwg = parse_whatwg(&chunks);
+    let python = parse_python_sdk(&chunks);
+    assert_eq!(whatwg, python);
+    assert!(whatwg.iter().all(|e| e.id.is_none() && e.retry.is_none()));
+
+    let data = json_events(&whatwg);
+    for (event, data) in whatwg.iter().zip(&data) {
+        assert_eq!(event.event.as_deref(), data["type"].as_str());
+    }
+    let text: String = data
+        .iter()
+        .filter_map(|d| d["delta"]["text"].as_str())
+        .collect();
+    assert_eq!(text, TEXT);
+    assert_eq!(
+        whatwg.last().unwrap().event.as_deref(),
+        Some("message_stop")
+    );
+}
+
+#[test]
+fn test_openai_with_sdk_parsers() {
+    let chunks = render(RenderFormat::OpenAi, false);
+    let whatwg = parse_whatwg(&chunks);
+    assert_eq!(whatwg, parse_python_sdk(&chunks));
+    // [DONE] 恰好一次且在最后
+    let done: Vec<_> = whatwg.iter().filter(|e| e.data == "[DONE]").collect();
+    assert_eq!(done.len(), 1);
+    assert_eq!(whatwg.last().unwrap().data, "[DONE]");
+    assert!(whatwg.iter().all(|e| e.event.is_none()));
+
+    let (payloads, finished) = parse_line_reader(&chunks);
+    assert!(finished);
+    assert_eq!(payloads, json_events(&whatwg[..whatwg.len() - 1]));
+    let text: String = payloads
+        .iter()
+        .filter_map(|p| p["choices"][0]["delta"]["content"].as_str())
+        .collect();
+    assert_eq!(text, TEXT);
+    let arguments: String = payloads
+        .iter()
+        .filter_map(|p| p["choices"][0]["delta"]["tool_calls"][0]["function"]["arguments"].as_str())
+        .collect();
+    assert_eq!(
+        serde_json::from_str::<Value>(&arguments).unwrap(),
+        json!({"text": "多行\n内容"})
+    );
+}
+
+#[test]
+fn test_responses_with_sdk_parsers() {
+    let chunks = render(RenderFormat::OpenAiResponses, false);
+    let whatwg = parse_whatwg(&chunks);
+    assert_eq!(whatwg, parse_python_sdk(&chunks));
+
+    let data = json_events(&whatwg);
+    for (i, (event, data)) in whatwg.iter().zip(&data).enumerate() {
+        assert_eq!(event.event.as_deref(), data["type"].as_str());
+        assert_eq!(data["sequence_number"], i);
+    }
+    let text: String = data
+        .iter()
+        .filter(|d| d["type"] == "response.output_text.delta")
+        .filter_map(|d| d["delta"].as_str())
+        .collect();
+    assert_eq!(text, TEXT);
+    assert_eq!(
+        whatwg.last().unwrap().event.as_deref(),
+        Some("response.completed")
+    );
+}
+
+#[test]
+fn test_error_terminates_stream() {
+    // Anthropic：error 之后不再有任何事件（包括保活）
+    let anthropic = parse_whatwg(&render(RenderFormat::Anthropic, true));
+    assert_eq!(anthropic.last().unwrap().event.as_deref(), Some("error"));
+
+    // OpenAI：error 之后紧跟 [DONE]，简易客户端能正常退出
+    let chunks = render(RenderFormat::OpenAi, true);
+    let openai = parse_whatwg(&chunks);
+    assert_eq!(openai.last().unwrap().data, "[DONE]");
+    assert_eq!(openai.iter().filter(|e| e.data == "[DONE]").count(), 1);
+    let (payloads, finished) = parse_line_reader(&chunks);
+    assert!(finished);
+    assert_eq!(
+        payloads.last().unwrap()["error"]["type"],
+        "overloaded_error"
+    );
+
+    let responses = parse_whatwg(&render(RenderFormat::OpenAiResponses, true));
+    assert_eq!(
+        responses.last().unwrap().event.as_deref(),
+        Some("response.failed")
+    );
+}
+
+#[test]
+fn test_sse_frame_splits_lines() {
+    let frame = sse_frame(Some("message"), "a\r\nb\rc\nd");
+    assert_eq!(
+        frame,
+        "event: message\ndata: a\ndata: b\ndata: c\ndata: d\n\n"
+    );
+    let parsed = parse_whatwg(&[frame.into_bytes()]);
+    assert_eq!(parsed[0].data, "a\nb\nc\nd");
+    assert_eq!(
+        parse_whatwg(&[sse_frame(None, "").into_bytes()])[0].data,
+        ""
+    );
+}
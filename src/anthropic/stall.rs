@@ -0,0 +1,495 @@
+//! 流式响应卡顿检测
+//!
+//! 上游流在生成中途可能长时间不再输出任何数据而连接不断开，客户端只能一直收到 ping。
+//! 开启 `stall` 后：
+//! - 首个数据块到达前卡顿：记录到对应凭据，切换凭据后透明重试
+//! - 之后卡顿：向客户端发送 SSE error 事件并结束流
+//!
+//! 无论是否开启，上游字节流都逐帧校验 Prelude/Message CRC，只向下游转发校验通过的完整帧：
+//! 首帧即损坏时透明重试；之后遇到损坏或无法识别的帧时跳过损坏的数据，在下一个有效的 Prelude
+//! 处重新同步并继续转发（计入 `kiro_stream_resyncs_total`），连续跳过超过 1 MiB 仍未找到有效帧时
+//! 以 `ReadError::Corrupted` 结束流。损坏的内容不会发给客户端。
+//!
+//! 换凭据重试只发生在转发任何数据之前（首个数据块读取失败、首帧损坏或上游无输出即结束，最多
+//! `streamFailoverRetries` 次）；已转发过数据后的失败直接结束流，客户端不会收到重复的部分输出。
+//! 请求以 `X-Kiro-Retry: none` 关闭重试时，上述换凭据重试与卡顿重试都不发生。
+
+use std::fmt;
+use std::future::Future;
+use std::time::Duration;
+
+use axum::http::HeaderMap;
+use bytes::{Buf, Bytes, BytesMut};
+use futures::stream::{self, BoxStream};
+use futures::{Stream, StreamExt};
+
+use super::stream::SseEvent;
+use crate::kiro::parser::error::ParseError;
+use crate::kiro::parser::frame;
+use crate::kiro::provider::{CallOptions, KiroProvider, RetryPolicy, ServedCredential};
+
+/// 读取上游流的错误
+#[derive(Debug)]
+pub enum ReadError {
+    /// 上游连接/读取失败
+    Upstream(reqwest::Error),
+    /// 连续超过指定时长没有数据
+    Stalled(Duration),
+    /// 上游事件流校验失败（CRC 不匹配或帧长度异常）
+    Corrupted(ParseError),
+    /// 合并的请求共用的上游流读取失败
+    Coalesced(String),
+}
+
+impl fmt::Display for ReadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Upstream(e) => write!(f, "{}", e),
+            Self::Stalled(d) => write!(f, "上游 {} 秒内无输出", d.as_secs()),
+            Self::Corrupted(e) => write!(f, "上游响应校验失败: {}", e),
+            Self::Coalesced(message) => f.write_str(message),
+        }
+    }
+}
+
+/// 带卡顿检测的上游字节流
+pub type UpstreamBody = BoxStream<'static, Result<Bytes, ReadError>>;
+
+/// 首个数据块是否表示上游在转发任何数据前就已失败（可安全地换凭据重试）
+///
+/// 卡顿由 [`open_watched`] 按 `stall.maxRetries` 单独重试，这里不再重复计数
+fn failed_before_first_frame(first: Option<&Result<Bytes, ReadError>>) -> bool {
+    matches!(
+        first,
+        None | Some(Err(ReadError::Upstream(_) | ReadError::Corrupted(_)))
+    )
+}
+
+/// 本次请求允许的换凭据重试次数（`RetryPolicy::Off` 时为 0）
+fn retries_for(configured: usize, options: &CallOptions) -> usize {
+    match options.retry {
+        RetryPolicy::Off => 0,
+        _ => configured,
+    }
+}
+
+/// 发起流式调用并返回经过帧校验与卡顿检测的字节流
+///
+/// 首个数据块到达前失败时换凭据重新调用（最多 `streamFailoverRetries` 次）；同时返回提供响应的凭据与
+/// 上游响应头
+pub async fn open_stream(
+    provider: &KiroProvider,
+    request_body: &str,
+    options: &CallOptions,
+) -> anyhow::Result<(UpstreamBody, Option<u64>, HeaderMap)> {
+    let max_retries = retries_for(
+        provider.token_manager().config().stream_failover_retries,
+        options,
+    );
+    failover(max_retries, options, |options| async move {
+        open_watched(provider, request_body, &options).await
+    })
+    .await
+}
+
+/// 调用 `open` 发起流式调用，首个数据块到达前失败时避开失败的凭据重试，最多 `max_retries` 次
+///
+/// 失败的凭据加入 `CallOptions::avoid_credentials`，各选择策略下都会换到其他凭据（如果有）
+async fn failover<F, Fut>(
+    max_retries: usize,
+    options: &CallOptions,
+    mut open: F,
+) -> anyhow::Result<(UpstreamBody, Option<u64>, HeaderMap)>
+where
+    F: FnMut(CallOptions) -> Fut,
+    Fut: Future<Output = anyhow::Result<(UpstreamBody, Option<u64>, HeaderMap)>>,
+{
+    let mut options = options.clone();
+    let mut retries = 0;
+    loop {
+        let (mut body, credential_id, headers) = open(options.clone()).await?;
+        match body.next().await {
+            first if retries < max_retries && failed_before_first_frame(first.as_ref()) => {
+                retries += 1;
+                match first {
+                    Some(Err(e)) => tracing::warn!(
+                        "上游流在首个数据块前失败，切换凭据重试（{}/{}）: {}",
+                        retries,
+                        max_retries,
+                        e
+                    ),
+                    _ => tracing::warn!(
+                        "上游流未输出任何数据即结束，切换凭据重试（{}/{}）",
+                        retries,
+                        max_retries
+                    ),
+                }
+                if let Some(id) = credential_id {
+                    options = options.avoiding(id);
+                }
+            }
+            first => {
+                return Ok((
+                    stream::iter(first).chain(body).boxed(),
+                    credential_id,
+                    headers,
+                ));
+            }
+        }
+    }
+}
+
+/// 发起流式调用，未开启卡顿检测时直接透传（校验后的）上游字节流
+async fn open_watched(
+    provider: &KiroProvider,
+    request_body: &str,
+    options: &CallOptions,
+) -> anyhow::Result<(UpstreamBody, Option<u64>, HeaderMap)> {
+    let config = provider.token_manager().config().stall.clone();
+    if !config.enabled || config.timeout_secs == 0 {
+        let response = provider.call_api_stream(request_body, options).await?;
+        let credential_id = ServedCredential::of(&response);
+        let headers = response.headers().clone();
+        return Ok((verify(response.bytes_stream()), credential_id, headers));
+    }
+
+    let timeout = Duration::from_secs(config.timeout_secs);
+    let max_retries = retries_for(config.max_retries, options);
+    let mut options = options.clone();
+    let mut retries = 0;
+    loop {
+        let response = provider.call_api_stream(request_body, &options).await?;
+        let credential_id = ServedCredential::of(&response);
+        let headers = response.headers().clone();
+        let mut chunks = verify(response.bytes_stream());
+
+        match tokio::time::timeout(timeout, chunks.next()).await {
+            Ok(first) => {
+                let first = stream::iter(first);
+                return Ok((
+                    first.chain(watch(chunks, timeout)).boxed(),
+                    credential_id,
+                    headers,
+                ));
+            }
+            Err(_) => {
+                if let Some(id) = credential_id {
+                    provider.token_manager().report_stall(id);
+                }
+                if retries >= max_retries {
+                    let stalled = stream::iter([Err(ReadError::Stalled(timeout))]).boxed();
+                    return Ok((stalled, credential_id, headers));
+                }
+                retries += 1;
+                tracing::warn!(
+                    "上游 {} 秒内无首个数据块，切换凭据重试（{}/{}）",
+                    timeout.as_secs(),
+                    retries,
+                    max_retries
+                );
+                if let Some(id) = credential_id {
+                    options = options.avoiding(id);
+                }
+            }
+        }
+    }
+}
+
+/// 重新同步时最多连续跳过的字节数
+const MAX_RESYNC_SKIP: usize = 1024 * 1024;
+
+/// 校验中的上游字节流
+struct Verifier<S> {
+    chunks: std::pin::Pin<Box<S>>,
+    buffer: BytesMut,
+    /// 已转发的校验通过的字节数
+    forwarded: usize,
+    /// 正在重新同步时已跳过的字节数
+    skipped: Option<usize>,
+}
+
+/// 只转发校验通过的完整帧（drop 上游流即断开连接）
+///
+/// 首帧即损坏时产出一次 `Corrupted` 并结束，由 [`open_stream`] 重试；之后的损坏跳到下一个有效
+/// Prelude 重新同步，跳过超过 [`MAX_RESYNC_SKIP`] 字节时产出 `Corrupted` 并结束。
+fn verify<S>(chunks: S) -> UpstreamBody
+where
+    S: Stream<Item = reqwest::Result<Bytes>> + Send + 'static,
+{
+    let verifier = Verifier {
+        chunks: Box::pin(chunks),
+        buffer: BytesMut::new(),
+        forwarded: 0,
+        skipped: None,
+    };
+    stream::unfold(Some(verifier), |state| async move {
+        let mut v = state?;
+        let mut items = Vec::new();
+        while items.is_empty() {
+            match v.chunks.next().await {
+                Some(Ok(chunk)) => {
+                    v.buffer.extend_from_slice(&chunk);
+                    loop {
+                        let (len, error) = frame::verified_len(&v.buffer);
+                        if len > 0 {
+                            if let Some(skipped) = v.skipped.take() {
+                                tracing::info!("上游事件流已重新同步（跳过 {} 字节）", skipped);
+                            }
+                            v.forwarded += len;
+                            items.push(Ok(v.buffer.split_to(len).freeze()));
+                        }
+                        let Some(e) = error else { break };
+                        if v.forwarded == 0 {
+                            tracing::error!("上游事件流首帧校验失败，中止读取: {}", e);
+                            items.push(Err(ReadError::Corrupted(e)));
+                            return Some((stream::iter(items), None));
+                        }
+                        if v.skipped.is_none() {
+                            frame::record_resync();
+                            tracing::warn!("上游事件流出现损坏的帧，尝试重新同步: {}", e);
+                        }
+                        // 跳到下一个有效 Prelude；暂未找到时只保留末尾可能是 Prelude 开头的字节
+                        let skip = match frame::find_prelude(&v.buffer[1..]) {
+                            Some(offset) => offset + 1,
+                            None => v
+                                .buffer
+                                .len()
+                                .saturating_sub(frame::PRELUDE_SIZE - 1)
+                                .max(1),
+                        };
+                        v.buffer.advance(skip);
+                        let skipped = v.skipped.get_or_insert(0);
+                        *skipped += skip;
+                        if *skipped > MAX_RESYNC_SKIP {
+                            tracing::error!(
+                                "上游事件流跳过 {} 字节仍未重新同步，中止读取: {}",
+                                skipped,
+                                e
+                            );
+                            items.push(Err(ReadError::Corrupted(e)));
+                            return Some((stream::iter(items), None));
+                        }
+                    }
+                }
+                Some(Err(e)) => {
+                    if v.forwarded > 0 {
+                        tracing::warn!(
+                            "上游流在转发 {} 字节后读取失败，不再换凭据重试: {}",
+                            v.forwarded,
+                            e
+                        );
+                    }
+                    return Some((stream::iter(vec![Err(ReadError::Upstream(e))]), None));
+                }
+                None => {
+                    if let Some(skipped) = v.skipped {
+                        tracing::warn!("上游流在重新同步前结束（已跳过 {} 字节）", skipped);
+                    } else if !v.buffer.is_empty() {
+                        tracing::warn!("上游流以不完整的帧结束（{} 字节）", v.buffer.len());
+                    }
+                    return None;
+                }
+            }
+        }
+        Some((stream::iter(items), Some(v)))
+    })
+    .flatten()
+    .boxed()
+}
+
+/// 每个数据块之间最多等待 `timeout`，超时后产出一次 `Stalled` 并结束
+fn watch(chunks: UpstreamBody, timeout: Duration) -> UpstreamBody {
+    stream::unfold(Some(chunks), move |state| async move {
+        let mut chunks = state?;
+        match tokio::time::timeout(timeout, chunks.next()).await {
+            Ok(Some(item)) => Some((item, Some(chunks))),
+            Ok(None) => None,
+            Err(_) => {
+                tracing::warn!("上游流 {} 秒内无输出，中止", timeout.as_secs());
+                Some((Err(ReadError::Stalled(timeout)), None))
+            }
+        }
+    })
+    .boxed()
+}
+
+/// 卡顿中止时发送给客户端的 error 事件
+pub fn stalled_event(timeout: Duration) -> SseEvent {
+    SseEvent::new(
+        "error",
+        serde_json::json!({
+            "type": "error",
+            "error": {
+                "type": "api_error",
+                "message": format!("上游 {} 秒内无输出，流已中止", timeout.as_secs()),
+            }
+        }),
+    )
+}
+
+/// 上游响应校验失败中止时发送给客户端的 error 事件
+pub fn corrupted_event() -> SseEvent {
+    SseEvent::new(
+        "error",
+        serde_json::json!({
+            "type": "error",
+            "error": {
+                "type": "api_error",
+                "message": "上游响应校验失败，流已中止",
+            }
+        }),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kiro::parser::crc::crc32;
+
+    /// 无头部、带 payload 的最小帧
+    fn frame(payload: &[u8]) -> Vec<u8> {
+        let total = (16 + payload.len()) as u32;
+        let mut buffer = Vec::new();
+        buffer.extend_from_slice(&total.to_be_bytes());
+        buffer.extend_from_slice(&0u32.to_be_bytes());
+        buffer.extend_from_slice(&crc32(&buffer).to_be_bytes());
+        buffer.extend_from_slice(payload);
+        buffer.extend_from_slice(&crc32(&buffer).to_be_bytes());
+        buffer
+    }
+
+    #[tokio::test]
+    async fn test_verify_forwards_only_intact_frames() {
+        let first = frame(b"hello");
+        let mut corrupted = frame(b"world");
+        corrupted[13] ^= 0xff;
+        let (head, tail) = first.split_at(7);
+        let chunks = stream::iter([
+            Ok(Bytes::copy_from_slice(head)),
+            Ok(Bytes::copy_from_slice(tail)),
+            Ok(Bytes::from(corrupted)),
+        ]);
+        let mut body = verify(chunks);
+
+        assert_eq!(body.next().await.unwrap().unwrap(), Bytes::from(first));
+        // 首帧之后的损坏帧被跳过，不转发给下游
+        assert!(body.next().await.is_none());
+
+        // 跳过损坏的数据后在下一个有效帧重新同步
+        let resyncs = frame::resync_count();
+        let next = frame(b"again");
+        let (head, tail) = next.split_at(5);
+        let chunks = stream::iter([
+            Ok(Bytes::from(frame(b"hello"))),
+            Ok(Bytes::from_static(b"\x00\x00\x01garbage")),
+            Ok(Bytes::copy_from_slice(head)),
+            Ok(Bytes::copy_from_slice(tail)),
+        ]);
+        let forwarded: Vec<_> = verify(chunks).map(|r| r.unwrap()).collect().await;
+        assert_eq!(
+            forwarded,
+            vec![Bytes::from(frame(b"hello")), Bytes::from(next)]
+        );
+        assert!(frame::resync_count() > resyncs);
+
+        // 首帧即损坏：产出 Corrupted 由调用方重试
+        let mut corrupted = frame(b"world");
+        corrupted[13] ^= 0xff;
+        let mut body = verify(stream::iter([Ok(Bytes::from(corrupted))]));
+        assert!(matches!(
+            body.next().await,
+            Some(Err(ReadError::Corrupted(
+                ParseError::MessageCrcMismatch { .. }
+            )))
+        ));
+        assert!(body.next().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_watch_reports_stall() {
+        let timeout = Duration::from_millis(20);
+        let chunks = stream::iter([Ok(Bytes::from_static(b"a"))]).chain(stream::pending());
+        let mut body = watch(chunks.boxed(), timeout);
+
+        assert_eq!(
+            body.next().await.unwrap().unwrap(),
+            Bytes::from_static(b"a")
+        );
+        assert!(matches!(
+            body.next().await,
+            Some(Err(ReadError::Stalled(d))) if d == timeout
+        ));
+        assert!(body.next().await.is_none());
+    }
+
+    #[test]
+    fn test_failover_only_before_first_frame() {
+        let corrupted = ParseError::MessageCrcMismatch {
+            expected: 0,
+            actual: 1,
+        };
+        assert!(failed_before_first_frame(None));
+        assert!(failed_before_first_frame(Some(&Err(ReadError::Corrupted(
+            corrupted
+        )))));
+        assert!(!failed_before_first_frame(Some(&Ok(Bytes::from(frame(
+            b"hello"
+        ))))));
+        // 卡顿按 stall.maxRetries 单独处理
+        assert!(!failed_before_first_frame(Some(&Err(ReadError::Stalled(
+            Duration::from_secs(1)
+        )))));
+    }
+
+    #[tokio::test]
+    async fn test_failover_loop_honours_retry_policy() {
+        use std::sync::Mutex;
+
+        // 每次调用都在首个数据块前结束（依次由凭据 1、2、3 提供），记录每次调用避开的凭据
+        async fn run(options: &CallOptions, configured: usize) -> Vec<Vec<u64>> {
+            let opened = Mutex::new(Vec::new());
+            let (mut body, _, _) = failover(retries_for(configured, options), options, |options| {
+                let mut opened = opened.lock().unwrap();
+                opened.push(options.avoid_credentials);
+                let id = opened.len() as u64;
+                async move { Ok((stream::empty().boxed(), Some(id), HeaderMap::new())) }
+            })
+            .await
+            .unwrap();
+            assert!(body.next().await.is_none());
+            opened.into_inner().unwrap()
+        }
+
+        // 重试时避开之前失败的凭据
+        assert_eq!(
+            run(&CallOptions::default(), 2).await,
+            vec![vec![], vec![1], vec![1, 2]]
+        );
+        let off = CallOptions::default().with_retry(RetryPolicy::Off);
+        assert_eq!(run(&off, 2).await, vec![Vec::<u64>::new()]);
+
+        // 首个数据块正常时不重试
+        let opened = std::sync::atomic::AtomicUsize::new(0);
+        let (mut body, _, _) = failover(2, &CallOptions::default(), |_| {
+            opened.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+            async {
+                let chunks = stream::iter([Ok(Bytes::from(frame(b"hello")))]);
+                Ok((verify(chunks), Some(1), HeaderMap::new()))
+            }
+        })
+        .await
+        .unwrap();
+        assert_eq!(
+            body.next().await.unwrap().unwrap(),
+            Bytes::from(frame(b"hello"))
+        );
+        assert_eq!(opened.into_inner(), 1);
+    }
+
+    #[test]
+    fn test_stalled_sse_is_error_event() {
+        let text = stalled_event(Duration::from_secs(45)).to_sse_string();
+        assert!(text.starts_with("event: error\ndata: "));
+        assert!(text.contains("\"type\":\"api_error\""));
+    }
+}
@@ -0,0 +1,137 @@
+//! 停止原因映射
+//!
+//! 统一上游事件/异常到 Anthropic `stop_reason` 与 OpenAI `finish_reason` 的映射，
+//! 各响应路径都应通过这里取值，而不是各自硬编码字符串。
+//!
+//! | StopReason         | Anthropic `stop_reason` | OpenAI `finish_reason` |
+//! |--------------------|-------------------------|------------------------|
+//! | `EndTurn`          | `end_turn`              | `stop`                 |
+//! | `MaxTokens`        | `max_tokens`            | `length`               |
+//! | `ToolUse`          | `tool_use`              | `tool_calls`           |
+//! | `ContentFilter`    | `refusal`               | `content_filter`       |
+//! | `MaxTurnsExceeded` | `max_turns_exceeded`    | `length`               |
+//! | `Timeout`          | `timeout`               | `length`               |
+
+/// 规范化的停止原因
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StopReason {
+    /// 模型自然结束
+    EndTurn,
+    /// 达到输出长度/上下文上限
+    MaxTokens,
+    /// 模型请求工具调用
+    ToolUse,
+    /// 被内容安全策略拦截
+    ContentFilter,
+    /// 代理执行的工具轮次或时长超过上限
+    MaxTurnsExceeded,
+    /// 请求超过最长时长，返回的是部分内容
+    Timeout,
+}
+
+impl StopReason {
+    /// Anthropic `stop_reason` 取值
+    pub fn as_anthropic(&self) -> &'static str {
+        match self {
+            Self::EndTurn => "end_turn",
+            Self::MaxTokens => "max_tokens",
+            Self::ToolUse => "tool_use",
+            Self::ContentFilter => "refusal",
+            Self::MaxTurnsExceeded => "max_turns_exceeded",
+            Self::Timeout => "timeout",
+        }
+    }
+
+    /// OpenAI `finish_reason` 取值（供 OpenAI 兼容格式输出使用）
+    pub fn as_openai(&self) -> &'static str {
+        match self {
+            Self::EndTurn => "stop",
+            Self::MaxTokens | Self::MaxTurnsExceeded | Self::Timeout => "length",
+            Self::ToolUse => "tool_calls",
+            Self::ContentFilter => "content_filter",
+        }
+    }
+
+    /// 从 Anthropic `stop_reason` 取值解析
+    pub fn from_anthropic(value: &str) -> Option<Self> {
+        match value {
+            "end_turn" => Some(Self::EndTurn),
+            "max_tokens" => Some(Self::MaxTokens),
+            "tool_use" => Some(Self::ToolUse),
+            "refusal" => Some(Self::ContentFilter),
+            "max_turns_exceeded" => Some(Self::MaxTurnsExceeded),
+            "timeout" => Some(Self::Timeout),
+            _ => None,
+        }
+    }
+
+    /// 从上游异常类型推导停止原因
+    ///
+    /// 返回 None 表示该异常不影响停止原因
+    pub fn from_exception(exception_type: &str) -> Option<Self> {
+        match exception_type {
+            "ContentLengthExceededException" => Some(Self::MaxTokens),
+            t if t.contains("Guardrail") || t.contains("ContentFilter") => {
+                Some(Self::ContentFilter)
+            }
+            _ => None,
+        }
+    }
+
+    /// 结合是否发生工具调用，得出最终停止原因
+    ///
+    /// 显式原因（如 max_tokens）优先；否则有工具调用时为 tool_use，其余为 end_turn
+    pub fn resolve(explicit: Option<Self>, has_tool_use: bool) -> Self {
+        match explicit {
+            Some(reason) => reason,
+            None if has_tool_use => Self::ToolUse,
+            None => Self::EndTurn,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mapping_table() {
+        let table = [
+            (StopReason::EndTurn, "end_turn", "stop"),
+            (StopReason::MaxTokens, "max_tokens", "length"),
+            (StopReason::ToolUse, "tool_use", "tool_calls"),
+            (StopReason::ContentFilter, "refusal", "content_filter"),
+            (StopReason::MaxTurnsExceeded, "max_turns_exceeded", "length"),
+            (StopReason::Timeout, "timeout", "length"),
+        ];
+        for (reason, anthropic, openai) in table {
+            assert_eq!(reason.as_anthropic(), anthropic);
+            assert_eq!(reason.as_openai(), openai);
+            assert_eq!(StopReason::from_anthropic(anthropic), Some(reason));
+        }
+    }
+
+    #[test]
+    fn test_from_exception() {
+        assert_eq!(
+            StopReason::from_exception("ContentLengthExceededException"),
+            Some(StopReason::MaxTokens)
+        );
+        assert_eq!(
+            StopReason::from_exception("GuardrailInterventionException"),
+            Some(StopReason::ContentFilter)
+        );
+        assert_eq!(StopReason::from_exception("ThrottlingException"), None);
+    }
+
+    #[test]
+    fn test_resolve() {
+        assert_eq!(StopReason::resolve(None, false), StopReason::EndTurn);
+        assert_eq!(StopReason::resolve(None, true), StopReason::ToolUse);
+        // 显式 max_tokens 优先于工具调用
+        assert_eq!(
+            StopReason::resolve(Some(StopReason::MaxTokens), true),
+            StopReason::MaxTokens
+        );
+    }
+}
@@ -8,6 +8,10 @@ use serde_json::json;
 use uuid::Uuid;
 
 use crate::kiro::model::events::Event;
+use crate::kiro::parser::text::TextJoiner;
+
+use super::render::sse_frame;
+use super::stop_reason::StopReason;
 
 /// 找到小于等于目标位置的最近有效UTF-8字符边界
 ///
@@ -188,10 +192,9 @@ impl SseEvent {
 
     /// 格式化为 SSE 字符串
     pub fn to_sse_string(&self) -> String {
-        format!(
-            "event: {}\ndata: {}\n\n",
-            self.event,
-            serde_json::to_string(&self.data).unwrap_or_default()
+        sse_frame(
+            Some(&self.event),
+            &serde_json::to_string(&self.data).unwrap_or_default(),
         )
     }
 }
@@ -233,8 +236,8 @@ pub struct SseStateManager {
     message_ended: bool,
     /// 下一个块索引
     next_block_index: i32,
-    /// 当前 stop_reason
-    stop_reason: Option<String>,
+    /// 当前 stop_reason（显式设置的原因，如 max_tokens）
+    stop_reason: Option<StopReason>,
     /// 是否有工具调用
     has_tool_use: bool,
 }
@@ -278,19 +281,13 @@ impl SseStateManager {
     }
 
     /// 设置 stop_reason
-    pub fn set_stop_reason(&mut self, reason: impl Into<String>) {
-        self.stop_reason = Some(reason.into());
+    pub fn set_stop_reason(&mut self, reason: StopReason) {
+        self.stop_reason = Some(reason);
     }
 
     /// 获取最终的 stop_reason
-    pub fn get_stop_reason(&self) -> String {
-        if let Some(ref reason) = self.stop_reason {
-            reason.clone()
-        } else if self.has_tool_use {
-            "tool_use".to_string()
-        } else {
-            "end_turn".to_string()
-        }
+    pub fn get_stop_reason(&self) -> StopReason {
+        StopReason::resolve(self.stop_reason, self.has_tool_use)
     }
 
     /// 处理 message_start 事件
@@ -422,7 +419,7 @@ impl SseStateManager {
                 json!({
                     "type": "message_delta",
                     "delta": {
-                        "stop_reason": self.get_stop_reason(),
+                        "stop_reason": self.get_stop_reason().as_anthropic(),
                         "stop_sequence": null
                     },
                     "usage": {
@@ -477,6 +474,8 @@ pub struct StreamContext {
     pub thinking_block_index: Option<i32>,
     /// 文本块索引（thinking 启用时动态分配）
     pub text_block_index: Option<i32>,
+    /// 拼接被拆到相邻事件中的多字节字符
+    text_joiner: TextJoiner,
 }
 
 impl StreamContext {
@@ -500,6 +499,7 @@ impl StreamContext {
             thinking_extracted: false,
             thinking_block_index: None,
             text_block_index: None,
+            text_joiner: TextJoiner::default(),
         }
     }
 
@@ -565,7 +565,10 @@ impl StreamContext {
     /// 处理 Kiro 事件并转换为 Anthropic SSE 事件
     pub fn process_kiro_event(&mut self, event: &Event) -> Vec<SseEvent> {
         match event {
-            Event::AssistantResponse(resp) => self.process_assistant_response(&resp.content),
+            Event::AssistantResponse(resp) => {
+                let text = resp.join(&mut self.text_joiner);
+                self.process_assistant_response(&text)
+            }
             Event::ToolUse(tool_use) => self.process_tool_use(tool_use),
             Event::ContextUsage(context_usage) => {
                 // 从上下文使用百分比计算实际的 input_tokens
@@ -592,17 +595,32 @@ impl StreamContext {
                 exception_type,
                 message,
             } => {
-                // 处理 ContentLengthExceededException
-                if exception_type == "ContentLengthExceededException" {
-                    self.state_manager.set_stop_reason("max_tokens");
+                // 处理影响停止原因的异常（如 ContentLengthExceededException）
+                if let Some(reason) = StopReason::from_exception(exception_type) {
+                    self.state_manager.set_stop_reason(reason);
                 }
                 tracing::warn!("收到异常事件: {} - {}", exception_type, message);
                 Vec::new()
             }
+            Event::Unknown {
+                event_type,
+                payload,
+            } => {
+                tracing::debug!("忽略未知事件: {} ({} bytes)", event_type, payload.len());
+                Vec::new()
+            }
             _ => Vec::new(),
         }
     }
 
+    /// 输出已保存文件的下载链接文本
+    ///
+    /// 不经过 thinking 解析，始终作为普通文本输出
+    pub fn process_artifact_text(&mut self, text: &str) -> Vec<SseEvent> {
+        self.output_tokens += estimate_tokens(text);
+        self.create_text_delta_events(text)
+    }
+
     /// 处理助手响应事件
     fn process_assistant_response(&mut self, content: &str) -> Vec<SseEvent> {
         if content.is_empty() {
@@ -935,9 +953,24 @@ impl StreamContext {
         events
     }
 
+    /// 设置停止原因（如流式请求超过最长时长时为 `timeout`）
+    pub fn set_stop_reason(&mut self, reason: StopReason) {
+        self.state_manager.set_stop_reason(reason);
+    }
+
+    /// 目前为止的 (input_tokens, output_tokens)
+    pub fn tokens(&self) -> (i32, i32) {
+        (
+            self.context_input_tokens.unwrap_or(self.input_tokens),
+            self.output_tokens,
+        )
+    }
+
     /// 生成最终事件序列
     pub fn generate_final_events(&mut self) -> Vec<SseEvent> {
-        let mut events = Vec::new();
+        // 输出末尾暂存的不完整字符
+        let tail = self.text_joiner.finish();
+        let mut events = self.process_assistant_response(&tail);
 
         // Flush thinking_buffer 中的剩余内容
         if self.thinking_enabled && !self.thinking_buffer.is_empty() {
@@ -1050,16 +1083,32 @@ impl BufferedStreamContext {
     ///
     /// 复用 StreamContext 的事件处理逻辑，但把结果缓存而不是立即发送。
     pub fn process_and_buffer(&mut self, event: &crate::kiro::model::events::Event) {
-        // 首次处理事件时，先生成初始事件（message_start 等）
+        self.ensure_initial_events();
+
+        // 处理事件并缓冲结果
+        let events = self.inner.process_kiro_event(event);
+        self.event_buffer.extend(events);
+    }
+
+    /// 缓冲已保存文件的下载链接文本
+    pub fn buffer_artifact_text(&mut self, text: &str) {
+        self.ensure_initial_events();
+        let events = self.inner.process_artifact_text(text);
+        self.event_buffer.extend(events);
+    }
+
+    /// 首次处理事件时，先生成初始事件（message_start 等）
+    fn ensure_initial_events(&mut self) {
         if !self.initial_events_generated {
             let initial_events = self.inner.generate_initial_events();
             self.event_buffer.extend(initial_events);
             self.initial_events_generated = true;
         }
+    }
 
-        // 处理事件并缓冲结果
-        let events = self.inner.process_kiro_event(event);
-        self.event_buffer.extend(events);
+    /// 设置停止原因
+    pub fn set_stop_reason(&mut self, reason: StopReason) {
+        self.inner.set_stop_reason(reason);
     }
 
     /// 完成流处理并返回所有事件
@@ -1070,11 +1119,7 @@ impl BufferedStreamContext {
     /// 3. 返回所有缓冲的事件
     pub fn finish_and_get_all_events(&mut self) -> Vec<SseEvent> {
         // 如果从未处理过事件，也要生成初始事件
-        if !self.initial_events_generated {
-            let initial_events = self.inner.generate_initial_events();
-            self.event_buffer.extend(initial_events);
-            self.initial_events_generated = true;
-        }
+        self.ensure_initial_events();
 
         // 生成最终事件
         let final_events = self.inner.generate_final_events();
@@ -1099,6 +1144,16 @@ impl BufferedStreamContext {
 
         std::mem::take(&mut self.event_buffer)
     }
+
+    /// 目前为止的 (input_tokens, output_tokens)
+    pub fn tokens(&self) -> (i32, i32) {
+        (
+            self.inner
+                .context_input_tokens
+                .unwrap_or(self.estimated_input_tokens),
+            self.inner.output_tokens,
+        )
+    }
 }
 
 /// 简单的 token 估算
@@ -1,5 +1,6 @@
 //! Anthropic API 类型定义
 
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
@@ -49,6 +50,28 @@ pub struct Model {
     #[serde(rename = "type")]
     pub model_type: String,
     pub max_tokens: i32,
+    /// 上下文窗口（tokens，与 OpenRouter 的字段名一致）
+    pub context_length: u64,
+    pub capabilities: ModelCapabilities,
+    pub pricing: ModelPricing,
+}
+
+/// 模型能力
+#[derive(Debug, Serialize)]
+pub struct ModelCapabilities {
+    pub streaming: bool,
+    pub tool_use: bool,
+    pub vision: bool,
+    pub thinking: bool,
+}
+
+/// 模型价格（OpenRouter 风格，单价为每 token 的美元字符串；未启用成本估算时为 "0" 占位）
+#[derive(Debug, Serialize)]
+pub struct ModelPricing {
+    pub prompt: String,
+    pub completion: String,
+    /// 每次请求消耗的 Kiro 请求次数
+    pub requests: f64,
 }
 
 /// 模型列表响应
@@ -64,7 +87,7 @@ pub struct ModelsResponse {
 const MAX_BUDGET_TOKENS: i32 = 24576;
 
 /// Thinking 配置
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct Thinking {
     #[serde(rename = "type")]
     pub thinking_type: String,
@@ -75,7 +98,7 @@ pub struct Thinking {
     pub budget_tokens: i32,
 }
 
-fn default_budget_tokens() -> i32 {
+pub(crate) fn default_budget_tokens() -> i32 {
     20000
 }
 fn deserialize_budget_tokens<'de, D>(deserializer: D) -> Result<i32, D::Error>
@@ -87,27 +110,37 @@ where
 }
 
 /// Claude Code 请求中的 metadata
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct Metadata {
     /// 用户 ID，格式如: user_xxx_account__session_0b4445e1-f5be-49e1-87ce-62bbc28ad705
     pub user_id: Option<String>,
 }
 
 /// Messages 请求体
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Serialize)]
 pub struct MessagesRequest {
     pub model: String,
+    /// 缺省时为 0，由客户端密钥的默认值补齐
+    #[serde(default)]
     pub max_tokens: i32,
     pub messages: Vec<Message>,
     #[serde(default)]
     pub stream: bool,
-    #[serde(default, deserialize_with = "deserialize_system")]
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        deserialize_with = "deserialize_system"
+    )]
     pub system: Option<Vec<SystemMessage>>,
     pub tools: Option<Vec<Tool>>,
     pub tool_choice: Option<serde_json::Value>,
     pub thinking: Option<Thinking>,
     /// Claude Code 请求中的 metadata，包含 session 信息
     pub metadata: Option<Metadata>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stop_sequences: Option<Vec<String>>,
 }
 
 /// 反序列化 system 字段，支持字符串或数组格式
@@ -272,3 +305,39 @@ pub struct CountTokensRequest {
 pub struct CountTokensResponse {
     pub input_tokens: i32,
 }
+
+// === 会话 Token 端点类型 ===
+
+/// 换取会话 Token 的请求（各字段只能收窄原密钥的范围）
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionTokenRequest {
+    /// 有效期（秒），默认 `sessionTokens.defaultTtlSecs`
+    #[serde(default)]
+    pub ttl_secs: Option<u64>,
+    /// 允许的模型（以 `*` 结尾按前缀匹配），为空时沿用原密钥的范围
+    #[serde(default)]
+    pub models: Vec<String>,
+    /// max_tokens 上限
+    #[serde(default)]
+    pub max_tokens: Option<i32>,
+}
+
+/// 会话 Token 响应
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionTokenResponse {
+    pub token: String,
+    pub token_type: &'static str,
+    pub expires_at: DateTime<Utc>,
+    pub expires_in: u64,
+}
+
+// === Artifacts 端点类型 ===
+
+/// 文件下载链接的签名参数
+#[derive(Debug, Deserialize)]
+pub struct ArtifactDownloadQuery {
+    pub expires: i64,
+    pub signature: String,
+}
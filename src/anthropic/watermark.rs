@@ -0,0 +1,104 @@
+//! 响应水印
+//!
+//! 按 `watermark.template` 渲染一段标记，追加到正常完成的响应末尾。
+//! `invisible` 时把标记的 UTF-8 字节编码为零宽字符，肉眼不可见但会随复制的文本一起保留。
+
+use chrono::Utc;
+
+use crate::model::config::WatermarkConfig;
+
+/// 零宽水印的起止标记（WORD JOINER）
+const INVISIBLE_FENCE: char = '\u{2060}';
+/// 比特 0（ZERO WIDTH SPACE）
+const INVISIBLE_ZERO: char = '\u{200B}';
+/// 比特 1（ZERO WIDTH NON-JOINER）
+const INVISIBLE_ONE: char = '\u{200C}';
+
+/// 渲染水印，未启用或渲染结果为空时返回 None
+pub fn render(config: &WatermarkConfig, client: &str, model: &str) -> Option<String> {
+    if !config.enabled {
+        return None;
+    }
+    let text = config
+        .template
+        .replace("{client}", client)
+        .replace("{model}", model)
+        .replace("{date}", &Utc::now().format("%Y-%m-%d").to_string());
+    if text.is_empty() {
+        return None;
+    }
+    Some(if config.invisible {
+        encode_invisible(&text)
+    } else {
+        text
+    })
+}
+
+/// 把文本编码为零宽字符序列
+fn encode_invisible(text: &str) -> String {
+    let mut out = String::with_capacity(text.len() * 8 * 3 + 6);
+    out.push(INVISIBLE_FENCE);
+    for byte in text.bytes() {
+        for bit in (0..8).rev() {
+            out.push(if byte >> bit & 1 == 1 {
+                INVISIBLE_ONE
+            } else {
+                INVISIBLE_ZERO
+            });
+        }
+    }
+    out.push(INVISIBLE_FENCE);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 从文本中提取零宽水印
+    fn decode_invisible(text: &str) -> Option<String> {
+        let start = text.find(INVISIBLE_FENCE)? + INVISIBLE_FENCE.len_utf8();
+        let end = start + text[start..].find(INVISIBLE_FENCE)?;
+        let bits: Vec<u8> = text[start..end]
+            .chars()
+            .map(|c| (c == INVISIBLE_ONE) as u8)
+            .collect();
+        let bytes = bits
+            .chunks(8)
+            .map(|chunk| chunk.iter().fold(0u8, |acc, b| acc << 1 | b))
+            .collect();
+        String::from_utf8(bytes).ok()
+    }
+
+    #[test]
+    fn test_render_template() {
+        let mut config = WatermarkConfig {
+            enabled: true,
+            template: " [{client}/{model}]".to_string(),
+            invisible: false,
+        };
+        assert_eq!(
+            render(&config, "team-a", "claude-sonnet-4-5").as_deref(),
+            Some(" [team-a/claude-sonnet-4-5]")
+        );
+
+        config.enabled = false;
+        assert!(render(&config, "team-a", "m").is_none());
+    }
+
+    #[test]
+    fn test_invisible_round_trip() {
+        let config = WatermarkConfig {
+            enabled: true,
+            template: "kiro-rs:{client}".to_string(),
+            invisible: true,
+        };
+        let mark = render(&config, "团队", "m").unwrap();
+        assert!(
+            mark.chars()
+                .all(|c| matches!(c, INVISIBLE_FENCE | INVISIBLE_ZERO | INVISIBLE_ONE))
+        );
+        let text = format!("回答内容{}", mark);
+        assert_eq!(decode_invisible(&text).as_deref(), Some("kiro-rs:团队"));
+    }
+}
@@ -13,64 +13,26 @@ use bytes::Bytes;
 use futures::{Stream, stream};
 use serde::{Deserialize, Serialize};
 use serde_json::json;
+use std::time::Duration;
 use uuid::Uuid;
 
+use crate::common::log_content::{clip, log_error};
+use crate::kiro::model::mcp::{JsonRpcId, JsonRpcRequest, McpToolCallParams, McpToolResult};
+use crate::kiro::provider::CallOptions;
+use crate::model::config::ToolLoopConfig;
+
+use super::stop_reason::StopReason;
 use super::stream::SseEvent;
 use super::types::{ErrorResponse, MessagesRequest};
 
-/// MCP 请求
-#[derive(Debug, Serialize)]
-pub struct McpRequest {
-    pub id: String,
-    pub jsonrpc: String,
-    pub method: String,
-    pub params: McpParams,
-}
-
-/// MCP 请求参数
-#[derive(Debug, Serialize)]
-pub struct McpParams {
-    pub name: String,
-    pub arguments: McpArguments,
-}
-
-/// MCP 参数
-#[derive(Debug, Serialize)]
+/// WebSearch 工具参数
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct McpArguments {
     pub query: String,
 }
 
-/// MCP 响应
-#[derive(Debug, Deserialize)]
-pub struct McpResponse {
-    pub error: Option<McpError>,
-    pub id: String,
-    pub jsonrpc: String,
-    pub result: Option<McpResult>,
-}
-
-/// MCP 错误
-#[derive(Debug, Deserialize)]
-pub struct McpError {
-    pub code: Option<i32>,
-    pub message: Option<String>,
-}
-
-/// MCP 结果
-#[derive(Debug, Deserialize)]
-pub struct McpResult {
-    pub content: Vec<McpContent>,
-    #[serde(rename = "isError")]
-    pub is_error: bool,
-}
-
-/// MCP 内容
-#[derive(Debug, Deserialize)]
-pub struct McpContent {
-    #[serde(rename = "type")]
-    pub content_type: String,
-    pub text: String,
-}
+/// WebSearch 的 MCP `tools/call` 请求
+pub type McpRequest = JsonRpcRequest<McpToolCallParams<McpArguments>>;
 
 /// WebSearch 搜索结果
 #[derive(Debug, Deserialize)]
@@ -107,6 +69,34 @@ pub fn has_web_search_tool(req: &MessagesRequest) -> bool {
     })
 }
 
+/// 统计对话中已由代理执行过的 WebSearch 轮次
+pub fn count_executed_rounds(req: &MessagesRequest) -> usize {
+    req.messages
+        .iter()
+        .filter(|m| m.role == "assistant")
+        .filter_map(|m| m.content.as_array())
+        .flatten()
+        .filter(|b| b.get("type").and_then(|t| t.as_str()) == Some("web_search_tool_result"))
+        .count()
+}
+
+/// 计算本对话允许的最大轮次（取配置与工具 max_uses 的较小值）
+fn effective_max_rounds(req: &MessagesRequest, config: &ToolLoopConfig) -> usize {
+    let max_uses = req
+        .tools
+        .iter()
+        .flatten()
+        .find(|t| t.is_web_search() || t.name == "web_search")
+        .and_then(|t| t.max_uses)
+        .filter(|n| *n > 0)
+        .map(|n| n as usize);
+
+    match max_uses {
+        Some(n) => n.min(config.max_rounds),
+        None => config.max_rounds,
+    }
+}
+
 /// 从消息中提取搜索查询
 ///
 /// 读取 messages 的第一条消息的第一个内容块
@@ -182,24 +172,26 @@ pub fn create_mcp_request(query: &str) -> (String, McpRequest) {
         Uuid::new_v4().to_string().replace('-', "")[..32].to_string()
     );
 
-    let request = McpRequest {
-        id: request_id,
-        jsonrpc: "2.0".to_string(),
-        method: "tools/call".to_string(),
-        params: McpParams {
+    let request = JsonRpcRequest::new(
+        JsonRpcId::String(request_id),
+        "tools/call",
+        McpToolCallParams {
             name: "web_search".to_string(),
             arguments: McpArguments {
                 query: query.to_string(),
             },
         },
-    };
+    );
 
     (tool_use_id, request)
 }
 
 /// 解析 MCP 响应中的搜索结果
-pub fn parse_search_results(mcp_response: &McpResponse) -> Option<WebSearchResults> {
-    let result = mcp_response.result.as_ref()?;
+pub fn parse_search_results(result: &McpToolResult) -> Option<WebSearchResults> {
+    if result.is_error {
+        tracing::warn!("MCP 工具返回错误结果");
+        return None;
+    }
     let content = result.content.first()?;
 
     if content.content_type != "text" {
@@ -392,7 +384,7 @@ fn generate_websearch_events(
         json!({
             "type": "message_delta",
             "delta": {
-                "stop_reason": "end_turn",
+                "stop_reason": StopReason::EndTurn.as_anthropic(),
                 "stop_sequence": null
             },
             "usage": {
@@ -421,10 +413,9 @@ fn generate_search_summary(query: &str, results: &Option<WebSearchResults>) -> S
             summary.push_str(&format!("{}. **{}**\n", i + 1, result.title));
             if let Some(ref snippet) = result.snippet {
                 // 截断过长的摘要
-                let truncated = if snippet.len() > 200 {
-                    format!("{}...", &snippet[..200])
-                } else {
-                    snippet.clone()
+                let truncated = match snippet.char_indices().nth(200) {
+                    Some((idx, _)) => format!("{}...", &snippet[..idx]),
+                    None => snippet.clone(),
                 };
                 summary.push_str(&format!("   {}\n", truncated));
             }
@@ -439,11 +430,98 @@ fn generate_search_summary(query: &str, results: &Option<WebSearchResults>) -> S
     summary
 }
 
+/// 生成工具轮次/时长超限的 SSE 响应（stop_reason 为 max_turns_exceeded）
+fn max_turns_exceeded_response(model: &str, input_tokens: i32, message: &str) -> Response {
+    let events = generate_max_turns_exceeded_events(model, input_tokens, message);
+    let stream = stream::iter(
+        events
+            .into_iter()
+            .map(|e| Ok::<_, Infallible>(Bytes::from(e.to_sse_string()))),
+    );
+
+    Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, "text/event-stream")
+        .header(header::CACHE_CONTROL, "no-cache")
+        .header(header::CONNECTION, "keep-alive")
+        .body(Body::from_stream(stream))
+        .unwrap()
+}
+
+/// 生成超限事件序列：单个文本块说明原因，并以 max_turns_exceeded 结束
+fn generate_max_turns_exceeded_events(
+    model: &str,
+    input_tokens: i32,
+    message: &str,
+) -> Vec<SseEvent> {
+    let message_id = format!(
+        "msg_{}",
+        &Uuid::new_v4().to_string().replace('-', "")[..24]
+    );
+
+    vec![
+        SseEvent::new(
+            "message_start",
+            json!({
+                "type": "message_start",
+                "message": {
+                    "id": message_id,
+                    "type": "message",
+                    "role": "assistant",
+                    "model": model,
+                    "content": [],
+                    "stop_reason": null,
+                    "stop_sequence": null,
+                    "usage": {
+                        "input_tokens": input_tokens,
+                        "output_tokens": 0
+                    }
+                }
+            }),
+        ),
+        SseEvent::new(
+            "content_block_start",
+            json!({
+                "type": "content_block_start",
+                "index": 0,
+                "content_block": {"type": "text", "text": ""}
+            }),
+        ),
+        SseEvent::new(
+            "content_block_delta",
+            json!({
+                "type": "content_block_delta",
+                "index": 0,
+                "delta": {"type": "text_delta", "text": message}
+            }),
+        ),
+        SseEvent::new(
+            "content_block_stop",
+            json!({"type": "content_block_stop", "index": 0}),
+        ),
+        SseEvent::new(
+            "message_delta",
+            json!({
+                "type": "message_delta",
+                "delta": {
+                    "stop_reason": StopReason::MaxTurnsExceeded.as_anthropic(),
+                    "stop_sequence": null
+                },
+                "usage": {
+                    "output_tokens": (message.len() as i32 + 3) / 4
+                }
+            }),
+        ),
+        SseEvent::new("message_stop", json!({"type": "message_stop"})),
+    ]
+}
+
 /// 处理 WebSearch 请求
 pub async fn handle_websearch_request(
     provider: std::sync::Arc<crate::kiro::provider::KiroProvider>,
     payload: &MessagesRequest,
     input_tokens: i32,
+    options: &CallOptions,
 ) -> Response {
     // 1. 提取搜索查询
     let query = match extract_search_query(payload) {
@@ -460,21 +538,49 @@ pub async fn handle_websearch_request(
         }
     };
 
-    tracing::info!(query = %query, "处理 WebSearch 请求");
+    let limit = provider.log_limit(options);
+    tracing::info!(query = %clip(limit, &query), "处理 WebSearch 请求");
+
+    // 2. 检查工具轮次上限
+    let limits = provider.token_manager().config().tool_loop.clone();
+    let executed = count_executed_rounds(payload);
+    let max_rounds = effective_max_rounds(payload, &limits);
+    if executed >= max_rounds {
+        tracing::warn!("WebSearch 轮次已达上限: {}/{}", executed, max_rounds);
+        return max_turns_exceeded_response(
+            &payload.model,
+            input_tokens,
+            &format!("已达到工具调用轮次上限（{} 轮），未执行本次搜索。", max_rounds),
+        );
+    }
 
-    // 2. 创建 MCP 请求
+    // 3. 创建 MCP 请求
     let (tool_use_id, mcp_request) = create_mcp_request(&query);
 
-    // 3. 调用 Kiro MCP API
-    let search_results = match call_mcp_api(&provider, &mcp_request).await {
-        Ok(response) => parse_search_results(&response),
-        Err(e) => {
-            tracing::warn!("MCP API 调用失败: {}", e);
-            None
-        }
-    };
+    // 4. 调用 Kiro MCP API（受总时长上限约束）
+    let deadline = Duration::from_secs(limits.max_duration_secs);
+    let search_results =
+        match tokio::time::timeout(deadline, call_mcp_api(&provider, &mcp_request, options)).await
+        {
+            Ok(Ok(response)) => parse_search_results(&response),
+            Ok(Err(e)) => {
+                tracing::warn!("MCP API 调用失败: {}", log_error(limit, &e));
+                None
+            }
+            Err(_) => {
+                tracing::warn!("WebSearch 执行超时（{} 秒）", limits.max_duration_secs);
+                return max_turns_exceeded_response(
+                    &payload.model,
+                    input_tokens,
+                    &format!(
+                        "工具执行超过时长上限（{} 秒），已中止。",
+                        limits.max_duration_secs
+                    ),
+                );
+            }
+        };
 
-    // 4. 生成 SSE 响应
+    // 5. 生成 SSE 响应
     let model = payload.model.clone();
     let stream =
         create_websearch_sse_stream(model, query, tool_use_id, search_results, input_tokens);
@@ -492,27 +598,9 @@ pub async fn handle_websearch_request(
 async fn call_mcp_api(
     provider: &crate::kiro::provider::KiroProvider,
     request: &McpRequest,
-) -> anyhow::Result<McpResponse> {
-    let request_body = serde_json::to_string(request)?;
-
-    tracing::debug!("MCP request: {}", request_body);
-
-    let response = provider.call_mcp(&request_body).await?;
-
-    let body = response.text().await?;
-    tracing::debug!("MCP response: {}", body);
-
-    let mcp_response: McpResponse = serde_json::from_str(&body)?;
-
-    if let Some(ref error) = mcp_response.error {
-        anyhow::bail!(
-            "MCP error: {} - {}",
-            error.code.unwrap_or(-1),
-            error.message.as_deref().unwrap_or("Unknown error")
-        );
-    }
-
-    Ok(mcp_response)
+    options: &CallOptions,
+) -> anyhow::Result<McpToolResult> {
+    provider.call_mcp_typed(request, options).await
 }
 
 #[cfg(test)]
@@ -542,6 +630,8 @@ mod tests {
             tool_choice: None,
             thinking: None,
             metadata: None,
+            temperature: None,
+            stop_sequences: None,
         };
 
         assert!(has_web_search_tool(&req));
@@ -579,6 +669,8 @@ mod tests {
             tool_choice: None,
             thinking: None,
             metadata: None,
+            temperature: None,
+            stop_sequences: None,
         };
 
         // 多个工具时不应该被识别为纯 websearch 请求
@@ -605,6 +697,8 @@ mod tests {
             tool_choice: None,
             thinking: None,
             metadata: None,
+            temperature: None,
+            stop_sequences: None,
         };
 
         let query = extract_search_query(&req);
@@ -629,6 +723,8 @@ mod tests {
             tool_choice: None,
             thinking: None,
             metadata: None,
+            temperature: None,
+            stop_sequences: None,
         };
 
         let query = extract_search_query(&req);
@@ -642,11 +738,12 @@ mod tests {
         assert!(tool_use_id.starts_with("srvtoolu_"));
         assert_eq!(request.jsonrpc, "2.0");
         assert_eq!(request.method, "tools/call");
-        assert_eq!(request.params.name, "web_search");
-        assert_eq!(request.params.arguments.query, "test query");
+        let params = request.params.as_ref().unwrap();
+        assert_eq!(params.name, "web_search");
+        assert_eq!(params.arguments.query, "test query");
 
         // 验证 ID 格式: web_search_tooluse_{22位}_{时间戳}_{8位}
-        assert!(request.id.starts_with("web_search_tooluse_"));
+        assert!(request.id.as_ref().unwrap().to_string().starts_with("web_search_tooluse_"));
     }
 
     #[test]
@@ -654,7 +751,9 @@ mod tests {
         let (_, request) = create_mcp_request("test");
 
         // 格式: web_search_tooluse_{22位}_{毫秒时间戳}_{8位}
-        let id = &request.id;
+        let Some(JsonRpcId::String(id)) = &request.id else {
+            panic!("MCP 请求 id 应为字符串");
+        };
         assert!(id.starts_with("web_search_tooluse_"));
 
         let suffix = &id["web_search_tooluse_".len()..];
@@ -679,17 +778,14 @@ mod tests {
 
     #[test]
     fn test_parse_search_results() {
-        let response = McpResponse {
-            error: None,
-            id: "test_id".to_string(),
-            jsonrpc: "2.0".to_string(),
-            result: Some(McpResult {
-                content: vec![McpContent {
-                    content_type: "text".to_string(),
-                    text: r#"{"results":[{"title":"Test","url":"https://example.com","snippet":"Test snippet"}],"totalResults":1}"#.to_string(),
-                }],
-                is_error: false,
-            }),
+        use crate::kiro::model::mcp::McpContent;
+
+        let response = McpToolResult {
+            content: vec![McpContent {
+                content_type: "text".to_string(),
+                text: r#"{"results":[{"title":"Test","url":"https://example.com","snippet":"Test snippet"}],"totalResults":1}"#.to_string(),
+            }],
+            is_error: false,
         };
 
         let results = parse_search_results(&response);
@@ -723,4 +819,66 @@ mod tests {
         assert!(summary.contains("https://example.com"));
         assert!(summary.contains("This is a test snippet"));
     }
+
+    #[test]
+    fn test_generate_search_summary_truncates_multibyte_snippet() {
+        let results = WebSearchResults {
+            results: vec![WebSearchResult {
+                title: "中文结果".to_string(),
+                url: "https://example.com".to_string(),
+                snippet: Some("摘".repeat(300)),
+                published_date: None,
+                id: None,
+                domain: None,
+                max_verbatim_word_limit: None,
+                public_domain: None,
+            }],
+            total_results: Some(1),
+            query: None,
+            error: None,
+        };
+
+        let summary = generate_search_summary("test", &Some(results));
+
+        assert!(summary.contains(&format!("{}...", "摘".repeat(200))));
+        assert!(!summary.contains(&"摘".repeat(201)));
+    }
+
+    #[test]
+    fn test_tool_round_limits() {
+        let req: MessagesRequest = serde_json::from_value(json!({
+            "model": "claude-sonnet-4",
+            "max_tokens": 1024,
+            "tools": [{"type": "web_search_20250305", "name": "web_search", "max_uses": 2}],
+            "messages": [
+                {"role": "user", "content": "Perform a web search for the query: rust"},
+                {"role": "assistant", "content": [
+                    {"type": "server_tool_use", "id": "srvtoolu_1", "name": "web_search", "input": {}},
+                    {"type": "web_search_tool_result", "tool_use_id": "srvtoolu_1", "content": []}
+                ]},
+                {"role": "user", "content": "again"}
+            ]
+        }))
+        .unwrap();
+
+        assert_eq!(count_executed_rounds(&req), 1);
+        assert_eq!(effective_max_rounds(&req, &ToolLoopConfig::default()), 2);
+
+        let tight = ToolLoopConfig {
+            max_rounds: 1,
+            ..Default::default()
+        };
+        assert_eq!(effective_max_rounds(&req, &tight), 1);
+    }
+
+    #[test]
+    fn test_max_turns_exceeded_events() {
+        let events = generate_max_turns_exceeded_events("claude-sonnet-4", 10, "limit");
+        let delta = events
+            .iter()
+            .find(|e| e.event == "message_delta")
+            .unwrap();
+        assert_eq!(delta.data["delta"]["stop_reason"], "max_turns_exceeded");
+        assert_eq!(events.last().unwrap().event, "message_stop");
+    }
 }
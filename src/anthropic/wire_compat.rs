@@ -0,0 +1,295 @@
+//! 与 Kiro IDE 真实流量的线上兼容性测试
+//!
+//! `testdata/wire/kiro-ide-<版本>/` 下存放脱敏后的 Kiro IDE 抓包：请求头、请求体和流式响应。
+//! 这里断言我们生成的请求头（顺序与取值）和请求体与抓包逐字段一致，
+//! 并能正确解码抓包中的 event-stream 响应，防止 `build_headers` 或请求构建悄悄漂移。
+//!
+//! 期望值中的占位符：
+//! - `<uuid>`：任意 UUID（每次请求随机生成的字段）
+//! - `<machine-id>`：测试使用的 machineId
+//! - `<token>`：测试使用的 accessToken
+
+use std::sync::Arc;
+
+use reqwest::header::HeaderMap;
+use serde::Deserialize;
+use serde_json::{Value, json};
+
+use super::converter::convert_request;
+use super::types::MessagesRequest;
+use crate::kiro::model::credentials::KiroCredentials;
+use crate::kiro::model::events::Event;
+use crate::kiro::model::requests::kiro::KiroRequest;
+use crate::kiro::parser::decoder::EventStreamDecoder;
+use crate::kiro::provider::{KiroProvider, SdkRequest};
+use crate::kiro::token_manager::{CallContext, MultiTokenManager};
+use crate::model::config::Config;
+
+/// 抓包对应的 Kiro IDE 版本（与 `kiroVersion` 默认值保持一致）
+const CORPUS_VERSION: &str = "0.9.2";
+
+/// 抓包中的首次尝试（SDK 默认最多尝试 3 次）
+const FIRST_ATTEMPT: SdkRequest<'static> = SdkRequest {
+    invocation_id: "00000000-0000-0000-0000-000000000000",
+    attempt: 0,
+    max_attempts: 3,
+};
+
+macro_rules! corpus {
+    ($file:literal) => {
+        include_str!(concat!("testdata/wire/kiro-ide-0.9.2/", $file))
+    };
+}
+
+macro_rules! corpus_bytes {
+    ($file:literal) => {
+        include_bytes!(concat!("testdata/wire/kiro-ide-0.9.2/", $file))
+    };
+}
+
+const MACHINE_ID: &str = "0123456789abcdef0123456789abcdef0123456789abcdef0123456789abcdef";
+const TOKEN: &str = "aoa-test-access-token";
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct HeadersCorpus {
+    config: CorpusConfig,
+    api: Vec<(String, String)>,
+    mcp: Vec<(String, String)>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CorpusConfig {
+    kiro_version: String,
+    system_version: String,
+    node_version: String,
+    region: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RequestCase {
+    description: String,
+    profile_arn: Option<String>,
+    anthropic: Value,
+    kiro: Value,
+}
+
+#[derive(Deserialize)]
+struct ResponseCase {
+    description: String,
+    events: Vec<Value>,
+}
+
+/// 按占位符规则比较，返回第一个不一致的位置
+fn compare(expected: &Value, actual: &Value, path: &str) -> Result<(), String> {
+    match (expected, actual) {
+        (Value::String(e), Value::String(a)) => {
+            let matched = match e.as_str() {
+                "<uuid>" => uuid::Uuid::parse_str(a).is_ok(),
+                _ => {
+                    e.replace("<machine-id>", MACHINE_ID)
+                        .replace("<token>", TOKEN)
+                        == *a
+                }
+            };
+            if matched {
+                Ok(())
+            } else {
+                Err(format!("{}: 期望 {:?}，实际 {:?}", path, e, a))
+            }
+        }
+        (Value::Object(e), Value::Object(a)) => {
+            for key in e.keys().chain(a.keys()) {
+                let child = format!("{}.{}", path, key);
+                match (e.get(key), a.get(key)) {
+                    (Some(ev), Some(av)) => compare(ev, av, &child)?,
+                    (Some(_), None) => return Err(format!("{}: 缺少字段", child)),
+                    (None, Some(av)) => return Err(format!("{}: 多出字段 {}", child, av)),
+                    (None, None) => unreachable!(),
+                }
+            }
+            Ok(())
+        }
+        (Value::Array(e), Value::Array(a)) => {
+            if e.len() != a.len() {
+                return Err(format!(
+                    "{}: 期望 {} 项，实际 {} 项",
+                    path,
+                    e.len(),
+                    a.len()
+                ));
+            }
+            for (i, (ev, av)) in e.iter().zip(a).enumerate() {
+                compare(ev, av, &format!("{}[{}]", path, i))?;
+            }
+            Ok(())
+        }
+        _ if expected == actual => Ok(()),
+        _ => Err(format!("{}: 期望 {}，实际 {}", path, expected, actual)),
+    }
+}
+
+fn header_pairs(headers: &HeaderMap) -> Value {
+    headers
+        .iter()
+        .map(|(name, value)| json!([name.as_str(), value.to_str().unwrap()]))
+        .collect()
+}
+
+fn corpus_provider(config: &CorpusConfig) -> (KiroProvider, CallContext) {
+    let app_config = Config {
+        kiro_version: config.kiro_version.clone(),
+        system_version: config.system_version.clone(),
+        node_version: config.node_version.clone(),
+        region: config.region.clone(),
+        machine_id: Some(MACHINE_ID.to_string()),
+        ..Default::default()
+    };
+
+    let credentials = KiroCredentials::default();
+    let tm =
+        MultiTokenManager::new(app_config, vec![credentials.clone()], None, None, false).unwrap();
+    let ctx = CallContext {
+        id: 1,
+        credentials,
+        token: TOKEN.to_string(),
+        lease: None,
+    };
+    (KiroProvider::builder(Arc::new(tm)).build().unwrap(), ctx)
+}
+
+/// 把解码出的事件转换为与抓包期望值相同的 JSON 形式
+fn event_json(event: &Event) -> Value {
+    match event {
+        Event::AssistantResponse(e) => {
+            json!({"type": "assistantResponseEvent", "content": e.content})
+        }
+        Event::ToolUse(e) => json!({
+            "type": "toolUseEvent",
+            "name": e.name,
+            "toolUseId": e.tool_use_id,
+            "input": e.input,
+            "stop": e.stop,
+        }),
+        Event::Metering(()) => json!({"type": "meteringEvent"}),
+        Event::ContextUsage(e) => json!({
+            "type": "contextUsageEvent",
+            "contextUsagePercentage": e.context_usage_percentage,
+        }),
+        Event::Artifact(_) => json!({"type": "artifactEvent"}),
+        Event::Unknown { event_type, .. } => json!({"type": "unknown", "eventType": event_type}),
+        Event::Error { error_code, .. } => json!({"type": "error", "errorCode": error_code}),
+        Event::Exception { exception_type, .. } => {
+            json!({"type": "exception", "exceptionType": exception_type})
+        }
+    }
+}
+
+/// 以指定分片大小喂入解码器，返回所有事件
+fn decode_events(bytes: &[u8], chunk_size: usize) -> Vec<Value> {
+    let mut decoder = EventStreamDecoder::new();
+    let mut events = Vec::new();
+    for chunk in bytes.chunks(chunk_size) {
+        decoder.feed(chunk).unwrap();
+        for frame in decoder.decode_iter() {
+            events.push(event_json(&Event::from_frame(frame.unwrap()).unwrap()));
+        }
+    }
+    events
+}
+
+fn check_request_case(raw: &str) {
+    let case: RequestCase = serde_json::from_str(raw).unwrap();
+    let request: MessagesRequest = serde_json::from_value(case.anthropic).unwrap();
+    let conversion = convert_request(&request).unwrap();
+    let mut kiro_request = KiroRequest::new(conversion.conversation_state);
+    if let Some(arn) = &case.profile_arn {
+        kiro_request = kiro_request.with_profile_arn(arn);
+    }
+    let actual: Value = serde_json::from_str(&kiro_request.to_json().unwrap()).unwrap();
+
+    if let Err(diff) = compare(&case.kiro, &actual, "$") {
+        panic!("{}: 请求体与抓包不一致 {}", case.description, diff);
+    }
+}
+
+fn check_response_case(raw: &str, stream: &[u8]) {
+    let case: ResponseCase = serde_json::from_str(raw).unwrap();
+    let expected = Value::Array(case.events);
+    // 整体喂入和逐字节喂入都应得到相同结果
+    for chunk_size in [stream.len(), 1, 7] {
+        let actual = Value::Array(decode_events(stream, chunk_size));
+        if let Err(diff) = compare(&expected, &actual, "$") {
+            panic!(
+                "{}（分片 {}）: 事件与抓包不一致 {}",
+                case.description, chunk_size, diff
+            );
+        }
+    }
+}
+
+#[test]
+fn test_corpus_matches_default_kiro_version() {
+    let corpus: HeadersCorpus = serde_json::from_str(corpus!("headers.json")).unwrap();
+    assert_eq!(corpus.config.kiro_version, CORPUS_VERSION);
+    assert_eq!(
+        Config::default().kiro_version,
+        CORPUS_VERSION,
+        "kiroVersion 默认值变更后需要录制对应版本的抓包"
+    );
+}
+
+#[test]
+fn test_api_headers_match_capture() {
+    let corpus: HeadersCorpus = serde_json::from_str(corpus!("headers.json")).unwrap();
+    let (provider, ctx) = corpus_provider(&corpus.config);
+    let headers = provider
+        .build_headers(&ctx, &corpus.config.region, &FIRST_ATTEMPT)
+        .unwrap();
+    let expected = serde_json::to_value(&corpus.api).unwrap();
+    if let Err(diff) = compare(&expected, &header_pairs(&headers), "api") {
+        panic!("API 请求头与抓包不一致 {}", diff);
+    }
+}
+
+#[test]
+fn test_mcp_headers_match_capture() {
+    let corpus: HeadersCorpus = serde_json::from_str(corpus!("headers.json")).unwrap();
+    let (provider, ctx) = corpus_provider(&corpus.config);
+    let headers = provider
+        .build_mcp_headers(&ctx, &corpus.config.region, &FIRST_ATTEMPT)
+        .unwrap();
+    let expected = serde_json::to_value(&corpus.mcp).unwrap();
+    if let Err(diff) = compare(&expected, &header_pairs(&headers), "mcp") {
+        panic!("MCP 请求头与抓包不一致 {}", diff);
+    }
+}
+
+#[test]
+fn test_request_bodies_match_capture() {
+    check_request_case(corpus!("request_text.json"));
+    check_request_case(corpus!("request_tool_result.json"));
+}
+
+#[test]
+fn test_responses_decode_like_capture() {
+    check_response_case(
+        corpus!("response_text.json"),
+        corpus_bytes!("response_text.bin"),
+    );
+    check_response_case(
+        corpus!("response_tool_use.json"),
+        corpus_bytes!("response_tool_use.bin"),
+    );
+}
+
+#[test]
+fn test_compare_reports_drift() {
+    let expected = json!({"a": "<uuid>", "b": "KiroIDE-<machine-id>"});
+    let ok = json!({"a": uuid::Uuid::new_v4().to_string(), "b": format!("KiroIDE-{}", MACHINE_ID)});
+    assert!(compare(&expected, &ok, "$").is_ok());
+    assert!(compare(&expected, &json!({"a": "x", "b": "KiroIDE-"}), "$").is_err());
+    assert!(compare(&json!({}), &json!({"extra": 1}), "$").is_err());
+}
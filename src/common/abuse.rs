@@ -0,0 +1,214 @@
+//! 滥用/误用检测
+//!
+//! 识别会拖垮共享凭据的异常调用模式：
+//! - 同一客户端在时间窗口内反复提交完全相同的请求
+//! - 对话末尾出现超长的连续工具调用轮次（疑似 agent 死循环）
+//!
+//! 命中后按配置标记（仅记录，Admin 可见）或限流（返回 429），不会静默丢弃请求。
+
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+use parking_lot::Mutex;
+use serde::Serialize;
+
+use crate::model::config::{AbuseAction, AbuseGuardConfig};
+
+/// 检测结果
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AbuseVerdict {
+    /// 正常
+    Allow,
+    /// 已标记，请求继续处理
+    Flagged(String),
+    /// 已标记并应拒绝请求
+    Throttled(String),
+}
+
+/// 单个客户端的标记记录（Admin 可见）
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AbuseFlag {
+    /// 客户端标识
+    pub client: String,
+    /// 最近一次命中原因
+    pub reason: String,
+    /// 累计命中次数
+    pub hits: u64,
+    /// 是否被限流
+    pub throttled: bool,
+    /// 首次命中时间
+    pub first_seen: DateTime<Utc>,
+    /// 最近命中时间
+    pub last_seen: DateTime<Utc>,
+}
+
+/// 滥用检测器
+pub struct AbuseGuard {
+    config: AbuseGuardConfig,
+    /// (客户端, 请求指纹) -> 窗口内的请求时间
+    recent: Mutex<HashMap<(String, String), VecDeque<Instant>>>,
+    /// 客户端 -> 标记记录
+    flags: Mutex<HashMap<String, AbuseFlag>>,
+}
+
+impl AbuseGuard {
+    pub fn new(config: AbuseGuardConfig) -> Self {
+        Self {
+            config,
+            recent: Mutex::new(HashMap::new()),
+            flags: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// 是否启用
+    pub fn is_enabled(&self) -> bool {
+        self.config.enabled
+    }
+
+    /// 检查一次请求
+    ///
+    /// # Arguments
+    /// * `client` - 客户端标识
+    /// * `fingerprint` - 请求内容指纹（相同请求应得到相同指纹）
+    /// * `tool_rounds` - 对话末尾的连续工具调用轮次
+    pub fn check(&self, client: &str, fingerprint: &str, tool_rounds: usize) -> AbuseVerdict {
+        if !self.config.enabled {
+            return AbuseVerdict::Allow;
+        }
+
+        let identical = self.record(client, fingerprint);
+
+        let reason = if identical > self.config.max_identical_requests {
+            Some(format!(
+                "{} 秒内重复提交相同请求 {} 次",
+                self.config.window_secs, identical
+            ))
+        } else if tool_rounds > self.config.max_tool_rounds {
+            Some(format!("连续工具调用 {} 轮，疑似死循环", tool_rounds))
+        } else {
+            None
+        };
+
+        let Some(reason) = reason else {
+            return AbuseVerdict::Allow;
+        };
+
+        let throttled = self.config.action == AbuseAction::Throttle;
+        self.flag(client, &reason, throttled);
+        tracing::warn!(client = %client, "检测到异常调用模式: {}", reason);
+
+        if throttled {
+            AbuseVerdict::Throttled(reason)
+        } else {
+            AbuseVerdict::Flagged(reason)
+        }
+    }
+
+    /// 记录请求并返回窗口内相同请求的次数（含本次）
+    fn record(&self, client: &str, fingerprint: &str) -> usize {
+        let window = Duration::from_secs(self.config.window_secs);
+        let now = Instant::now();
+        let mut recent = self.recent.lock();
+
+        // 清理过期记录，避免内存无限增长
+        recent.retain(|_, times| {
+            while times.front().is_some_and(|t| now.duration_since(*t) > window) {
+                times.pop_front();
+            }
+            !times.is_empty()
+        });
+
+        let times = recent
+            .entry((client.to_string(), fingerprint.to_string()))
+            .or_default();
+        times.push_back(now);
+        times.len()
+    }
+
+    fn flag(&self, client: &str, reason: &str, throttled: bool) {
+        let now = Utc::now();
+        let mut flags = self.flags.lock();
+        let flag = flags.entry(client.to_string()).or_insert_with(|| AbuseFlag {
+            client: client.to_string(),
+            reason: reason.to_string(),
+            hits: 0,
+            throttled,
+            first_seen: now,
+            last_seen: now,
+        });
+        flag.reason = reason.to_string();
+        flag.hits += 1;
+        flag.throttled = throttled;
+        flag.last_seen = now;
+    }
+
+    /// 获取所有标记（按最近命中时间倒序）
+    pub fn flags(&self) -> Vec<AbuseFlag> {
+        let mut flags: Vec<AbuseFlag> = self.flags.lock().values().cloned().collect();
+        flags.sort_by_key(|f| std::cmp::Reverse(f.last_seen));
+        flags
+    }
+
+    /// 清除指定客户端的标记，返回是否存在
+    pub fn clear_flag(&self, client: &str) -> bool {
+        self.flags.lock().remove(client).is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn guard(action: AbuseAction) -> AbuseGuard {
+        AbuseGuard::new(AbuseGuardConfig {
+            enabled: true,
+            max_identical_requests: 2,
+            max_tool_rounds: 3,
+            action,
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn test_disabled_always_allows() {
+        let guard = AbuseGuard::new(AbuseGuardConfig::default());
+        for _ in 0..100 {
+            assert_eq!(guard.check("c", "same", 1000), AbuseVerdict::Allow);
+        }
+        assert!(guard.flags().is_empty());
+    }
+
+    #[test]
+    fn test_identical_prompt_hammering_flagged() {
+        let guard = guard(AbuseAction::Flag);
+        assert_eq!(guard.check("c", "fp", 0), AbuseVerdict::Allow);
+        assert_eq!(guard.check("c", "fp", 0), AbuseVerdict::Allow);
+        assert!(matches!(guard.check("c", "fp", 0), AbuseVerdict::Flagged(_)));
+        // 其他客户端不受影响
+        assert_eq!(guard.check("other", "fp", 0), AbuseVerdict::Allow);
+
+        let flags = guard.flags();
+        assert_eq!(flags.len(), 1);
+        assert_eq!(flags[0].client, "c");
+        assert!(!flags[0].throttled);
+    }
+
+    #[test]
+    fn test_tool_loop_throttled() {
+        let guard = guard(AbuseAction::Throttle);
+        assert_eq!(guard.check("c", "a", 3), AbuseVerdict::Allow);
+        assert!(matches!(guard.check("c", "b", 4), AbuseVerdict::Throttled(_)));
+        assert!(guard.flags()[0].throttled);
+    }
+
+    #[test]
+    fn test_clear_flag() {
+        let guard = guard(AbuseAction::Flag);
+        guard.check("c", "x", 10);
+        assert!(guard.clear_flag("c"));
+        assert!(!guard.clear_flag("c"));
+        assert!(guard.flags().is_empty());
+    }
+}
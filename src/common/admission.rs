@@ -0,0 +1,302 @@
+//! 并发准入队列
+//!
+//! 进行中的请求达到上限后新请求按优先级类别排队。空出名额时选择有效优先级最高的等待者：
+//! 有效优先级 = 类别基础优先级（交互式 1，批处理 0）+ 已等待时间 / 老化间隔，
+//! 同分时先到先得。批处理请求等待一个老化间隔后即与新到的交互式请求同级，不会被持续的交互式负载饿死。
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use parking_lot::Mutex;
+use serde::Serialize;
+use tokio::sync::oneshot;
+
+use crate::model::config::{AdmissionConfig, PriorityClass};
+
+/// 排队中的请求
+struct Waiter {
+    id: u64,
+    class: PriorityClass,
+    enqueued_at: Instant,
+    admit: oneshot::Sender<()>,
+}
+
+/// 单个类别的排队统计
+#[derive(Debug, Default, Clone)]
+struct ClassStats {
+    admitted: u64,
+    timed_out: u64,
+    total_wait: Duration,
+    max_wait: Duration,
+}
+
+#[derive(Default)]
+struct QueueState {
+    running: usize,
+    next_id: u64,
+    waiting: Vec<Waiter>,
+    stats: HashMap<PriorityClass, ClassStats>,
+}
+
+/// 单个类别的排队指标
+#[derive(Debug, Clone, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ClassQueueMetrics {
+    pub class: PriorityClass,
+    /// 当前排队数
+    pub waiting: usize,
+    /// 启动以来经过排队后获准的请求数（未排队直接获准的不计入）
+    pub admitted: u64,
+    /// 排队超时被拒绝的请求数
+    pub timed_out: u64,
+    pub avg_wait_ms: u64,
+    pub max_wait_ms: u64,
+}
+
+/// 排队超时
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AdmissionTimeout {
+    pub waited: Duration,
+}
+
+impl fmt::Display for AdmissionTimeout {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "排队 {} 秒后仍无空闲名额", self.waited.as_secs())
+    }
+}
+
+impl std::error::Error for AdmissionTimeout {}
+
+/// 准入许可，drop 时释放名额并调度下一个等待者
+pub struct AdmissionPermit {
+    queue: Arc<AdmissionQueue>,
+}
+
+impl Drop for AdmissionPermit {
+    fn drop(&mut self) {
+        let mut state = self.queue.state.lock();
+        state.running -= 1;
+        self.queue.dispatch(&mut state);
+    }
+}
+
+/// 并发准入队列
+pub struct AdmissionQueue {
+    max_concurrent: usize,
+    aging: Duration,
+    max_wait: Duration,
+    state: Mutex<QueueState>,
+}
+
+impl AdmissionQueue {
+    /// 按配置创建，`maxConcurrent` 为 0 时返回 None（不排队）
+    pub fn from_config(config: &AdmissionConfig) -> Option<Arc<Self>> {
+        (config.max_concurrent > 0).then(|| {
+            Arc::new(Self {
+                max_concurrent: config.max_concurrent,
+                aging: Duration::from_secs(config.aging_secs.max(1)),
+                max_wait: Duration::from_secs(config.max_wait_secs),
+                state: Mutex::new(QueueState::default()),
+            })
+        })
+    }
+
+    /// 获取准入许可，名额已满时排队等待
+    pub async fn acquire(
+        self: &Arc<Self>,
+        class: PriorityClass,
+    ) -> Result<AdmissionPermit, AdmissionTimeout> {
+        let (id, enqueued_at, admitted) = {
+            let mut state = self.state.lock();
+            if state.running < self.max_concurrent && state.waiting.is_empty() {
+                state.running += 1;
+                return Ok(self.permit());
+            }
+            let (tx, rx) = oneshot::channel();
+            let id = state.next_id;
+            state.next_id += 1;
+            let enqueued_at = Instant::now();
+            state.waiting.push(Waiter {
+                id,
+                class,
+                enqueued_at,
+                admit: tx,
+            });
+            // 队列中可能只剩已取消的等待者，此时有空闲名额
+            self.dispatch(&mut state);
+            (id, enqueued_at, rx)
+        };
+
+        if let Ok(Ok(())) = tokio::time::timeout(self.max_wait, admitted).await {
+            return Ok(self.permit());
+        }
+
+        // 超时：仍在队列中则移除；已被调度（名额已计入）则照常获准
+        let mut state = self.state.lock();
+        let Some(pos) = state.waiting.iter().position(|w| w.id == id) else {
+            return Ok(self.permit());
+        };
+        state.waiting.remove(pos);
+        state.stats.entry(class).or_default().timed_out += 1;
+        Err(AdmissionTimeout {
+            waited: enqueued_at.elapsed(),
+        })
+    }
+
+    fn permit(self: &Arc<Self>) -> AdmissionPermit {
+        AdmissionPermit {
+            queue: self.clone(),
+        }
+    }
+
+    /// 有效优先级
+    fn effective_priority(&self, waiter: &Waiter, now: Instant) -> f64 {
+        let base = match waiter.class {
+            PriorityClass::Interactive => 1.0,
+            PriorityClass::Batch => 0.0,
+        };
+        base + now.duration_since(waiter.enqueued_at).as_secs_f64() / self.aging.as_secs_f64()
+    }
+
+    /// 在空闲名额内依次放行有效优先级最高的等待者
+    fn dispatch(&self, state: &mut QueueState) {
+        let now = Instant::now();
+        while state.running < self.max_concurrent {
+            let Some(pos) = state
+                .waiting
+                .iter()
+                .enumerate()
+                .max_by(|(_, a), (_, b)| {
+                    self.effective_priority(a, now)
+                        .total_cmp(&self.effective_priority(b, now))
+                        .then(b.enqueued_at.cmp(&a.enqueued_at))
+                })
+                .map(|(pos, _)| pos)
+            else {
+                return;
+            };
+            let waiter = state.waiting.remove(pos);
+            // 等待者已取消（客户端断开）时跳过
+            if waiter.admit.send(()).is_err() {
+                continue;
+            }
+            state.running += 1;
+            let wait = now.duration_since(waiter.enqueued_at);
+            let stats = state.stats.entry(waiter.class).or_default();
+            stats.admitted += 1;
+            stats.total_wait += wait;
+            stats.max_wait = stats.max_wait.max(wait);
+        }
+    }
+
+    /// 当前进行中的请求数
+    #[allow(dead_code)]
+    pub fn running(&self) -> usize {
+        self.state.lock().running
+    }
+
+    /// 各类别的排队指标
+    pub fn metrics(&self) -> Vec<ClassQueueMetrics> {
+        let state = self.state.lock();
+        PriorityClass::ALL
+            .iter()
+            .map(|class| {
+                let stats = state.stats.get(class).cloned().unwrap_or_default();
+                ClassQueueMetrics {
+                    class: *class,
+                    waiting: state
+                        .waiting
+                        .iter()
+                        .filter(|w| w.class == *class && !w.admit.is_closed())
+                        .count(),
+                    admitted: stats.admitted,
+                    timed_out: stats.timed_out,
+                    avg_wait_ms: stats
+                        .total_wait
+                        .as_millis()
+                        .checked_div(u128::from(stats.admitted))
+                        .unwrap_or(0) as u64,
+                    max_wait_ms: stats.max_wait.as_millis() as u64,
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn queue(max_wait_secs: u64) -> Arc<AdmissionQueue> {
+        AdmissionQueue::from_config(&AdmissionConfig {
+            max_concurrent: 1,
+            aging_secs: 10,
+            max_wait_secs,
+        })
+        .unwrap()
+    }
+
+    fn waiter(id: u64, class: PriorityClass, waited: Duration) -> Waiter {
+        Waiter {
+            id,
+            class,
+            enqueued_at: Instant::now() - waited,
+            admit: oneshot::channel().0,
+        }
+    }
+
+    #[test]
+    fn test_disabled_without_limit() {
+        assert!(AdmissionQueue::from_config(&AdmissionConfig::default()).is_none());
+    }
+
+    #[test]
+    fn test_batch_ages_past_interactive() {
+        let queue = queue(60);
+        let now = Instant::now();
+        let fresh = waiter(0, PriorityClass::Interactive, Duration::ZERO);
+        let young_batch = waiter(1, PriorityClass::Batch, Duration::from_secs(5));
+        let old_batch = waiter(2, PriorityClass::Batch, Duration::from_secs(15));
+
+        assert!(
+            queue.effective_priority(&fresh, now) > queue.effective_priority(&young_batch, now)
+        );
+        assert!(queue.effective_priority(&old_batch, now) > queue.effective_priority(&fresh, now));
+    }
+
+    #[tokio::test]
+    async fn test_queue_and_metrics() {
+        let queue = queue(60);
+        let running = queue.acquire(PriorityClass::Interactive).await.unwrap();
+
+        let batch = tokio::spawn({
+            let queue = queue.clone();
+            async move { queue.acquire(PriorityClass::Batch).await.map(|_| ()) }
+        });
+        tokio::task::yield_now().await;
+        while queue.metrics()[1].waiting == 0 {
+            tokio::task::yield_now().await;
+        }
+        assert_eq!(queue.running(), 1);
+
+        drop(running);
+        batch.await.unwrap().unwrap();
+        assert_eq!(queue.running(), 0);
+        let metrics = queue.metrics();
+        assert_eq!(metrics[1].class, PriorityClass::Batch);
+        assert_eq!(metrics[1].admitted, 1);
+        assert_eq!(metrics[1].waiting, 0);
+    }
+
+    #[tokio::test]
+    async fn test_wait_timeout() {
+        let queue = queue(0);
+        let _running = queue.acquire(PriorityClass::Interactive).await.unwrap();
+        assert!(queue.acquire(PriorityClass::Batch).await.is_err());
+        let metrics = queue.metrics();
+        assert_eq!(metrics[1].timed_out, 1);
+        assert_eq!(metrics[1].waiting, 0);
+    }
+}
@@ -8,17 +8,16 @@ use subtle::ConstantTimeEq;
 
 /// 从请求中提取 API Key
 ///
-/// 支持两种认证方式：
+/// 支持三种认证方式：
 /// - `x-api-key` header
+/// - `api-key` header（Azure OpenAI 约定）
 /// - `Authorization: Bearer <token>` header
 pub fn extract_api_key(request: &Request<Body>) -> Option<String> {
-    // 优先检查 x-api-key
-    if let Some(key) = request
-        .headers()
-        .get("x-api-key")
-        .and_then(|v| v.to_str().ok())
-    {
-        return Some(key.to_string());
+    // 优先检查 x-api-key，其次是 Azure 风格的 api-key
+    for name in ["x-api-key", "api-key"] {
+        if let Some(key) = request.headers().get(name).and_then(|v| v.to_str().ok()) {
+            return Some(key.to_string());
+        }
     }
 
     // 其次检查 Authorization: Bearer
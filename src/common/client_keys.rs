@@ -0,0 +1,348 @@
+//! 客户端 API Key 存储
+//!
+//! 配置文件 `clientKeys` 中的密钥只读；通过 Admin API 开通的密钥保存在 `{stateDir}/client_keys.json`，
+//! 开通和吊销都无需修改配置或重启。未设置 stateDir 时开通的密钥只保存在内存中，重启后失效。
+
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+use parking_lot::{Mutex, RwLock};
+use serde::{Deserialize, Serialize};
+
+use crate::common::{auth, persist};
+use crate::model::config::{ClientKeyConfig, ClientKeyScopes, RequestDefaults};
+
+/// 开通的密钥文件名（位于 stateDir）
+const PROVISIONED_FILE: &str = "client_keys.json";
+
+/// 通过 Admin API 开通的客户端密钥
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProvisionedKey {
+    #[serde(flatten)]
+    pub config: ClientKeyConfig,
+    pub created_at: DateTime<Utc>,
+}
+
+/// 密钥来源
+#[derive(Debug, Clone, Copy, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum ClientKeySource {
+    /// 配置文件 clientKeys
+    Config,
+    /// Admin API 开通
+    Provisioned,
+}
+
+/// 客户端密钥概览（Admin 可见，不含完整密钥）
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ClientKeySummary {
+    pub name: String,
+    /// 密钥前缀
+    pub key_preview: String,
+    pub source: ClientKeySource,
+    pub scopes: ClientKeyScopes,
+    #[serde(skip_serializing_if = "RequestDefaults::is_empty")]
+    pub defaults: RequestDefaults,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub created_at: Option<DateTime<Utc>>,
+}
+
+/// 开通/吊销失败原因
+#[derive(Debug)]
+pub enum ClientKeyError {
+    /// 请求参数无效（如名称为空或重复）
+    Invalid(String),
+    /// 开通的密钥中不存在该名称
+    NotFound(String),
+    /// 写入 stateDir 失败
+    Persist(String),
+}
+
+impl fmt::Display for ClientKeyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ClientKeyError::Invalid(msg) => write!(f, "{}", msg),
+            ClientKeyError::NotFound(name) => {
+                write!(f, "不存在通过 Admin API 开通的密钥: {}", name)
+            }
+            ClientKeyError::Persist(msg) => write!(f, "保存客户端密钥失败: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for ClientKeyError {}
+
+/// 客户端密钥存储
+pub struct ClientKeyStore {
+    configured: Vec<ClientKeyConfig>,
+    provisioned: RwLock<Vec<ProvisionedKey>>,
+    /// 开通的密钥文件路径（未设置 stateDir 时为 None）
+    path: Option<PathBuf>,
+    /// 客户端名称 -> 最近一分钟内的请求时间
+    recent: Mutex<HashMap<String, VecDeque<Instant>>>,
+}
+
+impl ClientKeyStore {
+    /// 仅包含配置文件中的密钥，开通的密钥不持久化
+    pub fn new(configured: Vec<ClientKeyConfig>) -> Self {
+        Self {
+            configured,
+            provisioned: RwLock::new(Vec::new()),
+            path: None,
+            recent: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// 加载配置文件中的密钥和 stateDir 中已开通的密钥
+    pub fn load(configured: Vec<ClientKeyConfig>, state_dir: Option<&str>) -> anyhow::Result<Self> {
+        use anyhow::Context;
+
+        let mut store = Self::new(configured);
+        let Some(state_dir) = state_dir else {
+            return Ok(store);
+        };
+        let path = PathBuf::from(state_dir).join(PROVISIONED_FILE);
+        if let Some(keys) = persist::read_with_fallback(&path, |content| {
+            serde_json::from_str::<Vec<ProvisionedKey>>(content)
+        })
+        .context("加载客户端密钥文件失败")?
+        {
+            tracing::info!("已加载 {} 个开通的客户端密钥", keys.len());
+            *store.provisioned.get_mut() = keys;
+        }
+        store.path = Some(path);
+        Ok(store)
+    }
+
+    /// 按密钥查找，返回 (序号, 配置)；序号用于未命名的配置密钥的默认名称
+    pub fn find(&self, key: &str) -> Option<(usize, ClientKeyConfig)> {
+        if let Some(found) = self
+            .configured
+            .iter()
+            .enumerate()
+            .find(|(_, c)| auth::constant_time_eq(key, &c.key))
+        {
+            return Some((found.0, found.1.clone()));
+        }
+        let provisioned = self.provisioned.read();
+        provisioned
+            .iter()
+            .enumerate()
+            .find(|(_, p)| auth::constant_time_eq(key, &p.config.key))
+            .map(|(i, p)| (self.configured.len() + i, p.config.clone()))
+    }
+
+    /// 按客户端名查找（未命名的配置密钥为 `client-{序号}`），返回 (序号, 配置)
+    pub fn find_by_name(&self, name: &str) -> Option<(usize, ClientKeyConfig)> {
+        let provisioned = self.provisioned.read();
+        self.configured
+            .iter()
+            .chain(provisioned.iter().map(|p| &p.config))
+            .enumerate()
+            .find(|(i, c)| match &c.name {
+                Some(n) => n == name,
+                None => format!("client-{}", i + 1) == name,
+            })
+            .map(|(i, c)| (i, c.clone()))
+    }
+
+    /// 列出所有密钥
+    pub fn list(&self) -> Vec<ClientKeySummary> {
+        self.list_locked(&self.provisioned.read())
+    }
+
+    /// 开通新密钥（`config.key` 为空时自动生成），返回包含完整密钥的记录
+    pub fn provision(&self, mut config: ClientKeyConfig) -> Result<ProvisionedKey, ClientKeyError> {
+        let name = config
+            .name
+            .as_deref()
+            .map(str::trim)
+            .filter(|n| !n.is_empty())
+            .ok_or_else(|| ClientKeyError::Invalid("开通的密钥必须指定 name".to_string()))?
+            .to_string();
+        if config.key.is_empty() {
+            config.key = format!("sk-kiro-{}", uuid::Uuid::new_v4().simple());
+        }
+
+        let mut provisioned = self.provisioned.write();
+        if self
+            .list_locked(&provisioned)
+            .iter()
+            .any(|s| s.name == name)
+        {
+            return Err(ClientKeyError::Invalid(format!(
+                "客户端名称已存在: {}",
+                name
+            )));
+        }
+        let duplicate_key = self
+            .configured
+            .iter()
+            .map(|c| &c.key)
+            .chain(provisioned.iter().map(|p| &p.config.key))
+            .any(|k| auth::constant_time_eq(k, &config.key));
+        if duplicate_key {
+            return Err(ClientKeyError::Invalid("密钥已存在".to_string()));
+        }
+
+        config.name = Some(name.clone());
+        let record = ProvisionedKey {
+            config,
+            created_at: Utc::now(),
+        };
+        let mut next = provisioned.clone();
+        next.push(record.clone());
+        self.persist(&next)?;
+        *provisioned = next;
+        tracing::info!("已开通客户端密钥: {}", name);
+        Ok(record)
+    }
+
+    /// 吊销开通的密钥（配置文件中的密钥需修改配置）
+    pub fn revoke(&self, name: &str) -> Result<(), ClientKeyError> {
+        let mut provisioned = self.provisioned.write();
+        let next: Vec<ProvisionedKey> = provisioned
+            .iter()
+            .filter(|p| p.config.name.as_deref() != Some(name))
+            .cloned()
+            .collect();
+        if next.len() == provisioned.len() {
+            return Err(ClientKeyError::NotFound(name.to_string()));
+        }
+        self.persist(&next)?;
+        *provisioned = next;
+        self.recent.lock().remove(name);
+        tracing::info!("已吊销客户端密钥: {}", name);
+        Ok(())
+    }
+
+    /// 记录一次请求并检查是否超过每分钟请求数
+    pub fn check_rate(&self, client: &str, per_minute: u32) -> bool {
+        let now = Instant::now();
+        let window = Duration::from_secs(60);
+        let mut recent = self.recent.lock();
+        let times = recent.entry(client.to_string()).or_default();
+        while times
+            .front()
+            .is_some_and(|t| now.duration_since(*t) >= window)
+        {
+            times.pop_front();
+        }
+        if times.len() >= per_minute as usize {
+            return false;
+        }
+        times.push_back(now);
+        true
+    }
+
+    fn list_locked(&self, provisioned: &[ProvisionedKey]) -> Vec<ClientKeySummary> {
+        let offset = self.configured.len();
+        self.configured
+            .iter()
+            .enumerate()
+            .map(|(i, c)| summary(i, c, ClientKeySource::Config, None))
+            .chain(provisioned.iter().enumerate().map(|(i, p)| {
+                summary(
+                    offset + i,
+                    &p.config,
+                    ClientKeySource::Provisioned,
+                    Some(p.created_at),
+                )
+            }))
+            .collect()
+    }
+
+    fn persist(&self, keys: &[ProvisionedKey]) -> Result<(), ClientKeyError> {
+        let Some(path) = &self.path else {
+            tracing::warn!("未设置 stateDir，开通的客户端密钥仅保存在内存中");
+            return Ok(());
+        };
+        let json = serde_json::to_string_pretty(keys)
+            .map_err(|e| ClientKeyError::Persist(e.to_string()))?;
+        persist::write_atomic(path, json.as_bytes())
+            .map_err(|e| ClientKeyError::Persist(format!("{:?}: {}", path, e)))
+    }
+}
+
+fn summary(
+    index: usize,
+    config: &ClientKeyConfig,
+    source: ClientKeySource,
+    created_at: Option<DateTime<Utc>>,
+) -> ClientKeySummary {
+    ClientKeySummary {
+        name: config
+            .name
+            .clone()
+            .unwrap_or_else(|| format!("client-{}", index + 1)),
+        key_preview: format!("{}…", config.key.chars().take(12).collect::<String>()),
+        source,
+        scopes: config.scopes.clone(),
+        defaults: config.defaults.clone(),
+        expires_at: config.expires_at,
+        created_at,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(name: &str) -> ClientKeyConfig {
+        serde_json::from_value(serde_json::json!({"key": "", "name": name})).unwrap()
+    }
+
+    #[test]
+    fn test_provision_persist_and_revoke() {
+        let dir = std::env::temp_dir().join(format!("kiro-client-keys-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let state_dir = dir.to_str().unwrap();
+
+        let mut configured = key("ops");
+        configured.key = "sk-ops".to_string();
+        let store = ClientKeyStore::load(vec![configured.clone()], Some(state_dir)).unwrap();
+
+        let record = store.provision(key("contractor")).unwrap();
+        assert!(record.config.key.starts_with("sk-kiro-"));
+        assert!(matches!(
+            store.provision(key("ops")),
+            Err(ClientKeyError::Invalid(_))
+        ));
+        assert!(matches!(
+            store.provision(key(" ")),
+            Err(ClientKeyError::Invalid(_))
+        ));
+
+        // 重新加载后仍可识别
+        let reloaded = ClientKeyStore::load(vec![configured.clone()], Some(state_dir)).unwrap();
+        let (index, found) = reloaded.find(&record.config.key).unwrap();
+        assert_eq!(index, 1);
+        assert_eq!(found.name.as_deref(), Some("contractor"));
+        assert_eq!(reloaded.list()[1].source, ClientKeySource::Provisioned);
+
+        assert!(matches!(
+            reloaded.revoke("ops"),
+            Err(ClientKeyError::NotFound(_))
+        ));
+        reloaded.revoke("contractor").unwrap();
+        assert!(reloaded.find(&record.config.key).is_none());
+        let reloaded = ClientKeyStore::load(vec![configured], Some(state_dir)).unwrap();
+        assert!(reloaded.find(&record.config.key).is_none());
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_check_rate() {
+        let store = ClientKeyStore::new(vec![]);
+        assert!(store.check_rate("a", 2));
+        assert!(store.check_rate("a", 2));
+        assert!(!store.check_rate("a", 2));
+        assert!(store.check_rate("b", 2));
+    }
+}
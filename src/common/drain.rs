@@ -0,0 +1,280 @@
+//! 下游连接写出统计与慢客户端检测
+//!
+//! 代理把一个响应块交给 hyper 后，要等它写入 socket、再次轮询响应体才会取下一块。客户端读得慢时
+//! TCP 发送缓冲区被写满，这段等待随之变长，上游持续输出的数据只能堆积在代理内存里。这里记录每个
+//! 连接每次交出数据块到下一次轮询之间的等待（写出阻塞），当前阻塞超过 `slowClientThresholdMs`
+//! 的连接视为慢客户端，便于定位造成缓冲压力的下游。
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+use parking_lot::Mutex;
+use serde::Serialize;
+
+/// 连接写出统计器
+pub struct DrainTracker {
+    threshold: Duration,
+    next_id: AtomicU64,
+    connections: Mutex<HashMap<u64, Connection>>,
+    totals: Mutex<DrainTotals>,
+}
+
+/// 进行中的连接
+struct Connection {
+    client: String,
+    path: String,
+    started_at: DateTime<Utc>,
+    bytes_written: u64,
+    chunks_written: u64,
+    write_stall: Duration,
+    max_write_stall: Duration,
+    slow_stalls: u64,
+    /// 最近一块交给 hyper 的时间（尚未再次轮询）
+    waiting_since: Option<Instant>,
+}
+
+impl Connection {
+    /// 结束一次等待，返回本次阻塞时长
+    fn end_wait(&mut self, threshold: Duration) -> Option<Duration> {
+        let stall = self.waiting_since.take()?.elapsed();
+        self.write_stall += stall;
+        self.max_write_stall = self.max_write_stall.max(stall);
+        if stall >= threshold {
+            self.slow_stalls += 1;
+        }
+        Some(stall)
+    }
+
+    fn current_stall(&self) -> Duration {
+        self.waiting_since
+            .map(|since| since.elapsed())
+            .unwrap_or_default()
+    }
+}
+
+/// 已结束连接的累计统计
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DrainTotals {
+    pub connections: u64,
+    pub bytes_written: u64,
+    pub write_stall_ms: u64,
+    /// 阻塞超过阈值的次数
+    pub slow_stalls: u64,
+}
+
+/// 单个连接的写出统计（Admin 可见）
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ConnectionStats {
+    pub id: u64,
+    pub client: String,
+    pub path: String,
+    pub started_at: DateTime<Utc>,
+    pub bytes_written: u64,
+    pub chunks_written: u64,
+    /// 累计写出阻塞
+    pub write_stall_ms: u64,
+    pub max_write_stall_ms: u64,
+    /// 当前这一块已等待的时长（0 表示下游正在读取或代理在等上游）
+    pub current_write_stall_ms: u64,
+    pub slow_stalls: u64,
+    /// 当前阻塞超过阈值
+    pub slow: bool,
+}
+
+/// 写出统计报告
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DrainReport {
+    pub threshold_ms: u64,
+    pub active: usize,
+    pub slow_clients: usize,
+    /// 已结束连接的累计值
+    pub completed: DrainTotals,
+    /// 进行中的连接，按当前阻塞时长降序
+    pub connections: Vec<ConnectionStats>,
+}
+
+impl DrainTracker {
+    pub fn new(threshold: Duration) -> Self {
+        Self {
+            threshold,
+            next_id: AtomicU64::new(1),
+            connections: Mutex::new(HashMap::new()),
+            totals: Mutex::new(DrainTotals::default()),
+        }
+    }
+
+    /// 开始统计一个连接的响应体
+    pub fn open(
+        self: &Arc<Self>,
+        client: impl Into<String>,
+        path: impl Into<String>,
+    ) -> DrainHandle {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        self.connections.lock().insert(
+            id,
+            Connection {
+                client: client.into(),
+                path: path.into(),
+                started_at: Utc::now(),
+                bytes_written: 0,
+                chunks_written: 0,
+                write_stall: Duration::ZERO,
+                max_write_stall: Duration::ZERO,
+                slow_stalls: 0,
+                waiting_since: None,
+            },
+        );
+        DrainHandle {
+            tracker: self.clone(),
+            id,
+        }
+    }
+
+    /// 当前阻塞超过阈值的连接数
+    pub fn slow_clients(&self) -> usize {
+        self.connections
+            .lock()
+            .values()
+            .filter(|c| c.current_stall() >= self.threshold)
+            .count()
+    }
+
+    /// 获取快照
+    pub fn report(&self) -> DrainReport {
+        let mut connections: Vec<ConnectionStats> = self
+            .connections
+            .lock()
+            .iter()
+            .map(|(&id, c)| {
+                let current = c.current_stall();
+                ConnectionStats {
+                    id,
+                    client: c.client.clone(),
+                    path: c.path.clone(),
+                    started_at: c.started_at,
+                    bytes_written: c.bytes_written,
+                    chunks_written: c.chunks_written,
+                    write_stall_ms: (c.write_stall + current).as_millis() as u64,
+                    max_write_stall_ms: c.max_write_stall.max(current).as_millis() as u64,
+                    current_write_stall_ms: current.as_millis() as u64,
+                    slow_stalls: c.slow_stalls,
+                    slow: current >= self.threshold,
+                }
+            })
+            .collect();
+        connections.sort_by(|a, b| {
+            b.current_write_stall_ms
+                .cmp(&a.current_write_stall_ms)
+                .then(a.id.cmp(&b.id))
+        });
+        DrainReport {
+            threshold_ms: self.threshold.as_millis() as u64,
+            active: connections.len(),
+            slow_clients: connections.iter().filter(|c| c.slow).count(),
+            completed: self.totals.lock().clone(),
+            connections,
+        }
+    }
+}
+
+/// 单个连接的统计句柄，drop 时（响应发送完毕或客户端断开）计入累计值
+pub struct DrainHandle {
+    tracker: Arc<DrainTracker>,
+    id: u64,
+}
+
+impl DrainHandle {
+    /// hyper 轮询下一块（上一块已写出）
+    pub fn polled(&self) {
+        let threshold = self.tracker.threshold;
+        let mut connections = self.tracker.connections.lock();
+        let Some(connection) = connections.get_mut(&self.id) else {
+            return;
+        };
+        if let Some(stall) = connection.end_wait(threshold)
+            && stall >= threshold
+        {
+            tracing::warn!(
+                "下游写出阻塞 {}ms: 客户端 {} ({})",
+                stall.as_millis(),
+                connection.client,
+                connection.path
+            );
+        }
+    }
+
+    /// 一块数据交给 hyper
+    pub fn written(&self, len: usize) {
+        if let Some(connection) = self.tracker.connections.lock().get_mut(&self.id) {
+            connection.bytes_written += len as u64;
+            connection.chunks_written += 1;
+            connection.waiting_since = Some(Instant::now());
+        }
+    }
+}
+
+impl Drop for DrainHandle {
+    fn drop(&mut self) {
+        let Some(mut connection) = self.tracker.connections.lock().remove(&self.id) else {
+            return;
+        };
+        // 断开时仍未写出的那一块同样计入阻塞
+        connection.end_wait(self.tracker.threshold);
+        let mut totals = self.tracker.totals.lock();
+        totals.connections += 1;
+        totals.bytes_written += connection.bytes_written;
+        totals.write_stall_ms += connection.write_stall.as_millis() as u64;
+        totals.slow_stalls += connection.slow_stalls;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stall_tracking() {
+        let tracker = Arc::new(DrainTracker::new(Duration::from_millis(20)));
+        let fast = tracker.open("fast", "/v1/messages");
+        let slow = tracker.open("slow", "/v1/messages");
+
+        fast.polled();
+        fast.written(10);
+        fast.polled();
+        slow.polled();
+        slow.written(100);
+        std::thread::sleep(Duration::from_millis(30));
+
+        let report = tracker.report();
+        assert_eq!(report.active, 2);
+        assert_eq!(report.slow_clients, 1);
+        assert_eq!(tracker.slow_clients(), 1);
+        // 当前阻塞最久的在前
+        assert_eq!(report.connections[0].client, "slow");
+        assert!(report.connections[0].slow);
+        assert!(report.connections[0].current_write_stall_ms >= 30);
+        assert_eq!(report.connections[1].bytes_written, 10);
+        assert_eq!(report.connections[1].current_write_stall_ms, 0);
+
+        // 下游读取后阻塞结束，计入累计值
+        slow.polled();
+        let report = tracker.report();
+        assert_eq!(report.slow_clients, 0);
+        assert_eq!(report.connections[1].slow_stalls, 1);
+
+        drop(slow);
+        drop(fast);
+        let report = tracker.report();
+        assert_eq!(report.active, 0);
+        assert_eq!(report.completed.connections, 2);
+        assert_eq!(report.completed.bytes_written, 110);
+        assert_eq!(report.completed.slow_stalls, 1);
+        assert!(report.completed.write_stall_ms >= 30);
+    }
+}
@@ -0,0 +1,188 @@
+//! 进行中的请求与中止
+//!
+//! 每个 `/v1`、`/cc/v1`、`/openai` 请求登记一个递增的请求 ID（响应头 `x-kiro-request-id`），
+//! Admin 可列出进行中的请求，并用 `DELETE /api/admin/requests/{id}` 中止其中一个：尚未返回响应时
+//! 丢弃处理中的 future（连同上游调用），返回 503；流式响应进行中时向客户端发送 SSE error 事件后
+//! 结束流，同时断开上游流。用于终止失控的长生成，避免它持续占用凭据。
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+use chrono::{DateTime, Utc};
+use parking_lot::Mutex;
+use serde::Serialize;
+use tokio::sync::Notify;
+
+/// 请求 ID 响应头
+pub const REQUEST_ID_HEADER: &str = "x-kiro-request-id";
+
+/// 进行中的请求登记表
+pub struct InFlightRequests {
+    next_id: AtomicU64,
+    requests: Mutex<HashMap<u64, Entry>>,
+}
+
+struct Entry {
+    client: String,
+    method: String,
+    path: String,
+    started_at: DateTime<Utc>,
+    cancel: Arc<Cancel>,
+}
+
+/// 中止信号
+#[derive(Default)]
+struct Cancel {
+    cancelled: AtomicBool,
+    notify: Notify,
+}
+
+/// 进行中的请求（Admin 可见）
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InFlightRequest {
+    pub id: u64,
+    pub client: String,
+    pub method: String,
+    pub path: String,
+    pub started_at: DateTime<Utc>,
+    pub elapsed_ms: u64,
+    /// 已请求中止、尚未结束
+    pub cancelled: bool,
+}
+
+impl Default for InFlightRequests {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl InFlightRequests {
+    pub fn new() -> Self {
+        Self {
+            next_id: AtomicU64::new(1),
+            requests: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// 登记一个请求，返回的句柄 drop 时（响应发送完毕或客户端断开）注销
+    pub fn register(
+        self: &Arc<Self>,
+        client: impl Into<String>,
+        method: impl Into<String>,
+        path: impl Into<String>,
+    ) -> InFlightHandle {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let cancel = Arc::new(Cancel::default());
+        self.requests.lock().insert(
+            id,
+            Entry {
+                client: client.into(),
+                method: method.into(),
+                path: path.into(),
+                started_at: Utc::now(),
+                cancel: cancel.clone(),
+            },
+        );
+        InFlightHandle {
+            registry: self.clone(),
+            id,
+            cancel,
+        }
+    }
+
+    /// 进行中的请求，最早开始的在前
+    pub fn list(&self) -> Vec<InFlightRequest> {
+        let now = Utc::now();
+        let mut requests: Vec<InFlightRequest> = self
+            .requests
+            .lock()
+            .iter()
+            .map(|(&id, entry)| InFlightRequest {
+                id,
+                client: entry.client.clone(),
+                method: entry.method.clone(),
+                path: entry.path.clone(),
+                started_at: entry.started_at,
+                elapsed_ms: (now - entry.started_at).num_milliseconds().max(0) as u64,
+                cancelled: entry.cancel.cancelled.load(Ordering::SeqCst),
+            })
+            .collect();
+        requests.sort_by_key(|r| r.id);
+        requests
+    }
+
+    /// 中止请求，返回中止前的请求（不存在时为 None）
+    pub fn cancel(&self, id: u64) -> Option<InFlightRequest> {
+        let request = self.list().into_iter().find(|r| r.id == id)?;
+        if let Some(entry) = self.requests.lock().get(&id) {
+            entry.cancel.cancelled.store(true, Ordering::SeqCst);
+            // notify_one 在无人等待时保留许可，请求在登记后、开始等待前被中止也不会丢失
+            entry.cancel.notify.notify_one();
+        }
+        Some(request)
+    }
+}
+
+/// 单个请求的登记句柄
+pub struct InFlightHandle {
+    registry: Arc<InFlightRequests>,
+    id: u64,
+    cancel: Arc<Cancel>,
+}
+
+impl InFlightHandle {
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// 等待中止（已中止时立即返回）
+    pub async fn cancelled(&self) {
+        if self.cancel.cancelled.load(Ordering::SeqCst) {
+            return;
+        }
+        self.cancel.notify.notified().await;
+        // 保留许可，之后再次等待（如响应体阶段）同样立即返回
+        self.cancel.notify.notify_one();
+    }
+}
+
+impl Drop for InFlightHandle {
+    fn drop(&mut self) {
+        self.registry.requests.lock().remove(&self.id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_register_and_cancel() {
+        let registry = Arc::new(InFlightRequests::new());
+        let first = registry.register("ci", "POST", "/v1/messages");
+        let second = registry.register("web", "POST", "/v1/responses");
+        assert_eq!(
+            registry.list().iter().map(|r| r.id).collect::<Vec<_>>(),
+            vec![first.id(), second.id()]
+        );
+
+        // 先中止、后等待也能收到
+        let cancelled = registry.cancel(second.id()).unwrap();
+        assert_eq!(cancelled.client, "web");
+        second.cancelled().await;
+        second.cancelled().await;
+        assert!(registry.list()[1].cancelled);
+        assert!(registry.cancel(999).is_none());
+
+        // 未中止的请求一直等待
+        let waiting = tokio::time::timeout(std::time::Duration::from_millis(20), first.cancelled());
+        assert!(waiting.await.is_err());
+
+        drop(second);
+        assert_eq!(registry.list().len(), 1);
+        drop(first);
+        assert!(registry.list().is_empty());
+    }
+}
@@ -0,0 +1,109 @@
+//! 实例锁
+//!
+//! 两个实例指向同一个状态目录（或凭据文件所在目录）时，会各自刷新同一批凭据：刷新会轮换
+//! refreshToken，后回写的一方覆盖先刷新的一方，另一方手里的 refreshToken 随即失效。启动时在该目录下
+//! 对 `.kiro-rs.lock` 加建议性文件锁（进程退出时由操作系统释放，不会残留），锁已被占用时按
+//! `instanceLock` 拒绝启动，或以只读模式启动：不刷新 Token、不回写凭据，改为读取持锁实例回写的凭据。
+
+use std::fs::{File, OpenOptions, TryLockError};
+use std::io::{self, Read, Seek, Write};
+use std::path::{Path, PathBuf};
+
+/// 锁文件名
+pub const LOCK_FILE: &str = ".kiro-rs.lock";
+
+/// 持有中的实例锁（drop 时释放）
+#[derive(Debug)]
+pub struct InstanceLock {
+    _file: File,
+    path: PathBuf,
+}
+
+/// 加锁结果
+#[derive(Debug)]
+pub enum LockOutcome {
+    Acquired(InstanceLock),
+    /// 已被其他实例持有（`pid` 为锁文件中记录的进程号）
+    Held {
+        path: PathBuf,
+        pid: Option<u32>,
+    },
+}
+
+/// 加锁的目录：状态目录，未设置时为回写的凭据文件所在目录（凭据不回写时不加锁）
+pub fn lock_dir(state_dir: Option<&str>, persist_path: Option<&Path>) -> Option<PathBuf> {
+    if let Some(dir) = state_dir {
+        return Some(PathBuf::from(dir));
+    }
+    let parent = persist_path?.parent()?;
+    Some(if parent.as_os_str().is_empty() {
+        PathBuf::from(".")
+    } else {
+        parent.to_path_buf()
+    })
+}
+
+impl InstanceLock {
+    /// 尝试对 `dir` 加锁，不等待
+    pub fn try_acquire(dir: &Path) -> io::Result<LockOutcome> {
+        let path = dir.join(LOCK_FILE);
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&path)?;
+        match file.try_lock() {
+            Ok(()) => {}
+            Err(TryLockError::WouldBlock) => {
+                let mut content = String::new();
+                let _ = file.read_to_string(&mut content);
+                return Ok(LockOutcome::Held {
+                    path,
+                    pid: content.trim().parse().ok(),
+                });
+            }
+            Err(TryLockError::Error(e)) => return Err(e),
+        }
+
+        // 记录持锁进程，便于排查
+        file.set_len(0)?;
+        file.rewind()?;
+        write!(file, "{}", std::process::id())?;
+        file.sync_all()?;
+        Ok(LockOutcome::Acquired(Self { _file: file, path }))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_second_lock_is_refused() {
+        let dir = std::env::temp_dir().join(format!("kiro-lock-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let LockOutcome::Acquired(lock) = InstanceLock::try_acquire(&dir).unwrap() else {
+            panic!("首次加锁应成功");
+        };
+        assert_eq!(lock.path(), dir.join(LOCK_FILE));
+        match InstanceLock::try_acquire(&dir).unwrap() {
+            LockOutcome::Held { pid, .. } => assert_eq!(pid, Some(std::process::id())),
+            LockOutcome::Acquired(_) => panic!("锁已被持有"),
+        }
+
+        // 释放后可以重新加锁
+        drop(lock);
+        assert!(matches!(
+            InstanceLock::try_acquire(&dir).unwrap(),
+            LockOutcome::Acquired(_)
+        ));
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
@@ -0,0 +1,555 @@
+//! 按请求记录的用量账本
+//!
+//! `UsageTracker` 只在内存中保留按客户端的累计值，重启即丢失，也无法按时间窗口或凭据汇总。
+//! 账本为每个请求记录一条（时间、客户端、凭据、模型、tokens、结果）：设置 stateDir 时由后台线程以
+//! JSON Lines 只追加写入 `{stateDir}/usage.jsonl`（请求路径上不做文件 IO），否则只在内存中保留最近的记录。
+//! `kiro-rs usage` 与 `GET /api/admin/usage/summary` 基于它按时间窗口汇总。
+//! 配置保留期时，后台任务定期用 [`UsageLedger::vacuum`] 按客户端删除过期记录。
+
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::fs::OpenOptions;
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::sync::mpsc::{self, Sender};
+use std::thread::JoinHandle;
+
+use chrono::{DateTime, Duration, Utc};
+use parking_lot::{Mutex, RwLock};
+use serde::{Deserialize, Serialize};
+
+use super::persist;
+use super::privacy::Capabilities;
+
+/// 账本文件名（位于 stateDir）
+pub const LEDGER_FILE: &str = "usage.jsonl";
+
+/// 未设置 stateDir 时内存中保留的记录数
+const MEMORY_LIMIT: usize = 10_000;
+
+/// 请求结果
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum UsageOutcome {
+    /// 正常完成
+    Completed,
+    /// 客户端中途断开
+    Disconnected,
+    /// 上游调用失败
+    Error,
+}
+
+/// 单个请求的用量记录
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UsageRecord {
+    pub time: DateTime<Utc>,
+    pub client: String,
+    /// 提供响应的凭据（上游调用失败时未知）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub credential_id: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    /// 请求的端点（路由模板，如 `/v1/messages`）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub endpoint: Option<String>,
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    /// 从开始处理到响应结束（或客户端断开）的时长
+    #[serde(default)]
+    pub duration_ms: u64,
+    pub outcome: UsageOutcome,
+}
+
+/// 汇总维度
+#[derive(Debug, Clone, Copy, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum GroupBy {
+    #[default]
+    Client,
+    Credential,
+    Model,
+}
+
+impl GroupBy {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Client => "client",
+            Self::Credential => "credential",
+            Self::Model => "model",
+        }
+    }
+}
+
+impl FromStr for GroupBy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "client" => Ok(Self::Client),
+            "credential" => Ok(Self::Credential),
+            "model" => Ok(Self::Model),
+            _ => Err(format!(
+                "未知的汇总维度: {}（可选 client、credential、model）",
+                s
+            )),
+        }
+    }
+}
+
+/// 汇总查询条件
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UsageSummaryQuery {
+    /// 时间窗口起点：相对时长（如 `30m`、`24h`、`7d`、`2w`）或 RFC3339 时间，默认 `7d`
+    pub since: Option<String>,
+    #[serde(default)]
+    pub group_by: GroupBy,
+}
+
+/// 汇总结果的一行
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct UsageSummaryRow {
+    /// 分组键（客户端名、凭据 ID 或模型名，未知时为 `-`）
+    pub key: String,
+    pub requests: u64,
+    pub completed: u64,
+    pub disconnected: u64,
+    pub errors: u64,
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    /// 上游调用失败的比例
+    pub error_rate: f64,
+}
+
+/// 解析时间窗口起点
+pub fn parse_since(since: &str, now: DateTime<Utc>) -> Result<DateTime<Utc>, String> {
+    if let Ok(time) = DateTime::parse_from_rfc3339(since) {
+        return Ok(time.with_timezone(&Utc));
+    }
+    let invalid = || {
+        format!(
+            "无效的时间窗口: {}（如 30m、24h、7d 或 RFC3339 时间）",
+            since
+        )
+    };
+    let (split, _) = since.char_indices().last().ok_or_else(invalid)?;
+    let (amount, unit) = since.split_at(split);
+    let amount: i64 = amount.parse().map_err(|_| invalid())?;
+    let duration = match unit {
+        "m" => Duration::try_minutes(amount),
+        "h" => Duration::try_hours(amount),
+        "d" => Duration::try_days(amount),
+        "w" => Duration::try_weeks(amount),
+        _ => None,
+    }
+    .ok_or_else(invalid)?;
+    Ok(now - duration)
+}
+
+/// 按维度汇总 `since` 之后的记录，按请求数降序
+pub fn summarize(
+    records: impl IntoIterator<Item = UsageRecord>,
+    since: DateTime<Utc>,
+    group_by: GroupBy,
+) -> Vec<UsageSummaryRow> {
+    let mut rows: HashMap<String, UsageSummaryRow> = HashMap::new();
+    for record in records.into_iter().filter(|r| r.time >= since) {
+        let key = match group_by {
+            GroupBy::Client => Some(record.client),
+            GroupBy::Credential => record.credential_id.map(|id| id.to_string()),
+            GroupBy::Model => record.model,
+        }
+        .unwrap_or_else(|| "-".to_string());
+        let row = rows.entry(key.clone()).or_insert_with(|| UsageSummaryRow {
+            key,
+            ..Default::default()
+        });
+        row.requests += 1;
+        row.input_tokens += record.input_tokens;
+        row.output_tokens += record.output_tokens;
+        match record.outcome {
+            UsageOutcome::Completed => row.completed += 1,
+            UsageOutcome::Disconnected => row.disconnected += 1,
+            UsageOutcome::Error => row.errors += 1,
+        }
+    }
+
+    let mut rows: Vec<UsageSummaryRow> = rows
+        .into_values()
+        .map(|mut row| {
+            row.error_rate = row.errors as f64 / row.requests as f64;
+            row
+        })
+        .collect();
+    rows.sort_by(|a, b| b.requests.cmp(&a.requests).then(a.key.cmp(&b.key)));
+    rows
+}
+
+/// 读取账本文件（文件不存在时为空）
+pub fn read_file(path: &Path) -> std::io::Result<Vec<UsageRecord>> {
+    match std::fs::File::open(path) {
+        Ok(file) => Ok(BufReader::new(file)
+            .lines()
+            .map_while(Result::ok)
+            .filter_map(|line| serde_json::from_str(&line).ok())
+            .collect()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(e),
+    }
+}
+
+/// 后台写入线程的指令
+enum WriterCommand {
+    /// 追加一行（已序列化的记录）
+    Append(String),
+    /// 此前的记录写完后回复
+    Flush(Sender<()>),
+}
+
+/// 后台追加写入账本文件
+///
+/// 记录经通道交给独立线程，按到达顺序批量追加；写入时持有文件的读锁，只有 `vacuum` 重写文件时
+/// 持有写锁。Drop 时写完通道中剩余的记录再退出。
+struct LedgerWriter {
+    sender: Option<Sender<WriterCommand>>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl LedgerWriter {
+    fn new(path: PathBuf, file_lock: Arc<RwLock<()>>) -> Self {
+        let (sender, receiver) = mpsc::channel::<WriterCommand>();
+        let handle = std::thread::Builder::new()
+            .name("kiro-usage-ledger".to_string())
+            .spawn(move || {
+                while let Ok(command) = receiver.recv() {
+                    let mut lines = Vec::new();
+                    let mut acks = Vec::new();
+                    // 合并已到达的指令，一次打开文件写入
+                    for command in std::iter::once(command).chain(receiver.try_iter()) {
+                        match command {
+                            WriterCommand::Append(line) => lines.push(line),
+                            WriterCommand::Flush(ack) => acks.push(ack),
+                        }
+                    }
+                    if !lines.is_empty() {
+                        let _guard = file_lock.read();
+                        let result = OpenOptions::new()
+                            .create(true)
+                            .append(true)
+                            .open(&path)
+                            .and_then(|mut file| {
+                                file.write_all(format!("{}\n", lines.join("\n")).as_bytes())
+                            });
+                        if let Err(e) = result {
+                            tracing::error!("写入用量账本失败 {}: {}", path.display(), e);
+                        }
+                    }
+                    for ack in acks {
+                        let _ = ack.send(());
+                    }
+                }
+            })
+            .ok();
+        if handle.is_none() {
+            tracing::warn!("无法启动用量账本写入线程，用量记录将不会持久化");
+        }
+        Self {
+            sender: Some(sender),
+            handle,
+        }
+    }
+
+    fn send(&self, command: WriterCommand) -> bool {
+        self.handle.is_some()
+            && self
+                .sender
+                .as_ref()
+                .is_some_and(|sender| sender.send(command).is_ok())
+    }
+
+    /// 等待此前提交的记录写完
+    fn flush(&self) {
+        let (ack, done) = mpsc::channel();
+        if self.send(WriterCommand::Flush(ack)) {
+            let _ = done.recv();
+        }
+    }
+}
+
+impl Drop for LedgerWriter {
+    fn drop(&mut self) {
+        self.sender.take();
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+/// 用量账本
+pub struct UsageLedger {
+    /// 是否保留记录（隐私模式下不保留）
+    history: bool,
+    path: Option<PathBuf>,
+    /// 未设置 stateDir 时的内存记录
+    memory: Mutex<VecDeque<UsageRecord>>,
+    /// 账本文件的读写锁：追加与汇总取读锁，只有 `vacuum` 重写文件时取写锁
+    file_lock: Arc<RwLock<()>>,
+    writer: Option<LedgerWriter>,
+}
+
+impl UsageLedger {
+    /// 写入 `{stateDir}/usage.jsonl`，未设置 stateDir 时仅内存中保留，隐私模式下不保留
+    pub fn open(state_dir: Option<&str>, capabilities: Capabilities) -> Self {
+        let path = state_dir
+            .filter(|_| capabilities.history)
+            .map(|dir| PathBuf::from(dir).join(LEDGER_FILE));
+        let file_lock = Arc::new(RwLock::new(()));
+        let writer = path
+            .clone()
+            .map(|path| LedgerWriter::new(path, file_lock.clone()));
+        Self {
+            history: capabilities.history,
+            path,
+            memory: Mutex::new(VecDeque::new()),
+            file_lock,
+            writer,
+        }
+    }
+
+    /// 追加一条记录（文件账本交给后台线程写入，不阻塞）
+    pub fn append(&self, record: UsageRecord) {
+        if !self.history {
+            return;
+        }
+        let Some(writer) = &self.writer else {
+            let mut memory = self.memory.lock();
+            memory.push_back(record);
+            while memory.len() > MEMORY_LIMIT {
+                memory.pop_front();
+            }
+            return;
+        };
+        match serde_json::to_string(&record) {
+            Ok(line) => {
+                writer.send(WriterCommand::Append(line));
+            }
+            Err(e) => tracing::error!("序列化用量记录失败: {}", e),
+        }
+    }
+
+    /// 按条件汇总（包含已提交但尚未写入文件的记录）
+    pub fn summary(&self, query: &UsageSummaryQuery) -> Result<Vec<UsageSummaryRow>, String> {
+        let since = parse_since(query.since.as_deref().unwrap_or("7d"), Utc::now())?;
+        let records = match (&self.path, &self.writer) {
+            (Some(path), Some(writer)) => {
+                writer.flush();
+                let _guard = self.file_lock.read();
+                read_file(path)
+                    .map_err(|e| format!("读取用量账本失败 {}: {}", path.display(), e))?
+            }
+            _ => self.memory.lock().iter().cloned().collect(),
+        };
+        Ok(summarize(records, since, query.group_by))
+    }
+
+    /// 删除超过保留期的记录，`retention_days` 返回客户端的保留天数（0 表示永久保留）
+    ///
+    /// 返回每个客户端删除的记录数。文件账本原子重写，并删除 `.bak` 备份，使删除的记录不再留存
+    pub fn vacuum(
+        &self,
+        now: DateTime<Utc>,
+        retention_days: impl Fn(&str) -> u32,
+    ) -> std::io::Result<BTreeMap<String, usize>> {
+        let mut removed = BTreeMap::new();
+        let mut expired = |record: &UsageRecord| {
+            let days = retention_days(&record.client);
+            let expired = days > 0 && record.time < now - Duration::days(days as i64);
+            if expired {
+                *removed.entry(record.client.clone()).or_insert(0) += 1;
+            }
+            expired
+        };
+
+        let (Some(path), Some(writer)) = (&self.path, &self.writer) else {
+            self.memory.lock().retain(|record| !expired(record));
+            return Ok(removed);
+        };
+        // 只阻塞后台写入线程，请求路径上的追加不受影响
+        writer.flush();
+        let _guard = self.file_lock.write();
+        let records = read_file(path)?;
+        let mut contents = String::new();
+        for record in records.iter().filter(|record| !expired(record)) {
+            contents.push_str(&serde_json::to_string(record).map_err(std::io::Error::other)?);
+            contents.push('\n');
+        }
+        if removed.is_empty() {
+            return Ok(removed);
+        }
+        persist::write_atomic(path, contents.as_bytes())?;
+        match std::fs::remove_file(persist::backup_path(path)) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e),
+            _ => {}
+        }
+        Ok(removed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(
+        client: &str,
+        credential_id: Option<u64>,
+        hours_ago: i64,
+        outcome: UsageOutcome,
+    ) -> UsageRecord {
+        UsageRecord {
+            time: Utc::now() - Duration::hours(hours_ago),
+            client: client.to_string(),
+            credential_id,
+            model: Some("claude-sonnet-4-5".to_string()),
+            endpoint: Some("/v1/messages".to_string()),
+            input_tokens: 100,
+            output_tokens: 10,
+            duration_ms: 1200,
+            outcome,
+        }
+    }
+
+    #[test]
+    fn test_append_and_summarize() {
+        let dir = std::env::temp_dir().join(format!("kiro-ledger-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let ledger = UsageLedger::open(dir.to_str(), Capabilities::ALL);
+        ledger.append(record("alice", Some(1), 1, UsageOutcome::Completed));
+        ledger.append(record("alice", Some(2), 2, UsageOutcome::Disconnected));
+        ledger.append(record("bob", None, 3, UsageOutcome::Error));
+        ledger.append(record("bob", Some(1), 24 * 30, UsageOutcome::Completed));
+
+        // 重新打开后仍能汇总（只追加写入文件）
+        let ledger = UsageLedger::open(dir.to_str(), Capabilities::ALL);
+        let by_credential = ledger
+            .summary(&UsageSummaryQuery {
+                since: Some("7d".to_string()),
+                group_by: GroupBy::Credential,
+            })
+            .unwrap();
+        let keys: Vec<&str> = by_credential.iter().map(|r| r.key.as_str()).collect();
+        assert_eq!(keys, ["-", "1", "2"]);
+        assert_eq!(by_credential[0].errors, 1);
+        assert_eq!(by_credential[0].error_rate, 1.0);
+        assert_eq!(by_credential[1].requests, 1);
+
+        let by_client = ledger
+            .summary(&UsageSummaryQuery {
+                since: Some("60d".to_string()),
+                group_by: GroupBy::Client,
+            })
+            .unwrap();
+        assert_eq!(by_client.len(), 2);
+        assert_eq!(by_client[0].key, "alice");
+        assert_eq!(by_client[1].requests, 2);
+        assert_eq!(by_client[1].error_rate, 0.5);
+        assert_eq!(by_client[0].input_tokens, 200);
+
+        assert!(
+            ledger
+                .summary(&UsageSummaryQuery {
+                    since: Some("7x".to_string()),
+                    ..Default::default()
+                })
+                .is_err()
+        );
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_append_does_not_wait_for_file_lock() {
+        let dir = std::env::temp_dir().join(format!("kiro-ledger-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let ledger = UsageLedger::open(dir.to_str(), Capabilities::ALL);
+
+        // 模拟正在重写文件的 vacuum：请求路径上的追加立即返回
+        let guard = ledger.file_lock.write();
+        for _ in 0..100 {
+            ledger.append(record("alice", Some(1), 1, UsageOutcome::Completed));
+        }
+        assert!(!dir.join(LEDGER_FILE).exists());
+        drop(guard);
+
+        let rows = ledger.summary(&UsageSummaryQuery::default()).unwrap();
+        assert_eq!(rows[0].requests, 100);
+
+        // Drop 时写完剩余记录
+        let ledger = UsageLedger::open(dir.to_str(), Capabilities::ALL);
+        ledger.append(record("bob", Some(1), 1, UsageOutcome::Completed));
+        drop(ledger);
+        assert_eq!(read_file(&dir.join(LEDGER_FILE)).unwrap().len(), 101);
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_vacuum_per_client_retention() {
+        let dir = std::env::temp_dir().join(format!("kiro-ledger-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        for state_dir in [dir.to_str(), None] {
+            let ledger = UsageLedger::open(state_dir, Capabilities::ALL);
+            ledger.append(record("alice", Some(1), 24 * 10, UsageOutcome::Completed));
+            ledger.append(record("alice", Some(1), 1, UsageOutcome::Completed));
+            ledger.append(record("bob", Some(1), 24 * 10, UsageOutcome::Completed));
+            ledger.append(record("carol", Some(1), 24 * 100, UsageOutcome::Completed));
+
+            let retention = |client: &str| match client {
+                "alice" => 7,
+                "bob" => 0,
+                _ => 90,
+            };
+            let removed = ledger.vacuum(Utc::now(), retention).unwrap();
+            assert_eq!(
+                removed,
+                BTreeMap::from([("alice".to_string(), 1), ("carol".to_string(), 1)])
+            );
+            assert!(ledger.vacuum(Utc::now(), retention).unwrap().is_empty());
+
+            let rows = ledger
+                .summary(&UsageSummaryQuery {
+                    since: Some("365d".to_string()),
+                    group_by: GroupBy::Client,
+                })
+                .unwrap();
+            let counts: Vec<(&str, u64)> =
+                rows.iter().map(|r| (r.key.as_str(), r.requests)).collect();
+            assert_eq!(counts, [("alice", 1), ("bob", 1)]);
+        }
+        assert!(!persist::backup_path(&dir.join(LEDGER_FILE)).exists());
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_parse_since() {
+        let now = Utc::now();
+        assert_eq!(parse_since("7d", now).unwrap(), now - Duration::days(7));
+        assert_eq!(
+            parse_since("30m", now).unwrap(),
+            now - Duration::minutes(30)
+        );
+        assert_eq!(
+            parse_since("2026-01-01T00:00:00Z", now)
+                .unwrap()
+                .to_rfc3339(),
+            "2026-01-01T00:00:00+00:00"
+        );
+        assert!(parse_since("", now).is_err());
+        assert!(parse_since("d", now).is_err());
+        assert!(parse_since("中d", now).is_err());
+        assert!(parse_since("7天", now).is_err());
+    }
+}
@@ -0,0 +1,132 @@
+//! 代理负载统计
+//!
+//! 统计进行中的请求数和排队深度，供 KEDA / HPA 外部指标按真实代理负载扩缩容：
+//! - `in_flight`：已进入 `/v1`、`/cc/v1` 且响应尚未发送完毕的请求（流式请求持续到流结束）
+//! - `queue_depth`：正在等待上游响应头的调用（获取凭据、刷新 Token、重试退避都计入）
+//! - `queues`：启用准入队列时各优先级类别的排队数与等待时间
+//! - `credentialEvents`：启动以来各类凭据事件（禁用、恢复、刷新、额度用尽）的次数
+//! - `slowClients`：当前写出阻塞超过阈值的下游连接数（见 `drain`）
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+
+use serde::Serialize;
+
+use crate::common::admission::ClassQueueMetrics;
+use crate::kiro::events::TokenEventCounts;
+
+/// 负载计数器
+#[derive(Debug, Default)]
+pub struct LoadTracker {
+    in_flight: AtomicUsize,
+    queue_depth: AtomicUsize,
+    total_requests: AtomicU64,
+}
+
+/// 计数守卫，drop 时对应计数减一
+#[derive(Debug)]
+pub struct LoadGuard {
+    tracker: Arc<LoadTracker>,
+    queued: bool,
+}
+
+impl Drop for LoadGuard {
+    fn drop(&mut self) {
+        let counter = if self.queued {
+            &self.tracker.queue_depth
+        } else {
+            &self.tracker.in_flight
+        };
+        counter.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// 负载快照
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LoadMetrics {
+    pub in_flight: usize,
+    pub queue_depth: usize,
+    /// 启动以来的请求总数
+    pub total_requests: u64,
+    pub available_credentials: usize,
+    pub total_credentials: usize,
+    /// 即将需要重新登录的凭据数量
+    pub credentials_expiring_soon: usize,
+    /// 准入队列各优先级类别的排队指标（未启用准入队列时为空）
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub queues: Vec<ClassQueueMetrics>,
+    /// 凭据事件计数
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub credential_events: Option<TokenEventCounts>,
+    /// 当前写出阻塞超过阈值的下游连接数
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub slow_clients: Option<usize>,
+}
+
+impl LoadTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 请求开始
+    pub fn start_request(self: &Arc<Self>) -> LoadGuard {
+        self.in_flight.fetch_add(1, Ordering::Relaxed);
+        self.total_requests.fetch_add(1, Ordering::Relaxed);
+        LoadGuard {
+            tracker: self.clone(),
+            queued: false,
+        }
+    }
+
+    /// 开始等待上游响应
+    pub fn enqueue(self: &Arc<Self>) -> LoadGuard {
+        self.queue_depth.fetch_add(1, Ordering::Relaxed);
+        LoadGuard {
+            tracker: self.clone(),
+            queued: true,
+        }
+    }
+
+    /// 进行中的请求数
+    pub fn in_flight(&self) -> usize {
+        self.in_flight.load(Ordering::Relaxed)
+    }
+
+    /// 获取快照
+    pub fn snapshot(&self, available_credentials: usize, total_credentials: usize) -> LoadMetrics {
+        LoadMetrics {
+            in_flight: self.in_flight.load(Ordering::Relaxed),
+            queue_depth: self.queue_depth.load(Ordering::Relaxed),
+            total_requests: self.total_requests.load(Ordering::Relaxed),
+            available_credentials,
+            total_credentials,
+            credentials_expiring_soon: 0,
+            queues: Vec::new(),
+            credential_events: None,
+            slow_clients: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_guards_track_counts() {
+        let tracker = Arc::new(LoadTracker::new());
+        let request = tracker.start_request();
+        let queued = tracker.enqueue();
+        let metrics = tracker.snapshot(1, 2);
+        assert_eq!(metrics.in_flight, 1);
+        assert_eq!(metrics.queue_depth, 1);
+
+        drop(queued);
+        assert_eq!(tracker.snapshot(1, 2).queue_depth, 0);
+        drop(request);
+        let metrics = tracker.snapshot(1, 2);
+        assert_eq!(metrics.in_flight, 0);
+        assert_eq!(metrics.total_requests, 1);
+    }
+}
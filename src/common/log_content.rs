@@ -0,0 +1,145 @@
+//! 日志中的请求/响应内容
+//!
+//! 提示词、模型输出与上游错误体（上游校验失败时可能回显请求内容）写入日志前按 `logContent.maxChars`
+//! 截断，客户端密钥可用 `logContent` 单独覆盖：`0`（默认）只记录长度，正整数保留前这么多个字符，
+//! `"full"` 完整输出。返回给客户端的错误信息不受影响。
+
+use std::fmt;
+
+use reqwest::StatusCode;
+
+use crate::model::config::LogContentLimit;
+
+/// 按长度限制输出的内容
+pub struct Clipped<'a> {
+    text: &'a str,
+    limit: LogContentLimit,
+}
+
+/// 按 `limit` 截断 `text`（用于日志参数）
+pub fn clip(limit: LogContentLimit, text: &str) -> Clipped<'_> {
+    Clipped { text, limit }
+}
+
+impl fmt::Display for Clipped<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let max = match self.limit {
+            LogContentLimit::Full(_) => return f.write_str(self.text),
+            LogContentLimit::Chars(max) => max,
+        };
+        let total = self.text.chars().count();
+        if total <= max {
+            return f.write_str(self.text);
+        }
+        if max == 0 {
+            return write!(f, "<已省略 {} 字符>", total);
+        }
+        let end = self
+            .text
+            .char_indices()
+            .nth(max)
+            .map_or(self.text.len(), |(i, _)| i);
+        write!(f, "{}…<共 {} 字符>", &self.text[..end], total)
+    }
+}
+
+/// 上游返回的错误响应
+///
+/// `Display` 包含完整的错误体（返回给客户端）；写日志时用 [`log_error`] 截断错误体
+#[derive(Debug)]
+pub struct UpstreamError {
+    message: String,
+    status: StatusCode,
+    body: String,
+}
+
+impl UpstreamError {
+    pub fn new(message: impl Into<String>, status: StatusCode, body: &str) -> Self {
+        Self {
+            message: message.into(),
+            status,
+            body: body.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for UpstreamError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {} {}", self.message, self.status, self.body)
+    }
+}
+
+impl std::error::Error for UpstreamError {}
+
+/// 错误的日志形式：其中的上游错误体按 `limit` 截断（含包装后的错误，如重试耗尽），其他错误原样输出
+pub fn log_error(limit: LogContentLimit, error: &anyhow::Error) -> String {
+    let message = error.to_string();
+    match error
+        .chain()
+        .find_map(|e| e.downcast_ref::<UpstreamError>())
+    {
+        Some(e) => message.replace(
+            &e.to_string(),
+            &format!("{}: {} {}", e.message, e.status, clip(limit, &e.body)),
+        ),
+        None => message,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kiro::retry_audit::RetryExhaustedError;
+    use crate::model::config::FullLogContent;
+
+    #[test]
+    fn test_clip_and_log_error() {
+        let text = "你好，世界";
+        assert_eq!(
+            clip(LogContentLimit::Chars(0), text).to_string(),
+            "<已省略 5 字符>"
+        );
+        assert_eq!(
+            clip(LogContentLimit::Chars(2), text).to_string(),
+            "你好…<共 5 字符>"
+        );
+        assert_eq!(clip(LogContentLimit::Chars(5), text).to_string(), text);
+        assert_eq!(
+            clip(LogContentLimit::Full(FullLogContent::Full), text).to_string(),
+            text
+        );
+
+        let limit: LogContentLimit = serde_json::from_str("\"full\"").unwrap();
+        assert_eq!(limit, LogContentLimit::Full(FullLogContent::Full));
+        let limit: LogContentLimit = serde_json::from_str("16").unwrap();
+        assert_eq!(limit, LogContentLimit::Chars(16));
+
+        let error: anyhow::Error = UpstreamError::new(
+            "流式 API 请求失败",
+            StatusCode::BAD_REQUEST,
+            "{\"message\":\"prompt: secret\"}",
+        )
+        .into();
+        assert!(
+            error
+                .to_string()
+                .ends_with("{\"message\":\"prompt: secret\"}")
+        );
+        assert_eq!(
+            log_error(LogContentLimit::default(), &error),
+            "流式 API 请求失败: 400 Bad Request <已省略 28 字符>"
+        );
+        // 重试耗尽后包装的错误同样截断
+        let wrapped: anyhow::Error = RetryExhaustedError {
+            last_error: error,
+            attempts: Vec::new(),
+        }
+        .into();
+        assert_eq!(
+            log_error(LogContentLimit::Chars(8), &wrapped),
+            "流式 API 请求失败: 400 Bad Request {\"messag…<共 28 字符>（尝试记录: ）"
+        );
+        let other = anyhow::anyhow!("连接超时");
+        assert_eq!(log_error(LogContentLimit::default(), &other), "连接超时");
+    }
+}
@@ -0,0 +1,122 @@
+//! 维护模式
+//!
+//! 进入维护模式后 `/v1`、`/cc/v1` 的新请求返回 503（附带 Retry-After），进行中的请求照常完成；
+//! Admin API 可查询进行中的请求数，确认排空后再轮换凭据或等待上游恢复，完成后退出维护模式。
+
+use std::sync::Arc;
+
+use chrono::{DateTime, Utc};
+use parking_lot::RwLock;
+use serde::Serialize;
+
+use crate::common::load::LoadTracker;
+use crate::model::config::MaintenanceConfig;
+
+/// 当前维护窗口
+#[derive(Debug, Clone, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct MaintenanceWindow {
+    /// 返回给客户端的提示
+    pub message: String,
+    /// Retry-After 秒数
+    pub retry_after_secs: u64,
+    pub since: DateTime<Utc>,
+}
+
+/// 维护模式状态（Admin 可见）
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MaintenanceStatus {
+    pub enabled: bool,
+    #[serde(flatten)]
+    pub window: Option<MaintenanceWindow>,
+    /// 进行中的请求数
+    pub in_flight: usize,
+    /// 维护模式下进行中的请求已全部完成
+    pub drained: bool,
+}
+
+/// 维护模式开关
+pub struct MaintenanceMode {
+    config: MaintenanceConfig,
+    load: Arc<LoadTracker>,
+    window: RwLock<Option<MaintenanceWindow>>,
+}
+
+impl MaintenanceMode {
+    pub fn new(config: MaintenanceConfig, load: Arc<LoadTracker>) -> Self {
+        Self {
+            config,
+            load,
+            window: RwLock::new(None),
+        }
+    }
+
+    /// 当前维护窗口（未处于维护模式时为 None）
+    pub fn current(&self) -> Option<MaintenanceWindow> {
+        self.window.read().clone()
+    }
+
+    /// 进入维护模式（已处于维护模式时更新提示），未指定的字段使用配置中的默认值
+    pub fn enter(
+        &self,
+        message: Option<String>,
+        retry_after_secs: Option<u64>,
+    ) -> MaintenanceWindow {
+        let mut window = self.window.write();
+        let since = window.as_ref().map(|w| w.since).unwrap_or_else(Utc::now);
+        let next = MaintenanceWindow {
+            message: message.unwrap_or_else(|| self.config.message.clone()),
+            retry_after_secs: retry_after_secs.unwrap_or(self.config.retry_after_secs),
+            since,
+        };
+        *window = Some(next.clone());
+        next
+    }
+
+    /// 退出维护模式，返回退出前的维护窗口
+    pub fn exit(&self) -> Option<MaintenanceWindow> {
+        self.window.write().take()
+    }
+
+    pub fn status(&self) -> MaintenanceStatus {
+        let window = self.current();
+        let in_flight = self.load.in_flight();
+        MaintenanceStatus {
+            enabled: window.is_some(),
+            drained: window.is_some() && in_flight == 0,
+            window,
+            in_flight,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_enter_drain_and_exit() {
+        let load = Arc::new(LoadTracker::new());
+        let mode = MaintenanceMode::new(MaintenanceConfig::default(), load.clone());
+        assert!(!mode.status().enabled);
+
+        let request = load.start_request();
+        let window = mode.enter(None, Some(300));
+        assert_eq!(window.message, MaintenanceConfig::default().message);
+        assert_eq!(window.retry_after_secs, 300);
+        let status = mode.status();
+        assert!(status.enabled);
+        assert_eq!(status.in_flight, 1);
+        assert!(!status.drained);
+
+        // 更新提示时保留进入时间
+        let updated = mode.enter(Some("轮换凭据".to_string()), None);
+        assert_eq!(updated.since, window.since);
+
+        drop(request);
+        assert!(mode.status().drained);
+        assert_eq!(mode.exit(), Some(updated));
+        assert!(mode.current().is_none());
+    }
+}
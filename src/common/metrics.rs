@@ -0,0 +1,393 @@
+//! Prometheus 请求分类指标
+//!
+//! 每个请求结束时（见 `UsageTracker`）按 `endpoint`、`model`、`client` 标签累计请求数、tokens 与耗时，
+//! 在 `GET /metrics` 以 Prometheus 文本格式输出，可以直接回答“哪个团队 / 模型在消耗额度”。
+//! 客户端名取 SHA-256 前缀作为标签值，不在指标中暴露原名；模型名来自请求，客户端数量取决于配置，
+//! 二者都有基数上限（`metrics.maxModels`、`metrics.maxClients`），超出后新出现的值归入 `other`。
+//!
+//! 所有指标都在 [`REGISTRY`] 中定义，`/metrics` 按定义输出 HELP/TYPE，
+//! `kiro-rs observability export` 据此生成仪表盘与告警规则。
+
+use std::collections::{BTreeMap, HashSet};
+use std::fmt::Write;
+
+use parking_lot::Mutex;
+use sha2::{Digest, Sha256};
+
+use super::ledger::{UsageOutcome, UsageRecord};
+use crate::model::config::MetricsConfig;
+
+/// 超出基数上限时使用的标签值
+const OVERFLOW_LABEL: &str = "other";
+
+/// 客户端标签取哈希的前几位（十六进制）
+const CLIENT_HASH_LEN: usize = 12;
+
+/// 指标类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MetricKind {
+    Counter,
+    Gauge,
+    /// 只输出 `_sum` 与 `_count`
+    Summary,
+}
+
+impl MetricKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Counter => "counter",
+            Self::Gauge => "gauge",
+            Self::Summary => "summary",
+        }
+    }
+}
+
+/// 指标定义
+#[derive(Debug)]
+pub struct Metric {
+    pub name: &'static str,
+    pub kind: MetricKind,
+    pub help: &'static str,
+    pub labels: &'static [&'static str],
+}
+
+pub const IN_FLIGHT_REQUESTS: Metric = Metric {
+    name: "kiro_in_flight_requests",
+    kind: MetricKind::Gauge,
+    help: "进行中的请求数",
+    labels: &[],
+};
+
+pub const QUEUE_DEPTH: Metric = Metric {
+    name: "kiro_queue_depth",
+    kind: MetricKind::Gauge,
+    help: "等待上游响应头的调用数",
+    labels: &[],
+};
+
+pub const AVAILABLE_CREDENTIALS: Metric = Metric {
+    name: "kiro_available_credentials",
+    kind: MetricKind::Gauge,
+    help: "可用凭据数",
+    labels: &[],
+};
+
+pub const CREDENTIALS: Metric = Metric {
+    name: "kiro_credentials",
+    kind: MetricKind::Gauge,
+    help: "凭据总数",
+    labels: &[],
+};
+
+pub const STREAM_RESYNCS: Metric = Metric {
+    name: "kiro_stream_resyncs_total",
+    kind: MetricKind::Counter,
+    help: "上游事件流在损坏的帧之后重新同步的次数",
+    labels: &[],
+};
+
+pub const REQUESTS: Metric = Metric {
+    name: "kiro_requests_total",
+    kind: MetricKind::Counter,
+    help: "按端点、模型、客户端与结果统计的请求数",
+    labels: &["endpoint", "model", "client", "outcome"],
+};
+
+pub const INPUT_TOKENS: Metric = Metric {
+    name: "kiro_input_tokens_total",
+    kind: MetricKind::Counter,
+    help: "输入 tokens",
+    labels: &["endpoint", "model", "client"],
+};
+
+pub const OUTPUT_TOKENS: Metric = Metric {
+    name: "kiro_output_tokens_total",
+    kind: MetricKind::Counter,
+    help: "输出 tokens（含客户端断开前已生成的部分）",
+    labels: &["endpoint", "model", "client"],
+};
+
+pub const REQUEST_DURATION: Metric = Metric {
+    name: "kiro_request_duration_seconds",
+    kind: MetricKind::Summary,
+    help: "从开始处理到响应结束（或客户端断开）的时长",
+    labels: &["endpoint", "model", "client"],
+};
+
+pub const LABEL_OVERFLOW: Metric = Metric {
+    name: "kiro_metrics_label_overflow_total",
+    kind: MetricKind::Counter,
+    help: "超出基数上限、归入 other 的请求数",
+    labels: &["label"],
+};
+
+pub const SLO_BURN_RATE: Metric = Metric {
+    name: "kiro_slo_burn_rate",
+    kind: MetricKind::Gauge,
+    help: "错误预算消耗速率（1 表示恰好在 SLO 周期内用完），需开启 slo",
+    labels: &["slo", "window"],
+};
+
+/// `/metrics` 输出的全部指标
+pub const REGISTRY: [&Metric; 11] = [
+    &IN_FLIGHT_REQUESTS,
+    &QUEUE_DEPTH,
+    &AVAILABLE_CREDENTIALS,
+    &CREDENTIALS,
+    &STREAM_RESYNCS,
+    &REQUESTS,
+    &INPUT_TOKENS,
+    &OUTPUT_TOKENS,
+    &REQUEST_DURATION,
+    &LABEL_OVERFLOW,
+    &SLO_BURN_RATE,
+];
+
+/// 客户端名的哈希标签
+pub fn client_label(client: &str) -> String {
+    let mut label = hex::encode(Sha256::digest(client.as_bytes()));
+    label.truncate(CLIENT_HASH_LEN);
+    label
+}
+
+/// 一组标签下的累计值
+#[derive(Debug, Default, Clone, Copy)]
+struct Series {
+    completed: u64,
+    disconnected: u64,
+    errors: u64,
+    input_tokens: u64,
+    output_tokens: u64,
+    duration_ms: u64,
+}
+
+/// 有上限的标签值集合
+#[derive(Debug, Default)]
+struct LabelSet {
+    values: HashSet<String>,
+    overflowed: u64,
+}
+
+impl LabelSet {
+    /// 已知值或未达上限时原样返回，否则归入 `other`
+    fn admit(&mut self, value: String, max: usize) -> String {
+        if self.values.contains(&value) {
+            return value;
+        }
+        if self.values.len() < max {
+            self.values.insert(value.clone());
+            return value;
+        }
+        self.overflowed += 1;
+        OVERFLOW_LABEL.to_string()
+    }
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    models: LabelSet,
+    clients: LabelSet,
+    /// (endpoint, model, client) → 累计值，按标签排序输出
+    series: BTreeMap<(String, String, String), Series>,
+}
+
+/// 请求分类指标
+#[derive(Debug, Default)]
+pub struct RequestMetrics {
+    config: MetricsConfig,
+    inner: Mutex<Inner>,
+}
+
+impl RequestMetrics {
+    pub fn new(config: MetricsConfig) -> Self {
+        Self {
+            config,
+            inner: Mutex::new(Inner::default()),
+        }
+    }
+
+    /// 累计一个已结束的请求
+    pub fn observe(&self, record: &UsageRecord) {
+        let mut inner = self.inner.lock();
+        let endpoint = record.endpoint.clone().unwrap_or_else(|| "-".to_string());
+        let model = record.model.clone().unwrap_or_else(|| "-".to_string());
+        let model = inner.models.admit(model, self.config.max_models);
+        let client = inner
+            .clients
+            .admit(client_label(&record.client), self.config.max_clients);
+        let series = inner.series.entry((endpoint, model, client)).or_default();
+        match record.outcome {
+            UsageOutcome::Completed => series.completed += 1,
+            UsageOutcome::Disconnected => series.disconnected += 1,
+            UsageOutcome::Error => series.errors += 1,
+        }
+        series.input_tokens += record.input_tokens;
+        series.output_tokens += record.output_tokens;
+        series.duration_ms += record.duration_ms;
+    }
+
+    /// 以 Prometheus 文本格式输出
+    pub fn render(&self, out: &mut String) {
+        let inner = self.inner.lock();
+
+        header(out, &REQUESTS);
+        for ((endpoint, model, client), series) in &inner.series {
+            for (outcome, count) in [
+                ("completed", series.completed),
+                ("disconnected", series.disconnected),
+                ("error", series.errors),
+            ] {
+                if count > 0 {
+                    let _ = writeln!(
+                        out,
+                        "{}{{{},outcome=\"{}\"}} {}",
+                        REQUESTS.name,
+                        labels(endpoint, model, client),
+                        outcome,
+                        count
+                    );
+                }
+            }
+        }
+
+        for (metric, value) in [
+            (&INPUT_TOKENS, (|s| s.input_tokens) as fn(&Series) -> u64),
+            (&OUTPUT_TOKENS, |s| s.output_tokens),
+        ] {
+            header(out, metric);
+            for ((endpoint, model, client), series) in &inner.series {
+                let _ = writeln!(
+                    out,
+                    "{}{{{}}} {}",
+                    metric.name,
+                    labels(endpoint, model, client),
+                    value(series)
+                );
+            }
+        }
+
+        header(out, &REQUEST_DURATION);
+        for ((endpoint, model, client), series) in &inner.series {
+            let labels = labels(endpoint, model, client);
+            let _ = writeln!(
+                out,
+                "{}_sum{{{}}} {:.3}",
+                REQUEST_DURATION.name,
+                labels,
+                series.duration_ms as f64 / 1000.0
+            );
+            let _ = writeln!(
+                out,
+                "{}_count{{{}}} {}",
+                REQUEST_DURATION.name,
+                labels,
+                series.completed + series.disconnected + series.errors
+            );
+        }
+
+        header(out, &LABEL_OVERFLOW);
+        for (label, overflowed) in [
+            ("model", inner.models.overflowed),
+            ("client", inner.clients.overflowed),
+        ] {
+            let _ = writeln!(
+                out,
+                "{}{{label=\"{}\"}} {}",
+                LABEL_OVERFLOW.name, label, overflowed
+            );
+        }
+    }
+}
+
+/// 输出一个无标签的指标（gauge 或 counter）
+pub fn single(out: &mut String, metric: &Metric, value: impl std::fmt::Display) {
+    header(out, metric);
+    let _ = writeln!(out, "{} {}", metric.name, value);
+}
+
+/// 输出指标的 HELP 与 TYPE 行
+pub fn header(out: &mut String, metric: &Metric) {
+    let _ = writeln!(out, "# HELP {} {}", metric.name, metric.help);
+    let _ = writeln!(out, "# TYPE {} {}", metric.name, metric.kind.as_str());
+}
+
+/// `endpoint`、`model`、`client` 标签
+fn labels(endpoint: &str, model: &str, client: &str) -> String {
+    format!(
+        "endpoint=\"{}\",model=\"{}\",client=\"{}\"",
+        escape(endpoint),
+        escape(model),
+        escape(client)
+    )
+}
+
+/// 转义标签值中的 `\`、`"` 与换行
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+
+    fn record(client: &str, model: &str, outcome: UsageOutcome) -> UsageRecord {
+        UsageRecord {
+            time: Utc::now(),
+            client: client.to_string(),
+            credential_id: Some(1),
+            model: Some(model.to_string()),
+            endpoint: Some("/v1/messages".to_string()),
+            input_tokens: 100,
+            output_tokens: 20,
+            duration_ms: 1500,
+            outcome,
+        }
+    }
+
+    #[test]
+    fn test_labels_hashed_and_capped() {
+        let metrics = RequestMetrics::new(MetricsConfig {
+            max_models: 2,
+            max_clients: 10,
+        });
+        metrics.observe(&record(
+            "team-a",
+            "claude-sonnet-4-5",
+            UsageOutcome::Completed,
+        ));
+        metrics.observe(&record("team-a", "claude-sonnet-4-5", UsageOutcome::Error));
+        metrics.observe(&record(
+            "team-b",
+            "claude-opus-4-5",
+            UsageOutcome::Completed,
+        ));
+        // 第三个模型超出上限，归入 other
+        metrics.observe(&record("team-b", "made-up\"model", UsageOutcome::Completed));
+
+        let mut out = String::new();
+        metrics.render(&mut out);
+        let team_a = client_label("team-a");
+        assert_eq!(team_a.len(), 12);
+        assert!(!out.contains("team-a"));
+        assert!(out.contains(&format!(
+            "kiro_requests_total{{endpoint=\"/v1/messages\",model=\"claude-sonnet-4-5\",client=\"{}\",outcome=\"completed\"}} 1",
+            team_a
+        )));
+        assert!(out.contains(&format!(
+            "kiro_input_tokens_total{{endpoint=\"/v1/messages\",model=\"claude-sonnet-4-5\",client=\"{}\"}} 200",
+            team_a
+        )));
+        assert!(out.contains(&format!(
+            "kiro_request_duration_seconds_sum{{endpoint=\"/v1/messages\",model=\"claude-sonnet-4-5\",client=\"{}\"}} 3.000",
+            team_a
+        )));
+        assert!(out.contains("model=\"other\""));
+        assert!(!out.contains("made-up"));
+        assert!(out.contains("kiro_metrics_label_overflow_total{label=\"model\"} 1"));
+        assert!(out.contains("kiro_metrics_label_overflow_total{label=\"client\"} 0"));
+    }
+}
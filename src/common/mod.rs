@@ -1,3 +1,20 @@
 //! 公共工具模块
 
+pub mod abuse;
+pub mod admission;
 pub mod auth;
+pub mod client_keys;
+pub mod drain;
+pub mod firehose;
+pub mod in_flight;
+pub mod instance_lock;
+pub mod ledger;
+pub mod load;
+pub mod log_content;
+pub mod maintenance;
+pub mod metrics;
+pub mod persist;
+pub mod privacy;
+pub mod session_token;
+pub mod slo;
+pub mod usage;
@@ -0,0 +1,141 @@
+//! 状态文件的原子写入
+//!
+//! 凭据回写（主凭据文件、状态目录、附加凭据来源）与开通的客户端密钥统一经此落盘：先写同目录的临时文件
+//! 并 fsync，再 rename 覆盖目标文件（Unix 上随后 fsync 所在目录），进程崩溃或断电时目标文件要么是旧内容、
+//! 要么是新内容，不会留下写了一半的文件。覆盖前把当前内容保存为 `{文件名}.bak`；加载时目标文件解析失败
+//! （内容损坏）则回退到这份备份。
+//!
+//! 按请求追加的用量账本与审计日志是 JSON Lines，读取时跳过不完整的行，不经此写入。
+
+use std::ffi::OsString;
+use std::fmt::Display;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// 上一个版本的备份路径（`{文件名}.bak`）
+pub fn backup_path(path: &Path) -> PathBuf {
+    with_suffix(path, ".bak")
+}
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.file_name().map(OsString::from).unwrap_or_default();
+    name.push(suffix);
+    path.with_file_name(name)
+}
+
+/// 原子地替换文件内容（在 Tokio runtime 内使用 block_in_place 避免阻塞 worker）
+pub fn write_atomic(path: &Path, contents: &[u8]) -> io::Result<()> {
+    if tokio::runtime::Handle::try_current().is_ok() {
+        tokio::task::block_in_place(|| write_atomic_blocking(path, contents))
+    } else {
+        write_atomic_blocking(path, contents)
+    }
+}
+
+fn write_atomic_blocking(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let tmp = with_suffix(path, &format!(".tmp-{}", uuid::Uuid::new_v4().simple()));
+    let result = (|| {
+        let mut file = File::create(&tmp)?;
+        file.write_all(contents)?;
+        file.sync_all()?;
+        drop(file);
+        // 保留原文件的权限（凭据文件通常是 0600）
+        if let Ok(metadata) = fs::metadata(path) {
+            fs::set_permissions(&tmp, metadata.permissions())?;
+        }
+
+        // 备份失败不影响写入：目标文件本身仍然是原子替换的
+        if path.exists()
+            && let Err(e) = fs::copy(path, backup_path(path))
+        {
+            tracing::warn!("备份状态文件失败 {}: {}", path.display(), e);
+        }
+        fs::rename(&tmp, path)?;
+        sync_parent(path)
+    })();
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp);
+    }
+    result
+}
+
+/// fsync 所在目录，使 rename 本身落盘
+#[cfg(unix)]
+fn sync_parent(path: &Path) -> io::Result<()> {
+    match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => File::open(dir)?.sync_all(),
+        _ => File::open(".")?.sync_all(),
+    }
+}
+
+#[cfg(not(unix))]
+fn sync_parent(_path: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+/// 读取并解析状态文件，内容损坏时回退到 `.bak` 备份
+///
+/// 文件不存在时返回 `Ok(None)`；文件与备份都无法解析时返回文件本身的解析错误
+pub fn read_with_fallback<T, E: Display>(
+    path: &Path,
+    parse: impl Fn(&str) -> Result<T, E>,
+) -> anyhow::Result<Option<T>> {
+    let content = match fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => anyhow::bail!("读取 {} 失败: {}", path.display(), e),
+    };
+    let error = match parse(&content) {
+        Ok(value) => return Ok(Some(value)),
+        Err(e) => e.to_string(),
+    };
+
+    let backup = backup_path(path);
+    if let Ok(content) = fs::read_to_string(&backup)
+        && let Ok(value) = parse(&content)
+    {
+        tracing::warn!(
+            "{} 已损坏（{}），已回退到备份 {}",
+            path.display(),
+            error,
+            backup.display()
+        );
+        return Ok(Some(value));
+    }
+    anyhow::bail!("解析 {} 失败: {}", path.display(), error)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_atomic_write_and_fallback() {
+        let dir = std::env::temp_dir().join(format!("kiro-persist-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("state.json");
+        let parse = |s: &str| serde_json::from_str::<Vec<u32>>(s);
+
+        assert!(read_with_fallback(&path, parse).unwrap().is_none());
+
+        write_atomic(&path, b"[1]").unwrap();
+        assert!(!backup_path(&path).exists());
+        write_atomic(&path, b"[1,2]").unwrap();
+        assert_eq!(fs::read_to_string(backup_path(&path)).unwrap(), "[1]");
+        assert_eq!(read_with_fallback(&path, parse).unwrap(), Some(vec![1, 2]));
+        // 不留下临时文件
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 2);
+
+        // 写了一半的文件：回退到上一个版本
+        fs::write(&path, b"[1,").unwrap();
+        assert_eq!(read_with_fallback(&path, parse).unwrap(), Some(vec![1]));
+
+        // 备份也无法解析时报告原文件的错误
+        fs::write(backup_path(&path), b"garbage").unwrap();
+        let err = read_with_fallback(&path, parse).unwrap_err().to_string();
+        assert!(err.contains("state.json"));
+
+        fs::remove_dir_all(&dir).ok();
+    }
+}
@@ -0,0 +1,65 @@
+//! 隐私模式
+//!
+//! 开启 `privacyMode` 后，所有会留存或外发请求相关数据的子系统一律关闭：日志中的提示词与模型输出
+//! （无视 `logContent` 与客户端密钥的覆盖）、上游原始帧抓取、用量账本（请求历史）、响应缓存、
+//! firehose、OTLP 凭据遥测与影子流量镜像。这些子系统的构造函数都接收 [`Capabilities`]，
+//! 新增同类子系统时无法绕过这个开关。
+
+use crate::model::config::Config;
+
+/// 允许的持久化与外发能力
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Capabilities {
+    /// 日志中记录请求/响应内容、抓取上游原始帧
+    pub transcripts: bool,
+    /// 保留请求历史（用量账本）
+    pub history: bool,
+    /// 缓存响应
+    pub cache: bool,
+    /// 向外部导出请求摘要、遥测与镜像流量
+    pub analytics: bool,
+}
+
+impl Capabilities {
+    /// 全部允许
+    pub const ALL: Self = Self {
+        transcripts: true,
+        history: true,
+        cache: true,
+        analytics: true,
+    };
+
+    /// 全部禁止（隐私模式）
+    pub const NONE: Self = Self {
+        transcripts: false,
+        history: false,
+        cache: false,
+        analytics: false,
+    };
+
+    pub fn from_config(config: &Config) -> Self {
+        if config.privacy_mode {
+            Self::NONE
+        } else {
+            Self::ALL
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_privacy_mode_disables_everything() {
+        assert_eq!(
+            Capabilities::from_config(&Config::default()),
+            Capabilities::ALL
+        );
+        let config = Config {
+            privacy_mode: true,
+            ..Config::default()
+        };
+        assert_eq!(Capabilities::from_config(&config), Capabilities::NONE);
+    }
+}
@@ -0,0 +1,367 @@
+//! 短期会话 Token
+//!
+//! 受信任的客户端用自己的 API Key 调用 `POST /v1/session-tokens`，换取一个短期有效、可进一步收窄
+//! 使用范围的 JWT（HS256，加 `kst_` 前缀），交给浏览器端应用使用，前端不接触真正的密钥。认证中间件校验签名与有效期后，
+//! 按签发时的客户端名找回原密钥，并要求其指纹与声明中的 `kid` 一致：原密钥被吊销、轮换（包括删除后同名重建）、过期
+//! 或不在访问时段内时 Token 随之失效，模型范围取声明与原密钥当前范围的交集；用量、预算与每分钟请求数计入原客户端。
+//! 会话 Token 不能再换取新的 Token，也不授予本地工具权限。
+
+use std::sync::Arc;
+
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use subtle::ConstantTimeEq;
+use uuid::Uuid;
+
+use crate::model::config::SessionTokenConfig;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// 会话 Token 前缀（与客户端密钥区分，避免形如 JWT 的客户端密钥被当作会话 Token）
+pub const TOKEN_PREFIX: &str = "kst_";
+
+/// JWT 头（固定为 HS256）
+const HEADER: &str = r#"{"alg":"HS256","typ":"JWT"}"#;
+
+/// 会话 Token 的声明
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionClaims {
+    /// 签发 Token 的客户端名
+    pub sub: String,
+    /// 由全局 apiKey 签发（此时不按 `sub` 查找客户端密钥）
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub global: bool,
+    /// 签发时原密钥的指纹（见 `key_fingerprint`）
+    pub kid: String,
+    pub iat: i64,
+    pub exp: i64,
+    /// 收窄后允许的模型（为空表示沿用原密钥的范围）
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub models: Vec<String>,
+    /// 收窄后的 max_tokens 上限
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_tokens: Option<i32>,
+}
+
+/// 会话 Token 签发与校验
+pub struct SessionTokenIssuer {
+    config: SessionTokenConfig,
+    secret: Vec<u8>,
+}
+
+impl SessionTokenIssuer {
+    /// 从配置创建（未启用时返回 None）
+    pub fn from_config(config: &SessionTokenConfig) -> Option<Arc<Self>> {
+        if !config.enabled {
+            return None;
+        }
+        let secret = match &config.signing_secret {
+            Some(secret) => secret.as_bytes().to_vec(),
+            None => {
+                tracing::warn!(
+                    "未配置 sessionTokens.signingSecret，已随机生成，重启后已签发的会话 Token 将失效"
+                );
+                format!("{}{}", Uuid::new_v4(), Uuid::new_v4()).into_bytes()
+            }
+        };
+        Some(Arc::new(Self {
+            config: config.clone(),
+            secret,
+        }))
+    }
+
+    /// 实际有效期：未指定时取默认值，不超过 `maxTtlSecs`
+    pub fn ttl_secs(&self, requested: Option<u64>) -> u64 {
+        requested
+            .unwrap_or(self.config.default_ttl_secs)
+            .clamp(1, self.config.max_ttl_secs.max(1))
+    }
+
+    /// 签发 Token（`claims.exp` 由调用方按 `ttl_secs` 计算）
+    pub fn issue(&self, claims: &SessionClaims) -> String {
+        let payload = serde_json::to_vec(claims).expect("声明可以序列化");
+        let signing_input = format!(
+            "{}.{}",
+            URL_SAFE_NO_PAD.encode(HEADER),
+            URL_SAFE_NO_PAD.encode(payload)
+        );
+        let signature = URL_SAFE_NO_PAD.encode(self.sign(&signing_input));
+        format!("{}{}.{}", TOKEN_PREFIX, signing_input, signature)
+    }
+
+    /// 校验签名与有效期，返回声明
+    pub fn verify(&self, token: &str, now: i64) -> Option<SessionClaims> {
+        let token = token.strip_prefix(TOKEN_PREFIX)?;
+        let (signing_input, signature) = token.rsplit_once('.')?;
+        let (header, payload) = signing_input.split_once('.')?;
+        let signature = URL_SAFE_NO_PAD.decode(signature).ok()?;
+        if !bool::from(self.sign(signing_input).ct_eq(&signature)) {
+            return None;
+        }
+        // 只接受本服务签发的 HS256 头（拒绝 alg: none 等）
+        if URL_SAFE_NO_PAD.decode(header).ok()? != HEADER.as_bytes() {
+            return None;
+        }
+        let claims: SessionClaims =
+            serde_json::from_slice(&URL_SAFE_NO_PAD.decode(payload).ok()?).ok()?;
+        (claims.exp > now).then_some(claims)
+    }
+
+    fn sign(&self, signing_input: &str) -> Vec<u8> {
+        let mut mac = HmacSha256::new_from_slice(&self.secret).expect("HMAC 接受任意长度密钥");
+        mac.update(signing_input.as_bytes());
+        mac.finalize().into_bytes().to_vec()
+    }
+}
+
+fn is_false(value: &bool) -> bool {
+    !*value
+}
+
+/// 密钥指纹：SHA-256 的前 8 字节（十六进制），不泄露密钥本身
+pub fn key_fingerprint(key: &str) -> String {
+    hex::encode(&Sha256::digest(key.as_bytes())[..8])
+}
+
+/// 是否为会话 Token（带 `kst_` 前缀）
+pub fn looks_like_token(key: &str) -> bool {
+    key.starts_with(TOKEN_PREFIX)
+}
+
+/// 模型范围的交集（为空表示不限）：保留一方覆盖另一方的模式，交集为空时返回 None
+pub fn intersect_models(claimed: &[String], allowed: &[String]) -> Option<Vec<String>> {
+    if claimed.is_empty() || allowed.is_empty() {
+        return Some(if claimed.is_empty() { allowed } else { claimed }.to_vec());
+    }
+    let within =
+        |a: &String, b: &String| models_within(std::slice::from_ref(a), std::slice::from_ref(b));
+    let mut models = Vec::new();
+    for claim in claimed {
+        for pattern in allowed {
+            let narrower = if within(claim, pattern) {
+                claim
+            } else if within(pattern, claim) {
+                pattern
+            } else {
+                continue;
+            };
+            if !models.contains(narrower) {
+                models.push(narrower.clone());
+            }
+        }
+    }
+    (!models.is_empty()).then_some(models)
+}
+
+/// 收窄后的模型范围是否被原范围覆盖（原范围为空表示不限）
+pub fn models_within(requested: &[String], allowed: &[String]) -> bool {
+    allowed.is_empty()
+        || requested.iter().all(|model| {
+            allowed
+                .iter()
+                .any(|pattern| match pattern.strip_suffix('*') {
+                    Some(prefix) => model.starts_with(prefix),
+                    None => pattern == model,
+                })
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn issuer() -> Arc<SessionTokenIssuer> {
+        SessionTokenIssuer::from_config(&SessionTokenConfig {
+            enabled: true,
+            signing_secret: Some("secret".to_string()),
+            ..Default::default()
+        })
+        .unwrap()
+    }
+
+    fn claims() -> SessionClaims {
+        SessionClaims {
+            sub: "web".to_string(),
+            global: false,
+            kid: key_fingerprint("sk-web"),
+            iat: 1000,
+            exp: 1900,
+            models: vec!["claude-haiku-*".to_string()],
+            max_tokens: Some(1024),
+        }
+    }
+
+    /// 拆出 (头, 声明, 签名) 三段
+    fn segments(token: &str) -> (String, String, String) {
+        let parts: Vec<&str> = token
+            .strip_prefix(TOKEN_PREFIX)
+            .unwrap()
+            .split('.')
+            .collect();
+        assert_eq!(parts.len(), 3);
+        (
+            parts[0].to_string(),
+            parts[1].to_string(),
+            parts[2].to_string(),
+        )
+    }
+
+    /// 用 `secret` 对任意头与声明签名
+    fn sign_raw(header: &str, payload: &str) -> String {
+        let signing_input = format!(
+            "{}.{}",
+            URL_SAFE_NO_PAD.encode(header),
+            URL_SAFE_NO_PAD.encode(payload)
+        );
+        let signature = URL_SAFE_NO_PAD.encode(issuer().sign(&signing_input));
+        format!("{}{}.{}", TOKEN_PREFIX, signing_input, signature)
+    }
+
+    #[test]
+    fn test_issue_and_verify() {
+        let issuer = issuer();
+        assert_eq!(issuer.ttl_secs(None), 900);
+        assert_eq!(issuer.ttl_secs(Some(86400)), 3600);
+
+        let token = issuer.issue(&claims());
+        assert!(token.starts_with(TOKEN_PREFIX));
+        assert!(looks_like_token(&token));
+        assert_eq!(issuer.verify(&token, 1500), Some(claims()));
+        // 缺少前缀的裸 JWT 不被接受
+        assert!(
+            issuer
+                .verify(token.strip_prefix(TOKEN_PREFIX).unwrap(), 1500)
+                .is_none()
+        );
+        // 形如 JWT 的客户端密钥不会被当作会话 Token
+        assert!(!looks_like_token("eyJhbGciOi.eyJzdWIiOi.c2ln"));
+    }
+
+    #[test]
+    fn test_verify_rejects_expired() {
+        let issuer = issuer();
+        let token = issuer.issue(&claims());
+        assert!(issuer.verify(&token, 1899).is_some());
+        assert!(issuer.verify(&token, 1900).is_none());
+        assert!(issuer.verify(&token, 5000).is_none());
+    }
+
+    #[test]
+    fn test_key_fingerprint_required() {
+        assert_eq!(key_fingerprint("sk-web"), key_fingerprint("sk-web"));
+        assert_ne!(key_fingerprint("sk-web"), key_fingerprint("sk-web2"));
+        assert_eq!(key_fingerprint("sk-web").len(), 16);
+
+        // 缺少 kid 的旧格式 Token 即使签名正确也不被接受
+        let legacy = r#"{"sub":"web","iat":1000,"exp":1900,"jti":"1"}"#;
+        assert!(issuer().verify(&sign_raw(HEADER, legacy), 1500).is_none());
+    }
+
+    #[test]
+    fn test_verify_rejects_tampering() {
+        let issuer = issuer();
+        let token = issuer.issue(&claims());
+        let (header, _, signature) = segments(&token);
+
+        // 篡改声明（提升为全局 apiKey、改客户端名）但沿用原签名
+        for forged in [
+            r#"{"sub":"web","global":true,"kid":"00","iat":1000,"exp":1900}"#,
+            r#"{"sub":"admin","kid":"00","iat":0,"exp":9999}"#,
+        ] {
+            let forged = format!(
+                "{}{}.{}.{}",
+                TOKEN_PREFIX,
+                header,
+                URL_SAFE_NO_PAD.encode(forged),
+                signature
+            );
+            assert!(issuer.verify(&forged, 1500).is_none());
+        }
+
+        // 篡改签名
+        let mut bad_signature = URL_SAFE_NO_PAD.decode(&signature).unwrap();
+        bad_signature[0] ^= 1;
+        let (_, payload, _) = segments(&token);
+        let forged = format!(
+            "{}{}.{}.{}",
+            TOKEN_PREFIX,
+            header,
+            payload,
+            URL_SAFE_NO_PAD.encode(bad_signature)
+        );
+        assert!(issuer.verify(&forged, 1500).is_none());
+
+        // 其他密钥签名
+        let other = SessionTokenIssuer::from_config(&SessionTokenConfig {
+            enabled: true,
+            ..Default::default()
+        })
+        .unwrap();
+        assert!(other.verify(&token, 1500).is_none());
+    }
+
+    #[test]
+    fn test_verify_rejects_other_algorithms() {
+        let issuer = issuer();
+        let payload = serde_json::to_string(&claims()).unwrap();
+
+        // 签名正确，但头部声明了其他算法
+        for header in [
+            r#"{"alg":"none","typ":"JWT"}"#,
+            r#"{"alg":"HS512","typ":"JWT"}"#,
+            r#"{"alg":"RS256","typ":"JWT"}"#,
+        ] {
+            assert!(issuer.verify(&sign_raw(header, &payload), 1500).is_none());
+        }
+        // alg: none 且签名为空
+        let unsigned = format!(
+            "{}{}.{}.",
+            TOKEN_PREFIX,
+            URL_SAFE_NO_PAD.encode(r#"{"alg":"none","typ":"JWT"}"#),
+            URL_SAFE_NO_PAD.encode(&payload)
+        );
+        assert!(issuer.verify(&unsigned, 1500).is_none());
+        // 对照：同样的方式用 HS256 头签名可以通过
+        assert!(issuer.verify(&sign_raw(HEADER, &payload), 1500).is_some());
+    }
+
+    #[test]
+    fn test_models_within_and_intersect() {
+        let allowed = vec!["claude-*".to_string()];
+        assert!(models_within(&claims().models, &allowed));
+        assert!(!models_within(&["gpt-4".to_string()], &allowed));
+        assert!(models_within(&["gpt-4".to_string()], &[]));
+
+        let models = |list: &[&str]| list.iter().map(|m| m.to_string()).collect::<Vec<_>>();
+        // 任一方不限时取另一方
+        assert_eq!(
+            intersect_models(&[], &models(&["claude-*"])),
+            Some(models(&["claude-*"]))
+        );
+        assert_eq!(
+            intersect_models(&models(&["claude-haiku-*"]), &[]),
+            Some(models(&["claude-haiku-*"]))
+        );
+        // 取较窄的一方
+        assert_eq!(
+            intersect_models(&models(&["claude-*"]), &models(&["claude-haiku-*"])),
+            Some(models(&["claude-haiku-*"]))
+        );
+        assert_eq!(
+            intersect_models(
+                &models(&["claude-haiku-*", "claude-sonnet-4.5"]),
+                &models(&["claude-*"])
+            ),
+            Some(models(&["claude-haiku-*", "claude-sonnet-4.5"]))
+        );
+        // 没有交集
+        assert_eq!(
+            intersect_models(&models(&["claude-opus-*"]), &models(&["claude-haiku-*"])),
+            None
+        );
+    }
+}
@@ -0,0 +1,429 @@
+//! 成功率与延迟 SLO
+//!
+//! 订阅每个请求结束时的用量记录，按 10 秒分桶统计长、短两个窗口内的请求：成功率 SLO 的“坏”请求是
+//! 上游调用失败的请求，延迟 SLO 的“坏”请求是耗时超过 `latencyThresholdMs` 的已完成请求。
+//! 消耗速率 = 窗口内坏请求比例 / 错误预算（1 - 目标）；两个窗口的消耗速率都超过 `burnRateThreshold`
+//! 时开始告警（日志 + `webhookUrl`），任一窗口回落后恢复。状态见 `GET /api/admin/slo`，
+//! 消耗速率同时在 `/metrics` 以 `kiro_slo_burn_rate` 输出。
+
+use std::collections::VecDeque;
+use std::fmt::Write;
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use parking_lot::Mutex;
+use reqwest::Client;
+use serde::Serialize;
+use tokio::sync::broadcast;
+
+use super::ledger::{UsageOutcome, UsageRecord};
+use super::metrics::{self, SLO_BURN_RATE};
+use crate::http_client::{ProxyConfig, build_client};
+use crate::model::config::{Config, SloConfig};
+
+/// 分桶粒度（秒）
+const BUCKET_SECS: i64 = 10;
+
+/// webhook 请求超时（秒）
+const WEBHOOK_TIMEOUT_SECS: u64 = 10;
+
+/// 一个分桶内的计数
+#[derive(Debug, Clone, Copy, Default)]
+struct Bucket {
+    start: i64,
+    requests: u64,
+    errors: u64,
+    completed: u64,
+    slow: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SloKind {
+    Success,
+    Latency,
+}
+
+impl SloKind {
+    const ALL: [Self; 2] = [Self::Success, Self::Latency];
+
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Success => "success",
+            Self::Latency => "latency",
+        }
+    }
+}
+
+/// 单个窗口的统计
+#[derive(Debug, Clone, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct SloWindow {
+    pub window_secs: u64,
+    /// 计入该 SLO 的请求数（延迟 SLO 只计已完成的请求）
+    pub requests: u64,
+    pub bad: u64,
+    pub bad_ratio: f64,
+    pub burn_rate: f64,
+}
+
+/// 单个 SLO 的状态
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SloStatus {
+    /// `success` 或 `latency`
+    pub name: &'static str,
+    pub target: f64,
+    /// 是否正在告警
+    pub firing: bool,
+    /// 长窗口在前
+    pub windows: Vec<SloWindow>,
+}
+
+/// SLO 报告（`GET /api/admin/slo`）
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SloReport {
+    pub enabled: bool,
+    pub burn_rate_threshold: f64,
+    pub latency_threshold_ms: u64,
+    pub slos: Vec<SloStatus>,
+}
+
+impl SloReport {
+    /// 未开启时的空报告
+    pub fn empty(config: &SloConfig) -> Self {
+        Self {
+            enabled: false,
+            burn_rate_threshold: config.burn_rate_threshold,
+            latency_threshold_ms: config.latency_threshold_ms,
+            slos: Vec::new(),
+        }
+    }
+}
+
+/// SLO 跟踪器
+pub struct SloTracker {
+    config: SloConfig,
+    /// webhook 客户端（配置了 `webhookUrl` 时存在）
+    client: Option<Client>,
+    /// 按开始时间排序的分桶，只保留长窗口内的
+    buckets: Mutex<VecDeque<Bucket>>,
+    /// 各 SLO 是否正在告警（顺序同 `SloKind::ALL`）
+    firing: Mutex<[bool; 2]>,
+}
+
+impl SloTracker {
+    /// 从配置创建（未开启 `slo.enabled` 时返回 None）
+    pub fn from_config(config: &Config, proxy: Option<&ProxyConfig>) -> Option<Arc<Self>> {
+        if !config.slo.enabled {
+            return None;
+        }
+        let client = match &config.slo.webhook_url {
+            Some(_) => match build_client(proxy, WEBHOOK_TIMEOUT_SECS, config.tls_backend) {
+                Ok(client) => Some(client),
+                Err(e) => {
+                    tracing::warn!("创建 SLO webhook 客户端失败，只在日志中告警: {}", e);
+                    None
+                }
+            },
+            None => None,
+        };
+        Some(Arc::new(Self::new(config.slo.clone(), client)))
+    }
+
+    fn new(config: SloConfig, client: Option<Client>) -> Self {
+        Self {
+            config,
+            client,
+            buckets: Mutex::new(VecDeque::new()),
+            firing: Mutex::new([false; 2]),
+        }
+    }
+
+    /// 累计一个已结束的请求
+    pub fn observe(&self, record: &UsageRecord) {
+        let start = record.time.timestamp().div_euclid(BUCKET_SECS) * BUCKET_SECS;
+        let mut buckets = self.buckets.lock();
+        let pos = buckets.partition_point(|b| b.start < start);
+        if buckets.get(pos).is_none_or(|b| b.start != start) {
+            buckets.insert(
+                pos,
+                Bucket {
+                    start,
+                    ..Bucket::default()
+                },
+            );
+        }
+        let bucket = &mut buckets[pos];
+        bucket.requests += 1;
+        match record.outcome {
+            UsageOutcome::Error => bucket.errors += 1,
+            UsageOutcome::Completed => {
+                bucket.completed += 1;
+                if record.duration_ms > self.config.latency_threshold_ms {
+                    bucket.slow += 1;
+                }
+            }
+            UsageOutcome::Disconnected => {}
+        }
+
+        let horizon = buckets.back().map(|b| b.start).unwrap_or(start) - self.max_window_secs();
+        while buckets.front().is_some_and(|b| b.start < horizon) {
+            buckets.pop_front();
+        }
+    }
+
+    fn max_window_secs(&self) -> i64 {
+        self.config
+            .long_window_secs
+            .max(self.config.short_window_secs) as i64
+    }
+
+    fn target(&self, kind: SloKind) -> f64 {
+        match kind {
+            SloKind::Success => self.config.success_target,
+            SloKind::Latency => self.config.latency_target,
+        }
+    }
+
+    /// 统计 `now` 之前 `window_secs` 内的请求
+    fn window(
+        &self,
+        buckets: &VecDeque<Bucket>,
+        kind: SloKind,
+        window_secs: u64,
+        now: DateTime<Utc>,
+    ) -> SloWindow {
+        let since = now.timestamp() - window_secs as i64;
+        let (requests, bad) = buckets.iter().filter(|b| b.start >= since).fold(
+            (0, 0),
+            |(requests, bad), b| match kind {
+                SloKind::Success => (requests + b.requests, bad + b.errors),
+                SloKind::Latency => (requests + b.completed, bad + b.slow),
+            },
+        );
+        let bad_ratio = if requests == 0 {
+            0.0
+        } else {
+            bad as f64 / requests as f64
+        };
+        SloWindow {
+            window_secs,
+            requests,
+            bad,
+            bad_ratio,
+            burn_rate: bad_ratio / (1.0 - self.target(kind)),
+        }
+    }
+
+    /// 按当前数据计算各 SLO 的窗口统计，`firing` 为当前的告警状态
+    fn statuses(&self, now: DateTime<Utc>) -> Vec<SloStatus> {
+        let buckets = self.buckets.lock();
+        let firing = *self.firing.lock();
+        SloKind::ALL
+            .iter()
+            .zip(firing)
+            .map(|(&kind, firing)| SloStatus {
+                name: kind.as_str(),
+                target: self.target(kind),
+                firing,
+                windows: [self.config.long_window_secs, self.config.short_window_secs]
+                    .into_iter()
+                    .map(|secs| self.window(&buckets, kind, secs, now))
+                    .collect(),
+            })
+            .collect()
+    }
+
+    /// 当前报告
+    pub fn report(&self, now: DateTime<Utc>) -> SloReport {
+        SloReport {
+            enabled: true,
+            burn_rate_threshold: self.config.burn_rate_threshold,
+            latency_threshold_ms: self.config.latency_threshold_ms,
+            slos: self.statuses(now),
+        }
+    }
+
+    /// 更新告警状态，返回状态发生变化的 SLO
+    fn check(&self, now: DateTime<Utc>) -> Vec<SloStatus> {
+        let mut changed = Vec::new();
+        for (i, mut status) in self.statuses(now).into_iter().enumerate() {
+            let burning = status
+                .windows
+                .iter()
+                .all(|w| w.burn_rate >= self.config.burn_rate_threshold);
+            let mut firing = self.firing.lock();
+            if firing[i] != burning {
+                firing[i] = burning;
+                status.firing = burning;
+                changed.push(status);
+            }
+        }
+        changed
+    }
+
+    /// 以 Prometheus 文本格式输出消耗速率
+    pub fn render(&self, out: &mut String, now: DateTime<Utc>) {
+        metrics::header(out, &SLO_BURN_RATE);
+        for status in self.statuses(now) {
+            for window in &status.windows {
+                let _ = writeln!(
+                    out,
+                    "{}{{slo=\"{}\",window=\"{}s\"}} {:.3}",
+                    SLO_BURN_RATE.name, status.name, window.window_secs, window.burn_rate
+                );
+            }
+        }
+    }
+
+    /// 在后台消费请求记录，并按间隔检查是否需要告警
+    pub fn spawn(self: &Arc<Self>, mut records: broadcast::Receiver<UsageRecord>) {
+        let tracker = self.clone();
+        tokio::spawn(async move {
+            loop {
+                match records.recv().await {
+                    Ok(record) => tracker.observe(&record),
+                    Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                        tracing::warn!("SLO 统计落后，丢失 {} 个请求记录", skipped);
+                    }
+                    Err(broadcast::error::RecvError::Closed) => return,
+                }
+            }
+        });
+
+        let tracker = self.clone();
+        let interval = Duration::from_secs(self.config.check_interval_secs.max(1));
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                for status in tracker.check(Utc::now()) {
+                    tracker.notify(&status).await;
+                }
+            }
+        });
+    }
+
+    /// 告警开始或恢复时记录日志并调用 webhook
+    async fn notify(&self, status: &SloStatus) {
+        let rates = status
+            .windows
+            .iter()
+            .map(|w| format!("{}s={:.2}", w.window_secs, w.burn_rate))
+            .collect::<Vec<_>>()
+            .join(", ");
+        if status.firing {
+            tracing::warn!(
+                "{} SLO（目标 {}）错误预算消耗过快: {}，阈值 {}",
+                status.name,
+                status.target,
+                rates,
+                self.config.burn_rate_threshold
+            );
+        } else {
+            tracing::info!("{} SLO 错误预算消耗已恢复正常: {}", status.name, rates);
+        }
+
+        let (Some(client), Some(url)) = (&self.client, &self.config.webhook_url) else {
+            return;
+        };
+        let body = serde_json::json!({
+            "type": "sloBurnRate",
+            "state": if status.firing { "firing" } else { "resolved" },
+            "slo": status.name,
+            "target": status.target,
+            "burnRateThreshold": self.config.burn_rate_threshold,
+            "windows": status.windows,
+            "time": Utc::now().to_rfc3339(),
+        });
+        match client.post(url).json(&body).send().await {
+            Ok(resp) if !resp.status().is_success() => {
+                tracing::warn!("SLO webhook 返回 {}", resp.status());
+            }
+            Ok(_) => {}
+            Err(e) => tracing::warn!("调用 SLO webhook 失败: {}", e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(
+        now: DateTime<Utc>,
+        secs_ago: i64,
+        outcome: UsageOutcome,
+        duration_ms: u64,
+    ) -> UsageRecord {
+        UsageRecord {
+            time: now - chrono::Duration::seconds(secs_ago),
+            client: "team-a".to_string(),
+            credential_id: Some(1),
+            model: None,
+            endpoint: None,
+            input_tokens: 10,
+            output_tokens: 1,
+            duration_ms,
+            outcome,
+        }
+    }
+
+    #[test]
+    fn test_burn_rate_alerts_on_both_windows() {
+        let tracker = SloTracker::new(
+            SloConfig {
+                enabled: true,
+                burn_rate_threshold: 5.0,
+                ..SloConfig::default()
+            },
+            None,
+        );
+        let now = Utc::now();
+        // 一小时前的请求已不在长窗口内
+        tracker.observe(&record(now, 7200, UsageOutcome::Error, 100));
+        for i in 0..90 {
+            let duration = if i < 10 { 70_000 } else { 1_000 };
+            tracker.observe(&record(now, 60, UsageOutcome::Completed, duration));
+        }
+        for _ in 0..10 {
+            tracker.observe(&record(now, 60, UsageOutcome::Error, 100));
+        }
+        tracker.observe(&record(now, 1800, UsageOutcome::Disconnected, 100));
+
+        let report = tracker.report(now);
+        let success = &report.slos[0];
+        assert_eq!(success.name, "success");
+        assert_eq!(
+            (success.windows[0].requests, success.windows[0].bad),
+            (101, 10)
+        );
+        assert_eq!(
+            (success.windows[1].requests, success.windows[1].bad),
+            (100, 10)
+        );
+        assert!((success.windows[1].burn_rate - 10.0).abs() < 1e-6);
+        let latency = &report.slos[1];
+        assert_eq!(
+            (latency.windows[1].requests, latency.windows[1].bad),
+            (90, 10)
+        );
+        assert!(latency.windows[1].burn_rate < 5.0);
+
+        let changed = tracker.check(now);
+        assert_eq!(changed.len(), 1);
+        assert_eq!((changed[0].name, changed[0].firing), ("success", true));
+        assert!(tracker.check(now).is_empty());
+        assert!(tracker.report(now).slos[0].firing);
+
+        // 短窗口内没有请求后恢复
+        let changed = tracker.check(now + chrono::Duration::seconds(600));
+        assert_eq!((changed[0].name, changed[0].firing), ("success", false));
+
+        let mut out = String::new();
+        tracker.render(&mut out, now);
+        assert!(out.contains("kiro_slo_burn_rate{slo=\"success\",window=\"300s\"} 10.000"));
+    }
+}
@@ -0,0 +1,299 @@
+//! 按客户端统计用量
+//!
+//! 除了正常完成的请求，也统计客户端中途断开的请求：断开时已经生成的 tokens 同样消耗了上游额度，
+//! 只统计完成的请求会低估实际用量。断开时如果上游响应尚未结束，代理会随之断开上游连接（记为中止上游）。
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Instant;
+
+use chrono::{DateTime, Utc};
+use parking_lot::Mutex;
+use serde::Serialize;
+use tokio::sync::broadcast;
+
+use super::ledger::{UsageLedger, UsageOutcome, UsageRecord};
+use super::metrics::RequestMetrics;
+
+/// 单个客户端的累计用量（Admin 可见）
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ClientUsage {
+    pub client: String,
+    /// 正常完成的请求数
+    pub completed: u64,
+    /// 客户端中途断开的请求数
+    pub disconnected: u64,
+    /// 断开时上游尚未结束、随之中止上游的请求数
+    pub upstream_aborted: u64,
+    pub input_tokens: u64,
+    /// 输出 tokens（含断开前已生成的部分）
+    pub output_tokens: u64,
+    /// 其中断开前已生成的输出 tokens
+    pub disconnected_output_tokens: u64,
+    pub last_seen: DateTime<Utc>,
+}
+
+/// 请求记录通道容量
+const RECORD_CHANNEL_CAPACITY: usize = 1024;
+
+/// 用量统计器
+pub struct UsageTracker {
+    clients: Mutex<HashMap<String, ClientUsage>>,
+    /// 按请求记录的账本（可选）
+    ledger: Option<UsageLedger>,
+    /// Prometheus 请求分类指标（可选）
+    metrics: Option<Arc<RequestMetrics>>,
+    /// 每个请求结束时发布一条记录（供 firehose 等订阅方使用）
+    records: broadcast::Sender<UsageRecord>,
+}
+
+impl Default for UsageTracker {
+    fn default() -> Self {
+        Self {
+            clients: Mutex::new(HashMap::new()),
+            ledger: None,
+            metrics: None,
+            records: broadcast::channel(RECORD_CHANNEL_CAPACITY).0,
+        }
+    }
+}
+
+impl UsageTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 同时把每个请求写入用量账本
+    pub fn with_ledger(mut self, ledger: UsageLedger) -> Self {
+        self.ledger = Some(ledger);
+        self
+    }
+
+    pub fn ledger(&self) -> Option<&UsageLedger> {
+        self.ledger.as_ref()
+    }
+
+    /// 同时按端点、模型、客户端累计 Prometheus 指标
+    pub fn with_metrics(mut self, metrics: Arc<RequestMetrics>) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    pub fn metrics(&self) -> Option<&Arc<RequestMetrics>> {
+        self.metrics.as_ref()
+    }
+
+    /// 订阅请求记录（订阅方处理过慢时丢失最旧的记录）
+    pub fn subscribe(&self) -> broadcast::Receiver<UsageRecord> {
+        self.records.subscribe()
+    }
+
+    /// 开始统计一个请求，`input_tokens` 为估算值，可在之后用 `set_tokens` 更正
+    pub fn start(self: &Arc<Self>, client: impl Into<String>, input_tokens: i32) -> UsageRecorder {
+        UsageRecorder {
+            tracker: self.clone(),
+            client: client.into(),
+            credential_id: None,
+            model: None,
+            endpoint: None,
+            started_at: Instant::now(),
+            input_tokens,
+            output_tokens: 0,
+            upstream_done: false,
+            pending: true,
+        }
+    }
+
+    /// 客户端累计消耗的 tokens（输入 + 输出）
+    pub fn tokens_used(&self, client: &str) -> u64 {
+        self.clients
+            .lock()
+            .get(client)
+            .map(|u| u.input_tokens + u.output_tokens)
+            .unwrap_or(0)
+    }
+
+    /// 按客户端名称排序的用量快照
+    pub fn snapshot(&self) -> Vec<ClientUsage> {
+        let mut usage: Vec<ClientUsage> = self.clients.lock().values().cloned().collect();
+        usage.sort_by(|a, b| a.client.cmp(&b.client));
+        usage
+    }
+
+    fn record(&self, recorder: &UsageRecorder, disconnected: bool) {
+        let outcome = if disconnected {
+            UsageOutcome::Disconnected
+        } else {
+            UsageOutcome::Completed
+        };
+        self.append_ledger(recorder, outcome);
+
+        let mut clients = self.clients.lock();
+        let entry = clients
+            .entry(recorder.client.clone())
+            .or_insert_with(|| ClientUsage {
+                client: recorder.client.clone(),
+                completed: 0,
+                disconnected: 0,
+                upstream_aborted: 0,
+                input_tokens: 0,
+                output_tokens: 0,
+                disconnected_output_tokens: 0,
+                last_seen: Utc::now(),
+            });
+        let output_tokens = recorder.output_tokens.max(0) as u64;
+        entry.input_tokens += recorder.input_tokens.max(0) as u64;
+        entry.output_tokens += output_tokens;
+        entry.last_seen = Utc::now();
+        if disconnected {
+            entry.disconnected += 1;
+            entry.disconnected_output_tokens += output_tokens;
+            if !recorder.upstream_done {
+                entry.upstream_aborted += 1;
+            }
+        } else {
+            entry.completed += 1;
+        }
+    }
+
+    /// 写入用量账本、累计指标并发布记录（上游调用失败时不计 tokens）
+    fn append_ledger(&self, recorder: &UsageRecorder, outcome: UsageOutcome) {
+        if self.ledger.is_none() && self.metrics.is_none() && self.records.receiver_count() == 0 {
+            return;
+        }
+        let (input_tokens, output_tokens) = match outcome {
+            UsageOutcome::Error => (0, 0),
+            _ => (
+                recorder.input_tokens.max(0) as u64,
+                recorder.output_tokens.max(0) as u64,
+            ),
+        };
+        let record = UsageRecord {
+            time: Utc::now(),
+            client: recorder.client.clone(),
+            credential_id: recorder.credential_id,
+            model: recorder.model.clone(),
+            endpoint: recorder.endpoint.map(str::to_string),
+            input_tokens,
+            output_tokens,
+            duration_ms: recorder.started_at.elapsed().as_millis() as u64,
+            outcome,
+        };
+        if let Some(metrics) = &self.metrics {
+            metrics.observe(&record);
+        }
+        let _ = self.records.send(record.clone());
+        if let Some(ledger) = &self.ledger {
+            ledger.append(record);
+        }
+    }
+}
+
+/// 单个请求的用量记录
+///
+/// 调用 `complete` 记为完成，`discard` 不计入（如上游调用失败，账本中记为失败）；
+/// 未调用二者就被 drop（客户端断开导致响应被丢弃）时记为断开
+pub struct UsageRecorder {
+    tracker: Arc<UsageTracker>,
+    client: String,
+    credential_id: Option<u64>,
+    model: Option<String>,
+    endpoint: Option<&'static str>,
+    started_at: Instant,
+    input_tokens: i32,
+    output_tokens: i32,
+    upstream_done: bool,
+    pending: bool,
+}
+
+impl UsageRecorder {
+    /// 设置请求的模型
+    pub fn with_model(mut self, model: impl Into<String>) -> Self {
+        self.model = Some(model.into());
+        self
+    }
+
+    /// 设置请求的端点（路由模板）
+    pub fn with_endpoint(mut self, endpoint: &'static str) -> Self {
+        self.endpoint = Some(endpoint);
+        self
+    }
+
+    /// 设置提供响应的凭据
+    pub fn set_credential(&mut self, credential_id: Option<u64>) {
+        self.credential_id = credential_id;
+    }
+
+    /// 更新目前为止的 tokens
+    pub fn set_tokens(&mut self, input_tokens: i32, output_tokens: i32) {
+        self.input_tokens = input_tokens;
+        self.output_tokens = output_tokens;
+    }
+
+    /// 上游响应已完整接收
+    pub fn upstream_finished(&mut self) {
+        self.upstream_done = true;
+    }
+
+    /// 记为完成
+    pub fn complete(mut self) {
+        self.pending = false;
+        self.tracker.record(&self, false);
+    }
+
+    /// 不计入用量
+    pub fn discard(mut self) {
+        self.pending = false;
+        self.tracker.append_ledger(&self, UsageOutcome::Error);
+    }
+}
+
+impl Drop for UsageRecorder {
+    fn drop(&mut self) {
+        if self.pending {
+            tracing::info!(
+                "客户端 {} 中途断开，已生成 {} 个输出 tokens{}",
+                self.client,
+                self.output_tokens,
+                if self.upstream_done {
+                    ""
+                } else {
+                    "，已中止上游"
+                }
+            );
+            self.tracker.record(self, true);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_records_completed_and_disconnected() {
+        let tracker = Arc::new(UsageTracker::new());
+
+        let mut done = tracker.start("agent", 100);
+        done.set_tokens(120, 40);
+        done.upstream_finished();
+        done.complete();
+
+        let mut dropped = tracker.start("agent", 50);
+        dropped.set_tokens(50, 15);
+        drop(dropped);
+
+        tracker.start("agent", 10).discard();
+
+        let usage = tracker.snapshot();
+        assert_eq!(usage.len(), 1);
+        let agent = &usage[0];
+        assert_eq!(agent.completed, 1);
+        assert_eq!(agent.disconnected, 1);
+        assert_eq!(agent.upstream_aborted, 1);
+        assert_eq!(agent.input_tokens, 170);
+        assert_eq!(agent.output_tokens, 55);
+        assert_eq!(agent.disconnected_output_tokens, 15);
+    }
+}
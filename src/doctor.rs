@@ -0,0 +1,505 @@
+//! `kiro doctor` 启动自检
+//!
+//! 依次检查配置、凭据、machineId、区域端点 DNS、代理连通性、TLS 握手和每个凭据的 Token 刷新，
+//! 输出带颜色的报告；存在失败项时以非零状态码退出，便于脚本使用。
+
+use std::collections::BTreeSet;
+use std::io::IsTerminal;
+use std::time::Duration;
+
+use tokio::net::{TcpStream, lookup_host};
+use tokio::time::timeout;
+
+use crate::http_client::{ProxyConfig, client_builder};
+use crate::kiro::machine_id;
+use crate::kiro::model::credentials::{CredentialsConfig, KiroCredentials};
+use crate::kiro::provider::{is_valid_endpoint, is_valid_region};
+use crate::kiro::token_manager::MultiTokenManager;
+use crate::kiro::vpc_endpoint;
+use crate::model::config::Config;
+use crate::model::env;
+
+/// 网络检查超时
+const NETWORK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// 检查结果
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Status {
+    Pass,
+    Warn,
+    Fail,
+}
+
+/// 自检报告
+struct Report {
+    color: bool,
+    failures: usize,
+    warnings: usize,
+    /// 已输出的检查项：(结果, 名称, 说明)
+    items: Vec<(Status, String, String)>,
+}
+
+impl Report {
+    fn new() -> Self {
+        Self {
+            color: std::io::stdout().is_terminal() && std::env::var_os("NO_COLOR").is_none(),
+            failures: 0,
+            warnings: 0,
+            items: Vec::new(),
+        }
+    }
+
+    fn section(&self, title: &str) {
+        println!();
+        if self.color {
+            println!("\x1b[1m{}\x1b[0m", title);
+        } else {
+            println!("{}", title);
+        }
+    }
+
+    fn item(&mut self, status: Status, name: &str, detail: impl AsRef<str>) {
+        let (label, code) = match status {
+            Status::Pass => (" OK ", "32"),
+            Status::Warn => ("WARN", "33"),
+            Status::Fail => ("FAIL", "31"),
+        };
+        match status {
+            Status::Pass => {}
+            Status::Warn => self.warnings += 1,
+            Status::Fail => self.failures += 1,
+        }
+        if self.color {
+            println!(
+                "  \x1b[{}m[{}]\x1b[0m {}: {}",
+                code,
+                label,
+                name,
+                detail.as_ref()
+            );
+        } else {
+            println!("  [{}] {}: {}", label, name, detail.as_ref());
+        }
+        self.items
+            .push((status, name.to_string(), detail.as_ref().to_string()));
+    }
+
+    fn pass(&mut self, name: &str, detail: impl AsRef<str>) {
+        self.item(Status::Pass, name, detail);
+    }
+
+    fn warn(&mut self, name: &str, detail: impl AsRef<str>) {
+        self.item(Status::Warn, name, detail);
+    }
+
+    fn fail(&mut self, name: &str, detail: impl AsRef<str>) {
+        self.item(Status::Fail, name, detail);
+    }
+
+    /// 打印汇总并返回退出码
+    fn finish(&self) -> i32 {
+        println!();
+        let summary = format!("{} 项失败，{} 项警告", self.failures, self.warnings);
+        let code = if self.failures > 0 { "31" } else { "32" };
+        if self.color {
+            println!("\x1b[1;{}m{}\x1b[0m", code, summary);
+        } else {
+            println!("{}", summary);
+        }
+        if self.failures > 0 { 1 } else { 0 }
+    }
+}
+
+/// 执行自检，返回进程退出码
+pub async fn run(config_path: &str, credentials_path: &str) -> i32 {
+    let mut report = Report::new();
+
+    report.section("配置");
+    let config = match Config::load_with_env(config_path) {
+        Ok(config) => {
+            let source = match env::var(env::CONFIG) {
+                Some(_) => env::CONFIG,
+                None => config_path,
+            };
+            report.pass("配置", source);
+            config
+        }
+        Err(e) => {
+            report.fail("配置", format!("{}: {}", config_path, e));
+            return report.finish();
+        }
+    };
+    check_config(&mut report, &config);
+
+    if let Some(state_dir) = &config.state_dir {
+        check_state_dir(&mut report, state_dir);
+    }
+
+    let resolved = match CredentialsConfig::resolve(
+        credentials_path,
+        config.state_dir.as_deref(),
+        &config.credential_sources,
+    ) {
+        Ok(r) => r,
+        Err(e) => {
+            report.fail("凭据", format!("{}: {}", credentials_path, e));
+            return report.finish();
+        }
+    };
+    let source = resolved.source;
+    let persist_path = resolved.persist_path;
+    let is_multiple_format = resolved.is_multiple_format;
+    let credentials = resolved.config.into_sorted_credentials();
+    if credentials.is_empty() {
+        report.fail("凭据", format!("{}: 未配置任何凭据", source));
+    } else {
+        report.pass("凭据", format!("{}: {} 个凭据", source, credentials.len()));
+    }
+
+    report.section("machineId");
+    check_machine_ids(&mut report, &credentials, &config);
+
+    let proxy = ProxyConfig::from_config(&config);
+    let regions: BTreeSet<String> = std::iter::once(config.region.clone())
+        .chain(credentials.iter().filter_map(|c| c.api_region.clone()))
+        .filter(|r| is_valid_region(r))
+        .collect();
+
+    report.section("网络");
+    for region in &regions {
+        // 配置了 VPC Endpoint 时检查端点名称（两种方式都解析到端点地址）
+        let host = vpc_endpoint::dns_name_for(&config.vpc_endpoint, region)
+            .unwrap_or_else(|| format!("q.{}.amazonaws.com", region));
+        check_dns(&mut report, &host).await;
+    }
+    check_proxy(&mut report, proxy.as_ref()).await;
+    for region in &regions {
+        check_tls(&mut report, &config, proxy.as_ref(), region).await;
+    }
+
+    report.section("Token 刷新");
+    if !credentials.is_empty() {
+        check_token_refresh(
+            &mut report,
+            config,
+            credentials,
+            proxy,
+            persist_path,
+            is_multiple_format,
+        )
+        .await;
+    }
+
+    report.finish()
+}
+
+fn check_config(report: &mut Report, config: &Config) {
+    match config.api_key.as_deref().map(str::trim) {
+        Some(key) if !key.is_empty() => report.pass("apiKey", "已配置"),
+        _ => report.fail("apiKey", "未配置，服务无法启动"),
+    }
+
+    if is_valid_region(&config.region) {
+        report.pass("region", &config.region);
+    } else {
+        report.fail("region", format!("无效的区域: {}", config.region));
+    }
+
+    match config.admin_api_key.as_deref().map(str::trim) {
+        Some(key) if !key.is_empty() => report.pass("adminApiKey", "已配置，Admin API 启用"),
+        Some(_) => report.warn("adminApiKey", "配置为空字符串，Admin API 不会启用"),
+        None => report.pass("adminApiKey", "未配置，Admin API 不启用"),
+    }
+}
+
+fn check_machine_ids(report: &mut Report, credentials: &[KiroCredentials], config: &Config) {
+    for (i, cred) in credentials.iter().enumerate() {
+        let name = credential_name(i, cred);
+        match machine_id::generate_from_credentials(cred, config) {
+            Some(id) => report.pass(&name, format!("{}…", &id[..12])),
+            None => report.fail(&name, "无法生成（缺少 refreshToken 且未配置 machineId）"),
+        }
+        if let Some(region) = &cred.api_region
+            && !is_valid_region(region)
+        {
+            report.fail(&name, format!("无效的 apiRegion: {}", region));
+        }
+        if let Some(endpoint) = &cred.api_endpoint
+            && !is_valid_endpoint(endpoint)
+        {
+            report.fail(&name, format!("无效的 apiEndpoint: {}", endpoint));
+        }
+    }
+}
+
+fn check_state_dir(report: &mut Report, state_dir: &str) {
+    let probe = std::path::Path::new(state_dir).join(".doctor-write-test");
+    let result = std::fs::create_dir_all(state_dir)
+        .and_then(|_| std::fs::write(&probe, b"ok"))
+        .and_then(|_| std::fs::remove_file(&probe));
+    match result {
+        Ok(()) => report.pass("stateDir", format!("{} 可写", state_dir)),
+        Err(e) => report.fail("stateDir", format!("{} 不可写: {}", state_dir, e)),
+    }
+}
+
+async fn check_dns(report: &mut Report, host: &str) {
+    let name = format!("DNS {}", host);
+    match timeout(NETWORK_TIMEOUT, lookup_host((host, 443))).await {
+        Ok(Ok(addrs)) => {
+            let addrs: Vec<String> = addrs.map(|a| a.ip().to_string()).collect();
+            if addrs.is_empty() {
+                report.fail(&name, "未解析到任何地址");
+            } else {
+                report.pass(&name, addrs.join(", "));
+            }
+        }
+        Ok(Err(e)) => report.fail(&name, e.to_string()),
+        Err(_) => report.fail(&name, "解析超时"),
+    }
+}
+
+async fn check_proxy(report: &mut Report, proxy: Option<&ProxyConfig>) {
+    let Some(proxy) = proxy else {
+        report.pass("代理", "未配置，直连");
+        return;
+    };
+
+    let url = match reqwest::Url::parse(&proxy.url) {
+        Ok(url) => url,
+        Err(e) => {
+            report.fail("代理", format!("地址无效 {}: {}", proxy.url, e));
+            return;
+        }
+    };
+    let (Some(host), Some(port)) = (url.host_str(), url.port_or_known_default()) else {
+        report.fail("代理", format!("地址缺少主机或端口: {}", proxy.url));
+        return;
+    };
+    match timeout(NETWORK_TIMEOUT, TcpStream::connect((host, port))).await {
+        Ok(Ok(_)) => report.pass("代理", format!("{}:{} 可连接", host, port)),
+        Ok(Err(e)) => report.fail("代理", format!("{}:{} 连接失败: {}", host, port, e)),
+        Err(_) => report.fail("代理", format!("{}:{} 连接超时", host, port)),
+    }
+}
+
+async fn check_tls(
+    report: &mut Report,
+    config: &Config,
+    proxy: Option<&ProxyConfig>,
+    region: &str,
+) {
+    let host = vpc_endpoint::connect_host_for(&config.vpc_endpoint, region)
+        .unwrap_or_else(|| format!("q.{}.amazonaws.com", region));
+    let url = format!("https://{}/", host);
+    let name = format!("TLS {}", region);
+    let client = match client_builder(proxy, NETWORK_TIMEOUT.as_secs(), config.tls_backend)
+        .and_then(|builder| Ok(vpc_endpoint::apply(builder, config).build()?))
+    {
+        Ok(c) => c,
+        Err(e) => {
+            report.fail(&name, format!("创建 HTTP 客户端失败: {}", e));
+            return;
+        }
+    };
+    // 任意 HTTP 响应（包括 403/404）都说明 TLS 握手成功
+    match client.get(&url).send().await {
+        Ok(resp) => report.pass(
+            &name,
+            format!(
+                "握手成功（{:?}，HTTP {}）",
+                config.tls_backend,
+                resp.status()
+            ),
+        ),
+        Err(e) => report.fail(&name, format!("{}: {}", url, e)),
+    }
+}
+
+async fn check_token_refresh(
+    report: &mut Report,
+    config: Config,
+    credentials: Vec<KiroCredentials>,
+    proxy: Option<ProxyConfig>,
+    persist_path: Option<std::path::PathBuf>,
+    is_multiple_format: bool,
+) {
+    // 通过 MultiTokenManager 刷新，刷新后的 Token 会按服务运行时的规则回写
+    let manager = match MultiTokenManager::new(
+        config,
+        credentials,
+        proxy,
+        persist_path,
+        is_multiple_format,
+    ) {
+        Ok(m) => m,
+        Err(e) => {
+            report.fail("凭据", e.to_string());
+            return;
+        }
+    };
+
+    for entry in manager.snapshot().entries {
+        let name = format!("凭据 #{}", entry.id);
+        if entry.disabled {
+            report.warn(&name, "已禁用，跳过");
+            continue;
+        }
+        match manager.force_refresh(entry.id).await {
+            Ok(creds) => report.pass(
+                &name,
+                format!(
+                    "刷新成功，过期时间 {}",
+                    creds.expires_at.as_deref().unwrap_or("未知")
+                ),
+            ),
+            Err(e) => report.fail(&name, e.to_string()),
+        }
+    }
+}
+
+fn credential_name(index: usize, cred: &KiroCredentials) -> String {
+    match cred.id {
+        Some(id) => format!("凭据 #{}", id),
+        None => format!("凭据 [{}]", index),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("kiro-doctor-{}-{}", uuid::Uuid::new_v4(), name))
+    }
+
+    /// 某一检查项的结果（同名多项时取第一项）
+    fn status_of(report: &Report, name: &str) -> Option<Status> {
+        report
+            .items
+            .iter()
+            .find(|(_, item, _)| item == name)
+            .map(|(status, _, _)| *status)
+    }
+
+    #[test]
+    fn test_check_config() {
+        let mut report = Report::new();
+        check_config(&mut report, &Config::default());
+        assert_eq!(status_of(&report, "apiKey"), Some(Status::Fail));
+        assert_eq!(status_of(&report, "region"), Some(Status::Pass));
+        assert_eq!(status_of(&report, "adminApiKey"), Some(Status::Pass));
+        assert_eq!(report.failures, 1);
+
+        let config = Config {
+            api_key: Some("sk-main".to_string()),
+            region: "us-east-1; rm -rf /".to_string(),
+            admin_api_key: Some(" ".to_string()),
+            ..Config::default()
+        };
+        let mut report = Report::new();
+        check_config(&mut report, &config);
+        assert_eq!(status_of(&report, "apiKey"), Some(Status::Pass));
+        assert_eq!(status_of(&report, "region"), Some(Status::Fail));
+        assert_eq!(status_of(&report, "adminApiKey"), Some(Status::Warn));
+        assert_eq!((report.failures, report.warnings), (1, 1));
+        assert_eq!(report.finish(), 1);
+
+        let config = Config {
+            api_key: Some("sk-main".to_string()),
+            admin_api_key: Some("sk-admin".to_string()),
+            ..Config::default()
+        };
+        let mut report = Report::new();
+        check_config(&mut report, &config);
+        assert_eq!((report.failures, report.warnings), (0, 0));
+        assert_eq!(report.finish(), 0);
+    }
+
+    #[test]
+    fn test_check_credentials() {
+        let credentials = vec![
+            KiroCredentials {
+                id: Some(1),
+                refresh_token: Some("refresh".to_string()),
+                ..Default::default()
+            },
+            // 缺少 refreshToken 且未配置 machineId
+            KiroCredentials {
+                id: Some(2),
+                ..Default::default()
+            },
+            KiroCredentials {
+                id: Some(3),
+                refresh_token: Some("refresh".to_string()),
+                api_region: Some("not a region".to_string()),
+                api_endpoint: Some("not an endpoint".to_string()),
+                ..Default::default()
+            },
+        ];
+        let mut report = Report::new();
+        check_machine_ids(&mut report, &credentials, &Config::default());
+        let results: Vec<(Status, &str)> = report
+            .items
+            .iter()
+            .map(|(status, name, _)| (*status, name.as_str()))
+            .collect();
+        assert_eq!(
+            results,
+            vec![
+                (Status::Pass, "凭据 #1"),
+                (Status::Fail, "凭据 #2"),
+                (Status::Pass, "凭据 #3"),
+                (Status::Fail, "凭据 #3"),
+                (Status::Fail, "凭据 #3"),
+            ]
+        );
+
+        // 全局 machineId 可以代替 refreshToken
+        let config = Config {
+            machine_id: Some("a".repeat(64)),
+            ..Config::default()
+        };
+        let mut report = Report::new();
+        check_machine_ids(&mut report, &credentials[1..2], &config);
+        assert_eq!(report.failures, 0);
+    }
+
+    #[test]
+    fn test_check_state_dir() {
+        let dir = temp_path("state");
+        let mut report = Report::new();
+        check_state_dir(&mut report, dir.to_str().unwrap());
+        assert_eq!(status_of(&report, "stateDir"), Some(Status::Pass));
+        // 探测文件已清理
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 0);
+
+        // 路径被普通文件占用
+        let file = temp_path("file");
+        std::fs::write(&file, b"x").unwrap();
+        let mut report = Report::new();
+        check_state_dir(&mut report, file.join("state").to_str().unwrap());
+        assert_eq!(status_of(&report, "stateDir"), Some(Status::Fail));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+        std::fs::remove_file(&file).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_run_stops_on_invalid_config_or_credentials() {
+        let missing = temp_path("missing.json");
+        assert_eq!(run(missing.to_str().unwrap(), "unused.json").await, 1);
+
+        let config = temp_path("config.json");
+        std::fs::write(&config, r#"{"apiKey": "sk-main"}"#).unwrap();
+        let credentials = temp_path("credentials.json");
+        std::fs::write(&credentials, "not json").unwrap();
+        assert_eq!(
+            run(config.to_str().unwrap(), credentials.to_str().unwrap()).await,
+            1
+        );
+
+        std::fs::remove_file(&config).unwrap();
+        std::fs::remove_file(&credentials).unwrap();
+    }
+}
@@ -2,10 +2,10 @@
 //!
 //! 提供统一的 HTTP Client 构建功能，支持代理配置
 
-use reqwest::{Client, Proxy};
+use reqwest::{Client, ClientBuilder, Proxy};
 use std::time::Duration;
 
-use crate::model::config::TlsBackend;
+use crate::model::config::{Config, TlsBackend};
 
 /// 代理配置
 #[derive(Debug, Clone, Default)]
@@ -28,6 +28,17 @@ impl ProxyConfig {
         }
     }
 
+    /// 从应用配置构建（未配置 proxyUrl 时返回 None）
+    pub fn from_config(config: &Config) -> Option<Self> {
+        config.proxy_url.as_ref().map(|url| {
+            let proxy = Self::new(url);
+            match (&config.proxy_username, &config.proxy_password) {
+                (Some(username), Some(password)) => proxy.with_auth(username, password),
+                _ => proxy,
+            }
+        })
+    }
+
     /// 设置认证信息
     pub fn with_auth(mut self, username: impl Into<String>, password: impl Into<String>) -> Self {
         self.username = Some(username.into());
@@ -49,6 +60,15 @@ pub fn build_client(
     timeout_secs: u64,
     tls_backend: TlsBackend,
 ) -> anyhow::Result<Client> {
+    Ok(client_builder(proxy, timeout_secs, tls_backend)?.build()?)
+}
+
+/// 构建预配置（超时、TLS、代理）的 ClientBuilder，供需要额外定制的调用方使用
+pub fn client_builder(
+    proxy: Option<&ProxyConfig>,
+    timeout_secs: u64,
+    tls_backend: TlsBackend,
+) -> anyhow::Result<ClientBuilder> {
     let mut builder = Client::builder().timeout(Duration::from_secs(timeout_secs));
 
     if tls_backend == TlsBackend::Rustls {
@@ -67,7 +87,7 @@ pub fn build_client(
         tracing::debug!("HTTP Client 使用代理: {}", proxy_config.url);
     }
 
-    Ok(builder.build()?)
+    Ok(builder)
 }
 
 #[cfg(test)]
@@ -0,0 +1,153 @@
+//! 上游调用的分阶段耗时
+//!
+//! 每次尝试记录获取调用上下文（含按需刷新 Token）、构建请求头、节流等待、首字节（收到响应头）
+//! 与总耗时，以 debug 级别逐次输出；整个调用结束时以 info 级别输出一行汇总。
+//! reqwest 不单独暴露建连耗时，DNS/TCP/TLS 建连计入首字节（复用连接时不含建连）。
+//! 失败后的退避等待不计入该次尝试的总耗时。
+
+use std::time::{Duration, Instant};
+
+use reqwest::StatusCode;
+
+use crate::kiro::interceptor::CallKind;
+use crate::kiro::provider::ServedCredential;
+
+/// 单次尝试的计时，drop 时输出 debug 日志
+pub struct AttemptTimer {
+    kind: CallKind,
+    /// 从 0 开始的尝试序号
+    attempt: usize,
+    max_attempts: usize,
+    started: Instant,
+    /// 当前阶段的开始时间
+    mark: Instant,
+    finished: Option<Instant>,
+    credential_id: Option<u64>,
+    status: Option<StatusCode>,
+    acquire: Duration,
+    build_headers: Duration,
+    pace: Duration,
+    ttfb: Option<Duration>,
+}
+
+impl AttemptTimer {
+    pub fn start(kind: CallKind, attempt: usize, max_attempts: usize) -> Self {
+        let now = Instant::now();
+        Self {
+            kind,
+            attempt,
+            max_attempts,
+            started: now,
+            mark: now,
+            finished: None,
+            credential_id: None,
+            status: None,
+            acquire: Duration::ZERO,
+            build_headers: Duration::ZERO,
+            pace: Duration::ZERO,
+            ttfb: None,
+        }
+    }
+
+    /// 结束当前阶段，返回其耗时
+    fn lap(&mut self) -> Duration {
+        let now = Instant::now();
+        let elapsed = now - self.mark;
+        self.mark = now;
+        elapsed
+    }
+
+    /// 获取调用上下文结束（失败时 `credential_id` 为 None）
+    pub fn acquired(&mut self, credential_id: Option<u64>) {
+        self.acquire = self.lap();
+        self.credential_id = credential_id;
+    }
+
+    pub fn headers_built(&mut self) {
+        self.build_headers = self.lap();
+    }
+
+    pub fn paced(&mut self) {
+        self.pace = self.lap();
+    }
+
+    /// 收到响应头
+    pub fn responded(&mut self, status: StatusCode) {
+        self.ttfb = Some(self.lap());
+        self.status = Some(status);
+    }
+
+    /// 结束计时（之后的退避等待不计入总耗时），可重复调用
+    pub fn finish(&mut self) {
+        self.finished.get_or_insert_with(Instant::now);
+    }
+
+    fn total(&self) -> Duration {
+        self.finished.unwrap_or_else(Instant::now) - self.started
+    }
+}
+
+impl Drop for AttemptTimer {
+    fn drop(&mut self) {
+        tracing::debug!(
+            kind = ?self.kind,
+            attempt = self.attempt + 1,
+            max_attempts = self.max_attempts,
+            credential_id = ?self.credential_id,
+            status = ?self.status.map(|s| s.as_u16()),
+            acquire_ms = self.acquire.as_millis() as u64,
+            build_headers_ms = self.build_headers.as_millis() as u64,
+            pace_ms = self.pace.as_millis() as u64,
+            ttfb_ms = ?self.ttfb.map(|d| d.as_millis() as u64),
+            total_ms = self.total().as_millis() as u64,
+            "上游调用尝试"
+        );
+    }
+}
+
+/// 整个调用结束时输出一行汇总
+pub fn log_call(
+    kind: CallKind,
+    attempts: usize,
+    elapsed: Duration,
+    result: &anyhow::Result<reqwest::Response>,
+) {
+    let total_ms = elapsed.as_millis() as u64;
+    match result {
+        Ok(response) => tracing::info!(
+            kind = ?kind,
+            attempts,
+            credential_id = ?ServedCredential::of(response),
+            status = response.status().as_u16(),
+            total_ms,
+            "上游调用完成"
+        ),
+        Err(_) => tracing::info!(kind = ?kind, attempts, total_ms, "上游调用失败"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_phases_fit_within_total() {
+        let mut timer = AttemptTimer::start(CallKind::Api, 0, 3);
+        timer.acquired(Some(1));
+        std::thread::sleep(Duration::from_millis(2));
+        timer.headers_built();
+        timer.paced();
+        timer.responded(StatusCode::OK);
+        timer.finish();
+        let total = timer.total();
+        // 退避等待不计入总耗时
+        std::thread::sleep(Duration::from_millis(2));
+        timer.finish();
+
+        assert_eq!(timer.total(), total);
+        assert_eq!(timer.credential_id, Some(1));
+        assert!(timer.build_headers >= Duration::from_millis(2));
+        let ttfb = timer.ttfb.unwrap();
+        assert!(timer.acquire + timer.build_headers + timer.pace + ttfb <= total);
+    }
+}
@@ -0,0 +1,185 @@
+//! AWS 错误码解析
+//!
+//! 上游 401/403 响应可能对应完全不同的情况：订阅过期的 `AccessDeniedException` 需要禁用凭据，
+//! 而被误报为 403 的 `ThrottlingException` 只需重试。这里从响应头 `x-amzn-ErrorType`
+//! 或 JSON 响应体的 `__type`/`code` 字段解析错误码，再按 `awsErrorRules` 决定处理方式。
+//! 429/5xx 响应同样按错误码区分限流类型（`ThrottleKind`），决定退避时长与是否切换凭据。
+
+use reqwest::header::HeaderMap;
+
+use crate::model::config::{AwsErrorAction, AwsErrorRule};
+
+/// AWS 错误类型响应头
+const ERROR_TYPE_HEADER: &str = "x-amzn-errortype";
+
+/// 429/5xx 响应中的限流/不可用类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ThrottleKind {
+    /// `ThrottlingException`：请求速率过高，常规退避后重试
+    Throttling,
+    /// `ServiceQuotaExceededException`：该凭据的配额超限，切换凭据
+    QuotaExceeded,
+    /// `ModelNotReadyException`：模型尚未就绪，退避更久
+    ModelNotReady,
+    /// 未识别的错误码，按常规瞬态错误处理
+    Other,
+}
+
+impl ThrottleKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Throttling => "ThrottlingException",
+            Self::QuotaExceeded => "ServiceQuotaExceededException",
+            Self::ModelNotReady => "ModelNotReadyException",
+            Self::Other => "瞬态错误",
+        }
+    }
+}
+
+/// 解析出的 AWS 错误
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AwsError {
+    /// 去掉命名空间前缀后的错误码
+    pub code: Option<String>,
+    pub message: Option<String>,
+}
+
+impl AwsError {
+    /// 从响应头和响应体解析（响应体优先）
+    pub fn parse(error_type_header: Option<&str>, body: &str) -> Self {
+        let json = serde_json::from_str::<serde_json::Value>(body).ok();
+        let field = |names: &[&str]| {
+            json.as_ref().and_then(|v| {
+                names
+                    .iter()
+                    .find_map(|name| v.get(*name).and_then(|f| f.as_str()))
+                    .map(|s| s.to_string())
+            })
+        };
+
+        let code = field(&["__type", "code", "Code"])
+            .or_else(|| error_type_header.map(|s| s.to_string()))
+            .map(|c| normalize_code(&c).to_string())
+            .filter(|c| !c.is_empty());
+
+        Self {
+            code,
+            message: field(&["message", "Message"]),
+        }
+    }
+
+    /// 取出响应头中的错误码（需在读取响应体之前调用）
+    pub fn header_value(headers: &HeaderMap) -> Option<String> {
+        headers
+            .get(ERROR_TYPE_HEADER)
+            .and_then(|v| v.to_str().ok())
+            .map(|s| s.to_string())
+    }
+
+    /// 429/5xx 响应的限流类型
+    pub fn throttle_kind(&self) -> ThrottleKind {
+        match self.code.as_deref() {
+            Some("ThrottlingException" | "TooManyRequestsException") => ThrottleKind::Throttling,
+            Some("ServiceQuotaExceededException") => ThrottleKind::QuotaExceeded,
+            Some("ModelNotReadyException") => ThrottleKind::ModelNotReady,
+            _ => ThrottleKind::Other,
+        }
+    }
+
+    /// 按规则决定处理方式，未命中时为 `Failover`
+    pub fn action(&self, rules: &[AwsErrorRule]) -> AwsErrorAction {
+        let Some(code) = &self.code else {
+            return AwsErrorAction::default();
+        };
+        let message = self.message.as_deref().unwrap_or("").to_ascii_lowercase();
+
+        rules
+            .iter()
+            .find(|rule| {
+                normalize_code(&rule.code).eq_ignore_ascii_case(code)
+                    && rule
+                        .message_contains
+                        .as_ref()
+                        .is_none_or(|needle| message.contains(&needle.to_ascii_lowercase()))
+            })
+            .map(|rule| rule.action)
+            .unwrap_or_default()
+    }
+}
+
+/// 去掉命名空间前缀和附加信息：
+/// `com.amazon.coral.service#AccessDeniedException:http://...` -> `AccessDeniedException`
+fn normalize_code(code: &str) -> &str {
+    let code = code.rsplit('#').next().unwrap_or(code);
+    code.split(':').next().unwrap_or(code).trim()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::config::Config;
+
+    #[test]
+    fn test_parse_code_sources() {
+        let err = AwsError::parse(
+            None,
+            r#"{"__type":"com.amazon.aws.codewhisperer#AccessDeniedException","message":"Subscription expired"}"#,
+        );
+        assert_eq!(err.code.as_deref(), Some("AccessDeniedException"));
+        assert_eq!(err.message.as_deref(), Some("Subscription expired"));
+
+        let err = AwsError::parse(Some("ThrottlingException:http://internal.amazon.com/"), "");
+        assert_eq!(err.code.as_deref(), Some("ThrottlingException"));
+
+        assert_eq!(AwsError::parse(None, "forbidden"), AwsError::default());
+    }
+
+    #[test]
+    fn test_default_rules() {
+        let rules = Config::default().aws_error_rules;
+        let action = |body: &str| AwsError::parse(None, body).action(&rules);
+
+        assert_eq!(
+            action(
+                r#"{"__type":"AccessDeniedException","message":"Your subscription has expired"}"#
+            ),
+            AwsErrorAction::Disable
+        );
+        assert_eq!(
+            action(r#"{"__type":"AccessDeniedException","message":"not authorized"}"#),
+            AwsErrorAction::Failover
+        );
+        assert_eq!(
+            action(r#"{"__type":"ThrottlingException","message":"Rate exceeded"}"#),
+            AwsErrorAction::Retry
+        );
+        assert_eq!(action("{}"), AwsErrorAction::Failover);
+    }
+
+    #[test]
+    fn test_throttle_kind() {
+        let kind = |header: Option<&str>, body: &str| AwsError::parse(header, body).throttle_kind();
+        assert_eq!(
+            kind(
+                None,
+                r#"{"__type":"ThrottlingException","message":"Rate exceeded"}"#
+            ),
+            ThrottleKind::Throttling
+        );
+        assert_eq!(
+            kind(
+                None,
+                r#"{"__type":"com.amazon.aws.codewhisperer#ServiceQuotaExceededException"}"#
+            ),
+            ThrottleKind::QuotaExceeded
+        );
+        assert_eq!(
+            kind(
+                Some("ModelNotReadyException:http://internal.amazon.com/"),
+                ""
+            ),
+            ThrottleKind::ModelNotReady
+        );
+        assert_eq!(kind(None, "Service Unavailable"), ThrottleKind::Other);
+    }
+}
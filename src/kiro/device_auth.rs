@@ -0,0 +1,489 @@
+//! 设备码授权（AWS SSO OIDC）
+//!
+//! refreshToken 失效后无需编辑文件：注册一个公共 OIDC 客户端并发起设备授权，操作员在浏览器中
+//! 打开验证链接、输入用户码完成登录，随后按服务端给出的间隔轮询换取新的 Token。
+//! 得到的凭据按 IdC 方式刷新（`authMethod: idc`，附带新注册的 clientId/clientSecret）。
+//! 授权完成后用新的 accessToken 查询账号可用的 profile（`ListAvailableProfiles`），取得 profileArn。
+
+use std::time::Duration;
+
+use anyhow::bail;
+use chrono::Utc;
+
+use crate::http_client::{ProxyConfig, build_client};
+use crate::kiro::model::credentials::KiroCredentials;
+use crate::kiro::model::device_auth::{
+    DeviceTokenRequest, DeviceTokenResponse, ListAvailableProfilesRequest,
+    ListAvailableProfilesResponse, OidcErrorResponse, RegisterClientRequest,
+    RegisterClientResponse, StartDeviceAuthorizationRequest, StartDeviceAuthorizationResponse,
+};
+use crate::kiro::token_manager::IDC_AMZ_USER_AGENT;
+use crate::model::config::Config;
+
+/// AWS Builder ID 的登录入口（IAM Identity Center 使用组织自己的 start URL）
+pub const BUILDER_ID_START_URL: &str = "https://view.awsapps.com/start";
+
+/// 注册客户端时申请的权限
+const SCOPES: [&str; 5] = [
+    "codewhisperer:completions",
+    "codewhisperer:analysis",
+    "codewhisperer:conversations",
+    "codewhisperer:transformations",
+    "codewhisperer:taskassist",
+];
+
+/// 服务端未给出轮询间隔时的默认值（秒）
+const DEFAULT_INTERVAL_SECS: u64 = 5;
+
+/// Kiro profile 所在的区域（与 OIDC 区域无关，按顺序查询）
+const PROFILE_REGIONS: [&str; 2] = ["us-east-1", "eu-central-1"];
+
+/// 进行中的设备授权
+#[derive(Debug, Clone)]
+pub struct DeviceAuthorization {
+    pub region: String,
+    pub client_id: String,
+    pub client_secret: String,
+    pub device_code: String,
+    /// 需要在验证页面输入的用户码
+    pub user_code: String,
+    pub verification_uri: String,
+    /// 已带上用户码的验证链接
+    pub verification_uri_complete: Option<String>,
+    /// 设备码有效期（秒）
+    pub expires_in: i64,
+    /// 轮询间隔（秒）
+    pub interval: u64,
+}
+
+/// 设备授权得到的 Token
+#[derive(Debug, Clone)]
+pub struct DeviceToken {
+    pub access_token: String,
+    pub refresh_token: String,
+    pub expires_in: Option<i64>,
+}
+
+/// 单次轮询的结果
+#[derive(Debug, PartialEq)]
+enum PollOutcome {
+    /// 用户尚未完成授权
+    Pending,
+    /// 轮询过快，需要增大间隔
+    SlowDown,
+}
+
+fn oidc_url(region: &str, path: &str) -> String {
+    format!("https://oidc.{}.amazonaws.com/{}", region, path)
+}
+
+async fn post_oidc<T: serde::Serialize>(
+    client: &reqwest::Client,
+    region: &str,
+    path: &str,
+    body: &T,
+) -> reqwest::Result<reqwest::Response> {
+    client
+        .post(oidc_url(region, path))
+        .header("Content-Type", "application/json")
+        .header("x-amz-user-agent", IDC_AMZ_USER_AGENT)
+        .header("User-Agent", "node")
+        .json(body)
+        .send()
+        .await
+}
+
+/// 注册 OIDC 客户端并发起设备授权
+pub async fn start(
+    region: &str,
+    start_url: &str,
+    config: &Config,
+    proxy: Option<&ProxyConfig>,
+) -> anyhow::Result<DeviceAuthorization> {
+    let client = build_client(proxy, 60, config.tls_backend)?;
+
+    let register = RegisterClientRequest {
+        client_name: "kiro-rs".to_string(),
+        client_type: "public".to_string(),
+        scopes: SCOPES.iter().map(|s| s.to_string()).collect(),
+    };
+    let response = post_oidc(&client, region, "client/register", &register).await?;
+    if !response.status().is_success() {
+        let status = response.status();
+        bail!(
+            "注册 OIDC 客户端失败: {} {}",
+            status,
+            response.text().await.unwrap_or_default()
+        );
+    }
+    let registered: RegisterClientResponse = response.json().await?;
+
+    let authorize = StartDeviceAuthorizationRequest {
+        client_id: registered.client_id.clone(),
+        client_secret: registered.client_secret.clone(),
+        start_url: start_url.to_string(),
+    };
+    let response = post_oidc(&client, region, "device_authorization", &authorize).await?;
+    if !response.status().is_success() {
+        let status = response.status();
+        bail!(
+            "发起设备授权失败: {} {}",
+            status,
+            response.text().await.unwrap_or_default()
+        );
+    }
+    let data: StartDeviceAuthorizationResponse = response.json().await?;
+
+    Ok(DeviceAuthorization {
+        region: region.to_string(),
+        client_id: registered.client_id,
+        client_secret: registered.client_secret,
+        device_code: data.device_code,
+        user_code: data.user_code,
+        verification_uri: data.verification_uri,
+        verification_uri_complete: data.verification_uri_complete,
+        expires_in: data.expires_in,
+        interval: data.interval.unwrap_or(DEFAULT_INTERVAL_SECS).max(1),
+    })
+}
+
+/// 轮询直到用户完成授权（或设备码过期、被拒绝）
+pub async fn poll(
+    auth: &DeviceAuthorization,
+    config: &Config,
+    proxy: Option<&ProxyConfig>,
+) -> anyhow::Result<DeviceToken> {
+    let client = build_client(proxy, 60, config.tls_backend)?;
+    let deadline = tokio::time::Instant::now() + Duration::from_secs(auth.expires_in.max(0) as u64);
+    let mut interval = auth.interval;
+    let body = DeviceTokenRequest {
+        client_id: auth.client_id.clone(),
+        client_secret: auth.client_secret.clone(),
+        grant_type: "urn:ietf:params:oauth:grant-type:device_code".to_string(),
+        device_code: auth.device_code.clone(),
+    };
+
+    loop {
+        tokio::time::sleep(Duration::from_secs(interval)).await;
+        if tokio::time::Instant::now() >= deadline {
+            bail!("设备码已过期，请重新发起授权");
+        }
+
+        let response = post_oidc(&client, &auth.region, "token", &body).await?;
+        let status = response.status();
+        if status.is_success() {
+            let data: DeviceTokenResponse = response.json().await?;
+            return Ok(DeviceToken {
+                access_token: data.access_token,
+                refresh_token: data.refresh_token,
+                expires_in: data.expires_in,
+            });
+        }
+
+        let text = response.text().await.unwrap_or_default();
+        match classify_poll_error(status.as_u16(), &text)? {
+            PollOutcome::Pending => {}
+            PollOutcome::SlowDown => interval += DEFAULT_INTERVAL_SECS,
+        }
+    }
+}
+
+/// 查询授权账号可用的 profile，返回第一个 profile 的 ARN
+///
+/// IAM Identity Center 账号的请求需要带上 profileArn；Builder ID 账号通常没有 profile，返回 None
+pub async fn fetch_profile_arn(
+    token: &DeviceToken,
+    config: &Config,
+    proxy: Option<&ProxyConfig>,
+) -> anyhow::Result<Option<String>> {
+    let client = build_client(proxy, 60, config.tls_backend)?;
+    let endpoints = PROFILE_REGIONS.map(|region| {
+        (
+            region,
+            format!("https://codewhisperer.{}.amazonaws.com/", region),
+        )
+    });
+    first_profile_arn(&client, &token.access_token, &endpoints).await
+}
+
+/// 按顺序查询各区域（`(区域, 端点)`）的可用 profile，返回第一个 profile 的 ARN
+///
+/// 某个区域查询失败时记录警告并继续查询下一个区域；所有区域都失败时返回最后一个错误
+async fn first_profile_arn(
+    client: &reqwest::Client,
+    access_token: &str,
+    endpoints: &[(&str, String)],
+) -> anyhow::Result<Option<String>> {
+    let mut last_error = None;
+    let mut queried = false;
+    for (region, url) in endpoints {
+        match list_profiles(client, url, access_token).await {
+            Ok(Some(arn)) => return Ok(Some(arn)),
+            Ok(None) => queried = true,
+            Err(e) => {
+                tracing::warn!("查询 {} 的可用 profile 失败: {}", region, e);
+                last_error = Some(e);
+            }
+        }
+    }
+    match last_error {
+        Some(e) if !queried => Err(e),
+        _ => Ok(None),
+    }
+}
+
+/// 查询一个区域的可用 profile（按 nextToken 翻页），返回第一个 profile 的 ARN
+async fn list_profiles(
+    client: &reqwest::Client,
+    url: &str,
+    access_token: &str,
+) -> anyhow::Result<Option<String>> {
+    let mut request = ListAvailableProfilesRequest::default();
+    loop {
+        let response = client
+            .post(url)
+            .header("Content-Type", "application/x-amz-json-1.0")
+            .header(
+                "x-amz-target",
+                "AmazonCodeWhispererService.ListAvailableProfiles",
+            )
+            .header("x-amz-user-agent", IDC_AMZ_USER_AGENT)
+            .header("User-Agent", "node")
+            .header("Authorization", format!("Bearer {}", access_token))
+            .json(&request)
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            let status = response.status();
+            bail!("{} {}", status, response.text().await.unwrap_or_default());
+        }
+        let data: ListAvailableProfilesResponse = response.json().await?;
+        if let Some(profile) = data.profiles.into_iter().next() {
+            return Ok(Some(profile.arn));
+        }
+        match data.next_token {
+            Some(next) => request.next_token = Some(next),
+            None => return Ok(None),
+        }
+    }
+}
+
+/// 设备授权完成后生成新凭据（`kiro login` 与 Admin 登录添加的凭据）
+///
+/// 未指定 profileArn 时查询账号可用的 profile（IAM Identity Center 账号需要），查询失败时只记录警告，
+/// 凭据不带 profileArn
+pub async fn login_credentials(
+    auth: &DeviceAuthorization,
+    token: DeviceToken,
+    priority: u32,
+    profile_arn: Option<String>,
+    config: &Config,
+    proxy: Option<&ProxyConfig>,
+) -> KiroCredentials {
+    let profile_arn = match profile_arn {
+        Some(arn) => Some(arn),
+        None => fetch_profile_arn(&token, config, proxy)
+            .await
+            .unwrap_or_else(|e| {
+                tracing::warn!("查询 profileArn 失败，新凭据不带 profileArn: {:#}", e);
+                None
+            }),
+    };
+    let mut credentials = auth.new_credentials(token);
+    credentials.priority = priority;
+    credentials.profile_arn = profile_arn;
+    credentials
+}
+
+/// 判断换取 Token 失败的原因：未完成授权时继续轮询，其余错误终止
+fn classify_poll_error(status: u16, body: &str) -> anyhow::Result<PollOutcome> {
+    let error = serde_json::from_str::<OidcErrorResponse>(body).ok();
+    match error.as_ref().map(|e| e.error.as_str()) {
+        Some("authorization_pending") => Ok(PollOutcome::Pending),
+        Some("slow_down") => Ok(PollOutcome::SlowDown),
+        Some("expired_token") => bail!("设备码已过期，请重新发起授权"),
+        Some("access_denied") => bail!("用户拒绝了授权"),
+        _ => {
+            let detail = error
+                .and_then(|e| e.error_description.or(Some(e.error)))
+                .unwrap_or_else(|| body.to_string());
+            bail!("换取 Token 失败: {} {}", status, detail)
+        }
+    }
+}
+
+impl DeviceAuthorization {
+    /// 将授权结果写入凭据：改为 IdC 方式刷新，保留 ID、优先级等元数据
+    pub fn apply(&self, token: DeviceToken, credentials: &mut KiroCredentials) {
+        credentials.access_token = Some(token.access_token);
+        credentials.refresh_token = Some(token.refresh_token);
+        credentials.expires_at = token
+            .expires_in
+            .map(|secs| (Utc::now() + chrono::Duration::seconds(secs)).to_rfc3339());
+        credentials.auth_method = Some("idc".to_string());
+        credentials.client_id = Some(self.client_id.clone());
+        credentials.client_secret = Some(self.client_secret.clone());
+        credentials.region = Some(self.region.clone());
+        credentials.refresh_token_obtained_at = Some(Utc::now().to_rfc3339());
+    }
+
+    /// 用授权结果生成一个新凭据（`kiro login` 与 Admin 登录添加的凭据）
+    pub fn new_credentials(&self, token: DeviceToken) -> KiroCredentials {
+        let mut credentials = KiroCredentials::default();
+        self.apply(token, &mut credentials);
+        credentials
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 本地 ListAvailableProfiles 端点：按请求体返回预设响应，记录收到的请求体
+    async fn profiles_endpoint(
+        respond: fn(&serde_json::Value) -> (u16, String),
+    ) -> (String, std::sync::Arc<parking_lot::Mutex<Vec<serde_json::Value>>>) {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        let requests = std::sync::Arc::new(parking_lot::Mutex::new(Vec::new()));
+        let seen = requests.clone();
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let mut buf = vec![0u8; 8192];
+                let n = socket.read(&mut buf).await.unwrap_or(0);
+                let text = String::from_utf8_lossy(&buf[..n]);
+                let body = text.split("\r\n\r\n").nth(1).unwrap_or_default();
+                let request: serde_json::Value =
+                    serde_json::from_str(body).unwrap_or(serde_json::Value::Null);
+                let (status, body) = respond(&request);
+                seen.lock().push(request);
+                let response = format!(
+                    "HTTP/1.1 {} X\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    status,
+                    body.len(),
+                    body
+                );
+                let _ = socket.write_all(response.as_bytes()).await;
+            }
+        });
+        (url, requests)
+    }
+
+    #[tokio::test]
+    async fn test_first_profile_arn_follows_next_token() {
+        let (url, requests) = profiles_endpoint(|request| match request.get("nextToken") {
+            None => (200, r#"{"profiles":[],"nextToken":"page-2"}"#.to_string()),
+            Some(_) => (200, r#"{"profiles":[{"arn":"arn:aws:profile/p"}]}"#.to_string()),
+        })
+        .await;
+        let client = reqwest::Client::new();
+        let arn = first_profile_arn(&client, "token", &[("us-east-1", url)])
+            .await
+            .unwrap();
+        assert_eq!(arn.as_deref(), Some("arn:aws:profile/p"));
+        assert_eq!(
+            *requests.lock(),
+            vec![
+                serde_json::json!({}),
+                serde_json::json!({"nextToken": "page-2"})
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_first_profile_arn_continues_after_region_failure() {
+        let (failing, _) = profiles_endpoint(|_| (403, "denied".to_string())).await;
+        let (eu, _) = profiles_endpoint(|_| {
+            (200, r#"{"profiles":[{"arn":"arn:aws:profile/eu"}]}"#.to_string())
+        })
+        .await;
+        let (empty, _) = profiles_endpoint(|_| (200, r#"{"profiles":[]}"#.to_string())).await;
+        let client = reqwest::Client::new();
+
+        let arn = first_profile_arn(
+            &client,
+            "token",
+            &[("us-east-1", failing.clone()), ("eu-central-1", eu)],
+        )
+        .await
+        .unwrap();
+        assert_eq!(arn.as_deref(), Some("arn:aws:profile/eu"));
+
+        // 有区域查询成功但没有 profile 时返回 None，全部失败时报错
+        let none = first_profile_arn(
+            &client,
+            "token",
+            &[("us-east-1", failing.clone()), ("eu-central-1", empty)],
+        )
+        .await
+        .unwrap();
+        assert!(none.is_none());
+        assert!(
+            first_profile_arn(&client, "token", &[("us-east-1", failing)])
+                .await
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_classify_poll_error() {
+        let pending = r#"{"error":"authorization_pending"}"#;
+        assert_eq!(
+            classify_poll_error(400, pending).unwrap(),
+            PollOutcome::Pending
+        );
+        let slow = r#"{"error":"slow_down"}"#;
+        assert_eq!(
+            classify_poll_error(400, slow).unwrap(),
+            PollOutcome::SlowDown
+        );
+
+        let expired = classify_poll_error(400, r#"{"error":"expired_token"}"#).unwrap_err();
+        assert!(expired.to_string().contains("过期"));
+        let other = r#"{"error":"invalid_client","error_description":"bad secret"}"#;
+        assert!(
+            classify_poll_error(401, other)
+                .unwrap_err()
+                .to_string()
+                .contains("bad secret")
+        );
+        assert!(classify_poll_error(500, "oops").is_err());
+    }
+
+    #[test]
+    fn test_apply_switches_to_idc() {
+        let auth = DeviceAuthorization {
+            region: "us-east-1".to_string(),
+            client_id: "client".to_string(),
+            client_secret: "secret".to_string(),
+            device_code: "device".to_string(),
+            user_code: "ABCD-EFGH".to_string(),
+            verification_uri: "https://device.sso.us-east-1.amazonaws.com/".to_string(),
+            verification_uri_complete: None,
+            expires_in: 600,
+            interval: 5,
+        };
+        let mut credentials = KiroCredentials {
+            id: Some(3),
+            priority: 2,
+            auth_method: Some("social".to_string()),
+            refresh_token: Some("dead".to_string()),
+            ..Default::default()
+        };
+        let token = DeviceToken {
+            access_token: "access".to_string(),
+            refresh_token: "fresh".to_string(),
+            expires_in: Some(3600),
+        };
+        auth.apply(token, &mut credentials);
+
+        assert_eq!(credentials.id, Some(3));
+        assert_eq!(credentials.priority, 2);
+        assert_eq!(credentials.auth_method.as_deref(), Some("idc"));
+        assert_eq!(credentials.refresh_token.as_deref(), Some("fresh"));
+        assert_eq!(credentials.client_secret.as_deref(), Some("secret"));
+        assert!(credentials.expires_at.is_some());
+    }
+}
@@ -0,0 +1,347 @@
+//! 凭据告警邮件（SMTP）
+//!
+//! 订阅凭据事件，按 `emailAlert.events` 过滤后经 SMTP（lettre）发送告警邮件。首个事件到达后等待
+//! `batchWindowSecs`，窗口内的事件合并为一封邮件，重试风暴中凭据接连被禁用也只会发出一封。
+//! 发送失败只记录日志，不重试；SMTP 连接不经过 `proxyUrl`。
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::Utc;
+use lettre::message::header::{ContentTransferEncoding, ContentType};
+use lettre::message::{Body, Mailbox};
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::transport::smtp::client::{Tls, TlsParameters};
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+use parking_lot::Mutex;
+use tokio::sync::broadcast;
+
+use crate::kiro::events::TokenEvent;
+use crate::kiro::token_manager::DisabledReason;
+use crate::model::config::{Config, EmailAlertConfig, SmtpTls};
+
+/// 单次发送（连接、握手到 QUIT）的超时
+const SMTP_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// 一封邮件最多列出的事件数
+const MAX_LISTED_EVENTS: usize = 200;
+
+/// 当前合并窗口内的事件
+#[derive(Default)]
+struct Batch {
+    lines: Vec<String>,
+    /// 超出 `MAX_LISTED_EVENTS` 未列出的事件数
+    omitted: usize,
+    all_exhausted: bool,
+    /// 已有等待发送的窗口
+    open: bool,
+}
+
+/// 凭据告警邮件
+pub struct EmailAlerts {
+    config: EmailAlertConfig,
+    from: Mailbox,
+    to: Vec<Mailbox>,
+    mailer: AsyncSmtpTransport<Tokio1Executor>,
+    batch: Mutex<Batch>,
+}
+
+impl EmailAlerts {
+    /// 从配置创建（未配置 `emailAlert.smtpHost` 时返回 None）
+    pub fn from_config(config: &Config) -> Option<Arc<Self>> {
+        let email = &config.email_alert;
+        let host = email.smtp_host.clone()?;
+        if email.to.is_empty() {
+            tracing::warn!("emailAlert 未配置收件人（to），告警邮件未启用");
+            return None;
+        }
+        let Some(from) = email.from.as_ref().or(email.username.as_ref()) else {
+            tracing::warn!("emailAlert 未配置发件人（from 或 username），告警邮件未启用");
+            return None;
+        };
+        let parse = |mailbox: &String| {
+            mailbox
+                .parse::<Mailbox>()
+                .map_err(|e| format!("无效的邮箱地址 {}: {}", mailbox, e))
+        };
+        let result = parse(from).and_then(|from| {
+            let to = email.to.iter().map(parse).collect::<Result<Vec<_>, _>>()?;
+            let mailer = Self::mailer(email, &host).map_err(|e| e.to_string())?;
+            Ok((from, to, mailer))
+        });
+        let (from, to, mailer) = match result {
+            Ok(parts) => parts,
+            Err(e) => {
+                tracing::warn!("emailAlert 配置无效，告警邮件未启用: {}", e);
+                return None;
+            }
+        };
+        Some(Arc::new(Self {
+            config: email.clone(),
+            from,
+            to,
+            mailer,
+            batch: Mutex::new(Batch::default()),
+        }))
+    }
+
+    /// 按配置创建 SMTP 传输（每封邮件单独建立连接）
+    fn mailer(
+        config: &EmailAlertConfig,
+        host: &str,
+    ) -> Result<AsyncSmtpTransport<Tokio1Executor>, lettre::transport::smtp::Error> {
+        let tls = match config.tls {
+            SmtpTls::None => Tls::None,
+            SmtpTls::Implicit => Tls::Wrapper(TlsParameters::new(host.to_string())?),
+            SmtpTls::Starttls => Tls::Required(TlsParameters::new(host.to_string())?),
+        };
+        let mut builder = AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(host)
+            .port(config.smtp_port)
+            .tls(tls)
+            .timeout(Some(SMTP_TIMEOUT));
+        if let Some(username) = &config.username {
+            let password = config.password.clone().unwrap_or_default();
+            builder = builder.credentials(Credentials::new(username.clone(), password));
+        }
+        Ok(builder.build())
+    }
+
+    /// 在后台消费凭据事件，每个合并窗口结束时发送一封邮件
+    pub fn spawn(self: &Arc<Self>, mut events: broadcast::Receiver<TokenEvent>) {
+        let alerts = self.clone();
+        let window = Duration::from_secs(self.config.batch_window_secs);
+        tokio::spawn(async move {
+            loop {
+                match events.recv().await {
+                    Ok(event) => {
+                        if alerts.push(&event) {
+                            let alerts = alerts.clone();
+                            tokio::spawn(async move {
+                                tokio::time::sleep(window).await;
+                                alerts.flush().await;
+                            });
+                        }
+                    }
+                    Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                        tracing::warn!("告警邮件落后，丢失 {} 个事件", skipped);
+                    }
+                    Err(broadcast::error::RecvError::Closed) => return,
+                }
+            }
+        });
+    }
+
+    /// 把事件记入当前窗口，返回是否新开了一个窗口
+    fn push(&self, event: &TokenEvent) -> bool {
+        if !self.config.events.iter().any(|kind| kind == event.kind()) {
+            return false;
+        }
+        let mut batch = self.batch.lock();
+        if batch.lines.len() < MAX_LISTED_EVENTS {
+            let time = Utc::now().format("%Y-%m-%d %H:%M:%S UTC");
+            batch.lines.push(format!("{} {}", time, describe(event)));
+        } else {
+            batch.omitted += 1;
+        }
+        batch.all_exhausted |= matches!(event, TokenEvent::AllExhausted { .. });
+        !std::mem::replace(&mut batch.open, true)
+    }
+
+    /// 发送当前窗口内的事件（之后到达的事件开启新窗口）
+    async fn flush(&self) {
+        let batch = std::mem::take(&mut *self.batch.lock());
+        if batch.lines.is_empty() {
+            return;
+        }
+        let count = batch.lines.len() + batch.omitted;
+        let subject = if batch.all_exhausted {
+            "[kiro-rs] 所有凭据均已禁用".to_string()
+        } else {
+            format!("[kiro-rs] {} 个凭据事件", count)
+        };
+        let mut body = batch.lines.join("\n");
+        if batch.omitted > 0 {
+            body.push_str(&format!("\n…… 另有 {} 个事件", batch.omitted));
+        }
+
+        let message = match self.message(&subject, body) {
+            Ok(message) => message,
+            Err(e) => {
+                tracing::error!("生成告警邮件失败: {}", e);
+                return;
+            }
+        };
+        match tokio::time::timeout(SMTP_TIMEOUT, self.mailer.send(message)).await {
+            Ok(Ok(_)) => tracing::info!("已发送告警邮件: {}（{} 个事件）", subject, count),
+            Ok(Err(e)) => tracing::error!("发送告警邮件失败: {}", e),
+            Err(_) => tracing::error!("发送告警邮件超时（{}s）", SMTP_TIMEOUT.as_secs()),
+        }
+    }
+
+    /// 邮件（正文 base64 编码）
+    fn message(&self, subject: &str, body: String) -> Result<Message, lettre::error::Error> {
+        let mut builder = Message::builder()
+            .from(self.from.clone())
+            .subject(subject)
+            .message_id(Some(format!("<{}@kiro-rs>", uuid::Uuid::new_v4())))
+            .header(ContentType::TEXT_PLAIN);
+        for to in &self.to {
+            builder = builder.to(to.clone());
+        }
+        let body = Body::new_with_encoding(body, ContentTransferEncoding::Base64)
+            .expect("base64 可以编码任意正文");
+        builder.body(body)
+    }
+}
+
+/// 事件的一行描述
+fn describe(event: &TokenEvent) -> String {
+    match event {
+        TokenEvent::CredentialDisabled { id, reason } => {
+            let reason = match reason {
+                DisabledReason::Manual => "手动禁用",
+                DisabledReason::TooManyFailures => "连续失败达到阈值",
+                DisabledReason::QuotaExceeded => "额度已用尽",
+                DisabledReason::AccessDenied => "上游拒绝访问",
+                DisabledReason::CanaryFailed => "灰度期错误率过高",
+            };
+            format!("凭据 #{} 被禁用（{}）", id, reason)
+        }
+        TokenEvent::CredentialRecovered { id } => format!("凭据 #{} 已恢复", id),
+        TokenEvent::TokenRefreshed { id, .. } => format!("凭据 #{} 已刷新 Token", id),
+        TokenEvent::QuotaExhausted { id } => format!("凭据 #{} 额度已用尽", id),
+        TokenEvent::AllExhausted { total } => format!("所有 {} 个凭据均已禁用", total),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use base64::Engine;
+    use base64::engine::general_purpose::STANDARD;
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::TcpListener;
+
+    /// 只接受一封邮件的 SMTP 服务器，返回收到的命令与邮件原文
+    async fn mock_server(listener: TcpListener) -> (Vec<String>, String) {
+        let (socket, _) = listener.accept().await.unwrap();
+        let mut stream = BufReader::new(socket);
+        stream.write_all(b"220 mock ESMTP\r\n").await.unwrap();
+        let mut commands = Vec::new();
+        let mut data = String::new();
+        loop {
+            let mut line = String::new();
+            if stream.read_line(&mut line).await.unwrap() == 0 {
+                break;
+            }
+            let line = line.trim_end().to_string();
+            let reply: &[u8] = match line.as_str() {
+                l if l.starts_with("EHLO") => b"250-mock\r\n250 AUTH PLAIN\r\n",
+                "DATA" => {
+                    stream.write_all(b"354 go ahead\r\n").await.unwrap();
+                    loop {
+                        let mut line = String::new();
+                        stream.read_line(&mut line).await.unwrap();
+                        if line == ".\r\n" {
+                            break;
+                        }
+                        data.push_str(line.strip_prefix('.').unwrap_or(&line));
+                    }
+                    b"250 queued\r\n"
+                }
+                "QUIT" => b"221 bye\r\n",
+                l if l.starts_with("AUTH") => b"235 ok\r\n",
+                _ => b"250 ok\r\n",
+            };
+            stream.write_all(reply).await.unwrap();
+            commands.push(line);
+        }
+        (commands, data)
+    }
+
+    fn config(port: u16) -> Config {
+        Config {
+            email_alert: EmailAlertConfig {
+                smtp_host: Some("127.0.0.1".to_string()),
+                smtp_port: port,
+                tls: SmtpTls::None,
+                username: Some("alerts".to_string()),
+                password: Some("secret".to_string()),
+                from: Some("kiro-rs <alerts@example.com>".to_string()),
+                to: vec!["ops@example.com".to_string()],
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_batches_events_into_one_email() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = tokio::spawn(mock_server(listener));
+        let alerts = EmailAlerts::from_config(&config(port)).unwrap();
+
+        // 只有第一个事件开启窗口，未订阅的事件类型被忽略
+        let disabled = |id| TokenEvent::CredentialDisabled {
+            id,
+            reason: DisabledReason::TooManyFailures,
+        };
+        assert!(alerts.push(&disabled(1)));
+        for id in 2..=30 {
+            assert!(!alerts.push(&disabled(id)));
+        }
+        assert!(!alerts.push(&TokenEvent::CredentialRecovered { id: 1 }));
+        assert!(!alerts.push(&TokenEvent::AllExhausted { total: 30 }));
+        alerts.flush().await;
+        // 窗口已清空，不会再发送
+        alerts.flush().await;
+
+        let (commands, data) = server.await.unwrap();
+        assert!(commands[0].starts_with("EHLO"));
+        assert_eq!(
+            commands[1],
+            format!("AUTH PLAIN {}", STANDARD.encode("\0alerts\0secret"))
+        );
+        assert!(commands.contains(&"MAIL FROM:<alerts@example.com>".to_string()));
+        assert!(commands.contains(&"RCPT TO:<ops@example.com>".to_string()));
+        assert_eq!(commands.last().unwrap(), "QUIT");
+
+        let (headers, encoded) = data.split_once("\r\n\r\n").unwrap();
+        assert!(headers.contains("From: kiro-rs <alerts@example.com>"));
+        assert!(headers.contains("To: ops@example.com"));
+        // 主题按 RFC 2047 编码
+        let subject = headers
+            .split("\r\n")
+            .find_map(|line| line.strip_prefix("Subject: "))
+            .unwrap();
+        assert_eq!(
+            subject,
+            format!(
+                "[kiro-rs] =?utf-8?b?{}?=",
+                STANDARD.encode("所有凭据均已禁用")
+            )
+        );
+        let body =
+            String::from_utf8(STANDARD.decode(encoded.replace("\r\n", "")).unwrap()).unwrap();
+        assert_eq!(body.lines().count(), 31);
+        assert!(body.contains("凭据 #30 被禁用（连续失败达到阈值）"));
+        assert!(body.ends_with("所有 30 个凭据均已禁用"));
+    }
+
+    #[test]
+    fn test_from_config_requires_valid_mailboxes() {
+        assert!(EmailAlerts::from_config(&Config::default()).is_none());
+
+        let mut invalid = config(25);
+        invalid.email_alert.to = vec!["not an address".to_string()];
+        assert!(EmailAlerts::from_config(&invalid).is_none());
+
+        // 未配置 from 时使用 username 作为发件人
+        let mut fallback = config(25);
+        fallback.email_alert.from = None;
+        fallback.email_alert.username = Some("alerts@example.com".to_string());
+        let alerts = EmailAlerts::from_config(&fallback).unwrap();
+        assert_eq!(alerts.from.email.to_string(), "alerts@example.com");
+    }
+}
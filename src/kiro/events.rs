@@ -0,0 +1,152 @@
+//! 凭据事件总线
+//!
+//! `MultiTokenManager` 在凭据状态变化时向 broadcast 通道发布类型化事件；指标统计、Admin 事件流等
+//! 订阅方各自调用 `subscribe()` 订阅，不依赖管理器内部状态。订阅方处理过慢时会丢失最旧的事件。
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use serde::Serialize;
+use tokio::sync::broadcast;
+
+use crate::kiro::token_manager::DisabledReason;
+
+/// 事件通道容量
+pub const EVENT_CHANNEL_CAPACITY: usize = 256;
+
+/// 凭据事件
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(
+    tag = "type",
+    rename_all = "camelCase",
+    rename_all_fields = "camelCase"
+)]
+pub enum TokenEvent {
+    /// 凭据被禁用
+    CredentialDisabled { id: u64, reason: DisabledReason },
+    /// 被禁用的凭据重新启用（自愈、探测成功或手动启用）
+    CredentialRecovered { id: u64 },
+    /// Token 刷新成功
+    TokenRefreshed { id: u64, expires_at: Option<String> },
+    /// 凭据额度已用尽（同时会发布 `CredentialDisabled`）
+    QuotaExhausted { id: u64 },
+    /// 所有凭据均已禁用
+    AllExhausted { total: usize },
+}
+
+impl TokenEvent {
+    /// 事件类型名（与序列化后的 `type` 一致）
+    pub fn kind(&self) -> &'static str {
+        match self {
+            TokenEvent::CredentialDisabled { .. } => "credentialDisabled",
+            TokenEvent::CredentialRecovered { .. } => "credentialRecovered",
+            TokenEvent::TokenRefreshed { .. } => "tokenRefreshed",
+            TokenEvent::QuotaExhausted { .. } => "quotaExhausted",
+            TokenEvent::AllExhausted { .. } => "allExhausted",
+        }
+    }
+}
+
+/// 各类事件的累计次数
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TokenEventCounts {
+    pub credential_disabled: u64,
+    pub credential_recovered: u64,
+    pub token_refreshed: u64,
+    pub quota_exhausted: u64,
+    pub all_exhausted: u64,
+}
+
+/// 事件计数订阅方（用于负载指标）
+#[derive(Default)]
+pub struct TokenEventCounters {
+    credential_disabled: AtomicU64,
+    credential_recovered: AtomicU64,
+    token_refreshed: AtomicU64,
+    quota_exhausted: AtomicU64,
+    all_exhausted: AtomicU64,
+}
+
+impl TokenEventCounters {
+    /// 在后台任务中消费事件并计数
+    pub fn spawn(mut events: broadcast::Receiver<TokenEvent>) -> Arc<Self> {
+        let counters = Arc::new(Self::default());
+        let task_counters = counters.clone();
+        tokio::spawn(async move {
+            loop {
+                match events.recv().await {
+                    Ok(event) => task_counters.record(&event),
+                    Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                        tracing::warn!("凭据事件计数落后，丢失 {} 个事件", skipped);
+                    }
+                    Err(broadcast::error::RecvError::Closed) => return,
+                }
+            }
+        });
+        counters
+    }
+
+    pub fn record(&self, event: &TokenEvent) {
+        let counter = match event {
+            TokenEvent::CredentialDisabled { .. } => &self.credential_disabled,
+            TokenEvent::CredentialRecovered { .. } => &self.credential_recovered,
+            TokenEvent::TokenRefreshed { .. } => &self.token_refreshed,
+            TokenEvent::QuotaExhausted { .. } => &self.quota_exhausted,
+            TokenEvent::AllExhausted { .. } => &self.all_exhausted,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn snapshot(&self) -> TokenEventCounts {
+        TokenEventCounts {
+            credential_disabled: self.credential_disabled.load(Ordering::Relaxed),
+            credential_recovered: self.credential_recovered.load(Ordering::Relaxed),
+            token_refreshed: self.token_refreshed.load(Ordering::Relaxed),
+            quota_exhausted: self.quota_exhausted.load(Ordering::Relaxed),
+            all_exhausted: self.all_exhausted.load(Ordering::Relaxed),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_event_serialization() {
+        let event = TokenEvent::CredentialDisabled {
+            id: 3,
+            reason: DisabledReason::QuotaExceeded,
+        };
+        assert_eq!(
+            serde_json::to_value(&event).unwrap(),
+            json!({"type": event.kind(), "id": 3, "reason": "quotaExceeded"})
+        );
+        let event = TokenEvent::TokenRefreshed {
+            id: 1,
+            expires_at: None,
+        };
+        assert_eq!(
+            serde_json::to_value(&event).unwrap(),
+            json!({"type": "tokenRefreshed", "id": 1, "expiresAt": null})
+        );
+    }
+
+    #[tokio::test]
+    async fn test_counters_subscribe() {
+        let (tx, rx) = broadcast::channel(EVENT_CHANNEL_CAPACITY);
+        let counters = TokenEventCounters::spawn(rx);
+        tx.send(TokenEvent::QuotaExhausted { id: 1 }).unwrap();
+        tx.send(TokenEvent::AllExhausted { total: 1 }).unwrap();
+        drop(tx);
+
+        while counters.snapshot().all_exhausted == 0 {
+            tokio::task::yield_now().await;
+        }
+        let counts = counters.snapshot();
+        assert_eq!(counts.quota_exhausted, 1);
+        assert_eq!(counts.credential_disabled, 0);
+    }
+}
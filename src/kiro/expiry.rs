@@ -0,0 +1,145 @@
+//! 凭据到期预估
+//!
+//! refreshToken 的使用时长从首次获得（或首次被本服务观察到）的时间算起，达到
+//! `expiryForecast.refreshTokenLifetimeDays` 后预计需要交互式重新登录；剩余不足 `warnDays` 天时
+//! 在 Admin API、负载指标和每日报告中标记为即将到期。accessToken 有效期取最近一次刷新的结果。
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use crate::kiro::token_manager::MultiTokenManager;
+use crate::model::config::ExpiryForecastConfig;
+
+/// 每日报告间隔
+const REPORT_INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// 单个凭据的到期预估
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExpiryForecast {
+    /// refreshToken 获得时间（RFC3339）
+    pub refresh_token_obtained_at: Option<String>,
+    /// refreshToken 已使用天数
+    pub refresh_token_age_days: Option<i64>,
+    /// 最近一次刷新得到的 accessToken 有效期（秒）
+    pub access_token_lifetime_secs: Option<i64>,
+    /// 预计需要重新登录的时间（RFC3339）
+    pub reauth_due_at: Option<String>,
+    /// 距离预计重新登录的天数（已过期时为负数）
+    pub reauth_in_days: Option<i64>,
+    /// 即将到期（或已过期）
+    pub expiring_soon: bool,
+}
+
+impl ExpiryForecast {
+    /// 根据 refreshToken 获得时间计算预估，时间未知时只保留 accessToken 有效期
+    pub fn compute(
+        obtained_at: Option<&str>,
+        access_token_lifetime_secs: Option<i64>,
+        config: &ExpiryForecastConfig,
+        now: DateTime<Utc>,
+    ) -> Self {
+        let Some(obtained) = obtained_at
+            .and_then(|t| DateTime::parse_from_rfc3339(t).ok())
+            .map(|t| t.with_timezone(&Utc))
+        else {
+            return Self {
+                access_token_lifetime_secs,
+                ..Self::default()
+            };
+        };
+
+        let lifetime = chrono::Duration::days(config.refresh_token_lifetime_days as i64);
+        let due = obtained + lifetime;
+        let remaining = due - now;
+        Self {
+            refresh_token_obtained_at: Some(obtained.to_rfc3339()),
+            refresh_token_age_days: Some((now - obtained).num_days()),
+            access_token_lifetime_secs,
+            reauth_due_at: Some(due.to_rfc3339()),
+            reauth_in_days: Some(remaining.num_days()),
+            expiring_soon: remaining <= chrono::Duration::days(config.warn_days as i64),
+        }
+    }
+}
+
+/// 启动每日到期报告（启动时先输出一次）
+pub fn spawn_daily_report(manager: Arc<MultiTokenManager>) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(REPORT_INTERVAL);
+        loop {
+            interval.tick().await;
+            report(&manager);
+        }
+    });
+}
+
+fn report(manager: &MultiTokenManager) {
+    let snapshot = manager.snapshot();
+    let mut expiring = 0;
+    for entry in &snapshot.entries {
+        let expiry = &entry.expiry;
+        if !expiry.expiring_soon {
+            continue;
+        }
+        expiring += 1;
+        match expiry.reauth_in_days {
+            Some(days) if days < 0 => tracing::warn!(
+                "凭据 #{} 的 refreshToken 已超过预计有效期 {} 天，可能需要重新登录",
+                entry.id,
+                -days
+            ),
+            Some(days) => tracing::warn!(
+                "凭据 #{} 预计 {} 天后需要重新登录（{}）",
+                entry.id,
+                days,
+                expiry.reauth_due_at.as_deref().unwrap_or_default()
+            ),
+            None => {}
+        }
+    }
+    tracing::info!(
+        "每日凭据到期报告：共 {} 个凭据，{} 个即将需要重新登录",
+        snapshot.total,
+        expiring
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compute_forecast() {
+        let config = ExpiryForecastConfig::default();
+        let now = DateTime::parse_from_rfc3339("2026-03-31T00:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+
+        let fresh = ExpiryForecast::compute(Some("2026-03-01T00:00:00Z"), Some(3600), &config, now);
+        assert_eq!(fresh.refresh_token_age_days, Some(30));
+        assert_eq!(fresh.reauth_in_days, Some(60));
+        assert_eq!(
+            fresh.reauth_due_at.as_deref(),
+            Some("2026-05-30T00:00:00+00:00")
+        );
+        assert_eq!(fresh.access_token_lifetime_secs, Some(3600));
+        assert!(!fresh.expiring_soon);
+
+        let old = ExpiryForecast::compute(Some("2026-01-05T00:00:00Z"), None, &config, now);
+        assert_eq!(old.reauth_in_days, Some(5));
+        assert!(old.expiring_soon);
+
+        let expired = ExpiryForecast::compute(Some("2025-12-01T00:00:00Z"), None, &config, now);
+        assert!(expired.reauth_in_days.unwrap() < 0);
+        assert!(expired.expiring_soon);
+
+        // 获得时间未知时无法预估
+        let unknown = ExpiryForecast::compute(None, Some(3600), &config, now);
+        assert_eq!(unknown.reauth_due_at, None);
+        assert!(!unknown.expiring_soon);
+    }
+}
@@ -0,0 +1,296 @@
+//! 请求头审计
+//!
+//! 上游对请求头顺序较为敏感（见 `build_mcp_headers` 中的严格顺序），
+//! 开启 `headerAudit` 后，每次发送请求前会将实际的请求头顺序与 Kiro IDE 真实抓包得到的参考顺序
+//! 进行比对：出现差异时以 warn 输出差异与请求头（token 脱敏），一致时只在 debug 级别输出，
+//! 便于排查漂移问题。
+
+use reqwest::header::HeaderMap;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+/// Kiro IDE generateAssistantResponse 请求的参考头顺序（小写）
+const KIRO_IDE_API_HEADERS: &[&str] = &[
+    "content-type",
+    "x-amzn-codewhisperer-optout",
+    "x-amzn-kiro-agent-mode",
+    "x-amz-user-agent",
+    "user-agent",
+    "host",
+    "amz-sdk-invocation-id",
+    "amz-sdk-request",
+    "authorization",
+    "connection",
+];
+
+/// Kiro IDE MCP 请求的参考头顺序（小写）
+const KIRO_IDE_MCP_HEADERS: &[&str] = &[
+    "content-type",
+    "x-amz-user-agent",
+    "user-agent",
+    "host",
+    "amz-sdk-invocation-id",
+    "amz-sdk-request",
+    "authorization",
+    "connection",
+];
+
+/// 需要脱敏的请求头
+const SENSITIVE_HEADERS: &[&str] = &["authorization", "cookie", "x-api-key"];
+
+/// 参考抓包
+///
+/// 可通过 `headerAuditReferencePath` 指定 JSON 文件覆盖内置参考：
+/// `{"api": ["content-type", ...], "mcp": ["content-type", ...]}`
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct HeaderAuditReference {
+    #[serde(default = "default_api_headers")]
+    pub api: Vec<String>,
+    #[serde(default = "default_mcp_headers")]
+    pub mcp: Vec<String>,
+}
+
+fn default_api_headers() -> Vec<String> {
+    KIRO_IDE_API_HEADERS.iter().map(|s| s.to_string()).collect()
+}
+
+fn default_mcp_headers() -> Vec<String> {
+    KIRO_IDE_MCP_HEADERS.iter().map(|s| s.to_string()).collect()
+}
+
+impl Default for HeaderAuditReference {
+    fn default() -> Self {
+        Self {
+            api: default_api_headers(),
+            mcp: default_mcp_headers(),
+        }
+    }
+}
+
+impl HeaderAuditReference {
+    /// 从文件加载参考抓包，头名统一转为小写
+    pub fn load<P: AsRef<Path>>(path: P) -> anyhow::Result<Self> {
+        let content = fs::read_to_string(path)?;
+        let mut reference: Self = serde_json::from_str(&content)?;
+        for name in reference.api.iter_mut().chain(reference.mcp.iter_mut()) {
+            *name = name.to_ascii_lowercase();
+        }
+        Ok(reference)
+    }
+}
+
+/// 请求头差异
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HeaderMismatch {
+    /// 参考中存在但实际缺失
+    Missing(String),
+    /// 实际存在但参考中没有
+    Unexpected(String),
+    /// 顺序不一致（位置均为在公共头序列中的下标）
+    OutOfOrder {
+        name: String,
+        expected: usize,
+        actual: usize,
+    },
+}
+
+impl std::fmt::Display for HeaderMismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Missing(name) => write!(f, "缺少请求头 {}", name),
+            Self::Unexpected(name) => write!(f, "多出请求头 {}", name),
+            Self::OutOfOrder {
+                name,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "请求头 {} 顺序不一致（期望位置 {}，实际位置 {}）",
+                name, expected, actual
+            ),
+        }
+    }
+}
+
+/// 按发送顺序渲染请求头，敏感值脱敏
+pub fn render_headers(headers: &HeaderMap) -> Vec<(String, String)> {
+    headers
+        .iter()
+        .map(|(name, value)| {
+            let name = name.as_str().to_string();
+            let value = value.to_str().unwrap_or("<non-ascii>");
+            let value = if SENSITIVE_HEADERS.contains(&name.as_str()) {
+                redact(value)
+            } else {
+                value.to_string()
+            };
+            (name, value)
+        })
+        .collect()
+}
+
+/// 脱敏：保留 scheme 与末尾 4 位
+fn redact(value: &str) -> String {
+    let (scheme, secret) = match value.split_once(' ') {
+        Some((scheme, secret)) => (format!("{} ", scheme), secret),
+        None => (String::new(), value),
+    };
+    let tail: String = secret
+        .chars()
+        .rev()
+        .take(4)
+        .collect::<Vec<_>>()
+        .into_iter()
+        .rev()
+        .collect();
+    if secret.chars().count() <= 8 {
+        format!("{}***", scheme)
+    } else {
+        format!("{}***{}", scheme, tail)
+    }
+}
+
+/// 将实际请求头与参考顺序比对
+pub fn compare(headers: &HeaderMap, reference: &[String]) -> Vec<HeaderMismatch> {
+    let actual: Vec<&str> = headers.keys().map(|k| k.as_str()).collect();
+    let mut mismatches = Vec::new();
+
+    for name in reference {
+        if !actual.contains(&name.as_str()) {
+            mismatches.push(HeaderMismatch::Missing(name.clone()));
+        }
+    }
+    for name in &actual {
+        if !reference.iter().any(|r| r == name) {
+            mismatches.push(HeaderMismatch::Unexpected(name.to_string()));
+        }
+    }
+
+    // 只比较双方都存在的头的相对顺序
+    let expected: Vec<&str> = reference
+        .iter()
+        .map(|s| s.as_str())
+        .filter(|name| actual.contains(name))
+        .collect();
+    let common: Vec<&str> = actual
+        .iter()
+        .copied()
+        .filter(|name| expected.contains(name))
+        .collect();
+    for (actual_pos, name) in common.iter().enumerate() {
+        let expected_pos = expected.iter().position(|e| e == name).unwrap_or(actual_pos);
+        if expected_pos != actual_pos {
+            mismatches.push(HeaderMismatch::OutOfOrder {
+                name: name.to_string(),
+                expected: expected_pos,
+                actual: actual_pos,
+            });
+        }
+    }
+
+    mismatches
+}
+
+/// 比对请求头并记录与参考的差异
+///
+/// 与参考一致时只在 debug 级别输出请求头；有差异时以 warn 输出差异与完整请求头
+pub fn audit(kind: &str, headers: &HeaderMap, reference: &[String]) -> Vec<HeaderMismatch> {
+    let dump = || {
+        render_headers(headers)
+            .into_iter()
+            .enumerate()
+            .map(|(i, (name, value))| format!("  {:>2}. {}: {}", i + 1, name, value))
+            .collect::<Vec<_>>()
+            .join("\n")
+    };
+
+    let mismatches = compare(headers, reference);
+    if mismatches.is_empty() {
+        tracing::debug!(
+            "[header-audit] {} 请求头与参考抓包一致（发送顺序）:\n{}",
+            kind,
+            dump()
+        );
+    } else {
+        for mismatch in &mismatches {
+            tracing::warn!("[header-audit] {} {}", kind, mismatch);
+        }
+        tracing::warn!("[header-audit] {} 请求头（发送顺序）:\n{}", kind, dump());
+    }
+    mismatches
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderValue;
+
+    fn headers_of(names: &[&'static str]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for name in names {
+            headers.insert(*name, HeaderValue::from_static("v"));
+        }
+        headers
+    }
+
+    fn reference_of(names: &[&str]) -> Vec<String> {
+        names.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_compare_identical() {
+        let headers = headers_of(&["content-type", "host", "authorization"]);
+        let reference = reference_of(&["content-type", "host", "authorization"]);
+        assert!(compare(&headers, &reference).is_empty());
+    }
+
+    #[test]
+    fn test_compare_missing_and_unexpected() {
+        let headers = headers_of(&["content-type", "x-extra"]);
+        let reference = reference_of(&["content-type", "host"]);
+        let mismatches = compare(&headers, &reference);
+        assert!(mismatches.contains(&HeaderMismatch::Missing("host".to_string())));
+        assert!(mismatches.contains(&HeaderMismatch::Unexpected("x-extra".to_string())));
+    }
+
+    #[test]
+    fn test_compare_out_of_order() {
+        let headers = headers_of(&["host", "content-type"]);
+        let reference = reference_of(&["content-type", "host"]);
+        let mismatches = compare(&headers, &reference);
+        assert_eq!(mismatches.len(), 2);
+        assert!(matches!(
+            &mismatches[0],
+            HeaderMismatch::OutOfOrder { name, expected: 1, actual: 0 } if name == "host"
+        ));
+    }
+
+    #[test]
+    fn test_render_headers_redacts_token() {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            "authorization",
+            HeaderValue::from_static("Bearer abcdefghijklmnop"),
+        );
+        headers.insert("host", HeaderValue::from_static("q.us-east-1.amazonaws.com"));
+        let rendered = render_headers(&headers);
+        assert_eq!(rendered[0].1, "Bearer ***mnop");
+        assert_eq!(rendered[1].1, "q.us-east-1.amazonaws.com");
+    }
+
+    #[test]
+    fn test_redact_short_secret() {
+        assert_eq!(redact("Bearer abc"), "Bearer ***");
+    }
+
+    #[test]
+    fn test_load_reference_lowercases() {
+        let path = std::env::temp_dir().join(format!("header-audit-{}.json", uuid::Uuid::new_v4()));
+        fs::write(&path, r#"{"api":["Content-Type","Host"]}"#).unwrap();
+        let reference = HeaderAuditReference::load(&path).unwrap();
+        let _ = fs::remove_file(&path);
+        assert_eq!(reference.api, vec!["content-type", "host"]);
+        assert_eq!(reference.mcp, default_mcp_headers());
+    }
+}
@@ -0,0 +1,245 @@
+//! 凭据健康状态持久化
+//!
+//! 禁用状态与原因、连续失败次数、额度用尽后的恢复时间和最近一次刷新时间保存在
+//! `{stateDir}/credential_state.json`（未设置 stateDir 时为凭据文件旁的 `{文件名}.state.json`），
+//! `MultiTokenManager` 启动时按凭据 ID 恢复，避免重启后立即重试额度已用尽的凭据。
+//! refreshToken 指纹不一致（凭据已在文件中被替换）的记录被忽略。
+
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Sender};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Datelike, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::common::persist;
+use crate::kiro::token_manager::DisabledReason;
+
+/// 状态目录中的文件名
+pub const STATE_FILE: &str = "credential_state.json";
+
+/// 单个凭据的健康状态
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CredentialHealth {
+    pub id: u64,
+    /// refreshToken 指纹（SHA-256 前 16 位十六进制）
+    pub fingerprint: String,
+    #[serde(default)]
+    pub disabled: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub disabled_reason: Option<DisabledReason>,
+    #[serde(default)]
+    pub failure_count: u32,
+    /// 额度用尽后预计恢复的时间（下个自然月开始），之后重启不再恢复禁用状态
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exhausted_until: Option<DateTime<Utc>>,
+    /// 最近一次刷新 Token 的时间
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_refresh_at: Option<DateTime<Utc>>,
+}
+
+/// 状态文件路径：优先放在状态目录，其次放在凭据文件旁；都没有时不持久化
+pub fn state_path(state_dir: Option<&str>, credentials_path: Option<&Path>) -> Option<PathBuf> {
+    if let Some(dir) = state_dir {
+        return Some(Path::new(dir).join(STATE_FILE));
+    }
+    let path = credentials_path?;
+    let stem = path.file_stem()?.to_string_lossy();
+    Some(path.with_file_name(format!("{}.state.json", stem)))
+}
+
+/// refreshToken 指纹
+pub fn fingerprint(refresh_token: Option<&str>) -> String {
+    let digest = Sha256::digest(refresh_token.unwrap_or_default().as_bytes());
+    hex::encode(&digest[..8])
+}
+
+/// 额度按自然月（UTC）重置：下个月 1 日 0 点
+pub fn next_month_start(now: DateTime<Utc>) -> DateTime<Utc> {
+    let (year, month) = if now.month() == 12 {
+        (now.year() + 1, 1)
+    } else {
+        (now.year(), now.month() + 1)
+    };
+    NaiveDate::from_ymd_opt(year, month, 1)
+        .and_then(|date| date.and_hms_opt(0, 0, 0))
+        .map(|t| t.and_utc())
+        .unwrap_or(now)
+}
+
+/// 读取状态文件，不存在或无法解析时返回空（只记录警告，不影响启动）
+pub fn load(path: &Path) -> Vec<CredentialHealth> {
+    match persist::read_with_fallback(path, |text| {
+        serde_json::from_str::<Vec<CredentialHealth>>(text)
+    }) {
+        Ok(states) => states.unwrap_or_default(),
+        Err(e) => {
+            tracing::warn!("读取凭据健康状态失败，忽略: {}", e);
+            Vec::new()
+        }
+    }
+}
+
+/// 原子地写入状态文件
+pub fn save(path: &Path, states: &[CredentialHealth]) -> anyhow::Result<()> {
+    let json = serde_json::to_vec_pretty(states)?;
+    persist::write_atomic(path, &json)?;
+    Ok(())
+}
+
+/// 合并写入的等待时间：窗口内的多次更新只写入最后一份
+const WRITE_DEBOUNCE: Duration = Duration::from_millis(500);
+
+/// 持续有更新时最长的合并时间（去抖时间的倍数），到期后先写入一次
+const MAX_DEBOUNCE_FACTOR: u32 = 10;
+
+/// 后台写入状态文件，避免请求路径上同步 fsync
+///
+/// 更新经通道交给独立线程，去抖后只写入最新快照；更新持续不断（如重试风暴）时最多合并
+/// `MAX_DEBOUNCE_FACTOR` 倍去抖时间就写入一次。Drop 时写出尚未落盘的快照再退出。
+pub struct HealthWriter {
+    sender: Option<Sender<Vec<CredentialHealth>>>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl HealthWriter {
+    pub fn new(path: PathBuf) -> Self {
+        Self::with_debounce(path, WRITE_DEBOUNCE)
+    }
+
+    fn with_debounce(path: PathBuf, debounce: Duration) -> Self {
+        let (sender, receiver) = mpsc::channel::<Vec<CredentialHealth>>();
+        let handle = std::thread::Builder::new()
+            .name("kiro-health-writer".to_string())
+            .spawn(move || {
+                while let Ok(mut latest) = receiver.recv() {
+                    // 超时、通道关闭或达到最长合并时间时结束合并
+                    let deadline = Instant::now() + debounce * MAX_DEBOUNCE_FACTOR;
+                    while let Some(wait) = deadline.checked_duration_since(Instant::now()) {
+                        match receiver.recv_timeout(wait.min(debounce)) {
+                            Ok(states) => latest = states,
+                            Err(_) => break,
+                        }
+                    }
+                    if let Err(e) = save(&path, &latest) {
+                        tracing::warn!("保存凭据健康状态失败: {}", e);
+                    }
+                }
+            })
+            .ok();
+        if handle.is_none() {
+            tracing::warn!("无法启动健康状态写入线程，健康状态将不会持久化");
+        }
+        Self {
+            sender: Some(sender),
+            handle,
+        }
+    }
+
+    /// 提交最新快照（不阻塞）
+    pub fn submit(&self, states: Vec<CredentialHealth>) {
+        if let Some(sender) = &self.sender {
+            let _ = sender.send(states);
+        }
+    }
+}
+
+impl Drop for HealthWriter {
+    fn drop(&mut self) {
+        // 关闭通道后写入线程立即写出最后一份快照并退出
+        self.sender.take();
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_paths_and_month_rollover() {
+        assert_eq!(
+            state_path(Some("/data"), Some(Path::new("/etc/kiro/credentials.json"))),
+            Some(PathBuf::from("/data/credential_state.json"))
+        );
+        assert_eq!(
+            state_path(None, Some(Path::new("/etc/kiro/credentials.json"))),
+            Some(PathBuf::from("/etc/kiro/credentials.state.json"))
+        );
+        assert_eq!(state_path(None, None), None);
+
+        let december = "2026-12-15T08:00:00Z".parse().unwrap();
+        assert_eq!(
+            next_month_start(december).to_rfc3339(),
+            "2027-01-01T00:00:00+00:00"
+        );
+        assert_eq!(fingerprint(Some("a")).len(), 16);
+        assert_ne!(fingerprint(Some("a")), fingerprint(Some("b")));
+    }
+
+    #[test]
+    fn test_writer_coalesces_and_flushes_on_drop() {
+        let path =
+            std::env::temp_dir().join(format!("kiro-health-writer-{}.json", uuid::Uuid::new_v4()));
+        let state = |failure_count| CredentialHealth {
+            id: 1,
+            fingerprint: fingerprint(Some("r1")),
+            disabled: false,
+            disabled_reason: None,
+            failure_count,
+            exhausted_until: None,
+            last_refresh_at: None,
+        };
+
+        let writer = HealthWriter::with_debounce(path.clone(), Duration::from_secs(60));
+        writer.submit(vec![state(1)]);
+        writer.submit(vec![state(2)]);
+        // 去抖窗口内不落盘，submit 不等待写入
+        assert!(!path.exists());
+        drop(writer);
+        assert_eq!(load(&path), vec![state(2)]);
+
+        let _ = std::fs::remove_file(&path);
+        let _ = std::fs::remove_file(persist::backup_path(&path));
+    }
+
+    #[test]
+    fn test_writer_flushes_during_continuous_updates() {
+        let path =
+            std::env::temp_dir().join(format!("kiro-health-storm-{}.json", uuid::Uuid::new_v4()));
+        let state = |failure_count| CredentialHealth {
+            id: 1,
+            fingerprint: fingerprint(Some("r1")),
+            disabled: false,
+            disabled_reason: None,
+            failure_count,
+            exhausted_until: None,
+            last_refresh_at: None,
+        };
+
+        // 更新间隔始终短于去抖时间：最长合并时间（200ms）到期后仍会写入
+        let writer = HealthWriter::with_debounce(path.clone(), Duration::from_millis(20));
+        let started = Instant::now();
+        let mut count = 0;
+        while !path.exists() {
+            assert!(
+                started.elapsed() < Duration::from_secs(5),
+                "持续更新时从未写入"
+            );
+            count += 1;
+            writer.submit(vec![state(count)]);
+            std::thread::sleep(Duration::from_millis(5));
+        }
+        assert!(load(&path)[0].failure_count >= 1);
+        drop(writer);
+        assert_eq!(load(&path), vec![state(count)]);
+
+        let _ = std::fs::remove_file(&path);
+        let _ = std::fs::remove_file(persist::backup_path(&path));
+    }
+}
@@ -0,0 +1,105 @@
+//! Provider 拦截器
+//!
+//! 供库使用者在 `KiroProvider` 上注册自定义逻辑（日志、改写请求头、指标等），
+//! 无需复制或修改重试循环。拦截器按注册顺序依次调用，所有方法都有空的默认实现。
+//! 影子流量请求不经过拦截器。
+
+use std::time::Duration;
+
+use reqwest::StatusCode;
+use reqwest::header::HeaderMap;
+
+/// 上游调用类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CallKind {
+    /// generateAssistantResponse（非流式）
+    Api,
+    /// generateAssistantResponse（流式）
+    ApiStream,
+    /// MCP 端点
+    Mcp,
+}
+
+/// 单次尝试的信息
+#[derive(Debug, Clone, Copy)]
+pub struct AttemptInfo<'a> {
+    pub kind: CallKind,
+    /// 从 0 开始的尝试序号
+    pub attempt: usize,
+    pub max_attempts: usize,
+    /// 本次调用的 `amz-sdk-invocation-id`（各次重试相同）
+    pub invocation_id: &'a str,
+    /// 本次尝试使用的凭据 ID
+    pub credential_id: u64,
+    pub region: &'a str,
+    pub url: &'a str,
+}
+
+/// Provider 拦截器
+pub trait Interceptor: Send + Sync {
+    /// 请求发送前调用，可修改请求头
+    fn on_request(&self, info: &AttemptInfo<'_>, headers: &mut HeaderMap) {
+        let _ = (info, headers);
+    }
+
+    /// 收到响应头后调用（包括非 2xx 响应）
+    fn on_response(&self, info: &AttemptInfo<'_>, status: StatusCode, elapsed: Duration) {
+        let _ = (info, status, elapsed);
+    }
+
+    /// 某次尝试失败、即将进行下一次尝试前调用
+    ///
+    /// `attempt` 为即将开始的尝试序号，`error` 为上一次尝试的错误
+    fn on_retry(&self, kind: CallKind, attempt: usize, error: &anyhow::Error) {
+        let _ = (kind, attempt, error);
+    }
+
+    /// 整个调用最终失败时调用（重试耗尽或不可重试的错误）
+    fn on_error(&self, kind: CallKind, error: &anyhow::Error) {
+        let _ = (kind, error);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parking_lot::Mutex;
+
+    #[derive(Default)]
+    struct Recorder {
+        events: Mutex<Vec<String>>,
+    }
+
+    impl Interceptor for Recorder {
+        fn on_request(&self, info: &AttemptInfo<'_>, headers: &mut HeaderMap) {
+            headers.insert("x-trace", "1".parse().unwrap());
+            self.events.lock().push(format!("request {}", info.attempt));
+        }
+
+        fn on_error(&self, kind: CallKind, _error: &anyhow::Error) {
+            self.events.lock().push(format!("error {:?}", kind));
+        }
+    }
+
+    #[test]
+    fn test_default_methods_and_header_mutation() {
+        let recorder = Recorder::default();
+        let info = AttemptInfo {
+            kind: CallKind::Mcp,
+            attempt: 0,
+            max_attempts: 3,
+            invocation_id: "00000000-0000-0000-0000-000000000000",
+            credential_id: 1,
+            region: "us-east-1",
+            url: "https://q.us-east-1.amazonaws.com/mcp",
+        };
+        let mut headers = HeaderMap::new();
+        recorder.on_request(&info, &mut headers);
+        recorder.on_response(&info, StatusCode::OK, Duration::ZERO);
+        recorder.on_retry(CallKind::Mcp, 1, &anyhow::anyhow!("boom"));
+        recorder.on_error(CallKind::Mcp, &anyhow::anyhow!("boom"));
+
+        assert_eq!(headers.get("x-trace").unwrap(), "1");
+        assert_eq!(*recorder.events.lock(), vec!["request 0", "error Mcp"]);
+    }
+}
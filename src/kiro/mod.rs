@@ -1,7 +1,24 @@
 //! Kiro API 客户端模块
 
+pub mod attempt_timing;
+pub mod aws_error;
+pub mod device_auth;
+pub mod email_alert;
+pub mod events;
+pub mod expiry;
+pub mod header_audit;
+pub mod health_state;
+pub mod interceptor;
 pub mod machine_id;
 pub mod model;
 pub mod parser;
 pub mod provider;
+pub mod rate_learning;
+pub mod raw_capture;
+pub mod reload;
+pub mod retry_audit;
+pub mod shadow;
+pub mod telemetry;
 pub mod token_manager;
+pub mod transform;
+pub mod vpc_endpoint;
@@ -5,7 +5,10 @@
 
 use serde::{Deserialize, Serialize};
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+
+use crate::common::persist;
+use crate::model::env;
 
 /// Kiro OAuth 凭证
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
@@ -48,6 +51,16 @@ pub struct KiroCredentials {
     #[serde(skip_serializing_if = "is_zero")]
     pub priority: u32,
 
+    /// 加权选择时的权重（`selectionStrategy` 为 `weighted` 时生效，默认 1）
+    /// 同一优先级内按权重比例分配请求，额度更大的账号可设置更大的权重
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub weight: Option<u32>,
+
+    /// 备用凭据：不参与正常轮换，可用的正常凭据少于 `reserveMinAvailable` 个时才启用
+    #[serde(default)]
+    #[serde(skip_serializing_if = "is_false")]
+    pub reserve: bool,
+
     /// 凭据级 Region 配置（用于 OIDC token 刷新）
     /// 未配置时回退到 config.json 的全局 region
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -57,6 +70,35 @@ pub struct KiroCredentials {
     /// 未配置时回退到 config.json 的 machineId；都未配置时由 refreshToken 派生
     #[serde(skip_serializing_if = "Option::is_none")]
     pub machine_id: Option<String>,
+
+    /// 凭据签发所在的 API Region（可选）
+    /// 配置后，使用该凭据的 API 调用必须落在此区域；
+    /// 区域不一致时按 config.json 的 regionMismatchPolicy 自动纠正或拒绝
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub api_region: Option<String>,
+
+    /// 自定义上游端点（可选），如内网镜像、VPC Endpoint 或企业网关
+    /// 替换默认的 `https://q.{region}.amazonaws.com`，可包含路径前缀与 `{region}` 占位符；
+    /// Host 头随之取该端点的主机名
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub api_endpoint: Option<String>,
+
+    /// refreshToken 获得时间（RFC3339，用于预估何时需要重新登录）
+    /// refreshToken 轮换时更新；未记录时取本服务首次加载该凭据的时间
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub refresh_token_obtained_at: Option<String>,
+
+    /// 来源文件（从凭据目录或 `credentialSources` 加载时记录，不序列化）
+    #[serde(skip)]
+    pub source_file: Option<SourceFile>,
+}
+
+/// 凭据来源文件
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceFile {
+    pub path: PathBuf,
+    /// 文件为数组格式（单对象格式的文件只剩一个凭据时仍按单对象回写）
+    pub multiple: bool,
 }
 
 /// 判断是否为零（用于跳过序列化）
@@ -64,6 +106,10 @@ fn is_zero(value: &u32) -> bool {
     *value == 0
 }
 
+fn is_false(value: &bool) -> bool {
+    !*value
+}
+
 fn canonicalize_auth_method_value(value: &str) -> &str {
     if value.eq_ignore_ascii_case("builder-id") || value.eq_ignore_ascii_case("iam") {
         "idc"
@@ -91,24 +137,11 @@ impl CredentialsConfig {
     ///
     /// - 如果文件不存在，返回空数组
     /// - 如果文件内容为空，返回空数组
+    /// - 如果文件内容损坏，回退到上次回写前的备份（见 `persist`）
     /// - 支持单对象或数组格式
     pub fn load<P: AsRef<Path>>(path: P) -> anyhow::Result<Self> {
-        let path = path.as_ref();
-
-        // 文件不存在时返回空数组
-        if !path.exists() {
-            return Ok(CredentialsConfig::Multiple(vec![]));
-        }
-
-        let content = fs::read_to_string(path)?;
-
-        // 文件为空时返回空数组
-        if content.trim().is_empty() {
-            return Ok(CredentialsConfig::Multiple(vec![]));
-        }
-
-        let config = serde_json::from_str(&content)?;
-        Ok(config)
+        Ok(persist::read_with_fallback(path.as_ref(), Self::from_json)?
+            .unwrap_or(CredentialsConfig::Multiple(vec![])))
     }
 
     /// 转换为按优先级排序的凭据列表
@@ -129,6 +162,14 @@ impl CredentialsConfig {
         }
     }
 
+    /// 转换为凭据列表（保持原有顺序）
+    fn into_credentials(self) -> Vec<KiroCredentials> {
+        match self {
+            CredentialsConfig::Single(cred) => vec![cred],
+            CredentialsConfig::Multiple(creds) => creds,
+        }
+    }
+
     /// 获取凭据数量
     pub fn len(&self) -> usize {
         match self {
@@ -149,6 +190,254 @@ impl CredentialsConfig {
     pub fn is_multiple(&self) -> bool {
         matches!(self, CredentialsConfig::Multiple(_))
     }
+
+    /// 追加一个凭据（转换为数组格式），分配比现有 ID 都大的 ID 并返回
+    pub fn push(&mut self, mut credentials: KiroCredentials) -> u64 {
+        let mut list =
+            std::mem::replace(self, CredentialsConfig::Multiple(vec![])).into_credentials();
+        let id = list
+            .iter()
+            .filter_map(|c| c.id)
+            .max()
+            .unwrap_or(0)
+            .max(list.len() as u64)
+            + 1;
+        credentials.id = Some(id);
+        list.push(credentials);
+        *self = CredentialsConfig::Multiple(list);
+        id
+    }
+
+    /// 从 JSON 字符串解析（空内容视为空数组）
+    pub fn from_json(content: &str) -> anyhow::Result<Self> {
+        if content.trim().is_empty() {
+            return Ok(CredentialsConfig::Multiple(vec![]));
+        }
+        Ok(serde_json::from_str(content)?)
+    }
+
+    /// 加载附加凭据来源（文件、目录或文件名含 `*` / `?` 通配符的路径），每个凭据记录来源文件
+    pub fn load_sources(sources: &[String]) -> anyhow::Result<Vec<KiroCredentials>> {
+        let mut files: Vec<PathBuf> = Vec::new();
+        for source in sources {
+            for file in expand_source(source)? {
+                if !files.contains(&file) {
+                    files.push(file);
+                }
+            }
+        }
+
+        let mut credentials = Vec::new();
+        for file in files {
+            let config = Self::load(&file)
+                .map_err(|e| anyhow::anyhow!("加载凭据文件 {} 失败: {}", file.display(), e))?;
+            let source_file = SourceFile {
+                path: file,
+                multiple: config.is_multiple(),
+            };
+            credentials.extend(config.into_credentials().into_iter().map(|mut cred| {
+                cred.source_file = Some(source_file.clone());
+                cred
+            }));
+        }
+        Ok(credentials)
+    }
+
+    /// 从 `KIRO_REFRESH_TOKEN_0..n` 构造凭据（编号从 0 开始连续，遇到缺失即停止）
+    ///
+    /// 同编号的 `KIRO_AUTH_METHOD_n` / `KIRO_CLIENT_ID_n` / `KIRO_CLIENT_SECRET_n` 可选，
+    /// 优先级取编号
+    fn from_refresh_token_env(get: &impl Fn(&str) -> Option<String>) -> Vec<KiroCredentials> {
+        (0..)
+            .map_while(|index: u32| {
+                let var = |prefix: &str| get(&format!("{}{}", prefix, index));
+                let refresh_token = var(env::REFRESH_TOKEN_PREFIX)?;
+                Some(KiroCredentials {
+                    refresh_token: Some(refresh_token.trim().to_string()),
+                    auth_method: var(env::AUTH_METHOD_PREFIX),
+                    client_id: var(env::CLIENT_ID_PREFIX),
+                    client_secret: var(env::CLIENT_SECRET_PREFIX),
+                    priority: index,
+                    ..Default::default()
+                })
+            })
+            .collect()
+    }
+
+    /// 按容器模式规则确定凭据来源与回写位置，并合并 `sources` 中的附加凭据文件
+    ///
+    /// 优先级：状态目录中已回写的凭据 > `KIRO_CREDENTIALS`（或 `KIRO_CREDENTIALS_JSON`）>
+    /// `KIRO_REFRESH_TOKEN_0..n` > `KIRO_CREDENTIALS_FILE` > 凭据文件。
+    /// 凭据文件路径为目录时加载其中全部 `.json` 文件。附加凭据记录来源文件并回写到原文件
+    pub fn resolve(
+        path: &str,
+        state_dir: Option<&str>,
+        sources: &[String],
+    ) -> anyhow::Result<ResolvedCredentials> {
+        Self::resolve_with(path, state_dir, sources, env::var)
+    }
+
+    fn resolve_with(
+        path: &str,
+        state_dir: Option<&str>,
+        sources: &[String],
+        get: impl Fn(&str) -> Option<String>,
+    ) -> anyhow::Result<ResolvedCredentials> {
+        let mut resolved = Self::resolve_primary(path, state_dir, get)?;
+        resolved.reload_sources.extend(sources.iter().cloned());
+        let extra = Self::load_sources(sources)?;
+        if !extra.is_empty() {
+            let mut credentials = resolved.config.into_credentials();
+            credentials.extend(extra);
+            resolved.config = CredentialsConfig::Multiple(credentials);
+        }
+        Ok(resolved)
+    }
+
+    fn resolve_primary(
+        path: &str,
+        state_dir: Option<&str>,
+        get: impl Fn(&str) -> Option<String>,
+    ) -> anyhow::Result<ResolvedCredentials> {
+        let state_path = state_dir.map(|dir| Path::new(dir).join("credentials.json"));
+
+        // 状态目录中的凭据是上次刷新后回写的结果，比初始来源更新
+        if let Some(state_path) = &state_path
+            && state_path.exists()
+        {
+            let config = Self::load(state_path)?;
+            if !config.is_empty() {
+                return Ok(ResolvedCredentials {
+                    source: format!("{}", state_path.display()),
+                    config,
+                    persist_path: Some(state_path.clone()),
+                    is_multiple_format: true,
+                    reload_sources: Vec::new(),
+                });
+            }
+        }
+
+        let inline = [env::CREDENTIALS, env::CREDENTIALS_JSON]
+            .into_iter()
+            .find_map(|name| get(name).map(|value| (name, value)));
+        let refresh_tokens = Self::from_refresh_token_env(&get);
+
+        // 状态目录存在时统一以数组格式回写到状态目录
+        let mut reload_sources = Vec::new();
+        let (config, source, default_persist) = if let Some((name, value)) = inline {
+            let json = env::decode_json(name, &value)?;
+            (Self::from_json(&json)?, name.to_string(), None)
+        } else if !refresh_tokens.is_empty() {
+            let last = refresh_tokens.len() - 1;
+            let source = format!("{}0..{}", env::REFRESH_TOKEN_PREFIX, last);
+            (CredentialsConfig::Multiple(refresh_tokens), source, None)
+        } else if let Some(file) = get(env::CREDENTIALS_FILE) {
+            // 挂载的 secret 通常只读，不回写
+            let content = fs::read_to_string(&file)
+                .map_err(|e| anyhow::anyhow!("读取 {} 失败: {}", file, e))?;
+            (Self::from_json(&content)?, file, None)
+        } else if Path::new(path).is_dir() {
+            // 目录中的凭据各自回写到来源文件
+            let creds = Self::load_sources(&[path.to_string()])?;
+            reload_sources.push(path.to_string());
+            (CredentialsConfig::Multiple(creds), path.to_string(), None)
+        } else {
+            (Self::load(path)?, path.to_string(), Some(PathBuf::from(path)))
+        };
+
+        let is_multiple_format = state_path.is_some() || config.is_multiple();
+        Ok(ResolvedCredentials {
+            config,
+            source,
+            persist_path: state_path.or(default_persist),
+            is_multiple_format,
+            reload_sources,
+        })
+    }
+}
+
+/// 展开单个凭据来源：目录取其中全部 `.json` 文件，通配符只允许出现在文件名中
+fn expand_source(source: &str) -> anyhow::Result<Vec<PathBuf>> {
+    let path = Path::new(source);
+    if path.is_dir() {
+        return list_dir(path, |name| name.ends_with(".json"));
+    }
+
+    let name = path
+        .file_name()
+        .and_then(|n| n.to_str())
+        .unwrap_or_default();
+    if !name.contains(['*', '?']) {
+        if !path.is_file() {
+            anyhow::bail!("凭据来源不存在: {}", source);
+        }
+        return Ok(vec![path.to_path_buf()]);
+    }
+
+    let dir = path
+        .parent()
+        .filter(|p| !p.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+    if dir.to_string_lossy().contains(['*', '?']) {
+        anyhow::bail!("凭据来源 {} 无效: 仅支持在文件名中使用通配符", source);
+    }
+    if !dir.is_dir() {
+        return Ok(Vec::new());
+    }
+    list_dir(dir, |candidate| wildcard_match(name, candidate))
+}
+
+/// 列出目录中文件名满足条件的文件（按路径排序）
+fn list_dir(dir: &Path, matches: impl Fn(&str) -> bool) -> anyhow::Result<Vec<PathBuf>> {
+    let entries = fs::read_dir(dir)
+        .map_err(|e| anyhow::anyhow!("读取凭据目录 {} 失败: {}", dir.display(), e))?;
+    let mut files: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|p| p.is_file())
+        .filter(|p| p.file_name().and_then(|n| n.to_str()).is_some_and(&matches))
+        .collect();
+    files.sort();
+    Ok(files)
+}
+
+/// 文件名通配符匹配：`*` 匹配任意个字符，`?` 匹配单个字符
+fn wildcard_match(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    let (mut p, mut n) = (0, 0);
+    // 最近一个 `*` 的位置及其当前匹配到的名称位置
+    let mut star: Option<(usize, usize)> = None;
+    while n < name.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == name[n]) {
+            p += 1;
+            n += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            star = Some((p, n));
+            p += 1;
+        } else if let Some((star_p, star_n)) = star {
+            // 回溯：让 `*` 多吞一个字符
+            p = star_p + 1;
+            n = star_n + 1;
+            star = Some((star_p, n));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+/// 已确定来源的凭据配置
+#[derive(Debug)]
+pub struct ResolvedCredentials {
+    pub config: CredentialsConfig,
+    /// 来源描述（文件路径或环境变量名）
+    pub source: String,
+    /// 刷新后回写的位置（None 表示不回写）
+    pub persist_path: Option<PathBuf>,
+    /// 是否按多凭据格式回写
+    pub is_multiple_format: bool,
+    /// 重新加载时需要重新展开的来源：作为凭据路径的目录与 `credentialSources`
+    pub reload_sources: Vec<String>,
 }
 
 impl KiroCredentials {
@@ -188,11 +477,137 @@ impl KiroCredentials {
             self.auth_method = Some(canonical.to_string());
         }
     }
+
+    /// 加权选择时的权重（未配置为 1，最小为 1）
+    pub fn effective_weight(&self) -> u32 {
+        self.weight.unwrap_or(1).max(1)
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_resolve_env_credentials_with_state_dir() {
+        let dir = std::env::temp_dir().join(format!("kiro-state-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        let state_dir = dir.to_str().unwrap();
+        let env_json = r#"{"refreshToken":"from-env"}"#;
+        let get = |k: &str| (k == env::CREDENTIALS).then(|| env_json.to_string());
+
+        // 状态目录中没有凭据时使用环境变量，并回写到状态目录
+        let resolved =
+            CredentialsConfig::resolve_with("missing.json", Some(state_dir), &[], get).unwrap();
+        assert_eq!(resolved.source, env::CREDENTIALS);
+        assert_eq!(resolved.persist_path, Some(dir.join("credentials.json")));
+        assert!(resolved.is_multiple_format);
+
+        // 状态目录中已有回写结果时优先使用
+        fs::write(dir.join("credentials.json"), r#"[{"refreshToken":"refreshed"}]"#).unwrap();
+        let resolved =
+            CredentialsConfig::resolve_with("missing.json", Some(state_dir), &[], get).unwrap();
+        let creds = resolved.config.into_sorted_credentials();
+        assert_eq!(creds[0].refresh_token.as_deref(), Some("refreshed"));
+
+        // 没有状态目录时环境变量来源不回写
+        let resolved = CredentialsConfig::resolve_with("missing.json", None, &[], get).unwrap();
+        assert!(resolved.persist_path.is_none());
+        assert!(!resolved.is_multiple_format);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_resolve_refresh_token_env() {
+        let vars = HashMap::from([
+            ("KIRO_REFRESH_TOKEN_0", "social-token"),
+            ("KIRO_REFRESH_TOKEN_1", "idc-token"),
+            ("KIRO_AUTH_METHOD_1", "builder-id"),
+            ("KIRO_CLIENT_ID_1", "client"),
+            ("KIRO_CLIENT_SECRET_1", "secret"),
+            // 编号不连续的不加载
+            ("KIRO_REFRESH_TOKEN_3", "skipped"),
+        ]);
+        let get = |k: &str| vars.get(k).map(|v| v.to_string());
+
+        let resolved = CredentialsConfig::resolve_with("missing.json", None, &[], get).unwrap();
+        assert_eq!(resolved.source, "KIRO_REFRESH_TOKEN_0..1");
+        assert!(resolved.persist_path.is_none());
+        let creds = resolved.config.into_sorted_credentials();
+        assert_eq!(creds.len(), 2);
+        assert_eq!(creds[0].refresh_token.as_deref(), Some("social-token"));
+        assert!(creds[0].auth_method.is_none());
+        assert_eq!(creds[1].auth_method.as_deref(), Some("idc"));
+        assert_eq!(creds[1].client_secret.as_deref(), Some("secret"));
+        assert_eq!(creds[1].priority, 1);
+
+        // 整份 JSON 优先于单个 refresh token
+        let get = |k: &str| {
+            (k == env::CREDENTIALS_JSON)
+                .then(|| r#"[{"refreshToken":"json"}]"#.to_string())
+                .or_else(|| vars.get(k).map(|v| v.to_string()))
+        };
+        let resolved = CredentialsConfig::resolve_with("missing.json", None, &[], get).unwrap();
+        assert_eq!(resolved.source, env::CREDENTIALS_JSON);
+        assert_eq!(resolved.config.len(), 1);
+    }
+
+    #[test]
+    fn test_load_sources_from_dir_and_glob() {
+        let dir = std::env::temp_dir().join(format!("kiro-sources-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("a.json"), r#"{"refreshToken":"a"}"#).unwrap();
+        let b = r#"[{"refreshToken":"b1"},{"refreshToken":"b2"}]"#;
+        fs::write(dir.join("b.json"), b).unwrap();
+        fs::write(dir.join("notes.txt"), "ignored").unwrap();
+
+        let creds = CredentialsConfig::load_sources(&[dir.display().to_string()]).unwrap();
+        let tokens: Vec<_> = creds.iter().map(|c| c.refresh_token.as_deref()).collect();
+        assert_eq!(tokens, [Some("a"), Some("b1"), Some("b2")]);
+        let source = creds[0].source_file.as_ref().unwrap();
+        assert_eq!(source.path, dir.join("a.json"));
+        assert!(!source.multiple);
+        assert!(creds[1].source_file.as_ref().unwrap().multiple);
+
+        // 重复匹配的文件只加载一次
+        let sources = [
+            dir.join("b*.json").display().to_string(),
+            dir.join("?.json").display().to_string(),
+        ];
+        let creds = CredentialsConfig::load_sources(&sources).unwrap();
+        assert_eq!(creds.len(), 3);
+        assert_eq!(creds[2].refresh_token.as_deref(), Some("a"));
+
+        // 与主凭据合并
+        let get = |_: &str| None;
+        let resolved =
+            CredentialsConfig::resolve_with("missing.json", None, &sources[..1], get).unwrap();
+        assert_eq!(resolved.config.len(), 2);
+        // 作为凭据路径的目录与附加来源在重新加载时重新展开
+        let dir_path = dir.display().to_string();
+        let resolved =
+            CredentialsConfig::resolve_with(&dir_path, None, &sources[..1], get).unwrap();
+        assert_eq!(resolved.reload_sources, [dir_path, sources[0].clone()]);
+
+        // 目录部分不支持通配符，普通文件必须存在
+        for invalid in ["*/a.json", "c.json"] {
+            let source = dir.join(invalid).display().to_string();
+            assert!(CredentialsConfig::load_sources(&[source]).is_err());
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_wildcard_match() {
+        assert!(wildcard_match("kiro-*.json", "kiro-work.json"));
+        assert!(wildcard_match("*.json", ".json"));
+        assert!(wildcard_match("a*b*c", "aXbYbZc"));
+        assert!(wildcard_match("?.json", "a.json"));
+        assert!(!wildcard_match("?.json", "ab.json"));
+        assert!(!wildcard_match("kiro-*.json", "kiro-work.json.bak"));
+    }
 
     #[test]
     fn test_from_json() {
@@ -235,8 +650,14 @@ mod tests {
             client_id: None,
             client_secret: None,
             priority: 0,
+            weight: None,
+            reserve: false,
             region: None,
             machine_id: None,
+            api_region: None,
+            api_endpoint: None,
+            refresh_token_obtained_at: None,
+            source_file: None,
         };
 
         let json = creds.to_pretty_json().unwrap();
@@ -345,8 +766,14 @@ mod tests {
             client_id: None,
             client_secret: None,
             priority: 0,
+            weight: None,
+            reserve: false,
             region: Some("eu-west-1".to_string()),
             machine_id: None,
+            api_region: None,
+            api_endpoint: None,
+            refresh_token_obtained_at: None,
+            source_file: None,
         };
 
         let json = creds.to_pretty_json().unwrap();
@@ -367,8 +794,14 @@ mod tests {
             client_id: None,
             client_secret: None,
             priority: 0,
+            weight: None,
+            reserve: false,
             region: None,
             machine_id: None,
+            api_region: None,
+            api_endpoint: None,
+            refresh_token_obtained_at: None,
+            source_file: None,
         };
 
         let json = creds.to_pretty_json().unwrap();
@@ -471,8 +904,14 @@ mod tests {
             client_id: None,
             client_secret: None,
             priority: 3,
+            weight: Some(2),
+            reserve: false,
             region: Some("us-west-2".to_string()),
             machine_id: Some("c".repeat(64)),
+            api_region: None,
+            api_endpoint: None,
+            refresh_token_obtained_at: None,
+            source_file: None,
         };
 
         let json = original.to_pretty_json().unwrap();
@@ -485,4 +924,32 @@ mod tests {
         assert_eq!(parsed.region, original.region);
         assert_eq!(parsed.machine_id, original.machine_id);
     }
+
+    #[test]
+    fn test_api_region_field_parsing() {
+        let json = r#"{"refreshToken": "test", "apiRegion": "eu-central-1"}"#;
+        let creds = KiroCredentials::from_json(json).unwrap();
+        assert_eq!(creds.api_region, Some("eu-central-1".to_string()));
+        assert_eq!(creds.region, None);
+
+        let json = creds.to_pretty_json().unwrap();
+        assert!(json.contains("apiRegion"));
+    }
+
+    #[test]
+    fn test_push_converts_to_array_with_new_id() {
+        let mut config = CredentialsConfig::from_json(r#"{"refreshToken": "a"}"#).unwrap();
+        let id = config.push(KiroCredentials {
+            refresh_token: Some("b".to_string()),
+            ..Default::default()
+        });
+        // 未分配 ID 的现有凭据启动时按顺序编号，新 ID 不与其冲突
+        assert_eq!(id, 2);
+        assert!(config.is_multiple());
+        assert_eq!(config.len(), 2);
+
+        let mut config =
+            CredentialsConfig::from_json(r#"[{"id": 7, "refreshToken": "a"}]"#).unwrap();
+        assert_eq!(config.push(KiroCredentials::default()), 8);
+    }
 }
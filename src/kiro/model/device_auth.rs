@@ -0,0 +1,129 @@
+//! AWS SSO OIDC 设备码授权的请求/响应类型
+
+use serde::{Deserialize, Serialize};
+
+/// 注册 OIDC 客户端请求体
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RegisterClientRequest {
+    pub client_name: String,
+    pub client_type: String,
+    pub scopes: Vec<String>,
+}
+
+/// 注册 OIDC 客户端响应体
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RegisterClientResponse {
+    pub client_id: String,
+    pub client_secret: String,
+}
+
+/// 发起设备授权请求体
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StartDeviceAuthorizationRequest {
+    pub client_id: String,
+    pub client_secret: String,
+    pub start_url: String,
+}
+
+/// 发起设备授权响应体
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StartDeviceAuthorizationResponse {
+    pub device_code: String,
+    pub user_code: String,
+    pub verification_uri: String,
+    #[serde(default)]
+    pub verification_uri_complete: Option<String>,
+    pub expires_in: i64,
+    #[serde(default)]
+    pub interval: Option<u64>,
+}
+
+/// 用设备码换取 Token 的请求体
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DeviceTokenRequest {
+    pub client_id: String,
+    pub client_secret: String,
+    pub grant_type: String,
+    pub device_code: String,
+}
+
+/// 用设备码换取 Token 的响应体
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DeviceTokenResponse {
+    pub access_token: String,
+    pub refresh_token: String,
+    #[serde(default)]
+    pub expires_in: Option<i64>,
+}
+
+/// OIDC 错误响应体（授权未完成时 `error` 为 `authorization_pending`）
+#[derive(Debug, Deserialize)]
+pub struct OidcErrorResponse {
+    pub error: String,
+    #[serde(default)]
+    pub error_description: Option<String>,
+}
+
+/// 查询可用 profile 的请求体（CodeWhisperer `ListAvailableProfiles`）
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ListAvailableProfilesRequest {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next_token: Option<String>,
+}
+
+/// 查询可用 profile 的响应体
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ListAvailableProfilesResponse {
+    #[serde(default)]
+    pub profiles: Vec<Profile>,
+    #[serde(default)]
+    pub next_token: Option<String>,
+}
+
+/// 账号可用的 Kiro profile
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Profile {
+    pub arn: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_list_available_profiles_pagination() {
+        // 首页请求不带 nextToken，后续页带上响应中的 nextToken
+        let first = ListAvailableProfilesRequest::default();
+        assert_eq!(serde_json::to_string(&first).unwrap(), "{}");
+
+        let page: ListAvailableProfilesResponse =
+            serde_json::from_str(r#"{"profiles":[],"nextToken":"page-2"}"#).unwrap();
+        assert!(page.profiles.is_empty());
+        let next = ListAvailableProfilesRequest {
+            next_token: page.next_token,
+        };
+        assert_eq!(
+            serde_json::to_string(&next).unwrap(),
+            r#"{"nextToken":"page-2"}"#
+        );
+
+        let last: ListAvailableProfilesResponse = serde_json::from_str(
+            r#"{"profiles":[{"arn":"arn:aws:codewhisperer:us-east-1:1:profile/P","profileName":"P"}]}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            last.profiles[0].arn,
+            "arn:aws:codewhisperer:us-east-1:1:profile/P"
+        );
+        assert!(last.next_token.is_none());
+    }
+}
@@ -0,0 +1,100 @@
+//! 文件/产物事件
+//!
+//! 上游在部分场景下会以独立事件下发文件内容（事件类型名包含 `artifact` 或 `file`，
+//! 负载中以 base64 携带文件数据）。这类事件不在已知事件列表中，
+//! 通过负载形态识别，避免被当作未知事件直接丢弃。
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use serde_json::Value;
+
+/// 文件/产物事件
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ArtifactEvent {
+    /// 文件名
+    pub name: String,
+    /// MIME 类型
+    pub mime_type: String,
+    /// 文件内容
+    pub data: Vec<u8>,
+}
+
+/// 负载中可能携带文件数据的字段
+const DATA_FIELDS: &[&str] = &["content", "data", "bytes"];
+/// 负载中可能携带文件名的字段
+const NAME_FIELDS: &[&str] = &["name", "fileName", "filename", "path"];
+/// 负载中可能携带 MIME 类型的字段
+const MIME_FIELDS: &[&str] = &["mimeType", "contentType", "mediaType"];
+
+impl ArtifactEvent {
+    /// 尝试从未知事件中识别文件/产物
+    ///
+    /// 返回 None 表示该事件不是文件事件
+    pub fn detect(event_type: &str, payload: &[u8]) -> Option<Self> {
+        let lower = event_type.to_ascii_lowercase();
+        if !lower.contains("artifact") && !lower.contains("file") {
+            return None;
+        }
+
+        let json: Value = serde_json::from_slice(payload).ok()?;
+        // 兼容 {"artifact": {...}} / {"file": {...}} 的嵌套形态
+        let body = ["artifact", "file"]
+            .iter()
+            .find_map(|key| json.get(*key).filter(|v| v.is_object()))
+            .unwrap_or(&json);
+
+        let field = |names: &[&str]| {
+            names
+                .iter()
+                .find_map(|key| body.get(*key).and_then(|v| v.as_str()))
+                .map(|s| s.to_string())
+        };
+
+        let data = STANDARD.decode(field(DATA_FIELDS)?).ok()?;
+        let name = field(NAME_FIELDS)
+            .map(|n| n.rsplit(['/', '\\']).next().unwrap_or_default().to_string())
+            .filter(|n| !n.is_empty())
+            .unwrap_or_else(|| "artifact.bin".to_string());
+        let mime_type = field(MIME_FIELDS).unwrap_or_else(|| {
+            mime_guess::from_path(&name)
+                .first_or_octet_stream()
+                .to_string()
+        });
+
+        Some(Self {
+            name,
+            mime_type,
+            data,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect_flat_payload() {
+        let payload = br#"{"fileName":"out/report.csv","content":"YSxiCjEsMg=="}"#;
+        let artifact = ArtifactEvent::detect("fileOutputEvent", payload).unwrap();
+        assert_eq!(artifact.name, "report.csv");
+        assert_eq!(artifact.mime_type, "text/csv");
+        assert_eq!(artifact.data, b"a,b\n1,2");
+    }
+
+    #[test]
+    fn test_detect_nested_payload() {
+        let payload = br#"{"artifact":{"name":"x.png","mimeType":"image/png","data":"AAE="}}"#;
+        let artifact = ArtifactEvent::detect("artifactEvent", payload).unwrap();
+        assert_eq!(artifact.mime_type, "image/png");
+        assert_eq!(artifact.data, vec![0, 1]);
+    }
+
+    #[test]
+    fn test_detect_rejects_other_events() {
+        let payload = br#"{"content":"YQ=="}"#;
+        assert!(ArtifactEvent::detect("followupPromptEvent", payload).is_none());
+        assert!(ArtifactEvent::detect("fileEvent", b"not json").is_none());
+        assert!(ArtifactEvent::detect("fileEvent", br#"{"content":"%%%"}"#).is_none());
+    }
+}
@@ -4,8 +4,9 @@
 
 use serde::{Deserialize, Serialize};
 
-use crate::kiro::parser::error::ParseResult;
+use crate::kiro::parser::error::{ParseError, ParseResult};
 use crate::kiro::parser::frame::Frame;
+use crate::kiro::parser::text::{TextJoiner, Unit, json_string_units};
 
 use super::base::EventPayload;
 
@@ -39,11 +40,43 @@ pub struct AssistantResponseEvent {
     #[serde(skip_serializing)]
     #[allow(dead_code)]
     extra: serde_json::Value,
+
+    /// 多字节字符被拆到相邻事件时的原始码元（此时 `content` 中被拆开的部分为 U+FFFD）
+    #[serde(skip)]
+    units: Option<Vec<Unit>>,
+}
+
+impl AssistantResponseEvent {
+    /// 从 payload 解析；标准反序列化失败时宽松提取 `content`，保留被拆开的字符
+    fn from_payload(payload: &[u8]) -> ParseResult<Self> {
+        let error = match serde_json::from_slice(payload) {
+            Ok(event) => return Ok(event),
+            Err(e) => e,
+        };
+        let units =
+            json_string_units(payload, "content").ok_or(ParseError::PayloadDeserialize(error))?;
+        let mut joiner = TextJoiner::default();
+        let mut content = joiner.push(&units);
+        content.push_str(&joiner.finish());
+        Ok(Self {
+            content,
+            units: Some(units),
+            ..Default::default()
+        })
+    }
+
+    /// 通过 `joiner` 取出文本：与上一个事件末尾未完成的字符拼接，本事件末尾未完成的字符暂存
+    pub fn join(&self, joiner: &mut TextJoiner) -> String {
+        match &self.units {
+            Some(units) => joiner.push(units),
+            None => joiner.push_str(&self.content),
+        }
+    }
 }
 
 impl EventPayload for AssistantResponseEvent {
     fn from_frame(frame: &Frame) -> ParseResult<Self> {
-        frame.payload_as_json()
+        Self::from_payload(&frame.payload)
     }
 }
 
@@ -52,6 +85,7 @@ impl Default for AssistantResponseEvent {
         Self {
             content: String::new(),
             extra: serde_json::Value::Null,
+            units: None,
         }
     }
 }
@@ -104,6 +138,26 @@ mod tests {
         assert!(!json.contains("extra"));
     }
 
+    #[test]
+    fn test_split_characters_are_joined() {
+        // "你🎉" 被按字节和按 UTF-16 代理对拆到相邻事件
+        let first = AssistantResponseEvent::from_payload(b"{\"content\":\"\xe4\xbd\"}").unwrap();
+        let second =
+            AssistantResponseEvent::from_payload(b"{\"content\":\"\xa0\\ud83c\"}").unwrap();
+        let third = AssistantResponseEvent::from_payload(br#"{"content":"\udf89ok"}"#).unwrap();
+        assert_eq!(first.content, "\u{FFFD}");
+
+        let mut joiner = TextJoiner::default();
+        let text: String = [first, second, third]
+            .iter()
+            .map(|event| event.join(&mut joiner))
+            .collect();
+        assert_eq!(text, "你🎉ok");
+        assert_eq!(joiner.finish(), "");
+
+        assert!(AssistantResponseEvent::from_payload(b"not json").is_err());
+    }
+
     #[test]
     fn test_display() {
         let event = AssistantResponseEvent {
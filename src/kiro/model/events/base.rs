@@ -71,8 +71,15 @@ pub enum Event {
     Metering(()),
     /// 上下文使用率
     ContextUsage(super::ContextUsageEvent),
+    /// 文件/产物
+    Artifact(super::ArtifactEvent),
     /// 未知事件 (保留原始帧数据)
-    Unknown {},
+    Unknown {
+        /// 事件类型
+        event_type: String,
+        /// 原始负载
+        payload: Vec<u8>,
+    },
     /// 服务端错误
     Error {
         /// 错误代码
@@ -121,7 +128,15 @@ impl Event {
                 let payload = super::ContextUsageEvent::from_frame(&frame)?;
                 Ok(Self::ContextUsage(payload))
             }
-            EventType::Unknown => Ok(Self::Unknown {}),
+            EventType::Unknown => {
+                match super::ArtifactEvent::detect(event_type_str, &frame.payload) {
+                    Some(artifact) => Ok(Self::Artifact(artifact)),
+                    None => Ok(Self::Unknown {
+                        event_type: event_type_str.to_string(),
+                        payload: frame.payload,
+                    }),
+                }
+            }
         }
     }
 
@@ -2,11 +2,13 @@
 //!
 //! 定义 generateAssistantResponse 流式响应的事件类型
 
+mod artifact;
 mod assistant;
 mod base;
 mod context_usage;
 mod tool_use;
 
+pub use artifact::ArtifactEvent;
 pub use assistant::AssistantResponseEvent;
 pub use base::Event;
 pub use context_usage::ContextUsageEvent;
@@ -0,0 +1,309 @@
+//! MCP JSON-RPC 类型定义
+//!
+//! Kiro `/mcp` 端点使用 JSON-RPC 2.0：
+//! - 请求带 `id` 时上游必须返回相同 `id` 的响应（用于关联校验）
+//! - 不带 `id` 的请求为通知（notification），上游不返回结果
+//! - 错误对象按 JSON-RPC 规范的错误码映射为 `JsonRpcErrorKind`
+
+use std::fmt;
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+/// JSON-RPC 协议版本
+pub const JSONRPC_VERSION: &str = "2.0";
+
+/// 请求 ID（规范允许字符串或数字）
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum JsonRpcId {
+    Number(i64),
+    String(String),
+}
+
+impl fmt::Display for JsonRpcId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Number(n) => write!(f, "{}", n),
+            Self::String(s) => write!(f, "{}", s),
+        }
+    }
+}
+
+/// JSON-RPC 请求
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JsonRpcRequest<P = serde_json::Value> {
+    pub jsonrpc: String,
+    /// None 表示通知
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<JsonRpcId>,
+    pub method: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub params: Option<P>,
+}
+
+impl<P> JsonRpcRequest<P> {
+    /// 创建需要响应的请求
+    pub fn new(id: JsonRpcId, method: impl Into<String>, params: P) -> Self {
+        Self {
+            jsonrpc: JSONRPC_VERSION.to_string(),
+            id: Some(id),
+            method: method.into(),
+            params: Some(params),
+        }
+    }
+
+    /// 创建通知（不带 id，上游不返回结果）
+    #[allow(dead_code)]
+    pub fn notification(method: impl Into<String>, params: Option<P>) -> Self {
+        Self {
+            jsonrpc: JSONRPC_VERSION.to_string(),
+            id: None,
+            method: method.into(),
+            params,
+        }
+    }
+
+    /// 是否为通知
+    pub fn is_notification(&self) -> bool {
+        self.id.is_none()
+    }
+}
+
+/// JSON-RPC 错误对象
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct JsonRpcError {
+    pub code: i64,
+    #[serde(default)]
+    pub message: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data: Option<serde_json::Value>,
+}
+
+/// JSON-RPC 错误分类
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JsonRpcErrorKind {
+    /// -32700 无法解析的 JSON
+    ParseError,
+    /// -32600 无效请求
+    InvalidRequest,
+    /// -32601 方法不存在
+    MethodNotFound,
+    /// -32602 参数无效
+    InvalidParams,
+    /// -32603 内部错误
+    InternalError,
+    /// -32000 ~ -32099 服务端实现定义的错误
+    ServerError,
+    /// 其他应用层错误码
+    Application,
+}
+
+impl JsonRpcError {
+    /// 按规范错误码分类
+    pub fn kind(&self) -> JsonRpcErrorKind {
+        match self.code {
+            -32700 => JsonRpcErrorKind::ParseError,
+            -32600 => JsonRpcErrorKind::InvalidRequest,
+            -32601 => JsonRpcErrorKind::MethodNotFound,
+            -32602 => JsonRpcErrorKind::InvalidParams,
+            -32603 => JsonRpcErrorKind::InternalError,
+            -32099..=-32000 => JsonRpcErrorKind::ServerError,
+            _ => JsonRpcErrorKind::Application,
+        }
+    }
+}
+
+impl fmt::Display for JsonRpcError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?} ({}): {}", self.kind(), self.code, self.message)
+    }
+}
+
+/// JSON-RPC 响应
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JsonRpcResponse<R = serde_json::Value> {
+    #[serde(default)]
+    pub jsonrpc: String,
+    #[serde(default)]
+    pub id: Option<JsonRpcId>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub result: Option<R>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<JsonRpcError>,
+}
+
+/// MCP 调用错误
+#[derive(Debug)]
+pub enum McpCallError {
+    /// 上游返回 JSON-RPC 错误对象
+    Rpc(JsonRpcError),
+    /// 响应 id 与请求不一致
+    IdMismatch {
+        expected: JsonRpcId,
+        actual: Option<JsonRpcId>,
+    },
+    /// 响应既没有 result 也没有 error
+    MissingResult,
+    /// 响应体不是合法的 JSON-RPC 响应
+    InvalidResponse(serde_json::Error),
+}
+
+impl fmt::Display for McpCallError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Rpc(e) => write!(f, "MCP error: {}", e),
+            Self::IdMismatch { expected, actual } => match actual {
+                Some(actual) => write!(f, "MCP 响应 id 不匹配: 期望 {}，实际 {}", expected, actual),
+                None => write!(f, "MCP 响应缺少 id: 期望 {}", expected),
+            },
+            Self::MissingResult => write!(f, "MCP 响应缺少 result"),
+            Self::InvalidResponse(e) => write!(f, "MCP 响应解析失败: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for McpCallError {}
+
+impl<R: DeserializeOwned> JsonRpcResponse<R> {
+    /// 解析响应体
+    pub fn parse(body: &str) -> Result<Self, McpCallError> {
+        serde_json::from_str(body).map_err(McpCallError::InvalidResponse)
+    }
+
+    /// 校验 id 关联并取出结果
+    ///
+    /// 错误对象优先于 id 校验：部分错误（如解析失败）按规范 id 为 null
+    pub fn into_result(self, expected_id: &JsonRpcId) -> Result<R, McpCallError> {
+        if let Some(error) = self.error {
+            return Err(McpCallError::Rpc(error));
+        }
+        if self.id.as_ref() != Some(expected_id) {
+            return Err(McpCallError::IdMismatch {
+                expected: expected_id.clone(),
+                actual: self.id,
+            });
+        }
+        self.result.ok_or(McpCallError::MissingResult)
+    }
+}
+
+/// `tools/call` 请求参数
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct McpToolCallParams<A = serde_json::Value> {
+    pub name: String,
+    pub arguments: A,
+}
+
+/// `tools/call` 结果
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct McpToolResult {
+    #[serde(default)]
+    pub content: Vec<McpContent>,
+    #[serde(rename = "isError", default)]
+    pub is_error: bool,
+}
+
+/// MCP 内容
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct McpContent {
+    #[serde(rename = "type")]
+    pub content_type: String,
+    #[serde(default)]
+    pub text: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn response(body: serde_json::Value) -> JsonRpcResponse<McpToolResult> {
+        JsonRpcResponse::parse(&body.to_string()).unwrap()
+    }
+
+    #[test]
+    fn test_request_and_notification_serialization() {
+        let request = JsonRpcRequest::new(
+            JsonRpcId::String("req-1".to_string()),
+            "tools/call",
+            McpToolCallParams {
+                name: "web_search".to_string(),
+                arguments: json!({"query": "rust"}),
+            },
+        );
+        let value = serde_json::to_value(&request).unwrap();
+        assert_eq!(value["jsonrpc"], "2.0");
+        assert_eq!(value["id"], "req-1");
+        assert_eq!(value["params"]["arguments"]["query"], "rust");
+
+        let notification: JsonRpcRequest =
+            JsonRpcRequest::notification("notifications/initialized", None);
+        assert!(notification.is_notification());
+        let value = serde_json::to_value(&notification).unwrap();
+        assert!(value.get("id").is_none());
+        assert!(value.get("params").is_none());
+    }
+
+    #[test]
+    fn test_into_result_correlates_id() {
+        let id = JsonRpcId::Number(7);
+        let ok = response(
+            json!({"jsonrpc": "2.0", "id": 7, "result": {"content": [{"type": "text", "text": "hi"}]}}),
+        );
+        let result = ok.into_result(&id).unwrap();
+        assert_eq!(result.content[0].text, "hi");
+        assert!(!result.is_error);
+
+        let mismatched = response(json!({"jsonrpc": "2.0", "id": 8, "result": {"content": []}}));
+        assert!(matches!(
+            mismatched.into_result(&id),
+            Err(McpCallError::IdMismatch {
+                actual: Some(JsonRpcId::Number(8)),
+                ..
+            })
+        ));
+
+        let empty = response(json!({"jsonrpc": "2.0", "id": 7}));
+        assert!(matches!(
+            empty.into_result(&id),
+            Err(McpCallError::MissingResult)
+        ));
+    }
+
+    #[test]
+    fn test_error_object_mapping() {
+        let id = JsonRpcId::String("x".to_string());
+        let err = response(
+            json!({"jsonrpc": "2.0", "id": null, "error": {"code": -32601, "message": "no such method"}}),
+        );
+        match err.into_result(&id) {
+            Err(McpCallError::Rpc(e)) => {
+                assert_eq!(e.kind(), JsonRpcErrorKind::MethodNotFound);
+                assert_eq!(e.message, "no such method");
+            }
+            other => panic!("unexpected: {:?}", other),
+        }
+
+        let server = JsonRpcError {
+            code: -32050,
+            message: String::new(),
+            data: None,
+        };
+        assert_eq!(server.kind(), JsonRpcErrorKind::ServerError);
+        let app = JsonRpcError {
+            code: 429,
+            message: String::new(),
+            data: None,
+        };
+        assert_eq!(app.kind(), JsonRpcErrorKind::Application);
+    }
+
+    #[test]
+    fn test_invalid_response() {
+        assert!(matches!(
+            JsonRpcResponse::<McpToolResult>::parse("not json"),
+            Err(McpCallError::InvalidResponse(_))
+        ));
+    }
+}
@@ -4,13 +4,17 @@
 //! - `common`: 共享类型（枚举和辅助结构体）
 //! - `events`: 响应事件类型
 //! - `requests`: 请求类型
+//! - `mcp`: MCP JSON-RPC 请求/响应
 //! - `credentials`: OAuth 凭证
+//! - `device_auth`: 设备码授权
 //! - `token_refresh`: Token 刷新
 //! - `usage_limits`: 使用额度查询
 
 pub mod common;
 pub mod credentials;
+pub mod device_auth;
 pub mod events;
+pub mod mcp;
 pub mod requests;
 pub mod token_refresh;
 pub mod usage_limits;
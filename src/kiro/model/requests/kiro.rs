@@ -4,7 +4,11 @@
 
 use serde::{Deserialize, Serialize};
 
-use super::conversation::ConversationState;
+use super::conversation::{
+    ConversationState, CurrentMessage, KiroImage, Message, UserInputMessage,
+    UserInputMessageContext,
+};
+use super::tool::{Tool, ToolResult};
 
 /// Kiro API 请求
 ///
@@ -13,18 +17,24 @@ use super::conversation::ConversationState;
 /// # 示例
 ///
 /// ```rust
-/// use kiro_rs::kiro::model::requests::{
-///     KiroRequest, ConversationState, CurrentMessage, UserInputMessage, Tool
+/// use kiro_rs::kiro::model::requests::kiro::KiroRequest;
+/// use kiro_rs::kiro::model::requests::conversation::{
+///     ConversationState, CurrentMessage, UserInputMessage,
 /// };
 ///
-/// // 创建简单请求
+/// // 手动组装对话状态
 /// let state = ConversationState::new("conv-123")
 ///     .with_agent_task_type("vibe")
 ///     .with_current_message(CurrentMessage::new(
 ///         UserInputMessage::new("Hello", "claude-3-5-sonnet")
 ///     ));
-///
 /// let request = KiroRequest::new(state);
+///
+/// // 或使用构建器（自动填充会话 ID、agentTaskType 等默认值）
+/// let request = KiroRequest::builder("claude-sonnet-4.5")
+///     .with_content("Hello")
+///     .build()
+///     .unwrap();
 /// let json = request.to_json().unwrap();
 /// ```
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -36,6 +46,163 @@ pub struct KiroRequest {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub profile_arn: Option<String>,
 }
+
+/// generateAssistantResponse 请求（与上游接口同名的别名）
+pub type AssistantRequest = KiroRequest;
+
+impl KiroRequest {
+    /// 从对话状态创建请求
+    pub fn new(conversation_state: ConversationState) -> Self {
+        Self {
+            conversation_state,
+            profile_arn: None,
+        }
+    }
+
+    /// 设置 Profile ARN
+    pub fn with_profile_arn(mut self, arn: impl Into<String>) -> Self {
+        self.profile_arn = Some(arn.into());
+        self
+    }
+
+    /// 创建请求构建器
+    pub fn builder(model_id: impl Into<String>) -> AssistantRequestBuilder {
+        AssistantRequestBuilder::new(model_id)
+    }
+
+    /// 序列化为请求体
+    pub fn to_json(&self) -> serde_json::Result<String> {
+        serde_json::to_string(self)
+    }
+}
+
+/// generateAssistantResponse 请求构建器
+///
+/// 默认值与 Anthropic 转换路径一致：随机会话 ID、`agentTaskType: "vibe"`、
+/// `chatTriggerType: "MANUAL"`、`origin: "AI_EDITOR"`
+#[derive(Debug, Clone)]
+pub struct AssistantRequestBuilder {
+    model_id: String,
+    conversation_id: Option<String>,
+    agent_continuation_id: Option<String>,
+    chat_trigger_type: String,
+    content: String,
+    images: Vec<KiroImage>,
+    tools: Vec<Tool>,
+    tool_results: Vec<ToolResult>,
+    history: Vec<Message>,
+    profile_arn: Option<String>,
+}
+
+impl AssistantRequestBuilder {
+    fn new(model_id: impl Into<String>) -> Self {
+        Self {
+            model_id: model_id.into(),
+            conversation_id: None,
+            agent_continuation_id: None,
+            chat_trigger_type: "MANUAL".to_string(),
+            content: String::new(),
+            images: Vec::new(),
+            tools: Vec::new(),
+            tool_results: Vec::new(),
+            history: Vec::new(),
+            profile_arn: None,
+        }
+    }
+
+    /// 设置会话 ID（默认随机生成）
+    pub fn with_conversation_id(mut self, id: impl Into<String>) -> Self {
+        self.conversation_id = Some(id.into());
+        self
+    }
+
+    /// 设置代理延续 ID（默认随机生成）
+    pub fn with_agent_continuation_id(mut self, id: impl Into<String>) -> Self {
+        self.agent_continuation_id = Some(id.into());
+        self
+    }
+
+    /// 设置聊天触发类型
+    pub fn with_chat_trigger_type(mut self, trigger_type: impl Into<String>) -> Self {
+        self.chat_trigger_type = trigger_type.into();
+        self
+    }
+
+    /// 设置当前消息内容
+    pub fn with_content(mut self, content: impl Into<String>) -> Self {
+        self.content = content.into();
+        self
+    }
+
+    /// 添加图片
+    pub fn with_image(mut self, image: KiroImage) -> Self {
+        self.images.push(image);
+        self
+    }
+
+    /// 添加工具定义
+    pub fn with_tool(mut self, tool: Tool) -> Self {
+        self.tools.push(tool);
+        self
+    }
+
+    /// 添加工具执行结果
+    pub fn with_tool_result(mut self, result: ToolResult) -> Self {
+        self.tool_results.push(result);
+        self
+    }
+
+    /// 追加历史消息
+    pub fn with_history_message(mut self, message: Message) -> Self {
+        self.history.push(message);
+        self
+    }
+
+    /// 设置 Profile ARN
+    pub fn with_profile_arn(mut self, arn: impl Into<String>) -> Self {
+        self.profile_arn = Some(arn.into());
+        self
+    }
+
+    /// 构建请求
+    ///
+    /// 模型 ID 不能为空；当前消息必须包含文本、图片或工具结果之一（否则上游返回 400）
+    pub fn build(self) -> anyhow::Result<KiroRequest> {
+        if self.model_id.trim().is_empty() {
+            anyhow::bail!("model_id 不能为空");
+        }
+        if self.content.trim().is_empty() && self.images.is_empty() && self.tool_results.is_empty()
+        {
+            anyhow::bail!("当前消息缺少内容：需要文本、图片或工具结果");
+        }
+
+        let context = UserInputMessageContext::new()
+            .with_tools(self.tools)
+            .with_tool_results(self.tool_results);
+        let user_input = UserInputMessage::new(self.content, self.model_id)
+            .with_context(context)
+            .with_images(self.images);
+
+        let conversation_id = self
+            .conversation_id
+            .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+        let agent_continuation_id = self
+            .agent_continuation_id
+            .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+        let state = ConversationState::new(conversation_id)
+            .with_agent_continuation_id(agent_continuation_id)
+            .with_agent_task_type("vibe")
+            .with_chat_trigger_type(self.chat_trigger_type)
+            .with_current_message(CurrentMessage::new(user_input))
+            .with_history(self.history);
+
+        Ok(KiroRequest {
+            conversation_state: state,
+            profile_arn: self.profile_arn,
+        })
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -65,4 +232,49 @@ mod tests {
             "Test message"
         );
     }
+
+    #[test]
+    fn test_builder_serializes_expected_payload() {
+        let request = KiroRequest::builder("claude-sonnet-4.5")
+            .with_conversation_id("conv-1")
+            .with_content("Hi")
+            .with_tool(Tool::new(
+                "lookup",
+                "Look up",
+                serde_json::json!({"type": "object"}),
+            ))
+            .with_history_message(Message::user("earlier", "claude-sonnet-4.5"))
+            .with_history_message(Message::assistant("ok"))
+            .with_profile_arn("arn:aws:test")
+            .build()
+            .unwrap();
+
+        let json: serde_json::Value = serde_json::from_str(&request.to_json().unwrap()).unwrap();
+        let state = &json["conversationState"];
+        assert_eq!(state["conversationId"], "conv-1");
+        assert_eq!(state["agentTaskType"], "vibe");
+        assert_eq!(state["chatTriggerType"], "MANUAL");
+        assert_eq!(state["history"].as_array().unwrap().len(), 2);
+        let message = &state["currentMessage"]["userInputMessage"];
+        assert_eq!(message["content"], "Hi");
+        assert_eq!(message["modelId"], "claude-sonnet-4.5");
+        assert_eq!(message["origin"], "AI_EDITOR");
+        assert_eq!(
+            message["userInputMessageContext"]["tools"][0]["toolSpecification"]["name"],
+            "lookup"
+        );
+        assert_eq!(json["profileArn"], "arn:aws:test");
+    }
+
+    #[test]
+    fn test_builder_rejects_empty_message() {
+        assert!(KiroRequest::builder("m").build().is_err());
+        assert!(KiroRequest::builder("").with_content("x").build().is_err());
+        assert!(
+            KiroRequest::builder("m")
+                .with_tool_result(ToolResult::success("t1", "done"))
+                .build()
+                .is_ok()
+        );
+    }
 }
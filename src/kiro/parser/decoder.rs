@@ -31,7 +31,7 @@
 //! ```
 
 use super::error::{ParseError, ParseResult};
-use super::frame::{Frame, PRELUDE_SIZE, parse_frame};
+use super::frame::{Frame, PRELUDE_SIZE, find_prelude, parse_frame, record_resync};
 use bytes::{Buf, BytesMut};
 
 /// 默认最大缓冲区大小 (16 MB)
@@ -236,7 +236,7 @@ impl EventStreamDecoder {
     /// 尝试容错恢复
     ///
     /// 根据错误类型采用不同的恢复策略（参考 kiro-kt 的设计）：
-    /// - Prelude 阶段错误（CRC 失败、长度异常）：跳过 1 字节，尝试找下一帧边界
+    /// - Prelude 阶段错误（CRC 失败、长度异常）：跳到下一个有效 Prelude 重新同步
     /// - Data 阶段错误（Message CRC 失败、Header 解析失败）：跳过整个损坏帧
     fn try_recover(&mut self, error: &ParseError) {
         if self.buffer.is_empty() {
@@ -244,16 +244,23 @@ impl EventStreamDecoder {
         }
 
         match error {
-            // Prelude 阶段错误：可能是帧边界错位，逐字节扫描找下一个有效边界
+            // Prelude 阶段错误：可能是帧边界错位，跳到下一个有效 Prelude；
+            // 暂未找到时只保留末尾可能是 Prelude 开头的字节
             ParseError::PreludeCrcMismatch { .. }
             | ParseError::MessageTooSmall { .. }
             | ParseError::MessageTooLarge { .. } => {
-                let skipped_byte = self.buffer[0];
-                self.buffer.advance(1);
-                self.bytes_skipped += 1;
+                let skip = match find_prelude(&self.buffer[1..]) {
+                    Some(offset) => {
+                        record_resync();
+                        offset + 1
+                    }
+                    None => self.buffer.len().saturating_sub(PRELUDE_SIZE - 1).max(1),
+                };
+                self.buffer.advance(skip);
+                self.bytes_skipped += skip;
                 tracing::warn!(
-                    "Prelude 错误恢复: 跳过字节 0x{:02x} (累计跳过 {} 字节)",
-                    skipped_byte,
+                    "Prelude 错误恢复: 跳过 {} 字节 (累计跳过 {} 字节)",
+                    skip,
                     self.bytes_skipped
                 );
             }
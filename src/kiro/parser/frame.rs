@@ -16,6 +16,8 @@
 //! - Payload: 载荷数据（通常是 JSON）
 //! - Message CRC: 整个消息（不含 Message CRC 自身）的 CRC32 校验
 
+use std::sync::atomic::{AtomicU64, Ordering};
+
 use super::crc::crc32;
 use super::error::{ParseError, ParseResult};
 use super::header::{Headers, parse_headers};
@@ -29,6 +31,9 @@ pub const MIN_MESSAGE_SIZE: usize = PRELUDE_SIZE + 4;
 /// 最大消息大小限制 (16 MB)
 pub const MAX_MESSAGE_SIZE: u32 = 16 * 1024 * 1024;
 
+/// 进程启动以来在损坏数据后重新同步的次数
+static RESYNCS: AtomicU64 = AtomicU64::new(0);
+
 /// 解析后的消息帧
 #[derive(Debug, Clone)]
 pub struct Frame {
@@ -73,6 +78,39 @@ impl Frame {
 /// - `Ok(None)` - 数据不足，需要更多数据
 /// - `Err(e)` - 解析错误
 pub fn parse_frame(buffer: &[u8]) -> ParseResult<Option<(Frame, usize)>> {
+    let Some(total_length) = check_frame(buffer)? else {
+        return Ok(None);
+    };
+    let header_length = u32::from_be_bytes([buffer[4], buffer[5], buffer[6], buffer[7]]) as usize;
+
+    // 解析头部
+    let headers_start = PRELUDE_SIZE;
+    let headers_end = headers_start + header_length;
+
+    // 验证头部边界
+    if headers_end > total_length - 4 {
+        return Err(ParseError::HeaderParseFailed(
+            "头部长度超出消息边界".to_string(),
+        ));
+    }
+
+    let headers = parse_headers(&buffer[headers_start..headers_end], header_length)?;
+
+    // 提取 payload (去除最后4字节的 message_crc)
+    let payload_start = headers_end;
+    let payload_end = total_length - 4;
+    let payload = buffer[payload_start..payload_end].to_vec();
+
+    Ok(Some((Frame { headers, payload }, total_length)))
+}
+
+/// 校验缓冲区起始处的帧长度与 Prelude/Message CRC（不解析头部）
+///
+/// # Returns
+/// - `Ok(Some(total_length))` - 完整且校验通过的帧
+/// - `Ok(None)` - 数据不足，需要更多数据
+/// - `Err(e)` - 长度异常或 CRC 校验失败
+pub fn check_frame(buffer: &[u8]) -> ParseResult<Option<usize>> {
     // 检查是否有足够的数据读取 prelude
     if buffer.len() < PRELUDE_SIZE {
         return Ok(None);
@@ -80,7 +118,6 @@ pub fn parse_frame(buffer: &[u8]) -> ParseResult<Option<(Frame, usize)>> {
 
     // 读取 prelude
     let total_length = u32::from_be_bytes([buffer[0], buffer[1], buffer[2], buffer[3]]);
-    let header_length = u32::from_be_bytes([buffer[4], buffer[5], buffer[6], buffer[7]]);
     let prelude_crc = u32::from_be_bytes([buffer[8], buffer[9], buffer[10], buffer[11]]);
 
     // 验证消息长度范围
@@ -98,15 +135,7 @@ pub fn parse_frame(buffer: &[u8]) -> ParseResult<Option<(Frame, usize)>> {
         });
     }
 
-    let total_length = total_length as usize;
-    let header_length = header_length as usize;
-
-    // 检查是否有完整的消息
-    if buffer.len() < total_length {
-        return Ok(None);
-    }
-
-    // 验证 Prelude CRC
+    // 验证 Prelude CRC（不等待完整消息，帧边界错位时尽早发现）
     let actual_prelude_crc = crc32(&buffer[..8]);
     if actual_prelude_crc != prelude_crc {
         return Err(ParseError::PreludeCrcMismatch {
@@ -115,6 +144,13 @@ pub fn parse_frame(buffer: &[u8]) -> ParseResult<Option<(Frame, usize)>> {
         });
     }
 
+    let total_length = total_length as usize;
+
+    // 检查是否有完整的消息
+    if buffer.len() < total_length {
+        return Ok(None);
+    }
+
     // 读取 Message CRC
     let message_crc = u32::from_be_bytes([
         buffer[total_length - 4],
@@ -132,25 +168,43 @@ pub fn parse_frame(buffer: &[u8]) -> ParseResult<Option<(Frame, usize)>> {
         });
     }
 
-    // 解析头部
-    let headers_start = PRELUDE_SIZE;
-    let headers_end = headers_start + header_length;
+    Ok(Some(total_length))
+}
 
-    // 验证头部边界
-    if headers_end > total_length - 4 {
-        return Err(ParseError::HeaderParseFailed(
-            "头部长度超出消息边界".to_string(),
-        ));
+/// 缓冲区开头连续的完整且校验通过的帧的总字节数（末尾不完整的帧不计入），
+/// 以及这些帧之后遇到的校验错误
+pub fn verified_len(buffer: &[u8]) -> (usize, Option<ParseError>) {
+    let mut offset = 0;
+    loop {
+        match check_frame(&buffer[offset..]) {
+            Ok(Some(length)) => offset += length,
+            Ok(None) => return (offset, None),
+            Err(e) => return (offset, Some(e)),
+        }
     }
+}
 
-    let headers = parse_headers(&buffer[headers_start..headers_end], header_length)?;
+/// 缓冲区中第一个有效 Prelude（长度在合法范围内且 Prelude CRC 校验通过）的偏移
+///
+/// 用于在损坏的数据之后寻找下一帧的边界；末尾不足 12 字节的部分不参与查找。
+pub fn find_prelude(buffer: &[u8]) -> Option<usize> {
+    (0..=buffer.len().checked_sub(PRELUDE_SIZE)?).find(|&offset| {
+        let prelude = &buffer[offset..offset + PRELUDE_SIZE];
+        let total_length = u32::from_be_bytes([prelude[0], prelude[1], prelude[2], prelude[3]]);
+        let prelude_crc = u32::from_be_bytes([prelude[8], prelude[9], prelude[10], prelude[11]]);
+        (MIN_MESSAGE_SIZE as u32..=MAX_MESSAGE_SIZE).contains(&total_length)
+            && crc32(&prelude[..8]) == prelude_crc
+    })
+}
 
-    // 提取 payload (去除最后4字节的 message_crc)
-    let payload_start = headers_end;
-    let payload_end = total_length - 4;
-    let payload = buffer[payload_start..payload_end].to_vec();
+/// 记录一次重新同步
+pub fn record_resync() {
+    RESYNCS.fetch_add(1, Ordering::Relaxed);
+}
 
-    Ok(Some((Frame { headers, payload }, total_length)))
+/// 进程启动以来的重新同步次数
+pub fn resync_count() -> u64 {
+    RESYNCS.load(Ordering::Relaxed)
 }
 
 #[cfg(test)]
@@ -175,4 +229,20 @@ mod tests {
         let result = parse_frame(&buffer);
         assert!(matches!(result, Err(ParseError::MessageTooSmall { .. })));
     }
+
+    #[test]
+    fn test_find_prelude_skips_garbage() {
+        let mut frame = vec![0u8; 16];
+        frame[0..4].copy_from_slice(&16u32.to_be_bytes());
+        let prelude_crc = crc32(&frame[0..8]);
+        frame[8..12].copy_from_slice(&prelude_crc.to_be_bytes());
+        let message_crc = crc32(&frame[0..12]);
+        frame[12..16].copy_from_slice(&message_crc.to_be_bytes());
+
+        let mut buffer = b"garbage".to_vec();
+        buffer.extend_from_slice(&frame);
+        assert_eq!(find_prelude(&buffer), Some(7));
+        assert_eq!(find_prelude(&buffer[..7 + PRELUDE_SIZE - 1]), None);
+        assert_eq!(find_prelude(b"garbage"), None);
+    }
 }
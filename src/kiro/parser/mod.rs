@@ -8,3 +8,4 @@ pub mod decoder;
 pub mod error;
 pub mod frame;
 pub mod header;
+pub mod text;
@@ -0,0 +1,328 @@
+//! 跨事件的文本拼接
+//!
+//! 上游偶尔会把一个多字节字符拆到两个 assistantResponseEvent 中：按字节切分时 payload 不是合法的
+//! UTF-8，按 UTF-16 切分时 `\uD83C` 与 `\uDF89` 这样的代理对分属两个事件。两种情况下 JSON 都无法
+//! 直接反序列化，过去整条事件被丢弃或显示为乱码。这里把无法单独解码的部分保留为码元，由
+//! `TextJoiner` 与下一个事件的开头拼接成完整字符，保证输出的每个文本片段都以完整字符结束。
+
+use std::char::REPLACEMENT_CHARACTER;
+
+/// 文本码元
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Unit {
+    /// 完整字符
+    Char(char),
+    /// 不构成完整字符的 UTF-8 字节
+    Byte(u8),
+    /// 未配对的 UTF-16 代理项（来自 `\u` 转义）
+    Surrogate(u16),
+}
+
+/// 从 JSON payload 中宽松地提取字符串字段
+///
+/// 用于标准反序列化失败时的回退：非法 UTF-8 字节保留为 `Unit::Byte`，代理项转义保留为
+/// `Unit::Surrogate`。找不到字段或字符串未闭合时返回 None
+pub fn json_string_units(payload: &[u8], field: &str) -> Option<Vec<Unit>> {
+    let key = format!("\"{}\"", field);
+    let mut search = 0;
+    while let Some(pos) = find(&payload[search..], key.as_bytes()) {
+        let after_key = search + pos + key.len();
+        search = after_key;
+        let rest = skip_whitespace(&payload[after_key..]);
+        let Some(rest) = rest.strip_prefix(b":") else {
+            continue;
+        };
+        if let Some(rest) = skip_whitespace(rest).strip_prefix(b"\"") {
+            return string_units(rest);
+        }
+    }
+    None
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
+
+fn skip_whitespace(bytes: &[u8]) -> &[u8] {
+    let start = bytes
+        .iter()
+        .position(|b| !b.is_ascii_whitespace())
+        .unwrap_or(bytes.len());
+    &bytes[start..]
+}
+
+/// 解码字符串字面量（从开引号之后到闭引号）
+fn string_units(bytes: &[u8]) -> Option<Vec<Unit>> {
+    let mut units = Vec::new();
+    let mut raw_start = 0;
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'"' => {
+                push_raw(&mut units, &bytes[raw_start..i]);
+                return Some(units);
+            }
+            b'\\' => {
+                push_raw(&mut units, &bytes[raw_start..i]);
+                let escape = *bytes.get(i + 1)?;
+                i += 2;
+                let unit = match escape {
+                    b'"' => Unit::Char('"'),
+                    b'\\' => Unit::Char('\\'),
+                    b'/' => Unit::Char('/'),
+                    b'b' => Unit::Char('\u{8}'),
+                    b'f' => Unit::Char('\u{c}'),
+                    b'n' => Unit::Char('\n'),
+                    b'r' => Unit::Char('\r'),
+                    b't' => Unit::Char('\t'),
+                    b'u' => {
+                        let hex = std::str::from_utf8(bytes.get(i..i + 4)?).ok()?;
+                        let code = u16::from_str_radix(hex, 16).ok()?;
+                        i += 4;
+                        match char::from_u32(code as u32) {
+                            Some(c) => Unit::Char(c),
+                            None => Unit::Surrogate(code),
+                        }
+                    }
+                    _ => return None,
+                };
+                units.push(unit);
+                raw_start = i;
+            }
+            _ => i += 1,
+        }
+    }
+    None
+}
+
+fn push_raw(units: &mut Vec<Unit>, raw: &[u8]) {
+    for chunk in raw.utf8_chunks() {
+        units.extend(chunk.valid().chars().map(Unit::Char));
+        units.extend(chunk.invalid().iter().copied().map(Unit::Byte));
+    }
+}
+
+/// 把依次到达的文本片段拼接为完整字符
+///
+/// 片段末尾未完成的 UTF-8 序列或高代理项会暂存，等下一个片段补全；中间无法补全的部分替换为 U+FFFD
+#[derive(Debug, Default)]
+pub struct TextJoiner {
+    bytes: Vec<u8>,
+    high: Option<u16>,
+}
+
+impl TextJoiner {
+    /// 追加正常解码的文本
+    pub fn push_str(&mut self, text: &str) -> String {
+        if self.bytes.is_empty() && self.high.is_none() {
+            return text.to_string();
+        }
+        let mut out = String::new();
+        self.flush_into(&mut out);
+        out.push_str(text);
+        out
+    }
+
+    /// 追加码元，返回已凑成完整字符的文本
+    pub fn push(&mut self, units: &[Unit]) -> String {
+        let mut out = String::new();
+        for unit in units {
+            match *unit {
+                Unit::Char(c) => {
+                    self.flush_into(&mut out);
+                    out.push(c);
+                }
+                Unit::Byte(b) => self.push_byte(b, &mut out),
+                Unit::Surrogate(s) => self.push_surrogate(s, &mut out),
+            }
+        }
+        out
+    }
+
+    /// 流结束：输出暂存的不完整字符（替换为 U+FFFD）
+    pub fn finish(&mut self) -> String {
+        let mut out = String::new();
+        self.flush_into(&mut out);
+        out
+    }
+
+    fn flush_into(&mut self, out: &mut String) {
+        if !self.bytes.is_empty() {
+            self.bytes.clear();
+            out.push(REPLACEMENT_CHARACTER);
+        }
+        if self.high.take().is_some() {
+            out.push(REPLACEMENT_CHARACTER);
+        }
+    }
+
+    fn push_byte(&mut self, byte: u8, out: &mut String) {
+        if self.high.take().is_some() {
+            out.push(REPLACEMENT_CHARACTER);
+        }
+        self.bytes.push(byte);
+        loop {
+            let error = match std::str::from_utf8(&self.bytes) {
+                Ok(text) => {
+                    out.push_str(text);
+                    self.bytes.clear();
+                    return;
+                }
+                Err(error) => error,
+            };
+            let valid = error.valid_up_to();
+            out.push_str(std::str::from_utf8(&self.bytes[..valid]).unwrap_or_default());
+            match error.error_len() {
+                // 序列尚未完整，等待后续字节
+                None => {
+                    self.bytes.drain(..valid);
+                    return;
+                }
+                Some(len) => {
+                    out.push(REPLACEMENT_CHARACTER);
+                    self.bytes.drain(..valid + len);
+                }
+            }
+        }
+    }
+
+    fn push_surrogate(&mut self, unit: u16, out: &mut String) {
+        if !self.bytes.is_empty() {
+            self.bytes.clear();
+            out.push(REPLACEMENT_CHARACTER);
+        }
+        if (0xD800..0xDC00).contains(&unit) {
+            if self.high.replace(unit).is_some() {
+                out.push(REPLACEMENT_CHARACTER);
+            }
+            return;
+        }
+        let c = self.high.take().and_then(|high| {
+            let code = 0x10000 + (((high - 0xD800) as u32) << 10) + (unit - 0xDC00) as u32;
+            char::from_u32(code)
+        });
+        out.push(c.unwrap_or(REPLACEMENT_CHARACTER));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALPHABET: &[char] = &[
+        'a', 'Z', ' ', '\n', '"', '\\', '中', '文', '日', '本', '語', 'é', '€', '🎉', '😀', '𠀋',
+    ];
+
+    fn random_text(rng: &mut fastrand::Rng) -> String {
+        (0..rng.usize(1..40))
+            .map(|_| ALPHABET[rng.usize(..ALPHABET.len())])
+            .collect()
+    }
+
+    /// 随机切分为若干段（切点可落在任意位置）
+    fn split<T: Clone>(rng: &mut fastrand::Rng, items: &[T]) -> Vec<Vec<T>> {
+        let mut cuts: Vec<usize> = (0..rng.usize(0..6))
+            .map(|_| rng.usize(0..=items.len()))
+            .collect();
+        cuts.push(0);
+        cuts.push(items.len());
+        cuts.sort();
+        cuts.windows(2)
+            .map(|w| items[w[0]..w[1]].to_vec())
+            .collect()
+    }
+
+    fn join(payloads: &[Vec<u8>]) -> String {
+        let mut joiner = TextJoiner::default();
+        let mut text = String::new();
+        for payload in payloads {
+            let units = json_string_units(payload, "content").expect("无法提取 content");
+            text.push_str(&joiner.push(&units));
+        }
+        text.push_str(&joiner.finish());
+        text
+    }
+
+    fn payload(content: &[u8]) -> Vec<u8> {
+        [
+            br#"{"content": ""#.as_slice(),
+            content,
+            br#"","modelId":"x"}"#,
+        ]
+        .concat()
+    }
+
+    /// 按 JSON 规则转义（只涉及 ASCII，按字节处理不会破坏多字节序列）
+    fn escape_bytes(bytes: &[u8]) -> Vec<u8> {
+        let mut out = Vec::new();
+        for &b in bytes {
+            match b {
+                b'"' => out.extend_from_slice(b"\\\""),
+                b'\\' => out.extend_from_slice(b"\\\\"),
+                b'\n' => out.extend_from_slice(b"\\n"),
+                _ => out.push(b),
+            }
+        }
+        out
+    }
+
+    #[test]
+    fn test_fuzz_utf8_split_across_events() {
+        let mut rng = fastrand::Rng::with_seed(465);
+        for _ in 0..2000 {
+            let text = random_text(&mut rng);
+            let payloads: Vec<Vec<u8>> = split(&mut rng, text.as_bytes())
+                .iter()
+                .map(|piece| payload(&escape_bytes(piece)))
+                .collect();
+            assert_eq!(join(&payloads), text);
+        }
+    }
+
+    #[test]
+    fn test_fuzz_surrogates_split_across_events() {
+        let mut rng = fastrand::Rng::with_seed(4650);
+        for _ in 0..2000 {
+            let text = random_text(&mut rng);
+            let utf16: Vec<u16> = text.encode_utf16().collect();
+            let payloads: Vec<Vec<u8>> = split(&mut rng, &utf16)
+                .iter()
+                .map(|piece| {
+                    let escaped: String = piece.iter().map(|u| format!("\\u{:04x}", u)).collect();
+                    payload(escaped.as_bytes())
+                })
+                .collect();
+            assert_eq!(join(&payloads), text);
+        }
+    }
+
+    #[test]
+    fn test_fuzz_garbage_never_panics() {
+        let mut rng = fastrand::Rng::with_seed(46500);
+        for _ in 0..2000 {
+            let bytes: Vec<u8> = (0..rng.usize(0..64)).map(|_| rng.u8(..)).collect();
+            let mut joiner = TextJoiner::default();
+            if let Some(units) = json_string_units(&payload(&bytes), "content") {
+                joiner.push(&units);
+            }
+            json_string_units(&bytes, "content");
+            joiner.finish();
+        }
+    }
+
+    #[test]
+    fn test_unrecoverable_sequences_are_replaced() {
+        let mut joiner = TextJoiner::default();
+        // 高代理项后跟普通字符、孤立的续字节
+        assert_eq!(
+            joiner.push(&[Unit::Surrogate(0xD83C), Unit::Char('a'), Unit::Byte(0x80)]),
+            "\u{FFFD}a\u{FFFD}"
+        );
+        // 不完整的序列在流结束时替换
+        assert_eq!(joiner.push(&[Unit::Byte(0xE4), Unit::Byte(0xBD)]), "");
+        assert_eq!(joiner.push_str("b"), "\u{FFFD}b");
+        assert_eq!(joiner.push(&[Unit::Byte(0xF0)]), "");
+        assert_eq!(joiner.finish(), "\u{FFFD}");
+        assert_eq!(joiner.finish(), "");
+    }
+}
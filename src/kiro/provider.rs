@@ -5,48 +5,397 @@
 //! 支持多凭据故障转移和重试
 
 use reqwest::Client;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
 use reqwest::header::{AUTHORIZATION, CONNECTION, CONTENT_TYPE, HOST, HeaderMap, HeaderValue};
+use std::fmt;
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use tokio::time::sleep;
 use uuid::Uuid;
 
-use crate::http_client::{ProxyConfig, build_client};
+use crate::common::load::LoadTracker;
+use crate::common::log_content::{UpstreamError, clip};
+use crate::common::privacy::Capabilities;
+use crate::http_client::{ProxyConfig, client_builder};
+use crate::kiro::attempt_timing::{self, AttemptTimer};
+use crate::kiro::aws_error::{AwsError, ThrottleKind};
+use crate::kiro::header_audit::{self, HeaderAuditReference};
+use crate::kiro::interceptor::{AttemptInfo, CallKind, Interceptor};
 use crate::kiro::machine_id;
-use crate::kiro::token_manager::{CallContext, MultiTokenManager};
-
-#[cfg(test)]
 use crate::kiro::model::credentials::KiroCredentials;
+use crate::kiro::model::mcp::{JsonRpcRequest, JsonRpcResponse};
+use crate::kiro::model::requests::kiro::KiroRequest;
+use crate::kiro::rate_learning::RatePacer;
+use crate::kiro::retry_audit::{AttemptClass, RetryAudit};
+use crate::kiro::raw_capture::RawCapture;
+use crate::kiro::shadow::{PrimaryOutcome, ShadowMirror};
+use crate::kiro::transform::{BodyTransformer, TransformRegistry};
+use crate::kiro::token_manager::{CallContext, CallLease, MultiTokenManager};
+use crate::kiro::vpc_endpoint;
+use crate::model::config::{AwsErrorAction, LogContentLimit, RegionMismatchPolicy, TlsBackend};
 
 /// 每个凭据的最大重试次数
-const MAX_RETRIES_PER_CREDENTIAL: usize = 3;
+pub(crate) const MAX_RETRIES_PER_CREDENTIAL: usize = 3;
 
 /// 总重试次数硬上限（避免无限重试）
-const MAX_TOTAL_RETRIES: usize = 9;
+pub(crate) const MAX_TOTAL_RETRIES: usize = 9;
+
+/// 幂等请求的每凭据重试次数与总重试次数上限（`RetryPolicy::Aggressive`）
+const AGGRESSIVE_RETRIES_PER_CREDENTIAL: usize = 5;
+const AGGRESSIVE_TOTAL_RETRIES: usize = 15;
+
+/// 单次调用的重试策略（客户端通过 `X-Kiro-Retry` 请求头选择）
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RetryPolicy {
+    /// 每个凭据最多 3 次，总计最多 9 次
+    #[default]
+    Standard,
+    /// 只尝试一次：不重试、不故障转移
+    Off,
+    /// 幂等请求：每个凭据最多 5 次，总计最多 15 次
+    Aggressive,
+}
+
+impl RetryPolicy {
+    /// 解析请求头的值（`none` / `default` / `aggressive`，不区分大小写）
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "none" => Some(Self::Off),
+            "default" => Some(Self::Standard),
+            "aggressive" => Some(Self::Aggressive),
+            _ => None,
+        }
+    }
+
+    /// 本次调用最多尝试的次数
+    pub fn max_attempts(self, total_credentials: usize) -> usize {
+        match self {
+            Self::Standard => {
+                (total_credentials * MAX_RETRIES_PER_CREDENTIAL).min(MAX_TOTAL_RETRIES)
+            }
+            Self::Off => 1,
+            Self::Aggressive => (total_credentials * AGGRESSIVE_RETRIES_PER_CREDENTIAL)
+                .min(AGGRESSIVE_TOTAL_RETRIES),
+        }
+    }
+}
+
+/// 单次请求的调用选项
+#[derive(Debug, Clone, Default)]
+pub struct CallOptions {
+    /// 覆盖全局 region（None 时使用 config.region）
+    pub region: Option<String>,
+    /// 日志中保留的内容长度（None 时使用 config.logContent.maxChars）
+    pub log_content: Option<LogContentLimit>,
+    /// 重试策略
+    pub retry: RetryPolicy,
+    /// 尽量避开的凭据（同一请求中已失败的凭据，调用方换凭据重试时设置）
+    pub avoid_credentials: Vec<u64>,
+}
+
+impl CallOptions {
+    pub fn with_region(mut self, region: impl Into<String>) -> Self {
+        self.region = Some(region.into());
+        self
+    }
+
+    pub fn with_log_content(mut self, limit: Option<LogContentLimit>) -> Self {
+        self.log_content = limit;
+        self
+    }
+
+    pub fn with_retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// 之后的调用避开指定凭据（没有其他可用凭据时仍可使用）
+    pub fn avoiding(mut self, credential_id: u64) -> Self {
+        if !self.avoid_credentials.contains(&credential_id) {
+            self.avoid_credentials.push(credential_id);
+        }
+        self
+    }
+}
+
+/// 预演的上游调用（见 `KiroProvider::plan_api_call`）
+#[derive(Debug, Clone)]
+pub struct CallPlan {
+    /// 将使用的凭据
+    pub credential_id: u64,
+    pub region: String,
+    pub url: String,
+    /// 实际发送的请求体（已应用请求体改写）
+    pub request_body: String,
+}
+
+/// 成功响应所使用的凭据 ID（存放在 `reqwest::Response` 的 extensions 中）
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ServedCredential(pub u64);
+
+impl ServedCredential {
+    /// 取出响应对应的凭据 ID
+    pub fn of(response: &reqwest::Response) -> Option<u64> {
+        response.extensions().get::<Self>().map(|c| c.0)
+    }
+}
+
+/// 把凭据的进行中调用计数绑定到响应体：响应体读完或被丢弃时才归还，
+/// 流式响应在整个输出期间都计入 `least_loaded` 的负载
+fn attach_lease(response: reqwest::Response, lease: Option<Arc<CallLease>>) -> reqwest::Response {
+    use futures::StreamExt;
+
+    let Some(lease) = lease else {
+        return response;
+    };
+    let mut builder = http::Response::builder()
+        .status(response.status())
+        .version(response.version());
+    if let Some(headers) = builder.headers_mut() {
+        *headers = response.headers().clone();
+    }
+    let extensions = response.extensions().clone();
+    let body = response.bytes_stream().map(move |chunk| {
+        let _lease = &lease;
+        chunk
+    });
+    let mut leased: reqwest::Response = builder
+        .body(reqwest::Body::wrap_stream(body))
+        .expect("复制响应头失败")
+        .into();
+    *leased.extensions_mut() = extensions;
+    leased
+}
+
+/// 检查 region 名称是否合法（用于拼接域名，仅允许小写字母、数字和 `-`）
+pub fn is_valid_region(region: &str) -> bool {
+    !region.is_empty()
+        && region.len() <= 32
+        && region
+            .bytes()
+            .all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'-')
+}
+
+/// 检查自定义上游端点是否合法（`{region}` 替换后须为带主机名的 http/https URL）
+pub fn is_valid_endpoint(endpoint: &str) -> bool {
+    reqwest::Url::parse(&endpoint.replace("{region}", "us-east-1"))
+        .is_ok_and(|url| matches!(url.scheme(), "http" | "https") && url.host_str().is_some())
+}
+
+/// 构造请求头值，含非法字符时返回错误（错误信息不包含值本身，避免泄露 token）
+fn header_value(name: &str, value: &str) -> anyhow::Result<HeaderValue> {
+    HeaderValue::from_str(value).map_err(|_| anyhow::anyhow!("请求头 {} 的值含非法字符", name))
+}
+
+/// AWS SDK 的调用标识
+///
+/// 与 SDK 一致：同一次调用的各次重试共用 `amz-sdk-invocation-id`，`amz-sdk-request` 记录实际的
+/// 尝试序号（从 1 开始）与最大尝试次数，上游据此关联同一调用的重试
+pub(crate) struct SdkRequest<'a> {
+    pub invocation_id: &'a str,
+    /// 尝试序号（从 0 开始）
+    pub attempt: usize,
+    pub max_attempts: usize,
+}
+
+impl SdkRequest<'_> {
+    /// 写入调用标识请求头（位置与 SDK 一致，由构建请求头时调用）
+    fn insert_into(&self, headers: &mut HeaderMap) -> anyhow::Result<()> {
+        headers.insert(
+            "amz-sdk-invocation-id",
+            header_value("amz-sdk-invocation-id", self.invocation_id)?,
+        );
+        let request = format!("attempt={}; max={}", self.attempt + 1, self.max_attempts);
+        headers.insert(
+            "amz-sdk-request",
+            header_value("amz-sdk-request", &request)?,
+        );
+        Ok(())
+    }
+}
 
 /// Kiro API Provider
 ///
 /// 核心组件，负责与 Kiro API 通信
 /// 支持多凭据故障转移和重试机制
+///
+/// 各字段均为共享句柄或小型配置，克隆开销很小（影子请求据此在后台任务中构建请求）
+#[derive(Clone)]
 pub struct KiroProvider {
     token_manager: Arc<MultiTokenManager>,
     client: Client,
+    /// 请求头审计参考（仅在开启 headerAudit 时存在）
+    header_audit: Option<HeaderAuditReference>,
+    /// 影子流量镜像器（仅在开启 shadow 时存在）
+    shadow: Option<Arc<ShadowMirror>>,
+    /// 上游原始帧抓取器（由 Admin API 预约）
+    raw_capture: Arc<RawCapture>,
+    /// 按模型调整请求体的转换器
+    transforms: TransformRegistry,
+    /// 隐私模式下关闭的能力
+    capabilities: Capabilities,
+    /// 已注册的拦截器（按注册顺序调用）
+    interceptors: Vec<Arc<dyn Interceptor>>,
+    /// 负载统计（等待上游响应的调用计入排队深度）
+    load: Option<Arc<LoadTracker>>,
+    /// 从 429 学到的按凭据节流（仅在开启 rateLearning 时存在）
+    rate_pacer: Option<Arc<RatePacer>>,
 }
 
-impl KiroProvider {
-    /// 创建新的 KiroProvider 实例
-    pub fn new(token_manager: Arc<MultiTokenManager>) -> Self {
-        Self::with_proxy(token_manager, None)
+/// KiroProvider 构建失败的原因
+#[derive(Debug)]
+pub enum ProviderBuildError {
+    /// 代理地址无法解析
+    InvalidProxy { url: String, reason: String },
+    /// HTTP 客户端（TLS 后端）初始化失败
+    TlsBackend { backend: TlsBackend, reason: String },
+}
+
+impl fmt::Display for ProviderBuildError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProviderBuildError::InvalidProxy { url, reason } => write!(
+                f,
+                "代理地址无效 {}: {}（请检查 proxyUrl，支持 http://、https://、socks5://）",
+                url, reason
+            ),
+            ProviderBuildError::TlsBackend { backend, reason } => {
+                let alternative = match backend {
+                    TlsBackend::Rustls => "native-tls",
+                    TlsBackend::NativeTls => "rustls",
+                };
+                write!(
+                    f,
+                    "初始化 HTTP 客户端失败（tlsBackend = {:?}）: {}（可尝试将 tlsBackend 改为 {}）",
+                    backend, reason, alternative
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for ProviderBuildError {}
+
+/// KiroProvider 构建器
+pub struct KiroProviderBuilder {
+    token_manager: Arc<MultiTokenManager>,
+    proxy: Option<ProxyConfig>,
+}
+
+impl KiroProviderBuilder {
+    /// 设置上游请求使用的代理
+    pub fn proxy(mut self, proxy: Option<ProxyConfig>) -> Self {
+        self.proxy = proxy;
+        self
     }
 
-    /// 创建带代理配置的 KiroProvider 实例
-    pub fn with_proxy(token_manager: Arc<MultiTokenManager>, proxy: Option<ProxyConfig>) -> Self {
-        let client = build_client(proxy.as_ref(), 720, token_manager.config().tls_backend)
-            .expect("创建 HTTP 客户端失败");
+    /// 创建 HTTP 客户端并构建 KiroProvider
+    pub fn build(self) -> Result<KiroProvider, ProviderBuildError> {
+        let token_manager = self.token_manager;
+        let tls_backend = token_manager.config().tls_backend;
+        if let Some(proxy) = &self.proxy {
+            reqwest::Proxy::all(&proxy.url).map_err(|e| ProviderBuildError::InvalidProxy {
+                url: proxy.url.clone(),
+                reason: e.to_string(),
+            })?;
+        }
+        let tls_error = |e: anyhow::Error| ProviderBuildError::TlsBackend {
+            backend: tls_backend,
+            reason: format!("{:#}", e),
+        };
+        let builder = client_builder(self.proxy.as_ref(), 720, tls_backend).map_err(tls_error)?;
+        let client = vpc_endpoint::apply(builder, token_manager.config())
+            .build()
+            .map_err(|e| tls_error(e.into()))?;
+
+        let header_audit = KiroProvider::load_header_audit_reference(&token_manager);
+
+        let capabilities = Capabilities::from_config(token_manager.config());
+        let shadow_config = &token_manager.config().shadow;
+        let shadow = (shadow_config.enabled && capabilities.analytics)
+            .then(|| Arc::new(ShadowMirror::new(shadow_config.clone(), client.clone())));
+        let raw_capture = Arc::new(RawCapture::new(token_manager.config(), capabilities));
+        let config = token_manager.config();
+        let transform_rules: Vec<_> = config
+            .model_transforms
+            .iter()
+            .cloned()
+            .chain(
+                config
+                    .custom_models
+                    .iter()
+                    .filter_map(|m| m.transform_rule()),
+            )
+            .collect();
+        let transforms = TransformRegistry::from_rules(&transform_rules);
+        let rate_pacer = RatePacer::from_config(token_manager.config());
 
-        Self {
+        Ok(KiroProvider {
             token_manager,
             client,
+            header_audit,
+            shadow,
+            raw_capture,
+            transforms,
+            capabilities,
+            interceptors: Vec::new(),
+            load: None,
+            rate_pacer,
+        })
+    }
+}
+
+impl KiroProvider {
+    /// 创建 KiroProvider 构建器
+    pub fn builder(token_manager: Arc<MultiTokenManager>) -> KiroProviderBuilder {
+        KiroProviderBuilder {
+            token_manager,
+            proxy: None,
+        }
+    }
+
+    /// 设置负载统计
+    pub fn with_load_tracker(mut self, load: Arc<LoadTracker>) -> Self {
+        self.load = Some(load);
+        self
+    }
+
+    /// 注册请求体转换器（在 `modelTransforms` 配置规则之后应用）
+    ///
+    /// `model` 为 Kiro 模型 ID，以 `*` 结尾时按前缀匹配
+    pub fn with_body_transformer(
+        mut self,
+        model: impl Into<String>,
+        transformer: Arc<dyn BodyTransformer>,
+    ) -> Self {
+        self.transforms.register(model, transformer);
+        self
+    }
+
+    /// 注册拦截器
+    pub fn with_interceptor(mut self, interceptor: Arc<dyn Interceptor>) -> Self {
+        self.interceptors.push(interceptor);
+        self
+    }
+
+    /// 加载请求头审计参考抓包（未开启审计时返回 None）
+    fn load_header_audit_reference(
+        token_manager: &MultiTokenManager,
+    ) -> Option<HeaderAuditReference> {
+        let config = token_manager.config();
+        if !config.header_audit {
+            return None;
+        }
+        let Some(path) = &config.header_audit_reference_path else {
+            return Some(HeaderAuditReference::default());
+        };
+        match HeaderAuditReference::load(path) {
+            Ok(reference) => Some(reference),
+            Err(e) => {
+                tracing::warn!("加载请求头审计参考抓包失败，使用内置参考: {}", e);
+                Some(HeaderAuditReference::default())
+            }
         }
     }
 
@@ -55,32 +404,163 @@ impl KiroProvider {
         &self.token_manager
     }
 
-    /// 获取 API 基础 URL
-    pub fn base_url(&self) -> String {
-        format!(
-            "https://q.{}.amazonaws.com/generateAssistantResponse",
-            self.token_manager.config().region
-        )
+    /// 获取影子流量镜像器（未开启 shadow 时返回 None）
+    pub fn shadow(&self) -> Option<Arc<ShadowMirror>> {
+        self.shadow.clone()
+    }
+
+    /// 获取上游原始帧抓取器
+    pub fn raw_capture(&self) -> Arc<RawCapture> {
+        self.raw_capture.clone()
+    }
+
+    /// 获取按凭据的自动节流器（未开启 rateLearning 时返回 None）
+    pub fn rate_pacer(&self) -> Option<Arc<RatePacer>> {
+        self.rate_pacer.clone()
+    }
+
+    /// 获取指定 region 的 API 基础域名
+    pub fn base_domain_for(&self, region: &str) -> String {
+        format!("q.{}.amazonaws.com", region)
+    }
+
+    /// 获取凭据实际使用的上游端点（不含末尾 `/`）
+    ///
+    /// 凭据配置 `apiEndpoint` 时以其替换默认的 AWS 公网地址，`{region}` 占位符替换为调用区域
+    /// 否则配置了 `vpcEndpoint`（connect 方式）时连接端点 DNS 名称
+    fn endpoint_for(&self, credentials: &KiroCredentials, region: &str) -> String {
+        match &credentials.api_endpoint {
+            Some(endpoint) => endpoint
+                .replace("{region}", region)
+                .trim_end_matches('/')
+                .to_string(),
+            None => {
+                // 经 VPC Endpoint 连接时 Host 头仍为公网域名，见 host_for
+                let host = vpc_endpoint::connect_host_for(
+                    &self.token_manager.config().vpc_endpoint,
+                    region,
+                )
+                .unwrap_or_else(|| self.base_domain_for(region));
+                format!("https://{}", host)
+            }
+        }
     }
 
-    /// 获取 MCP API URL
-    pub fn mcp_url(&self) -> String {
+    /// 获取凭据在指定 region 的 API URL
+    pub fn credential_api_url(&self, credentials: &KiroCredentials, region: &str) -> String {
         format!(
-            "https://q.{}.amazonaws.com/mcp",
-            self.token_manager.config().region
+            "{}/generateAssistantResponse",
+            self.endpoint_for(credentials, region)
         )
     }
 
-    /// 获取 API 基础域名
-    pub fn base_domain(&self) -> String {
-        format!("q.{}.amazonaws.com", self.token_manager.config().region)
+    /// 获取凭据在指定 region 的 MCP API URL
+    pub fn credential_mcp_url(&self, credentials: &KiroCredentials, region: &str) -> String {
+        format!("{}/mcp", self.endpoint_for(credentials, region))
+    }
+
+    /// 获取凭据在指定 region 的 Host 头（自定义端点时取其主机名，非默认端口时带端口）
+    pub fn host_for(&self, credentials: &KiroCredentials, region: &str) -> String {
+        credentials
+            .api_endpoint
+            .as_ref()
+            .and_then(|_| reqwest::Url::parse(&self.endpoint_for(credentials, region)).ok())
+            .and_then(|url| {
+                let host = url.host_str()?;
+                Some(match url.port() {
+                    Some(port) => format!("{}:{}", host, port),
+                    None => host.to_string(),
+                })
+            })
+            .unwrap_or_else(|| self.base_domain_for(region))
+    }
+
+    /// 解析本次调用实际使用的 region（请求级覆盖优先于全局配置）
+    fn api_region<'a>(&'a self, options: &'a CallOptions) -> &'a str {
+        options
+            .region
+            .as_deref()
+            .unwrap_or(&self.token_manager.config().region)
+    }
+
+    /// 解析凭据实际可用的 region
+    ///
+    /// 凭据绑定了 apiRegion 且与请求区域不一致时，按 regionMismatchPolicy
+    /// 自动改用凭据区域或拒绝该凭据（避免用错区域反复触发 403 浪费重试，调用方改用其他凭据）
+    fn resolve_region(&self, ctx: &CallContext, options: &CallOptions) -> anyhow::Result<String> {
+        let requested = self.api_region(options);
+        let Some(bound) = ctx.credentials.api_region.as_deref() else {
+            return Ok(requested.to_string());
+        };
+        if bound == requested {
+            return Ok(requested.to_string());
+        }
+
+        match self.token_manager.config().region_mismatch_policy {
+            RegionMismatchPolicy::Correct => {
+                tracing::warn!(
+                    "凭据 #{} 绑定区域 {} 与请求区域 {} 不一致，已自动改用凭据区域",
+                    ctx.id,
+                    bound,
+                    requested
+                );
+                Ok(bound.to_string())
+            }
+            RegionMismatchPolicy::Reject => anyhow::bail!(
+                "凭据 #{} 绑定区域 {} 与请求区域 {} 不一致，已拒绝请求",
+                ctx.id,
+                bound,
+                requested
+            ),
+        }
+    }
+
+    /// 预演一次 API 调用：下一次调用将使用的凭据、端点与实际发送的请求体
+    ///
+    /// 不刷新 Token、不切换凭据，也不调用上游
+    pub fn plan_api_call(
+        &self,
+        request_body: &str,
+        options: &CallOptions,
+    ) -> anyhow::Result<CallPlan> {
+        // 与实际调用一致：区域不符被拒绝的凭据跳过，改用下一个凭据
+        let mut rejected = Vec::new();
+        let (ctx, region) = loop {
+            let Some((id, credentials)) = self.token_manager.peek_next(&rejected) else {
+                anyhow::bail!("没有可用的凭据");
+            };
+            let ctx = CallContext {
+                id,
+                credentials,
+                token: String::new(),
+                lease: None,
+            };
+            match self.resolve_region(&ctx, options) {
+                Ok(region) => break (ctx, region),
+                Err(e) if rejected.len() + 1 >= self.token_manager.total_count() => return Err(e),
+                Err(_) => rejected.push(id),
+            }
+        };
+        Ok(CallPlan {
+            credential_id: ctx.id,
+            url: self.credential_api_url(&ctx.credentials, &region),
+            region,
+            request_body: self.transforms.apply(request_body).into_owned(),
+        })
     }
 
     /// 构建请求头
     ///
     /// # Arguments
     /// * `ctx` - API 调用上下文，包含凭据和 token
-    fn build_headers(&self, ctx: &CallContext) -> anyhow::Result<HeaderMap> {
+    /// * `region` - 本次调用使用的 region（决定 Host 头）
+    /// * `sdk` - 本次尝试的 SDK 调用标识
+    pub(crate) fn build_headers(
+        &self,
+        ctx: &CallContext,
+        region: &str,
+        sdk: &SdkRequest,
+    ) -> anyhow::Result<HeaderMap> {
         let config = self.token_manager.config();
 
         let machine_id = machine_id::generate_from_credentials(&ctx.credentials, config)
@@ -102,29 +582,29 @@ impl KiroProvider {
         headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
         headers.insert(
             "x-amzn-codewhisperer-optout",
-            HeaderValue::from_static("true"),
+            HeaderValue::from_static(if config.codewhisperer_optout {
+                "true"
+            } else {
+                "false"
+            }),
         );
         headers.insert("x-amzn-kiro-agent-mode", HeaderValue::from_static("vibe"));
         headers.insert(
             "x-amz-user-agent",
-            HeaderValue::from_str(&x_amz_user_agent).unwrap(),
+            header_value("x-amz-user-agent", &x_amz_user_agent)?,
         );
         headers.insert(
             reqwest::header::USER_AGENT,
-            HeaderValue::from_str(&user_agent).unwrap(),
+            header_value("user-agent", &user_agent)?,
         );
-        headers.insert(HOST, HeaderValue::from_str(&self.base_domain()).unwrap());
         headers.insert(
-            "amz-sdk-invocation-id",
-            HeaderValue::from_str(&Uuid::new_v4().to_string()).unwrap(),
-        );
-        headers.insert(
-            "amz-sdk-request",
-            HeaderValue::from_static("attempt=1; max=3"),
+            HOST,
+            header_value("host", &self.host_for(&ctx.credentials, region))?,
         );
+        sdk.insert_into(&mut headers)?;
         headers.insert(
             AUTHORIZATION,
-            HeaderValue::from_str(&format!("Bearer {}", ctx.token)).unwrap(),
+            header_value("authorization", &format!("Bearer {}", ctx.token))?,
         );
         headers.insert(CONNECTION, HeaderValue::from_static("close"));
 
@@ -132,7 +612,12 @@ impl KiroProvider {
     }
 
     /// 构建 MCP 请求头
-    fn build_mcp_headers(&self, ctx: &CallContext) -> anyhow::Result<HeaderMap> {
+    pub(crate) fn build_mcp_headers(
+        &self,
+        ctx: &CallContext,
+        region: &str,
+        sdk: &SdkRequest,
+    ) -> anyhow::Result<HeaderMap> {
         let config = self.token_manager.config();
 
         let machine_id = machine_id::generate_from_credentials(&ctx.credentials, config)
@@ -158,48 +643,64 @@ impl KiroProvider {
         );
         headers.insert(
             "x-amz-user-agent",
-            HeaderValue::from_str(&x_amz_user_agent).unwrap(),
+            header_value("x-amz-user-agent", &x_amz_user_agent)?,
         );
         headers.insert(
             "user-agent",
-            HeaderValue::from_str(&user_agent).unwrap(),
+            header_value("user-agent", &user_agent)?,
         );
         headers.insert(
             "host",
-            HeaderValue::from_str(&self.base_domain()).unwrap(),
-        );
-        headers.insert(
-            "amz-sdk-invocation-id",
-            HeaderValue::from_str(&Uuid::new_v4().to_string()).unwrap(),
-        );
-        headers.insert(
-            "amz-sdk-request",
-            HeaderValue::from_static("attempt=1; max=3"),
+            header_value("host", &self.host_for(&ctx.credentials, region))?,
         );
+        sdk.insert_into(&mut headers)?;
         headers.insert(
             "Authorization",
-            HeaderValue::from_str(&format!("Bearer {}", ctx.token)).unwrap(),
+            header_value("authorization", &format!("Bearer {}", ctx.token))?,
         );
         headers.insert("Connection", HeaderValue::from_static("close"));
 
         Ok(headers)
     }
 
+    /// 审计即将发送的最终请求头（含调用标识与拦截器的修改）
+    fn audit_headers(&self, kind: CallKind, headers: &HeaderMap) {
+        let Some(reference) = &self.header_audit else {
+            return;
+        };
+        match kind {
+            CallKind::Mcp => header_audit::audit("MCP", headers, &reference.mcp),
+            CallKind::Api | CallKind::ApiStream => {
+                header_audit::audit("API", headers, &reference.api)
+            }
+        };
+    }
+
     /// 发送非流式 API 请求
     ///
     /// 支持多凭据故障转移：
     /// - 400 Bad Request: 直接返回错误，不计入凭据失败
     /// - 401/403: 视为凭据/权限问题，计入失败次数并允许故障转移
     /// - 402 MONTHLY_REQUEST_COUNT: 视为额度用尽，禁用凭据并切换
-    /// - 429/5xx/网络等瞬态错误: 重试但不禁用或切换凭据（避免误把所有凭据锁死）
+    /// - 429/5xx/网络等瞬态错误: 重试但不禁用凭据（避免误把所有凭据锁死）；按 AWS 异常类型区分退避，
+    ///   `ServiceQuotaExceededException` 切换凭据，`ModelNotReadyException` 等待更久
     ///
     /// # Arguments
     /// * `request_body` - JSON 格式的请求体字符串
+    /// * `options` - 单次请求的调用选项（如 region 覆盖）
     ///
     /// # Returns
     /// 返回原始的 HTTP Response，不做解析
-    pub async fn call_api(&self, request_body: &str) -> anyhow::Result<reqwest::Response> {
-        self.call_api_with_retry(request_body, false).await
+    pub async fn call_api(
+        &self,
+        request_body: &str,
+        options: &CallOptions,
+    ) -> anyhow::Result<reqwest::Response> {
+        let request_body = self.transforms.apply(request_body);
+        let started = Instant::now();
+        let result = self.call_api_with_retry(&request_body, false, options).await;
+        self.mirror_to_shadow(&request_body, options, started, &result);
+        result.map(|resp| self.raw_capture.tap(resp))
     }
 
     /// 发送流式 API 请求
@@ -208,56 +709,270 @@ impl KiroProvider {
     /// - 400 Bad Request: 直接返回错误，不计入凭据失败
     /// - 401/403: 视为凭据/权限问题，计入失败次数并允许故障转移
     /// - 402 MONTHLY_REQUEST_COUNT: 视为额度用尽，禁用凭据并切换
-    /// - 429/5xx/网络等瞬态错误: 重试但不禁用或切换凭据（避免误把所有凭据锁死）
+    /// - 429/5xx/网络等瞬态错误: 重试但不禁用凭据（避免误把所有凭据锁死）；按 AWS 异常类型区分退避，
+    ///   `ServiceQuotaExceededException` 切换凭据，`ModelNotReadyException` 等待更久
     ///
     /// # Arguments
     /// * `request_body` - JSON 格式的请求体字符串
+    /// * `options` - 单次请求的调用选项（如 region 覆盖）
     ///
     /// # Returns
     /// 返回原始的 HTTP Response，调用方负责处理流式数据
-    pub async fn call_api_stream(&self, request_body: &str) -> anyhow::Result<reqwest::Response> {
-        self.call_api_with_retry(request_body, true).await
+    pub async fn call_api_stream(
+        &self,
+        request_body: &str,
+        options: &CallOptions,
+    ) -> anyhow::Result<reqwest::Response> {
+        let request_body = self.transforms.apply(request_body);
+        let started = Instant::now();
+        let result = self.call_api_with_retry(&request_body, true, options).await;
+        self.mirror_to_shadow(&request_body, options, started, &result);
+        result.map(|resp| self.raw_capture.tap(resp))
     }
 
-    /// 发送 MCP API 请求
+    /// 发送类型化的非流式 API 请求
     ///
-    /// 用于 WebSearch 等工具调用
+    /// 与 `call_api` 相同，只是由 `KiroRequest` 负责序列化；
+    /// 需要发送未建模的字段时可继续使用 `call_api` 传入原始 JSON
+    pub async fn call_api_typed(
+        &self,
+        request: &KiroRequest,
+        options: &CallOptions,
+    ) -> anyhow::Result<reqwest::Response> {
+        self.call_api(&request.to_json()?, options).await
+    }
+
+    /// 发送类型化的流式 API 请求
+    pub async fn call_api_stream_typed(
+        &self,
+        request: &KiroRequest,
+        options: &CallOptions,
+    ) -> anyhow::Result<reqwest::Response> {
+        self.call_api_stream(&request.to_json()?, options).await
+    }
+
+    /// 按抽样比例把请求镜像到影子目标
     ///
-    /// # Arguments
-    /// * `request_body` - JSON 格式的 MCP 请求体字符串
+    /// 影子请求在后台获取凭据并发送，不阻塞主响应（凭据需要刷新 Token 时也不等待）；
+    /// 失败只记录，不影响凭据状态
+    fn mirror_to_shadow(
+        &self,
+        request_body: &str,
+        options: &CallOptions,
+        started: Instant,
+        primary: &anyhow::Result<reqwest::Response>,
+    ) {
+        let Some(shadow) = self.shadow.as_ref().filter(|s| s.should_mirror()) else {
+            return;
+        };
+        let outcome = PrimaryOutcome {
+            latency: started.elapsed(),
+            ok: primary.is_ok(),
+        };
+        let region = shadow
+            .region()
+            .unwrap_or_else(|| self.api_region(options))
+            .to_string();
+        let provider = self.clone();
+        let shadow = shadow.clone();
+        let body = request_body.to_string();
+        tokio::spawn(async move {
+            if let Some((url, headers)) = provider.shadow_request(&shadow, &region).await {
+                shadow.send(url, headers, body, outcome).await;
+            }
+        });
+    }
+
+    /// 为影子请求获取凭据并构建 URL 与请求头，失败时返回 None
+    async fn shadow_request(
+        &self,
+        shadow: &ShadowMirror,
+        region: &str,
+    ) -> Option<(String, HeaderMap)> {
+        let ctx = match self.token_manager.acquire_context().await {
+            Ok(ctx) => ctx,
+            Err(e) => {
+                tracing::debug!("影子请求获取凭据失败: {}", e);
+                return None;
+            }
+        };
+        // 影子请求只发送一次，不重试
+        let invocation_id = Uuid::new_v4().to_string();
+        let sdk = SdkRequest {
+            invocation_id: &invocation_id,
+            attempt: 0,
+            max_attempts: 1,
+        };
+        let mut headers = match self.build_headers(&ctx, region, &sdk) {
+            Ok(h) => h,
+            Err(e) => {
+                tracing::debug!("影子请求构建请求头失败: {}", e);
+                return None;
+            }
+        };
+        let url = match shadow.endpoint() {
+            Some(endpoint) => {
+                // 自定义端点时 Host 头需与端点一致
+                if let Some(host) = reqwest::Url::parse(endpoint)
+                    .ok()
+                    .and_then(|u| u.host_str().map(|h| h.to_string()))
+                    .and_then(|h| HeaderValue::from_str(&h).ok())
+                {
+                    headers.insert(HOST, host);
+                }
+                endpoint.to_string()
+            }
+            None => self.credential_api_url(&ctx.credentials, region),
+        };
+        self.audit_headers(CallKind::Api, &headers);
+        Some((url, headers))
+    }
+
+    /// 发送类型化的 MCP JSON-RPC 请求
     ///
-    /// # Returns
-    /// 返回原始的 HTTP Response
-    pub async fn call_mcp(&self, request_body: &str) -> anyhow::Result<reqwest::Response> {
-        self.call_mcp_with_retry(request_body).await
+    /// 校验响应 id 与请求一致，并把 JSON-RPC 错误对象映射为 `McpCallError`
+    pub async fn call_mcp_typed<P: Serialize, R: DeserializeOwned>(
+        &self,
+        request: &JsonRpcRequest<P>,
+        options: &CallOptions,
+    ) -> anyhow::Result<R> {
+        let Some(id) = &request.id else {
+            anyhow::bail!("MCP 请求缺少 id，通知没有响应结果");
+        };
+        let request_body = serde_json::to_string(request)?;
+        let limit = self.log_limit(options);
+        tracing::debug!("MCP request: {}", clip(limit, &request_body));
+
+        let response = self.call_mcp_with_retry(&request_body, options).await?;
+        let body = response.text().await?;
+        tracing::debug!("MCP response: {}", clip(limit, &body));
+
+        Ok(JsonRpcResponse::<R>::parse(&body)?.into_result(id)?)
     }
 
     /// 内部方法：带重试逻辑的 MCP API 调用
-    async fn call_mcp_with_retry(&self, request_body: &str) -> anyhow::Result<reqwest::Response> {
+    async fn call_mcp_with_retry(
+        &self,
+        request_body: &str,
+        options: &CallOptions,
+    ) -> anyhow::Result<reqwest::Response> {
+        let _queued = self.load.as_ref().map(|l| l.enqueue());
+        let mut audit = RetryAudit::default();
+        let started = Instant::now();
+        let result = self
+            .call_mcp_attempts(request_body, options, &mut audit)
+            .await;
+        let attempts = audit.attempt_count() + usize::from(result.is_ok());
+        attempt_timing::log_call(CallKind::Mcp, attempts, started.elapsed(), &result);
+        let result = result.map_err(|e| audit.attach(e));
+        self.notify_error(CallKind::Mcp, &result);
+        result
+    }
+
+    async fn call_mcp_attempts(
+        &self,
+        request_body: &str,
+        options: &CallOptions,
+        audit: &mut RetryAudit,
+    ) -> anyhow::Result<reqwest::Response> {
+        let kind = CallKind::Mcp;
         let total_credentials = self.token_manager.total_count();
-        let max_retries = (total_credentials * MAX_RETRIES_PER_CREDENTIAL).min(MAX_TOTAL_RETRIES);
+        let max_retries = options.retry.max_attempts(total_credentials);
         let mut last_error: Option<anyhow::Error> = None;
+        let limit = self.log_limit(options);
+
+        // 本次调用中区域不符被拒绝的凭据，之后的尝试连同调用方指定的凭据一起避开
+        let mut rejected: Vec<u64> = Vec::new();
+        let mut avoid = options.avoid_credentials.clone();
+
+        // 同一次调用的各次重试共用 invocation id
+        let invocation_id = Uuid::new_v4().to_string();
 
         for attempt in 0..max_retries {
+            if attempt > 0
+                && let Some(e) = &last_error
+            {
+                for interceptor in &self.interceptors {
+                    interceptor.on_retry(kind, attempt, e);
+                }
+            }
+
+            let mut timer = AttemptTimer::start(kind, attempt, max_retries);
+
             // 获取调用上下文
-            let ctx = match self.token_manager.acquire_context().await {
+            let ctx = match self.token_manager.acquire_context_avoiding(&avoid).await {
                 Ok(c) => c,
                 Err(e) => {
+                    timer.acquired(None);
+                    audit.record(None, None, Duration::ZERO, AttemptClass::NoCredential);
                     last_error = Some(e);
                     continue;
                 }
             };
+            timer.acquired(Some(ctx.id));
 
-            let url = self.mcp_url();
-            let headers = match self.build_mcp_headers(&ctx) {
+            let region = match self.resolve_region(&ctx, options) {
+                Ok(region) => region,
+                Err(e) => {
+                    audit.record(
+                        Some(ctx.id),
+                        None,
+                        Duration::ZERO,
+                        AttemptClass::RegionMismatch,
+                    );
+                    last_error = Some(e);
+                    // 已避开过该凭据仍被选中：没有区域一致的凭据
+                    if rejected.contains(&ctx.id) {
+                        break;
+                    }
+                    tracing::warn!("凭据 #{} 区域不符，尝试其他凭据", ctx.id);
+                    rejected.push(ctx.id);
+                    avoid.push(ctx.id);
+                    continue;
+                }
+            };
+            let url = self.credential_mcp_url(&ctx.credentials, &region);
+            let sdk = SdkRequest {
+                invocation_id: &invocation_id,
+                attempt,
+                max_attempts: max_retries,
+            };
+            let mut headers = match self.build_mcp_headers(&ctx, &region, &sdk) {
                 Ok(h) => h,
                 Err(e) => {
+                    audit.record(
+                        Some(ctx.id),
+                        None,
+                        Duration::ZERO,
+                        AttemptClass::InvalidHeaders,
+                    );
                     last_error = Some(e);
                     continue;
                 }
             };
 
-            // 发送请求
+            timer.headers_built();
+
+            let info = AttemptInfo {
+                kind,
+                attempt,
+                max_attempts: max_retries,
+                invocation_id: &invocation_id,
+                credential_id: ctx.id,
+                region: &region,
+                url: &url,
+            };
+            for interceptor in &self.interceptors {
+                interceptor.on_request(&info, &mut headers);
+            }
+            self.audit_headers(kind, &headers);
+
+            // 发送请求（学到上游限额后先按需节流）
+            if let Some(pacer) = &self.rate_pacer {
+                pacer.pace(ctx.id).await;
+            }
+            timer.paced();
+            let attempt_started = Instant::now();
             let response = match self
                 .client
                 .post(&url)
@@ -268,14 +983,16 @@ impl KiroProvider {
             {
                 Ok(resp) => resp,
                 Err(e) => {
-                    tracing::warn!(
-                        "MCP 请求发送失败（尝试 {}/{}）: {}",
-                        attempt + 1,
-                        max_retries,
-                        e
+                    audit.record(
+                        Some(ctx.id),
+                        None,
+                        attempt_started.elapsed(),
+                        AttemptClass::Network,
                     );
+                    tracing::warn!("MCP 请求发送失败: {}", e);
                     last_error = Some(e.into());
                     if attempt + 1 < max_retries {
+                        timer.finish();
                         sleep(Self::retry_delay(attempt)).await;
                     }
                     continue;
@@ -283,65 +1000,113 @@ impl KiroProvider {
             };
 
             let status = response.status();
+            timer.responded(status);
+            for interceptor in &self.interceptors {
+                interceptor.on_response(&info, status, attempt_started.elapsed());
+            }
+            if status.as_u16() == 429
+                && let Some(pacer) = &self.rate_pacer
+            {
+                pacer.record_throttle(ctx.id);
+            }
 
             // 成功响应
             if status.is_success() {
                 self.token_manager.report_success(ctx.id);
-                return Ok(response);
+                let mut response = response;
+                response.extensions_mut().insert(ServedCredential(ctx.id));
+                return Ok(attach_lease(response, ctx.lease.clone()));
             }
 
             // 失败响应
+            let error_type = AwsError::header_value(response.headers());
             let body = response.text().await.unwrap_or_default();
+            let quota_exhausted = status.as_u16() == 402 && Self::is_monthly_request_limit(&body);
+            let aws_action = matches!(status.as_u16(), 401 | 403)
+                .then(|| self.aws_error_action(error_type.as_deref(), &body));
+            audit.record(
+                Some(ctx.id),
+                Some(status),
+                attempt_started.elapsed(),
+                match aws_action {
+                    Some(AwsErrorAction::Retry) => AttemptClass::Transient,
+                    _ => AttemptClass::from_status(status, quota_exhausted),
+                },
+            );
 
             // 402 额度用尽
-            if status.as_u16() == 402 && Self::is_monthly_request_limit(&body) {
+            if quota_exhausted {
                 let has_available = self.token_manager.report_quota_exhausted(ctx.id);
                 if !has_available {
-                    anyhow::bail!("MCP 请求失败（所有凭据已用尽）: {} {}", status, body);
+                    return Err(Self::upstream_error("MCP", status, &body, true));
                 }
-                last_error = Some(anyhow::anyhow!("MCP 请求失败: {} {}", status, body));
+                last_error = Some(Self::upstream_error("MCP", status, &body, false));
                 continue;
             }
 
             // 400 Bad Request
             if status.as_u16() == 400 {
-                anyhow::bail!("MCP 请求失败: {} {}", status, body);
+                return Err(Self::upstream_error("MCP", status, &body, false));
             }
 
-            // 401/403 凭据问题
-            if matches!(status.as_u16(), 401 | 403) {
-                let has_available = self.token_manager.report_failure(ctx.id);
+            // 401/403：按 AWS 错误码决定重试/直接失败/禁用/故障转移
+            if let Some(action) = aws_action {
+                if action == AwsErrorAction::Retry {
+                    tracing::warn!(
+                        "MCP 请求失败（错误码判定为瞬态错误）: {} {}",
+                        status,
+                        clip(limit, &body)
+                    );
+                    last_error = Some(Self::upstream_error("MCP", status, &body, false));
+                    if attempt + 1 < max_retries {
+                        timer.finish();
+                        sleep(Self::retry_delay(attempt)).await;
+                    }
+                    continue;
+                }
+                if action == AwsErrorAction::Fail {
+                    return Err(Self::upstream_error("MCP", status, &body, false));
+                }
+
+                let has_available = if action == AwsErrorAction::Disable {
+                    self.token_manager.report_access_denied(ctx.id)
+                } else {
+                    self.token_manager.report_failure(ctx.id)
+                };
                 if !has_available {
-                    anyhow::bail!("MCP 请求失败（所有凭据已用尽）: {} {}", status, body);
+                    return Err(Self::upstream_error("MCP", status, &body, true));
                 }
-                last_error = Some(anyhow::anyhow!("MCP 请求失败: {} {}", status, body));
+                last_error = Some(Self::upstream_error("MCP", status, &body, false));
                 continue;
             }
 
-            // 瞬态错误
+            // 瞬态错误（按 AWS 异常类型区分退避）
             if matches!(status.as_u16(), 408 | 429) || status.is_server_error() {
+                let throttle = AwsError::parse(error_type.as_deref(), &body).throttle_kind();
                 tracing::warn!(
-                    "MCP 请求失败（上游瞬态错误，尝试 {}/{}）: {} {}",
-                    attempt + 1,
-                    max_retries,
+                    "MCP 请求失败（上游{}）: {} {}",
+                    throttle.as_str(),
                     status,
-                    body
+                    clip(limit, &body)
                 );
-                last_error = Some(anyhow::anyhow!("MCP 请求失败: {} {}", status, body));
+                last_error = Some(Self::upstream_error("MCP", status, &body, false));
                 if attempt + 1 < max_retries {
-                    sleep(Self::retry_delay(attempt)).await;
+                    timer.finish();
+                    self.transient_backoff(throttle, attempt, ctx.id, &mut avoid)
+                        .await;
                 }
                 continue;
             }
 
             // 其他 4xx
             if status.is_client_error() {
-                anyhow::bail!("MCP 请求失败: {} {}", status, body);
+                return Err(Self::upstream_error("MCP", status, &body, false));
             }
 
             // 兜底
-            last_error = Some(anyhow::anyhow!("MCP 请求失败: {} {}", status, body));
+            last_error = Some(Self::upstream_error("MCP", status, &body, false));
             if attempt + 1 < max_retries {
+                timer.finish();
                 sleep(Self::retry_delay(attempt)).await;
             }
         }
@@ -353,7 +1118,7 @@ impl KiroProvider {
 
     /// 内部方法：带重试逻辑的 API 调用
     ///
-    /// 重试策略：
+    /// 重试策略（`options.retry` 为默认的 `Standard` 时）：
     /// - 每个凭据最多重试 MAX_RETRIES_PER_CREDENTIAL 次
     /// - 总重试次数 = min(凭据数量 × 每凭据重试次数, MAX_TOTAL_RETRIES)
     /// - 硬上限 9 次，避免无限重试
@@ -361,32 +1126,132 @@ impl KiroProvider {
         &self,
         request_body: &str,
         is_stream: bool,
+        options: &CallOptions,
+    ) -> anyhow::Result<reqwest::Response> {
+        let _queued = self.load.as_ref().map(|l| l.enqueue());
+        let mut audit = RetryAudit::default();
+        let started = Instant::now();
+        let result = self
+            .call_api_attempts(request_body, is_stream, options, &mut audit)
+            .await;
+        let kind = if is_stream { CallKind::ApiStream } else { CallKind::Api };
+        let attempts = audit.attempt_count() + usize::from(result.is_ok());
+        attempt_timing::log_call(kind, attempts, started.elapsed(), &result);
+        let result = result.map_err(|e| audit.attach(e));
+        self.notify_error(kind, &result);
+        result
+    }
+
+    async fn call_api_attempts(
+        &self,
+        request_body: &str,
+        is_stream: bool,
+        options: &CallOptions,
+        audit: &mut RetryAudit,
     ) -> anyhow::Result<reqwest::Response> {
+        let kind = if is_stream { CallKind::ApiStream } else { CallKind::Api };
         let total_credentials = self.token_manager.total_count();
-        let max_retries = (total_credentials * MAX_RETRIES_PER_CREDENTIAL).min(MAX_TOTAL_RETRIES);
+        let max_retries = options.retry.max_attempts(total_credentials);
         let mut last_error: Option<anyhow::Error> = None;
-        let api_type = if is_stream { "流式" } else { "非流式" };
+        let api_type = if is_stream {
+            "流式 API"
+        } else {
+            "非流式 API"
+        };
+        let limit = self.log_limit(options);
+
+        // 本次调用中区域不符被拒绝的凭据，之后的尝试连同调用方指定的凭据一起避开
+        let mut rejected: Vec<u64> = Vec::new();
+        let mut avoid = options.avoid_credentials.clone();
+
+        // 同一次调用的各次重试共用 invocation id
+        let invocation_id = Uuid::new_v4().to_string();
 
         for attempt in 0..max_retries {
+            if attempt > 0
+                && let Some(e) = &last_error
+            {
+                for interceptor in &self.interceptors {
+                    interceptor.on_retry(kind, attempt, e);
+                }
+            }
+
+            let mut timer = AttemptTimer::start(kind, attempt, max_retries);
+
             // 获取调用上下文（绑定 index、credentials、token）
-            let ctx = match self.token_manager.acquire_context().await {
+            let ctx = match self.token_manager.acquire_context_avoiding(&avoid).await {
                 Ok(c) => c,
                 Err(e) => {
+                    timer.acquired(None);
+                    audit.record(None, None, Duration::ZERO, AttemptClass::NoCredential);
                     last_error = Some(e);
                     continue;
                 }
             };
+            timer.acquired(Some(ctx.id));
 
-            let url = self.base_url();
-            let headers = match self.build_headers(&ctx) {
+            let region = match self.resolve_region(&ctx, options) {
+                Ok(region) => region,
+                Err(e) => {
+                    audit.record(
+                        Some(ctx.id),
+                        None,
+                        Duration::ZERO,
+                        AttemptClass::RegionMismatch,
+                    );
+                    last_error = Some(e);
+                    // 已避开过该凭据仍被选中：没有区域一致的凭据
+                    if rejected.contains(&ctx.id) {
+                        break;
+                    }
+                    tracing::warn!("凭据 #{} 区域不符，尝试其他凭据", ctx.id);
+                    rejected.push(ctx.id);
+                    avoid.push(ctx.id);
+                    continue;
+                }
+            };
+            let url = self.credential_api_url(&ctx.credentials, &region);
+            let sdk = SdkRequest {
+                invocation_id: &invocation_id,
+                attempt,
+                max_attempts: max_retries,
+            };
+            let mut headers = match self.build_headers(&ctx, &region, &sdk) {
                 Ok(h) => h,
                 Err(e) => {
+                    audit.record(
+                        Some(ctx.id),
+                        None,
+                        Duration::ZERO,
+                        AttemptClass::InvalidHeaders,
+                    );
                     last_error = Some(e);
                     continue;
                 }
             };
 
-            // 发送请求
+            timer.headers_built();
+
+            let info = AttemptInfo {
+                kind,
+                attempt,
+                max_attempts: max_retries,
+                invocation_id: &invocation_id,
+                credential_id: ctx.id,
+                region: &region,
+                url: &url,
+            };
+            for interceptor in &self.interceptors {
+                interceptor.on_request(&info, &mut headers);
+            }
+            self.audit_headers(kind, &headers);
+
+            // 发送请求（学到上游限额后先按需节流）
+            if let Some(pacer) = &self.rate_pacer {
+                pacer.pace(ctx.id).await;
+            }
+            timer.paced();
+            let attempt_started = Instant::now();
             let response = match self
                 .client
                 .post(&url)
@@ -397,16 +1262,18 @@ impl KiroProvider {
             {
                 Ok(resp) => resp,
                 Err(e) => {
-                    tracing::warn!(
-                        "API 请求发送失败（尝试 {}/{}）: {}",
-                        attempt + 1,
-                        max_retries,
-                        e
+                    audit.record(
+                        Some(ctx.id),
+                        None,
+                        attempt_started.elapsed(),
+                        AttemptClass::Network,
                     );
+                    tracing::warn!("API 请求发送失败: {}", e);
                     // 网络错误通常是上游/链路瞬态问题，不应导致"禁用凭据"或"切换凭据"
                     // （否则一段时间网络抖动会把所有凭据都误禁用，需要重启才能恢复）
                     last_error = Some(e.into());
                     if attempt + 1 < max_retries {
+                        timer.finish();
                         sleep(Self::retry_delay(attempt)).await;
                     }
                     continue;
@@ -414,121 +1281,135 @@ impl KiroProvider {
             };
 
             let status = response.status();
+            timer.responded(status);
+            for interceptor in &self.interceptors {
+                interceptor.on_response(&info, status, attempt_started.elapsed());
+            }
+            if status.as_u16() == 429
+                && let Some(pacer) = &self.rate_pacer
+            {
+                pacer.record_throttle(ctx.id);
+            }
 
             // 成功响应
             if status.is_success() {
                 self.token_manager.report_success(ctx.id);
-                return Ok(response);
+                let mut response = response;
+                response.extensions_mut().insert(ServedCredential(ctx.id));
+                return Ok(attach_lease(response, ctx.lease.clone()));
             }
 
             // 失败响应：读取 body 用于日志/错误信息
+            let error_type = AwsError::header_value(response.headers());
             let body = response.text().await.unwrap_or_default();
+            let quota_exhausted = status.as_u16() == 402 && Self::is_monthly_request_limit(&body);
+            let aws_action = matches!(status.as_u16(), 401 | 403)
+                .then(|| self.aws_error_action(error_type.as_deref(), &body));
+            audit.record(
+                Some(ctx.id),
+                Some(status),
+                attempt_started.elapsed(),
+                match aws_action {
+                    Some(AwsErrorAction::Retry) => AttemptClass::Transient,
+                    _ => AttemptClass::from_status(status, quota_exhausted),
+                },
+            );
 
             // 402 Payment Required 且额度用尽：禁用凭据并故障转移
-            if status.as_u16() == 402 && Self::is_monthly_request_limit(&body) {
+            if quota_exhausted {
                 tracing::warn!(
-                    "API 请求失败（额度已用尽，禁用凭据并切换，尝试 {}/{}）: {} {}",
-                    attempt + 1,
-                    max_retries,
+                    "API 请求失败（额度已用尽，禁用凭据并切换）: {} {}",
                     status,
-                    body
+                    clip(limit, &body)
                 );
 
                 let has_available = self.token_manager.report_quota_exhausted(ctx.id);
                 if !has_available {
-                    anyhow::bail!(
-                        "{} API 请求失败（所有凭据已用尽）: {} {}",
-                        api_type,
-                        status,
-                        body
-                    );
+                    return Err(Self::upstream_error(api_type, status, &body, true));
                 }
 
-                last_error = Some(anyhow::anyhow!(
-                    "{} API 请求失败: {} {}",
-                    api_type,
-                    status,
-                    body
-                ));
+                last_error = Some(Self::upstream_error(api_type, status, &body, false));
                 continue;
             }
 
             // 400 Bad Request - 请求问题，重试/切换凭据无意义
             if status.as_u16() == 400 {
-                anyhow::bail!("{} API 请求失败: {} {}", api_type, status, body);
+                return Err(Self::upstream_error(api_type, status, &body, false));
             }
 
-            // 401/403 - 更可能是凭据/权限问题：计入失败并允许故障转移
-            if matches!(status.as_u16(), 401 | 403) {
+            // 401/403 - 更可能是凭据/权限问题：按 AWS 错误码决定处理方式，
+            // 未命中规则时计入失败并允许故障转移
+            if let Some(action) = aws_action {
+                if action == AwsErrorAction::Retry {
+                    tracing::warn!(
+                        "API 请求失败（错误码判定为瞬态错误）: {} {}",
+                        status,
+                        clip(limit, &body)
+                    );
+                    last_error = Some(Self::upstream_error(api_type, status, &body, false));
+                    if attempt + 1 < max_retries {
+                        timer.finish();
+                        sleep(Self::retry_delay(attempt)).await;
+                    }
+                    continue;
+                }
+                if action == AwsErrorAction::Fail {
+                    return Err(Self::upstream_error(api_type, status, &body, false));
+                }
+
                 tracing::warn!(
-                    "API 请求失败（可能为凭据错误，尝试 {}/{}）: {} {}",
-                    attempt + 1,
-                    max_retries,
+                    "API 请求失败（可能为凭据错误）: {} {}",
                     status,
-                    body
+                    clip(limit, &body)
                 );
 
-                let has_available = self.token_manager.report_failure(ctx.id);
+                let has_available = if action == AwsErrorAction::Disable {
+                    self.token_manager.report_access_denied(ctx.id)
+                } else {
+                    self.token_manager.report_failure(ctx.id)
+                };
                 if !has_available {
-                    anyhow::bail!(
-                        "{} API 请求失败（所有凭据已用尽）: {} {}",
-                        api_type,
-                        status,
-                        body
-                    );
+                    return Err(Self::upstream_error(api_type, status, &body, true));
                 }
 
-                last_error = Some(anyhow::anyhow!(
-                    "{} API 请求失败: {} {}",
-                    api_type,
-                    status,
-                    body
-                ));
+                last_error = Some(Self::upstream_error(api_type, status, &body, false));
                 continue;
             }
 
-            // 429/408/5xx - 瞬态上游错误：重试但不禁用或切换凭据
-            // （避免 429 high traffic / 502 high load 等瞬态错误把所有凭据锁死）
+            // 429/408/5xx - 瞬态上游错误：重试但不禁用凭据
+            // （避免 429 high traffic / 502 high load 等瞬态错误把所有凭据锁死），
+            // 按 AWS 异常类型区分退避，配额超限时切换凭据
             if matches!(status.as_u16(), 408 | 429) || status.is_server_error() {
+                let throttle = AwsError::parse(error_type.as_deref(), &body).throttle_kind();
                 tracing::warn!(
-                    "API 请求失败（上游瞬态错误，尝试 {}/{}）: {} {}",
-                    attempt + 1,
-                    max_retries,
+                    "API 请求失败（上游{}）: {} {}",
+                    throttle.as_str(),
                     status,
-                    body
+                    clip(limit, &body)
                 );
-                last_error = Some(anyhow::anyhow!(
-                    "{} API 请求失败: {} {}",
-                    api_type,
-                    status,
-                    body
-                ));
+                last_error = Some(Self::upstream_error(api_type, status, &body, false));
                 if attempt + 1 < max_retries {
-                    sleep(Self::retry_delay(attempt)).await;
+                    timer.finish();
+                    self.transient_backoff(throttle, attempt, ctx.id, &mut avoid)
+                        .await;
                 }
                 continue;
             }
 
             // 其他 4xx - 通常为请求/配置问题：直接返回，不计入凭据失败
             if status.is_client_error() {
-                anyhow::bail!("{} API 请求失败: {} {}", api_type, status, body);
+                return Err(Self::upstream_error(api_type, status, &body, false));
             }
 
             // 兜底：当作可重试的瞬态错误处理（不切换凭据）
             tracing::warn!(
-                "API 请求失败（未知错误，尝试 {}/{}）: {} {}",
-                attempt + 1,
-                max_retries,
+                "API 请求失败（未知错误）: {} {}",
                 status,
-                body
+                clip(limit, &body)
             );
-            last_error = Some(anyhow::anyhow!(
-                "{} API 请求失败: {} {}",
-                api_type,
-                status,
-                body
-            ));
+            last_error = Some(Self::upstream_error(api_type, status, &body, false));
             if attempt + 1 < max_retries {
+                timer.finish();
                 sleep(Self::retry_delay(attempt)).await;
             }
         }
@@ -536,19 +1417,89 @@ impl KiroProvider {
         // 所有重试都失败
         Err(last_error.unwrap_or_else(|| {
             anyhow::anyhow!(
-                "{} API 请求失败：已达到最大重试次数（{}次）",
+                "{} 请求失败：已达到最大重试次数（{}次）",
                 api_type,
                 max_retries
             )
         }))
     }
 
+    /// 上游错误响应（日志中按 `logContent` 截断错误体）
+    fn upstream_error(
+        api_type: &str,
+        status: reqwest::StatusCode,
+        body: &str,
+        exhausted: bool,
+    ) -> anyhow::Error {
+        let message = if exhausted {
+            format!("{} 请求失败（所有凭据已用尽）", api_type)
+        } else {
+            format!("{} 请求失败", api_type)
+        };
+        UpstreamError::new(message, status, body).into()
+    }
+
+    /// 本次调用在日志中保留的内容长度（隐私模式下只记录长度）
+    pub fn log_limit(&self, options: &CallOptions) -> LogContentLimit {
+        if !self.capabilities.transcripts {
+            return LogContentLimit::default();
+        }
+        options
+            .log_content
+            .unwrap_or(self.token_manager.config().log_content.max_chars)
+    }
+
+    /// 按 `awsErrorRules` 决定 401/403 的处理方式
+    fn aws_error_action(&self, error_type_header: Option<&str>, body: &str) -> AwsErrorAction {
+        AwsError::parse(error_type_header, body).action(&self.token_manager.config().aws_error_rules)
+    }
+
+    /// 调用最终失败时通知拦截器
+    fn notify_error(&self, kind: CallKind, result: &anyhow::Result<reqwest::Response>) {
+        if let Err(e) = result {
+            for interceptor in &self.interceptors {
+                interceptor.on_error(kind, e);
+            }
+        }
+    }
+
+    /// 瞬态错误重试前的等待
+    ///
+    /// - `ServiceQuotaExceededException`：该凭据配额超限，之后的尝试避开该凭据并立即重试
+    ///   （只有一个凭据时常规退避）
+    /// - `ModelNotReadyException`：模型尚未就绪，按更长的退避等待
+    /// - 其他（含 `ThrottlingException`）：常规指数退避
+    async fn transient_backoff(
+        &self,
+        throttle: ThrottleKind,
+        attempt: usize,
+        credential_id: u64,
+        avoid: &mut Vec<u64>,
+    ) {
+        match throttle {
+            ThrottleKind::QuotaExceeded if self.token_manager.total_count() > 1 => {
+                if !avoid.contains(&credential_id) {
+                    avoid.push(credential_id);
+                }
+            }
+            ThrottleKind::ModelNotReady => sleep(Self::model_not_ready_delay(attempt)).await,
+            _ => sleep(Self::retry_delay(attempt)).await,
+        }
+    }
+
     fn retry_delay(attempt: usize) -> Duration {
+        Self::backoff(attempt, 200, 2_000)
+    }
+
+    /// 模型尚未就绪时的退避（通常需要数秒到十几秒）
+    fn model_not_ready_delay(attempt: usize) -> Duration {
+        Self::backoff(attempt, 2_000, 15_000)
+    }
+
+    fn backoff(attempt: usize, base_ms: u64, max_ms: u64) -> Duration {
         // 指数退避 + 少量抖动，避免上游抖动时放大故障
-        const BASE_MS: u64 = 200;
-        const MAX_MS: u64 = 2_000;
-        let exp = BASE_MS.saturating_mul(2u64.saturating_pow(attempt.min(6) as u32));
-        let backoff = exp.min(MAX_MS);
+        let exp = base_ms.saturating_mul(2u64.saturating_pow(attempt.min(6) as u32));
+        let backoff = exp.min(max_ms);
         let jitter_max = (backoff / 4).max(1);
         let jitter = fastrand::u64(0..=jitter_max);
         Duration::from_millis(backoff.saturating_add(jitter))
@@ -584,27 +1535,388 @@ mod tests {
     use crate::kiro::token_manager::CallContext;
     use crate::model::config::Config;
 
+    /// 首次尝试的调用标识
+    const FIRST_ATTEMPT: SdkRequest<'static> = SdkRequest {
+        invocation_id: "00000000-0000-0000-0000-000000000000",
+        attempt: 0,
+        max_attempts: 3,
+    };
+
     fn create_test_provider(config: Config, credentials: KiroCredentials) -> KiroProvider {
         let tm = MultiTokenManager::new(config, vec![credentials], None, None, false).unwrap();
-        KiroProvider::new(Arc::new(tm))
+        KiroProvider::builder(Arc::new(tm)).build().unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_shadow_mirror_runs_in_background() {
+        let config = Config {
+            shadow: crate::model::config::ShadowConfig {
+                enabled: true,
+                percentage: 100.0,
+                region: None,
+                endpoint: Some("http://127.0.0.1:9/generateAssistantResponse".to_string()),
+                timeout_secs: 2,
+            },
+            ..Config::default()
+        };
+        let credentials = KiroCredentials {
+            refresh_token: Some("a".repeat(150)),
+            access_token: Some("token".to_string()),
+            expires_at: Some((chrono::Utc::now() + chrono::Duration::hours(1)).to_rfc3339()),
+            ..Default::default()
+        };
+        let provider = create_test_provider(config, credentials);
+        let shadow = provider.shadow().unwrap();
+
+        // 获取凭据与发送都在后台任务中完成，调用方不等待
+        let primary = Err(anyhow::anyhow!("primary failed"));
+        provider.mirror_to_shadow("{}", &CallOptions::default(), Instant::now(), &primary);
+        assert_eq!(shadow.report().samples, 0);
+        for _ in 0..100 {
+            if shadow.report().samples > 0 {
+                break;
+            }
+            sleep(Duration::from_millis(50)).await;
+        }
+        let report = shadow.report();
+        assert_eq!(report.samples, 1);
+        assert_eq!(report.shadow_errors, 1);
+    }
+
+    #[tokio::test]
+    async fn test_lease_held_until_body_consumed() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let in_flight = Arc::new(AtomicUsize::new(0));
+        let mut response: reqwest::Response =
+            http::Response::new(reqwest::Body::from("body")).into();
+        response.extensions_mut().insert(ServedCredential(7));
+        let response = attach_lease(response, Some(CallLease::acquire(&in_flight)));
+
+        // 收到响应头后仍计入进行中调用，直到响应体读完
+        assert_eq!(in_flight.load(Ordering::Relaxed), 1);
+        assert_eq!(ServedCredential::of(&response), Some(7));
+        assert_eq!(response.text().await.unwrap(), "body");
+        assert_eq!(in_flight.load(Ordering::Relaxed), 0);
+    }
+
+    #[test]
+    fn test_builder_rejects_invalid_proxy() {
+        let tm = MultiTokenManager::new(Config::default(), vec![], None, None, false).unwrap();
+        let result = KiroProvider::builder(Arc::new(tm))
+            .proxy(Some(ProxyConfig::new("not a url")))
+            .build();
+        let Err(err) = result else {
+            panic!("无效代理地址应构建失败");
+        };
+        assert!(matches!(err, ProviderBuildError::InvalidProxy { .. }));
+        assert!(err.to_string().contains("proxyUrl"));
+    }
+
+    #[test]
+    fn test_retry_policy() {
+        assert_eq!(RetryPolicy::parse(" None "), Some(RetryPolicy::Off));
+        assert_eq!(RetryPolicy::parse("default"), Some(RetryPolicy::Standard));
+        assert_eq!(
+            RetryPolicy::parse("aggressive"),
+            Some(RetryPolicy::Aggressive)
+        );
+        assert_eq!(RetryPolicy::parse("always"), None);
+
+        assert_eq!(RetryPolicy::Standard.max_attempts(2), 6);
+        assert_eq!(RetryPolicy::Standard.max_attempts(5), MAX_TOTAL_RETRIES);
+        assert_eq!(RetryPolicy::Off.max_attempts(5), 1);
+        assert_eq!(RetryPolicy::Aggressive.max_attempts(2), 10);
+        assert_eq!(RetryPolicy::Aggressive.max_attempts(5), 15);
     }
 
     #[test]
     fn test_base_url() {
         let config = Config::default();
         let credentials = KiroCredentials::default();
-        let provider = create_test_provider(config, credentials);
-        assert!(provider.base_url().contains("amazonaws.com"));
-        assert!(provider.base_url().contains("generateAssistantResponse"));
+        let provider = create_test_provider(config, credentials.clone());
+        let url = provider.credential_api_url(&credentials, "us-east-1");
+        assert!(url.contains("amazonaws.com"));
+        assert!(url.contains("generateAssistantResponse"));
     }
 
     #[test]
     fn test_base_domain() {
+        let provider = create_test_provider(Config::default(), KiroCredentials::default());
+        assert_eq!(
+            provider.base_domain_for("us-east-1"),
+            "q.us-east-1.amazonaws.com"
+        );
+    }
+
+    #[test]
+    fn test_region_override_changes_endpoint_and_host() {
+        let credentials = KiroCredentials {
+            refresh_token: Some("a".repeat(150)),
+            ..Default::default()
+        };
+        let provider = create_test_provider(Config::default(), credentials.clone());
+
+        let default_options = CallOptions::default();
+        assert_eq!(
+            provider.api_region(&default_options),
+            provider.token_manager().config().region
+        );
+
+        let options = CallOptions::default().with_region("eu-central-1");
+        let region = provider.api_region(&options);
+        assert_eq!(region, "eu-central-1");
+        assert_eq!(
+            provider.credential_api_url(&credentials, region),
+            "https://q.eu-central-1.amazonaws.com/generateAssistantResponse"
+        );
+        assert_eq!(
+            provider.credential_mcp_url(&credentials, region),
+            "https://q.eu-central-1.amazonaws.com/mcp"
+        );
+
+        let ctx = CallContext {
+            id: 1,
+            credentials,
+            token: "test_token".to_string(),
+            lease: None,
+        };
+        let headers = provider
+            .build_headers(&ctx, region, &FIRST_ATTEMPT)
+            .unwrap();
+        assert_eq!(headers.get(HOST).unwrap(), "q.eu-central-1.amazonaws.com");
+    }
+
+    #[test]
+    fn test_credential_endpoint_override() {
+        let provider = create_test_provider(Config::default(), KiroCredentials::default());
+        let credentials = KiroCredentials {
+            refresh_token: Some("a".repeat(150)),
+            api_endpoint: Some("https://kiro-gw.corp.example:8443/{region}/".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            provider.credential_api_url(&credentials, "eu-central-1"),
+            "https://kiro-gw.corp.example:8443/eu-central-1/generateAssistantResponse"
+        );
+        assert_eq!(
+            provider.credential_mcp_url(&credentials, "eu-central-1"),
+            "https://kiro-gw.corp.example:8443/eu-central-1/mcp"
+        );
+
+        let ctx = CallContext {
+            id: 1,
+            credentials,
+            token: "test_token".to_string(),
+            lease: None,
+        };
+        let headers = provider
+            .build_headers(&ctx, "eu-central-1", &FIRST_ATTEMPT)
+            .unwrap();
+        assert_eq!(headers.get(HOST).unwrap(), "kiro-gw.corp.example:8443");
+        let headers = provider
+            .build_mcp_headers(&ctx, "eu-central-1", &FIRST_ATTEMPT)
+            .unwrap();
+        assert_eq!(headers.get("host").unwrap(), "kiro-gw.corp.example:8443");
+
+        // 未配置时保持 AWS 公网地址
+        let default = KiroCredentials::default();
+        assert_eq!(
+            provider.credential_api_url(&default, "us-east-1"),
+            "https://q.us-east-1.amazonaws.com/generateAssistantResponse"
+        );
+        assert_eq!(
+            provider.host_for(&default, "us-east-1"),
+            "q.us-east-1.amazonaws.com"
+        );
+    }
+
+    #[test]
+    fn test_vpc_endpoint_keeps_public_host() {
         let mut config = Config::default();
-        config.region = "us-east-1".to_string();
-        let credentials = KiroCredentials::default();
-        let provider = create_test_provider(config, credentials);
-        assert_eq!(provider.base_domain(), "q.us-east-1.amazonaws.com");
+        config.vpc_endpoint.dns_name = Some("vpce-0abc.q.{region}.vpce.amazonaws.com".to_string());
+        let credentials = KiroCredentials {
+            refresh_token: Some("a".repeat(150)),
+            ..Default::default()
+        };
+        let provider = create_test_provider(config, credentials.clone());
+        assert_eq!(
+            provider.credential_api_url(&credentials, "us-east-1"),
+            "https://vpce-0abc.q.us-east-1.vpce.amazonaws.com/generateAssistantResponse"
+        );
+
+        let ctx = CallContext {
+            id: 1,
+            credentials,
+            token: "test_token".to_string(),
+            lease: None,
+        };
+        let headers = provider
+            .build_headers(&ctx, "us-east-1", &FIRST_ATTEMPT)
+            .unwrap();
+        assert_eq!(headers.get(HOST).unwrap(), "q.us-east-1.amazonaws.com");
+    }
+
+    #[test]
+    fn test_resolve_region_with_credential_binding() {
+        let make_ctx = |api_region: Option<&str>| CallContext {
+            id: 1,
+            credentials: KiroCredentials {
+                refresh_token: Some("a".repeat(150)),
+                api_region: api_region.map(|r| r.to_string()),
+                ..Default::default()
+            },
+            token: "test_token".to_string(),
+            lease: None,
+        };
+        let options = CallOptions::default().with_region("us-east-1");
+
+        let provider = create_test_provider(Config::default(), KiroCredentials::default());
+        // 未绑定：使用请求区域
+        assert_eq!(
+            provider.resolve_region(&make_ctx(None), &options).unwrap(),
+            "us-east-1"
+        );
+        // 默认策略：自动纠正为凭据区域
+        assert_eq!(
+            provider
+                .resolve_region(&make_ctx(Some("eu-central-1")), &options)
+                .unwrap(),
+            "eu-central-1"
+        );
+
+        let config = Config {
+            region_mismatch_policy: RegionMismatchPolicy::Reject,
+            ..Default::default()
+        };
+        let provider = create_test_provider(config, KiroCredentials::default());
+        assert!(
+            provider
+                .resolve_region(&make_ctx(Some("eu-central-1")), &options)
+                .is_err()
+        );
+        assert!(
+            provider
+                .resolve_region(&make_ctx(Some("us-east-1")), &options)
+                .is_ok()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_region_mismatch_fails_over_to_matching_credential() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let mut buf = [0u8; 4096];
+                let _ = socket.read(&mut buf).await;
+                let response = "HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nok";
+                let _ = socket.write_all(response.as_bytes()).await;
+            }
+        });
+
+        let credential = |api_region: &str| KiroCredentials {
+            refresh_token: Some("a".repeat(150)),
+            access_token: Some("token".to_string()),
+            expires_at: Some((chrono::Utc::now() + chrono::Duration::hours(1)).to_rfc3339()),
+            api_region: Some(api_region.to_string()),
+            api_endpoint: Some(format!("http://{}/", addr)),
+            ..Default::default()
+        };
+        let config = Config {
+            region_mismatch_policy: RegionMismatchPolicy::Reject,
+            ..Default::default()
+        };
+        let options = CallOptions::default().with_region("us-east-1");
+        let tm = MultiTokenManager::new(
+            config.clone(),
+            vec![credential("eu-central-1"), credential("us-east-1")],
+            None,
+            None,
+            false,
+        )
+        .unwrap();
+        let provider = KiroProvider::builder(Arc::new(tm)).build().unwrap();
+
+        // 区域不符的凭据被跳过，预演与实际调用都改用区域一致的凭据
+        assert_eq!(provider.plan_api_call("{}", &options).unwrap().credential_id, 2);
+        let response = provider.call_api("{}", &options).await.unwrap();
+        assert_eq!(ServedCredential::of(&response), Some(2));
+
+        // 没有区域一致的凭据时记录在尝试记录中并失败
+        let provider = create_test_provider(config, credential("eu-central-1"));
+        assert!(provider.plan_api_call("{}", &options).is_err());
+        let err = provider.call_api("{}", &options).await.unwrap_err();
+        assert!(err.to_string().contains("region_mismatch"), "{}", err);
+    }
+
+    #[tokio::test]
+    async fn test_quota_exceeded_moves_to_another_credential_under_least_loaded() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        // 依次启动两个上游：第一个总是返回配额超限，第二个返回成功
+        async fn upstream(response: String) -> std::net::SocketAddr {
+            let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let addr = listener.local_addr().unwrap();
+            tokio::spawn(async move {
+                while let Ok((mut socket, _)) = listener.accept().await {
+                    let mut buf = [0u8; 4096];
+                    let _ = socket.read(&mut buf).await;
+                    let _ = socket.write_all(response.as_bytes()).await;
+                }
+            });
+            addr
+        }
+        let body = r#"{"__type":"com.amazon.aws.codewhisperer#ServiceQuotaExceededException"}"#;
+        let throttled = upstream(format!(
+            "HTTP/1.1 429 Too Many Requests\r\nContent-Length: {}\r\n\r\n{}",
+            body.len(),
+            body
+        ))
+        .await;
+        let ok = upstream("HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nok".to_string()).await;
+
+        let credential = |addr: std::net::SocketAddr| KiroCredentials {
+            refresh_token: Some("a".repeat(150)),
+            access_token: Some("token".to_string()),
+            expires_at: Some((chrono::Utc::now() + chrono::Duration::hours(1)).to_rfc3339()),
+            api_endpoint: Some(format!("http://{}/", addr)),
+            ..Default::default()
+        };
+        let config = Config {
+            selection_strategy: crate::model::config::SelectionStrategy::LeastLoaded,
+            ..Default::default()
+        };
+        let tm = MultiTokenManager::new(
+            config,
+            vec![credential(throttled), credential(ok)],
+            None,
+            None,
+            false,
+        )
+        .unwrap();
+        let provider = KiroProvider::builder(Arc::new(tm)).build().unwrap();
+
+        let response = provider
+            .call_api("{}", &CallOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(ServedCredential::of(&response), Some(2));
+    }
+
+    #[test]
+    fn test_is_valid_region() {
+        assert!(is_valid_region("us-east-1"));
+        assert!(is_valid_region("eu-central-1"));
+        assert!(!is_valid_region(""));
+        assert!(!is_valid_region("US-EAST-1"));
+        assert!(!is_valid_region("evil.com/x"));
+        assert!(is_valid_endpoint("https://kiro-gw.corp.example/{region}"));
+        assert!(is_valid_endpoint("http://10.0.0.8:8080"));
+        assert!(!is_valid_endpoint("kiro-gw.corp.example"));
+        assert!(!is_valid_endpoint("ftp://kiro-gw.corp.example"));
     }
 
     #[test]
@@ -622,8 +1934,11 @@ mod tests {
             id: 1,
             credentials,
             token: "test_token".to_string(),
+            lease: None,
         };
-        let headers = provider.build_headers(&ctx).unwrap();
+        let headers = provider
+            .build_headers(&ctx, "us-east-1", &FIRST_ATTEMPT)
+            .unwrap();
 
         assert_eq!(headers.get(CONTENT_TYPE).unwrap(), "application/json");
         assert_eq!(headers.get("x-amzn-codewhisperer-optout").unwrap(), "true");
@@ -637,6 +1952,101 @@ mod tests {
                 .starts_with("Bearer ")
         );
         assert_eq!(headers.get(CONNECTION).unwrap(), "close");
+
+        let config = Config {
+            codewhisperer_optout: false,
+            ..Config::default()
+        };
+        let provider = create_test_provider(config, ctx.credentials.clone());
+        let headers = provider
+            .build_headers(&ctx, "us-east-1", &FIRST_ATTEMPT)
+            .unwrap();
+        assert_eq!(headers.get("x-amzn-codewhisperer-optout").unwrap(), "false");
+    }
+
+    #[test]
+    fn test_built_headers_match_reference_capture() {
+        let credentials = KiroCredentials {
+            refresh_token: Some("a".repeat(150)),
+            ..Default::default()
+        };
+
+        let provider = create_test_provider(Config::default(), credentials.clone());
+        let ctx = CallContext {
+            id: 1,
+            credentials,
+            token: "test_token".to_string(),
+            lease: None,
+        };
+        let reference = HeaderAuditReference::default();
+
+        let headers = provider
+            .build_headers(&ctx, "us-east-1", &FIRST_ATTEMPT)
+            .unwrap();
+        assert!(header_audit::compare(&headers, &reference.api).is_empty());
+
+        let headers = provider
+            .build_mcp_headers(&ctx, "us-east-1", &FIRST_ATTEMPT)
+            .unwrap();
+        assert!(header_audit::compare(&headers, &reference.mcp).is_empty());
+    }
+
+    #[test]
+    fn test_sdk_request_headers_per_attempt() {
+        let credentials = KiroCredentials {
+            refresh_token: Some("a".repeat(150)),
+            ..Default::default()
+        };
+        let provider = create_test_provider(Config::default(), credentials.clone());
+        let ctx = CallContext {
+            id: 1,
+            credentials,
+            token: "test_token".to_string(),
+            lease: None,
+        };
+        let names = |headers: &HeaderMap| headers.keys().map(|k| k.to_string()).collect::<Vec<_>>();
+
+        let invocation_id = Uuid::new_v4().to_string();
+        let sdk = |attempt| SdkRequest {
+            invocation_id: &invocation_id,
+            attempt,
+            max_attempts: 6,
+        };
+        let first = provider.build_headers(&ctx, "us-east-1", &sdk(0)).unwrap();
+        let retry = provider.build_headers(&ctx, "us-east-1", &sdk(2)).unwrap();
+
+        // 重试共用 invocation id，尝试序号递增，请求头顺序不变
+        assert_eq!(first["amz-sdk-invocation-id"], invocation_id.as_str());
+        assert_eq!(retry["amz-sdk-invocation-id"], invocation_id.as_str());
+        assert_eq!(first.get("amz-sdk-request").unwrap(), "attempt=1; max=6");
+        assert_eq!(retry.get("amz-sdk-request").unwrap(), "attempt=3; max=6");
+        assert_eq!(names(&retry), names(&first));
+    }
+
+    #[test]
+    fn test_build_headers_rejects_invalid_token() {
+        let credentials = KiroCredentials {
+            refresh_token: Some("a".repeat(150)),
+            ..Default::default()
+        };
+        let provider = create_test_provider(Config::default(), credentials.clone());
+        let ctx = CallContext {
+            id: 1,
+            credentials,
+            token: "secret\ntoken".to_string(),
+            lease: None,
+        };
+
+        let err = provider
+            .build_headers(&ctx, "us-east-1", &FIRST_ATTEMPT)
+            .unwrap_err();
+        assert!(err.to_string().contains("authorization"));
+        assert!(!err.to_string().contains("secret"));
+        assert!(
+            provider
+                .build_mcp_headers(&ctx, "us-east-1", &FIRST_ATTEMPT)
+                .is_err()
+        );
     }
 
     #[test]
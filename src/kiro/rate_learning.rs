@@ -0,0 +1,340 @@
+//! 从上游 429 学习限流窗口
+//!
+//! 上游的限流规则不公开。这里按凭据记录每个请求的发送时间，收到 429 时统计各候选窗口（10s、30s、
+//! 60s、300s）内上游已接受的请求数；观察到 `minSamples` 次 429 后，取各次计数最稳定（变异系数最小）
+//! 的窗口作为有效窗口，其中最小的计数即学到的限额。之后该凭据的请求在窗口内按 `限额 × headroom`
+//! 节流，稳定地停在限额之下而不是反复撞上 429；超过 `forgetAfterSecs` 没有再收到 429 时忘记学到的限额。
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use parking_lot::Mutex;
+use serde::Serialize;
+
+use crate::model::config::{Config, RateLearningConfig};
+
+/// 候选的上游限流窗口（秒）
+const CANDIDATE_WINDOWS_SECS: [u64; 4] = [10, 30, 60, 300];
+
+/// 发送时间保留的时长（最大候选窗口）
+const HORIZON: Duration = Duration::from_secs(300);
+
+/// 每个凭据保留的 429 样本数
+const MAX_SAMPLES: usize = 20;
+
+/// 一次 429
+struct Throttle {
+    at: Instant,
+    /// 各候选窗口内上游已接受的请求数（不含被限流的这次）
+    counts: [usize; CANDIDATE_WINDOWS_SECS.len()],
+}
+
+/// 学到的限流
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Learned {
+    window: Duration,
+    limit: usize,
+    /// 节流目标（窗口内最多发送的请求数）
+    target: usize,
+}
+
+impl Learned {
+    /// 发送下一个请求前需要等待的时长
+    fn delay(&self, sent: &VecDeque<Instant>, now: Instant) -> Duration {
+        let mut in_window: Vec<Instant> = sent
+            .iter()
+            .copied()
+            .filter(|&t| t + self.window > now)
+            .collect();
+        if in_window.len() < self.target {
+            return Duration::ZERO;
+        }
+        in_window.sort();
+        // 等到窗口内只剩 target - 1 个请求
+        (in_window[in_window.len() - self.target] + self.window).saturating_duration_since(now)
+    }
+}
+
+/// 单个凭据的发送记录
+#[derive(Default)]
+struct CredentialRate {
+    /// 已发送（或已预约发送）且未被限流的请求
+    sent: VecDeque<Instant>,
+    throttles: VecDeque<Throttle>,
+    learned: Option<Learned>,
+    paced_requests: u64,
+    paced_ms: u64,
+}
+
+impl CredentialRate {
+    /// 丢弃过期的发送记录；长时间没有 429 时忘记学到的限额
+    fn expire(&mut self, id: u64, now: Instant, forget_after: Duration) {
+        while self
+            .sent
+            .front()
+            .is_some_and(|&t| now.saturating_duration_since(t) >= HORIZON)
+        {
+            self.sent.pop_front();
+        }
+        if self
+            .throttles
+            .back()
+            .is_some_and(|t| now.saturating_duration_since(t.at) >= forget_after)
+        {
+            self.throttles.clear();
+            if self.learned.take().is_some() {
+                tracing::info!("凭据 #{} 长时间未被限流，取消自动节流", id);
+            }
+        }
+    }
+}
+
+/// 各窗口内（不晚于 now）的请求数
+fn count_within(sent: &VecDeque<Instant>, now: Instant, window: Duration) -> usize {
+    sent.iter()
+        .filter(|&&t| t <= now && now.duration_since(t) < window)
+        .count()
+}
+
+/// 取各次计数最稳定的窗口，其最小计数为限额
+fn estimate(throttles: &VecDeque<Throttle>, headroom: f64) -> Option<Learned> {
+    let mut best: Option<(f64, usize)> = None;
+    for i in 0..CANDIDATE_WINDOWS_SECS.len() {
+        let counts: Vec<f64> = throttles.iter().map(|t| t.counts[i] as f64).collect();
+        let mean = counts.iter().sum::<f64>() / counts.len() as f64;
+        // 窗口内几乎没有请求，不可能是触发限流的窗口
+        if mean < 1.0 {
+            continue;
+        }
+        let variance = counts.iter().map(|c| (c - mean).powi(2)).sum::<f64>() / counts.len() as f64;
+        let cv = variance.sqrt() / mean;
+        // 同样稳定时取较短的窗口（节流更平滑）
+        if best.is_none_or(|(best_cv, _)| cv < best_cv - 1e-9) {
+            best = Some((cv, i));
+        }
+    }
+    let (_, i) = best?;
+    let limit = throttles.iter().map(|t| t.counts[i]).min()?.max(1);
+    Some(Learned {
+        window: Duration::from_secs(CANDIDATE_WINDOWS_SECS[i]),
+        limit,
+        target: ((limit as f64 * headroom).floor() as usize).max(1),
+    })
+}
+
+/// 单个凭据的限流学习状态（Admin 可见）
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CredentialRateStats {
+    pub id: u64,
+    /// 最近 60 秒发送的请求数
+    pub recent_requests: usize,
+    /// 保留的 429 样本数
+    pub throttles: usize,
+    pub last_throttle_secs_ago: Option<u64>,
+    /// 学到的限流窗口（秒）
+    pub window_secs: Option<u64>,
+    /// 学到的窗口内限额
+    pub learned_limit: Option<usize>,
+    /// 节流目标
+    pub target: Option<usize>,
+    /// 被节流等待过的请求数
+    pub paced_requests: u64,
+    /// 累计节流等待（毫秒）
+    pub paced_ms: u64,
+}
+
+/// 限流学习报告
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RateLearningReport {
+    pub enabled: bool,
+    pub headroom: f64,
+    pub min_samples: usize,
+    /// 按凭据 ID 排序
+    pub credentials: Vec<CredentialRateStats>,
+}
+
+impl RateLearningReport {
+    /// 未开启时的空报告
+    pub fn empty(config: &RateLearningConfig) -> Self {
+        Self {
+            enabled: false,
+            headroom: config.headroom,
+            min_samples: config.min_samples,
+            credentials: Vec::new(),
+        }
+    }
+}
+
+/// 按凭据学习上游限流并节流
+pub struct RatePacer {
+    config: RateLearningConfig,
+    credentials: Mutex<HashMap<u64, CredentialRate>>,
+}
+
+impl RatePacer {
+    /// 从配置创建（未开启 `rateLearning` 时返回 None）
+    pub fn from_config(config: &Config) -> Option<Arc<Self>> {
+        config.rate_learning.enabled.then(|| {
+            Arc::new(Self {
+                config: config.rate_learning.clone(),
+                credentials: Mutex::new(HashMap::new()),
+            })
+        })
+    }
+
+    /// 发送请求前调用：学到限额后按需等待
+    pub async fn pace(&self, id: u64) {
+        let delay = self.reserve(id, Instant::now());
+        if !delay.is_zero() {
+            tracing::debug!("凭据 #{} 自动节流 {}ms", id, delay.as_millis());
+            tokio::time::sleep(delay).await;
+        }
+    }
+
+    /// 上游返回 429 时调用
+    pub fn record_throttle(&self, id: u64) {
+        self.throttled(id, Instant::now());
+    }
+
+    /// 预约发送时间，返回需要等待的时长
+    fn reserve(&self, id: u64, now: Instant) -> Duration {
+        let forget_after = Duration::from_secs(self.config.forget_after_secs);
+        let max_delay = Duration::from_millis(self.config.max_delay_ms);
+        let mut credentials = self.credentials.lock();
+        let rate = credentials.entry(id).or_default();
+        rate.expire(id, now, forget_after);
+        let delay = rate
+            .learned
+            .map(|learned| learned.delay(&rate.sent, now))
+            .unwrap_or_default()
+            .min(max_delay);
+        if !delay.is_zero() {
+            rate.paced_requests += 1;
+            rate.paced_ms += delay.as_millis() as u64;
+        }
+        rate.sent.push_back(now + delay);
+        delay
+    }
+
+    fn throttled(&self, id: u64, now: Instant) {
+        let mut credentials = self.credentials.lock();
+        let rate = credentials.entry(id).or_default();
+        // 被限流的请求未被上游接受，不计入窗口
+        if let Some(pos) = rate.sent.iter().rposition(|&t| t <= now) {
+            rate.sent.remove(pos);
+        }
+        let counts = CANDIDATE_WINDOWS_SECS
+            .map(|secs| count_within(&rate.sent, now, Duration::from_secs(secs)));
+        rate.throttles.push_back(Throttle { at: now, counts });
+        while rate.throttles.len() > MAX_SAMPLES {
+            rate.throttles.pop_front();
+        }
+        if rate.throttles.len() < self.config.min_samples.max(1) {
+            return;
+        }
+
+        let learned = estimate(&rate.throttles, self.config.headroom);
+        if learned != rate.learned
+            && let Some(learned) = learned
+        {
+            tracing::info!(
+                "凭据 #{} 学到上游限流: {}s 内约 {} 个请求，节流到 {} 个",
+                id,
+                learned.window.as_secs(),
+                learned.limit,
+                learned.target
+            );
+        }
+        rate.learned = learned;
+    }
+
+    /// 获取快照
+    pub fn report(&self) -> RateLearningReport {
+        let now = Instant::now();
+        let mut credentials: Vec<CredentialRateStats> = self
+            .credentials
+            .lock()
+            .iter()
+            .map(|(&id, rate)| CredentialRateStats {
+                id,
+                recent_requests: count_within(&rate.sent, now, Duration::from_secs(60)),
+                throttles: rate.throttles.len(),
+                last_throttle_secs_ago: rate
+                    .throttles
+                    .back()
+                    .map(|t| now.saturating_duration_since(t.at).as_secs()),
+                window_secs: rate.learned.map(|l| l.window.as_secs()),
+                learned_limit: rate.learned.map(|l| l.limit),
+                target: rate.learned.map(|l| l.target),
+                paced_requests: rate.paced_requests,
+                paced_ms: rate.paced_ms,
+            })
+            .collect();
+        credentials.sort_by_key(|c| c.id);
+        RateLearningReport {
+            enabled: true,
+            headroom: self.config.headroom,
+            min_samples: self.config.min_samples,
+            credentials,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_learns_window_and_stays_under_limit() {
+        let config = Config {
+            rate_learning: RateLearningConfig {
+                enabled: true,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let pacer = RatePacer::from_config(&config).unwrap();
+
+        // 上游：10 秒内最多接受 5 个请求；客户端每 500ms 发起一次
+        let upstream_window = Duration::from_secs(10);
+        let start = Instant::now();
+        let mut accepted: VecDeque<Instant> = VecDeque::new();
+        let mut throttled_at = Vec::new();
+        let mut next = start;
+        for i in 0..400 {
+            let delay = pacer.reserve(1, next);
+            let sent = next + delay;
+            let in_window = accepted
+                .iter()
+                .filter(|&&t| sent.duration_since(t) < upstream_window)
+                .count();
+            if in_window >= 5 {
+                pacer.throttled(1, sent);
+                throttled_at.push(i);
+            } else {
+                accepted.push_back(sent);
+            }
+            next = sent.max(next + Duration::from_millis(500));
+        }
+
+        let report = pacer.report();
+        let stats = &report.credentials[0];
+        assert_eq!(stats.window_secs, Some(10));
+        assert_eq!(stats.learned_limit, Some(5));
+        assert_eq!(stats.target, Some(4));
+        assert!(stats.paced_requests > 0);
+        // 学到限额之后不再被限流
+        assert_eq!(throttled_at.len(), 3);
+        assert!(throttled_at.iter().all(|&i| i < 20));
+
+        // 长时间没有 429 后取消节流
+        let later = next + Duration::from_secs(3600);
+        assert_eq!(pacer.reserve(1, later), Duration::ZERO);
+        assert_eq!(pacer.report().credentials[0].learned_limit, None);
+
+        assert!(RatePacer::from_config(&Config::default()).is_none());
+    }
+}
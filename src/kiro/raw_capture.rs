@@ -0,0 +1,290 @@
+//! 上游原始帧抓取
+//!
+//! 解码器遇到未知帧时，无需重新编译加日志：通过 Admin API 指定抓取之后 N 个请求，
+//! 这些请求的上游 event-stream 原始字节会原样写入抓取目录（每个请求一个 `.bin` 文件），
+//! 单文件大小和保留文件数都有上限。
+
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::SystemTime;
+
+use chrono::{DateTime, Utc};
+use futures::StreamExt;
+use parking_lot::Mutex;
+use serde::Serialize;
+
+use crate::common::privacy::Capabilities;
+use crate::kiro::provider::ServedCredential;
+use crate::model::config::{Config, RawCaptureConfig};
+
+/// 一次最多预约的抓取请求数
+pub const MAX_ARMED: usize = 20;
+
+/// 单个抓取文件
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CaptureFile {
+    pub file: String,
+    pub time: DateTime<Utc>,
+    pub credential_id: Option<u64>,
+    pub status: u16,
+    pub bytes: usize,
+    /// 是否因超过 maxBytes 被截断
+    pub truncated: bool,
+}
+
+/// 抓取状态（Admin 可见）
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CaptureReport {
+    /// 尚未使用的抓取次数
+    pub armed: usize,
+    pub dir: String,
+    pub max_bytes: usize,
+    pub files: Vec<CaptureFile>,
+}
+
+/// 原始帧抓取器
+pub struct RawCapture {
+    /// 隐私模式下不抓取
+    enabled: bool,
+    dir: PathBuf,
+    config: RawCaptureConfig,
+    armed: AtomicUsize,
+    files: Mutex<VecDeque<CaptureFile>>,
+}
+
+impl RawCapture {
+    pub fn new(config: &Config, capabilities: Capabilities) -> Self {
+        let dir = match (&config.raw_capture.dir, &config.state_dir) {
+            (Some(dir), _) => PathBuf::from(dir),
+            (None, Some(state_dir)) => PathBuf::from(state_dir).join("captures"),
+            (None, None) => PathBuf::from("captures"),
+        };
+        Self {
+            enabled: capabilities.transcripts,
+            dir,
+            config: config.raw_capture.clone(),
+            armed: AtomicUsize::new(0),
+            files: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    /// 预约抓取之后的 `count` 个请求（覆盖之前的预约，0 表示取消），返回实际预约数（隐私模式下为 0）
+    pub fn arm(&self, count: usize) -> usize {
+        if !self.enabled && count > 0 {
+            tracing::warn!("隐私模式已开启，不抓取上游原始帧");
+            return 0;
+        }
+        let count = count.min(MAX_ARMED);
+        self.armed.store(count, Ordering::Relaxed);
+        count
+    }
+
+    /// 占用一次抓取名额
+    fn take(&self) -> bool {
+        self.armed
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |n| n.checked_sub(1))
+            .is_ok()
+    }
+
+    /// 获取抓取状态
+    pub fn report(&self) -> CaptureReport {
+        CaptureReport {
+            armed: self.armed.load(Ordering::Relaxed),
+            dir: self.dir.display().to_string(),
+            max_bytes: self.config.max_bytes,
+            files: self.files.lock().iter().rev().cloned().collect(),
+        }
+    }
+
+    /// 有抓取名额时把响应体复制到抓取文件，否则原样返回
+    pub fn tap(self: &Arc<Self>, response: reqwest::Response) -> reqwest::Response {
+        if !self.take() {
+            return response;
+        }
+
+        let credential_id = ServedCredential::of(&response);
+        let time = Utc::now();
+        let name = format!(
+            "{}-{}.bin",
+            time.format("%Y%m%dT%H%M%S%.3fZ"),
+            &uuid::Uuid::new_v4().simple().to_string()[..8]
+        );
+        let path = self.dir.join(&name);
+        let file = match std::fs::create_dir_all(&self.dir).and_then(|_| File::create(&path)) {
+            Ok(f) => f,
+            Err(e) => {
+                tracing::warn!("创建抓取文件失败 {}: {}", path.display(), e);
+                return response;
+            }
+        };
+        tracing::info!("抓取上游原始帧: {}", path.display());
+
+        let mut writer = CaptureWriter {
+            capture: self.clone(),
+            file: BufWriter::new(file),
+            record: CaptureFile {
+                file: name,
+                time,
+                credential_id,
+                status: response.status().as_u16(),
+                bytes: 0,
+                truncated: false,
+            },
+            failed: false,
+        };
+
+        let mut builder = http::Response::builder()
+            .status(response.status())
+            .version(response.version());
+        if let Some(headers) = builder.headers_mut() {
+            *headers = response.headers().clone();
+        }
+        let body = response.bytes_stream().map(move |chunk| {
+            if let Ok(bytes) = &chunk {
+                writer.write(bytes);
+            }
+            chunk
+        });
+        let mut tapped: reqwest::Response = builder
+            .body(reqwest::Body::wrap_stream(body))
+            .expect("复制响应头失败")
+            .into();
+        if let Some(id) = credential_id {
+            tapped.extensions_mut().insert(ServedCredential(id));
+        }
+        tapped
+    }
+
+    /// 记录完成的抓取文件，超出保留数量时删除最旧的
+    fn finish(&self, record: CaptureFile) {
+        let mut files = self.files.lock();
+        files.push_back(record);
+        while files.len() > self.config.max_files.max(1) {
+            if let Some(old) = files.pop_front()
+                && let Err(e) = std::fs::remove_file(self.dir.join(&old.file))
+            {
+                tracing::debug!("删除旧抓取文件失败 {}: {}", old.file, e);
+            }
+        }
+    }
+
+    /// 删除早于 `cutoff` 的抓取文件（包括之前运行留下的），返回删除的文件数
+    pub fn remove_older_than(&self, cutoff: DateTime<Utc>) -> usize {
+        let cutoff_time = SystemTime::from(cutoff);
+        let Ok(entries) = std::fs::read_dir(&self.dir) else {
+            return 0;
+        };
+        let mut removed = 0;
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let expired = path.extension().is_some_and(|ext| ext == "bin")
+                && entry
+                    .metadata()
+                    .and_then(|m| m.modified())
+                    .is_ok_and(|modified| modified < cutoff_time);
+            if expired && std::fs::remove_file(&path).is_ok() {
+                removed += 1;
+            }
+        }
+        self.files.lock().retain(|file| file.time >= cutoff);
+        removed
+    }
+}
+
+/// 单个请求的抓取写入器，drop（响应体读完或被丢弃）时登记文件
+struct CaptureWriter {
+    capture: Arc<RawCapture>,
+    file: BufWriter<File>,
+    record: CaptureFile,
+    /// 写入失败后不再继续写
+    failed: bool,
+}
+
+impl CaptureWriter {
+    fn write(&mut self, bytes: &[u8]) {
+        if self.failed {
+            return;
+        }
+        let remaining = self
+            .capture
+            .config
+            .max_bytes
+            .saturating_sub(self.record.bytes);
+        let len = bytes.len().min(remaining);
+        if len < bytes.len() {
+            self.record.truncated = true;
+        }
+        if len == 0 {
+            return;
+        }
+        match self.file.write_all(&bytes[..len]) {
+            Ok(()) => self.record.bytes += len,
+            Err(e) => {
+                tracing::warn!("写入抓取文件失败 {}: {}", self.record.file, e);
+                self.record.truncated = true;
+                self.failed = true;
+            }
+        }
+    }
+}
+
+impl Drop for CaptureWriter {
+    fn drop(&mut self) {
+        if let Err(e) = self.file.flush() {
+            tracing::warn!("写入抓取文件失败 {}: {}", self.record.file, e);
+        }
+        self.capture.finish(self.record.clone());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn capture(dir: &std::path::Path, max_bytes: usize) -> Arc<RawCapture> {
+        let mut config = Config::default();
+        config.raw_capture.dir = Some(dir.display().to_string());
+        config.raw_capture.max_bytes = max_bytes;
+        Arc::new(RawCapture::new(&config, Capabilities::ALL))
+    }
+
+    fn response(body: &'static [u8]) -> reqwest::Response {
+        http::Response::builder()
+            .status(200)
+            .body(reqwest::Body::from(body))
+            .unwrap()
+            .into()
+    }
+
+    #[tokio::test]
+    async fn test_tap_writes_armed_requests_only() {
+        let dir = std::env::temp_dir().join(format!("kiro-capture-{}", uuid::Uuid::new_v4()));
+        let capture = capture(&dir, 4);
+
+        let body = capture.tap(response(b"abcdef")).bytes().await.unwrap();
+        assert_eq!(&body[..], b"abcdef");
+        assert!(capture.report().files.is_empty());
+
+        assert_eq!(capture.arm(1), 1);
+        let body = capture.tap(response(b"abcdef")).bytes().await.unwrap();
+        assert_eq!(&body[..], b"abcdef");
+
+        let report = capture.report();
+        assert_eq!(report.armed, 0);
+        assert_eq!(report.files.len(), 1);
+        assert_eq!(report.files[0].bytes, 4);
+        assert!(report.files[0].truncated);
+        assert_eq!(
+            std::fs::read(dir.join(&report.files[0].file)).unwrap(),
+            b"abcd"
+        );
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
@@ -0,0 +1,231 @@
+//! 凭据文件热重载
+//!
+//! 收到 SIGHUP（或配置了 `credentialsWatchIntervalSecs` 时检测到凭据文件的修改时间、大小变化）后
+//! 调用 [`MultiTokenManager::reload_credentials`] 重新读取凭据，无需重启服务。服务自身回写凭据文件
+//! （Token 刷新、分配 ID 等）造成的变化不会触发重新加载。
+
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use crate::kiro::token_manager::MultiTokenManager;
+
+/// 文件指纹（修改时间与大小，不存在时为 None）
+pub type Fingerprint = Vec<Option<(SystemTime, u64)>>;
+
+pub fn fingerprint(paths: &[PathBuf]) -> Fingerprint {
+    paths
+        .iter()
+        .map(|path| {
+            let meta = std::fs::metadata(path).ok()?;
+            Some((meta.modified().ok()?, meta.len()))
+        })
+        .collect()
+}
+
+/// 凭据文件变更检测
+struct Watcher {
+    last: Fingerprint,
+}
+
+impl Watcher {
+    fn new(manager: &MultiTokenManager) -> Self {
+        Self {
+            last: fingerprint(&manager.watched_paths()),
+        }
+    }
+
+    /// 自上次检查以来凭据文件是否被外部修改（与服务最近一次回写后的指纹相同时不算）
+    fn changed(&mut self, manager: &MultiTokenManager) -> bool {
+        let current = fingerprint(&manager.watched_paths());
+        if current == self.last {
+            return false;
+        }
+        let self_written = manager.self_written_fingerprint().as_ref() == Some(&current);
+        self.last = current;
+        !self_written
+    }
+
+    /// 以重新加载后的状态为准（回写分配的 ID 也会改变指纹）
+    fn reset(&mut self, manager: &MultiTokenManager) {
+        self.last = fingerprint(&manager.watched_paths());
+    }
+}
+
+/// 在后台监听 SIGHUP，并在 `poll` 不为 None 时按间隔检查凭据文件是否变更
+pub fn spawn(manager: Arc<MultiTokenManager>, poll: Option<Duration>) {
+    #[cfg(unix)]
+    let mut hangup = match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup()) {
+        Ok(signal) => Some(signal),
+        Err(e) => {
+            tracing::warn!("无法监听 SIGHUP，凭据文件只能通过轮询重新加载: {}", e);
+            None
+        }
+    };
+    tokio::spawn(async move {
+        let mut watcher = Watcher::new(&manager);
+        let mut interval = poll.map(tokio::time::interval);
+        loop {
+            let tick = async {
+                match interval.as_mut() {
+                    Some(interval) => {
+                        interval.tick().await;
+                    }
+                    None => std::future::pending().await,
+                }
+            };
+            #[cfg(unix)]
+            let hangup_recv = async {
+                match hangup.as_mut() {
+                    Some(signal) => {
+                        signal.recv().await;
+                    }
+                    None => std::future::pending().await,
+                }
+            };
+            #[cfg(not(unix))]
+            let hangup_recv = std::future::pending::<()>();
+
+            let reason = tokio::select! {
+                _ = hangup_recv => "SIGHUP",
+                _ = tick => {
+                    if !watcher.changed(&manager) {
+                        continue;
+                    }
+                    "文件变更"
+                }
+            };
+            tracing::info!("重新加载凭据（{}）", reason);
+            if let Err(e) = manager.reload_credentials().await {
+                tracing::warn!("重新加载凭据失败，保留当前凭据: {:#}", e);
+            }
+            watcher.reset(&manager);
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kiro::model::credentials::CredentialsConfig;
+    use crate::model::config::Config;
+
+    /// 在临时目录中创建凭据文件与管理器，返回 (目录, 凭据文件, 管理器)
+    fn setup(creds: serde_json::Value) -> (PathBuf, PathBuf, Arc<MultiTokenManager>) {
+        let dir = std::env::temp_dir().join(format!("kiro-watch-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("credentials.json");
+        std::fs::write(&path, creds.to_string()).unwrap();
+        let creds = CredentialsConfig::load(&path)
+            .unwrap()
+            .into_sorted_credentials();
+        let manager =
+            MultiTokenManager::new(Config::default(), creds, None, Some(path.clone()), true)
+                .unwrap();
+        (dir, path, Arc::new(manager))
+    }
+
+    /// 等待条件成立（最多 5 秒）
+    async fn wait_for(condition: impl Fn() -> bool) -> bool {
+        for _ in 0..100 {
+            if condition() {
+                return true;
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+        false
+    }
+
+    #[test]
+    fn test_fingerprint() {
+        let dir = std::env::temp_dir().join(format!("kiro-watch-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("a.json");
+        let paths = [file.clone(), dir.join("missing.json")];
+
+        let before = fingerprint(&paths);
+        assert_eq!(before, vec![None, None]);
+        std::fs::write(&file, "[]").unwrap();
+        let written = fingerprint(&paths);
+        assert!(written[0].is_some() && written[1].is_none());
+        assert_eq!(fingerprint(&paths), written);
+        std::fs::write(&file, "[{}]").unwrap();
+        assert_ne!(fingerprint(&paths), written);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_watcher_ignores_self_writes() {
+        let (dir, path, manager) = setup(serde_json::json!([{"id": 1, "refreshToken": "a"}]));
+        let mut watcher = Watcher::new(&manager);
+        assert!(!watcher.changed(&manager));
+
+        // 服务自身回写（如 Token 刷新后保存）不算变更
+        manager.set_priority(1, 3).unwrap();
+        assert!(manager.self_written_fingerprint().is_some());
+        assert!(!watcher.changed(&manager));
+
+        // 外部修改
+        std::fs::write(
+            &path,
+            serde_json::json!([{"id": 1, "refreshToken": "a", "priority": 7}]).to_string(),
+        )
+        .unwrap();
+        assert!(watcher.changed(&manager));
+        assert!(!watcher.changed(&manager));
+
+        // 回写之后紧接着的外部修改仍然生效
+        manager.set_priority(1, 4).unwrap();
+        std::fs::write(
+            &path,
+            serde_json::json!([{"id": 1, "refreshToken": "b"}]).to_string(),
+        )
+        .unwrap();
+        assert!(watcher.changed(&manager));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_poll_reloads_external_changes() {
+        let (dir, path, manager) = setup(serde_json::json!([{"id": 1, "refreshToken": "a"}]));
+        spawn(manager.clone(), Some(Duration::from_millis(20)));
+
+        std::fs::write(
+            &path,
+            serde_json::json!([{"id": 1, "refreshToken": "a"}, {"id": 2, "refreshToken": "b"}])
+                .to_string(),
+        )
+        .unwrap();
+        assert!(wait_for(|| manager.total_count() == 2).await);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_sighup_reloads() {
+        let (dir, path, manager) = setup(serde_json::json!([{"id": 1, "refreshToken": "a"}]));
+        // 不轮询，只响应 SIGHUP
+        spawn(manager.clone(), None);
+
+        std::fs::write(
+            &path,
+            serde_json::json!([{"id": 1, "refreshToken": "a"}, {"id": 2, "refreshToken": "b"}])
+                .to_string(),
+        )
+        .unwrap();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(manager.total_count(), 1);
+
+        let status = std::process::Command::new("kill")
+            .args(["-HUP", &std::process::id().to_string()])
+            .status()
+            .unwrap();
+        assert!(status.success());
+        assert!(wait_for(|| manager.total_count() == 2).await);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
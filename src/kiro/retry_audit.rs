@@ -0,0 +1,235 @@
+//! 重试审计
+//!
+//! 记录一次上游调用中每次尝试的凭据、状态码、耗时和分类，
+//! 调用最终失败时把完整记录附加到错误上，避免只看到最后一次错误（如“所有凭据已用尽”）
+//! 而无法判断之前的尝试发生了什么。
+
+use std::fmt;
+use std::time::Duration;
+
+use reqwest::StatusCode;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+/// 单次尝试的失败分类
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AttemptClass {
+    /// 没有可用凭据 / 获取 Token 失败
+    NoCredential,
+    /// 构建请求头失败
+    InvalidHeaders,
+    /// 凭据绑定区域与请求区域不一致（regionMismatchPolicy 为 reject）
+    RegionMismatch,
+    /// 请求发送失败（网络/TLS/超时）
+    Network,
+    /// 402 额度用尽
+    QuotaExhausted,
+    /// 400 请求问题
+    BadRequest,
+    /// 401/403 凭据问题
+    Credential,
+    /// 408/429/5xx 瞬态错误
+    Transient,
+    /// 其他 4xx
+    ClientError,
+    /// 其他状态码
+    Unknown,
+}
+
+impl AttemptClass {
+    /// 按响应状态码分类（与重试循环中的分支保持一致）
+    pub fn from_status(status: StatusCode, quota_exhausted: bool) -> Self {
+        match status.as_u16() {
+            402 if quota_exhausted => Self::QuotaExhausted,
+            400 => Self::BadRequest,
+            401 | 403 => Self::Credential,
+            408 | 429 => Self::Transient,
+            _ if status.is_server_error() => Self::Transient,
+            _ if status.is_client_error() => Self::ClientError,
+            _ => Self::Unknown,
+        }
+    }
+
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::NoCredential => "no_credential",
+            Self::InvalidHeaders => "invalid_headers",
+            Self::RegionMismatch => "region_mismatch",
+            Self::Network => "network",
+            Self::QuotaExhausted => "quota_exhausted",
+            Self::BadRequest => "bad_request",
+            Self::Credential => "credential",
+            Self::Transient => "transient",
+            Self::ClientError => "client_error",
+            Self::Unknown => "unknown",
+        }
+    }
+
+    /// 是否为请求本身的问题（重试无意义）
+    fn is_request_error(self) -> bool {
+        matches!(self, Self::BadRequest | Self::ClientError)
+    }
+}
+
+/// 单次尝试记录
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AttemptRecord {
+    /// 从 1 开始的尝试序号
+    pub attempt: usize,
+    /// 凭据 ID 的短哈希（未获取到凭据时为 None）
+    pub credential: Option<String>,
+    pub status: Option<u16>,
+    pub latency_ms: u64,
+    pub class: AttemptClass,
+}
+
+/// 凭据 ID 的短哈希，避免在返回给客户端的错误中直接暴露内部 ID
+pub fn credential_hash(id: u64) -> String {
+    let digest = Sha256::digest(format!("kiro-credential:{}", id).as_bytes());
+    hex::encode(&digest[..4])
+}
+
+/// 一次调用的尝试记录
+#[derive(Debug, Default)]
+pub struct RetryAudit {
+    attempts: Vec<AttemptRecord>,
+}
+
+impl RetryAudit {
+    /// 记录一次失败的尝试
+    pub fn record(
+        &mut self,
+        credential_id: Option<u64>,
+        status: Option<StatusCode>,
+        latency: Duration,
+        class: AttemptClass,
+    ) {
+        self.attempts.push(AttemptRecord {
+            attempt: self.attempts.len() + 1,
+            credential: credential_id.map(credential_hash),
+            status: status.map(|s| s.as_u16()),
+            latency_ms: latency.as_millis() as u64,
+            class,
+        });
+    }
+
+    /// 已记录的失败尝试次数
+    pub fn attempt_count(&self) -> usize {
+        self.attempts.len()
+    }
+
+    /// 把尝试记录附加到最终错误上
+    ///
+    /// 仅一次尝试且为请求本身的问题（400/其他 4xx）时原样返回，保持错误信息简洁
+    pub fn attach(self, error: anyhow::Error) -> anyhow::Error {
+        match self.attempts.as_slice() {
+            [] => error,
+            [only] if only.class.is_request_error() => error,
+            _ => RetryExhaustedError {
+                last_error: error,
+                attempts: self.attempts,
+            }
+            .into(),
+        }
+    }
+}
+
+/// 携带尝试记录的最终错误
+///
+/// 可通过 `error.downcast_ref::<RetryExhaustedError>()` 取出结构化记录
+#[derive(Debug)]
+pub struct RetryExhaustedError {
+    pub last_error: anyhow::Error,
+    pub attempts: Vec<AttemptRecord>,
+}
+
+impl fmt::Display for RetryExhaustedError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}（尝试记录: ", self.last_error)?;
+        for (i, a) in self.attempts.iter().enumerate() {
+            if i > 0 {
+                write!(f, "; ")?;
+            }
+            write!(
+                f,
+                "#{} 凭据={} 状态={} 耗时={}ms {}",
+                a.attempt,
+                a.credential.as_deref().unwrap_or("-"),
+                a.status.map(|s| s.to_string()).as_deref().unwrap_or("-"),
+                a.latency_ms,
+                a.class.as_str()
+            )?;
+        }
+        write!(f, "）")
+    }
+}
+
+impl std::error::Error for RetryExhaustedError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(self.last_error.as_ref())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_status_matches_retry_branches() {
+        assert_eq!(
+            AttemptClass::from_status(StatusCode::PAYMENT_REQUIRED, true),
+            AttemptClass::QuotaExhausted
+        );
+        assert_eq!(
+            AttemptClass::from_status(StatusCode::PAYMENT_REQUIRED, false),
+            AttemptClass::ClientError
+        );
+        assert_eq!(
+            AttemptClass::from_status(StatusCode::FORBIDDEN, false),
+            AttemptClass::Credential
+        );
+        assert_eq!(
+            AttemptClass::from_status(StatusCode::BAD_GATEWAY, false),
+            AttemptClass::Transient
+        );
+    }
+
+    #[test]
+    fn test_attach_formats_history() {
+        let mut audit = RetryAudit::default();
+        audit.record(
+            Some(1),
+            Some(StatusCode::TOO_MANY_REQUESTS),
+            Duration::from_millis(120),
+            AttemptClass::Transient,
+        );
+        audit.record(None, None, Duration::ZERO, AttemptClass::NoCredential);
+
+        let error = audit.attach(anyhow::anyhow!("所有凭据已用尽"));
+        let message = error.to_string();
+        assert!(message.starts_with("所有凭据已用尽（尝试记录: #1 凭据="));
+        assert!(message.contains(&format!(
+            "凭据={} 状态=429 耗时=120ms transient",
+            credential_hash(1)
+        )));
+        assert!(message.contains("#2 凭据=- 状态=- 耗时=0ms no_credential"));
+
+        let audit_error = error.downcast_ref::<RetryExhaustedError>().unwrap();
+        assert_eq!(audit_error.attempts.len(), 2);
+    }
+
+    #[test]
+    fn test_attach_keeps_single_request_error() {
+        let mut audit = RetryAudit::default();
+        audit.record(
+            Some(1),
+            Some(StatusCode::BAD_REQUEST),
+            Duration::from_millis(5),
+            AttemptClass::BadRequest,
+        );
+        let error = audit.attach(anyhow::anyhow!("bad request"));
+        assert_eq!(error.to_string(), "bad request");
+    }
+}
@@ -0,0 +1,274 @@
+//! 影子流量
+//!
+//! 按 `shadow.percentage` 抽样，把主请求镜像到另一个 region/端点（如待切换的新区域），
+//! 影子响应直接丢弃，只记录与主请求的延迟、错误差异，供运维在切换前评估。
+//! 影子请求不重试，也不影响凭据的成功/失败统计。
+
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+use parking_lot::Mutex;
+use reqwest::Client;
+use reqwest::header::HeaderMap;
+use serde::Serialize;
+
+use crate::model::config::ShadowConfig;
+
+/// 保留的最近样本数
+const RECENT_SAMPLES: usize = 50;
+
+/// 主请求结果
+#[derive(Debug, Clone, Copy)]
+pub struct PrimaryOutcome {
+    /// 收到响应头的耗时（含重试）
+    pub latency: Duration,
+    /// 是否成功
+    pub ok: bool,
+}
+
+/// 单次镜像样本
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ShadowSample {
+    pub time: DateTime<Utc>,
+    pub primary_latency_ms: u64,
+    pub primary_ok: bool,
+    pub shadow_latency_ms: u64,
+    /// 影子响应状态码（请求失败/超时时为 None）
+    pub shadow_status: Option<u16>,
+    pub shadow_error: Option<String>,
+}
+
+impl ShadowSample {
+    fn shadow_ok(&self) -> bool {
+        self.shadow_status.is_some_and(|s| (200..300).contains(&s))
+    }
+}
+
+/// 影子流量统计（Admin 可见）
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ShadowReport {
+    pub enabled: bool,
+    pub percentage: f64,
+    pub region: Option<String>,
+    pub endpoint: Option<String>,
+    /// 已完成的镜像次数
+    pub samples: u64,
+    pub primary_errors: u64,
+    pub shadow_errors: u64,
+    /// 主/影子一成功一失败的次数
+    pub mismatches: u64,
+    pub avg_primary_latency_ms: u64,
+    pub avg_shadow_latency_ms: u64,
+    /// 影子减主请求的平均延迟差（毫秒，正数表示影子更慢）
+    pub avg_latency_delta_ms: i64,
+    pub recent: Vec<ShadowSample>,
+}
+
+impl ShadowReport {
+    /// 尚无样本的报告（未开启 shadow 时也用于 Admin 展示配置）
+    pub fn empty(config: &ShadowConfig) -> Self {
+        Self {
+            enabled: config.enabled,
+            percentage: config.percentage,
+            region: config.region.clone(),
+            endpoint: config.endpoint.clone(),
+            samples: 0,
+            primary_errors: 0,
+            shadow_errors: 0,
+            mismatches: 0,
+            avg_primary_latency_ms: 0,
+            avg_shadow_latency_ms: 0,
+            avg_latency_delta_ms: 0,
+            recent: Vec::new(),
+        }
+    }
+}
+
+#[derive(Default)]
+struct ShadowStats {
+    samples: u64,
+    primary_errors: u64,
+    shadow_errors: u64,
+    mismatches: u64,
+    primary_latency_ms_total: u64,
+    shadow_latency_ms_total: u64,
+    recent: VecDeque<ShadowSample>,
+}
+
+/// 影子流量镜像器
+pub struct ShadowMirror {
+    config: ShadowConfig,
+    client: Client,
+    stats: Mutex<ShadowStats>,
+}
+
+impl ShadowMirror {
+    pub fn new(config: ShadowConfig, client: Client) -> Self {
+        Self {
+            config,
+            client,
+            stats: Mutex::new(ShadowStats::default()),
+        }
+    }
+
+    /// 本次请求是否需要镜像（按比例抽样）
+    pub fn should_mirror(&self) -> bool {
+        self.config.enabled && fastrand::f64() * 100.0 < self.config.percentage
+    }
+
+    /// 影子请求使用的 region（None 时与主请求相同）
+    pub fn region(&self) -> Option<&str> {
+        self.config.region.as_deref()
+    }
+
+    /// 影子请求的完整 URL 覆盖
+    pub fn endpoint(&self) -> Option<&str> {
+        self.config.endpoint.as_deref()
+    }
+
+    /// 发送影子请求并记录结果（在后台任务中调用）
+    pub async fn send(
+        &self,
+        url: String,
+        headers: HeaderMap,
+        body: String,
+        primary: PrimaryOutcome,
+    ) {
+        let started = Instant::now();
+        let timeout = Duration::from_secs(self.config.timeout_secs);
+        let request = self.client.post(&url).headers(headers).body(body).send();
+
+        let (shadow_status, shadow_error) = match tokio::time::timeout(timeout, request).await {
+            Ok(Ok(response)) => {
+                let status = response.status().as_u16();
+                // 读完响应体再丢弃，避免连接被提前中断影响上游统计
+                let _ = tokio::time::timeout(timeout, response.bytes()).await;
+                (Some(status), None)
+            }
+            Ok(Err(e)) => (None, Some(e.to_string())),
+            Err(_) => (None, Some("timeout".to_string())),
+        };
+
+        self.record(ShadowSample {
+            time: Utc::now(),
+            primary_latency_ms: primary.latency.as_millis() as u64,
+            primary_ok: primary.ok,
+            shadow_latency_ms: started.elapsed().as_millis() as u64,
+            shadow_status,
+            shadow_error,
+        });
+    }
+
+    fn record(&self, sample: ShadowSample) {
+        let shadow_ok = sample.shadow_ok();
+        if !shadow_ok || !sample.primary_ok {
+            tracing::debug!(
+                "影子请求结果: primary_ok={}, shadow_status={:?}, shadow_error={:?}",
+                sample.primary_ok,
+                sample.shadow_status,
+                sample.shadow_error
+            );
+        }
+
+        let mut stats = self.stats.lock();
+        stats.samples += 1;
+        if !sample.primary_ok {
+            stats.primary_errors += 1;
+        }
+        if !shadow_ok {
+            stats.shadow_errors += 1;
+        }
+        if shadow_ok != sample.primary_ok {
+            stats.mismatches += 1;
+        }
+        stats.primary_latency_ms_total += sample.primary_latency_ms;
+        stats.shadow_latency_ms_total += sample.shadow_latency_ms;
+        if stats.recent.len() >= RECENT_SAMPLES {
+            stats.recent.pop_front();
+        }
+        stats.recent.push_back(sample);
+    }
+
+    /// 获取统计报告
+    pub fn report(&self) -> ShadowReport {
+        let stats = self.stats.lock();
+        let avg = |total: u64| total.checked_div(stats.samples).unwrap_or(0);
+        let avg_primary = avg(stats.primary_latency_ms_total);
+        let avg_shadow = avg(stats.shadow_latency_ms_total);
+
+        ShadowReport {
+            samples: stats.samples,
+            primary_errors: stats.primary_errors,
+            shadow_errors: stats.shadow_errors,
+            mismatches: stats.mismatches,
+            avg_primary_latency_ms: avg_primary,
+            avg_shadow_latency_ms: avg_shadow,
+            avg_latency_delta_ms: avg_shadow as i64 - avg_primary as i64,
+            recent: stats.recent.iter().rev().cloned().collect(),
+            ..ShadowReport::empty(&self.config)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mirror(percentage: f64) -> ShadowMirror {
+        ShadowMirror::new(
+            ShadowConfig {
+                enabled: true,
+                percentage,
+                ..Default::default()
+            },
+            Client::new(),
+        )
+    }
+
+    fn sample(
+        primary_ms: u64,
+        primary_ok: bool,
+        shadow_ms: u64,
+        status: Option<u16>,
+    ) -> ShadowSample {
+        ShadowSample {
+            time: Utc::now(),
+            primary_latency_ms: primary_ms,
+            primary_ok,
+            shadow_latency_ms: shadow_ms,
+            shadow_status: status,
+            shadow_error: None,
+        }
+    }
+
+    #[test]
+    fn test_should_mirror_percentage_bounds() {
+        assert!(!mirror(0.0).should_mirror());
+        assert!(mirror(100.0).should_mirror());
+        assert!(!ShadowMirror::new(ShadowConfig::default(), Client::new()).should_mirror());
+    }
+
+    #[test]
+    fn test_report_aggregates_deltas() {
+        let mirror = mirror(100.0);
+        assert_eq!(mirror.report().samples, 0);
+
+        mirror.record(sample(100, true, 150, Some(200)));
+        mirror.record(sample(100, true, 250, Some(500)));
+        mirror.record(sample(400, false, 200, None));
+
+        let report = mirror.report();
+        assert_eq!(report.samples, 3);
+        assert_eq!(report.primary_errors, 1);
+        assert_eq!(report.shadow_errors, 2);
+        assert_eq!(report.mismatches, 1);
+        assert_eq!(report.avg_primary_latency_ms, 200);
+        assert_eq!(report.avg_shadow_latency_ms, 200);
+        assert_eq!(report.avg_latency_delta_ms, 0);
+        // 最近样本按时间倒序
+        assert_eq!(report.recent[0].primary_latency_ms, 400);
+    }
+}
@@ -0,0 +1,467 @@
+//! 凭据生命周期遥测（OTLP 导出）
+//!
+//! 与单个请求的追踪分开，这里按凭据记录长期状态：每个凭据是一个独立的 OTel Resource
+//! （`kiro.credential.id`），定期经 OTLP/HTTP（JSON 编码）导出刷新次数、禁用/恢复次数、
+//! 累计调用等指标；禁用期间与 Token 刷新周期作为 Span 导出，同一凭据的 Span 共用一个 trace，
+//! 在追踪后端中即可按时间线查看该凭据的生命周期。
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::Utc;
+use parking_lot::Mutex;
+use reqwest::Client;
+use serde_json::{Value, json};
+use tokio::sync::broadcast;
+
+use crate::common::privacy::Capabilities;
+use crate::http_client::{ProxyConfig, build_client};
+use crate::kiro::events::TokenEvent;
+use crate::kiro::token_manager::{DisabledReason, ManagerSnapshot, MultiTokenManager};
+use crate::model::config::{Config, OtelConfig};
+
+/// 等待导出的 Span 上限（接收端长时间不可用时丢弃最旧的）
+const MAX_PENDING_SPANS: usize = 1024;
+
+/// 导出请求超时（秒）
+const EXPORT_TIMEOUT_SECS: u64 = 10;
+
+/// 指标与 Span 的 instrumentation scope
+const SCOPE_NAME: &str = "kiro-rs.credential-lifecycle";
+
+/// 单个凭据的生命周期状态
+struct Lifecycle {
+    /// 该凭据所有 Span 共用的 trace id
+    trace_id: String,
+    refreshes: u64,
+    disables: u64,
+    recoveries: u64,
+    quota_exhausted: u64,
+    /// 最近一次刷新时间（纳秒时间戳），首个刷新周期从开始记录时算起
+    last_refresh_at: u64,
+    /// 当前禁用期间的开始时间与原因
+    disabled_since: Option<(u64, DisabledReason)>,
+}
+
+impl Lifecycle {
+    fn new(now: u64) -> Self {
+        Self {
+            trace_id: uuid::Uuid::new_v4().simple().to_string(),
+            refreshes: 0,
+            disables: 0,
+            recoveries: 0,
+            quota_exhausted: 0,
+            last_refresh_at: now,
+            disabled_since: None,
+        }
+    }
+}
+
+/// 已结束、等待导出的 Span
+struct FinishedSpan {
+    credential_id: u64,
+    trace_id: String,
+    name: &'static str,
+    start: u64,
+    end: u64,
+    attributes: Vec<Value>,
+}
+
+/// 凭据生命周期遥测
+pub struct CredentialTelemetry {
+    config: OtelConfig,
+    client: Client,
+    token_manager: Arc<MultiTokenManager>,
+    /// 开始记录的时间（累计指标的起点）
+    started_at: u64,
+    lifecycles: Mutex<HashMap<u64, Lifecycle>>,
+    spans: Mutex<Vec<FinishedSpan>>,
+}
+
+impl CredentialTelemetry {
+    /// 从配置创建（未配置 `otel.endpoint` 或隐私模式下返回 None）
+    pub fn from_config(
+        config: &Config,
+        token_manager: Arc<MultiTokenManager>,
+        proxy: Option<&ProxyConfig>,
+        capabilities: Capabilities,
+    ) -> Option<Arc<Self>> {
+        if !capabilities.analytics {
+            return None;
+        }
+        config.otel.endpoint.as_ref()?;
+        let client = match build_client(proxy, EXPORT_TIMEOUT_SECS, config.tls_backend) {
+            Ok(client) => client,
+            Err(e) => {
+                tracing::warn!("创建 OTLP 导出客户端失败，凭据遥测未启用: {}", e);
+                return None;
+            }
+        };
+        Some(Arc::new(Self {
+            config: config.otel.clone(),
+            client,
+            token_manager,
+            started_at: now_nanos(),
+            lifecycles: Mutex::new(HashMap::new()),
+            spans: Mutex::new(Vec::new()),
+        }))
+    }
+
+    /// 在后台消费凭据事件，并按间隔导出
+    pub fn spawn(self: &Arc<Self>, mut events: broadcast::Receiver<TokenEvent>) {
+        let telemetry = self.clone();
+        tokio::spawn(async move {
+            loop {
+                match events.recv().await {
+                    Ok(event) => telemetry.record(&event, now_nanos()),
+                    Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                        tracing::warn!("凭据遥测落后，丢失 {} 个事件", skipped);
+                    }
+                    Err(broadcast::error::RecvError::Closed) => return,
+                }
+            }
+        });
+
+        let telemetry = self.clone();
+        let interval = Duration::from_secs(self.config.export_interval_secs.max(1));
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            ticker.tick().await;
+            loop {
+                ticker.tick().await;
+                telemetry.export().await;
+            }
+        });
+    }
+
+    /// 记录一个凭据事件
+    fn record(&self, event: &TokenEvent, now: u64) {
+        let id = match event {
+            TokenEvent::CredentialDisabled { id, .. }
+            | TokenEvent::CredentialRecovered { id }
+            | TokenEvent::TokenRefreshed { id, .. }
+            | TokenEvent::QuotaExhausted { id } => *id,
+            TokenEvent::AllExhausted { .. } => return,
+        };
+        let mut lifecycles = self.lifecycles.lock();
+        let lifecycle = lifecycles
+            .entry(id)
+            .or_insert_with(|| Lifecycle::new(self.started_at));
+
+        let span = match event {
+            TokenEvent::TokenRefreshed { expires_at, .. } => {
+                lifecycle.refreshes += 1;
+                let start = std::mem::replace(&mut lifecycle.last_refresh_at, now);
+                let attributes = expires_at
+                    .iter()
+                    .map(|t| attribute("kiro.token.expires_at", json!({"stringValue": t})))
+                    .collect();
+                Some(("token.refresh_cycle", start, attributes))
+            }
+            TokenEvent::CredentialDisabled { reason, .. } => {
+                lifecycle.disables += 1;
+                lifecycle.disabled_since.get_or_insert((now, *reason));
+                None
+            }
+            TokenEvent::CredentialRecovered { .. } => {
+                lifecycle.recoveries += 1;
+                lifecycle.disabled_since.take().map(|(start, reason)| {
+                    let reason = serde_json::to_value(reason).unwrap_or_default();
+                    let attributes = vec![attribute(
+                        "kiro.disabled.reason",
+                        json!({"stringValue": reason}),
+                    )];
+                    ("credential.disabled", start, attributes)
+                })
+            }
+            TokenEvent::QuotaExhausted { .. } => {
+                lifecycle.quota_exhausted += 1;
+                None
+            }
+            TokenEvent::AllExhausted { .. } => None,
+        };
+
+        if let Some((name, start, attributes)) = span {
+            let mut spans = self.spans.lock();
+            if spans.len() >= MAX_PENDING_SPANS {
+                spans.remove(0);
+            }
+            spans.push(FinishedSpan {
+                credential_id: id,
+                trace_id: lifecycle.trace_id.clone(),
+                name,
+                start,
+                end: now,
+                attributes,
+            });
+        }
+    }
+
+    /// 导出一次指标与已结束的 Span（失败时 Span 放回队列，下次重试）
+    async fn export(&self) {
+        let now = now_nanos();
+        let snapshot = self.token_manager.snapshot();
+        let metrics = self.metrics_payload(&snapshot, now);
+        if let Err(e) = self.post("/v1/metrics", &metrics).await {
+            tracing::warn!("导出凭据指标失败: {}", e);
+        }
+
+        let spans = std::mem::take(&mut *self.spans.lock());
+        if spans.is_empty() {
+            return;
+        }
+        let traces = self.traces_payload(&snapshot, &spans);
+        if let Err(e) = self.post("/v1/traces", &traces).await {
+            tracing::warn!("导出凭据生命周期 Span 失败: {}", e);
+            let mut pending = self.spans.lock();
+            let mut restored = spans;
+            restored.append(&mut pending);
+            let excess = restored.len().saturating_sub(MAX_PENDING_SPANS);
+            restored.drain(..excess);
+            *pending = restored;
+        }
+    }
+
+    async fn post(&self, path: &str, body: &Value) -> anyhow::Result<()> {
+        let endpoint = self.config.endpoint.as_deref().unwrap_or_default();
+        let mut request = self
+            .client
+            .post(format!("{}{}", endpoint.trim_end_matches('/'), path))
+            .json(body);
+        for (name, value) in &self.config.headers {
+            request = request.header(name, value);
+        }
+        let response = request.send().await?;
+        if !response.status().is_success() {
+            anyhow::bail!("HTTP {}", response.status());
+        }
+        Ok(())
+    }
+
+    /// 凭据的 Resource（`service.name` + 凭据标识）
+    fn resource(&self, snapshot: &ManagerSnapshot, id: u64) -> Value {
+        let mut attributes = vec![
+            attribute(
+                "service.name",
+                json!({"stringValue": self.config.service_name}),
+            ),
+            attribute("kiro.credential.id", int_value(id)),
+        ];
+        if let Some(entry) = snapshot.entries.iter().find(|e| e.id == id) {
+            attributes.push(attribute(
+                "kiro.credential.priority",
+                int_value(entry.priority as u64),
+            ));
+            if let Some(method) = &entry.auth_method {
+                attributes.push(attribute(
+                    "kiro.credential.auth_method",
+                    json!({"stringValue": method}),
+                ));
+            }
+        }
+        json!({ "attributes": attributes })
+    }
+
+    /// OTLP `ExportMetricsServiceRequest`：每个凭据一个 ResourceMetrics
+    fn metrics_payload(&self, snapshot: &ManagerSnapshot, now: u64) -> Value {
+        let lifecycles = self.lifecycles.lock();
+        let resource_metrics: Vec<Value> = snapshot
+            .entries
+            .iter()
+            .map(|entry| {
+                let lifecycle = lifecycles.get(&entry.id);
+                let count = |f: fn(&Lifecycle) -> u64| lifecycle.map(f).unwrap_or(0);
+                let mut metrics = vec![
+                    self.sum("kiro.credential.refreshes", count(|l| l.refreshes), now),
+                    self.sum("kiro.credential.disables", count(|l| l.disables), now),
+                    self.sum("kiro.credential.recoveries", count(|l| l.recoveries), now),
+                    self.sum(
+                        "kiro.credential.quota_exhausted",
+                        count(|l| l.quota_exhausted),
+                        now,
+                    ),
+                    self.sum("kiro.credential.requests", entry.success_count, now),
+                    self.sum("kiro.credential.stalls", entry.stall_count, now),
+                    gauge("kiro.credential.disabled", entry.disabled as i64, now),
+                    gauge(
+                        "kiro.credential.consecutive_failures",
+                        entry.failure_count as i64,
+                        now,
+                    ),
+                ];
+                if let Some(days) = entry.expiry.reauth_in_days {
+                    metrics.push(gauge("kiro.credential.reauth_in_days", days, now));
+                }
+                json!({
+                    "resource": self.resource(snapshot, entry.id),
+                    "scopeMetrics": [{"scope": {"name": SCOPE_NAME}, "metrics": metrics}],
+                })
+            })
+            .collect();
+        json!({ "resourceMetrics": resource_metrics })
+    }
+
+    /// OTLP `ExportTraceServiceRequest`：按凭据分组 Span
+    fn traces_payload(&self, snapshot: &ManagerSnapshot, spans: &[FinishedSpan]) -> Value {
+        let mut grouped: Vec<(u64, Vec<Value>)> = Vec::new();
+        for span in spans {
+            let value = json!({
+                "traceId": span.trace_id,
+                "spanId": format!("{:016x}", fastrand::u64(1..)),
+                "name": span.name,
+                // SPAN_KIND_INTERNAL
+                "kind": 1,
+                "startTimeUnixNano": span.start.to_string(),
+                "endTimeUnixNano": span.end.to_string(),
+                "attributes": span.attributes,
+            });
+            match grouped.iter_mut().find(|(id, _)| *id == span.credential_id) {
+                Some((_, values)) => values.push(value),
+                None => grouped.push((span.credential_id, vec![value])),
+            }
+        }
+        let resource_spans: Vec<Value> = grouped
+            .into_iter()
+            .map(|(id, spans)| {
+                json!({
+                    "resource": self.resource(snapshot, id),
+                    "scopeSpans": [{"scope": {"name": SCOPE_NAME}, "spans": spans}],
+                })
+            })
+            .collect();
+        json!({ "resourceSpans": resource_spans })
+    }
+
+    /// 累计值（自开始记录以来单调递增）
+    fn sum(&self, name: &str, value: u64, now: u64) -> Value {
+        json!({
+            "name": name,
+            "unit": "1",
+            "sum": {
+                // AGGREGATION_TEMPORALITY_CUMULATIVE
+                "aggregationTemporality": 2,
+                "isMonotonic": true,
+                "dataPoints": [{
+                    "startTimeUnixNano": self.started_at.to_string(),
+                    "timeUnixNano": now.to_string(),
+                    "asInt": value.to_string(),
+                }],
+            },
+        })
+    }
+}
+
+fn gauge(name: &str, value: i64, now: u64) -> Value {
+    json!({
+        "name": name,
+        "gauge": {
+            "dataPoints": [{"timeUnixNano": now.to_string(), "asInt": value.to_string()}],
+        },
+    })
+}
+
+fn attribute(key: &str, value: Value) -> Value {
+    json!({"key": key, "value": value})
+}
+
+/// OTLP JSON 中 64 位整数编码为字符串
+fn int_value(value: u64) -> Value {
+    json!({"intValue": value.to_string()})
+}
+
+fn now_nanos() -> u64 {
+    Utc::now().timestamp_nanos_opt().unwrap_or_default().max(0) as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kiro::model::credentials::KiroCredentials;
+
+    #[test]
+    fn test_lifecycle_metrics_and_spans() {
+        let config = Config {
+            otel: OtelConfig {
+                endpoint: Some("http://127.0.0.1:4318".to_string()),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let credentials = KiroCredentials {
+            refresh_token: Some("a".repeat(150)),
+            ..Default::default()
+        };
+        let manager = Arc::new(
+            MultiTokenManager::new(config.clone(), vec![credentials], None, None, false).unwrap(),
+        );
+        let id = manager.snapshot().entries[0].id;
+        let telemetry =
+            CredentialTelemetry::from_config(&config, manager.clone(), None, Capabilities::ALL)
+                .unwrap();
+
+        let start = telemetry.started_at;
+        telemetry.record(
+            &TokenEvent::TokenRefreshed {
+                id,
+                expires_at: None,
+            },
+            start + 10,
+        );
+        telemetry.record(
+            &TokenEvent::CredentialDisabled {
+                id,
+                reason: DisabledReason::QuotaExceeded,
+            },
+            start + 20,
+        );
+        telemetry.record(&TokenEvent::QuotaExhausted { id }, start + 20);
+        telemetry.record(&TokenEvent::CredentialRecovered { id }, start + 50);
+        manager.report_success(id);
+
+        let snapshot = manager.snapshot();
+        let metrics = telemetry.metrics_payload(&snapshot, start + 60);
+        let resource = &metrics["resourceMetrics"][0];
+        assert_eq!(
+            resource["resource"]["attributes"][1],
+            json!({"key": "kiro.credential.id", "value": {"intValue": id.to_string()}})
+        );
+        let values: HashMap<&str, &str> = resource["scopeMetrics"][0]["metrics"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|m| {
+                let points = m.get("sum").or(m.get("gauge")).unwrap();
+                (
+                    m["name"].as_str().unwrap(),
+                    points["dataPoints"][0]["asInt"].as_str().unwrap(),
+                )
+            })
+            .collect();
+        assert_eq!(values["kiro.credential.refreshes"], "1");
+        assert_eq!(values["kiro.credential.disables"], "1");
+        assert_eq!(values["kiro.credential.recoveries"], "1");
+        assert_eq!(values["kiro.credential.quota_exhausted"], "1");
+        assert_eq!(values["kiro.credential.requests"], "1");
+        assert_eq!(values["kiro.credential.disabled"], "0");
+
+        // 刷新周期与禁用期间两个 Span，同属该凭据的 trace
+        let spans = std::mem::take(&mut *telemetry.spans.lock());
+        let traces = telemetry.traces_payload(&snapshot, &spans);
+        let spans = traces["resourceSpans"][0]["scopeSpans"][0]["spans"]
+            .as_array()
+            .unwrap();
+        assert_eq!(spans.len(), 2);
+        assert_eq!(spans[0]["name"], "token.refresh_cycle");
+        assert_eq!(spans[0]["startTimeUnixNano"], start.to_string());
+        assert_eq!(spans[1]["name"], "credential.disabled");
+        assert_eq!(spans[1]["startTimeUnixNano"], (start + 20).to_string());
+        assert_eq!(spans[1]["endTimeUnixNano"], (start + 50).to_string());
+        assert_eq!(
+            spans[1]["attributes"][0]["value"]["stringValue"],
+            "quotaExceeded"
+        );
+        assert_eq!(spans[0]["traceId"], spans[1]["traceId"]);
+        assert_eq!(spans[0]["traceId"].as_str().unwrap().len(), 32);
+    }
+}
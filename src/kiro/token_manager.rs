@@ -6,19 +6,28 @@
 use anyhow::bail;
 use chrono::{DateTime, Duration, Utc};
 use parking_lot::Mutex;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use tokio::sync::Mutex as TokioMutex;
+use tokio::sync::broadcast;
 
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::time::Instant;
 
+use crate::common::persist;
 use crate::http_client::{ProxyConfig, build_client};
+use crate::kiro::events::{EVENT_CHANNEL_CAPACITY, TokenEvent};
+use crate::kiro::expiry::ExpiryForecast;
+use crate::kiro::health_state::{self, CredentialHealth, HealthWriter};
 use crate::kiro::machine_id;
-use crate::kiro::model::credentials::KiroCredentials;
+use crate::kiro::model::credentials::{CredentialsConfig, KiroCredentials, SourceFile};
 use crate::kiro::model::token_refresh::{
     IdcRefreshRequest, IdcRefreshResponse, RefreshRequest, RefreshResponse,
 };
 use crate::kiro::model::usage_limits::UsageLimitsResponse;
-use crate::model::config::Config;
+use crate::kiro::reload::{self, Fingerprint};
+use crate::model::config::{CanaryConfig, Config, SelectionStrategy};
 
 /// Token 管理器
 ///
@@ -141,14 +150,22 @@ pub(crate) async fn refresh_token(
         }
     });
 
-    if auth_method.eq_ignore_ascii_case("idc")
+    let mut refreshed = if auth_method.eq_ignore_ascii_case("idc")
         || auth_method.eq_ignore_ascii_case("builder-id")
         || auth_method.eq_ignore_ascii_case("iam")
     {
-        refresh_idc_token(credentials, config, proxy).await
+        refresh_idc_token(credentials, config, proxy).await?
     } else {
-        refresh_social_token(credentials, config, proxy).await
+        refresh_social_token(credentials, config, proxy).await?
+    };
+
+    // refreshToken 轮换后重新计算使用时长
+    if refreshed.refresh_token != credentials.refresh_token
+        || refreshed.refresh_token_obtained_at.is_none()
+    {
+        refreshed.refresh_token_obtained_at = Some(Utc::now().to_rfc3339());
     }
+    Ok(refreshed)
 }
 
 /// 刷新 Social Token
@@ -224,7 +241,7 @@ async fn refresh_social_token(
 }
 
 /// IdC Token 刷新所需的 x-amz-user-agent header
-const IDC_AMZ_USER_AGENT: &str = "aws-sdk-js/3.738.0 ua/2.1 os/other lang/js md/browser#unknown_unknown api/sso-oidc#3.738.0 m/E KiroIDE";
+pub(crate) const IDC_AMZ_USER_AGENT: &str = "aws-sdk-js/3.738.0 ua/2.1 os/other lang/js md/browser#unknown_unknown api/sso-oidc#3.738.0 m/E KiroIDE";
 
 /// 刷新 IdC Token (AWS SSO OIDC)
 async fn refresh_idc_token(
@@ -372,6 +389,17 @@ pub(crate) async fn get_usage_limits(
     Ok(data)
 }
 
+/// 原子地写入凭据文件（见 `persist`）
+fn write_credentials_file(path: &Path, json: &str) -> anyhow::Result<()> {
+    use anyhow::Context;
+
+    persist::write_atomic(path, json.as_bytes())
+        .with_context(|| format!("回写凭据文件失败: {:?}", path))?;
+
+    tracing::debug!("已回写凭据到文件: {:?}", path);
+    Ok(())
+}
+
 // ============================================================================
 // 多凭据 Token 管理器
 // ============================================================================
@@ -388,17 +416,104 @@ struct CredentialEntry {
     disabled: bool,
     /// 禁用原因（用于区分手动禁用 vs 自动禁用，便于自愈）
     disabled_reason: Option<DisabledReason>,
+    /// 软禁用：下一次允许探测的时间
+    next_probe_at: Option<Instant>,
+    /// 软禁用：连续探测成功次数
+    probe_successes: u32,
+    /// 冷却结束、尝试刷新 Token 以自动恢复的时间
+    cooldown_until: Option<Instant>,
+    /// 流式响应卡顿次数
+    stall_count: u64,
+    /// API 调用成功次数（启动以来）
+    success_count: u64,
+    /// 最近一次刷新得到的 accessToken 有效期（秒）
+    access_token_lifetime_secs: Option<i64>,
+    /// 最近一次刷新 Token 的时间
+    last_refresh_at: Option<DateTime<Utc>>,
+    /// 额度用尽后预计恢复的时间
+    exhausted_until: Option<DateTime<Utc>>,
+    /// 平滑加权轮询的当前权重
+    current_weight: i64,
+    /// 灰度期状态（运行时新增的凭据，灰度期结束后为 None）
+    canary: Option<Canary>,
+    /// 进行中的调用数（`least_loaded` 策略）
+    in_flight: Arc<AtomicUsize>,
+}
+
+/// 灰度期状态
+struct Canary {
+    /// 灰度期结束时间
+    until: Instant,
+    /// 灰度期内的成功次数
+    successes: u32,
+    /// 灰度期内的失败次数
+    failures: u32,
+}
+
+impl Canary {
+    /// 请求数达到 `minRequests` 且错误率超过 `maxErrorRate`
+    fn failed(&self, config: &CanaryConfig) -> bool {
+        let total = self.successes + self.failures;
+        total >= config.min_requests.max(1)
+            && f64::from(self.failures) / f64::from(total) > config.max_error_rate
+    }
+}
+
+impl CredentialEntry {
+    fn new(id: u64, credentials: KiroCredentials) -> Self {
+        Self {
+            id,
+            credentials,
+            failure_count: 0,
+            disabled: false,
+            disabled_reason: None,
+            next_probe_at: None,
+            probe_successes: 0,
+            cooldown_until: None,
+            stall_count: 0,
+            success_count: 0,
+            access_token_lifetime_secs: None,
+            last_refresh_at: None,
+            exhausted_until: None,
+            current_weight: 0,
+            canary: None,
+            in_flight: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    /// 重新启用，并清除失败计数与冷却、软禁用探测、额度恢复时间等自动禁用留下的状态
+    fn reenable(&mut self) {
+        self.disabled = false;
+        self.disabled_reason = None;
+        self.failure_count = 0;
+        self.next_probe_at = None;
+        self.probe_successes = 0;
+        self.cooldown_until = None;
+        self.exhausted_until = None;
+    }
 }
 
 /// 禁用原因
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-enum DisabledReason {
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum DisabledReason {
     /// Admin API 手动禁用
     Manual,
     /// 连续失败达到阈值后自动禁用
     TooManyFailures,
     /// 额度已用尽（如 MONTHLY_REQUEST_COUNT）
     QuotaExceeded,
+    /// 上游按错误码判定为不可恢复的拒绝访问（如订阅过期）
+    AccessDenied,
+    /// 灰度期内错误率超过 `canary.maxErrorRate`
+    CanaryFailed,
+}
+
+impl DisabledReason {
+    /// 冷却结束后是否尝试自动恢复（只有连续失败可能是暂时的，拒绝访问等视为不可恢复）
+    pub fn recovers_after_cooldown(self) -> bool {
+        self == Self::TooManyFailures
+    }
 }
 
 // ============================================================================
@@ -413,16 +528,34 @@ pub struct CredentialEntrySnapshot {
     pub id: u64,
     /// 优先级
     pub priority: u32,
+    /// 加权选择时的权重
+    pub weight: u32,
+    /// 是否为备用凭据
+    pub reserve: bool,
+    /// 是否处于灰度期
+    pub canary: bool,
     /// 是否被禁用
     pub disabled: bool,
     /// 连续失败次数
     pub failure_count: u32,
+    /// 流式响应卡顿次数
+    pub stall_count: u64,
+    /// API 调用成功次数（启动以来）
+    pub success_count: u64,
     /// 认证方式
     pub auth_method: Option<String>,
     /// 是否有 Profile ARN
     pub has_profile_arn: bool,
     /// Token 过期时间
     pub expires_at: Option<String>,
+    /// 来源文件（来自凭据目录或 `credentialSources` 时）
+    pub source_file: Option<String>,
+    /// 到期预估
+    pub expiry: ExpiryForecast,
+    /// 最近一次刷新 Token 的时间（RFC3339）
+    pub last_refresh_at: Option<String>,
+    /// 额度用尽后预计恢复的时间（RFC3339）
+    pub exhausted_until: Option<String>,
 }
 
 /// 凭据管理器状态快照
@@ -439,9 +572,32 @@ pub struct ManagerSnapshot {
     pub available: usize,
 }
 
+/// 重新加载凭据文件的结果
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CredentialReload {
+    /// 新增的凭据
+    pub added: Vec<u64>,
+    /// 从文件中移除的凭据
+    pub removed: Vec<u64>,
+    /// refreshToken 被替换的凭据（重置健康状态）
+    pub replaced: Vec<u64>,
+    /// 只修改了优先级、权重等元数据的凭据（保留健康状态）
+    pub updated: Vec<u64>,
+}
+
+impl CredentialReload {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty()
+            && self.removed.is_empty()
+            && self.replaced.is_empty()
+            && self.updated.is_empty()
+    }
+}
+
 /// 多凭据 Token 管理器
 ///
-/// 支持多个凭据的管理，实现固定优先级 + 故障转移策略
+/// 支持多个凭据的管理，实现固定优先级 + 故障转移策略（`selectionStrategy` 为 `weighted` 时
+/// 在优先级最高的可用凭据之间按权重轮询）
 /// 故障统计基于 API 调用结果，而非 Token 刷新结果
 pub struct MultiTokenManager {
     config: Config,
@@ -456,11 +612,211 @@ pub struct MultiTokenManager {
     credentials_path: Option<PathBuf>,
     /// 是否为多凭据格式（数组格式才回写）
     is_multiple_format: bool,
+    /// 附加凭据来源文件（回写时即使其中的凭据已全部删除也需要重写）
+    source_files: Mutex<Vec<SourceFile>>,
+    /// 重新加载时重新展开的来源（凭据目录与 `credentialSources`），见 [`Self::with_reload_sources`]
+    reload_sources: Vec<String>,
+    /// 凭据事件通道
+    events: broadcast::Sender<TokenEvent>,
+    /// 只读模式（另一实例持有实例锁）：不刷新 Token、不回写凭据
+    read_only: bool,
+    /// 健康状态后台写入器（见 [`health_state`]），未配置状态文件或只读模式下为 None
+    health_writer: Option<HealthWriter>,
+    /// 备用凭据当前是否参与轮换（用于记录启用/退出）
+    reserves_active: AtomicBool,
+    /// 最近一次回写后监视路径的指纹（见 [`reload`]）
+    self_written: Mutex<Option<Fingerprint>>,
+}
+
+/// 补全凭据的 machineId 与 refreshToken 获得时间，返回是否有改动
+fn fill_missing_fields(cred: &mut KiroCredentials, config: &Config) -> bool {
+    let mut changed = false;
+    if cred.machine_id.is_none()
+        && let Some(machine_id) = machine_id::generate_from_credentials(cred, config)
+    {
+        cred.machine_id = Some(machine_id);
+        changed = true;
+    }
+    // 未记录 refreshToken 获得时间时从首次加载开始计算
+    if cred.refresh_token.is_some() && cred.refresh_token_obtained_at.is_none() {
+        cred.refresh_token_obtained_at = Some(Utc::now().to_rfc3339());
+        changed = true;
+    }
+    changed
+}
+
+/// 用重新加载的凭据覆盖除 Token 外的所有字段，返回是否有变化
+///
+/// accessToken、过期时间与 refreshToken 获得时间是运行时状态，保留当前值；
+/// 文件中未写 machineId 时保留加载时派生的值
+fn apply_reloaded_settings(current: &mut KiroCredentials, reloaded: KiroCredentials) -> bool {
+    let KiroCredentials {
+        profile_arn,
+        auth_method,
+        client_id,
+        client_secret,
+        priority,
+        weight,
+        reserve,
+        region,
+        machine_id,
+        api_region,
+        api_endpoint,
+        source_file,
+        ..
+    } = reloaded;
+    let machine_id = machine_id.or_else(|| current.machine_id.clone());
+    let changed = current.profile_arn != profile_arn
+        || current.auth_method != auth_method
+        || current.client_id != client_id
+        || current.client_secret != client_secret
+        || current.priority != priority
+        || current.weight != weight
+        || current.reserve != reserve
+        || current.region != region
+        || current.machine_id != machine_id
+        || current.api_region != api_region
+        || current.api_endpoint != api_endpoint;
+    current.profile_arn = profile_arn;
+    current.auth_method = auth_method;
+    current.client_id = client_id;
+    current.client_secret = client_secret;
+    current.priority = priority;
+    current.weight = weight;
+    current.reserve = reserve;
+    current.region = region;
+    current.machine_id = machine_id;
+    current.api_region = api_region;
+    current.api_endpoint = api_endpoint;
+    current.source_file = source_file;
+    changed
+}
+
+/// 按 ID 恢复上次运行保存的健康状态，返回恢复的凭据数
+fn restore_health(
+    entries: &mut [CredentialEntry],
+    states: &[CredentialHealth],
+    config: &Config,
+) -> usize {
+    let now = Utc::now();
+    let mut restored = 0;
+    for entry in entries.iter_mut() {
+        let fingerprint = health_state::fingerprint(entry.credentials.refresh_token.as_deref());
+        let Some(state) = states
+            .iter()
+            .find(|s| s.id == entry.id && s.fingerprint == fingerprint)
+        else {
+            continue;
+        };
+        restored += 1;
+        entry.last_refresh_at = state.last_refresh_at;
+        let quota_reset = state.disabled_reason == Some(DisabledReason::QuotaExceeded)
+            && state.exhausted_until.is_none_or(|until| until <= now);
+        if quota_reset {
+            tracing::info!("凭据 #{} 的额度已重置，不再保持禁用", entry.id);
+            continue;
+        }
+        entry.failure_count = state.failure_count;
+        if !state.disabled {
+            continue;
+        }
+        entry.disabled = true;
+        entry.disabled_reason = Some(state.disabled_reason.unwrap_or(DisabledReason::Manual));
+        entry.exhausted_until = state.exhausted_until;
+        if entry.disabled_reason == Some(DisabledReason::TooManyFailures) {
+            entry.next_probe_at = Some(
+                Instant::now()
+                    + std::time::Duration::from_secs(config.soft_disable.probe_interval_secs),
+            );
+        }
+        if entry
+            .disabled_reason
+            .is_some_and(DisabledReason::recovers_after_cooldown)
+            && config.credential_cooldown_secs > 0
+        {
+            entry.cooldown_until = Some(
+                Instant::now() + std::time::Duration::from_secs(config.credential_cooldown_secs),
+            );
+        }
+    }
+    restored
 }
 
 /// 每个凭据最大 API 调用失败次数
 const MAX_FAILURES_PER_CREDENTIAL: u32 = 3;
 
+/// 检查冷却是否结束的最长间隔（秒）
+const COOLDOWN_CHECK_INTERVAL_SECS: u64 = 30;
+
+/// 是否参与轮换：未禁用且不在灰度期，备用凭据只在 `reserves` 为 true 时参与
+fn in_rotation(entry: &CredentialEntry, reserves: bool) -> bool {
+    !entry.disabled && entry.canary.is_none() && (reserves || !entry.credentials.reserve)
+}
+
+/// 选择下一个凭据的下标：`exclude` 以外优先级最高的可用凭据，`priority` 以外的策略在其中按策略选择
+///
+/// 只读，轮询类策略选中后需调用 [`advance_weights`]
+fn select_candidate(
+    entries: &[CredentialEntry],
+    strategy: SelectionStrategy,
+    reserves: bool,
+    exclude: &[u64],
+) -> Option<usize> {
+    let eligible = |e: &CredentialEntry| in_rotation(e, reserves) && !exclude.contains(&e.id);
+    let top = entries
+        .iter()
+        .filter(|e| eligible(e))
+        .map(|e| e.credentials.priority)
+        .min()?;
+    let mut group = entries
+        .iter()
+        .enumerate()
+        .filter(|(_, e)| eligible(e) && e.credentials.priority == top);
+    match strategy {
+        SelectionStrategy::Priority => group.next(),
+        SelectionStrategy::Weighted | SelectionStrategy::RoundRobin => {
+            group.max_by_key(|(i, e)| {
+                (
+                    e.current_weight + rotation_weight(e, strategy),
+                    std::cmp::Reverse(*i),
+                )
+            })
+        }
+        SelectionStrategy::LeastLoaded => {
+            group.min_by_key(|(i, e)| (e.in_flight.load(Ordering::Relaxed), e.success_count, *i))
+        }
+    }
+    .map(|(i, _)| i)
+}
+
+/// 轮询时的权重：`weighted` 取凭据的 `weight`，`round_robin` 一律为 1
+fn rotation_weight(entry: &CredentialEntry, strategy: SelectionStrategy) -> i64 {
+    match strategy {
+        SelectionStrategy::Weighted => i64::from(entry.credentials.effective_weight()),
+        _ => 1,
+    }
+}
+
+/// 平滑加权轮询（与 nginx 相同）：同组凭据的当前权重各加上自身权重，选中的凭据再减去组内总权重
+fn advance_weights(
+    entries: &mut [CredentialEntry],
+    picked: usize,
+    strategy: SelectionStrategy,
+    reserves: bool,
+) {
+    let priority = entries[picked].credentials.priority;
+    let mut total = 0;
+    for e in entries
+        .iter_mut()
+        .filter(|e| in_rotation(e, reserves) && e.credentials.priority == priority)
+    {
+        let weight = rotation_weight(e, strategy);
+        e.current_weight += weight;
+        total += weight;
+    }
+    entries[picked].current_weight -= total;
+}
+
 /// API 调用上下文
 ///
 /// 绑定特定凭据的调用上下文，确保 token、credentials 和 id 的一致性
@@ -473,6 +829,24 @@ pub struct CallContext {
     pub credentials: KiroCredentials,
     /// 访问 Token
     pub token: String,
+    /// 进行中调用的计数，上下文（及其全部克隆）与持有它的响应体都 drop 后归还
+    pub lease: Option<Arc<CallLease>>,
+}
+
+/// 凭据上一次进行中的调用
+pub struct CallLease(Arc<AtomicUsize>);
+
+impl CallLease {
+    pub(crate) fn acquire(in_flight: &Arc<AtomicUsize>) -> Arc<Self> {
+        in_flight.fetch_add(1, Ordering::Relaxed);
+        Arc::new(Self(in_flight.clone()))
+    }
+}
+
+impl Drop for CallLease {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
 }
 
 impl MultiTokenManager {
@@ -495,7 +869,7 @@ impl MultiTokenManager {
         let max_existing_id = credentials.iter().filter_map(|c| c.id).max().unwrap_or(0);
         let mut next_id = max_existing_id + 1;
         let mut has_new_ids = false;
-        let mut has_new_machine_ids = false;
+        let mut has_new_fields = false;
         let config_ref = &config;
 
         let entries: Vec<CredentialEntry> = credentials
@@ -509,21 +883,8 @@ impl MultiTokenManager {
                     has_new_ids = true;
                     id
                 });
-                if cred.machine_id.is_none() {
-                    if let Some(machine_id) =
-                        machine_id::generate_from_credentials(&cred, config_ref)
-                    {
-                        cred.machine_id = Some(machine_id);
-                        has_new_machine_ids = true;
-                    }
-                }
-                CredentialEntry {
-                    id,
-                    credentials: cred,
-                    failure_count: 0,
-                    disabled: false,
-                    disabled_reason: None,
-                }
+                has_new_fields |= fill_missing_fields(&mut cred, config_ref);
+                CredentialEntry::new(id, cred)
             })
             .collect();
 
@@ -539,13 +900,40 @@ impl MultiTokenManager {
             anyhow::bail!("检测到重复的凭据 ID: {:?}", duplicate_ids);
         }
 
-        // 选择初始凭据：优先级最高（priority 最小）的凭据，无凭据时为 0
+        let health_path =
+            health_state::state_path(config.state_dir.as_deref(), credentials_path.as_deref());
+        let mut entries = entries;
+        if let Some(path) = &health_path {
+            let states = health_state::load(path);
+            let restored = restore_health(&mut entries, &states, &config);
+            if restored > 0 {
+                let disabled = entries.iter().filter(|e| e.disabled).count();
+                tracing::info!(
+                    "已恢复 {} 个凭据的健康状态（{} 个保持禁用）",
+                    restored,
+                    disabled
+                );
+            }
+        }
+
+        let mut source_files: Vec<SourceFile> = Vec::new();
+        for source in entries
+            .iter()
+            .filter_map(|e| e.credentials.source_file.as_ref())
+        {
+            if !source_files.contains(source) {
+                source_files.push(source.clone());
+            }
+        }
+
+        // 选择初始凭据：优先级最高（priority 最小）的可用凭据，无凭据时为 0
         let initial_id = entries
             .iter()
-            .min_by_key(|e| e.credentials.priority)
+            .min_by_key(|e| (e.disabled, e.credentials.priority))
             .map(|e| e.id)
             .unwrap_or(0);
 
+        let reload_sources = config.credential_sources.clone();
         let manager = Self {
             config,
             proxy,
@@ -554,10 +942,17 @@ impl MultiTokenManager {
             refresh_lock: TokioMutex::new(()),
             credentials_path,
             is_multiple_format,
+            source_files: Mutex::new(source_files),
+            reload_sources,
+            events: broadcast::channel(EVENT_CHANNEL_CAPACITY).0,
+            read_only: false,
+            health_writer: health_path.map(HealthWriter::new),
+            reserves_active: AtomicBool::new(false),
+            self_written: Mutex::new(None),
         };
 
-        // 如果有新分配的 ID 或新生成的 machineId，立即持久化到配置文件
-        if has_new_ids || has_new_machine_ids {
+        // 如果有新分配的 ID、新生成的 machineId 或新记录的 refreshToken 获得时间，立即持久化到配置文件
+        if has_new_ids || has_new_fields {
             if let Err(e) = manager.persist_credentials() {
                 tracing::warn!("补全凭据 ID/machineId 后持久化失败: {}", e);
             } else {
@@ -568,11 +963,49 @@ impl MultiTokenManager {
         Ok(manager)
     }
 
+    /// 重新加载凭据时重新展开的来源（默认为 `credentialSources`）
+    ///
+    /// 凭据路径为目录时需要包含该目录，才能在重新加载时发现其中新增的文件
+    pub fn with_reload_sources(mut self, sources: Vec<String>) -> Self {
+        self.reload_sources = sources;
+        self
+    }
+
+    /// 以只读模式运行（另一实例持有实例锁时）
+    ///
+    /// Token 即将过期时不调用刷新接口（刷新会轮换 refreshToken，与持锁实例冲突），
+    /// 而是重新读取持锁实例回写的凭据文件；所有修改只保留在内存中
+    pub fn with_read_only(mut self, read_only: bool) -> Self {
+        self.read_only = read_only;
+        if read_only {
+            self.health_writer = None;
+        }
+        self
+    }
+
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
     /// 获取配置的引用
     pub fn config(&self) -> &Config {
         &self.config
     }
 
+    /// 获取代理配置
+    pub fn proxy(&self) -> Option<&ProxyConfig> {
+        self.proxy.as_ref()
+    }
+
+    /// 获取指定凭据的克隆
+    pub fn credentials_of(&self, id: u64) -> Option<KiroCredentials> {
+        self.entries
+            .lock()
+            .iter()
+            .find(|e| e.id == id)
+            .map(|e| e.credentials.clone())
+    }
+
     /// 获取当前活动凭据的克隆
     pub fn credentials(&self) -> KiroCredentials {
         let entries = self.entries.lock();
@@ -584,6 +1017,16 @@ impl MultiTokenManager {
             .unwrap_or_default()
     }
 
+    /// 订阅凭据事件
+    pub fn subscribe(&self) -> broadcast::Receiver<TokenEvent> {
+        self.events.subscribe()
+    }
+
+    /// 发布凭据事件（没有订阅方时丢弃）
+    fn emit(&self, event: TokenEvent) {
+        let _ = self.events.send(event);
+    }
+
     /// 获取凭据总数
     pub fn total_count(&self) -> usize {
         self.entries.lock().len()
@@ -594,19 +1037,78 @@ impl MultiTokenManager {
         self.entries.lock().iter().filter(|e| !e.disabled).count()
     }
 
+    /// 是否存在无需人工介入即可服务请求的凭据
+    ///
+    /// 连续失败被自动禁用的凭据会自愈或被探测，仍视为可用；没有凭据，
+    /// 或全部因手动禁用、额度用尽、拒绝访问而禁用时返回 false（降级模式）
+    pub fn has_usable_credentials(&self) -> bool {
+        self.entries
+            .lock()
+            .iter()
+            .any(|e| !e.disabled || e.disabled_reason == Some(DisabledReason::TooManyFailures))
+    }
+
+    /// 备用凭据是否参与轮换：未禁用的正常凭据少于 `reserveMinAvailable` 个时启用
+    fn reserves_active(&self, entries: &[CredentialEntry]) -> bool {
+        let min_available = self.config.reserve_min_available;
+        let active = entries
+            .iter()
+            .filter(|e| !e.disabled && e.canary.is_none() && !e.credentials.reserve)
+            .count()
+            < min_available;
+        let has_reserves = entries.iter().any(|e| e.credentials.reserve);
+        if has_reserves && self.reserves_active.swap(active, Ordering::Relaxed) != active {
+            if active {
+                tracing::warn!("可用的正常凭据少于 {} 个，启用备用凭据", min_available);
+            } else {
+                tracing::info!("正常凭据已恢复，备用凭据退出轮换");
+            }
+        }
+        active
+    }
+
+    /// 下一次调用将使用的凭据：当前凭据可用时为当前凭据，否则为优先级最高的可用凭据
+    /// （加权选择时为按权重轮询的下一个凭据），`exclude` 中的凭据不参与选择
+    ///
+    /// 只读，不刷新 Token、不切换当前凭据（用于预演）
+    pub fn peek_next(&self, exclude: &[u64]) -> Option<(u64, KiroCredentials)> {
+        let entries = self.entries.lock();
+        let reserves = self.reserves_active(&entries);
+        let strategy = self.config.selection_strategy;
+        let current_id = *self.current_id.lock();
+        let current = (strategy == SelectionStrategy::Priority && !exclude.contains(&current_id))
+            .then(|| {
+                entries
+                    .iter()
+                    .position(|e| e.id == current_id && in_rotation(e, reserves))
+            })
+            .flatten();
+        current
+            .or_else(|| select_candidate(&entries, strategy, reserves, exclude))
+            .map(|i| (entries[i].id, entries[i].credentials.clone()))
+    }
+
     /// 获取 API 调用上下文
     ///
     /// 返回绑定了 id、credentials 和 token 的调用上下文
     /// 确保整个 API 调用过程中使用一致的凭据信息
     ///
     /// 如果 Token 过期或即将过期，会自动刷新
-    /// Token 刷新失败时会尝试下一个可用凭据（不计入失败次数）
+    /// Token 刷新失败时会尝试下一个可用凭据（不计入失败次数，本次调用内不再选择该凭据）
     pub async fn acquire_context(&self) -> anyhow::Result<CallContext> {
+        self.acquire_context_avoiding(&[]).await
+    }
+
+    /// 获取 API 调用上下文，尽量避开 `avoid` 中的凭据（同一次调用中已失败的凭据）
+    ///
+    /// 所有策略都遵守该排除（轮询类策略不看 current_id，只切换当前凭据无法换到其他凭据）；
+    /// 除此之外没有可用凭据时仍会选择 `avoid` 中的凭据
+    pub async fn acquire_context_avoiding(&self, avoid: &[u64]) -> anyhow::Result<CallContext> {
         let total = self.total_count();
-        let mut tried_count = 0;
+        let mut tried: Vec<u64> = Vec::new();
 
         loop {
-            if tried_count >= total {
+            if tried.len() >= total {
                 anyhow::bail!(
                     "所有凭据均无法获取有效 Token（可用: {}/{}）",
                     self.available_count(),
@@ -614,19 +1116,70 @@ impl MultiTokenManager {
                 );
             }
 
+            // 已失败或需避开的凭据不参与探测与灰度分流
+            let excluded: Vec<u64> = tried.iter().chain(avoid).copied().collect();
+
+            // 软禁用探测：到期的自动禁用凭据放行本次请求（不改变当前凭据）
+            if let Some((id, credentials)) = self.take_due_probe(&excluded) {
+                match self.try_ensure_token(id, &credentials).await {
+                    Ok(ctx) => {
+                        tracing::info!("凭据 #{} 处于软禁用状态，本次请求作为探测", id);
+                        return Ok(ctx);
+                    }
+                    Err(e) => {
+                        tracing::warn!("凭据 #{} 探测时 Token 刷新失败: {}", id, e);
+                        tried.push(id);
+                        continue;
+                    }
+                }
+            }
+
+            // 灰度期凭据按 `canary.trafficPercent` 分流（不改变当前凭据）
+            if let Some((id, credentials)) = self.take_canary(&excluded) {
+                match self.try_ensure_token(id, &credentials).await {
+                    Ok(ctx) => return Ok(ctx),
+                    Err(e) => {
+                        tracing::warn!("灰度期凭据 #{} Token 刷新失败: {}", id, e);
+                        tried.push(id);
+                        continue;
+                    }
+                }
+            }
+
+            let mut healed = false;
             let (id, credentials) = {
                 let mut entries = self.entries.lock();
                 let current_id = *self.current_id.lock();
 
-                // 找到当前凭据
-                if let Some(entry) = entries.iter().find(|e| e.id == current_id && !e.disabled) {
+                let strategy = self.config.selection_strategy;
+                let mut reserves = self.reserves_active(&entries);
+
+                // 找到当前凭据（priority 以外的策略每次请求重新选择）
+                let pick = |entries: &[CredentialEntry], reserves: bool| {
+                    select_candidate(entries, strategy, reserves, &excluded)
+                        .or_else(|| select_candidate(entries, strategy, reserves, &tried))
+                };
+
+                if strategy == SelectionStrategy::Priority
+                    && !excluded.contains(&current_id)
+                    && let Some(entry) = entries
+                        .iter()
+                        .find(|e| e.id == current_id && in_rotation(e, reserves))
+                {
                     (entry.id, entry.credentials.clone())
                 } else {
-                    // 当前凭据不可用，选择优先级最高的可用凭据
-                    let mut best = entries
-                        .iter()
-                        .filter(|e| !e.disabled)
-                        .min_by_key(|e| e.credentials.priority);
+                    // 当前凭据不可用，选择优先级最高的可用凭据（加权选择时按权重轮询）
+                    let mut best = pick(&entries, reserves);
+
+                    // 只剩灰度期凭据可用：提前加入正常轮换
+                    if best.is_none() && entries.iter().any(|e| !e.disabled && e.canary.is_some()) {
+                        tracing::warn!("没有其他可用凭据，灰度期凭据提前加入正常轮换");
+                        for e in entries.iter_mut() {
+                            e.canary = None;
+                        }
+                        reserves = self.reserves_active(&entries);
+                        best = pick(&entries, reserves);
+                    }
 
                     // 没有可用凭据：如果是“自动禁用导致全灭”，做一次类似重启的自愈
                     if best.is_none()
@@ -634,27 +1187,34 @@ impl MultiTokenManager {
                             e.disabled && e.disabled_reason == Some(DisabledReason::TooManyFailures)
                         })
                     {
+                        healed = true;
                         tracing::warn!(
                             "所有凭据均已被自动禁用，执行自愈：重置失败计数并重新启用（等价于重启）"
                         );
                         for e in entries.iter_mut() {
                             if e.disabled_reason == Some(DisabledReason::TooManyFailures) {
-                                e.disabled = false;
-                                e.disabled_reason = None;
-                                e.failure_count = 0;
+                                e.reenable();
+                                self.emit(TokenEvent::CredentialRecovered { id: e.id });
                             }
                         }
-                        best = entries
-                            .iter()
-                            .filter(|e| !e.disabled)
-                            .min_by_key(|e| e.credentials.priority);
+                        reserves = self.reserves_active(&entries);
+                        best = pick(&entries, reserves);
                     }
 
-                    if let Some(entry) = best {
+                    if let Some(index) = best {
+                        if matches!(
+                            strategy,
+                            SelectionStrategy::Weighted | SelectionStrategy::RoundRobin
+                        ) {
+                            advance_weights(&mut entries, index, strategy, reserves);
+                        }
                         // 先提取数据
-                        let new_id = entry.id;
-                        let new_creds = entry.credentials.clone();
+                        let new_id = entries[index].id;
+                        let new_creds = entries[index].credentials.clone();
                         drop(entries);
+                        if healed {
+                            self.save_health();
+                        }
                         // 更新 current_id
                         let mut current_id = self.current_id.lock();
                         *current_id = new_id;
@@ -664,6 +1224,13 @@ impl MultiTokenManager {
                         // 因为 available_count() 会尝试获取 entries 锁，
                         // 而此时我们已经持有该锁，会导致死锁
                         let available = entries.iter().filter(|e| !e.disabled).count();
+                        if !tried.is_empty() {
+                            anyhow::bail!(
+                                "所有凭据均无法获取有效 Token（可用: {}/{}）",
+                                available,
+                                total
+                            );
+                        }
                         anyhow::bail!("所有凭据均已禁用（{}/{}）", available, total);
                     }
                 }
@@ -677,46 +1244,93 @@ impl MultiTokenManager {
                 Err(e) => {
                     tracing::warn!("凭据 #{} Token 刷新失败，尝试下一个凭据: {}", id, e);
 
-                    // Token 刷新失败，切换到下一个优先级的凭据（不计入失败次数）
-                    self.switch_to_next_by_priority();
-                    tried_count += 1;
+                    // Token 刷新失败，本次调用排除该凭据后重新选择（不计入失败次数）
+                    tried.push(id);
                 }
             }
         }
     }
 
-    /// 切换到下一个优先级最高的可用凭据（内部方法）
-    fn switch_to_next_by_priority(&self) {
-        let entries = self.entries.lock();
-        let mut current_id = self.current_id.lock();
+    /// 取出一个到期需要探测、且不在 `excluded` 中的软禁用凭据，并安排下一次探测时间（内部方法）
+    fn take_due_probe(&self, excluded: &[u64]) -> Option<(u64, KiroCredentials)> {
+        let soft_disable = &self.config.soft_disable;
+        if !soft_disable.enabled {
+            return None;
+        }
 
-        // 选择优先级最高的未禁用凭据（排除当前凭据）
-        if let Some(entry) = entries
+        let now = Instant::now();
+        let mut entries = self.entries.lock();
+        let entry = entries.iter_mut().find(|e| {
+            e.disabled
+                && e.disabled_reason == Some(DisabledReason::TooManyFailures)
+                && e.next_probe_at.is_some_and(|at| at <= now)
+                && !excluded.contains(&e.id)
+        })?;
+        entry.next_probe_at =
+            Some(now + std::time::Duration::from_secs(soft_disable.probe_interval_secs));
+        Some((entry.id, entry.credentials.clone()))
+    }
+
+    /// 按 `canary.trafficPercent` 的概率取出一个不在 `excluded` 中的灰度期凭据，灰度期已结束的凭据
+    /// 先加入正常轮换（内部方法）
+    fn take_canary(&self, excluded: &[u64]) -> Option<(u64, KiroCredentials)> {
+        let percent = self.config.canary.traffic_percent;
+        let mut entries = self.entries.lock();
+        let now = Instant::now();
+        for entry in entries.iter_mut() {
+            if let Some(canary) = entry.canary.take_if(|c| c.until <= now) {
+                tracing::info!(
+                    "凭据 #{} 灰度期结束（成功 {} 次，失败 {} 次），加入正常轮换",
+                    entry.id,
+                    canary.successes,
+                    canary.failures
+                );
+            }
+        }
+        let candidates: Vec<usize> = entries
             .iter()
-            .filter(|e| !e.disabled && e.id != *current_id)
-            .min_by_key(|e| e.credentials.priority)
-        {
-            *current_id = entry.id;
-            tracing::info!(
-                "已切换到凭据 #{}（优先级 {}）",
-                entry.id,
-                entry.credentials.priority
-            );
+            .enumerate()
+            .filter(|(_, e)| !e.disabled && e.canary.is_some() && !excluded.contains(&e.id))
+            .map(|(i, _)| i)
+            .collect();
+        if candidates.is_empty() || fastrand::u32(..100) >= percent {
+            return None;
+        }
+        let entry = &entries[candidates[fastrand::usize(..candidates.len())]];
+        Some((entry.id, entry.credentials.clone()))
+    }
+
+    /// 运行时新增的凭据进入灰度期（`canary.trafficPercent` 为 0 时直接加入轮换）
+    fn start_canary(&self, entry: &mut CredentialEntry) {
+        let config = &self.config.canary;
+        if config.traffic_percent == 0 {
+            return;
         }
+        entry.canary = Some(Canary {
+            until: Instant::now() + std::time::Duration::from_secs(config.probation_secs),
+            successes: 0,
+            failures: 0,
+        });
+        tracing::info!(
+            "凭据 #{} 进入灰度期：{} 秒内分到 {}% 的请求",
+            entry.id,
+            config.probation_secs,
+            config.traffic_percent
+        );
     }
 
     /// 选择优先级最高的未禁用凭据作为当前凭据（内部方法）
     ///
-    /// 与 `switch_to_next_by_priority` 不同，此方法不排除当前凭据，
-    /// 纯粹按优先级选择，用于优先级变更后立即生效
+    /// 不排除当前凭据，纯粹按优先级选择，用于优先级变更后立即生效
     fn select_highest_priority(&self) {
         let entries = self.entries.lock();
+        let reserves = self.reserves_active(&entries);
         let mut current_id = self.current_id.lock();
 
         // 选择优先级最高的未禁用凭据（不排除当前凭据）
         if let Some(best) = entries
             .iter()
-            .filter(|e| !e.disabled)
+            .filter(|e| in_rotation(e, reserves))
             .min_by_key(|e| e.credentials.priority)
         {
             if best.id != *current_id {
@@ -762,20 +1376,14 @@ impl MultiTokenManager {
 
             if is_token_expired(&current_creds) || is_token_expiring_soon(&current_creds) {
                 // 确实需要刷新
-                let new_creds =
-                    refresh_token(&current_creds, &self.config, self.proxy.as_ref()).await?;
+                let new_creds = self.refresh_entry(id, &current_creds).await?;
 
                 if is_token_expired(&new_creds) {
                     anyhow::bail!("刷新后的 Token 仍然无效或已过期");
                 }
 
                 // 更新凭据
-                {
-                    let mut entries = self.entries.lock();
-                    if let Some(entry) = entries.iter_mut().find(|e| e.id == id) {
-                        entry.credentials = new_creds.clone();
-                    }
-                }
+                self.store_refreshed(id, &new_creds);
 
                 // 回写凭据到文件（仅多凭据格式），失败只记录警告
                 if let Err(e) = self.persist_credentials() {
@@ -797,16 +1405,87 @@ impl MultiTokenManager {
             .clone()
             .ok_or_else(|| anyhow::anyhow!("没有可用的 accessToken"))?;
 
+        let lease = self
+            .entries
+            .lock()
+            .iter()
+            .find(|e| e.id == id)
+            .map(|e| CallLease::acquire(&e.in_flight));
         Ok(CallContext {
             id,
             credentials: creds,
             token,
+            lease,
         })
     }
 
+    /// 刷新凭据的 Token；只读模式下改为读取持锁实例回写的凭据
+    async fn refresh_entry(
+        &self,
+        id: u64,
+        current: &KiroCredentials,
+    ) -> anyhow::Result<KiroCredentials> {
+        if !self.read_only {
+            return refresh_token(current, &self.config, self.proxy.as_ref()).await;
+        }
+
+        let path = current
+            .source_file
+            .as_ref()
+            .map(|source| source.path.clone())
+            .or_else(|| self.credentials_path.clone())
+            .ok_or_else(|| anyhow::anyhow!("只读模式下凭据 #{} 没有可重新读取的凭据文件", id))?;
+        let mut reloaded = CredentialsConfig::load(&path)?
+            .into_sorted_credentials()
+            .into_iter()
+            .find(|c| c.id == Some(id))
+            .ok_or_else(|| anyhow::anyhow!("凭据文件 {:?} 中没有凭据 #{}", path, id))?;
+        if is_token_expired(&reloaded) {
+            bail!(
+                "只读模式（另一实例持有实例锁）下不刷新 Token，凭据 #{} 尚未被持锁实例刷新",
+                id
+            );
+        }
+        reloaded.source_file = current.source_file.clone();
+        Ok(reloaded)
+    }
+
+    /// 保存刷新后的凭据，记录 accessToken 有效期并发布刷新事件
+    fn store_refreshed(&self, id: u64, credentials: &KiroCredentials) {
+        let lifetime = credentials
+            .expires_at
+            .as_deref()
+            .and_then(|t| DateTime::parse_from_rfc3339(t).ok())
+            .map(|expires| (expires.with_timezone(&Utc) - Utc::now()).num_seconds());
+        {
+            let mut entries = self.entries.lock();
+            if let Some(entry) = entries.iter_mut().find(|e| e.id == id) {
+                entry.credentials = credentials.clone();
+                entry.access_token_lifetime_secs = lifetime;
+                entry.last_refresh_at = Some(Utc::now());
+            }
+        }
+        self.emit(TokenEvent::TokenRefreshed {
+            id,
+            expires_at: credentials.expires_at.clone(),
+        });
+    }
+
+    /// 所有凭据均已禁用时发布 `AllExhausted`，返回是否还有可用凭据
+    fn check_exhausted(&self, entries: &[CredentialEntry]) -> bool {
+        let any_available = entries.iter().any(|e| !e.disabled);
+        if !any_available {
+            self.emit(TokenEvent::AllExhausted {
+                total: entries.len(),
+            });
+        }
+        any_available
+    }
+
     /// 将凭据列表回写到源文件
     ///
-    /// 仅在以下条件满足时回写：
+    /// 来自凭据目录或 `credentialSources` 的凭据回写到各自的来源文件；
+    /// 其余凭据仅在以下条件满足时回写：
     /// - 源文件是多凭据格式（数组）
     /// - credentials_path 已设置
     ///
@@ -814,18 +1493,24 @@ impl MultiTokenManager {
     /// - `Ok(true)` - 成功写入文件
     /// - `Ok(false)` - 跳过写入（非多凭据格式或无路径配置）
     /// - `Err(_)` - 写入失败
+    ///
+    /// 写入后记录监视路径的指纹，热重载据此忽略服务自身的回写
     fn persist_credentials(&self) -> anyhow::Result<bool> {
+        let written = self.write_credentials()?;
+        if written {
+            *self.self_written.lock() = Some(reload::fingerprint(&self.watched_paths()));
+        }
+        Ok(written)
+    }
+
+    fn write_credentials(&self) -> anyhow::Result<bool> {
         use anyhow::Context;
 
-        // 仅多凭据格式才回写
-        if !self.is_multiple_format {
+        // 只读模式下由持锁实例负责回写
+        if self.read_only {
             return Ok(false);
         }
-
-        let path = match &self.credentials_path {
-            Some(p) => p,
-            None => return Ok(false),
-        };
+        self.save_health();
 
         // 收集所有凭据
         let credentials: Vec<KiroCredentials> = {
@@ -840,18 +1525,45 @@ impl MultiTokenManager {
                 .collect()
         };
 
-        // 序列化为 pretty JSON
-        let json = serde_json::to_string_pretty(&credentials).context("序列化凭据失败")?;
+        let mut written = false;
+        let source_files = self.source_files.lock().clone();
+        for source in &source_files {
+            let creds: Vec<&KiroCredentials> = credentials
+                .iter()
+                .filter(|c| c.source_file.as_ref() == Some(source))
+                .collect();
+            // 单对象格式的文件保持单对象，文件中的凭据被删除后写为空数组
+            let json = match creds.as_slice() {
+                [cred] if !source.multiple => serde_json::to_string_pretty(cred),
+                _ => serde_json::to_string_pretty(&creds),
+            }
+            .context("序列化凭据失败")?;
+            write_credentials_file(&source.path, &json)?;
+            written = true;
+        }
 
-        // 写入文件（在 Tokio runtime 内使用 block_in_place 避免阻塞 worker）
-        if tokio::runtime::Handle::try_current().is_ok() {
-            tokio::task::block_in_place(|| std::fs::write(path, &json))
-                .with_context(|| format!("回写凭据文件失败: {:?}", path))?;
-        } else {
-            std::fs::write(path, &json).with_context(|| format!("回写凭据文件失败: {:?}", path))?;
+        // 仅多凭据格式才回写
+        if !self.is_multiple_format {
+            return Ok(written);
+        }
+
+        let path = match &self.credentials_path {
+            Some(p) => p,
+            None => return Ok(written),
+        };
+
+        let credentials: Vec<&KiroCredentials> = credentials
+            .iter()
+            .filter(|c| c.source_file.is_none())
+            .collect();
+        // 凭据全部来自附加来源时不创建主凭据文件
+        if credentials.is_empty() && !path.exists() {
+            return Ok(written);
         }
 
-        tracing::debug!("已回写凭据到文件: {:?}", path);
+        // 序列化为 pretty JSON
+        let json = serde_json::to_string_pretty(&credentials).context("序列化凭据失败")?;
+        write_credentials_file(path, &json)?;
         Ok(true)
     }
 
@@ -862,11 +1574,48 @@ impl MultiTokenManager {
     /// # Arguments
     /// * `id` - 凭据 ID（来自 CallContext）
     pub fn report_success(&self, id: u64) {
+        if self.record_success(id) {
+            self.save_health();
+        }
+    }
+
+    /// 记录一次成功调用，返回健康状态是否有变化
+    fn record_success(&self, id: u64) -> bool {
         let mut entries = self.entries.lock();
         if let Some(entry) = entries.iter_mut().find(|e| e.id == id) {
+            if entry.disabled {
+                // 软禁用探测成功
+                if self.config.soft_disable.enabled
+                    && entry.disabled_reason == Some(DisabledReason::TooManyFailures)
+                {
+                    entry.probe_successes += 1;
+                    let required = self.config.soft_disable.probe_successes.max(1);
+                    if entry.probe_successes >= required {
+                        entry.reenable();
+                        tracing::info!("凭据 #{} 连续 {} 次探测成功，已自动重新启用", id, required);
+                        self.emit(TokenEvent::CredentialRecovered { id });
+                        return true;
+                    } else {
+                        tracing::info!(
+                            "凭据 #{} 探测成功（{}/{}）",
+                            id,
+                            entry.probe_successes,
+                            required
+                        );
+                    }
+                }
+                return false;
+            }
+            let had_failures = entry.failure_count > 0;
             entry.failure_count = 0;
+            entry.success_count += 1;
+            if let Some(canary) = entry.canary.as_mut() {
+                canary.successes += 1;
+            }
             tracing::debug!("凭据 #{} API 调用成功", id);
+            return had_failures;
         }
+        false
     }
 
     /// 报告指定凭据 API 调用失败
@@ -877,6 +1626,12 @@ impl MultiTokenManager {
     /// # Arguments
     /// * `id` - 凭据 ID（来自 CallContext）
     pub fn report_failure(&self, id: u64) -> bool {
+        let available = self.record_failure(id);
+        self.save_health();
+        available
+    }
+
+    fn record_failure(&self, id: u64) -> bool {
         let mut entries = self.entries.lock();
         let mut current_id = self.current_id.lock();
 
@@ -885,6 +1640,35 @@ impl MultiTokenManager {
             None => return entries.iter().any(|e| !e.disabled),
         };
 
+        // 软禁用探测失败：重新累计成功次数，等待下一次探测
+        if entry.disabled {
+            if entry.probe_successes > 0 {
+                tracing::warn!("凭据 #{} 探测失败，重新累计探测成功次数", id);
+            }
+            entry.probe_successes = 0;
+            return entries.iter().any(|e| !e.disabled);
+        }
+
+        if let Some(canary) = entry.canary.as_mut() {
+            canary.failures += 1;
+            if canary.failed(&self.config.canary) {
+                tracing::error!(
+                    "凭据 #{} 灰度期错误率过高（失败 {}/{}），已被禁用",
+                    id,
+                    canary.failures,
+                    canary.successes + canary.failures
+                );
+                entry.canary = None;
+                entry.disabled = true;
+                entry.disabled_reason = Some(DisabledReason::CanaryFailed);
+                self.emit(TokenEvent::CredentialDisabled {
+                    id,
+                    reason: DisabledReason::CanaryFailed,
+                });
+                return entries.iter().any(|e| !e.disabled);
+            }
+        }
+
         entry.failure_count += 1;
         let failure_count = entry.failure_count;
 
@@ -898,12 +1682,23 @@ impl MultiTokenManager {
         if failure_count >= MAX_FAILURES_PER_CREDENTIAL {
             entry.disabled = true;
             entry.disabled_reason = Some(DisabledReason::TooManyFailures);
+            entry.probe_successes = 0;
+            entry.next_probe_at = Some(
+                Instant::now()
+                    + std::time::Duration::from_secs(self.config.soft_disable.probe_interval_secs),
+            );
+            entry.cooldown_until = self.cooldown_deadline();
             tracing::error!("凭据 #{} 已连续失败 {} 次，已被禁用", id, failure_count);
+            self.emit(TokenEvent::CredentialDisabled {
+                id,
+                reason: DisabledReason::TooManyFailures,
+            });
 
             // 切换到优先级最高的可用凭据
+            let reserves = self.reserves_active(&entries);
             if let Some(next) = entries
                 .iter()
-                .filter(|e| !e.disabled)
+                .filter(|e| in_rotation(e, reserves))
                 .min_by_key(|e| e.credentials.priority)
             {
                 *current_id = next.id;
@@ -914,7 +1709,7 @@ impl MultiTokenManager {
                 );
             } else {
                 tracing::error!("所有凭据均已禁用！");
-                return false;
+                return self.check_exhausted(&entries);
             }
         }
 
@@ -929,6 +1724,22 @@ impl MultiTokenManager {
     /// - 切换到下一个可用凭据继续重试
     /// - 返回是否还有可用凭据
     pub fn report_quota_exhausted(&self, id: u64) -> bool {
+        let available = self.disable_immediately(id, DisabledReason::QuotaExceeded);
+        self.save_health();
+        available
+    }
+
+    /// 报告指定凭据被上游拒绝访问且不可恢复
+    ///
+    /// 用于 401/403 且 AWS 错误码命中 `disable` 规则的场景（如订阅过期），
+    /// 立即禁用并切换，返回是否还有可用凭据
+    pub fn report_access_denied(&self, id: u64) -> bool {
+        let available = self.disable_immediately(id, DisabledReason::AccessDenied);
+        self.save_health();
+        available
+    }
+
+    fn disable_immediately(&self, id: u64, reason: DisabledReason) -> bool {
         let mut entries = self.entries.lock();
         let mut current_id = self.current_id.lock();
 
@@ -942,16 +1753,32 @@ impl MultiTokenManager {
         }
 
         entry.disabled = true;
-        entry.disabled_reason = Some(DisabledReason::QuotaExceeded);
+        entry.disabled_reason = Some(reason);
         // 设为阈值，便于在管理面板中直观看到该凭据已不可用
         entry.failure_count = MAX_FAILURES_PER_CREDENTIAL;
+        entry.cooldown_until = self
+            .cooldown_deadline()
+            .filter(|_| reason.recovers_after_cooldown());
+        if reason == DisabledReason::QuotaExceeded {
+            entry.exhausted_until = Some(health_state::next_month_start(Utc::now()));
+        }
 
-        tracing::error!("凭据 #{} 额度已用尽（MONTHLY_REQUEST_COUNT），已被禁用", id);
+        match reason {
+            DisabledReason::AccessDenied => {
+                tracing::error!("凭据 #{} 被上游拒绝访问（不可恢复），已被禁用", id)
+            }
+            _ => tracing::error!("凭据 #{} 额度已用尽（MONTHLY_REQUEST_COUNT），已被禁用", id),
+        }
+        self.emit(TokenEvent::CredentialDisabled { id, reason });
+        if reason == DisabledReason::QuotaExceeded {
+            self.emit(TokenEvent::QuotaExhausted { id });
+        }
 
         // 切换到优先级最高的可用凭据
+        let reserves = self.reserves_active(&entries);
         if let Some(next) = entries
             .iter()
-            .filter(|e| !e.disabled)
+            .filter(|e| in_rotation(e, reserves))
             .min_by_key(|e| e.credentials.priority)
         {
             *current_id = next.id;
@@ -964,7 +1791,117 @@ impl MultiTokenManager {
         }
 
         tracing::error!("所有凭据均已禁用！");
-        false
+        self.check_exhausted(&entries)
+    }
+
+    /// 保存健康状态（只读模式下由持锁实例负责）：快照交给后台线程去抖写入，不在请求路径上 fsync
+    fn save_health(&self) {
+        let Some(writer) = &self.health_writer else {
+            return;
+        };
+        let states: Vec<CredentialHealth> = self
+            .entries
+            .lock()
+            .iter()
+            .map(|e| CredentialHealth {
+                id: e.id,
+                fingerprint: health_state::fingerprint(e.credentials.refresh_token.as_deref()),
+                disabled: e.disabled,
+                disabled_reason: e.disabled_reason,
+                failure_count: e.failure_count,
+                exhausted_until: e.exhausted_until,
+                last_refresh_at: e.last_refresh_at,
+            })
+            .collect();
+        writer.submit(states);
+    }
+
+    /// 自动禁用后的冷却结束时间（未配置 `credentialCooldownSecs` 时为 None）
+    fn cooldown_deadline(&self) -> Option<Instant> {
+        let secs = self.config.credential_cooldown_secs;
+        (secs > 0).then(|| Instant::now() + std::time::Duration::from_secs(secs))
+    }
+
+    /// 冷却结束后尝试恢复：对因连续失败被自动禁用的凭据强制刷新 Token，成功则重新启用，
+    /// 失败则再冷却一轮。返回重新启用的凭据 ID
+    pub async fn recover_cooled_down(&self) -> Vec<u64> {
+        let now = Instant::now();
+        let due: Vec<u64> = {
+            let mut entries = self.entries.lock();
+            entries
+                .iter_mut()
+                .filter(|e| {
+                    e.disabled
+                        && e.disabled_reason
+                            .is_some_and(DisabledReason::recovers_after_cooldown)
+                        && e.cooldown_until.is_some_and(|at| at <= now)
+                })
+                .map(|e| {
+                    e.cooldown_until = self.cooldown_deadline();
+                    e.id
+                })
+                .collect()
+        };
+
+        let mut recovered = Vec::new();
+        for id in due {
+            if let Err(e) = self.force_refresh(id).await {
+                tracing::warn!("凭据 #{} 冷却结束后刷新 Token 失败，继续冷却: {}", id, e);
+                continue;
+            }
+            if self.finish_recovery(id) {
+                recovered.push(id);
+            }
+        }
+        if !recovered.is_empty() {
+            self.save_health();
+        }
+        recovered
+    }
+
+    /// 刷新成功后重新启用凭据
+    ///
+    /// 刷新期间凭据可能已被手动禁用或因其他原因禁用，持锁后重新检查禁用原因，只恢复仍可自动恢复的凭据
+    fn finish_recovery(&self, id: u64) -> bool {
+        let mut entries = self.entries.lock();
+        let Some(entry) = entries.iter_mut().find(|e| {
+            e.id == id
+                && e.disabled
+                && e.disabled_reason
+                    .is_some_and(DisabledReason::recovers_after_cooldown)
+        }) else {
+            return false;
+        };
+        entry.reenable();
+        tracing::info!("凭据 #{} 冷却结束后刷新 Token 成功，已自动重新启用", id);
+        self.emit(TokenEvent::CredentialRecovered { id });
+        true
+    }
+
+    /// 配置了 `credentialCooldownSecs` 时在后台定期尝试恢复冷却结束的凭据
+    pub fn spawn_cooldown_recovery(self: &Arc<Self>) {
+        let secs = self.config.credential_cooldown_secs;
+        if secs == 0 {
+            return;
+        }
+        let manager = self.clone();
+        let period = std::time::Duration::from_secs(secs.min(COOLDOWN_CHECK_INTERVAL_SECS));
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(period);
+            loop {
+                interval.tick().await;
+                manager.recover_cooled_down().await;
+            }
+        });
+    }
+
+    /// 记录一次流式响应卡顿（只计数，不影响失败次数和禁用状态）
+    pub fn report_stall(&self, id: u64) {
+        let mut entries = self.entries.lock();
+        if let Some(entry) = entries.iter_mut().find(|e| e.id == id) {
+            entry.stall_count += 1;
+            tracing::warn!("凭据 #{} 流式响应卡顿（累计 {} 次）", id, entry.stall_count);
+        }
     }
 
     /// 切换到优先级最高的可用凭据
@@ -972,12 +1909,13 @@ impl MultiTokenManager {
     /// 返回是否成功切换
     pub fn switch_to_next(&self) -> bool {
         let entries = self.entries.lock();
+        let reserves = self.reserves_active(&entries);
         let mut current_id = self.current_id.lock();
 
         // 选择优先级最高的未禁用凭据（排除当前凭据）
         if let Some(next) = entries
             .iter()
-            .filter(|e| !e.disabled && e.id != *current_id)
+            .filter(|e| in_rotation(e, reserves) && e.id != *current_id)
             .min_by_key(|e| e.credentials.priority)
         {
             *current_id = next.id;
@@ -1014,6 +1952,7 @@ impl MultiTokenManager {
         let entries = self.entries.lock();
         let current_id = *self.current_id.lock();
         let available = entries.iter().filter(|e| !e.disabled).count();
+        let now = Utc::now();
 
         ManagerSnapshot {
             entries: entries
@@ -1021,8 +1960,13 @@ impl MultiTokenManager {
                 .map(|e| CredentialEntrySnapshot {
                     id: e.id,
                     priority: e.credentials.priority,
+                    weight: e.credentials.effective_weight(),
+                    reserve: e.credentials.reserve,
+                    canary: e.canary.is_some(),
                     disabled: e.disabled,
                     failure_count: e.failure_count,
+                    stall_count: e.stall_count,
+                    success_count: e.success_count,
                     auth_method: e.credentials.auth_method.as_deref().map(|m| {
                         if m.eq_ignore_ascii_case("builder-id") || m.eq_ignore_ascii_case("iam") {
                             "idc".to_string()
@@ -1032,6 +1976,14 @@ impl MultiTokenManager {
                     }),
                     has_profile_arn: e.credentials.profile_arn.is_some(),
                     expires_at: e.credentials.expires_at.clone(),
+                    source_file: e
+                        .credentials
+                        .source_file
+                        .as_ref()
+                        .map(|source| source.path.display().to_string()),
+                    expiry: self.forecast(e, now),
+                    last_refresh_at: e.last_refresh_at.map(|t| t.to_rfc3339()),
+                    exhausted_until: e.exhausted_until.map(|t| t.to_rfc3339()),
                 })
                 .collect(),
             current_id,
@@ -1040,6 +1992,25 @@ impl MultiTokenManager {
         }
     }
 
+    fn forecast(&self, entry: &CredentialEntry, now: DateTime<Utc>) -> ExpiryForecast {
+        ExpiryForecast::compute(
+            entry.credentials.refresh_token_obtained_at.as_deref(),
+            entry.access_token_lifetime_secs,
+            &self.config.expiry_forecast,
+            now,
+        )
+    }
+
+    /// 即将需要重新登录的凭据数量
+    pub fn expiring_soon_count(&self) -> usize {
+        let now = Utc::now();
+        self.entries
+            .lock()
+            .iter()
+            .filter(|e| self.forecast(e, now).expiring_soon)
+            .count()
+    }
+
     /// 设置凭据禁用状态（Admin API）
     pub fn set_disabled(&self, id: u64, disabled: bool) -> anyhow::Result<()> {
         {
@@ -1048,15 +2019,26 @@ impl MultiTokenManager {
                 .iter_mut()
                 .find(|e| e.id == id)
                 .ok_or_else(|| anyhow::anyhow!("凭据不存在: {}", id))?;
+            let was_disabled = entry.disabled;
             entry.disabled = disabled;
             if !disabled {
-                // 启用时重置失败计数
-                entry.failure_count = 0;
-                entry.disabled_reason = None;
+                // 启用时重置失败计数与自动禁用的状态，避免冷却或探测状态残留
+                entry.reenable();
+                if was_disabled {
+                    self.emit(TokenEvent::CredentialRecovered { id });
+                }
             } else {
                 entry.disabled_reason = Some(DisabledReason::Manual);
+                if !was_disabled {
+                    self.emit(TokenEvent::CredentialDisabled {
+                        id,
+                        reason: DisabledReason::Manual,
+                    });
+                    self.check_exhausted(&entries);
+                }
             }
         }
+        self.save_health();
         // 持久化更改
         self.persist_credentials()?;
         Ok(())
@@ -1090,15 +2072,70 @@ impl MultiTokenManager {
                 .iter_mut()
                 .find(|e| e.id == id)
                 .ok_or_else(|| anyhow::anyhow!("凭据不存在: {}", id))?;
-            entry.failure_count = 0;
-            entry.disabled = false;
-            entry.disabled_reason = None;
+            if entry.disabled {
+                self.emit(TokenEvent::CredentialRecovered { id });
+            }
+            entry.reenable();
         }
+        self.save_health();
         // 持久化更改
         self.persist_credentials()?;
         Ok(())
     }
 
+    /// 用重新登录得到的认证信息替换凭据（Admin API）
+    ///
+    /// `update` 修改凭据内容；随后重置失败计数并重新启用，回写凭据文件
+    pub fn reauthenticate(
+        &self,
+        id: u64,
+        update: impl FnOnce(&mut KiroCredentials),
+    ) -> anyhow::Result<()> {
+        let credentials = {
+            let mut entries = self.entries.lock();
+            let entry = entries
+                .iter_mut()
+                .find(|e| e.id == id)
+                .ok_or_else(|| anyhow::anyhow!("凭据不存在: {}", id))?;
+            update(&mut entry.credentials);
+            if entry.disabled {
+                self.emit(TokenEvent::CredentialRecovered { id });
+            }
+            entry.reenable();
+            entry.credentials.clone()
+        };
+        self.store_refreshed(id, &credentials);
+        self.save_health();
+        self.persist_credentials()?;
+        tracing::info!("凭据 #{} 已重新登录", id);
+        Ok(())
+    }
+
+    /// 强制刷新指定凭据的 Token（无论是否即将过期）
+    ///
+    /// 刷新成功后回写凭据文件，返回新凭据
+    pub async fn force_refresh(&self, id: u64) -> anyhow::Result<KiroCredentials> {
+        let _guard = self.refresh_lock.lock().await;
+        let current_creds = {
+            let entries = self.entries.lock();
+            entries
+                .iter()
+                .find(|e| e.id == id)
+                .map(|e| e.credentials.clone())
+                .ok_or_else(|| anyhow::anyhow!("凭据不存在: {}", id))?
+        };
+
+        let new_creds = self.refresh_entry(id, &current_creds).await?;
+        if is_token_expired(&new_creds) {
+            anyhow::bail!("刷新后的 Token 仍然无效或已过期");
+        }
+        self.store_refreshed(id, &new_creds);
+        if let Err(e) = self.persist_credentials() {
+            tracing::warn!("Token 刷新后持久化失败: {}", e);
+        }
+        Ok(new_creds)
+    }
+
     /// 获取指定凭据的使用额度（Admin API）
     pub async fn get_usage_limits_for(&self, id: u64) -> anyhow::Result<UsageLimitsResponse> {
         let credentials = {
@@ -1125,14 +2162,8 @@ impl MultiTokenManager {
             };
 
             if is_token_expired(&current_creds) || is_token_expiring_soon(&current_creds) {
-                let new_creds =
-                    refresh_token(&current_creds, &self.config, self.proxy.as_ref()).await?;
-                {
-                    let mut entries = self.entries.lock();
-                    if let Some(entry) = entries.iter_mut().find(|e| e.id == id) {
-                        entry.credentials = new_creds.clone();
-                    }
-                }
+                let new_creds = self.refresh_entry(id, &current_creds).await?;
+                self.store_refreshed(id, &new_creds);
                 // 持久化失败只记录警告，不影响本次请求
                 if let Err(e) = self.persist_credentials() {
                     tracing::warn!("Token 刷新后持久化失败（不影响本次请求）: {}", e);
@@ -1176,6 +2207,10 @@ impl MultiTokenManager {
     /// - `Ok(u64)` - 新凭据 ID
     /// - `Err(_)` - 验证失败或添加失败
     pub async fn add_credential(&self, new_cred: KiroCredentials) -> anyhow::Result<u64> {
+        if self.read_only {
+            bail!("只读模式（另一实例持有实例锁）下不能添加凭据");
+        }
+
         // 1. 基本验证
         validate_refresh_token(&new_cred)?;
 
@@ -1205,14 +2240,9 @@ impl MultiTokenManager {
         validated_cred.machine_id = new_cred.machine_id;
 
         {
-            let mut entries = self.entries.lock();
-            entries.push(CredentialEntry {
-                id: new_id,
-                credentials: validated_cred,
-                failure_count: 0,
-                disabled: false,
-                disabled_reason: None,
-            });
+            let mut entry = CredentialEntry::new(new_id, validated_cred);
+            self.start_canary(&mut entry);
+            self.entries.lock().push(entry);
         }
 
         // 5. 持久化
@@ -1284,12 +2314,193 @@ impl MultiTokenManager {
         tracing::info!("已删除凭据 #{}", id);
         Ok(())
     }
+
+    /// 凭据既没有回写文件也没有来源文件（来自 `KIRO_CREDENTIALS_JSON`、`KIRO_REFRESH_TOKEN_n` 等），
+    /// 重新加载时无从对比
+    fn is_pinned(&self, entry: &CredentialEntry) -> bool {
+        self.credentials_path.is_none() && entry.credentials.source_file.is_none()
+    }
+
+    /// 最近一次回写凭据文件后监视路径的指纹（从未回写时为 None）
+    pub fn self_written_fingerprint(&self) -> Option<Fingerprint> {
+        self.self_written.lock().clone()
+    }
+
+    /// 需要监视变更的路径：回写的凭据文件、附加来源文件，以及重新加载的来源（凭据目录与
+    /// `credentialSources`，通配符路径取所在目录），目录的修改时间在其中增删文件时变化
+    pub fn watched_paths(&self) -> Vec<PathBuf> {
+        let mut paths: Vec<PathBuf> = self.credentials_path.iter().cloned().collect();
+        paths.extend(self.source_files.lock().iter().map(|s| s.path.clone()));
+        for source in &self.reload_sources {
+            let path = Path::new(source);
+            let path = if source.contains(['*', '?']) {
+                path.parent().unwrap_or(Path::new("."))
+            } else {
+                path
+            };
+            if !paths.iter().any(|p| p == path) {
+                paths.push(path.to_path_buf());
+            }
+        }
+        paths
+    }
+
+    /// 重新读取凭据文件，与内存中的凭据对比后一次性替换
+    ///
+    /// 按 ID 对应：refreshToken 未变的凭据保留内存中的 Token 与健康状态（失败计数、禁用状态等），
+    /// 只更新优先级、权重、Region 等元数据；refreshToken 被替换的凭据视为新凭据；没有 ID 的凭据
+    /// 分配新 ID 并回写。文件中已不存在的凭据直接移除，使用它的进行中请求不受影响。
+    /// 目录与通配符来源重新展开，已删除的来源文件视为其中的凭据全部移除；
+    /// 没有回写文件也没有来源文件的凭据（来自环境变量等）不参与对比，保持不变。
+    /// 与 Token 刷新互斥，避免用刷新前的旧文件覆盖刚轮换的 refreshToken。
+    /// 重新加载后一个凭据也没有时视为文件正在编辑，不做修改
+    pub async fn reload_credentials(&self) -> anyhow::Result<CredentialReload> {
+        let _guard = self.refresh_lock.lock().await;
+
+        let mut loaded = match &self.credentials_path {
+            Some(path) => CredentialsConfig::load(path)?.into_sorted_credentials(),
+            None => Vec::new(),
+        };
+        let mut sources: Vec<String> = self.reload_sources.clone();
+        for source in self.source_files.lock().iter() {
+            let path = source.path.display().to_string();
+            if !sources.contains(&path) {
+                sources.push(path);
+            }
+        }
+        // 已删除的普通文件不再加载（其中的凭据随后被移除），目录与通配符由展开处理
+        sources.retain(|source| {
+            let keep = source.contains(['*', '?']) || Path::new(source).exists();
+            if !keep {
+                tracing::info!("凭据来源 {} 已不存在，移除其中的凭据", source);
+            }
+            keep
+        });
+        loaded.extend(CredentialsConfig::load_sources(&sources)?);
+        let pinned_count = self
+            .entries
+            .lock()
+            .iter()
+            .filter(|e| self.is_pinned(e))
+            .count();
+        if loaded.is_empty() && self.total_count() > pinned_count {
+            bail!("重新加载后没有任何凭据，忽略（文件可能正在编辑）");
+        }
+
+        let mut reload = CredentialReload::default();
+        let mut needs_persist = false;
+        {
+            let mut entries = self.entries.lock();
+            let mut next_id = entries
+                .iter()
+                .map(|e| e.id)
+                .chain(loaded.iter().filter_map(|c| c.id))
+                .max()
+                .unwrap_or(0)
+                + 1;
+            // 修改当前凭据前检查重复 ID，出错时保留当前凭据（新分配的 ID 不会重复）
+            let mut seen: std::collections::HashSet<u64> = entries
+                .iter()
+                .filter(|e| self.is_pinned(e))
+                .map(|e| e.id)
+                .collect();
+            for id in loaded.iter().filter_map(|c| c.id) {
+                if !seen.insert(id) {
+                    bail!("重新加载的凭据中存在重复 ID: {}", id);
+                }
+            }
+            // 不来自任何文件的凭据原样保留
+            let (pinned, rest): (Vec<_>, Vec<_>) =
+                entries.drain(..).partition(|e| self.is_pinned(e));
+            *entries = rest;
+            let mut reloaded = Vec::with_capacity(loaded.len() + pinned.len());
+            for mut cred in loaded {
+                cred.canonicalize_auth_method();
+                let id = *cred.id.get_or_insert_with(|| {
+                    needs_persist = true;
+                    next_id += 1;
+                    next_id - 1
+                });
+                let existing = entries.iter().position(|e| e.id == id);
+                let entry = match existing.map(|i| entries.remove(i)) {
+                    Some(mut entry) if entry.credentials.refresh_token == cred.refresh_token => {
+                        if apply_reloaded_settings(&mut entry.credentials, cred) {
+                            reload.updated.push(id);
+                        }
+                        entry
+                    }
+                    previous => {
+                        needs_persist |= fill_missing_fields(&mut cred, &self.config);
+                        let mut entry = CredentialEntry::new(id, cred);
+                        match previous {
+                            Some(_) => reload.replaced.push(id),
+                            None => {
+                                reload.added.push(id);
+                                self.start_canary(&mut entry);
+                            }
+                        }
+                        entry
+                    }
+                };
+                reloaded.push(entry);
+            }
+            reload.removed = entries.iter().map(|e| e.id).collect();
+            reloaded.extend(pinned);
+            *entries = reloaded;
+
+            let mut source_files: Vec<SourceFile> = Vec::new();
+            for source in entries
+                .iter()
+                .filter_map(|e| e.credentials.source_file.as_ref())
+            {
+                if !source_files.contains(source) {
+                    source_files.push(source.clone());
+                }
+            }
+            *self.source_files.lock() = source_files;
+        }
+
+        if reload.is_empty() {
+            return Ok(reload);
+        }
+        let current_id = *self.current_id.lock();
+        if !reload.updated.is_empty()
+            || reload.removed.contains(&current_id)
+            || reload.replaced.contains(&current_id)
+            || current_id == 0
+        {
+            self.select_highest_priority();
+        }
+        if self.total_count() == 0 {
+            *self.current_id.lock() = 0;
+        }
+        if needs_persist && let Err(e) = self.persist_credentials() {
+            tracing::warn!("重新加载凭据后回写失败: {}", e);
+        }
+        tracing::info!(
+            "已重新加载凭据: 新增 {:?}，移除 {:?}，替换 {:?}，更新 {:?}",
+            reload.added,
+            reload.removed,
+            reload.replaced,
+            reload.updated
+        );
+        Ok(reload)
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    /// 删除测试用的凭据文件及其备份、健康状态文件
+    fn remove_test_files(path: &Path) {
+        let state = health_state::state_path(None, Some(path)).unwrap();
+        for file in [path, state.as_path()] {
+            std::fs::remove_file(file).ok();
+            std::fs::remove_file(persist::backup_path(file)).ok();
+        }
+    }
+
     #[test]
     fn test_token_manager_new() {
         let config = Config::default();
@@ -1374,6 +2585,441 @@ mod tests {
         assert_eq!(manager.available_count(), 2);
     }
 
+    #[test]
+    fn test_persist_writes_back_to_source_files() {
+        use crate::kiro::model::credentials::CredentialsConfig;
+
+        let dir = std::env::temp_dir().join(format!("kiro-sources-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("a.json"), r#"{"refreshToken":"a"}"#).unwrap();
+        std::fs::write(dir.join("b.json"), r#"[{"refreshToken":"b","priority":1}]"#).unwrap();
+        let read = |name: &str| -> serde_json::Value {
+            serde_json::from_str(&std::fs::read_to_string(dir.join(name)).unwrap()).unwrap()
+        };
+
+        // 新分配的 ID 回写到各自的来源文件，并保持原格式
+        let creds = CredentialsConfig::load_sources(&[dir.display().to_string()]).unwrap();
+        let manager = MultiTokenManager::new(Config::default(), creds, None, None, false).unwrap();
+        assert_eq!(read("a.json")["id"], 1);
+        assert_eq!(read("b.json")[0]["id"], 2);
+        let snapshot = manager.snapshot();
+        let source = dir.join("a.json").display().to_string();
+        assert_eq!(
+            snapshot.entries[0].source_file.as_deref(),
+            Some(source.as_str())
+        );
+        // 首次加载时记录 refreshToken 获得时间，用于到期预估
+        assert!(read("a.json")["refreshTokenObtainedAt"].is_string());
+        assert_eq!(snapshot.entries[0].expiry.reauth_in_days, Some(89));
+        assert_eq!(manager.expiring_soon_count(), 0);
+
+        // 删除文件中唯一的凭据后写为空数组，其他文件不受影响
+        manager.set_disabled(1, true).unwrap();
+        manager.delete_credential(1).unwrap();
+        assert_eq!(read("a.json"), serde_json::json!([]));
+        assert_eq!(read("b.json")[0]["refreshToken"], "b");
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_reload_credentials_preserves_health() {
+        use crate::kiro::model::credentials::CredentialsConfig;
+
+        let path = std::env::temp_dir().join(format!("kiro-reload-{}.json", uuid::Uuid::new_v4()));
+        let write = |creds: serde_json::Value| {
+            std::fs::write(&path, serde_json::to_string(&creds).unwrap()).unwrap()
+        };
+        write(serde_json::json!([
+            {"id": 1, "refreshToken": "a", "priority": 0},
+            {"id": 2, "refreshToken": "b", "priority": 1},
+        ]));
+        let creds = CredentialsConfig::load(&path)
+            .unwrap()
+            .into_sorted_credentials();
+        let manager =
+            MultiTokenManager::new(Config::default(), creds, None, Some(path.clone()), true)
+                .unwrap();
+        manager.report_failure(1);
+
+        // #1 只改优先级，删除 #2，新增一个没有 ID 的凭据
+        write(serde_json::json!([
+            {"id": 1, "refreshToken": "a", "priority": 5},
+            {"refreshToken": "c"},
+        ]));
+        let reload = manager.reload_credentials().await.unwrap();
+        assert_eq!(
+            reload,
+            CredentialReload {
+                added: vec![3],
+                removed: vec![2],
+                replaced: vec![],
+                updated: vec![1],
+            }
+        );
+        let snapshot = manager.snapshot();
+        let ids: Vec<u64> = snapshot.entries.iter().map(|e| e.id).collect();
+        // 按优先级排序，切换到优先级最高的新凭据
+        assert_eq!(ids, [3, 1]);
+        assert_eq!(snapshot.entries[1].priority, 5);
+        assert_eq!(snapshot.entries[1].failure_count, 1);
+        assert_eq!(snapshot.current_id, 3);
+        let written: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        assert!(written.as_array().unwrap().iter().any(|c| c["id"] == 3));
+
+        // 无变化时不做修改；清空文件视为编辑中途，保留当前凭据
+        assert!(manager.reload_credentials().await.unwrap().is_empty());
+
+        // refreshToken 不变时其他字段（profileArn、clientId 等）同样生效
+        write(serde_json::json!([
+            {"id": 1, "refreshToken": "a", "priority": 5, "profileArn": "arn:p",
+             "authMethod": "builder-id", "clientId": "cid", "clientSecret": "cs"},
+            {"id": 3, "refreshToken": "c"},
+        ]));
+        let reload = manager.reload_credentials().await.unwrap();
+        assert_eq!(reload.updated, [1]);
+        {
+            let entries = manager.entries.lock();
+            let creds = &entries.iter().find(|e| e.id == 1).unwrap().credentials;
+            assert_eq!(creds.profile_arn.as_deref(), Some("arn:p"));
+            assert_eq!(creds.auth_method.as_deref(), Some("idc"));
+            assert_eq!(creds.client_id.as_deref(), Some("cid"));
+            assert_eq!(creds.client_secret.as_deref(), Some("cs"));
+        }
+        assert!(manager.reload_credentials().await.unwrap().is_empty());
+        write(serde_json::json!([]));
+        assert!(manager.reload_credentials().await.is_err());
+        assert_eq!(manager.total_count(), 2);
+
+        // 重复 ID 时报错，当前凭据保持不变
+        write(serde_json::json!([
+            {"id": 1, "refreshToken": "a"},
+            {"id": 1, "refreshToken": "d"},
+        ]));
+        assert!(manager.reload_credentials().await.is_err());
+        assert_eq!(manager.total_count(), 2);
+
+        remove_test_files(&path);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_reload_reexpands_sources_and_keeps_env_credentials() {
+        use crate::kiro::model::credentials::CredentialsConfig;
+
+        let dir = std::env::temp_dir().join(format!("kiro-reload-dir-{}", uuid::Uuid::new_v4()));
+        let extra = dir.join("extra");
+        std::fs::create_dir_all(&extra).unwrap();
+        let write = |path: PathBuf, id: u64| {
+            let cred = serde_json::json!({"id": id, "refreshToken": format!("r{}", id)});
+            std::fs::write(path, cred.to_string()).unwrap()
+        };
+        write(dir.join("a.json"), 1);
+        write(extra.join("kiro-b.json"), 2);
+
+        // 凭据目录 + 通配符附加来源 + 一个来自环境变量的凭据
+        let config = Config {
+            credential_sources: vec![extra.join("kiro-*.json").display().to_string()],
+            ..Default::default()
+        };
+        let dir_source = dir.display().to_string();
+        let mut creds = CredentialsConfig::load_sources(std::slice::from_ref(&dir_source)).unwrap();
+        creds.extend(CredentialsConfig::load_sources(&config.credential_sources).unwrap());
+        creds.push(KiroCredentials {
+            id: Some(10),
+            refresh_token: Some("env".to_string()),
+            ..Default::default()
+        });
+        let mut sources = vec![dir_source];
+        sources.extend(config.credential_sources.clone());
+        let manager = MultiTokenManager::new(config, creds, None, None, false)
+            .unwrap()
+            .with_reload_sources(sources);
+        assert!(manager.watched_paths().contains(&dir));
+        assert!(manager.watched_paths().contains(&extra));
+
+        // 目录与通配符中新增的文件被发现
+        write(dir.join("c.json"), 3);
+        write(extra.join("kiro-d.json"), 4);
+        let reload = manager.reload_credentials().await.unwrap();
+        assert_eq!(reload.added, [3, 4]);
+        assert!(reload.removed.is_empty());
+
+        // 删除的来源文件视为移除其中的凭据，环境变量凭据保持不变
+        std::fs::remove_file(dir.join("a.json")).unwrap();
+        std::fs::remove_file(extra.join("kiro-b.json")).unwrap();
+        let reload = manager.reload_credentials().await.unwrap();
+        assert_eq!(reload.removed, [1, 2]);
+        let mut ids: Vec<u64> = manager.snapshot().entries.iter().map(|e| e.id).collect();
+        ids.sort();
+        assert_eq!(ids, [3, 4, 10]);
+
+        // 文件来源全部删除时视为编辑中途，环境变量凭据不计入
+        std::fs::remove_file(dir.join("c.json")).unwrap();
+        std::fs::remove_file(extra.join("kiro-d.json")).unwrap();
+        assert!(manager.reload_credentials().await.is_err());
+        assert_eq!(manager.total_count(), 3);
+
+        // 文件中的 ID 与环境变量凭据重复时报错，不移除任何凭据
+        write(dir.join("e.json"), 10);
+        assert!(manager.reload_credentials().await.is_err());
+        assert_eq!(manager.total_count(), 3);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_canary_credential_probation() {
+        use crate::kiro::model::credentials::CredentialsConfig;
+
+        let path = std::env::temp_dir().join(format!("kiro-canary-{}.json", uuid::Uuid::new_v4()));
+        let expires_at = (Utc::now() + Duration::hours(1)).to_rfc3339();
+        let cred = |id: u64| {
+            serde_json::json!({
+                "id": id, "refreshToken": format!("r{}", id),
+                "accessToken": format!("t{}", id), "expiresAt": expires_at,
+            })
+        };
+        let write = |creds: Vec<serde_json::Value>| {
+            std::fs::write(&path, serde_json::to_string(&creds).unwrap()).unwrap()
+        };
+        write(vec![cred(1)]);
+        let mut config = Config::default();
+        config.canary.traffic_percent = 100;
+        config.canary.min_requests = 2;
+        config.canary.max_error_rate = 0.5;
+        let creds = CredentialsConfig::load(&path)
+            .unwrap()
+            .into_sorted_credentials();
+        let manager =
+            MultiTokenManager::new(config, creds, None, Some(path.clone()), true).unwrap();
+
+        // 新增的凭据进入灰度期，按比例分流；错误率过高时禁用
+        write(vec![cred(1), cred(2)]);
+        manager.reload_credentials().await.unwrap();
+        assert!(manager.snapshot().entries[1].canary);
+        assert_eq!(manager.acquire_context().await.unwrap().id, 2);
+        // 调用方要求避开的灰度期凭据不会被分流选中
+        for _ in 0..20 {
+            assert_eq!(manager.acquire_context_avoiding(&[2]).await.unwrap().id, 1);
+        }
+        assert!(manager.report_failure(2));
+        assert_eq!(manager.available_count(), 2);
+        manager.report_failure(2);
+        let entries = manager.snapshot().entries;
+        assert!(entries[1].disabled && !entries[1].canary);
+        assert_eq!(manager.acquire_context().await.unwrap().id, 1);
+
+        remove_test_files(&path);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_cooldown_recovers_failed_credential() {
+        let path =
+            std::env::temp_dir().join(format!("kiro-cooldown-{}.json", uuid::Uuid::new_v4()));
+        let creds = serde_json::json!([
+            {
+                "id": 1, "refreshToken": "r1", "accessToken": "fresh",
+                "expiresAt": (Utc::now() + Duration::hours(1)).to_rfc3339(),
+            },
+            {
+                "id": 2, "refreshToken": "r2", "accessToken": "fresh",
+                "expiresAt": (Utc::now() + Duration::hours(1)).to_rfc3339(),
+            },
+        ]);
+        let config = Config {
+            credential_cooldown_secs: 60,
+            ..Config::default()
+        };
+        let cred = |id: u64| KiroCredentials {
+            id: Some(id),
+            refresh_token: Some(format!("r{}", id)),
+            ..Default::default()
+        };
+        // 只读模式下“刷新”改为重新读取凭据文件，无需访问网络
+        let manager = MultiTokenManager::new(
+            config,
+            vec![cred(1), cred(2)],
+            None,
+            Some(path.clone()),
+            true,
+        )
+        .unwrap()
+        .with_read_only(true);
+        for _ in 0..MAX_FAILURES_PER_CREDENTIAL {
+            manager.report_failure(1);
+        }
+        // 拒绝访问视为不可恢复，不进入冷却
+        manager.report_access_denied(2);
+        assert!(manager.entries.lock()[1].cooldown_until.is_none());
+        // 持锁实例刷新后回写的凭据
+        std::fs::write(&path, creds.to_string()).unwrap();
+
+        // 冷却未结束时不尝试
+        assert!(manager.recover_cooled_down().await.is_empty());
+        for entry in manager.entries.lock().iter_mut() {
+            entry.cooldown_until = Some(Instant::now());
+        }
+        assert_eq!(manager.recover_cooled_down().await, vec![1]);
+        assert_eq!(manager.available_count(), 1);
+        let entries = manager.snapshot().entries;
+        assert!(!entries[0].disabled);
+        assert_eq!(
+            manager.entries.lock()[1].disabled_reason,
+            Some(DisabledReason::AccessDenied)
+        );
+
+        remove_test_files(&path);
+    }
+
+    #[test]
+    fn test_recovery_keeps_manual_disable_made_during_refresh() {
+        let manager = MultiTokenManager::new(
+            Config::default(),
+            vec![KiroCredentials {
+                id: Some(1),
+                refresh_token: Some("r".to_string()),
+                ..Default::default()
+            }],
+            None,
+            None,
+            false,
+        )
+        .unwrap();
+        for _ in 0..MAX_FAILURES_PER_CREDENTIAL {
+            manager.report_failure(1);
+        }
+        // 刷新 Token 期间管理员手动禁用
+        manager.set_disabled(1, true).unwrap();
+
+        assert!(!manager.finish_recovery(1));
+        let entries = manager.entries.lock();
+        assert!(entries[0].disabled);
+        assert_eq!(entries[0].disabled_reason, Some(DisabledReason::Manual));
+    }
+
+    #[test]
+    fn test_manual_enable_clears_auto_disable_state() {
+        let config = Config {
+            credential_cooldown_secs: 60,
+            ..Config::default()
+        };
+        let cred = KiroCredentials {
+            id: Some(1),
+            refresh_token: Some("r".to_string()),
+            ..Default::default()
+        };
+        let manager = MultiTokenManager::new(config, vec![cred], None, None, false).unwrap();
+        for _ in 0..MAX_FAILURES_PER_CREDENTIAL {
+            manager.report_failure(1);
+        }
+        {
+            let entries = manager.entries.lock();
+            assert!(entries[0].cooldown_until.is_some() && entries[0].next_probe_at.is_some());
+        }
+
+        manager.set_disabled(1, false).unwrap();
+        let entries = manager.entries.lock();
+        let entry = &entries[0];
+        assert!(!entry.disabled && entry.disabled_reason.is_none());
+        assert_eq!((entry.failure_count, entry.probe_successes), (0, 0));
+        assert!(entry.cooldown_until.is_none() && entry.next_probe_at.is_none());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_health_state_survives_restart() {
+        let path = std::env::temp_dir().join(format!("kiro-health-{}.json", uuid::Uuid::new_v4()));
+        let creds = || {
+            vec![
+                KiroCredentials {
+                    id: Some(1),
+                    refresh_token: Some("r1".to_string()),
+                    ..Default::default()
+                },
+                KiroCredentials {
+                    id: Some(2),
+                    refresh_token: Some("r2".to_string()),
+                    priority: 1,
+                    ..Default::default()
+                },
+            ]
+        };
+        let manager =
+            MultiTokenManager::new(Config::default(), creds(), None, Some(path.clone()), true)
+                .unwrap();
+        manager.report_quota_exhausted(1);
+        manager.report_failure(2);
+        let until = manager.snapshot().entries[0].exhausted_until.clone();
+        assert!(until.is_some());
+        drop(manager);
+
+        // 重启后额度用尽的凭据保持禁用，初始凭据跳过它
+        let manager =
+            MultiTokenManager::new(Config::default(), creds(), None, Some(path.clone()), true)
+                .unwrap();
+        let snapshot = manager.snapshot();
+        assert!(snapshot.entries[0].disabled);
+        assert_eq!(
+            manager.entries.lock()[0].disabled_reason,
+            Some(DisabledReason::QuotaExceeded)
+        );
+        assert_eq!(snapshot.entries[0].exhausted_until, until);
+        assert_eq!(snapshot.entries[1].failure_count, 1);
+        assert_eq!(snapshot.current_id, 2);
+
+        // 重置并启用后写入状态文件，重启后不再禁用
+        manager.reset_and_enable(1).unwrap();
+        assert!(manager.entries.lock()[0].exhausted_until.is_none());
+        drop(manager);
+        let manager =
+            MultiTokenManager::new(Config::default(), creds(), None, Some(path.clone()), true)
+                .unwrap();
+        assert!(!manager.snapshot().entries[0].disabled);
+        drop(manager);
+
+        // refreshToken 已被替换的凭据不恢复
+        let mut replaced = creds();
+        replaced[0].refresh_token = Some("r1-new".to_string());
+        let manager =
+            MultiTokenManager::new(Config::default(), replaced, None, Some(path.clone()), true)
+                .unwrap();
+        assert_eq!(manager.available_count(), 2);
+
+        remove_test_files(&path);
+    }
+
+    #[test]
+    fn test_reauthenticate_replaces_tokens_and_enables() {
+        let config = Config::default();
+        let manager =
+            MultiTokenManager::new(config, vec![KiroCredentials::default()], None, None, false)
+                .unwrap();
+        let mut events = manager.subscribe();
+        manager.report_access_denied(1);
+        manager
+            .reauthenticate(1, |cred| {
+                cred.refresh_token = Some("fresh".to_string());
+                cred.expires_at = Some((Utc::now() + Duration::hours(1)).to_rfc3339());
+            })
+            .unwrap();
+
+        assert_eq!(manager.available_count(), 1);
+        assert_eq!(
+            manager.credentials().refresh_token.as_deref(),
+            Some("fresh")
+        );
+        let kinds: Vec<_> = std::iter::from_fn(|| events.try_recv().ok())
+            .map(|e| e.kind())
+            .collect();
+        assert!(kinds.contains(&"credentialRecovered"));
+        assert!(kinds.contains(&"tokenRefreshed"));
+        let lifetime = manager.snapshot().entries[0]
+            .expiry
+            .access_token_lifetime_secs;
+        assert!(lifetime.is_some_and(|secs| secs > 3500));
+        assert!(manager.reauthenticate(9, |_| {}).is_err());
+    }
+
     #[test]
     fn test_multi_token_manager_empty_credentials() {
         let config = Config::default();
@@ -1383,6 +3029,7 @@ mod tests {
         let manager = result.unwrap();
         assert_eq!(manager.total_count(), 0);
         assert_eq!(manager.available_count(), 0);
+        assert!(!manager.has_usable_credentials());
     }
 
     #[test]
@@ -1474,6 +3121,24 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_multi_token_manager_peek_next() {
+        let manager = MultiTokenManager::new(
+            Config::default(),
+            vec![KiroCredentials::default(), KiroCredentials::default()],
+            None,
+            None,
+            false,
+        )
+        .unwrap();
+
+        assert_eq!(manager.peek_next(&[]).map(|(id, _)| id), Some(1));
+        manager.set_disabled(1, true).unwrap();
+        assert_eq!(manager.peek_next(&[]).map(|(id, _)| id), Some(2));
+        manager.set_disabled(2, true).unwrap();
+        assert!(manager.peek_next(&[]).is_none());
+    }
+
     #[tokio::test]
     async fn test_multi_token_manager_acquire_context_auto_recovers_all_disabled() {
         let config = Config::default();
@@ -1496,6 +3161,7 @@ mod tests {
         }
 
         assert_eq!(manager.available_count(), 0);
+        assert!(manager.has_usable_credentials());
 
         // 应触发自愈：重置失败计数并重新启用，避免必须重启进程
         let ctx = manager.acquire_context().await.unwrap();
@@ -1503,6 +3169,180 @@ mod tests {
         assert_eq!(manager.available_count(), 2);
     }
 
+    #[tokio::test]
+    async fn test_reserve_credentials_activate_below_threshold() {
+        let config = Config {
+            reserve_min_available: 2,
+            ..Config::default()
+        };
+        let creds: Vec<KiroCredentials> = (1..=3)
+            .map(|i| KiroCredentials {
+                access_token: Some(format!("t{}", i)),
+                expires_at: Some((Utc::now() + Duration::hours(1)).to_rfc3339()),
+                // 备用凭据优先级最高，也不参与正常轮换
+                reserve: i == 1,
+                priority: if i == 1 { 0 } else { 1 },
+                ..KiroCredentials::default()
+            })
+            .collect();
+        let manager = MultiTokenManager::new(config, creds, None, None, false).unwrap();
+        assert_eq!(manager.acquire_context().await.unwrap().id, 2);
+        assert_eq!(manager.peek_next(&[]).map(|(id, _)| id), Some(2));
+
+        // 正常凭据只剩 1 个可用，启用备用凭据
+        manager.set_disabled(3, true).unwrap();
+        manager.switch_to_next();
+        assert_eq!(manager.acquire_context().await.unwrap().id, 1);
+
+        // 恢复后备用凭据退出轮换
+        manager.set_disabled(3, false).unwrap();
+        assert_eq!(manager.acquire_context().await.unwrap().id, 2);
+        assert!(manager.snapshot().entries[0].reserve);
+    }
+
+    #[tokio::test]
+    async fn test_multi_token_manager_weighted_selection() {
+        let config = Config {
+            selection_strategy: SelectionStrategy::Weighted,
+            ..Config::default()
+        };
+        let credential = |weight: Option<u32>, priority: u32| KiroCredentials {
+            access_token: Some("t".to_string()),
+            expires_at: Some((Utc::now() + Duration::hours(1)).to_rfc3339()),
+            weight,
+            priority,
+            ..Default::default()
+        };
+        let manager = MultiTokenManager::new(
+            config,
+            vec![
+                credential(Some(3), 0),
+                credential(None, 0),
+                credential(Some(9), 1),
+            ],
+            None,
+            None,
+            false,
+        )
+        .unwrap();
+
+        // 3:1 平滑交错，较低优先级的凭据不参与
+        let mut picked = Vec::new();
+        for _ in 0..8 {
+            let next = manager.peek_next(&[]).map(|(id, _)| id);
+            let ctx = manager.acquire_context().await.unwrap();
+            assert_eq!(next, Some(ctx.id));
+            picked.push(ctx.id);
+        }
+        assert_eq!(picked, vec![1, 1, 2, 1, 1, 1, 2, 1]);
+
+        // 组内凭据全部禁用后落到下一优先级
+        manager.set_disabled(1, true).unwrap();
+        manager.set_disabled(2, true).unwrap();
+        assert_eq!(manager.acquire_context().await.unwrap().id, 3);
+    }
+
+    #[tokio::test]
+    async fn test_round_robin_and_least_loaded_selection() {
+        let credential = |weight: Option<u32>| KiroCredentials {
+            access_token: Some("t".to_string()),
+            expires_at: Some((Utc::now() + Duration::hours(1)).to_rfc3339()),
+            weight,
+            ..Default::default()
+        };
+        let manager = |strategy: SelectionStrategy| {
+            let config = Config {
+                selection_strategy: strategy,
+                ..Config::default()
+            };
+            let creds = vec![credential(Some(3)), credential(None), credential(None)];
+            MultiTokenManager::new(config, creds, None, None, false).unwrap()
+        };
+
+        // round_robin 忽略权重依次轮流
+        let round_robin = manager(SelectionStrategy::RoundRobin);
+        let mut picked = Vec::new();
+        for _ in 0..6 {
+            picked.push(round_robin.acquire_context().await.unwrap().id);
+        }
+        assert_eq!(picked, vec![1, 2, 3, 1, 2, 3]);
+
+        // least_loaded 选择进行中调用最少的凭据，上下文 drop 后归还
+        let least_loaded = manager(SelectionStrategy::LeastLoaded);
+        let first = least_loaded.acquire_context().await.unwrap();
+        let second = least_loaded.acquire_context().await.unwrap();
+        assert_eq!((first.id, second.id), (1, 2));
+        least_loaded.report_success(3);
+        assert_eq!(least_loaded.peek_next(&[]).map(|(id, _)| id), Some(3));
+        let third = least_loaded.acquire_context().await.unwrap();
+        assert_eq!(third.id, 3);
+        // 负载相同时选择成功次数较少的；克隆共享同一个计数，全部 drop 后才归还
+        let second_clone = second.clone();
+        drop(second);
+        assert_eq!(least_loaded.peek_next(&[]).map(|(id, _)| id), Some(1));
+        drop(second_clone);
+        assert_eq!(least_loaded.acquire_context().await.unwrap().id, 2);
+        drop((first, third));
+    }
+
+    #[tokio::test]
+    async fn test_refresh_failure_excludes_credential_under_least_loaded() {
+        // #1 没有 refreshToken，刷新必然失败；least_loaded 下不应反复选中它
+        let config = Config {
+            selection_strategy: SelectionStrategy::LeastLoaded,
+            ..Config::default()
+        };
+        let healthy = KiroCredentials {
+            refresh_token: Some("r".to_string()),
+            access_token: Some("t".to_string()),
+            expires_at: Some((Utc::now() + Duration::hours(1)).to_rfc3339()),
+            ..Default::default()
+        };
+        let manager = MultiTokenManager::new(
+            config,
+            vec![KiroCredentials::default(), healthy],
+            None,
+            None,
+            false,
+        )
+        .unwrap();
+
+        assert_eq!(manager.acquire_context().await.unwrap().id, 2);
+        manager.set_disabled(2, true).unwrap();
+        let err = manager.acquire_context().await.err().unwrap().to_string();
+        assert!(err.contains("所有凭据均无法获取有效 Token"), "{}", err);
+    }
+
+    #[tokio::test]
+    async fn test_acquire_context_avoiding_applies_to_every_strategy() {
+        let credential = || KiroCredentials {
+            access_token: Some("t".to_string()),
+            expires_at: Some((Utc::now() + Duration::hours(1)).to_rfc3339()),
+            ..Default::default()
+        };
+        for strategy in [
+            SelectionStrategy::Priority,
+            SelectionStrategy::Weighted,
+            SelectionStrategy::RoundRobin,
+            SelectionStrategy::LeastLoaded,
+        ] {
+            let config = Config {
+                selection_strategy: strategy,
+                ..Config::default()
+            };
+            let manager =
+                MultiTokenManager::new(config, vec![credential(), credential()], None, None, false)
+                    .unwrap();
+            for _ in 0..3 {
+                let ctx = manager.acquire_context_avoiding(&[1]).await.unwrap();
+                assert_eq!(ctx.id, 2, "{:?}", strategy);
+            }
+            // 没有其他可用凭据时仍使用被避开的凭据
+            manager.set_disabled(2, true).unwrap();
+            assert_eq!(manager.acquire_context_avoiding(&[1]).await.unwrap().id, 1);
+        }
+    }
+
     #[test]
     fn test_multi_token_manager_report_quota_exhausted() {
         let config = Config::default();
@@ -1518,8 +3358,123 @@ mod tests {
         assert_eq!(manager.available_count(), 1);
 
         // 再禁用第二个后，无可用凭据
+        assert!(manager.has_usable_credentials());
         assert!(!manager.report_quota_exhausted(2));
         assert_eq!(manager.available_count(), 0);
+        assert!(!manager.has_usable_credentials());
+    }
+
+    #[test]
+    fn test_multi_token_manager_emits_events() {
+        let manager = MultiTokenManager::new(
+            Config::default(),
+            vec![KiroCredentials::default(), KiroCredentials::default()],
+            None,
+            None,
+            false,
+        )
+        .unwrap();
+        let mut events = manager.subscribe();
+
+        for _ in 0..MAX_FAILURES_PER_CREDENTIAL {
+            manager.report_failure(1);
+        }
+        manager.report_quota_exhausted(2);
+        manager.set_disabled(1, false).unwrap();
+
+        let received: Vec<TokenEvent> = std::iter::from_fn(|| events.try_recv().ok()).collect();
+        assert_eq!(
+            received,
+            vec![
+                TokenEvent::CredentialDisabled {
+                    id: 1,
+                    reason: DisabledReason::TooManyFailures
+                },
+                TokenEvent::CredentialDisabled {
+                    id: 2,
+                    reason: DisabledReason::QuotaExceeded
+                },
+                TokenEvent::QuotaExhausted { id: 2 },
+                TokenEvent::AllExhausted { total: 2 },
+                TokenEvent::CredentialRecovered { id: 1 },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_soft_disable_probe_reenables_after_successes() {
+        let mut config = Config::default();
+        config.soft_disable.enabled = true;
+        config.soft_disable.probe_interval_secs = 0;
+        config.soft_disable.probe_successes = 2;
+        config.credential_cooldown_secs = 60;
+        let manager = MultiTokenManager::new(
+            config,
+            vec![KiroCredentials::default(), KiroCredentials::default()],
+            None,
+            None,
+            false,
+        )
+        .unwrap();
+
+        for _ in 0..MAX_FAILURES_PER_CREDENTIAL {
+            manager.report_failure(1);
+        }
+        assert_eq!(manager.available_count(), 1);
+        assert!(manager.entries.lock()[0].cooldown_until.is_some());
+
+        // 已失败或需避开的凭据不参与探测
+        assert!(manager.take_due_probe(&[1]).is_none());
+        // 探测失败会清零连续成功次数
+        assert_eq!(manager.take_due_probe(&[]).map(|(id, _)| id), Some(1));
+        manager.report_success(1);
+        manager.report_failure(1);
+        manager.report_success(1);
+        assert_eq!(manager.available_count(), 1);
+
+        manager.report_success(1);
+        assert_eq!(manager.available_count(), 2);
+        // 重新启用时一并清除冷却时间
+        assert!(manager.entries.lock()[0].cooldown_until.is_none());
+        assert!(manager.take_due_probe(&[]).is_none());
+    }
+
+    #[test]
+    fn test_soft_disable_off_does_not_probe() {
+        let manager = MultiTokenManager::new(
+            Config::default(),
+            vec![KiroCredentials::default(), KiroCredentials::default()],
+            None,
+            None,
+            false,
+        )
+        .unwrap();
+        for _ in 0..MAX_FAILURES_PER_CREDENTIAL {
+            manager.report_failure(1);
+        }
+        assert!(manager.take_due_probe(&[]).is_none());
+        manager.report_success(1);
+        manager.report_success(1);
+        assert_eq!(manager.available_count(), 1);
+    }
+
+    #[test]
+    fn test_multi_token_manager_report_access_denied() {
+        let config = Config::default();
+        let manager = MultiTokenManager::new(
+            config,
+            vec![KiroCredentials::default(), KiroCredentials::default()],
+            None,
+            None,
+            false,
+        )
+        .unwrap();
+
+        // 不等待连续失败阈值，立即禁用
+        assert!(manager.report_access_denied(1));
+        assert_eq!(manager.available_count(), 1);
+        assert!(!manager.report_access_denied(2));
+        assert_eq!(manager.available_count(), 0);
     }
 
     #[tokio::test]
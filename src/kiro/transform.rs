@@ -0,0 +1,236 @@
+//! 按模型调整上游请求体
+//!
+//! 部分 Kiro 模型需要略有不同的请求字段。这里维护一个按 `modelId` 匹配的转换器注册表，
+//! 在请求发送前应用，让这类差异以配置（`modelTransforms`）或独立的转换器实现存在，
+//! 而不是在请求转换层里堆 if-else。
+
+use std::borrow::Cow;
+use std::sync::Arc;
+
+use serde_json::Value;
+
+use crate::model::config::ModelTransformRule;
+
+/// 当前消息的模型 ID 位置
+const MODEL_ID_POINTER: &str = "/conversationState/currentMessage/userInputMessage/modelId";
+
+/// 请求体转换器
+pub trait BodyTransformer: Send + Sync {
+    /// 原地修改请求体
+    fn transform(&self, body: &mut Value);
+}
+
+/// 配置驱动的转换器：设置/删除 JSON Pointer 指向的字段
+struct RuleTransformer {
+    rule: ModelTransformRule,
+}
+
+impl BodyTransformer for RuleTransformer {
+    fn transform(&self, body: &mut Value) {
+        for pointer in &self.rule.remove {
+            remove_pointer(body, pointer);
+        }
+        for (pointer, value) in &self.rule.set {
+            set_pointer(body, pointer, value.clone());
+        }
+    }
+}
+
+/// 模型匹配模式：精确匹配，或以 `*` 结尾时按前缀匹配
+pub(crate) fn model_matches(pattern: &str, model: &str) -> bool {
+    match pattern.strip_suffix('*') {
+        Some(prefix) => model.starts_with(prefix),
+        None => pattern == model,
+    }
+}
+
+/// 转换器注册表（按注册顺序全部应用）
+#[derive(Default, Clone)]
+pub struct TransformRegistry {
+    entries: Vec<(String, Arc<dyn BodyTransformer>)>,
+}
+
+impl TransformRegistry {
+    /// 从配置规则创建
+    pub fn from_rules(rules: &[ModelTransformRule]) -> Self {
+        let mut registry = Self::default();
+        for rule in rules {
+            let model = rule.model.clone();
+            registry.register(model, Arc::new(RuleTransformer { rule: rule.clone() }));
+        }
+        registry
+    }
+
+    /// 注册转换器
+    pub fn register(&mut self, model: impl Into<String>, transformer: Arc<dyn BodyTransformer>) {
+        self.entries.push((model.into(), transformer));
+    }
+
+    /// 对请求体应用匹配的转换器，无匹配或请求体无法解析时原样返回
+    pub fn apply<'a>(&self, request_body: &'a str) -> Cow<'a, str> {
+        if self.entries.is_empty() {
+            return Cow::Borrowed(request_body);
+        }
+        let Ok(mut body) = serde_json::from_str::<Value>(request_body) else {
+            return Cow::Borrowed(request_body);
+        };
+        let Some(model) = body
+            .pointer(MODEL_ID_POINTER)
+            .and_then(|v| v.as_str())
+            .map(|s| s.to_string())
+        else {
+            return Cow::Borrowed(request_body);
+        };
+
+        let mut applied = false;
+        for (pattern, transformer) in &self.entries {
+            if model_matches(pattern, &model) {
+                transformer.transform(&mut body);
+                applied = true;
+            }
+        }
+        if !applied {
+            return Cow::Borrowed(request_body);
+        }
+
+        tracing::debug!("已按模型 {} 调整请求体", model);
+        match serde_json::to_string(&body) {
+            Ok(s) => Cow::Owned(s),
+            Err(e) => {
+                tracing::warn!("序列化调整后的请求体失败，使用原请求体: {}", e);
+                Cow::Borrowed(request_body)
+            }
+        }
+    }
+}
+
+/// 拆分 JSON Pointer（`~1` -> `/`，`~0` -> `~`）
+fn pointer_tokens(pointer: &str) -> Vec<String> {
+    pointer
+        .split('/')
+        .skip(1)
+        .map(|t| t.replace("~1", "/").replace("~0", "~"))
+        .collect()
+}
+
+fn set_pointer(body: &mut Value, pointer: &str, value: Value) {
+    let tokens = pointer_tokens(pointer);
+    let Some((last, parents)) = tokens.split_last() else {
+        *body = value;
+        return;
+    };
+
+    let mut target = body;
+    for token in parents {
+        if target.is_null() {
+            *target = Value::Object(Default::default());
+        }
+        target = match target {
+            Value::Object(map) => map
+                .entry(token.clone())
+                .or_insert_with(|| Value::Object(Default::default())),
+            Value::Array(items) => match token.parse::<usize>().ok().and_then(|i| items.get_mut(i))
+            {
+                Some(item) => item,
+                None => return,
+            },
+            _ => return,
+        };
+    }
+
+    match target {
+        Value::Object(map) => {
+            map.insert(last.clone(), value);
+        }
+        Value::Array(items) => {
+            if let Some(item) = last.parse::<usize>().ok().and_then(|i| items.get_mut(i)) {
+                *item = value;
+            } else if last == "-" {
+                items.push(value);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn remove_pointer(body: &mut Value, pointer: &str) {
+    let Some((parent, last)) = pointer.rsplit_once('/') else {
+        return;
+    };
+    let last = last.replace("~1", "/").replace("~0", "~");
+    match body.pointer_mut(parent) {
+        Some(Value::Object(map)) => {
+            map.remove(&last);
+        }
+        Some(Value::Array(items)) => {
+            if let Some(i) = last.parse::<usize>().ok().filter(|i| *i < items.len()) {
+                items.remove(i);
+            }
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn body(model: &str) -> String {
+        json!({
+            "conversationState": {
+                "agentTaskType": "vibe",
+                "currentMessage": {"userInputMessage": {"content": "hi", "modelId": model}}
+            }
+        })
+        .to_string()
+    }
+
+    fn rule(value: Value) -> ModelTransformRule {
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn test_apply_matching_rules() {
+        let registry = TransformRegistry::from_rules(&[
+            rule(json!({
+                "model": "claude-opus-*",
+                "set": {"/conversationState/inferenceConfig/maxTokens": 8192},
+                "remove": ["/conversationState/agentTaskType"]
+            })),
+            rule(json!({"model": "claude-haiku-4.5", "set": {"/conversationState/x": 1}})),
+        ]);
+
+        let out: Value = serde_json::from_str(&registry.apply(&body("claude-opus-4.6"))).unwrap();
+        assert_eq!(
+            out.pointer("/conversationState/inferenceConfig/maxTokens"),
+            Some(&json!(8192))
+        );
+        assert!(out.pointer("/conversationState/agentTaskType").is_none());
+        assert!(out.pointer("/conversationState/x").is_none());
+
+        let untouched = body("claude-sonnet-4.5");
+        assert!(matches!(registry.apply(&untouched), Cow::Borrowed(_)));
+    }
+
+    #[test]
+    fn test_register_custom_transformer() {
+        struct Upper;
+        impl BodyTransformer for Upper {
+            fn transform(&self, body: &mut Value) {
+                let content = body
+                    .pointer_mut("/conversationState/currentMessage/userInputMessage/content")
+                    .unwrap();
+                *content = json!(content.as_str().unwrap().to_uppercase());
+            }
+        }
+
+        let mut registry = TransformRegistry::default();
+        registry.register("claude-sonnet-4.5", Arc::new(Upper));
+        let out: Value = serde_json::from_str(&registry.apply(&body("claude-sonnet-4.5"))).unwrap();
+        assert_eq!(
+            out.pointer("/conversationState/currentMessage/userInputMessage/content"),
+            Some(&json!("HI"))
+        );
+    }
+}
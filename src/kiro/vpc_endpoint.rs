@@ -0,0 +1,139 @@
+//! AWS VPC Endpoint（PrivateLink）支持
+//!
+//! 受限 AWS 账号内无法访问公网，需要经接口端点访问 Kiro API。端点按 Host 头路由到服务，
+//! 因此 Host 头始终保持公网域名 `q.{region}.amazonaws.com`，区别只在于连接谁：
+//! - `connect`：请求发往端点 DNS 名称，TLS SNI 为端点名称（端点证书覆盖该名称）
+//! - `resolve`：URL 与 SNI 也保持公网域名，只把公网域名解析为端点地址，
+//!   适用于未启用私有 DNS、又要求 SNI 与公网域名一致的场景
+//!
+//! 配置了 `proxyUrl` 时 DNS 由代理完成，`resolve` 方式不生效。
+
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use reqwest::ClientBuilder;
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+
+use crate::model::config::{Config, VpcEndpointConfig, VpcEndpointMode};
+
+/// 端点在指定 region 的 DNS 名称（未配置时返回 None）
+pub fn dns_name_for(config: &VpcEndpointConfig, region: &str) -> Option<String> {
+    config
+        .dns_name
+        .as_ref()
+        .map(|name| name.replace("{region}", region))
+}
+
+/// `connect` 方式下实际连接的主机（其它情况返回 None，直连公网域名）
+pub fn connect_host_for(config: &VpcEndpointConfig, region: &str) -> Option<String> {
+    match config.mode {
+        VpcEndpointMode::Connect => dns_name_for(config, region),
+        VpcEndpointMode::Resolve => None,
+    }
+}
+
+/// 为上游 HTTP 客户端挂载 `resolve` 方式的 DNS 解析器（未启用时原样返回）
+pub fn apply(builder: ClientBuilder, config: &Config) -> ClientBuilder {
+    match VpcEndpointResolver::from_config(config) {
+        Some(resolver) => builder.dns_resolver(resolver),
+        None => builder,
+    }
+}
+
+/// 从公网域名 `q.{region}.amazonaws.com` 中取出 region
+fn public_region(host: &str) -> Option<&str> {
+    host.strip_prefix("q.")?
+        .strip_suffix(".amazonaws.com")
+        .filter(|region| !region.contains('.'))
+}
+
+/// 把 Kiro API 公网域名解析为端点地址的 DNS 解析器（`resolve` 方式）
+///
+/// 其它域名按系统 DNS 解析
+pub struct VpcEndpointResolver {
+    config: VpcEndpointConfig,
+}
+
+impl VpcEndpointResolver {
+    /// 从配置创建（仅 `resolve` 方式且配置了端点时启用）
+    pub fn from_config(config: &Config) -> Option<Arc<Self>> {
+        let vpc = &config.vpc_endpoint;
+        if vpc.mode != VpcEndpointMode::Resolve || vpc.dns_name.is_none() {
+            return None;
+        }
+        if config.proxy_url.is_some() {
+            tracing::warn!("已配置 proxyUrl，DNS 由代理解析，vpcEndpoint 的 resolve 方式不生效");
+        }
+        Some(Arc::new(Self {
+            config: vpc.clone(),
+        }))
+    }
+
+    /// 实际解析的主机名
+    fn target(&self, host: &str) -> String {
+        public_region(host)
+            .and_then(|region| dns_name_for(&self.config, region))
+            .unwrap_or_else(|| host.to_string())
+    }
+}
+
+impl Resolve for VpcEndpointResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let target = self.target(name.as_str());
+        Box::pin(async move {
+            // 端口为 0，由连接器替换为 URL 中的端口
+            let addrs: Vec<SocketAddr> = tokio::net::lookup_host((target.as_str(), 0))
+                .await?
+                .collect();
+            Ok(Box::new(addrs.into_iter()) as Addrs)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_resolver_maps_public_domain_to_endpoint() {
+        let mut config = Config {
+            vpc_endpoint: VpcEndpointConfig {
+                dns_name: Some("vpce-0abc.q.{region}.vpce.amazonaws.com".to_string()),
+                mode: VpcEndpointMode::Connect,
+            },
+            ..Default::default()
+        };
+        assert!(VpcEndpointResolver::from_config(&config).is_none());
+        assert_eq!(
+            connect_host_for(&config.vpc_endpoint, "eu-central-1").as_deref(),
+            Some("vpce-0abc.q.eu-central-1.vpce.amazonaws.com")
+        );
+
+        config.vpc_endpoint.mode = VpcEndpointMode::Resolve;
+        assert!(connect_host_for(&config.vpc_endpoint, "eu-central-1").is_none());
+        let resolver = VpcEndpointResolver::from_config(&config).unwrap();
+        assert_eq!(
+            resolver.target("q.eu-central-1.amazonaws.com"),
+            "vpce-0abc.q.eu-central-1.vpce.amazonaws.com"
+        );
+        assert_eq!(
+            resolver.target("oidc.us-east-1.amazonaws.com"),
+            "oidc.us-east-1.amazonaws.com"
+        );
+        assert_eq!(
+            resolver.target("q.evil.com.amazonaws.com"),
+            "q.evil.com.amazonaws.com"
+        );
+
+        // 端点名称解析到的地址即为公网域名的解析结果
+        config.vpc_endpoint.dns_name = Some("localhost".to_string());
+        let resolver = VpcEndpointResolver::from_config(&config).unwrap();
+        let addrs: Vec<SocketAddr> = resolver
+            .resolve("q.us-east-1.amazonaws.com".parse().unwrap())
+            .await
+            .unwrap()
+            .collect();
+        assert!(!addrs.is_empty());
+        assert!(addrs.iter().all(|addr| addr.ip().is_loopback()));
+    }
+}
@@ -0,0 +1,19 @@
+//! kiro-rs 库
+//!
+//! 二进制入口（`main.rs`）只负责解析参数和组装服务；各模块在这里导出，
+//! 以便其他程序直接嵌入 `KiroProvider`（注册拦截器、请求体转换器，使用类型化 API 等）。
+
+pub mod admin;
+pub mod admin_ui;
+pub mod anthropic;
+pub mod common;
+pub mod doctor;
+pub mod http_client;
+pub mod kiro;
+pub mod login;
+pub mod model;
+pub mod observability;
+pub mod openapi;
+pub mod simulate;
+pub mod token;
+pub mod usage;
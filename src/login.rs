@@ -0,0 +1,141 @@
+//! `kiro login` 设备码登录
+//!
+//! 发起 AWS Builder ID / IAM Identity Center 设备授权，在浏览器中完成登录后把得到的凭据（IdC 方式
+//! 刷新，未指定 `--profile-arn` 时自动查询账号的 profileArn）追加到凭据文件；设置了 stateDir 时
+//! 追加到 `{stateDir}/credentials.json`，与服务回写的位置一致。
+//! 无需再从 Kiro IDE 中手动提取 refreshToken。服务运行中时也可以调用 Admin API 的
+//! `POST /api/admin/login`，由运行中的实例直接添加。
+
+use std::path::PathBuf;
+
+use anyhow::Context;
+
+use crate::common::persist;
+use crate::http_client::ProxyConfig;
+use crate::kiro::device_auth::{self, BUILDER_ID_START_URL};
+use crate::kiro::model::credentials::{CredentialsConfig, KiroCredentials};
+use crate::model::config::Config;
+
+/// 登录参数
+pub struct LoginOptions {
+    pub start_url: Option<String>,
+    pub region: Option<String>,
+    pub priority: u32,
+    pub profile_arn: Option<String>,
+}
+
+/// 执行登录并追加凭据，返回进程退出码
+pub async fn run(config_path: &str, credentials_path: &str, options: LoginOptions) -> i32 {
+    match login(config_path, credentials_path, options).await {
+        Ok(()) => 0,
+        Err(e) => {
+            eprintln!("登录失败: {:#}", e);
+            1
+        }
+    }
+}
+
+async fn login(
+    config_path: &str,
+    credentials_path: &str,
+    options: LoginOptions,
+) -> anyhow::Result<()> {
+    let config = Config::load_with_env(config_path)
+        .with_context(|| format!("加载配置失败: {}", config_path))?;
+    let state_dir = config.state_dir.as_deref();
+    // 发起授权前先确认凭据文件可以追加
+    resolve_target(credentials_path, state_dir)?;
+
+    let proxy = ProxyConfig::from_config(&config);
+    let region = options.region.as_deref().unwrap_or(&config.region);
+    let start_url = options.start_url.as_deref().unwrap_or(BUILDER_ID_START_URL);
+    let auth = device_auth::start(region, start_url, &config, proxy.as_ref()).await?;
+    println!("请在浏览器中打开以下链接完成登录：");
+    println!(
+        "  {}",
+        auth.verification_uri_complete
+            .as_deref()
+            .unwrap_or(&auth.verification_uri)
+    );
+    println!("用户码: {}（{} 秒内有效）", auth.user_code, auth.expires_in);
+
+    let token = device_auth::poll(&auth, &config, proxy.as_ref()).await?;
+    let credentials = device_auth::login_credentials(
+        &auth,
+        token,
+        options.priority,
+        options.profile_arn,
+        &config,
+        proxy.as_ref(),
+    )
+    .await;
+    if let Some(arn) = &credentials.profile_arn {
+        println!("profileArn: {}", arn);
+    }
+
+    let (id, path) = append_credentials(credentials_path, state_dir, credentials)?;
+    println!("已将凭据 #{} 追加到 {}", id, path.display());
+    println!("服务运行中时发送 SIGHUP（或配置 credentialsWatchIntervalSecs）即可加载，无需重启");
+    Ok(())
+}
+
+/// 解析追加凭据的目标文件（附加凭据来源各自回写到来源文件，这里只追加到主凭据文件）
+fn resolve_target(
+    credentials_path: &str,
+    state_dir: Option<&str>,
+) -> anyhow::Result<(CredentialsConfig, PathBuf)> {
+    let resolved = CredentialsConfig::resolve(credentials_path, state_dir, &[])?;
+    match resolved.persist_path {
+        Some(path) => Ok((resolved.config, path)),
+        None => anyhow::bail!(
+            "凭据来自 {}，无法追加；请通过 --credentials 指定凭据文件或设置 stateDir",
+            resolved.source
+        ),
+    }
+}
+
+/// 把新凭据追加到凭据文件，返回分配的 ID 与写入的文件
+///
+/// 授权完成后重新读取凭据文件，保留登录期间服务回写的内容
+fn append_credentials(
+    credentials_path: &str,
+    state_dir: Option<&str>,
+    credentials: KiroCredentials,
+) -> anyhow::Result<(u64, PathBuf)> {
+    let (mut stored, path) = resolve_target(credentials_path, state_dir)?;
+    let id = stored.push(credentials);
+    let json = serde_json::to_string_pretty(&stored)?;
+    persist::write_atomic(&path, json.as_bytes())
+        .with_context(|| format!("写入凭据失败: {}", path.display()))?;
+    Ok((id, path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_append_credentials_keeps_existing_entries() {
+        let dir = std::env::temp_dir().join(format!("kiro-login-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("credentials.json");
+        std::fs::write(&path, r#"[{"id": 1, "refreshToken": "a"}]"#).unwrap();
+        let credentials = KiroCredentials {
+            refresh_token: Some("b".to_string()),
+            profile_arn: Some("arn:aws:profile/p".to_string()),
+            ..Default::default()
+        };
+
+        let (id, written) =
+            append_credentials(&path.display().to_string(), None, credentials).unwrap();
+        assert_eq!(id, 2);
+        assert_eq!(written, path);
+        let stored: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(stored[0]["refreshToken"], "a");
+        assert_eq!(stored[1]["id"], 2);
+        assert_eq!(stored[1]["profileArn"], "arn:aws:profile/p");
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
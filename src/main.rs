@@ -1,20 +1,36 @@
-mod admin;
-mod admin_ui;
-mod anthropic;
-mod common;
-mod http_client;
-mod kiro;
-mod model;
-pub mod token;
+use kiro_rs::{
+    admin, admin_ui, anthropic, common, doctor, http_client, kiro, login, model, observability,
+    openapi, simulate, token, usage,
+};
 
 use std::sync::Arc;
+use std::time::Duration;
 
+use admin::retention::RetentionVacuum;
 use clap::Parser;
+use common::abuse::AbuseGuard;
+use common::admission::AdmissionQueue;
+use common::client_keys::ClientKeyStore;
+use common::drain::DrainTracker;
+use common::in_flight::InFlightRequests;
+use common::firehose::Firehose;
+use common::instance_lock::{self, InstanceLock, LockOutcome};
+use common::ledger::UsageLedger;
+use common::metrics::RequestMetrics;
+use common::slo::SloTracker;
+use common::usage::UsageTracker;
+use common::load::LoadTracker;
+use common::maintenance::MaintenanceMode;
+use common::privacy::Capabilities;
+use common::session_token::SessionTokenIssuer;
+use kiro::email_alert::EmailAlerts;
+use kiro::events::TokenEventCounters;
 use kiro::model::credentials::{CredentialsConfig, KiroCredentials};
 use kiro::provider::KiroProvider;
+use kiro::telemetry::CredentialTelemetry;
 use kiro::token_manager::MultiTokenManager;
-use model::arg::Args;
-use model::config::Config;
+use model::arg::{Args, Command, ObservabilityCommand};
+use model::config::{Config, InstanceLockMode};
 
 #[tokio::main]
 async fn main() {
@@ -33,26 +49,142 @@ async fn main() {
     let config_path = args
         .config
         .unwrap_or_else(|| Config::default_config_path().to_string());
-    let config = Config::load(&config_path).unwrap_or_else(|e| {
+    let credentials_path = args
+        .credentials
+        .unwrap_or_else(|| KiroCredentials::default_credentials_path().to_string());
+
+    match &args.command {
+        Some(Command::Doctor) => {
+            std::process::exit(doctor::run(&config_path, &credentials_path).await);
+        }
+        Some(Command::Simulate { profile, json }) => {
+            std::process::exit(
+                simulate::run(&config_path, &credentials_path, profile, *json).await,
+            );
+        }
+        Some(Command::Usage {
+            since,
+            group_by,
+            csv,
+            url,
+            api_key,
+        }) => {
+            let source = match url {
+                Some(url) => usage::Source::Admin {
+                    url: url.clone(),
+                    api_key: api_key.clone(),
+                },
+                None => usage::Source::Ledger,
+            };
+            std::process::exit(usage::run(&config_path, source, since, *group_by, *csv).await);
+        }
+        Some(Command::Login {
+            start_url,
+            region,
+            priority,
+            profile_arn,
+        }) => {
+            let options = login::LoginOptions {
+                start_url: start_url.clone(),
+                region: region.clone(),
+                priority: *priority,
+                profile_arn: profile_arn.clone(),
+            };
+            std::process::exit(login::run(&config_path, &credentials_path, options).await);
+        }
+        Some(Command::Observability {
+            command: ObservabilityCommand::Export { out_dir },
+        }) => {
+            std::process::exit(observability::run(out_dir));
+        }
+        None => {}
+    }
+
+    let mut config = Config::load_with_env(&config_path).unwrap_or_else(|e| {
         tracing::error!("加载配置失败: {}", e);
         std::process::exit(1);
     });
 
+    // 可写状态目录（只读文件系统下用于凭据回写和本地文件存储）
+    if let Some(state_dir) = &config.state_dir {
+        if let Err(e) = std::fs::create_dir_all(state_dir) {
+            tracing::error!("创建状态目录失败 {}: {}", state_dir, e);
+            std::process::exit(1);
+        }
+        if config.artifacts.dir.is_none() {
+            config.artifacts.dir = Some(format!("{}/artifacts", state_dir.trim_end_matches('/')));
+        }
+        tracing::info!("状态目录: {}", state_dir);
+    }
+
     // 加载凭证（支持单对象或数组格式）
-    let credentials_path = args
-        .credentials
-        .unwrap_or_else(|| KiroCredentials::default_credentials_path().to_string());
-    let credentials_config = CredentialsConfig::load(&credentials_path).unwrap_or_else(|e| {
+    // 来源优先级：状态目录 > KIRO_CREDENTIALS(_JSON) > KIRO_REFRESH_TOKEN_n > KIRO_CREDENTIALS_FILE > 凭证文件，
+    // 另合并 credentialSources
+    let resolved = CredentialsConfig::resolve(
+        &credentials_path,
+        config.state_dir.as_deref(),
+        &config.credential_sources,
+    )
+    .unwrap_or_else(|e| {
         tracing::error!("加载凭证失败: {}", e);
         std::process::exit(1);
     });
 
-    // 判断是否为多凭据格式（用于刷新后回写）
-    let is_multiple_format = credentials_config.is_multiple();
+    // 多凭据格式（或配置了状态目录）时刷新后回写
+    let is_multiple_format = resolved.is_multiple_format;
+    let persist_path = resolved.persist_path;
+    let reload_sources = resolved.reload_sources;
+
+    // 实例锁：两个实例同时刷新同一批凭据会互相轮换掉对方的 refreshToken
+    let mut read_only = false;
+    let lock_dir = instance_lock::lock_dir(config.state_dir.as_deref(), persist_path.as_deref());
+    let _instance_lock = match lock_dir {
+        Some(dir) if config.instance_lock != InstanceLockMode::Off => {
+            match InstanceLock::try_acquire(&dir) {
+                Ok(LockOutcome::Acquired(lock)) => {
+                    tracing::debug!("已获取实例锁 {}", lock.path().display());
+                    Some(lock)
+                }
+                Ok(LockOutcome::Held { path, pid }) => {
+                    let holder = pid.map(|p| format!("（pid {}）", p)).unwrap_or_default();
+                    if config.instance_lock == InstanceLockMode::Refuse {
+                        tracing::error!(
+                            "另一实例{}已持有实例锁 {}，拒绝启动；请为各实例配置不同的 stateDir，或将 instanceLock 设为 readOnly",
+                            holder,
+                            path.display()
+                        );
+                        std::process::exit(1);
+                    }
+                    tracing::warn!(
+                        "另一实例{}已持有实例锁 {}，以只读模式启动：不刷新 Token、不回写凭据",
+                        holder,
+                        path.display()
+                    );
+                    read_only = true;
+                    None
+                }
+                Err(e) => {
+                    tracing::warn!("创建实例锁失败 {}: {}", dir.display(), e);
+                    None
+                }
+            }
+        }
+        _ => None,
+    };
 
     // 转换为按优先级排序的凭据列表
-    let credentials_list = credentials_config.into_sorted_credentials();
-    tracing::info!("已加载 {} 个凭据配置", credentials_list.len());
+    let credentials_list = resolved.config.into_sorted_credentials();
+    tracing::info!(
+        "已从 {} 加载 {} 个凭据配置",
+        resolved.source,
+        credentials_list.len()
+    );
+
+    if credentials_list.is_empty() {
+        tracing::warn!(
+            "未加载任何凭据，以降级模式启动：API 请求返回 503，可通过 Admin API 添加凭据"
+        );
+    }
 
     // 获取第一个凭据用于日志显示
     let first_credentials = credentials_list.first().cloned().unwrap_or_default();
@@ -65,13 +197,7 @@ async fn main() {
     });
 
     // 构建代理配置
-    let proxy_config = config.proxy_url.as_ref().map(|url| {
-        let mut proxy = http_client::ProxyConfig::new(url);
-        if let (Some(username), Some(password)) = (&config.proxy_username, &config.proxy_password) {
-            proxy = proxy.with_auth(username, password);
-        }
-        proxy
-    });
+    let proxy_config = http_client::ProxyConfig::from_config(&config);
 
     if proxy_config.is_some() {
         tracing::info!("已配置 HTTP 代理: {}", config.proxy_url.as_ref().unwrap());
@@ -82,31 +208,261 @@ async fn main() {
         config.clone(),
         credentials_list,
         proxy_config.clone(),
-        Some(credentials_path.into()),
+        persist_path,
         is_multiple_format,
     )
     .unwrap_or_else(|e| {
         tracing::error!("创建 Token 管理器失败: {}", e);
         std::process::exit(1);
     });
-    let token_manager = Arc::new(token_manager);
-    let kiro_provider = KiroProvider::with_proxy(token_manager.clone(), proxy_config.clone());
+    let token_manager = Arc::new(
+        token_manager
+            .with_reload_sources(reload_sources)
+            .with_read_only(read_only),
+    );
+    let capabilities = Capabilities::from_config(&config);
+    if config.privacy_mode {
+        tracing::info!("隐私模式已开启: 不记录请求内容与请求历史，不缓存响应，不导出统计与遥测");
+    }
+    if config.expiry_forecast.daily_report {
+        kiro::expiry::spawn_daily_report(token_manager.clone());
+    }
+    kiro::reload::spawn(
+        token_manager.clone(),
+        (config.credentials_watch_interval_secs > 0)
+            .then(|| Duration::from_secs(config.credentials_watch_interval_secs)),
+    );
+    token_manager.spawn_cooldown_recovery();
+    if let Some(telemetry) = CredentialTelemetry::from_config(
+        &config,
+        token_manager.clone(),
+        proxy_config.as_ref(),
+        capabilities,
+    ) {
+        telemetry.spawn(token_manager.subscribe());
+        tracing::info!(
+            "凭据生命周期遥测已启用: 每 {}s 导出到 {}",
+            config.otel.export_interval_secs,
+            config.otel.endpoint.as_deref().unwrap_or_default()
+        );
+    }
+    if let Some(alerts) = EmailAlerts::from_config(&config) {
+        alerts.spawn(token_manager.subscribe());
+        tracing::info!(
+            "告警邮件已启用: 发送到 {}，合并窗口 {}s",
+            config.email_alert.to.join(", "),
+            config.email_alert.batch_window_secs
+        );
+    }
+    let load = Arc::new(LoadTracker::new());
+    let kiro_provider = KiroProvider::builder(token_manager.clone())
+        .proxy(proxy_config.clone())
+        .build()
+        .unwrap_or_else(|e| {
+            tracing::error!("创建 Kiro Provider 失败: {}", e);
+            std::process::exit(1);
+        })
+        .with_load_tracker(load.clone());
+    let shadow = kiro_provider.shadow();
+    let raw_capture = kiro_provider.raw_capture();
+    let rate_pacer = kiro_provider.rate_pacer();
 
     // 初始化 count_tokens 配置
     token::init_config(token::CountTokensConfig {
         api_url: config.count_tokens_api_url.clone(),
         api_key: config.count_tokens_api_key.clone(),
         auth_type: config.count_tokens_auth_type.clone(),
-        proxy: proxy_config,
+        proxy: proxy_config.clone(),
         tls_backend: config.tls_backend,
     });
 
-    // 构建 Anthropic API 路由（从第一个凭据获取 profile_arn）
-    let anthropic_app = anthropic::create_router_with_provider(
-        &api_key,
-        Some(kiro_provider),
-        first_credentials.profile_arn.clone(),
+    // 注册自定义模型
+    anthropic::init_custom_models(
+        config
+            .custom_models
+            .iter()
+            .map(|m| anthropic::CustomModel {
+                id: m.id.clone(),
+                model_id: m.model_id.clone(),
+                agent_task_type: m.agent_task_type.clone(),
+                display_name: m.display_name.clone(),
+            })
+            .collect(),
+    );
+
+    // 滥用检测器（Anthropic API 与 Admin API 共享）
+    let abuse_guard = Arc::new(AbuseGuard::new(config.abuse_guard.clone()));
+    // 客户端密钥（配置文件 + stateDir 中开通的，Anthropic API 与 Admin API 共享）
+    let client_keys = Arc::new(
+        ClientKeyStore::load(config.client_keys.clone(), config.state_dir.as_deref())
+            .unwrap_or_else(|e| {
+                tracing::error!("加载客户端密钥失败: {}", e);
+                std::process::exit(1);
+            }),
+    );
+    // 用量统计（Anthropic API 与 Admin API 共享），逐请求记入用量账本并累计 Prometheus 指标
+    let usage = Arc::new(
+        UsageTracker::new()
+            .with_ledger(UsageLedger::open(config.state_dir.as_deref(), capabilities))
+            .with_metrics(Arc::new(RequestMetrics::new(config.metrics.clone()))),
     );
+    if let Some(firehose) = Firehose::from_config(&config, capabilities) {
+        firehose.spawn(usage.subscribe());
+    }
+    // SLO 跟踪（Anthropic API 与 Admin API 共享）
+    let slo = SloTracker::from_config(&config, proxy_config.as_ref());
+    if let Some(slo) = &slo {
+        slo.spawn(usage.subscribe());
+        tracing::info!(
+            "SLO 跟踪已启用: 成功率 {}，{}ms 内完成 {}，消耗速率阈值 {}",
+            config.slo.success_target,
+            config.slo.latency_threshold_ms,
+            config.slo.latency_target,
+            config.slo.burn_rate_threshold
+        );
+    }
+    // 审计日志（Admin API 变更与数据保留期清理共用）
+    let audit = Arc::new(admin::audit::AuditLog::open(config.state_dir.as_deref()));
+    // 下游连接写出统计（Anthropic API 与 Admin API 共享）
+    let drain = Arc::new(DrainTracker::new(Duration::from_millis(
+        config.slow_client_threshold_ms,
+    )));
+    // 进行中的请求（Anthropic API 与 Admin API 共享，Admin 可中止）
+    let in_flight = Arc::new(InFlightRequests::new());
+    // 请求/响应过滤器
+    let filters = anthropic::filters::FilterChain::from_config(&config)
+        .unwrap_or_else(|e| {
+            tracing::error!("加载过滤器失败: {:#}", e);
+            std::process::exit(1);
+        });
+
+    // 维护模式（Anthropic API 与 Admin API 共享）
+    let maintenance = Arc::new(MaintenanceMode::new(
+        config.maintenance.clone(),
+        load.clone(),
+    ));
+
+    // 构建 Anthropic API 路由（从第一个凭据获取 profile_arn）
+    let mut app_state = anthropic::AppState::new(&api_key)
+        .with_kiro_provider(kiro_provider)
+        .with_abuse_guard(abuse_guard.clone())
+        .with_usage_tracker(usage.clone())
+        .with_drain_tracker(drain.clone())
+        .with_in_flight(in_flight.clone())
+        .with_client_key_store(client_keys.clone())
+        .with_filters(filters)
+        .with_maintenance(maintenance.clone())
+        .with_token_events(TokenEventCounters::spawn(token_manager.subscribe()))
+        .with_load_tracker(load.clone());
+    app_state = app_state.with_presets(anthropic::RequestPresets::from_config(&config));
+    if let Some(arn) = first_credentials.profile_arn.clone() {
+        app_state = app_state.with_profile_arn(arn);
+    }
+    if let Some(slo) = &slo {
+        app_state = app_state.with_slo(slo.clone());
+    }
+    if let Some(admission) = AdmissionQueue::from_config(&config.admission) {
+        tracing::info!(
+            "准入队列已启用: 最大并发 {}，老化间隔 {}s",
+            config.admission.max_concurrent,
+            config.admission.aging_secs
+        );
+        app_state = app_state.with_admission(admission);
+    }
+    if let Some(compressor) = anthropic::ContextCompressor::from_config(&config.context_compression)
+    {
+        tracing::info!(
+            "上下文压缩已启用: 超过 {} tokens 时摘要较早的轮次",
+            config.context_compression.threshold_tokens
+        );
+        app_state = app_state.with_context_compressor(compressor);
+    }
+    if let Some(table) = anthropic::CostTable::from_config(&config.cost_estimation) {
+        app_state = app_state.with_cost_table(table);
+    }
+    if let Some(forwarder) = anthropic::HeaderForwarder::from_config(&config.forward_headers) {
+        app_state = app_state.with_header_forwarder(forwarder);
+    }
+    if let Some(cache) = anthropic::ResponseCache::from_config(&config.response_cache, capabilities)
+    {
+        tracing::info!(
+            "响应缓存已启用: 保留 {} 秒，最多 {} 条",
+            config.response_cache.ttl_secs,
+            config.response_cache.max_entries
+        );
+        app_state = app_state.with_response_cache(cache);
+    }
+    if let Some(coalescer) = anthropic::Coalescer::from_config(&config.coalesce) {
+        tracing::info!(
+            "相同流式请求合并已启用: 窗口 {} 毫秒",
+            config.coalesce.window_ms
+        );
+        app_state = app_state.with_coalescer(coalescer);
+    }
+    if let Some(issuer) = SessionTokenIssuer::from_config(&config.session_tokens) {
+        tracing::info!(
+            "会话 Token 已启用: 默认有效期 {} 秒，最长 {} 秒",
+            config.session_tokens.default_ttl_secs,
+            config.session_tokens.max_ttl_secs
+        );
+        app_state = app_state.with_session_tokens(issuer);
+    }
+    app_state = app_state.with_azure_deployments(config.azure_deployments.clone());
+    if let Some(router) = anthropic::ContextRouter::from_config(&config.context_routing) {
+        tracing::info!(
+            "上下文长度路由已启用: 默认窗口 {} tokens，{} 条模型规则",
+            config.context_routing.default_context_window,
+            config.context_routing.models.len()
+        );
+        app_state = app_state.with_context_router(router);
+    }
+    if config.local_tools.enabled {
+        let runner = anthropic::LocalToolRunner::new(
+            config.local_tools.clone(),
+            proxy_config.as_ref(),
+            config.tls_backend,
+        )
+        .unwrap_or_else(|e| {
+            tracing::error!("创建本地工具执行器失败: {}", e);
+            std::process::exit(1);
+        });
+        app_state = app_state.with_local_tools(runner);
+        tracing::info!("本地工具沙箱已启用");
+    }
+    if config.artifacts.enabled {
+        let store = anthropic::ArtifactStore::new(
+            config.artifacts.clone(),
+            format!("http://{}:{}", config.host, config.port),
+            proxy_config.as_ref(),
+            config.tls_backend,
+        )
+        .unwrap_or_else(|e| {
+            tracing::error!("创建文件存储失败: {}", e);
+            std::process::exit(1);
+        });
+        app_state = app_state.with_artifacts(store);
+        tracing::info!("上游文件存储已启用: {:?}", config.artifacts.backend);
+    }
+    if let Some(mut vacuum) = RetentionVacuum::from_config(
+        &config.retention,
+        usage.clone(),
+        client_keys.clone(),
+        audit.clone(),
+    ) {
+        vacuum = vacuum.with_raw_capture(raw_capture.clone());
+        if let Some(artifacts) = &app_state.artifacts {
+            vacuum = vacuum.with_artifacts(artifacts.clone());
+        }
+        vacuum.spawn();
+        if config.retention.usage_days > 0 {
+            tracing::info!(
+                "用量记录保留 {} 天，每 {}s 清理一次",
+                config.retention.usage_days,
+                config.retention.vacuum_interval_secs
+            );
+        }
+    }
+    let anthropic_app = anthropic::create_router(app_state);
 
     // 构建 Admin API 路由（如果配置了非空的 admin_api_key）
     // 安全检查：空字符串被视为未配置，防止空 key 绕过认证
@@ -119,16 +475,43 @@ async fn main() {
     let app = if let Some(admin_key) = &config.admin_api_key {
         if admin_key.trim().is_empty() {
             tracing::warn!("admin_api_key 配置为空，Admin API 未启用");
+            if !config.admin_viewer_keys.is_empty() {
+                tracing::warn!("未配置 adminApiKey，adminViewerKeys 不生效");
+            }
             anthropic_app
         } else {
-            let admin_service = admin::AdminService::new(token_manager.clone());
-            let admin_state = admin::AdminState::new(admin_key, admin_service);
+            let mut admin_service = admin::AdminService::new(token_manager.clone())
+                .with_abuse_guard(abuse_guard)
+                .with_usage_tracker(usage)
+                .with_drain_tracker(drain)
+                .with_in_flight(in_flight)
+                .with_client_key_store(client_keys)
+                .with_maintenance(maintenance)
+                .with_audit_log(audit)
+                .with_raw_capture(raw_capture);
+            if let Some(shadow) = shadow {
+                admin_service = admin_service.with_shadow(shadow);
+            }
+            if let Some(pacer) = rate_pacer {
+                admin_service = admin_service.with_rate_pacer(pacer);
+            }
+            if let Some(slo) = slo {
+                admin_service = admin_service.with_slo(slo);
+            }
+            let admin_state = admin::AdminState::new(admin_key, admin_service)
+                .with_viewer_keys(config.admin_viewer_keys.clone());
             let admin_app = admin::create_admin_router(admin_state);
 
             // 创建 Admin UI 路由
             let admin_ui_app = admin_ui::create_admin_ui_router();
 
             tracing::info!("Admin API 已启用");
+            if !config.admin_viewer_keys.is_empty() {
+                tracing::info!(
+                    "已配置 {} 个只读 Admin 密钥",
+                    config.admin_viewer_keys.len()
+                );
+            }
             tracing::info!("Admin UI 已启用: /admin");
             anthropic_app
                 .nest("/api/admin", admin_app)
@@ -137,6 +520,7 @@ async fn main() {
     } else {
         anthropic_app
     };
+    let app = app.merge(openapi::create_openapi_router(admin_key_valid));
 
     // 启动服务器
     let addr = format!("{}:{}", config.host, config.port);
@@ -146,13 +530,45 @@ async fn main() {
     tracing::info!("  GET  /v1/models");
     tracing::info!("  POST /v1/messages");
     tracing::info!("  POST /v1/messages/count_tokens");
+    tracing::info!("  POST /v1/responses");
+    tracing::info!("  POST /v1/dry-run");
+    tracing::info!("  POST /v1/session-tokens");
+    tracing::info!("  POST /openai/deployments/:deployment/chat/completions");
+    tracing::info!("  GET  /metrics");
+    tracing::info!("  GET  /metrics/load");
+    tracing::info!("  GET  /health");
+    tracing::info!("  GET  /openapi.json");
     if admin_key_valid {
         tracing::info!("Admin API:");
         tracing::info!("  GET  /api/admin/credentials");
         tracing::info!("  POST /api/admin/credentials/:index/disabled");
+        tracing::info!("  POST /api/admin/credentials/:index/enable");
+        tracing::info!("  POST /api/admin/credentials/:index/disable");
+        tracing::info!("  POST /api/admin/credentials/:index/refresh");
         tracing::info!("  POST /api/admin/credentials/:index/priority");
         tracing::info!("  POST /api/admin/credentials/:index/reset");
         tracing::info!("  GET  /api/admin/credentials/:index/balance");
+        tracing::info!("  POST /api/admin/credentials/:index/reauth");
+        tracing::info!("  GET  /api/admin/credentials/:index/reauth");
+        tracing::info!("  POST /api/admin/login");
+        tracing::info!("  GET  /api/admin/login/:session");
+        tracing::info!("  GET  /api/admin/abuse-flags");
+        tracing::info!("  GET  /api/admin/client-keys");
+        tracing::info!("  POST /api/admin/client-keys");
+        tracing::info!("  DELETE /api/admin/client-keys/:name");
+        tracing::info!("  GET  /api/admin/usage");
+        tracing::info!("  GET  /api/admin/usage/summary");
+        tracing::info!("  GET  /api/admin/requests");
+        tracing::info!("  DELETE /api/admin/requests/:id");
+        tracing::info!("  GET  /api/admin/audit");
+        tracing::info!("  GET  /api/admin/shadow");
+        tracing::info!("  GET  /api/admin/rate-limits");
+        tracing::info!("  GET  /api/admin/slo");
+        tracing::info!("  GET  /api/admin/raw-capture");
+        tracing::info!("  POST /api/admin/raw-capture");
+        tracing::info!("  GET  /api/admin/maintenance");
+        tracing::info!("  POST /api/admin/maintenance");
+        tracing::info!("  GET  /api/admin/events");
         tracing::info!("Admin UI:");
         tracing::info!("  GET  /admin");
     }
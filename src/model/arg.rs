@@ -1,14 +1,85 @@
-use clap::Parser;
+use clap::{Parser, Subcommand};
+
+use crate::common::ledger::GroupBy;
 
 /// Anthropic <-> Kiro API 客户端
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
 pub struct Args {
     /// 配置文件路径
-    #[arg(short, long)]
+    #[arg(short, long, global = true, env = "KIRO_CONFIG_PATH")]
     pub config: Option<String>,
 
     /// 凭证文件路径
-    #[arg(long)]
+    #[arg(long, global = true, env = "KIRO_CREDENTIALS_PATH")]
     pub credentials: Option<String>,
+
+    /// 子命令（不指定时启动服务）
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+/// 子命令
+#[derive(Subcommand, Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    /// 启动自检：配置、DNS、代理、TLS、Token 刷新、machineId
+    Doctor,
+    /// 离线回放流量剖面，估算凭据集能支撑多久、哪些请求会失败
+    Simulate {
+        /// 流量剖面文件（JSON）
+        #[arg(long)]
+        profile: String,
+        /// 以 JSON 输出报告
+        #[arg(long)]
+        json: bool,
+    },
+    /// 按时间窗口汇总用量账本：请求数、tokens 与错误率
+    Usage {
+        /// 时间窗口起点：相对时长（如 30m、24h、7d）或 RFC3339 时间
+        #[arg(long, default_value = "7d")]
+        since: String,
+        /// 汇总维度：client、credential 或 model
+        #[arg(long, default_value = "client")]
+        group_by: GroupBy,
+        /// 以 CSV 输出
+        #[arg(long)]
+        csv: bool,
+        /// 查询运行中实例的 Admin API（如 http://127.0.0.1:8990），不指定时直接读取 stateDir 中的账本
+        #[arg(long)]
+        url: Option<String>,
+        /// Admin API Key（默认取配置中的 adminApiKey）
+        #[arg(long)]
+        api_key: Option<String>,
+    },
+    /// 设备码登录（AWS Builder ID / IAM Identity Center），把得到的凭据追加到凭据文件
+    Login {
+        /// 登录入口（默认 AWS Builder ID；IAM Identity Center 填组织的 start URL）
+        #[arg(long)]
+        start_url: Option<String>,
+        /// OIDC 区域（默认 config.json 的 region）
+        #[arg(long)]
+        region: Option<String>,
+        /// 新凭据的优先级
+        #[arg(long, default_value_t = 0)]
+        priority: u32,
+        /// Profile ARN（设备授权不返回，需要时手动指定）
+        #[arg(long)]
+        profile_arn: Option<String>,
+    },
+    /// 监控配置
+    Observability {
+        #[command(subcommand)]
+        command: ObservabilityCommand,
+    },
+}
+
+/// `observability` 子命令
+#[derive(Subcommand, Debug, Clone, PartialEq, Eq)]
+pub enum ObservabilityCommand {
+    /// 根据指标定义生成 Grafana 仪表盘 JSON 与 Prometheus 告警规则
+    Export {
+        /// 输出目录
+        #[arg(long, default_value = ".")]
+        out_dir: String,
+    },
 }
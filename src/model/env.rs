@@ -0,0 +1,59 @@
+//! 容器环境变量配置
+//!
+//! 容器内可以完全不依赖磁盘文件运行：
+//! - `KIRO_CONFIG`：整份 config.json（JSON 原文或 base64）
+//! - `KIRO_CREDENTIALS` / `KIRO_CREDENTIALS_JSON`：整份 credentials.json（JSON 原文或 base64）
+//! - `KIRO_REFRESH_TOKEN_0..n`：逐个注入 refresh token，可配合同编号的 `KIRO_AUTH_METHOD_n` /
+//!   `KIRO_CLIENT_ID_n` / `KIRO_CLIENT_SECRET_n`
+//! - `KIRO_CREDENTIALS_FILE`：挂载的 secret 文件路径（只读，不回写）
+//! - `KIRO_HOST` / `KIRO_PORT` / `KIRO_REGION` / `KIRO_API_KEY` / `KIRO_ADMIN_API_KEY` /
+//!   `KIRO_PROXY_URL` / `KIRO_PROXY_USERNAME` / `KIRO_PROXY_PASSWORD` / `KIRO_STATE_DIR`：单项覆盖
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+
+/// 整份配置
+pub const CONFIG: &str = "KIRO_CONFIG";
+/// 整份凭据
+pub const CREDENTIALS: &str = "KIRO_CREDENTIALS";
+/// 整份凭据（`KIRO_CREDENTIALS` 的别名）
+pub const CREDENTIALS_JSON: &str = "KIRO_CREDENTIALS_JSON";
+/// 单个凭据的 refresh token（后接编号）
+pub const REFRESH_TOKEN_PREFIX: &str = "KIRO_REFRESH_TOKEN_";
+/// 单个凭据的认证方式（后接编号）
+pub const AUTH_METHOD_PREFIX: &str = "KIRO_AUTH_METHOD_";
+/// 单个凭据的 OIDC Client ID（后接编号）
+pub const CLIENT_ID_PREFIX: &str = "KIRO_CLIENT_ID_";
+/// 单个凭据的 OIDC Client Secret（后接编号）
+pub const CLIENT_SECRET_PREFIX: &str = "KIRO_CLIENT_SECRET_";
+/// 凭据 secret 文件路径
+pub const CREDENTIALS_FILE: &str = "KIRO_CREDENTIALS_FILE";
+
+/// 读取非空环境变量
+pub fn var(name: &str) -> Option<String> {
+    std::env::var(name).ok().filter(|v| !v.trim().is_empty())
+}
+
+/// 解析 JSON 原文或 base64 编码的 JSON
+pub fn decode_json(name: &str, value: &str) -> anyhow::Result<String> {
+    let trimmed = value.trim();
+    if trimmed.starts_with('{') || trimmed.starts_with('[') {
+        return Ok(trimmed.to_string());
+    }
+    let bytes = STANDARD
+        .decode(trimmed)
+        .map_err(|e| anyhow::anyhow!("{} 既不是 JSON 也不是合法的 base64: {}", name, e))?;
+    String::from_utf8(bytes).map_err(|e| anyhow::anyhow!("{} base64 解码后不是 UTF-8: {}", name, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_json_accepts_raw_and_base64() {
+        assert_eq!(decode_json("X", r#" {"a":1} "#).unwrap(), r#"{"a":1}"#);
+        assert_eq!(decode_json("X", "W10=").unwrap(), "[]");
+        assert!(decode_json("X", "%%%").is_err());
+    }
+}
@@ -2,3 +2,4 @@
 
 pub mod arg;
 pub mod config;
+pub mod env;
@@ -0,0 +1,306 @@
+//! `kiro observability export` 监控配置生成
+//!
+//! 根据 `common::metrics::REGISTRY` 中的指标定义生成 Grafana 仪表盘（每个指标一个面板）与
+//! Prometheus 告警规则，指标名、类型与标签都取自代码中的定义，重命名或新增指标后重新导出即可，
+//! 不会与 `/metrics` 的实际输出不一致。
+
+use std::fmt::Write;
+use std::path::Path;
+
+use serde_json::{Value, json};
+
+use crate::common::metrics::{
+    AVAILABLE_CREDENTIALS, CREDENTIALS, LABEL_OVERFLOW, Metric, MetricKind, QUEUE_DEPTH, REGISTRY,
+    REQUEST_DURATION, REQUESTS, STREAM_RESYNCS,
+};
+
+/// 仪表盘文件名
+const DASHBOARD_FILE: &str = "kiro-rs-dashboard.json";
+
+/// 告警规则文件名
+const ALERTS_FILE: &str = "kiro-rs-alerts.yml";
+
+/// 面板高度、宽度（Grafana 网格单位，一行 24）
+const PANEL_HEIGHT: u64 = 8;
+const PANEL_WIDTH: u64 = 12;
+
+/// 告警规则
+struct AlertRule {
+    name: &'static str,
+    expr: String,
+    for_duration: &'static str,
+    severity: &'static str,
+    summary: &'static str,
+}
+
+/// 导出仪表盘与告警规则到 `out_dir`，返回进程退出码
+pub fn run(out_dir: &str) -> i32 {
+    match export(Path::new(out_dir)) {
+        Ok(()) => 0,
+        Err(e) => {
+            eprintln!("导出监控配置失败: {:#}", e);
+            1
+        }
+    }
+}
+
+fn export(out_dir: &Path) -> anyhow::Result<()> {
+    use anyhow::Context;
+
+    std::fs::create_dir_all(out_dir)
+        .with_context(|| format!("创建输出目录失败: {}", out_dir.display()))?;
+    for (file, contents) in [
+        (DASHBOARD_FILE, serde_json::to_string_pretty(&dashboard())?),
+        (ALERTS_FILE, render_alert_rules(&alert_rules())),
+    ] {
+        let path = out_dir.join(file);
+        std::fs::write(&path, contents).with_context(|| format!("写入失败: {}", path.display()))?;
+        println!("已写入 {}", path.display());
+    }
+    Ok(())
+}
+
+/// 面板的 PromQL、单位与图例
+fn panel_query(metric: &Metric) -> (String, &'static str, String) {
+    let legend = metric
+        .labels
+        .iter()
+        .map(|label| format!("{{{{{}}}}}", label))
+        .collect::<Vec<_>>()
+        .join(" ");
+    match metric.kind {
+        MetricKind::Gauge => (metric.name.to_string(), "short", legend),
+        MetricKind::Counter if metric.labels.is_empty() => (
+            format!("rate({}[$__rate_interval])", metric.name),
+            "short",
+            legend,
+        ),
+        MetricKind::Counter => (
+            format!(
+                "sum by ({}) (rate({}[$__rate_interval]))",
+                metric.labels.join(", "),
+                metric.name
+            ),
+            "short",
+            legend,
+        ),
+        // 平均时长
+        MetricKind::Summary => (
+            format!(
+                "sum(rate({0}_sum[$__rate_interval])) / sum(rate({0}_count[$__rate_interval]))",
+                metric.name
+            ),
+            "s",
+            String::new(),
+        ),
+    }
+}
+
+/// Grafana 仪表盘（数据源通过 `datasource` 变量选择）
+fn dashboard() -> Value {
+    let panels: Vec<Value> = REGISTRY
+        .iter()
+        .enumerate()
+        .map(|(i, metric)| {
+            let (expr, unit, legend) = panel_query(metric);
+            let i = i as u64;
+            json!({
+                "id": i + 1,
+                "type": "timeseries",
+                "title": metric.name,
+                "description": metric.help,
+                "datasource": {"type": "prometheus", "uid": "${datasource}"},
+                "gridPos": {
+                    "h": PANEL_HEIGHT,
+                    "w": PANEL_WIDTH,
+                    "x": (i % 2) * PANEL_WIDTH,
+                    "y": (i / 2) * PANEL_HEIGHT,
+                },
+                "fieldConfig": {"defaults": {"unit": unit}, "overrides": []},
+                "targets": [{"refId": "A", "expr": expr, "legendFormat": legend}],
+            })
+        })
+        .collect();
+    json!({
+        "title": "kiro-rs",
+        "uid": "kiro-rs",
+        "tags": ["kiro-rs"],
+        "schemaVersion": 39,
+        "time": {"from": "now-6h", "to": "now"},
+        "templating": {
+            "list": [{
+                "name": "datasource",
+                "label": "数据源",
+                "type": "datasource",
+                "query": "prometheus",
+            }],
+        },
+        "panels": panels,
+    })
+}
+
+/// 告警规则（表达式中的指标名均引用指标定义）
+fn alert_rules() -> Vec<AlertRule> {
+    let requests = REQUESTS.name;
+    let duration = REQUEST_DURATION.name;
+    vec![
+        AlertRule {
+            name: "KiroNoAvailableCredentials",
+            expr: format!(
+                "{} == 0 and {} > 0",
+                AVAILABLE_CREDENTIALS.name, CREDENTIALS.name
+            ),
+            for_duration: "2m",
+            severity: "critical",
+            summary: "没有可用凭据，API 返回 503",
+        },
+        AlertRule {
+            name: "KiroCredentialsDegraded",
+            expr: format!(
+                "{} / {} < 0.5",
+                AVAILABLE_CREDENTIALS.name, CREDENTIALS.name
+            ),
+            for_duration: "15m",
+            severity: "warning",
+            summary: "超过一半的凭据不可用",
+        },
+        AlertRule {
+            name: "KiroHighErrorRate",
+            expr: format!(
+                "sum(rate({0}{{outcome=\"error\"}}[5m])) / sum(rate({0}[5m])) > 0.05",
+                requests
+            ),
+            for_duration: "10m",
+            severity: "warning",
+            summary: "超过 5% 的请求上游调用失败",
+        },
+        AlertRule {
+            name: "KiroSlowRequests",
+            expr: format!(
+                "sum(rate({0}_sum[5m])) / sum(rate({0}_count[5m])) > 60",
+                duration
+            ),
+            for_duration: "15m",
+            severity: "warning",
+            summary: "请求平均耗时超过 60 秒",
+        },
+        AlertRule {
+            name: "KiroUpstreamQueueBacklog",
+            expr: format!("{} > 20", QUEUE_DEPTH.name),
+            for_duration: "5m",
+            severity: "warning",
+            summary: "等待上游响应头的调用持续积压",
+        },
+        AlertRule {
+            name: "KiroStreamResyncs",
+            expr: format!("increase({}[15m]) > 0", STREAM_RESYNCS.name),
+            for_duration: "0m",
+            severity: "info",
+            summary: "上游事件流出现损坏的帧",
+        },
+        AlertRule {
+            name: "KiroMetricsLabelOverflow",
+            expr: format!("increase({}[1h]) > 0", LABEL_OVERFLOW.name),
+            for_duration: "0m",
+            severity: "info",
+            summary: "模型或客户端数量超出指标基数上限，部分请求归入 other",
+        },
+    ]
+}
+
+/// 以 Prometheus 规则文件（YAML）格式输出
+fn render_alert_rules(rules: &[AlertRule]) -> String {
+    let mut out = String::from("groups:\n  - name: kiro-rs\n    rules:\n");
+    for rule in rules {
+        let _ = writeln!(out, "      - alert: {}", rule.name);
+        let _ = writeln!(out, "        expr: {}", quote(&rule.expr));
+        let _ = writeln!(out, "        for: {}", rule.for_duration);
+        let _ = writeln!(
+            out,
+            "        labels:\n          severity: {}",
+            rule.severity
+        );
+        let _ = writeln!(
+            out,
+            "        annotations:\n          summary: {}",
+            quote(rule.summary)
+        );
+    }
+    out
+}
+
+/// YAML 单引号字符串
+fn quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "''"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::ledger::{UsageOutcome, UsageRecord};
+    use crate::common::metrics::{self, RequestMetrics};
+    use crate::model::config::MetricsConfig;
+
+    /// 表达式中出现的指标名（去掉 summary 的 `_sum` / `_count` 后缀）
+    fn referenced_metrics(expr: &str) -> Vec<String> {
+        expr.split(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+            .filter(|word| word.starts_with("kiro_"))
+            .map(|word| {
+                word.strip_suffix("_sum")
+                    .or_else(|| word.strip_suffix("_count"))
+                    .unwrap_or(word)
+                    .to_string()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_exports_match_registry() {
+        let registered: Vec<&str> = REGISTRY.iter().map(|m| m.name).collect();
+
+        // /metrics 输出的每个指标都在注册表中
+        let metrics = RequestMetrics::new(MetricsConfig::default());
+        metrics.observe(&UsageRecord {
+            time: chrono::Utc::now(),
+            client: "team-a".to_string(),
+            credential_id: Some(1),
+            model: Some("claude-sonnet-4-5".to_string()),
+            endpoint: Some("/v1/messages".to_string()),
+            input_tokens: 10,
+            output_tokens: 1,
+            duration_ms: 100,
+            outcome: UsageOutcome::Completed,
+        });
+        let mut out = String::new();
+        metrics.render(&mut out);
+        metrics::single(&mut out, &QUEUE_DEPTH, 0);
+        for line in out.lines().filter(|l| l.starts_with("# TYPE ")) {
+            let name = line.split(' ').nth(2).unwrap();
+            assert!(registered.contains(&name), "{} 未注册", name);
+        }
+
+        let dashboard = dashboard();
+        let panels = dashboard["panels"].as_array().unwrap();
+        assert_eq!(panels.len(), REGISTRY.len());
+        for panel in panels {
+            let expr = panel["targets"][0]["expr"].as_str().unwrap();
+            for name in referenced_metrics(expr) {
+                assert!(registered.contains(&name.as_str()), "{}", expr);
+            }
+        }
+
+        let rules = alert_rules();
+        for rule in &rules {
+            let names = referenced_metrics(&rule.expr);
+            assert!(!names.is_empty());
+            for name in names {
+                assert!(registered.contains(&name.as_str()), "{}", rule.expr);
+            }
+        }
+        let yaml = render_alert_rules(&rules);
+        assert!(yaml.contains(
+            "        expr: 'sum(rate(kiro_requests_total{outcome=\"error\"}[5m])) / sum(rate(kiro_requests_total[5m])) > 0.05'"
+        ));
+        assert_eq!(yaml.matches("- alert: ").count(), rules.len());
+    }
+}
@@ -0,0 +1,1097 @@
+//! OpenAPI 文档
+//!
+//! `GET /openapi.json` 返回描述兼容端点与 Admin API 的 OpenAPI 3.1 文档（无需 API Key），可用于生成
+//! 客户端，Admin 面板也据此展示接口说明。文档在启动时生成一次：未启用 Admin API 时不包含 `/api/admin`
+//! 路径。兼容端点的请求体沿用 Anthropic / OpenAI 的格式，嵌套结构只描述到第一层。
+//!
+//! 文档是手写的（不依赖 utoipa 等派生库），由测试保证与实现同步：路由与路径一一对应，
+//! 请求体 schema 的字段与对应 DTO 接受的字段一致，示例请求体经 schema 校验后能被 DTO 反序列化。
+
+use std::sync::Arc;
+
+use axum::{Json, Router, routing::get};
+use serde_json::{Map, Value, json};
+
+/// 接口的认证方式
+#[derive(Clone, Copy)]
+enum Auth {
+    /// 无需认证
+    None,
+    /// API Key（或会话 Token）
+    ApiKey,
+    /// Admin API Key
+    Admin,
+}
+
+/// 一个接口
+struct Operation {
+    method: &'static str,
+    path: &'static str,
+    tag: &'static str,
+    summary: &'static str,
+    auth: Auth,
+    /// 请求体 schema（`components.schemas` 中的名称）
+    request: Option<&'static str>,
+    /// 流式响应（SSE）
+    stream: bool,
+}
+
+const fn op(
+    method: &'static str,
+    path: &'static str,
+    tag: &'static str,
+    summary: &'static str,
+    auth: Auth,
+) -> Operation {
+    Operation {
+        method,
+        path,
+        tag,
+        summary,
+        auth,
+        request: None,
+        stream: false,
+    }
+}
+
+impl Operation {
+    const fn body(mut self, schema: &'static str) -> Self {
+        self.request = Some(schema);
+        self
+    }
+
+    const fn streaming(mut self) -> Self {
+        self.stream = true;
+        self
+    }
+}
+
+/// 兼容端点与运维端点
+const API_OPERATIONS: &[Operation] = &[
+    op(
+        "get",
+        "/v1/models",
+        "models",
+        "获取可用模型列表",
+        Auth::ApiKey,
+    ),
+    op(
+        "post",
+        "/v1/messages",
+        "messages",
+        "创建消息（对话）",
+        Auth::ApiKey,
+    )
+    .body("MessagesRequest")
+    .streaming(),
+    op(
+        "post",
+        "/v1/messages/count_tokens",
+        "messages",
+        "估算 Token 数量",
+        Auth::ApiKey,
+    )
+    .body("CountTokensRequest"),
+    op(
+        "post",
+        "/v1/responses",
+        "openai",
+        "OpenAI Responses API 兼容端点",
+        Auth::ApiKey,
+    )
+    .body("ResponsesRequest")
+    .streaming(),
+    op(
+        "post",
+        "/v1/dry-run",
+        "messages",
+        "预演 Messages 请求，返回将发送给上游的请求体与将使用的凭据，不调用上游",
+        Auth::ApiKey,
+    )
+    .body("MessagesRequest"),
+    op(
+        "post",
+        "/v1/session-tokens",
+        "auth",
+        "用 API Key 换取短期会话 Token",
+        Auth::ApiKey,
+    )
+    .body("SessionTokenRequest"),
+    op(
+        "get",
+        "/v1/artifacts/{id}",
+        "artifacts",
+        "下载上游文件（签名链接）",
+        Auth::None,
+    ),
+    op(
+        "post",
+        "/cc/v1/messages",
+        "messages",
+        "创建消息（Claude Code 兼容：流式响应等待上游完成后返回准确的 input_tokens）",
+        Auth::ApiKey,
+    )
+    .body("MessagesRequest")
+    .streaming(),
+    op(
+        "post",
+        "/cc/v1/messages/count_tokens",
+        "messages",
+        "估算 Token 数量",
+        Auth::ApiKey,
+    )
+    .body("CountTokensRequest"),
+    op(
+        "post",
+        "/openai/deployments/{deployment}/chat/completions",
+        "openai",
+        "Azure OpenAI 风格的 Chat Completions",
+        Auth::ApiKey,
+    )
+    .body("ChatCompletionsRequest")
+    .streaming(),
+    op("get", "/health", "ops", "健康检查", Auth::None),
+    op("get", "/metrics", "ops", "Prometheus 指标", Auth::None),
+    op("get", "/metrics/load", "ops", "负载指标", Auth::None),
+    op("get", "/openapi.json", "ops", "OpenAPI 文档", Auth::None),
+];
+
+/// Admin API（挂载在 `/api/admin` 下）
+const ADMIN_OPERATIONS: &[Operation] = &[
+    op(
+        "get",
+        "/credentials",
+        "credentials",
+        "获取所有凭据状态",
+        Auth::Admin,
+    ),
+    op(
+        "post",
+        "/credentials",
+        "credentials",
+        "添加新凭据",
+        Auth::Admin,
+    )
+    .body("AddCredentialRequest"),
+    op(
+        "delete",
+        "/credentials/{id}",
+        "credentials",
+        "删除凭据",
+        Auth::Admin,
+    ),
+    op(
+        "post",
+        "/credentials/{id}/disabled",
+        "credentials",
+        "设置凭据禁用状态",
+        Auth::Admin,
+    )
+    .body("SetDisabledRequest"),
+    op(
+        "post",
+        "/credentials/{id}/enable",
+        "credentials",
+        "启用凭据",
+        Auth::Admin,
+    ),
+    op(
+        "post",
+        "/credentials/{id}/disable",
+        "credentials",
+        "禁用凭据",
+        Auth::Admin,
+    ),
+    op(
+        "post",
+        "/credentials/{id}/refresh",
+        "credentials",
+        "强制刷新 Token",
+        Auth::Admin,
+    ),
+    op(
+        "post",
+        "/credentials/{id}/priority",
+        "credentials",
+        "设置凭据优先级",
+        Auth::Admin,
+    )
+    .body("SetPriorityRequest"),
+    op(
+        "post",
+        "/credentials/{id}/reset",
+        "credentials",
+        "重置失败计数并重新启用",
+        Auth::Admin,
+    ),
+    op(
+        "get",
+        "/credentials/{id}/balance",
+        "credentials",
+        "获取凭据余额",
+        Auth::Admin,
+    ),
+    op(
+        "post",
+        "/credentials/{id}/reauth",
+        "credentials",
+        "发起设备码重新登录",
+        Auth::Admin,
+    )
+    .body("StartReauthRequest"),
+    op(
+        "get",
+        "/credentials/{id}/reauth",
+        "credentials",
+        "查询重新登录状态",
+        Auth::Admin,
+    ),
+    op(
+        "post",
+        "/login",
+        "credentials",
+        "发起设备码登录以添加新凭据",
+        Auth::Admin,
+    )
+    .body("StartLoginRequest"),
+    op(
+        "get",
+        "/login/{session}",
+        "credentials",
+        "查询登录状态",
+        Auth::Admin,
+    ),
+    op(
+        "get",
+        "/abuse-flags",
+        "clients",
+        "获取滥用检测标记",
+        Auth::Admin,
+    ),
+    op(
+        "delete",
+        "/abuse-flags/{client}",
+        "clients",
+        "清除客户端的滥用检测标记",
+        Auth::Admin,
+    ),
+    op(
+        "get",
+        "/client-keys",
+        "clients",
+        "列出客户端密钥",
+        Auth::Admin,
+    ),
+    op(
+        "post",
+        "/client-keys",
+        "clients",
+        "开通客户端密钥",
+        Auth::Admin,
+    )
+    .body("ProvisionClientKeyRequest"),
+    op(
+        "delete",
+        "/client-keys/{name}",
+        "clients",
+        "吊销开通的客户端密钥",
+        Auth::Admin,
+    ),
+    op("get", "/usage", "usage", "获取客户端用量", Auth::Admin),
+    op(
+        "get",
+        "/usage/summary",
+        "usage",
+        "按时间窗口汇总用量账本",
+        Auth::Admin,
+    ),
+    op(
+        "get",
+        "/connections",
+        "usage",
+        "获取进行中连接的写出统计与慢客户端",
+        Auth::Admin,
+    ),
+    op("get", "/requests", "ops", "获取进行中的请求", Auth::Admin),
+    op(
+        "delete",
+        "/requests/{id}",
+        "ops",
+        "中止进行中的请求",
+        Auth::Admin,
+    ),
+    op("get", "/audit", "ops", "查询审计日志", Auth::Admin),
+    op("get", "/shadow", "ops", "获取影子流量统计", Auth::Admin),
+    op(
+        "get",
+        "/rate-limits",
+        "ops",
+        "获取从上游 429 学到的按凭据限流",
+        Auth::Admin,
+    ),
+    op(
+        "get",
+        "/slo",
+        "ops",
+        "获取成功率与延迟 SLO 的错误预算消耗速率",
+        Auth::Admin,
+    ),
+    op(
+        "get",
+        "/raw-capture",
+        "ops",
+        "获取原始帧抓取状态",
+        Auth::Admin,
+    ),
+    op(
+        "post",
+        "/raw-capture",
+        "ops",
+        "预约抓取之后若干个请求的上游原始字节",
+        Auth::Admin,
+    )
+    .body("ArmRawCaptureRequest"),
+    op(
+        "get",
+        "/maintenance",
+        "ops",
+        "获取维护模式状态",
+        Auth::Admin,
+    ),
+    op(
+        "post",
+        "/maintenance",
+        "ops",
+        "进入/退出维护模式",
+        Auth::Admin,
+    )
+    .body("SetMaintenanceRequest"),
+    op("get", "/events", "ops", "以 SSE 推送凭据事件", Auth::Admin).streaming(),
+];
+
+/// Admin API 的路径前缀
+const ADMIN_PREFIX: &str = "/api/admin";
+
+/// 生成 OpenAPI 文档（`admin` 为 false 时不包含 Admin API）
+pub fn document(admin: bool) -> Value {
+    let mut paths = Map::new();
+    for operation in API_OPERATIONS {
+        add_operation(&mut paths, operation.path.to_string(), operation);
+    }
+    if admin {
+        for operation in ADMIN_OPERATIONS {
+            add_operation(
+                &mut paths,
+                format!("{}{}", ADMIN_PREFIX, operation.path),
+                operation,
+            );
+        }
+    }
+
+    json!({
+        "openapi": "3.1.0",
+        "info": {
+            "title": "kiro-rs",
+            "version": env!("CARGO_PKG_VERSION"),
+            "description": "Anthropic / OpenAI 兼容的 Kiro API 代理",
+        },
+        "paths": paths,
+        "components": {
+            "securitySchemes": {
+                "apiKey": {"type": "apiKey", "in": "header", "name": "x-api-key"},
+                "bearer": {"type": "http", "scheme": "bearer"},
+                "azureApiKey": {"type": "apiKey", "in": "header", "name": "api-key"},
+            },
+            "schemas": schemas(),
+        },
+    })
+}
+
+fn add_operation(paths: &mut Map<String, Value>, path: String, operation: &Operation) {
+    let mut value = json!({
+        "operationId": operation_id(operation.method, operation.path),
+        "summary": operation.summary,
+        "tags": [operation.tag],
+        "responses": responses(operation),
+    });
+    let parameters: Vec<Value> = path_parameters(operation.path)
+        .map(|name| json!({"name": name, "in": "path", "required": true, "schema": {"type": "string"}}))
+        .collect();
+    if !parameters.is_empty() {
+        value["parameters"] = Value::Array(parameters);
+    }
+    if let Some(schema) = operation.request {
+        value["requestBody"] = json!({
+            "required": true,
+            "content": {"application/json": {"schema": schema_ref(schema)}},
+        });
+    }
+    value["security"] = match operation.auth {
+        Auth::None => json!([]),
+        // 会话 Token 与管理密钥同样可以放在 Authorization: Bearer 中
+        Auth::ApiKey => json!([{"apiKey": []}, {"bearer": []}, {"azureApiKey": []}]),
+        Auth::Admin => json!([{"apiKey": []}, {"bearer": []}]),
+    };
+
+    let item = paths
+        .entry(path)
+        .or_insert_with(|| Value::Object(Map::new()));
+    item[operation.method] = value;
+}
+
+fn responses(operation: &Operation) -> Value {
+    let mut content = json!({"application/json": {"schema": {"type": "object"}}});
+    if operation.stream {
+        content["text/event-stream"] = json!({"schema": {"type": "string"}});
+    }
+    let mut responses = json!({
+        "200": {"description": "成功", "content": content},
+    });
+    if !matches!(operation.auth, Auth::None) {
+        responses["401"] = error_response("认证失败");
+    }
+    if operation.request.is_some() {
+        responses["400"] = error_response("请求无效");
+    }
+    responses
+}
+
+fn error_response(description: &str) -> Value {
+    json!({
+        "description": description,
+        "content": {"application/json": {"schema": schema_ref("Error")}},
+    })
+}
+
+fn schema_ref(name: &str) -> Value {
+    json!({"$ref": format!("#/components/schemas/{}", name)})
+}
+
+/// 路径中的 `{参数}`
+fn path_parameters(path: &str) -> impl Iterator<Item = &str> {
+    path.split('/')
+        .filter_map(|segment| segment.strip_prefix('{')?.strip_suffix('}'))
+}
+
+/// 如 `post /credentials/{id}/priority` → `postCredentialsIdPriority`
+fn operation_id(method: &str, path: &str) -> String {
+    let mut id = method.to_string();
+    let mut upper = true;
+    for c in path.chars() {
+        if c.is_ascii_alphanumeric() {
+            if upper {
+                id.extend(c.to_uppercase());
+            } else {
+                id.push(c);
+            }
+            upper = false;
+        } else {
+            upper = true;
+        }
+    }
+    id
+}
+
+fn schemas() -> Value {
+    let string = json!({"type": "string"});
+    let integer = json!({"type": "integer"});
+    let boolean = json!({"type": "boolean"});
+    let message = json!({
+        "type": "object",
+        "required": ["role", "content"],
+        "properties": {
+            "role": {"type": "string", "enum": ["user", "assistant"]},
+            "content": {"oneOf": [{"type": "string"}, {"type": "array", "items": {"type": "object"}}]},
+        },
+    });
+    json!({
+        "Error": {
+            "type": "object",
+            "properties": {
+                "error": {
+                    "type": "object",
+                    "properties": {"type": string, "message": string},
+                },
+            },
+        },
+        "MessagesRequest": {
+            "type": "object",
+            "required": ["model", "messages"],
+            "properties": {
+                "model": string,
+                "max_tokens": integer,
+                "messages": {"type": "array", "items": message},
+                "system": {"oneOf": [{"type": "string"}, {"type": "array", "items": {"type": "object"}}]},
+                "stream": boolean,
+                "tools": {"type": "array", "items": {"type": "object"}},
+                "tool_choice": {"type": "object"},
+                "thinking": {"type": "object"},
+                "temperature": {"type": "number"},
+                "stop_sequences": {"type": "array", "items": string},
+                "metadata": {"type": "object"},
+            },
+        },
+        "CountTokensRequest": {
+            "type": "object",
+            "required": ["model", "messages"],
+            "properties": {
+                "model": string,
+                "messages": {"type": "array", "items": message},
+                "system": {"oneOf": [{"type": "string"}, {"type": "array", "items": {"type": "object"}}]},
+                "tools": {"type": "array", "items": {"type": "object"}},
+            },
+        },
+        "ResponsesRequest": {
+            "type": "object",
+            "required": ["model", "input"],
+            "properties": {
+                "model": string,
+                "input": {"oneOf": [{"type": "string"}, {"type": "array", "items": {"type": "object"}}]},
+                "instructions": string,
+                "max_output_tokens": integer,
+                "stream": boolean,
+                "tools": {"type": "array", "items": {"type": "object"}},
+                "reasoning": {"type": "object", "properties": {"effort": string}},
+                "temperature": {"type": "number"},
+                "previous_response_id": string,
+            },
+        },
+        "ChatCompletionsRequest": {
+            "type": "object",
+            "required": ["messages"],
+            "properties": {
+                "model": string,
+                "messages": {"type": "array", "items": {"type": "object"}},
+                "max_tokens": integer,
+                "max_completion_tokens": integer,
+                "stream": boolean,
+                "tools": {"type": "array", "items": {"type": "object"}},
+                "temperature": {"type": "number"},
+                "reasoning_effort": string,
+            },
+        },
+        "SessionTokenRequest": {
+            "type": "object",
+            "properties": {
+                "ttlSecs": integer,
+                "models": {"type": "array", "items": string},
+                "maxTokens": integer,
+            },
+        },
+        "AddCredentialRequest": {
+            "type": "object",
+            "required": ["refreshToken"],
+            "properties": {
+                "refreshToken": string,
+                "authMethod": {"type": "string", "enum": ["social", "idc"]},
+                "clientId": string,
+                "clientSecret": string,
+                "priority": integer,
+                "weight": integer,
+                "reserve": boolean,
+                "region": string,
+                "machineId": string,
+                "apiRegion": string,
+                "apiEndpoint": string,
+            },
+        },
+        "SetDisabledRequest": {
+            "type": "object",
+            "required": ["disabled"],
+            "properties": {"disabled": boolean},
+        },
+        "SetPriorityRequest": {
+            "type": "object",
+            "required": ["priority"],
+            "properties": {"priority": integer},
+        },
+        "StartReauthRequest": {
+            "type": "object",
+            "properties": {"startUrl": string, "region": string},
+        },
+        "StartLoginRequest": {
+            "type": "object",
+            "properties": {
+                "startUrl": string,
+                "region": string,
+                "priority": integer,
+                "profileArn": string,
+            },
+        },
+        "ProvisionClientKeyRequest": {
+            "type": "object",
+            "required": ["name"],
+            "properties": {
+                "name": string,
+                "scopes": {
+                    "type": "object",
+                    "properties": {
+                        "models": {"type": "array", "items": string},
+                        "maxTokens": integer,
+                        "requestsPerMinute": integer,
+                        "tokenBudget": integer,
+                    },
+                },
+                "defaults": {"type": "object"},
+                "priority": {"type": "string", "enum": ["interactive", "batch"]},
+                "maxDurationSecs": integer,
+                "streamMaxDurationSecs": integer,
+                "preset": string,
+                "usageRetentionDays": integer,
+                "localTools": {"type": "array", "items": {"type": "string", "enum": ["fs-read", "shell", "http-fetch"]}},
+                "streamPolicy": {"type": "string", "enum": ["passthrough", "force", "forbid"]},
+                "notBefore": {"type": "string", "format": "date-time"},
+                "expiresAt": {"type": "string", "format": "date-time"},
+                "accessWindows": {"type": "array", "items": {"type": "object"}},
+                "responseCache": {"type": "string", "enum": ["off", "exact", "semantic"]},
+                "logContent": {"oneOf": [{"type": "integer"}, {"const": "full"}]},
+                "sessionTokens": boolean,
+            },
+        },
+        "ArmRawCaptureRequest": {
+            "type": "object",
+            "required": ["requests"],
+            "properties": {"requests": integer},
+        },
+        "SetMaintenanceRequest": {
+            "type": "object",
+            "required": ["enabled"],
+            "properties": {
+                "enabled": boolean,
+                "message": string,
+                "retryAfterSecs": integer,
+            },
+        },
+    })
+}
+
+/// 创建 `/openapi.json` 路由
+pub fn create_openapi_router(admin: bool) -> Router {
+    let document = Arc::new(document(admin));
+    Router::new().route(
+        "/openapi.json",
+        get(move || {
+            let document = document.clone();
+            async move { Json(document.as_ref().clone()) }
+        }),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_document_paths() {
+        let doc = document(true);
+        assert_eq!(doc["openapi"], "3.1.0");
+        assert_eq!(
+            doc["paths"]["/v1/messages"]["post"]["requestBody"]["content"]["application/json"]["schema"]
+                ["$ref"],
+            "#/components/schemas/MessagesRequest"
+        );
+        let priority = &doc["paths"]["/api/admin/credentials/{id}/priority"]["post"];
+        assert_eq!(priority["operationId"], "postCredentialsIdPriority");
+        assert_eq!(priority["parameters"][0]["name"], "id");
+        assert_eq!(
+            doc["paths"]["/api/admin/credentials/{id}/refresh"]["post"]["operationId"],
+            "postCredentialsIdRefresh"
+        );
+        // 同一路径的多个方法合并在一起
+        let credentials = &doc["paths"]["/api/admin/credentials"];
+        assert!(credentials["get"].is_object() && credentials["post"].is_object());
+        assert_eq!(doc["paths"]["/health"]["get"]["security"], json!([]));
+
+        // 引用的 schema 都已定义，operationId 不重复
+        let mut ids = std::collections::HashSet::new();
+        for operation in API_OPERATIONS.iter().chain(ADMIN_OPERATIONS) {
+            if let Some(schema) = operation.request {
+                assert!(
+                    doc["components"]["schemas"][schema].is_object(),
+                    "{}",
+                    schema
+                );
+            }
+            assert!(ids.insert(operation_id(operation.method, operation.path)));
+        }
+
+        let doc = document(false);
+        let paths = doc["paths"].as_object().unwrap();
+        assert!(!paths.keys().any(|p| p.starts_with(ADMIN_PREFIX)));
+    }
+
+    /// 从路由源码中取出第一个字符串字面量
+    fn first_literal(text: &str) -> Option<&str> {
+        let start = text.find('"')? + 1;
+        let len = text[start..].find('"')?;
+        Some(&text[start..start + len])
+    }
+
+    /// 从路由源码中解析出 (方法, 完整路径)：按 `let x = Router::new()` 分组，组的前缀取自
+    /// `.nest("/prefix", x` 或 `.nest("/prefix", y.merge(x))`
+    fn parse_routes(source: &str, base: &str) -> Vec<(String, String)> {
+        // 只看实现部分
+        let source = source.split("#[cfg(test)]").next().unwrap();
+        let groups: Vec<&str> = source.split("Router::new()").collect();
+        let mut routes = Vec::new();
+        for (i, group) in groups.iter().enumerate().skip(1) {
+            let before = groups[i - 1].trim_end();
+            let prefix = match before
+                .strip_suffix('=')
+                .and_then(|s| s.trim_end().rsplit_once("let "))
+            {
+                Some((_, var)) => {
+                    let var = var.trim();
+                    source
+                        .match_indices(".nest(")
+                        .map(|(at, _)| &source[at..at + source[at..].find('\n').unwrap()])
+                        .find(|line| {
+                            line.split(|c: char| !c.is_alphanumeric() && c != '_')
+                                .any(|word| word == var)
+                        })
+                        .and_then(first_literal)
+                        .unwrap_or_else(|| panic!("未找到 {} 的挂载路径", var))
+                }
+                None => "",
+            };
+            for route in group.split(".route(").skip(1) {
+                let path = first_literal(route).unwrap();
+                // 处理函数写在路径之后、下一个 `.layer(` / `.route(` 等调用之前
+                let end = [".layer(", ".nest(", ".with_state(", ".route(", ";"]
+                    .iter()
+                    .filter_map(|stop| route.find(stop))
+                    .min()
+                    .unwrap_or(route.len());
+                let handlers = &route[route.find(path).unwrap() + path.len()..end];
+                for method in ["get", "post", "put", "patch", "delete"] {
+                    let calls = handlers
+                        .match_indices(&format!("{}(", method))
+                        .filter(|(at, _)| {
+                            !handlers[..*at].ends_with(|c: char| c.is_alphanumeric() || c == '_')
+                        })
+                        .count();
+                    for _ in 0..calls {
+                        routes.push((method.to_string(), format!("{}{}{}", base, prefix, path)));
+                    }
+                }
+            }
+        }
+        routes
+    }
+
+    #[test]
+    fn test_document_covers_router() {
+        let mut routes = parse_routes(include_str!("anthropic/router.rs"), "");
+        routes.extend(parse_routes(include_str!("admin/router.rs"), ADMIN_PREFIX));
+        routes.extend(parse_routes(include_str!("openapi.rs"), ""));
+        // 解析本身没有漏掉分组
+        for expected in [
+            ("post", "/v1/messages"),
+            ("get", "/v1/artifacts/{id}"),
+            ("post", "/cc/v1/messages"),
+            ("post", "/openai/deployments/{deployment}/chat/completions"),
+            ("get", "/health"),
+            ("post", "/api/admin/credentials"),
+            ("get", "/openapi.json"),
+        ] {
+            assert!(
+                routes.contains(&(expected.0.to_string(), expected.1.to_string())),
+                "{:?}",
+                expected
+            );
+        }
+
+        // 每个路由都出现在文档中，文档中也没有不存在的路由
+        let doc = document(true);
+        for (method, path) in &routes {
+            assert!(
+                doc["paths"][path][method].is_object(),
+                "文档缺少 {} {}",
+                method.to_uppercase(),
+                path
+            );
+        }
+        let documented = doc["paths"]
+            .as_object()
+            .unwrap()
+            .iter()
+            .flat_map(|(path, item)| {
+                item.as_object()
+                    .unwrap()
+                    .keys()
+                    .map(move |method| (method.clone(), path.clone()))
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(documented.len(), routes.len());
+    }
+
+    /// 按文档中的 schema 校验 JSON（只支持文档用到的关键字；声明了 properties 的对象不允许其他字段）
+    fn validate(schema: &Value, value: &Value, at: &str) -> Result<(), String> {
+        let schemas = schemas();
+        let schema = match schema["$ref"].as_str() {
+            Some(reference) => {
+                &schemas[reference.trim_start_matches("#/components/schemas/")].clone()
+            }
+            None => schema,
+        };
+        if let Some(options) = schema["oneOf"].as_array() {
+            let matched = options
+                .iter()
+                .filter(|option| validate(option, value, at).is_ok())
+                .count();
+            return if matched == 1 {
+                Ok(())
+            } else {
+                Err(format!("{}: 匹配了 {} 个 oneOf 分支", at, matched))
+            };
+        }
+        if let Some(expected) = schema.get("const") {
+            return (expected == value)
+                .then_some(())
+                .ok_or_else(|| format!("{}: 应为 {}", at, expected));
+        }
+        if let Some(options) = schema["enum"].as_array()
+            && !options.contains(value)
+        {
+            return Err(format!("{}: {} 不在 enum 中", at, value));
+        }
+        let type_ok = match schema["type"].as_str() {
+            Some("string") => value.is_string(),
+            Some("integer") => value.is_i64() || value.is_u64(),
+            Some("number") => value.is_number(),
+            Some("boolean") => value.is_boolean(),
+            Some("array") => value.is_array(),
+            Some("object") => value.is_object(),
+            _ => true,
+        };
+        if !type_ok {
+            return Err(format!("{}: {} 不是 {}", at, value, schema["type"]));
+        }
+        if schema["format"] == "date-time"
+            && chrono::DateTime::parse_from_rfc3339(value.as_str().unwrap()).is_err()
+        {
+            return Err(format!("{}: 不是 date-time", at));
+        }
+        if let Some(items) = value.as_array() {
+            for (i, item) in items.iter().enumerate() {
+                validate(&schema["items"], item, &format!("{}[{}]", at, i))?;
+            }
+        }
+        if let (Some(object), Some(properties)) =
+            (value.as_object(), schema["properties"].as_object())
+        {
+            for required in schema["required"].as_array().into_iter().flatten() {
+                if !object.contains_key(required.as_str().unwrap()) {
+                    return Err(format!("{}: 缺少必填字段 {}", at, required));
+                }
+            }
+            for (key, field) in object {
+                let field_schema = properties
+                    .get(key)
+                    .ok_or_else(|| format!("{}: 文档中没有字段 {}", at, key))?;
+                validate(field_schema, field, &format!("{}.{}", at, key))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// 只记录结构体字段名的 Deserializer，用于取出 DTO 反序列化时接受的字段（已按 serde 重命名）
+    struct FieldNames<'a>(&'a mut &'static [&'static str]);
+
+    impl<'de> serde::Deserializer<'de> for FieldNames<'_> {
+        type Error = serde::de::value::Error;
+
+        fn deserialize_any<V: serde::de::Visitor<'de>>(
+            self,
+            _: V,
+        ) -> Result<V::Value, Self::Error> {
+            Err(serde::de::Error::custom("不是结构体"))
+        }
+
+        fn deserialize_struct<V: serde::de::Visitor<'de>>(
+            self,
+            _name: &'static str,
+            fields: &'static [&'static str],
+            _visitor: V,
+        ) -> Result<V::Value, Self::Error> {
+            *self.0 = fields;
+            Err(serde::de::Error::custom("只记录字段名"))
+        }
+
+        serde::forward_to_deserialize_any! {
+            bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string bytes byte_buf
+            option unit unit_struct newtype_struct seq tuple tuple_struct map enum identifier
+            ignored_any
+        }
+    }
+
+    fn field_names<T: serde::de::DeserializeOwned>() -> Vec<&'static str> {
+        let mut fields: &'static [&'static str] = &[];
+        let _ = T::deserialize(FieldNames(&mut fields));
+        let mut fields = fields.to_vec();
+        fields.sort_unstable();
+        fields
+    }
+
+    /// 示例请求体：符合文档 schema，能被对应的 DTO 反序列化，文档字段与 DTO 字段一致
+    fn check_dto<T: serde::de::DeserializeOwned>(schema: &str, example: Value) -> T {
+        let reference = schema_ref(schema);
+        validate(&reference, &example, schema).unwrap_or_else(|e| panic!("{}", e));
+
+        let schemas = schemas();
+        let mut documented: Vec<&str> = schemas[schema]["properties"]
+            .as_object()
+            .unwrap()
+            .keys()
+            .map(|key| key.as_str())
+            .collect();
+        documented.sort_unstable();
+        assert_eq!(
+            documented,
+            field_names::<T>(),
+            "{} 的文档字段与 DTO 不一致",
+            schema
+        );
+
+        serde_json::from_value(example).unwrap_or_else(|e| panic!("{}: {}", schema, e))
+    }
+
+    #[test]
+    fn test_request_schemas_match_dtos() {
+        use crate::admin::types::*;
+        use crate::anthropic::chat_completions::ChatCompletionRequest;
+        use crate::anthropic::responses::ResponsesRequest;
+        use crate::anthropic::types::{CountTokensRequest, MessagesRequest, SessionTokenRequest};
+
+        let messages = json!([
+            {"role": "user", "content": "你好"},
+            {"role": "assistant", "content": [{"type": "text", "text": "你好！"}]},
+        ]);
+        // Serialize 的 DTO 再序列化一次，结果仍符合文档
+        let request: MessagesRequest = check_dto(
+            "MessagesRequest",
+            json!({
+                "model": "claude-sonnet-4-5",
+                "max_tokens": 1024,
+                "messages": messages,
+                "system": "简洁回答",
+                "stream": true,
+                "tools": [{"name": "read", "description": "读文件", "input_schema": {"type": "object"}}],
+                "tool_choice": {"type": "auto"},
+                "thinking": {"type": "enabled", "budget_tokens": 2048},
+                "temperature": 0.5,
+                "stop_sequences": ["END"],
+                "metadata": {"user_id": "user_1"},
+            }),
+        );
+        let round_trip = serde_json::to_value(&request).unwrap();
+        validate(
+            &schema_ref("MessagesRequest"),
+            &round_trip,
+            "MessagesRequest",
+        )
+        .unwrap();
+        assert_eq!(round_trip["thinking"]["budget_tokens"], 2048);
+
+        let request: CountTokensRequest = check_dto(
+            "CountTokensRequest",
+            json!({"model": "claude-sonnet-4-5", "messages": messages, "system": [{"type": "text", "text": "简洁回答"}]}),
+        );
+        validate(
+            &schema_ref("CountTokensRequest"),
+            &serde_json::to_value(&request).unwrap(),
+            "CountTokensRequest",
+        )
+        .unwrap();
+
+        let request: ResponsesRequest = check_dto(
+            "ResponsesRequest",
+            json!({
+                "model": "claude-sonnet-4-5",
+                "input": "你好",
+                "instructions": "简洁回答",
+                "max_output_tokens": 512,
+                "reasoning": {"effort": "low"},
+                "temperature": 0.2,
+                "previous_response_id": "resp_1",
+            }),
+        );
+        assert_eq!(request.max_output_tokens, Some(512));
+
+        let request: ChatCompletionRequest = check_dto(
+            "ChatCompletionsRequest",
+            json!({"messages": [{"role": "user", "content": "你好"}], "max_completion_tokens": 256, "reasoning_effort": "high"}),
+        );
+        assert_eq!(request.max_completion_tokens, Some(256));
+
+        let request: SessionTokenRequest = check_dto(
+            "SessionTokenRequest",
+            json!({"ttlSecs": 600, "models": ["claude-haiku-*"], "maxTokens": 1024}),
+        );
+        assert_eq!(request.ttl_secs, Some(600));
+
+        let request: AddCredentialRequest = check_dto(
+            "AddCredentialRequest",
+            json!({"refreshToken": "r", "authMethod": "idc", "clientId": "c", "clientSecret": "s", "reserve": true, "apiRegion": "eu-central-1"}),
+        );
+        assert!(request.reserve);
+        assert_eq!(request.api_region.as_deref(), Some("eu-central-1"));
+
+        let request: SetDisabledRequest =
+            check_dto("SetDisabledRequest", json!({"disabled": true}));
+        assert!(request.disabled);
+        let request: SetPriorityRequest = check_dto("SetPriorityRequest", json!({"priority": 3}));
+        assert_eq!(request.priority, 3);
+        let request: StartReauthRequest =
+            check_dto("StartReauthRequest", json!({"region": "us-east-1"}));
+        assert_eq!(request.region.as_deref(), Some("us-east-1"));
+        let request: StartLoginRequest = check_dto(
+            "StartLoginRequest",
+            json!({"startUrl": "https://example.awsapps.com/start", "priority": 2, "profileArn": "arn"}),
+        );
+        assert_eq!(request.priority, 2);
+        let request: ArmRawCaptureRequest =
+            check_dto("ArmRawCaptureRequest", json!({"requests": 5}));
+        assert_eq!(request.requests, 5);
+        let request: SetMaintenanceRequest = check_dto(
+            "SetMaintenanceRequest",
+            json!({"enabled": true, "message": "升级中", "retryAfterSecs": 60}),
+        );
+        assert_eq!(request.retry_after_secs, Some(60));
+
+        let request: ProvisionClientKeyRequest = check_dto(
+            "ProvisionClientKeyRequest",
+            json!({
+                "name": "web",
+                "scopes": {"models": ["claude-*"], "maxTokens": 4096, "requestsPerMinute": 60},
+                "priority": "batch",
+                "localTools": ["fs-read"],
+                "streamPolicy": "force",
+                "expiresAt": "2026-12-31T00:00:00Z",
+                "responseCache": "exact",
+                "logContent": "full",
+                "preset": "fast",
+                "sessionTokens": true,
+            }),
+        );
+        assert_eq!(request.scopes.max_tokens, Some(4096));
+        assert!(request.session_tokens);
+
+        // 不符合文档的请求体会被校验拒绝
+        for (schema, example) in [
+            ("SetPriorityRequest", json!({"priority": "high"})),
+            ("SetDisabledRequest", json!({})),
+            (
+                "ProvisionClientKeyRequest",
+                json!({"name": "web", "priority": "urgent"}),
+            ),
+            ("SessionTokenRequest", json!({"ttl_secs": 600})),
+        ] {
+            assert!(
+                validate(&schema_ref(schema), &example, schema).is_err(),
+                "{}",
+                schema
+            );
+        }
+    }
+}
@@ -0,0 +1,453 @@
+//! `kiro simulate` 容量规划模拟
+//!
+//! 完全离线地把录制的流量剖面回放到凭据选择、故障转移和额度逻辑上：使用真实的 `MultiTokenManager`，
+//! Token 视为始终有效，不发起任何网络请求，也不回写凭据文件。报告给定凭据集能支撑到剖面中的哪个时间点、
+//! 各凭据何时被禁用，以及哪些请求会失败。
+//!
+//! # 流量剖面
+//!
+//! ```json
+//! {
+//!   "defaultQuota": 500,
+//!   "quotas": {"1": 200},
+//!   "requests": [
+//!     {"offsetSecs": 0},
+//!     {"offsetSecs": 2.5, "statuses": [429, 200]},
+//!     {"offsetSecs": 3, "repeat": 100}
+//!   ]
+//! }
+//! ```
+//!
+//! - `defaultQuota` / `quotas`：每个凭据（按凭据 ID）剩余可用的请求数，未配置时不限；用尽后该凭据返回
+//!   402 `MONTHLY_REQUEST_COUNT`
+//! - `requests`：按时间排序的请求。`statuses` 为各次上游尝试返回的状态码（默认 `[200]`，尝试次数超出时
+//!   沿用最后一个），`repeat` 表示连续重复若干次
+
+use std::collections::HashMap;
+use std::io::IsTerminal;
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+
+use crate::kiro::events::TokenEvent;
+use crate::kiro::model::credentials::{CredentialsConfig, KiroCredentials};
+use crate::kiro::provider::{MAX_RETRIES_PER_CREDENTIAL, MAX_TOTAL_RETRIES};
+use crate::kiro::token_manager::{DisabledReason, MultiTokenManager};
+use crate::model::config::Config;
+
+/// 报告中最多列出的失败请求数
+const MAX_LISTED_FAILURES: usize = 20;
+
+/// 流量剖面
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TrafficProfile {
+    #[serde(default)]
+    pub default_quota: Option<u64>,
+    #[serde(default)]
+    pub quotas: HashMap<u64, u64>,
+    #[serde(default)]
+    pub requests: Vec<ProfileRequest>,
+}
+
+/// 剖面中的一个请求
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProfileRequest {
+    #[serde(default)]
+    pub offset_secs: f64,
+    #[serde(default = "default_statuses")]
+    pub statuses: Vec<u16>,
+    #[serde(default = "default_repeat")]
+    pub repeat: usize,
+}
+
+fn default_statuses() -> Vec<u16> {
+    vec![200]
+}
+
+fn default_repeat() -> usize {
+    1
+}
+
+/// 单个凭据的模拟结果
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CredentialOutcome {
+    pub id: u64,
+    pub priority: u32,
+    /// 成功服务的请求数
+    pub served: u64,
+    /// 剩余额度（未配置额度时为 None）
+    pub quota_remaining: Option<u64>,
+    /// 被禁用的时间点（剖面中的秒数，未被禁用时为 None）
+    pub disabled_at_secs: Option<f64>,
+    pub disabled_reason: Option<DisabledReason>,
+}
+
+/// 失败的请求
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FailedRequest {
+    /// 请求序号（从 0 开始，展开 repeat 之后）
+    pub index: usize,
+    pub offset_secs: f64,
+    pub reason: String,
+}
+
+/// 模拟报告
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SimulationReport {
+    pub total_requests: usize,
+    pub succeeded: usize,
+    pub failed: usize,
+    /// 首次尝试之外的重试次数
+    pub retries: usize,
+    /// 所有凭据首次全部禁用的时间点（凭据集能支撑的时长）
+    pub exhausted_at_secs: Option<f64>,
+    pub credentials: Vec<CredentialOutcome>,
+    /// 失败的请求（最多列出前 20 个）
+    pub failures: Vec<FailedRequest>,
+}
+
+/// 单次请求的结果
+enum Outcome {
+    Served,
+    Failed(String),
+}
+
+/// 模拟器状态
+struct Simulator {
+    manager: MultiTokenManager,
+    quotas: HashMap<u64, Option<u64>>,
+    served: HashMap<u64, u64>,
+    disabled: HashMap<u64, (f64, DisabledReason)>,
+    exhausted_at: Option<f64>,
+    retries: usize,
+}
+
+impl Simulator {
+    /// 按剖面发送一个请求（与 `KiroProvider` 的重试策略一致）
+    async fn request(&mut self, statuses: &[u16]) -> Outcome {
+        let total = self.manager.total_count();
+        let max_retries = (total * MAX_RETRIES_PER_CREDENTIAL).min(MAX_TOTAL_RETRIES);
+        let mut last_error = "没有可用凭据".to_string();
+
+        for attempt in 0..max_retries {
+            if attempt > 0 {
+                self.retries += 1;
+            }
+            let ctx = match self.manager.acquire_context().await {
+                Ok(ctx) => ctx,
+                Err(e) => {
+                    last_error = e.to_string();
+                    continue;
+                }
+            };
+
+            // 额度用尽的凭据返回 402，否则按剖面回放上游状态码
+            let quota = self.quotas.get(&ctx.id).copied().flatten();
+            let status = if quota == Some(0) {
+                402
+            } else {
+                statuses
+                    .get(attempt)
+                    .or(statuses.last())
+                    .copied()
+                    .unwrap_or(200)
+            };
+
+            match status {
+                200..=299 => {
+                    self.manager.report_success(ctx.id);
+                    *self.served.entry(ctx.id).or_default() += 1;
+                    if let Some(Some(remaining)) = self.quotas.get_mut(&ctx.id) {
+                        *remaining -= 1;
+                    }
+                    return Outcome::Served;
+                }
+                402 => {
+                    if !self.manager.report_quota_exhausted(ctx.id) {
+                        return Outcome::Failed("所有凭据额度已用尽".to_string());
+                    }
+                    last_error = format!("凭据 #{} 额度已用尽", ctx.id);
+                }
+                401 | 403 => {
+                    if !self.manager.report_failure(ctx.id) {
+                        return Outcome::Failed(format!("{}（所有凭据已禁用）", status));
+                    }
+                    last_error = format!("凭据 #{} 返回 {}", ctx.id, status);
+                }
+                408 | 429 | 500..=599 => {
+                    last_error = format!("上游瞬态错误 {}", status);
+                }
+                _ => return Outcome::Failed(format!("上游返回 {}（不重试）", status)),
+            }
+        }
+        Outcome::Failed(format!(
+            "已达到最大重试次数（{}次）: {}",
+            max_retries, last_error
+        ))
+    }
+}
+
+/// 离线回放流量剖面
+pub async fn simulate(
+    config: Config,
+    credentials: Vec<KiroCredentials>,
+    profile: &TrafficProfile,
+) -> anyhow::Result<SimulationReport> {
+    // Token 视为始终有效，避免刷新；不回写来源文件
+    let credentials = credentials
+        .into_iter()
+        .map(|mut cred| {
+            cred.access_token = Some("simulated".to_string());
+            cred.expires_at = Some("2999-01-01T00:00:00Z".to_string());
+            cred.source_file = None;
+            cred
+        })
+        .collect();
+    let manager = MultiTokenManager::new(config, credentials, None, None, false)?;
+    let mut events = manager.subscribe();
+    let snapshot = manager.snapshot();
+    let quotas = snapshot
+        .entries
+        .iter()
+        .map(|e| {
+            let quota = profile.quotas.get(&e.id).copied().or(profile.default_quota);
+            (e.id, quota)
+        })
+        .collect();
+    let mut sim = Simulator {
+        manager,
+        quotas,
+        served: HashMap::new(),
+        disabled: HashMap::new(),
+        exhausted_at: None,
+        retries: 0,
+    };
+
+    let mut index = 0;
+    let mut succeeded = 0;
+    let mut failures = Vec::new();
+    for request in &profile.requests {
+        for _ in 0..request.repeat {
+            match sim.request(&request.statuses).await {
+                Outcome::Served => succeeded += 1,
+                Outcome::Failed(reason) => failures.push(FailedRequest {
+                    index,
+                    offset_secs: request.offset_secs,
+                    reason,
+                }),
+            }
+            while let Ok(event) = events.try_recv() {
+                match event {
+                    TokenEvent::CredentialDisabled { id, reason } => {
+                        sim.disabled
+                            .entry(id)
+                            .or_insert((request.offset_secs, reason));
+                    }
+                    TokenEvent::AllExhausted { .. } => {
+                        sim.exhausted_at.get_or_insert(request.offset_secs);
+                    }
+                    _ => {}
+                }
+            }
+            index += 1;
+        }
+    }
+
+    let credentials = snapshot
+        .entries
+        .iter()
+        .map(|e| {
+            let disabled = sim.disabled.get(&e.id);
+            CredentialOutcome {
+                id: e.id,
+                priority: e.priority,
+                served: sim.served.get(&e.id).copied().unwrap_or(0),
+                quota_remaining: sim.quotas.get(&e.id).copied().flatten(),
+                disabled_at_secs: disabled.map(|(at, _)| *at),
+                disabled_reason: disabled.map(|(_, reason)| *reason),
+            }
+        })
+        .collect();
+    let failed = failures.len();
+    failures.truncate(MAX_LISTED_FAILURES);
+    Ok(SimulationReport {
+        total_requests: index,
+        succeeded,
+        failed,
+        retries: sim.retries,
+        exhausted_at_secs: sim.exhausted_at,
+        credentials,
+        failures,
+    })
+}
+
+/// 执行模拟，返回进程退出码
+pub async fn run(config_path: &str, credentials_path: &str, profile_path: &str, json: bool) -> i32 {
+    match load_and_simulate(config_path, credentials_path, profile_path).await {
+        Ok(report) if json => {
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&report).unwrap_or_default()
+            );
+            0
+        }
+        Ok(report) => {
+            print_report(&report);
+            0
+        }
+        Err(e) => {
+            eprintln!("模拟失败: {:#}", e);
+            1
+        }
+    }
+}
+
+async fn load_and_simulate(
+    config_path: &str,
+    credentials_path: &str,
+    profile_path: &str,
+) -> anyhow::Result<SimulationReport> {
+    let config = Config::load_with_env(config_path).context("加载配置失败")?;
+    let credentials = CredentialsConfig::resolve(
+        credentials_path,
+        config.state_dir.as_deref(),
+        &config.credential_sources,
+    )
+    .context("加载凭据失败")?
+    .config
+    .into_sorted_credentials();
+    if credentials.is_empty() {
+        anyhow::bail!("未配置任何凭据");
+    }
+    let content = std::fs::read_to_string(profile_path)
+        .with_context(|| format!("读取流量剖面失败: {}", profile_path))?;
+    let profile: TrafficProfile = serde_json::from_str(&content)
+        .with_context(|| format!("解析流量剖面失败: {}", profile_path))?;
+    simulate(config, credentials, &profile).await
+}
+
+fn print_report(report: &SimulationReport) {
+    let bold = |text: &str| {
+        if std::io::stdout().is_terminal() && std::env::var_os("NO_COLOR").is_none() {
+            format!("\x1b[1m{}\x1b[0m", text)
+        } else {
+            text.to_string()
+        }
+    };
+
+    println!("{}", bold("请求"));
+    println!(
+        "  共 {} 个，成功 {}，失败 {}，重试 {} 次",
+        report.total_requests, report.succeeded, report.failed, report.retries
+    );
+    match report.exhausted_at_secs {
+        Some(at) => println!("  所有凭据在 {:.1}s 时全部禁用", at),
+        None => println!("  剖面结束时仍有可用凭据"),
+    }
+
+    println!();
+    println!("{}", bold("凭据"));
+    for cred in &report.credentials {
+        let quota = cred
+            .quota_remaining
+            .map(|q| format!("，剩余额度 {}", q))
+            .unwrap_or_default();
+        let disabled = match (cred.disabled_at_secs, cred.disabled_reason) {
+            (Some(at), Some(reason)) => format!("，{:.1}s 时被禁用（{:?}）", at, reason),
+            _ => String::new(),
+        };
+        println!(
+            "  #{}（优先级 {}）: 服务 {} 个请求{}{}",
+            cred.id, cred.priority, cred.served, quota, disabled
+        );
+    }
+
+    if !report.failures.is_empty() {
+        println!();
+        println!("{}", bold("失败的请求"));
+        for failure in &report.failures {
+            println!(
+                "  #{}（{:.1}s）: {}",
+                failure.index, failure.offset_secs, failure.reason
+            );
+        }
+        if report.failed > report.failures.len() {
+            println!("  …… 另有 {} 个", report.failed - report.failures.len());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn credentials(count: usize) -> Vec<KiroCredentials> {
+        (0..count)
+            .map(|i| KiroCredentials {
+                refresh_token: Some("a".repeat(150)),
+                priority: i as u32,
+                ..Default::default()
+            })
+            .collect()
+    }
+
+    fn profile(json: &str) -> TrafficProfile {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_quota_exhaustion_with_failover() {
+        let profile = profile(
+            r#"{"quotas": {"1": 2, "2": 3}, "requests": [
+                {"offsetSecs": 0, "repeat": 4},
+                {"offsetSecs": 10, "repeat": 2}
+            ]}"#,
+        );
+        let report = simulate(Config::default(), credentials(2), &profile)
+            .await
+            .unwrap();
+
+        assert_eq!(report.total_requests, 6);
+        assert_eq!(report.succeeded, 5);
+        assert_eq!(report.failed, 1);
+        assert_eq!(report.failures[0].index, 5);
+        assert_eq!(report.exhausted_at_secs, Some(10.0));
+        assert_eq!(report.credentials[0].served, 2);
+        assert_eq!(report.credentials[0].disabled_at_secs, Some(0.0));
+        assert_eq!(
+            report.credentials[0].disabled_reason,
+            Some(DisabledReason::QuotaExceeded)
+        );
+        assert_eq!(report.credentials[1].served, 3);
+        assert_eq!(report.credentials[1].quota_remaining, Some(0));
+    }
+
+    #[tokio::test]
+    async fn test_transient_errors_and_auth_failover() {
+        let profile = profile(
+            r#"{"requests": [
+                {"offsetSecs": 0, "statuses": [429, 200]},
+                {"offsetSecs": 1, "statuses": [403, 403, 403, 200]},
+                {"offsetSecs": 2, "statuses": [400]}
+            ]}"#,
+        );
+        let report = simulate(Config::default(), credentials(2), &profile)
+            .await
+            .unwrap();
+
+        assert_eq!(report.succeeded, 2);
+        assert_eq!(report.retries, 4);
+        assert_eq!(
+            report.credentials[0].disabled_reason,
+            Some(DisabledReason::TooManyFailures)
+        );
+        assert_eq!(report.credentials[1].served, 1);
+        assert!(report.exhausted_at_secs.is_none());
+        assert!(report.failures[0].reason.contains("400"));
+    }
+}
@@ -0,0 +1,219 @@
+//! `kiro usage` 用量查询
+//!
+//! 按时间窗口汇总用量账本，按客户端、凭据或模型分组输出请求数、tokens 与错误率。默认直接读取
+//! `{stateDir}/usage.jsonl`（无需服务运行）；指定 `--url` 时改为查询运行中实例的
+//! `GET /api/admin/usage/summary`，适用于未设置 stateDir（账本只在内存中）或在其它机器上查询。
+
+use anyhow::Context;
+
+use crate::common::ledger::{GroupBy, UsageLedger, UsageSummaryQuery, UsageSummaryRow};
+use crate::common::privacy::Capabilities;
+use crate::model::config::Config;
+
+/// 用量数据来源
+pub enum Source {
+    /// 读取 stateDir 中的账本文件
+    Ledger,
+    /// 查询运行中实例的 Admin API
+    Admin {
+        url: String,
+        api_key: Option<String>,
+    },
+}
+
+/// 执行用量查询，返回进程退出码
+pub async fn run(
+    config_path: &str,
+    source: Source,
+    since: &str,
+    group_by: GroupBy,
+    csv: bool,
+) -> i32 {
+    match load_summary(config_path, source, since, group_by).await {
+        Ok(rows) => {
+            let output = if csv {
+                render_csv(&rows)
+            } else {
+                render_table(&rows, group_by)
+            };
+            print!("{}", output);
+            0
+        }
+        Err(e) => {
+            eprintln!("查询用量失败: {:#}", e);
+            1
+        }
+    }
+}
+
+async fn load_summary(
+    config_path: &str,
+    source: Source,
+    since: &str,
+    group_by: GroupBy,
+) -> anyhow::Result<Vec<UsageSummaryRow>> {
+    let config = Config::load_with_env(config_path).context("加载配置失败")?;
+    match source {
+        Source::Ledger => {
+            let state_dir = config
+                .state_dir
+                .as_deref()
+                .context("未配置 stateDir，用量账本只在运行中实例的内存中，请使用 --url 查询")?;
+            UsageLedger::open(Some(state_dir), Capabilities::from_config(&config))
+                .summary(&UsageSummaryQuery {
+                    since: Some(since.to_string()),
+                    group_by,
+                })
+                .map_err(anyhow::Error::msg)
+        }
+        Source::Admin { url, api_key } => {
+            let api_key = api_key
+                .or(config.admin_api_key)
+                .context("未指定 --api-key，配置中也没有 adminApiKey")?;
+            let response = reqwest::Client::new()
+                .get(format!(
+                    "{}/api/admin/usage/summary",
+                    url.trim_end_matches('/')
+                ))
+                .query(&[("since", since), ("groupBy", group_by.as_str())])
+                .header("x-api-key", api_key)
+                .send()
+                .await
+                .with_context(|| format!("请求 {} 失败", url))?;
+            let status = response.status();
+            if !status.is_success() {
+                let body = response.text().await.unwrap_or_default();
+                anyhow::bail!("Admin API 返回 {}: {}", status, body);
+            }
+            response.json().await.context("解析 Admin API 响应失败")
+        }
+    }
+}
+
+/// 对齐的文本表格
+fn render_table(rows: &[UsageSummaryRow], group_by: GroupBy) -> String {
+    if rows.is_empty() {
+        return "时间窗口内没有请求\n".to_string();
+    }
+    let header = [
+        group_by.as_str().to_string(),
+        "requests".to_string(),
+        "completed".to_string(),
+        "disconnected".to_string(),
+        "errors".to_string(),
+        "input_tokens".to_string(),
+        "output_tokens".to_string(),
+        "error_rate".to_string(),
+    ];
+    let lines: Vec<[String; 8]> = std::iter::once(header)
+        .chain(rows.iter().map(|row| {
+            [
+                row.key.clone(),
+                row.requests.to_string(),
+                row.completed.to_string(),
+                row.disconnected.to_string(),
+                row.errors.to_string(),
+                row.input_tokens.to_string(),
+                row.output_tokens.to_string(),
+                format!("{:.1}%", row.error_rate * 100.0),
+            ]
+        }))
+        .collect();
+
+    let mut widths = [0; 8];
+    for line in &lines {
+        for (width, cell) in widths.iter_mut().zip(line) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    let mut output = String::new();
+    for line in &lines {
+        let cells: Vec<String> = line
+            .iter()
+            .zip(widths)
+            .enumerate()
+            .map(|(i, (cell, width))| {
+                let pad = " ".repeat(width - cell.chars().count());
+                // 分组键左对齐，数值右对齐
+                if i == 0 {
+                    format!("{}{}", cell, pad)
+                } else {
+                    format!("{}{}", pad, cell)
+                }
+            })
+            .collect();
+        output.push_str(cells.join("  ").trim_end());
+        output.push('\n');
+    }
+    output
+}
+
+/// CSV（分组键按需加引号）
+fn render_csv(rows: &[UsageSummaryRow]) -> String {
+    let mut output = String::from(
+        "key,requests,completed,disconnected,errors,input_tokens,output_tokens,error_rate\n",
+    );
+    for row in rows {
+        let key = if row.key.contains([',', '"', '\n']) {
+            format!("\"{}\"", row.key.replace('"', "\"\""))
+        } else {
+            row.key.clone()
+        };
+        output.push_str(&format!(
+            "{},{},{},{},{},{},{},{:.4}\n",
+            key,
+            row.requests,
+            row.completed,
+            row.disconnected,
+            row.errors,
+            row.input_tokens,
+            row.output_tokens,
+            row.error_rate
+        ));
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_table_and_csv() {
+        let rows = vec![
+            UsageSummaryRow {
+                key: "1".to_string(),
+                requests: 120,
+                completed: 110,
+                disconnected: 4,
+                errors: 6,
+                input_tokens: 52000,
+                output_tokens: 8100,
+                error_rate: 0.05,
+            },
+            UsageSummaryRow {
+                key: "a,\"b\"".to_string(),
+                requests: 3,
+                errors: 3,
+                error_rate: 1.0,
+                ..Default::default()
+            },
+        ];
+
+        let table = render_table(&rows, GroupBy::Credential);
+        let lines: Vec<&str> = table.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].starts_with("credential"));
+        assert!(lines[1].ends_with("5.0%"));
+        assert!(lines[2].ends_with("100.0%"));
+        // 各列右对齐，行宽一致
+        assert_eq!(lines[1].len(), lines[2].len());
+        assert_eq!(render_table(&[], GroupBy::Client), "时间窗口内没有请求\n");
+
+        let csv = render_csv(&rows);
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines[1], "1,120,110,4,6,52000,8100,0.0500");
+        assert_eq!(lines[2], "\"a,\"\"b\"\"\",3,0,0,3,0,0,1.0000");
+    }
+}